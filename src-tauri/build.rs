@@ -23,10 +23,12 @@ fn main() {
             ".wcf.PathMsg.path",
             "#[schema(example = \"C:/图片/文件/等路径/必须存在否则失败.jpeg\")]",
         )
+        .field_attribute(".wcf.PathMsg.filename", "#[serde(default)]")
         .compile_protos(
             &[
                 "src/wcferry/lib/wcf.proto",
                 "src/wcferry/lib/roomdata.proto",
+                "src/wcferry/lib/bytesextra.proto",
             ],
             &["."],
         )
@@ -1,29 +1,92 @@
+mod validation;
+
+use crate::handler::message::payload;
+use crate::service::{
+    admin_notify_service::{self, Incident},
+    announce_service::{self, AnnounceResult},
+    announcement_service::{self, Ack, AckReport, Pending},
+    at_all_service::{self, AtAllMessage, AtAllState},
+    anomaly_service::RoomVolume,
+    backup_service::{self, BackupReport, TargetResult},
+    batch_service::{self, BatchItem, BatchReport, ItemState},
+    bridge_identity_service::BridgeIdentity,
+    bridge_thread_service::ThreadLink,
+    checkin_service::CheckinStat,
+    config_service::{self, ConfigBundle},
+    db_snapshot_service::{self, SnapshotInfo},
+    distribute_service::{self, Delivery, DeliveryState, DistributionReport},
+    dnd_service,
+    global_service::GLOBAL,
+    identity_service::Identity,
+    import_service::{self, ImportFormat, ImportReport},
+    markdown_service, media_service,
+    message_store_service::{PurgeFilter, PurgeReport},
+    moderation_service,
+    name_history_service::{NameField, NameRecord},
+    pause_service::PauseStatus,
+    privacy_service::{self, ErasureReport, SubjectData},
+    poll_service::{OptionResult, PollResult},
+    preflight_service::{self, PreflightReport},
+    quiet_hours_service::{self, QueuedText},
+    raffle_service::{self, Draw, Raffle},
+    receipt_service::{self, Receipt, ReceiptFields},
+    replay_service::{self, ReplayReport},
+    risk_guard_service::{BudgetStatus, RiskOperation},
+    rule_limit_service::{self, RuleStats},
+    rule_service::{self, ActionPreview, RuleGroup, RuleTrace},
+    rule_var_service::RuleVar,
+    sdk_service::{self, SdkVersion},
+    sink_validate_service::{self, SinkKind, SinkReport},
+    stats_service::{self, MemberActivity, RoomHeatmap, WordCloud, WordCount, WordPeriod},
+    template_service::{Filled, MessageTemplate, RichTemplate},
+    update_service::{self, VersionInfo},
+    watchdog_service::SelfHeal,
+    webhook_service::{self, Rejected},
+    word_cloud_service,
+    wechat_installer_service::{self, InstallReport},
+};
+use crate::utils::{
+    compression::compressed,
+    contact::{self, ContactKind},
+    jobs::{self, Job, JobState},
+    log_buffer::{self, LogEntry},
+    metrics,
+    mention, pacing,
+    pipeline::{self, HandlerStage, PipelineStats, ReceiveStage, SinkStage},
+    send_log::{self, Expect},
+    system_event::{self, TypedEvent},
+    video, wxid,
+};
 use crate::wcferry::{
     wcf::{
         AttachMsg, AudioMsg, DbNames, DbQuery, DbTable, DbTables, DecPath, ForwardMsg, MemberMgmt,
         MsgTypes, PatMsg, PathMsg, RichText, RpcContact, RpcContacts, TextMsg, Transfer, UserInfo,
-        Verification,
+        Verification, WxMsg, XmlMsg,
     },
-    SelfInfo, WeChat,
+    MsgMedia, RoomRole, SelfInfo, WeChat,
 };
 use base64::encode;
-use log::{debug, error};
+use image::codecs::jpeg::JpegEncoder;
+use log::{debug, error, info, warn};
+use quickxml_to_serde::{xml_string_to_json, Config as XmlConfig};
 use reqwest::get;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
 use std::fs::File;
 use std::io::{copy, Cursor};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread::sleep;
 use std::time::Duration;
 use tokio::fs;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 use utoipa_swagger_ui::Config;
 use uuid::Uuid;
+use warp::filters::BoxedFilter;
 use warp::reply::Json;
+use validation::{body_limit, json_body, FieldError, InvalidFields, DEFAULT_BODY_LIMIT};
 use warp::{
     http::Uri,
     hyper::{Response, StatusCode},
@@ -39,11 +102,13 @@ macro_rules! wechat_api_handler {
         match result {
             Ok(data) => Ok(warp::reply::json(&ApiResponse {
                 status: 0,
+                code: None,
                 error: None,
                 data: Some(data),
             })),
             Err(error) => Ok(warp::reply::json(&ApiResponse::<()> {
                 status: 1,
+                code: None,
                 error: Some(format!("{}失败: {}", $desc, error)),
                 data: None,
             })),
@@ -55,11 +120,13 @@ macro_rules! wechat_api_handler {
         match result {
             Ok(data) => Ok(warp::reply::json(&ApiResponse {
                 status: 0,
+                code: None,
                 error: None,
                 data: Some(data),
             })),
             Err(error) => Ok(warp::reply::json(&ApiResponse::<()> {
                 status: 1,
+                code: None,
                 error: Some(format!("{}失败: {}", $desc, error)),
                 data: None,
             })),
@@ -69,12 +136,54 @@ macro_rules! wechat_api_handler {
 
 #[macro_export]
 macro_rules! build_route_fn {
+    ($func_name:ident, GET $path:literal / PATH $param_type:ident / $suffix:literal, $handler:expr, $wechat:expr) => {
+        pub fn $func_name(
+            wechat: Arc<Mutex<WeChat>>,
+        ) -> BoxedFilter<(impl Reply,)> {
+            warp::path($path)
+                .and(warp::path::param::<$param_type>())
+                .and(warp::path($suffix))
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(require_login($path, wechat.clone()))
+                .and(warp::any().map(move || wechat.clone()))
+                .and_then($handler).boxed()
+        }
+    };
+    ($func_name:ident, POST $path:literal / PATH $param_type:ident / $suffix:literal, $handler:expr, $wechat:expr) => {
+        pub fn $func_name(
+            wechat: Arc<Mutex<WeChat>>,
+        ) -> BoxedFilter<(impl Reply,)> {
+            warp::path($path)
+                .and(warp::path::param::<$param_type>())
+                .and(warp::path($suffix))
+                .and(warp::path::end())
+                .and(warp::post())
+                .and(require_login($path, wechat.clone()))
+                .and(warp::any().map(move || wechat.clone()))
+                .and_then($handler).boxed()
+        }
+    };
+    ($func_name:ident, GET $path:literal / PATH $param_type:ty, $handler:expr, $wechat:expr) => {
+        pub fn $func_name(
+            wechat: Arc<Mutex<WeChat>>,
+        ) -> BoxedFilter<(impl Reply,)> {
+            warp::path($path)
+                .and(warp::path::param::<$param_type>())
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(require_login($path, wechat.clone()))
+                .and(warp::any().map(move || wechat.clone()))
+                .and_then($handler).boxed()
+        }
+    };
     ($func_name:ident, GET $path:expr, $handler:expr, $wechat:expr) => {
         pub fn $func_name(
             wechat: Arc<Mutex<WeChat>>,
-        ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        ) -> BoxedFilter<(impl Reply,)> {
             warp::path($path)
                 .and(warp::get())
+                .and(require_login($path, wechat.clone()))
                 .and(warp::any().map(move || wechat.clone()))
                 .and_then($handler).boxed()
         }
@@ -82,10 +191,11 @@ macro_rules! build_route_fn {
     ($func_name:ident, GET $path:expr, $handler:expr, PATH $param_type:ty, $wechat:expr) => {
         pub fn $func_name(
             wechat: Arc<Mutex<WeChat>>,
-        ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        ) -> BoxedFilter<(impl Reply,)> {
             warp::path::param::<$param_type>()
                 .and(warp::path($path))
                 .and(warp::get())
+                .and(require_login($path, wechat.clone()))
                 .and(warp::any().map(move || wechat.clone()))
                 .and_then($handler).boxed()
         }
@@ -93,9 +203,10 @@ macro_rules! build_route_fn {
     ($func_name:ident, GET $path:expr, $handler:expr, QUERY $param_type:ty, $wechat:expr) => {
         pub fn $func_name(
             wechat: Arc<Mutex<WeChat>>,
-        ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        ) -> BoxedFilter<(impl Reply,)> {
             warp::path($path)
                 .and(warp::get())
+                .and(require_login($path, wechat.clone()))
                 .and(warp::query::<$param_type>())
                 .and(warp::any().map(move || wechat.clone()))
                 .and_then($handler).boxed()
@@ -104,9 +215,10 @@ macro_rules! build_route_fn {
     ($func_name:ident, POST $path:expr, $handler:expr, $wechat:expr) => {
         pub fn $func_name(
             wechat: Arc<Mutex<WeChat>>,
-        ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        ) -> BoxedFilter<(impl Reply,)> {
             warp::path($path)
                 .and(warp::post())
+                .and(require_login($path, wechat.clone()))
                 .and(warp::any().map(move || wechat.clone()))
                 .and_then($handler).boxed()
         }
@@ -114,9 +226,10 @@ macro_rules! build_route_fn {
     ($func_name:ident, POST $path:expr, $handler:expr, QUERY $param_type:ty, $wechat:expr) => {
         pub fn $func_name(
             wechat: Arc<Mutex<WeChat>>,
-        ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        ) -> BoxedFilter<(impl Reply,)> {
             warp::path($path)
                 .and(warp::post())
+                .and(require_login($path, wechat.clone()))
                 .and(warp::query::<$param_type>())
                 .and(warp::any().map(move || wechat.clone()))
                 .and_then($handler).boxed()
@@ -125,10 +238,24 @@ macro_rules! build_route_fn {
     ($func_name:ident, POST $path:expr, $handler:expr, JSON, $wechat:expr) => {
         pub fn $func_name(
             wechat: Arc<Mutex<WeChat>>,
-        ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        ) -> BoxedFilter<(impl Reply,)> {
+            warp::path($path)
+                .and(warp::post())
+                .and(require_login($path, wechat.clone()))
+                .and(json_body($path))
+                .and(warp::any().map(move || wechat.clone()))
+                .and_then($handler).boxed()
+        }
+    };
+    ($func_name:ident, POST $path:expr, $handler:expr, JSON DRY_RUN, $wechat:expr) => {
+        pub fn $func_name(
+            wechat: Arc<Mutex<WeChat>>,
+        ) -> BoxedFilter<(impl Reply,)> {
             warp::path($path)
                 .and(warp::post())
-                .and(warp::body::json())
+                .and(require_login($path, wechat.clone()))
+                .and(json_body($path))
+                .and(warp::query::<DryRunQuery>())
                 .and(warp::any().map(move || wechat.clone()))
                 .and_then($handler).boxed()
         }
@@ -139,26 +266,482 @@ macro_rules! build_route_fn {
 #[aliases(ApiResponseBool = ApiResponse<bool>,
     ApiResponseString = ApiResponse<String>,
     ApiResponseUserInfo = ApiResponse<SelfInfo>,
-    ApiResponseContacts = ApiResponse<RpcContacts>,
+    ApiResponseContacts = ApiResponse<ContactList>,
+    ApiResponseContactVec = ApiResponse<Vec<RpcContact>>,
     ApiResponseDbNames = ApiResponse<DbNames>,
     ApiResponseMsgTypes = ApiResponse<MsgTypes>,
     ApiResponseDbTables = ApiResponse<DbTables>,
-    ApiResponseMembers = ApiResponse<Vec<Member>>)]
+    ApiResponseMembers = ApiResponse<Vec<Member>>,
+    ApiResponseBudgets = ApiResponse<Vec<BudgetStatus>>,
+    ApiResponseHealth = ApiResponse<HealthStatus>,
+    ApiResponseRoomRole = ApiResponse<RoomRole>,
+    ApiResponseMentions = ApiResponse<Vec<ResolvedMention>>,
+    ApiResponseRoomPermissions = ApiResponse<RoomPermissions>,
+    ApiResponsePoll = ApiResponse<PollResult>,
+    ApiResponseAckReport = ApiResponse<AckReport>,
+    ApiResponseAnnounce = ApiResponse<Vec<AnnounceResult>>,
+    ApiResponseAtAll = ApiResponse<AtAllMessage>,
+    ApiResponseAtAlls = ApiResponse<Vec<AtAllMessage>>,
+    ApiResponseDistribution = ApiResponse<DistributionReport>,
+    ApiResponseBatch = ApiResponse<BatchReport>,
+    ApiResponseRuleTraces = ApiResponse<Vec<RuleTrace>>,
+    ApiResponseRuleGroups = ApiResponse<Vec<RuleGroup>>,
+    ApiResponseRuleStats = ApiResponse<Vec<RuleStats>>,
+    ApiResponseRuleVars = ApiResponse<Vec<RuleVar>>,
+    ApiResponseTemplate = ApiResponse<MessageTemplate>,
+    ApiResponseTemplates = ApiResponse<Vec<MessageTemplate>>,
+    ApiResponseIdentity = ApiResponse<Identity>,
+    ApiResponseIdentities = ApiResponse<Vec<Identity>>,
+    ApiResponseBridgeIdentity = ApiResponse<BridgeIdentity>,
+    ApiResponseBridgeIdentities = ApiResponse<Vec<BridgeIdentity>>,
+    ApiResponseThreadLink = ApiResponse<ThreadLink>,
+    ApiResponseThreadLinks = ApiResponse<Vec<ThreadLink>>,
+    ApiResponseNameHistory = ApiResponse<Vec<NameRecord>>,
+    ApiResponseCheckin = ApiResponse<Vec<CheckinStat>>,
+    ApiResponseReceipts = ApiResponse<Vec<Receipt>>,
+    ApiResponseRaffle = ApiResponse<Raffle>,
+    ApiResponseQueuedTexts = ApiResponse<Vec<QueuedText>>,
+    ApiResponsePause = ApiResponse<PauseStatus>,
+    ApiResponseConfigBundle = ApiResponse<ConfigBundle>,
+    ApiResponseStrings = ApiResponse<Vec<String>>,
+    ApiResponseSinkReports = ApiResponse<Vec<SinkReport>>,
+    ApiResponsePipeline = ApiResponse<PipelineStats>,
+    ApiResponseVolumes = ApiResponse<Vec<RoomVolume>>,
+    ApiResponseFieldErrors = ApiResponse<Vec<FieldError>>,
+    ApiResponseSendResult = ApiResponse<SendResult>,
+    ApiResponsePurge = ApiResponse<PurgeReport>,
+    ApiResponseSubjectData = ApiResponse<SubjectData>,
+    ApiResponseErasure = ApiResponse<ErasureReport>,
+    ApiResponseBackup = ApiResponse<BackupReport>,
+    ApiResponseImport = ApiResponse<ImportReport>,
+    ApiResponseJob = ApiResponse<Job>,
+    ApiResponseJobs = ApiResponse<Vec<Job>>,
+    ApiResponseSnapshots = ApiResponse<Vec<SnapshotInfo>>,
+    ApiResponseHeatmap = ApiResponse<RoomHeatmap>,
+    ApiResponseWordCloud = ApiResponse<WordCloud>)]
 struct ApiResponse<T>
 where
     T: Serialize,
 {
     status: u16,
+    /// 错误码，便于调用方区分错误类型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
     error: Option<String>,
     data: Option<T>,
 }
 
+/// 超出风控预算
+pub const CODE_BUDGET_EXCEEDED: &str = "BUDGET_EXCEEDED";
+/// 当前账号不是群主或管理员
+pub const CODE_NOT_ADMIN: &str = "NOT_ADMIN";
+/// 微信未登录
+pub const CODE_NOT_LOGGED_IN: &str = "NOT_LOGGED_IN";
+/// 缺少访问令牌或令牌错误
+pub const CODE_UNAUTHORIZED: &str = "UNAUTHORIZED";
+/// 请求字段校验不通过，data 中为逐字段的错误
+pub const CODE_INVALID_FIELDS: &str = "INVALID_FIELDS";
+/// 请求体不是合法的 JSON 或字段类型不对
+pub const CODE_INVALID_BODY: &str = "INVALID_BODY";
+/// 请求体超出大小上限
+pub const CODE_PAYLOAD_TOO_LARGE: &str = "PAYLOAD_TOO_LARGE";
+/// 请求没有 Content-Length，不支持分块传输的请求体
+pub const CODE_LENGTH_REQUIRED: &str = "LENGTH_REQUIRED";
+/// 发送内容未通过审核
+pub const CODE_CONTENT_BLOCKED: &str = "CONTENT_BLOCKED";
+/// webhook 缺少时间戳或 nonce，或时间戳已过期
+pub const CODE_STALE_DELIVERY: &str = "STALE_DELIVERY";
+/// webhook 的 nonce 已经用过
+pub const CODE_DUPLICATE_DELIVERY: &str = "DUPLICATE_DELIVERY";
+
+// 未登录时也可以调用的接口
+const LOGIN_EXEMPT: &[&str] = &["qrcode", "islogin", "health", "risk-budget", "emotion"];
+
+#[derive(Debug)]
+struct NotLoggedIn;
+
+impl warp::reject::Reject for NotLoggedIn {}
+
+// 未登录时直接拒绝，不再把请求发给微信
+fn require_login(path: &'static str, wechat: Arc<Mutex<WeChat>>) -> BoxedFilter<()> {
+    warp::any()
+        .and_then(move || {
+            let wechat = wechat.clone();
+            async move {
+                if LOGIN_EXEMPT.contains(&path) || wechat.lock().unwrap().is_login_cached() {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(NotLoggedIn))
+                }
+            }
+        })
+        .untuple_one()
+        .boxed()
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+// 配置了访问令牌时校验请求头；本地模式下必须配置令牌，未配置时拒绝所有请求
+fn require_token() -> BoxedFilter<()> {
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>("x-api-token"))
+        .and_then(|auth: Option<String>, token: Option<String>| async move {
+            let (local_only, expected) = {
+                let global = GLOBAL.get().unwrap();
+                let config = global.wechat_config.read().unwrap();
+                (config.local_only, config.api_token.clone())
+            };
+            if expected.is_empty() && !local_only {
+                return Ok(());
+            }
+            let given = token.or_else(|| auth.and_then(|a| a.strip_prefix("Bearer ").map(|t| t.trim().to_string())));
+            if expected.matches(given.as_deref()) {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(Unauthorized))
+            }
+        })
+        .untuple_one()
+        .boxed()
+}
+
+// 本地模式下不提供 Swagger 页面
+fn swagger_enabled() -> BoxedFilter<()> {
+    warp::any()
+        .and_then(|| async {
+            let local_only = GLOBAL.get().unwrap().wechat_config.read().unwrap().local_only;
+            if local_only {
+                Err(warp::reject::not_found())
+            } else {
+                Ok(())
+            }
+        })
+        .untuple_one()
+        .boxed()
+}
+
+async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        return Ok(warp::reply::with_status(
+            api_error_code(CODE_UNAUTHORIZED, "缺少访问令牌或令牌错误"),
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    if rejection.find::<NotLoggedIn>().is_some() {
+        return Ok(warp::reply::with_status(
+            api_error_code(CODE_NOT_LOGGED_IN, "微信未登录"),
+            StatusCode::CONFLICT,
+        ));
+    }
+    if let Some(invalid) = rejection.find::<InvalidFields>() {
+        return Ok(warp::reply::with_status(invalid_fields(invalid), StatusCode::BAD_REQUEST));
+    }
+    if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        return Ok(warp::reply::with_status(
+            api_error_code(CODE_PAYLOAD_TOO_LARGE, "请求体过大"),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    }
+    if rejection.find::<warp::reject::LengthRequired>().is_some() {
+        return Ok(warp::reply::with_status(
+            api_error_code(CODE_LENGTH_REQUIRED, "请求缺少 Content-Length，不支持分块传输的请求体"),
+            StatusCode::LENGTH_REQUIRED,
+        ));
+    }
+    if let Some(e) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        return Ok(warp::reply::with_status(
+            api_error_code(CODE_INVALID_BODY, e),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    Err(rejection)
+}
+
+// 逐字段的校验错误放在 data 中
+fn invalid_fields(invalid: &InvalidFields) -> Json {
+    warp::reply::json(&ApiResponse {
+        status: 1,
+        code: Some(CODE_INVALID_FIELDS),
+        error: Some(invalid.summary()),
+        data: Some(invalid.0.clone()),
+    })
+}
+
+fn api_ok<T: Serialize>(data: T) -> Json {
+    warp::reply::json(&ApiResponse {
+        status: 0,
+        code: None,
+        error: None,
+        data: Some(data),
+    })
+}
+
+fn api_error(error: impl ToString) -> Json {
+    warp::reply::json(&ApiResponse::<()> {
+        status: 1,
+        code: None,
+        error: Some(error.to_string()),
+        data: None,
+    })
+}
+
+fn api_error_code(code: &'static str, error: impl ToString) -> Json {
+    warp::reply::json(&ApiResponse::<()> {
+        status: 1,
+        code: Some(code),
+        error: Some(error.to_string()),
+        data: None,
+    })
+}
+
+// 扣减风控预算，超出时返回带 BUDGET_EXCEEDED 的错误响应
+fn consume_budget(operation: RiskOperation, count: u32) -> Result<(), Json> {
+    let global = GLOBAL.get().unwrap();
+    let mut guard = global.risk_guard_service.lock().unwrap();
+    guard.consume(operation, count).map_err(|e| {
+        admin_notify_service::report(Incident::BudgetExceeded(e.clone()));
+        api_error_code(CODE_BUDGET_EXCEEDED, e)
+    })
+}
+
+// 内容审核，未通过时返回带 CONTENT_BLOCKED 的错误响应
+async fn moderate(receiver: &str, text: String, query: &DryRunQuery) -> Result<(), Json> {
+    let receiver = receiver.to_string();
+    let token = query.override_token.clone();
+    tokio::task::spawn_blocking(move || moderation_service::check(&receiver, &text, token.as_deref()))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))
+        .map_err(|e| api_error_code(CODE_CONTENT_BLOCKED, e))
+}
+
+// xml 中所有的文字和属性值（标题、描述、链接等），一行一个，用于内容审核；解析失败时审核原文
+fn xml_text(xml: &str) -> String {
+    fn collect(value: &Value, out: &mut Vec<String>) {
+        match value {
+            Value::String(s) if !s.trim().is_empty() => out.push(s.trim().to_string()),
+            Value::Array(items) => items.iter().for_each(|v| collect(v, out)),
+            Value::Object(map) => map.values().for_each(|v| collect(v, out)),
+            _ => {}
+        }
+    }
+    match xml_string_to_json(xml.to_string(), &XmlConfig::new_with_defaults()) {
+        Ok(json) => {
+            let mut out = vec![];
+            collect(&json, &mut out);
+            out.join("\n")
+        }
+        Err(_) => xml.to_string(),
+    }
+}
+
+// 在后台线程审核并发送程序生成的文本，不占用微信的锁
+async fn send_checked(wechat: Arc<Mutex<WeChat>>, text: TextMsg) -> Result<bool, String> {
+    tokio::task::spawn_blocking(move || {
+        let wc = wechat.lock().unwrap().clone();
+        moderation_service::send_text(&wc, text)
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()))
+}
+
+fn member_count(wxids: &str) -> u32 {
+    wxids.split(',').filter(|w| !w.trim().is_empty()).count() as u32
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DryRunQuery {
+    /// 演练模式，只校验参数并记录日志，不实际执行
+    dry_run: Option<bool>,
+    /// 发送后等待消息回调确认已发出，返回消息 id
+    verify: Option<bool>,
+    /// 内容审核豁免令牌，用于人工确认过的内容
+    override_token: Option<String>,
+}
+
+/// 演练模式下的返回，data 中给出将要执行的操作
+#[derive(Serialize, ToSchema)]
+pub struct DryRunResult {
+    dry_run: bool,
+    /// 操作名称
+    action: String,
+    /// 请求参数
+    params: Value,
+}
+
+// 请求参数或全局配置任一开启即为演练
+fn is_dry_run(query: &DryRunQuery) -> bool {
+    if query.dry_run.unwrap_or(false) {
+        return true;
+    }
+    let global = GLOBAL.get().unwrap();
+    let config = global.wechat_config.read().unwrap();
+    config.dry_run
+}
+
+/// 发送接口的返回
+#[derive(Serialize, ToSchema)]
+pub struct SendResult {
+    /// 接口调用是否成功
+    sent: bool,
+    /// 是否在消息回调中看到了这条消息
+    verified: bool,
+    /// 微信分配的消息 id，可用于撤回、引用；未在回调中看到时为空，不确认且未配置 capture_ms 时不等待回调，也为空
+    msg_id: Option<u64>,
+}
+
+// 返回是否确认发送和等待消息回调的时长；不确认时按 capture_ms 短暂等待以取得消息 id，默认不等待
+fn send_wait(query: &DryRunQuery) -> (bool, Duration) {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().send_verify.clone();
+    if query.verify.unwrap_or(config.enabled) {
+        (true, Duration::from_secs(config.timeout_secs.max(1)))
+    } else {
+        (false, Duration::from_millis(config.capture_ms))
+    }
+}
+
+// 调用发送接口，等待自己发出的消息出现在回调中，返回消息 id
+async fn send_and_verify<F>(
+    wechat: Arc<Mutex<WeChat>>,
+    dry: &DryRunQuery,
+    desc: &str,
+    receiver: String,
+    expect: Expect,
+    send: F,
+) -> Result<Json, Infallible>
+where
+    F: FnOnce(&WeChat) -> Result<bool, Box<dyn std::error::Error>>,
+{
+    let (verify, timeout) = send_wait(dry);
+    let since = send_log::mark();
+    let (sent, listening) = {
+        let wechat = wechat.lock().unwrap();
+        match send(&*wechat) {
+            Ok(sent) => (sent, wechat.listening.load(Ordering::Relaxed)),
+            Err(e) => return Ok(api_error(format!("{}失败: {}", desc, e))),
+        }
+    };
+    // 没开消息接收时收不到回调，拿不到消息 id
+    let msg_id = if sent && listening && !timeout.is_zero() {
+        let roomid = receiver.clone();
+        tokio::task::spawn_blocking(move || send_log::wait(&roomid, &expect, since, timeout))
+            .await
+            .unwrap_or(None)
+    } else {
+        None
+    };
+    if verify && sent && msg_id.is_none() {
+        warn!("{}后未能确认消息已发出: {}", desc, receiver);
+    }
+    Ok(api_ok(SendResult {
+        sent,
+        verified: msg_id.is_some(),
+        msg_id,
+    }))
+}
+
+fn dry_run_reply<T: Serialize>(action: &str, params: &T, check: Result<(), String>) -> Json {
+    let params = serde_json::to_value(params).unwrap_or(Value::Null);
+    if let Err(error) = check {
+        info!("[演练] {} 参数错误: {}", action, error);
+        return api_error(format!("{}失败: {}", action, error));
+    }
+    info!("[演练] {}: {}", action, params);
+    api_ok(DryRunResult {
+        dry_run: true,
+        action: action.to_string(),
+        params,
+    })
+}
+
+fn check_receiver(receiver: &str) -> Result<(), String> {
+    wxid::check_id(receiver).map(|_| ()).map_err(|e| format!("receiver {}", e))
+}
+
+fn check_room(roomid: &str) -> Result<(), String> {
+    wxid::check_room(roomid)
+}
+
+fn check_wxids(wxids: &str) -> Result<(), String> {
+    wxid::check_users(wxids).map_err(|e| format!("wxid {}", e))
+}
+
+// 表情发送本地文件或已缓存的表情，返回要发送的路径
+fn emoji_path(emoji: &EmojiMsg) -> Result<String, String> {
+    if !emoji.md5.is_empty() {
+        return media_service::find_cached_emotion(&emoji.md5)
+            .map(|p| p.to_string_lossy().to_string())
+            .ok_or_else(|| format!("表情 {} 未缓存", emoji.md5));
+    }
+    if emoji.path.is_empty() {
+        return Err("需要指定 path 或 md5".to_string());
+    }
+    let lower = emoji.path.to_lowercase();
+    if !lower.ends_with(".gif") && !lower.ends_with(".emoji") {
+        return Err(format!("只支持 gif 或 emoji 格式的表情: {}", emoji.path));
+    }
+    check_send_path(&emoji.path, "")?;
+    Ok(emoji.path.clone())
+}
+
+// 本地路径需存在，网络地址和 base64 在发送时才会处理
+// 本地视频需要是 mp4，下载和 base64 的内容统一保存为 mp4
+fn check_video_path(path: &str, base64: &str) -> Result<(), String> {
+    if !base64.is_empty() || path.starts_with("http") || path.to_lowercase().ends_with(".mp4") {
+        return Ok(());
+    }
+    Err(format!("只支持 mp4 格式的视频: {}", path))
+}
+
+fn check_send_path(path: &str, base64: &str) -> Result<(), String> {
+    if !base64.is_empty() || path.starts_with("http") {
+        return Ok(());
+    }
+    if !Path::new(path).exists() {
+        return Err(format!("文件不存在: {}", path));
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize, IntoParams)]
 #[into_params(parameter_in = Query)]
 pub struct Id {
     id: u64,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubjectQuery {
+    /// 联系人 wxid
+    wxid: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ContactsQuery {
+    /// 页码，从 1 开始
+    page: Option<usize>,
+    /// 每页数量
+    size: Option<usize>,
+    /// 逗号分隔的返回字段
+    fields: Option<String>,
+    /// 联系人类型
+    #[serde(rename = "type")]
+    kind: Option<ContactKind>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ContactList {
+    /// 联系人列表，指定 fields 时只包含所选字段
+    #[schema(value_type = Vec<RpcContact>)]
+    contacts: Vec<serde_json::Value>,
+    /// 过滤后的联系人总数
+    total: usize,
+}
+
 #[derive(Debug, Deserialize, IntoParams)]
 #[into_params(parameter_in = Query)]
 pub struct RoomId {
@@ -168,6 +751,422 @@ pub struct RoomId {
     wxids: Option<String>, // 新增过滤字段
 }
 
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SendText {
+    #[serde(flatten)]
+    text: TextMsg,
+    /// 要 @ 的群成员，可以是 wxid 或群昵称，all 表示 @所有人；自动填入 aters 并在内容前加上 @群昵称
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(example = json!(["wxid_a", "张三"]))]
+    mentions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct MentionQuery {
+    /// 群ID
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 含有 @昵称 的消息内容
+    #[schema(example = "@张三\u{2005}@李四 开会了")]
+    text: String,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct ResolvedMention {
+    /// 消息中 @ 后的文本
+    mention: String,
+    /// 匹配到的成员，没找到时为空
+    wxid: Option<String>,
+    /// 成员的群昵称
+    name: Option<String>,
+    /// 是否完全匹配
+    exact: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RoomQuery {
+    /// 群ID
+    roomid: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// 只推送该会话的事件
+    #[serde(default)]
+    roomid: Option<String>,
+    /// 只推送这些类型，多个用逗号分隔，如 revoke,join,kick
+    #[serde(default)]
+    kinds: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BridgeQuery {
+    /// telegram 或 discord，为空时返回全部平台
+    #[serde(default)]
+    platform: Option<String>,
+    /// 按微信账号查找，同一身份的其他账号也能查到
+    #[serde(default)]
+    wxid: Option<String>,
+    /// 按外部平台的用户 id 查找
+    #[serde(default)]
+    external_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ThreadQuery {
+    /// telegram 或 discord
+    platform: String,
+    /// 按微信消息 id 查找
+    #[serde(default)]
+    msg_id: Option<u64>,
+    /// 外部平台的会话 id，与 external_id 一起使用
+    #[serde(default)]
+    chat: Option<String>,
+    /// 按外部平台的消息 id 查找
+    #[serde(default)]
+    external_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReceiptQuery {
+    /// 发送人 wxid，为空时返回全部
+    #[serde(default)]
+    sender: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionAction {
+    Grant,
+    Revoke,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PermissionChange {
+    /// 群ID
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 要授权或撤销的 wxid，多个用逗号分隔
+    #[schema(example = "wxid_a,wxid_b")]
+    wxids: String,
+    action: PermissionAction,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct RoomPermissions {
+    roomid: String,
+    /// 群主，自动拥有权限，查不到群时为空
+    owner: Option<String>,
+    /// 手动授权的 wxid
+    wxids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewAnnouncement {
+    /// 群ID
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 通知内容
+    #[schema(example = "明天上午 9 点开会，收到请回复")]
+    content: String,
+    /// 确认词，不填时使用配置的 announcement.ack_keywords
+    #[serde(default)]
+    #[schema(example = json!(["收到", "+1"]))]
+    keywords: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Announce {
+    /// 要发送的群ID，重复的只发一次
+    #[schema(example = json!(["88888888888@chatroom", "99999999999@chatroom"]))]
+    rooms: Vec<String>,
+    /// 通知内容，{room} 替换为群名，{admin} 替换为群主昵称
+    #[schema(example = "{room} 的各位：今晚 8 点例行维护，有问题请找 {admin}")]
+    content: String,
+    /// 账号是群主或管理员时是否 @所有人，默认为 true
+    #[serde(default = "default_at_all")]
+    at_all: bool,
+}
+
+fn default_at_all() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AtAll {
+    /// 群ID
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 消息内容，自动加上 @所有人 前缀
+    #[schema(example = "今晚 8 点例行维护")]
+    content: String,
+    /// 定时发送的时间，unix 秒或 YYYY-MM-DD HH:MM:SS，为空或已过去时立即发送
+    #[serde(default)]
+    #[schema(example = "2024-01-01 20:00:00")]
+    send_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewDistribution {
+    /// 要分发的本地文件
+    #[schema(example = "C:/files/课件.zip")]
+    path: String,
+    /// 目标群ID，按顺序发送，重复的只发一次
+    #[schema(example = json!(["88888888888@chatroom", "99999999999@chatroom"]))]
+    rooms: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ContactCard {
+    /// 名片中的联系人，须在通讯录中
+    #[schema(example = "wxid_xxxxxxxxxxxxx")]
+    wxid: String,
+    /// 接收人
+    #[schema(example = "filehelper")]
+    receiver: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Location {
+    /// 纬度，GCJ-02 坐标
+    #[schema(example = 31.2304)]
+    lat: f64,
+    /// 经度，GCJ-02 坐标
+    #[schema(example = 121.4737)]
+    lng: f64,
+    /// 地点名称
+    #[schema(example = "人民广场")]
+    label: String,
+    /// 详细地址
+    #[serde(default)]
+    #[schema(example = "上海市黄浦区人民大道")]
+    address: String,
+    /// 接收人
+    #[schema(example = "filehelper")]
+    receiver: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarkdownMsg {
+    /// Markdown 内容
+    #[schema(example = "| 名称 | 数量 |\n| --- | --- |\n| 苹果 | 3 |")]
+    markdown: String,
+    /// 接收人
+    #[schema(example = "filehelper")]
+    receiver: String,
+    /// 图片宽度，为空时使用 markdown.width 配置
+    #[serde(default)]
+    #[schema(example = 800)]
+    width: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewBatch {
+    /// 按顺序发送的消息，每条可以发给不同的接收人
+    messages: Vec<TextMsg>,
+    /// 两条消息之间的间隔，单位毫秒，不能小于 batch.interval_ms，更小或为空时使用 batch.interval_ms
+    #[serde(default)]
+    #[schema(example = 1000)]
+    interval_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RuleTest {
+    /// 消息内容
+    #[schema(example = "天气 上海")]
+    content: String,
+    /// 发送人 wxid
+    #[schema(example = "wxid_xxxxxxxxxxxxx")]
+    sender: String,
+    /// 群ID，为空时按私聊处理
+    #[serde(default)]
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 消息时间，unix 秒或 YYYY-MM-DD HH:MM:SS，为空时为当前时间
+    #[serde(default)]
+    #[schema(example = "2024-01-01 20:00:00")]
+    at: Option<String>,
+    /// 消息类型，为空时为 1 文本消息
+    #[serde(default, rename = "type")]
+    #[schema(example = 1)]
+    kind: Option<u32>,
+    /// 是否 @ 了自己，用于 is_at_me 条件
+    #[serde(default)]
+    at_me: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EmojiMsg {
+    /// 本地 gif 或 emoji 文件，与 md5 二选一
+    #[serde(default)]
+    #[schema(example = "C:/表情/doge.gif")]
+    path: String,
+    /// 收到过的表情的 md5，使用缓存的表情文件
+    #[serde(default)]
+    #[schema(example = "0123456789abcdef0123456789abcdef")]
+    md5: String,
+    /// 消息接收人
+    #[schema(example = "wxid_a")]
+    receiver: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewTemplate {
+    /// 模板名称，同名的会被覆盖
+    #[schema(example = "welcome")]
+    name: String,
+    /// 文本消息的内容，用 {{变量}} 表示要填入的地方
+    #[serde(default)]
+    #[schema(example = "欢迎 {{name}} 加入 {{team}}")]
+    text: String,
+    /// 设置后发送卡片消息，text 不再使用
+    #[serde(default)]
+    rich_text: Option<RichTemplate>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SendTemplate {
+    /// 模板名称
+    #[schema(example = "welcome")]
+    name: String,
+    /// 消息接收人，wxid 或者 roomid
+    #[schema(example = "filehelper")]
+    receiver: String,
+    /// 变量名 -> 值，值可以是字符串、数字或布尔值
+    #[serde(default)]
+    #[schema(value_type = Object, example = json!({"name": "张三", "team": "产品组"}))]
+    vars: HashMap<String, Value>,
+    /// 文本模板的消息中要 @ 的群成员，同 /text
+    #[serde(default)]
+    mentions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewIdentity {
+    /// 主账号
+    #[schema(example = "wxid_a")]
+    canonical: String,
+    /// 显示名，为空时使用群昵称
+    #[serde(default)]
+    name: String,
+    /// 同一个人的其他账号，为空表示取消合并
+    #[schema(example = json!(["wxid_b"]))]
+    aliases: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewBridgeIdentity {
+    /// telegram 或 discord
+    #[schema(example = "telegram")]
+    platform: String,
+    /// 微信账号
+    #[schema(example = "wxid_a")]
+    wxid: String,
+    /// 外部平台的用户 id，为空表示删除映射
+    #[schema(example = "123456789")]
+    external_id: String,
+    /// 转发到外部平台时显示的名字
+    #[serde(default)]
+    display_name: String,
+    /// 转发到外部平台时使用的头像地址
+    #[serde(default)]
+    avatar: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewThreadLink {
+    /// telegram 或 discord
+    #[schema(example = "telegram")]
+    platform: String,
+    /// 微信消息 id
+    #[schema(example = 1234567890)]
+    msg_id: u64,
+    /// 微信消息所在的群或联系人
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 外部平台的会话 id
+    #[schema(example = "-1001234567890")]
+    chat: String,
+    /// 外部平台的消息 id
+    #[schema(example = "42")]
+    external_id: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewPoll {
+    /// 群ID
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 投票问题
+    #[schema(example = "周五聚餐吃什么")]
+    question: String,
+    /// 选项，至少两个
+    #[schema(example = json!(["火锅", "烧烤"]))]
+    options: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewRaffle {
+    /// 群ID
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 参与关键词
+    #[schema(example = "抽奖")]
+    keyword: String,
+    /// 中奖人数
+    #[schema(example = 3)]
+    count: usize,
+    /// 报名时长，单位分钟
+    #[schema(example = 30)]
+    minutes: u32,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SinkValidation {
+    /// 地址类型，默认为 http 回调
+    #[serde(default = "default_sink_kind")]
+    kind: SinkKind,
+    /// 要检查的地址，为空时检查配置中的所有回调和 socketIO 地址
+    #[serde(default)]
+    #[schema(example = "https://example.com/wechat/callback")]
+    url: Option<String>,
+}
+
+fn default_sink_kind() -> SinkKind {
+    SinkKind::Http
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PurgeRequest {
+    /// 只删除该会话的消息
+    #[serde(default)]
+    #[schema(example = "12345678@chatroom")]
+    roomid: Option<String>,
+    /// 起始时间（含），unix 秒或 2024-01-01 00:00:00
+    #[serde(default)]
+    from: Option<String>,
+    /// 结束时间（不含），格式同 from
+    #[serde(default)]
+    #[schema(example = "2024-01-01 00:00:00")]
+    to: Option<String>,
+    /// 同时删除这些消息引用的、位于 file_dir 下的媒体文件
+    #[serde(default)]
+    media: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PauseRequest {
+    /// 暂停原因，会记录在日志和状态中
+    #[serde(default)]
+    #[schema(example = "规则误触发刷屏")]
+    reason: String,
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct Image {
     /// 消息里的 id
@@ -213,6 +1212,10 @@ pub struct Member {
     /// 群内昵称
     pub name: String,
     pub state: i32,
+    /// 小头像地址
+    pub avatar: Option<String>,
+    /// 邀请人 wxid，RoomData 中没有记录时为空
+    pub inviter: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -263,21 +1266,245 @@ pub struct DownloadFileParams {
     thumb: String,
 }
 
-pub fn get_routes(
-    wechat: Arc<Mutex<WeChat>>,
-) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    let config = Arc::new(Config::from("/api-doc.json"));
-
-    #[derive(OpenApi)]
-    #[openapi(
-        info(description = "<a href='https://github.com/lich0821/WeChatFerry'>WeChatFerry</a> 一个玩微信的工具。<table align='left'><tbody><tr><td align='center'><img width='160' alt='碲矿' src='https://s2.loli.net/2023/09/25/fub5VAPSa8srwyM.jpg'><div align='center' width='200'>后台回复 <code>WCF</code> 加群交流</div></td><td align='center'><img width='160' alt='赞赏' src='https://s2.loli.net/2023/09/25/gkh9uWZVOxzNPAX.jpg'><div align='center' width='200'>如果你觉得有用</div></td><td width='20%'></td><td width='20%'></td><td width='20%'></td></tr></tbody></table>"),
-        paths(refresh_qrcode, is_login, get_self_wxid, get_user_info, get_contacts, get_dbs, get_tables, get_msg_types, save_audio,
-            refresh_pyq, send_text, send_image, send_file, send_rich_text, send_pat_msg, forward_msg, save_image,save_file,
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct ResolveMediaParams {
+    /// 消息里的 id
+    id: u64,
+    /// 超时时间，单位秒
+    #[serde(default = "default_media_timeout")]
+    timeout: u8,
+}
+
+fn default_media_timeout() -> u8 {
+    10
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct ThumbnailParams {
+    /// 消息里的 id
+    id: u64,
+    /// 缩略图最长边，单位像素
+    #[serde(default = "default_thumbnail_max")]
+    max: u32,
+    /// 超时时间，单位秒
+    #[serde(default = "default_media_timeout")]
+    timeout: u8,
+}
+
+fn default_thumbnail_max() -> u32 {
+    256
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct VideoPreviewParams {
+    /// 消息里的 id
+    id: u64,
+    /// 是否强制用 ffmpeg 截取首帧
+    #[serde(default)]
+    frame: bool,
+    /// 超时时间，单位秒
+    #[serde(default = "default_media_timeout")]
+    timeout: u8,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FriendCheck {
+    /// 用于探测的群，建议使用只有自己的小群
+    #[schema(example = "88888888888@chatroom")]
+    roomid: String,
+    /// 待检测的 wxid 列表，单次最多 20 个
+    wxids: Vec<String>,
+    /// 确认已知晓风险：对方会短暂被拉入群并可能收到提示
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FriendState {
+    /// 仍是好友
+    Normal,
+    /// 无法拉入群，可能已被对方删除或拉黑
+    Removed,
+    /// 本地通讯录中已不是好友，未探测
+    NotInContacts,
+    /// 探测出错
+    Unknown,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FriendStatus {
+    wxid: String,
+    state: FriendState,
+    detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FriendCheckReport {
+    /// 风险提示
+    warning: String,
+    results: Vec<FriendStatus>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReplayQuery {
+    /// tap.dir 消息记录目录中的 NDJSON 文件名，不能是目录外的文件，不传则读取请求体
+    file: Option<String>,
+    /// 相邻两条消息的间隔，单位毫秒
+    #[serde(default)]
+    interval_ms: u64,
+    /// 最多回放条数
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BackfillQuery {
+    /// 回填最近多少天的消息，0 表示全部
+    days: u32,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HeatmapQuery {
+    /// 起始时间（含），unix 秒或 2024-01-01 00:00:00，为空表示不限
+    #[serde(default)]
+    from: Option<String>,
+    /// 结束时间（不含），格式同 from
+    #[serde(default)]
+    to: Option<String>,
+}
+
+/// 词云默认返回的词数
+const WORD_CLOUD_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WordCloudQuery {
+    /// 统计范围：day 今天、week 最近 7 天、month 最近 30 天、all 全部
+    #[serde(default)]
+    period: WordPeriod,
+    /// 最多返回的词数，默认 100
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnapshotQuery {
+    /// 微信数据库名，如 MSG0.db
+    db: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// 文件格式
+    format: ImportFormat,
+    /// data/imports 目录中的文件名，可带子目录，不传则读取请求体
+    file: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogQuery {
+    /// 最低级别：error、warn、info
+    level: Option<String>,
+    /// 起始时间，unix 秒或 2024-01-01 12:00:00
+    since: Option<String>,
+    /// 包含的关键字
+    grep: Option<String>,
+    /// 最多返回条数，默认 200
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct HealthStatus {
+    /// 已登录且自检通过
+    ok: bool,
+    logged_in: bool,
+    /// 最近一次启动自检结果
+    preflight: Option<PreflightReport>,
+    /// 看门狗自动重建消息接收的次数
+    self_heals: u32,
+    /// 最近一次自动重建记录
+    last_self_heal: Option<SelfHeal>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct SdkOverview {
+    /// 当前使用的 SDK 版本
+    active: String,
+    /// 本机安装的微信版本
+    installed_wechat: Option<String>,
+    versions: Vec<SdkVersion>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SdkSelect {
+    /// SDK 版本，不填时按本机微信版本自动选择
+    #[schema(example = "39.5.1")]
+    version: Option<String>,
+    /// 压缩包的 SHA-256，本地没有该版本需要下载时必填
+    #[serde(default)]
+    sha256: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct InstallWechat {
+    /// 微信版本，不填时使用当前 SDK 适配的版本
+    #[schema(example = "3.9.12.51")]
+    version: Option<String>,
+    /// 安装包的 SHA-256，核对一致才会保存和启动
+    #[serde(default)]
+    sha256: String,
+    /// 下载后启动安装程序
+    #[serde(default)]
+    launch: bool,
+    /// 禁止微信自动更新
+    #[serde(default)]
+    block_update: bool,
+}
+
+/// 上传的回放文件大小上限
+const REPLAY_MAX_BODY: u64 = 32 * 1024 * 1024;
+/// 导入历史消息的请求体上限，更大的文件用 file 参数
+const IMPORT_MAX_BODY: u64 = 64 * 1024 * 1024;
+
+/// 单次检测上限
+const FRIEND_CHECK_MAX: usize = 20;
+/// 两次探测间隔
+const FRIEND_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+/// 同一时间只允许一个检测任务
+static FRIEND_CHECK_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// 请求被中断时也能释放检测标记
+struct FriendCheckGuard;
+
+impl Drop for FriendCheckGuard {
+    fn drop(&mut self) {
+        FRIEND_CHECK_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn get_routes(
+    wechat: Arc<Mutex<WeChat>>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let config = Arc::new(Config::from("/api-doc.json"));
+
+    #[derive(OpenApi)]
+    #[openapi(
+        info(description = "<a href='https://github.com/lich0821/WeChatFerry'>WeChatFerry</a> 一个玩微信的工具。<table align='left'><tbody><tr><td align='center'><img width='160' alt='碲矿' src='https://s2.loli.net/2023/09/25/fub5VAPSa8srwyM.jpg'><div align='center' width='200'>后台回复 <code>WCF</code> 加群交流</div></td><td align='center'><img width='160' alt='赞赏' src='https://s2.loli.net/2023/09/25/gkh9uWZVOxzNPAX.jpg'><div align='center' width='200'>如果你觉得有用</div></td><td width='20%'></td><td width='20%'></td><td width='20%'></td></tr></tbody></table>"),
+        paths(refresh_qrcode, is_login, get_self_wxid, get_user_info, get_contacts, get_dbs, get_tables, get_msg_types, save_audio,
+            refresh_pyq, send_text, send_image, send_file, send_video, send_emoji, send_rich_text, send_xml, send_contact_card, send_location, send_markdown, send_pat_msg, forward_msg, save_image,save_file,
             recv_transfer, query_sql, accept_new_friend, add_chatroom_member, invite_chatroom_member,
-            delete_chatroom_member, revoke_msg, query_room_member, download_image, download_file),
+            delete_chatroom_member, revoke_msg, query_room_member, get_room_role, resolve_mentions, get_command_permissions, change_command_permissions, create_poll, get_poll, close_poll, create_announcement, get_announcement_acks, announce, at_all, list_at_all, create_distribution, get_distribution, resume_distribution, send_batch_text, get_batch_text, list_templates, save_template, delete_template, send_template, test_rules, list_rule_groups, enable_rule_group, disable_rule_group, get_rule_stats, list_rule_vars, delete_rule_var, list_identities, set_identity, list_bridge_identities, set_bridge_identity, list_bridge_threads, record_bridge_thread, get_name_history, get_receipts, get_checkin_stats, create_raffle, get_raffle, draw_raffle, get_quiet_queue, get_message_volume, download_image, download_file, resolve_media, get_thumbnail, get_video_preview, get_emotion, get_friends, get_chatrooms, get_muted_contacts, stream_events, check_friend_status, get_risk_budget, get_health, replay_messages, query_logs, get_sdk_versions, select_sdk_version, install_wechat, get_version, update_client, pause_automation, resume_automation, export_config, import_config, list_profiles, save_profile, apply_profile, validate_sink, purge_messages, export_subject_data, erase_subject_data, run_backup, import_messages, backfill_messages, create_snapshot, list_snapshots, get_room_heatmap, get_word_cloud, get_word_cloud_image, list_jobs, get_job, get_metrics, get_pipeline, receive_webhook),
         components(schemas(
-            ApiResponse<bool>, ApiResponse<String>, AttachMsg, AudioMsg, DbNames, DbQuery, DbTable, DbTables,
-            DecPath, FieldContent, ForwardMsg, Image, SaveFile, MemberMgmt, MsgTypes, PatMsg, PathMsg, RichText, RpcContact,
+            ApiResponse<bool>, ApiResponse<String>, AttachMsg, BudgetStatus, CheckinStat, ConfigBundle, Draw, DryRunResult, HandlerStage, HealthStatus, PauseRequest, PauseStatus, PipelineStats, PurgeReport, PurgeRequest, SubjectData, ErasureReport, BackupReport, TargetResult, ImportFormat, ImportReport, SnapshotInfo, RoomHeatmap, MemberActivity, WordCloud, WordCount, WordPeriod, Job, JobState, ReceiveStage, SinkStage, LogEntry, PreflightReport, InstallReport, InstallWechat, SdkOverview, SdkSelect, SdkVersion, SinkKind, SinkReport, SinkValidation, VersionInfo, ReplayReport, RiskOperation, AudioMsg, DbNames, DbQuery, DbTable, DbTables,
+            ContactKind, ContactList, DecPath, EmojiMsg, SendText, FieldError, SendResult, FriendCheck, FriendCheckReport, FriendState, FriendStatus, FieldContent, ForwardMsg, Image, SaveFile, MemberMgmt, MentionQuery, MsgTypes, NewPoll, OptionResult, PatMsg, PathMsg, PermissionAction, QueuedText, PollResult, NewAnnouncement, AckReport, Ack, Pending, Announce, AnnounceResult, AtAll, AtAllMessage, AtAllState, NewDistribution, DistributionReport, Delivery, DeliveryState, NewBatch, BatchReport, BatchItem, ItemState, NewTemplate, MessageTemplate, RichTemplate, SendTemplate, RuleTest, RuleTrace, ActionPreview, RuleGroup, RuleStats, RuleVar, Identity, NewIdentity, BridgeIdentity, NewBridgeIdentity, ThreadLink, NewThreadLink, NameField, NameRecord, Receipt, ReceiptFields, PermissionChange, NewRaffle, Raffle, RoomVolume, SelfHeal, ResolvedMention, RichText, XmlMsg, ContactCard, Location, MarkdownMsg, RoomPermissions, RoomRole, RpcContact,
             RpcContacts, TextMsg, Transfer, UserInfo, Verification, ApiResponse<Member>, Member, SelfInfo
         )),
         tags((name = "WCF", description = "玩微信的接口")),
@@ -288,7 +1515,211 @@ pub fn get_routes(
         .and(warp::get())
         .map(|| warp::reply::json(&ApiDoc::openapi()));
 
-    let swagger_ui = warp::path("swagger")
+    let message_schema = warp::path!("schemas" / "message.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&payload::message_json_schema()));
+
+    let replay = warp::path!("admin" / "replay")
+        .and(warp::post())
+        .and(warp::query::<ReplayQuery>())
+        .and(warp::body::content_length_limit(REPLAY_MAX_BODY))
+        .and(warp::body::bytes())
+        .and_then(replay_messages);
+
+    let logs = warp::path!("admin" / "logs")
+        .and(warp::get())
+        .and(warp::query::<LogQuery>())
+        .and_then(query_logs);
+
+    let sdk_versions = warp::path!("admin" / "sdk-version")
+        .and(warp::get())
+        .and_then(get_sdk_versions);
+
+    let sdk_select = warp::path!("admin" / "sdk-version")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(DEFAULT_BODY_LIMIT))
+        .and(warp::body::json())
+        .and_then(select_sdk_version);
+
+    let install = warp::path!("admin" / "install-wechat")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(DEFAULT_BODY_LIMIT))
+        .and(warp::body::json())
+        .and_then(install_wechat);
+
+    let version = warp::path!("admin" / "version")
+        .and(warp::get())
+        .and_then(get_version);
+
+    let update = warp::path!("admin" / "update")
+        .and(warp::post())
+        .and_then(update_client);
+
+    let pause = warp::path!("admin" / "pause")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(DEFAULT_BODY_LIMIT))
+        .and(warp::body::json())
+        .and_then(pause_automation);
+
+    let resume = warp::path!("admin" / "resume")
+        .and(warp::post())
+        .and_then(resume_automation);
+
+    let config_export = warp::path!("admin" / "config" / "export")
+        .and(warp::get())
+        .and_then(export_config);
+
+    let config_import = warp::path!("admin" / "config" / "import")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit("admin/config/import")))
+        .and(warp::body::json())
+        .and_then(import_config);
+
+    let profiles = warp::path!("admin" / "config" / "profiles")
+        .and(warp::get())
+        .and_then(list_profiles);
+
+    let profile_save = warp::path!("admin" / "config" / "profiles" / String)
+        .and(warp::post())
+        .and_then(save_profile);
+
+    let profile_apply = warp::path!("admin" / "config" / "profiles" / String / "apply")
+        .and(warp::post())
+        .and_then(apply_profile);
+
+    let validate_sink = warp::path!("admin" / "validate-sink")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(DEFAULT_BODY_LIMIT))
+        .and(warp::body::json())
+        .and_then(validate_sink);
+
+    let rules_test = warp::path!("rules" / "test")
+        .and(warp::post())
+        .and(json_body("rules/test"))
+        .and_then(test_rules);
+
+    let rule_groups = warp::path!("rules" / "groups")
+        .and(warp::get())
+        .and_then(list_rule_groups);
+
+    let rule_stats = warp::path!("rules" / "stats")
+        .and(warp::get())
+        .and_then(get_rule_stats);
+
+    let rule_vars = warp::path!("rules" / "vars")
+        .and(warp::get())
+        .and_then(list_rule_vars);
+
+    let rule_var_delete = warp::path!("rules" / "vars" / String)
+        .and(warp::delete())
+        .and_then(delete_rule_var);
+
+    let rule_group_enable = warp::path!("rules" / "groups" / String / "enable")
+        .and(warp::post())
+        .and_then(enable_rule_group);
+
+    let rule_group_disable = warp::path!("rules" / "groups" / String / "disable")
+        .and(warp::post())
+        .and_then(disable_rule_group);
+
+    let template_delete = warp::path!("templates" / String)
+        .and(warp::delete())
+        .and_then(delete_template);
+
+    let purge = warp::path!("admin" / "purge")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(DEFAULT_BODY_LIMIT))
+        .and(warp::body::json())
+        .and_then(purge_messages);
+
+    let privacy_export = warp::path!("privacy" / "export")
+        .and(warp::get())
+        .and(warp::query::<SubjectQuery>())
+        .and_then(export_subject_data);
+
+    let privacy_erase = warp::path!("privacy" / "data")
+        .and(warp::delete())
+        .and(warp::query::<SubjectQuery>())
+        .and_then(erase_subject_data);
+
+    let import = warp::path!("admin" / "import")
+        .and(warp::post())
+        .and(warp::query::<ImportQuery>())
+        .and(warp::body::content_length_limit(IMPORT_MAX_BODY))
+        .and(warp::body::bytes())
+        .and_then(import_messages);
+
+    let backfill_wechat = wechat.clone();
+    let backfill = warp::path!("admin" / "backfill")
+        .and(warp::post())
+        .and(require_login("admin/backfill", wechat.clone()))
+        .and(warp::query::<BackfillQuery>())
+        .and(warp::any().map(move || backfill_wechat.clone()))
+        .and_then(backfill_messages);
+
+    let snapshot_wechat = wechat.clone();
+    let snapshot = warp::path!("admin" / "snapshot")
+        .and(warp::post())
+        .and(require_login("admin/snapshot", wechat.clone()))
+        .and(warp::query::<SnapshotQuery>())
+        .and(warp::any().map(move || snapshot_wechat.clone()))
+        .and_then(create_snapshot);
+
+    let snapshot_list = warp::path!("admin" / "snapshots")
+        .and(warp::get())
+        .and_then(list_snapshots);
+
+    let heatmap_wechat = wechat.clone();
+    let heatmap = warp::path!("stats" / "rooms" / String / "heatmap")
+        .and(warp::get())
+        .and(warp::query::<HeatmapQuery>())
+        .and(warp::any().map(move || heatmap_wechat.clone()))
+        .and_then(get_room_heatmap);
+
+    let word_cloud = warp::path!("stats" / "rooms" / String / "wordcloud")
+        .and(warp::get())
+        .and(warp::query::<WordCloudQuery>())
+        .and_then(get_word_cloud);
+
+    let word_cloud_image = warp::path!("stats" / "rooms" / String / "wordcloud.png")
+        .and(warp::get())
+        .and(warp::query::<WordCloudQuery>())
+        .and_then(get_word_cloud_image);
+
+    // 外部系统用 hook 的密钥签名，不需要访问令牌
+    let webhook_wechat = wechat.clone();
+    let webhook = warp::path!("webhook" / String)
+        .and(warp::post())
+        .and(warp::header::optional::<String>("x-webhook-timestamp"))
+        .and(warp::header::optional::<String>("x-webhook-nonce"))
+        .and(warp::header::optional::<String>("x-webhook-signature"))
+        .and(warp::body::content_length_limit(DEFAULT_BODY_LIMIT))
+        .and(warp::body::bytes())
+        .and(warp::any().map(move || webhook_wechat.clone()))
+        .and_then(receive_webhook);
+
+    let job_list = warp::path!("admin" / "jobs")
+        .and(warp::get())
+        .and_then(list_jobs);
+
+    let job = warp::path!("admin" / "jobs" / u64)
+        .and(warp::get())
+        .and_then(get_job);
+
+    let backup = warp::path!("admin" / "backup")
+        .and(warp::post())
+        .and_then(run_backup);
+
+    let pipeline = warp::path!("admin" / "pipeline")
+        .and(warp::get())
+        .and_then(get_pipeline);
+
+    let open_metrics = warp::path!("metrics")
+        .and(warp::get())
+        .and_then(get_metrics);
+
+    let swagger_ui = swagger_enabled()
+        .and(warp::path("swagger"))
         .and(warp::get())
         .and(warp::path::full())
         .and(warp::path::tail())
@@ -299,38 +1730,132 @@ pub fn get_routes(
     build_route_fn!(islogin, GET "islogin", is_login, wechat);
     build_route_fn!(selfwxid, GET "selfwxid", get_self_wxid, wechat);
     build_route_fn!(userinfo, GET "userinfo", get_user_info, wechat);
-    build_route_fn!(contacts, GET "contacts", get_contacts, wechat);
+    build_route_fn!(contacts, GET "contacts", get_contacts, QUERY ContactsQuery, wechat);
+    build_route_fn!(friends, GET "friends", get_friends, wechat);
+    build_route_fn!(chatrooms, GET "chatrooms", get_chatrooms, wechat);
+    build_route_fn!(mutedcontacts, GET "muted-contacts", get_muted_contacts, wechat);
+    build_route_fn!(events, GET "events", stream_events, QUERY EventsQuery, wechat);
     build_route_fn!(dbs, GET "dbs", get_dbs, wechat);
     build_route_fn!(tables, GET "tables", get_tables, PATH String, wechat);
     build_route_fn!(msgtypes, GET "msg-types", get_msg_types, wechat);
     build_route_fn!(pyq, GET "pyq", refresh_pyq, QUERY Id, wechat);
-    build_route_fn!(sendtext, POST "text", send_text, JSON, wechat);
-    build_route_fn!(sendimage, POST "image", send_image, JSON, wechat);
-    build_route_fn!(sendfile, POST "file", send_file, JSON, wechat);
-    build_route_fn!(sendrichtext, POST "rich-text", send_rich_text, JSON, wechat);
-    build_route_fn!(sendpatmsg, POST "pat", send_pat_msg, JSON, wechat);
-    build_route_fn!(forwardmsg, POST "forward-msg", forward_msg, JSON, wechat);
+    build_route_fn!(sendtext, POST "text", send_text, JSON DRY_RUN, wechat);
+    build_route_fn!(sendimage, POST "image", send_image, JSON DRY_RUN, wechat);
+    build_route_fn!(sendfile, POST "file", send_file, JSON DRY_RUN, wechat);
+    build_route_fn!(sendvideo, POST "video", send_video, JSON DRY_RUN, wechat);
+    build_route_fn!(sendemoji, POST "emoji", send_emoji, JSON DRY_RUN, wechat);
+    build_route_fn!(sendrichtext, POST "rich-text", send_rich_text, JSON DRY_RUN, wechat);
+    build_route_fn!(sendxml, POST "xml", send_xml, JSON DRY_RUN, wechat);
+    build_route_fn!(sendcontactcard, POST "contact-card", send_contact_card, JSON DRY_RUN, wechat);
+    build_route_fn!(sendlocation, POST "location", send_location, JSON DRY_RUN, wechat);
+    build_route_fn!(sendmarkdown, POST "markdown", send_markdown, JSON DRY_RUN, wechat);
+    build_route_fn!(sendpatmsg, POST "pat", send_pat_msg, JSON DRY_RUN, wechat);
+    build_route_fn!(forwardmsg, POST "forward-msg", forward_msg, JSON DRY_RUN, wechat);
     build_route_fn!(saveaudio, POST "audio", save_audio, JSON, wechat);
     build_route_fn!(saveimage, POST "save-image", save_image, JSON, wechat);
     build_route_fn!(savefile, POST "save-file", save_file, JSON, wechat);
     build_route_fn!(recvtransfer, POST "receive-transfer", recv_transfer, JSON, wechat);
     build_route_fn!(querysql, POST "sql", query_sql, JSON, wechat);
     build_route_fn!(acceptnewfriend, POST "accept-new-friend", accept_new_friend, JSON, wechat);
-    build_route_fn!(addchatroommember, POST "add-chatroom-member", add_chatroom_member, JSON, wechat);
-    build_route_fn!(invitechatroommember, POST "invite-chatroom-member", invite_chatroom_member, JSON, wechat);
-    build_route_fn!(deletechatroommember, POST "delete-chatroom-member", delete_chatroom_member, JSON, wechat);
+    build_route_fn!(addchatroommember, POST "add-chatroom-member", add_chatroom_member, JSON DRY_RUN, wechat);
+    build_route_fn!(invitechatroommember, POST "invite-chatroom-member", invite_chatroom_member, JSON DRY_RUN, wechat);
+    build_route_fn!(deletechatroommember, POST "delete-chatroom-member", delete_chatroom_member, JSON DRY_RUN, wechat);
     build_route_fn!(revokemsg, POST "revoke-msg", revoke_msg, QUERY Id, wechat);
+    build_route_fn!(checkfriendstatus, POST "check-friend-status", check_friend_status, JSON, wechat);
+    build_route_fn!(riskbudget, GET "risk-budget", get_risk_budget, wechat);
+    build_route_fn!(health, GET "health", get_health, wechat);
     build_route_fn!(queryroommember, GET "query-room-member", query_room_member, QUERY RoomId, wechat);
+    build_route_fn!(roomrole, GET "chatroom" / PATH String / "role", get_room_role, wechat);
+    build_route_fn!(resolvementions, POST "resolve-mentions", resolve_mentions, JSON, wechat);
+    build_route_fn!(getpermissions, GET "command-permissions", get_command_permissions, QUERY RoomQuery, wechat);
+    build_route_fn!(changepermissions, POST "command-permissions", change_command_permissions, JSON, wechat);
+    build_route_fn!(closepoll, POST "polls" / PATH u64 / "close", close_poll, wechat);
+    build_route_fn!(getpoll, GET "polls" / PATH u64, get_poll, wechat);
+    build_route_fn!(createpoll, POST "polls", create_poll, JSON, wechat);
+    build_route_fn!(announcementacks, GET "announcements" / PATH u64 / "acks", get_announcement_acks, wechat);
+    build_route_fn!(createannouncement, POST "announcements", create_announcement, JSON, wechat);
+    build_route_fn!(announcerooms, POST "announce", announce, JSON, wechat);
+    build_route_fn!(atall, POST "at-all", at_all, JSON, wechat);
+    build_route_fn!(listatall, GET "at-all", list_at_all, wechat);
+    build_route_fn!(resumedistribution, POST "distributions" / PATH u64 / "resume", resume_distribution, wechat);
+    build_route_fn!(getdistribution, GET "distributions" / PATH u64, get_distribution, wechat);
+    build_route_fn!(createdistribution, POST "distributions", create_distribution, JSON, wechat);
+    build_route_fn!(getbatchtext, GET "batch-text" / PATH u64, get_batch_text, wechat);
+    build_route_fn!(sendbatchtext, POST "batch-text", send_batch_text, JSON, wechat);
+    build_route_fn!(templates, GET "templates", list_templates, wechat);
+    build_route_fn!(savetemplate, POST "templates", save_template, JSON, wechat);
+    build_route_fn!(sendtemplate, POST "send-template", send_template, JSON DRY_RUN, wechat);
+    build_route_fn!(identities, GET "identities", list_identities, wechat);
+    build_route_fn!(setidentity, POST "identities", set_identity, JSON, wechat);
+    build_route_fn!(bridgeidentities, GET "bridge-identities", list_bridge_identities, QUERY BridgeQuery, wechat);
+    build_route_fn!(setbridgeidentity, POST "bridge-identities", set_bridge_identity, JSON, wechat);
+    build_route_fn!(bridgethreads, GET "bridge-threads", list_bridge_threads, QUERY ThreadQuery, wechat);
+    build_route_fn!(recordbridgethread, POST "bridge-threads", record_bridge_thread, JSON, wechat);
+    build_route_fn!(namehistory, GET "contact" / PATH String / "names", get_name_history, wechat);
+    build_route_fn!(receipts, GET "receipts", get_receipts, QUERY ReceiptQuery, wechat);
+    build_route_fn!(checkinstats, GET "checkin", get_checkin_stats, QUERY RoomQuery, wechat);
+    build_route_fn!(drawraffle, POST "raffles" / PATH u64 / "draw", draw_raffle, wechat);
+    build_route_fn!(getraffle, GET "raffles" / PATH u64, get_raffle, wechat);
+    build_route_fn!(createraffle, POST "raffles", create_raffle, JSON, wechat);
+    build_route_fn!(quietqueue, GET "quiet-hours", get_quiet_queue, wechat);
+    build_route_fn!(messagevolume, GET "message-volume", get_message_volume, wechat);
     build_route_fn!(downloadimage, GET "download-image", download_image, QUERY DownloadImageParams, wechat);
     build_route_fn!(downloadfile, GET "download-file", download_file, QUERY DownloadFileParams, wechat);
+    build_route_fn!(resolvemedia, GET "resolve-media", resolve_media, QUERY ResolveMediaParams, wechat);
+    build_route_fn!(thumbnail, GET "thumbnail", get_thumbnail, QUERY ThumbnailParams, wechat);
+    build_route_fn!(videopreview, GET "video-preview", get_video_preview, QUERY VideoPreviewParams, wechat);
+    build_route_fn!(emotion, GET "emotion" / PATH String, get_emotion, wechat);
 
-    api_doc
+    let routes = api_doc
+        .or(message_schema)
+        .or(replay)
+        .or(compressed(logs.boxed()))
+        .or(sdk_versions)
+        .or(sdk_select)
+        .or(install)
+        .or(version)
+        .or(update)
+        .or(pause)
+        .or(resume)
+        .or(config_export)
+        .or(config_import)
+        .or(profiles)
+        .or(profile_save)
+        .or(profile_apply)
+        .or(validate_sink)
+        .or(purge)
+        .or(rules_test)
+        .or(rule_groups)
+        .or(rule_stats)
+        .or(rule_vars)
+        .or(rule_var_delete)
+        .or(rule_group_enable)
+        .or(rule_group_disable)
+        .or(template_delete)
+        .or(privacy_export)
+        .or(privacy_erase)
+        .or(backup)
+        .or(import)
+        .or(backfill)
+        .or(snapshot)
+        .or(snapshot_list)
+        .or(heatmap)
+        .or(word_cloud)
+        .or(word_cloud_image)
+        .or(job_list)
+        .or(job)
+        .or(pipeline)
+        .or(open_metrics)
         .or(swagger_ui)
         .or(qrcode(wechat.clone()))
         .or(islogin(wechat.clone()))
         .or(selfwxid(wechat.clone()))
         .or(userinfo(wechat.clone()))
-        .or(contacts(wechat.clone()))
+        .or(compressed(contacts(wechat.clone())))
+        .or(compressed(friends(wechat.clone())))
+        .or(compressed(chatrooms(wechat.clone())))
+        .or(mutedcontacts(wechat.clone()))
+        .or(events(wechat.clone()))
         .or(dbs(wechat.clone()))
         .or(tables(wechat.clone()))
         .or(msgtypes(wechat.clone()))
@@ -338,22 +1863,70 @@ pub fn get_routes(
         .or(sendtext(wechat.clone()))
         .or(sendimage(wechat.clone()))
         .or(sendfile(wechat.clone()))
+        .or(sendvideo(wechat.clone()))
+        .or(sendemoji(wechat.clone()))
         .or(sendrichtext(wechat.clone()))
+        .or(sendxml(wechat.clone()))
+        .or(sendcontactcard(wechat.clone()))
+        .or(sendlocation(wechat.clone()))
+        .or(sendmarkdown(wechat.clone()))
         .or(sendpatmsg(wechat.clone()))
         .or(forwardmsg(wechat.clone()))
         .or(saveaudio(wechat.clone()))
         .or(saveimage(wechat.clone()))
         .or(savefile(wechat.clone()))
         .or(recvtransfer(wechat.clone()))
-        .or(querysql(wechat.clone()))
+        .or(compressed(querysql(wechat.clone())))
         .or(acceptnewfriend(wechat.clone()))
         .or(addchatroommember(wechat.clone()))
         .or(invitechatroommember(wechat.clone()))
         .or(deletechatroommember(wechat.clone()))
         .or(revokemsg(wechat.clone()))
-        .or(queryroommember(wechat.clone()))
+        .or(checkfriendstatus(wechat.clone()))
+        .or(riskbudget(wechat.clone()))
+        .or(health(wechat.clone()))
+        .or(compressed(queryroommember(wechat.clone())))
+        .or(roomrole(wechat.clone()))
+        .or(resolvementions(wechat.clone()))
+        .or(getpermissions(wechat.clone()))
+        .or(changepermissions(wechat.clone()))
+        .or(closepoll(wechat.clone()))
+        .or(getpoll(wechat.clone()))
+        .or(createpoll(wechat.clone()))
+        .or(announcementacks(wechat.clone()))
+        .or(createannouncement(wechat.clone()))
+        .or(announcerooms(wechat.clone()))
+        .or(atall(wechat.clone()))
+        .or(listatall(wechat.clone()))
+        .or(resumedistribution(wechat.clone()))
+        .or(getdistribution(wechat.clone()))
+        .or(createdistribution(wechat.clone()))
+        .or(getbatchtext(wechat.clone()))
+        .or(sendbatchtext(wechat.clone()))
+        .or(templates(wechat.clone()))
+        .or(savetemplate(wechat.clone()))
+        .or(sendtemplate(wechat.clone()))
+        .or(identities(wechat.clone()))
+        .or(setidentity(wechat.clone()))
+        .or(bridgeidentities(wechat.clone()))
+        .or(setbridgeidentity(wechat.clone()))
+        .or(bridgethreads(wechat.clone()))
+        .or(recordbridgethread(wechat.clone()))
+        .or(namehistory(wechat.clone()))
+        .or(receipts(wechat.clone()))
+        .or(checkinstats(wechat.clone()))
+        .or(drawraffle(wechat.clone()))
+        .or(getraffle(wechat.clone()))
+        .or(createraffle(wechat.clone()))
+        .or(quietqueue(wechat.clone()))
+        .or(messagevolume(wechat.clone()))
         .or(downloadimage(wechat.clone()))
         .or(downloadfile(wechat.clone()))
+        .or(resolvemedia(wechat.clone()))
+        .or(thumbnail(wechat.clone()))
+        .or(videopreview(wechat.clone()))
+        .or(emotion(wechat.clone()));
+    webhook.or(require_token().and(routes)).recover(handle_rejection)
 }
 
 async fn serve_swagger(
@@ -445,12 +2018,190 @@ pub async fn get_user_info(wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallibl
     get,
     tag = "WCF",
     path = "/contacts",
+    params(
+        ("page" = Option<usize>, Query, description = "页码，从 1 开始，不传则返回全部"),
+        ("size" = Option<usize>, Query, description = "每页数量，默认 100"),
+        ("fields" = Option<String>, Query, example = "wxid,name,remark", description = "逗号分隔的返回字段"),
+        ("type" = Option<ContactKind>, Query, description = "联系人类型：friend、room、official、system")
+    ),
     responses(
         (status = 200, body = ApiResponseContacts, description = "查询所有联系人，包括服务号、公众号、群聊等")
     )
 )]
-pub async fn get_contacts(wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    wechat_api_handler!(wechat, WeChat::get_contacts, "获取所有联系人")
+pub async fn get_contacts(query: ContactsQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let result = {
+        let wechat = wechat.lock().unwrap();
+        wechat.get_contacts()
+    };
+    let rpc_contacts = match result {
+        Ok(rpc_contacts) => rpc_contacts,
+        Err(error) => {
+            return Ok(warp::reply::json(&ApiResponse::<()> {
+                status: 1,
+                code: None,
+                error: Some(format!("获取所有联系人失败: {}", error)),
+                data: None,
+            }))
+        }
+    };
+
+    let filtered: Vec<RpcContact> = rpc_contacts
+        .contacts
+        .into_iter()
+        .filter(|c| query.kind.map_or(true, |kind| contact::classify(&c.wxid) == kind))
+        .collect();
+    let total = filtered.len();
+
+    let page: Vec<RpcContact> = if query.page.is_some() || query.size.is_some() {
+        let size = query.size.unwrap_or(100).max(1);
+        let page = query.page.unwrap_or(1).max(1);
+        filtered.into_iter().skip((page - 1).saturating_mul(size)).take(size).collect()
+    } else {
+        filtered
+    };
+
+    let fields: HashSet<&str> = query
+        .fields
+        .as_deref()
+        .map(|s| s.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()).collect())
+        .unwrap_or_default();
+    let contacts = page
+        .iter()
+        .map(|c| {
+            let mut value = json!(c);
+            if !fields.is_empty() {
+                if let Some(obj) = value.as_object_mut() {
+                    obj.retain(|k, _| fields.contains(k.as_str()));
+                }
+            }
+            value
+        })
+        .collect();
+
+    Ok(warp::reply::json(&ApiResponse {
+        status: 0,
+        code: None,
+        error: None,
+        data: Some(ContactList { contacts, total }),
+    }))
+}
+
+/// 获取好友列表
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/friends",
+    responses(
+        (status = 200, body = ApiResponseContactVec, description = "只返回通讯录中的好友，不含群聊、公众号、系统账号")
+    )
+)]
+pub async fn get_friends(wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    wechat_api_handler!(
+        wechat,
+        |wc: &WeChat| -> Result<Vec<RpcContact>, Box<dyn std::error::Error>> {
+            let flags = wc.query_contact_flags()?;
+            Ok(wc
+                .get_contacts()?
+                .contacts
+                .into_iter()
+                .filter(|c| contact::classify(&c.wxid) == ContactKind::Friend)
+                .filter(|c| flags.get(&c.wxid).map_or(false, |f| f.is_friend()))
+                .collect())
+        },
+        "获取好友列表"
+    )
+}
+
+/// 获取群聊列表
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/chatrooms",
+    responses(
+        (status = 200, body = ApiResponseContactVec, description = "只返回群聊")
+    )
+)]
+pub async fn get_chatrooms(wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    wechat_api_handler!(
+        wechat,
+        |wc: &WeChat| -> Result<Vec<RpcContact>, Box<dyn std::error::Error>> {
+            Ok(wc
+                .get_contacts()?
+                .contacts
+                .into_iter()
+                .filter(|c| contact::classify(&c.wxid) == ContactKind::Room)
+                .collect())
+        },
+        "获取群聊列表"
+    )
+}
+
+/// 获取设置了消息免打扰的会话
+///
+/// 重新读取微信里的免打扰设置并合并配置中的 mute、unmute，http、socketio 推送默认跳过这些会话。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/muted-contacts",
+    responses(
+        (status = 200, body = ApiResponseStrings, description = "免打扰的好友和群 id")
+    )
+)]
+pub async fn get_muted_contacts(wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    wechat_api_handler!(
+        wechat,
+        |wc: &WeChat| -> Result<Vec<String>, Box<dyn std::error::Error>> { Ok(dnd_service::refresh(wc)?) },
+        "获取免打扰会话"
+    )
+}
+
+// 事件是否符合订阅条件
+fn event_wanted(event: &TypedEvent, roomid: &Option<String>, kinds: &[String]) -> bool {
+    roomid.as_ref().map_or(true, |r| *r == event.roomid)
+        && (kinds.is_empty() || kinds.iter().any(|k| k == event.event.kind()))
+}
+
+/// 订阅系统事件
+///
+/// 以 Server-Sent Events 推送撤回、进群、踢人、退群、改群名、红包、转账、拍一拍等系统消息，event 为类型，data 为 JSON，不包含普通聊天消息。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/events",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "事件流", content_type = "text/event-stream")
+    )
+)]
+pub async fn stream_events(query: EventsQuery, _wechat: Arc<Mutex<WeChat>>) -> Result<impl Reply, Infallible> {
+    let roomid = query.roomid.filter(|r| !r.trim().is_empty());
+    let kinds: Vec<String> = query
+        .kinds
+        .unwrap_or_default()
+        .split(',')
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+    let stream = futures_util::stream::unfold(
+        (system_event::subscribe(), roomid, kinds),
+        |(mut rx, roomid, kinds)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) if event_wanted(&event, &roomid, &kinds) => {
+                        let sse = warp::sse::Event::default().event(event.event.kind()).json_data(&event);
+                        match sse {
+                            Ok(sse) => return Some((Ok::<_, Infallible>(sse), (rx, roomid, kinds))),
+                            Err(e) => warn!("系统事件序列化失败: {}", e),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => warn!("事件订阅者处理过慢，丢弃了 {} 条事件", n),
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
 }
 
 /// 获取所有可查询数据库
@@ -509,18 +2260,183 @@ pub async fn refresh_pyq(query: Id, wechat: Arc<Mutex<WeChat>>) -> Result<Json,
     wechat_api_handler!(wechat, WeChat::refresh_pyq, query.id, "刷新朋友圈")
 }
 
+// 把 mentions 解析为 aters 并在内容前加上 @群昵称，返回要发送的消息和是否 @所有人
+fn mention_text(wechat: &WeChat, text: SendText) -> Result<(TextMsg, bool), String> {
+    let SendText { mut text, mentions } = text;
+    if mentions.is_empty() {
+        return Ok((text, false));
+    }
+    let members = match wechat.query_room_member(text.receiver.clone()).map_err(|e| e.to_string())? {
+        Some(members) => members,
+        None => return Err("群聊不存在".to_string()),
+    };
+    let names: Vec<(String, String)> = members.into_iter().map(|m| (m.wxid, m.name)).collect();
+    let targets = mention::targets(&mentions, &names)?;
+    if targets.all {
+        let content = at_all_service::text(&text.receiver, &text.msg);
+        return Ok((content, true));
+    }
+    let mut aters: Vec<&str> = text.aters.split(',').map(str::trim).filter(|w| !w.is_empty()).collect();
+    let mut shown = vec![];
+    for &i in &targets.members {
+        let (wxid, name) = &names[i];
+        if !aters.contains(&wxid.as_str()) {
+            aters.push(wxid);
+        }
+        shown.push(if name.is_empty() { wxid.as_str() } else { name.as_str() });
+    }
+    text.aters = aters.join(",");
+    text.msg = format!("{}{}", mention::prefix(&shown), text.msg);
+    Ok((text, false))
+}
+
 /// 发送文本消息
+///
+/// 群消息可以用 mentions 指定要 @ 的成员，不用再自己拼 @昵称 和 aters。@所有人 时与 /at-all 一样需要是群主或管理员并受次数限制。
 #[utoipa::path(
     post,
     tag = "WCF",
     path = "/text",
-    request_body = TextMsg,
+    params(DryRunQuery),
+    request_body = SendText,
     responses(
-        (status = 200, body = ApiResponseBool, description = "发送文本消息")
+        (status = 200, body = ApiResponseSendResult, description = "发送文本消息"),
+        (status = 400, body = ApiResponseFieldErrors, description = "参数校验失败")
     )
 )]
-pub async fn send_text(text: TextMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    wechat_api_handler!(wechat, WeChat::send_text, text, "发送文本消息")
+pub async fn send_text(
+    text: SendText,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    let resolved = mention_text(&wechat.lock().unwrap(), text);
+    if is_dry_run(&dry) {
+        return Ok(match &resolved {
+            Ok((text, _)) => dry_run_reply("发送文本消息", text, check_receiver(&text.receiver)),
+            Err(e) => dry_run_reply("发送文本消息", &Value::Null, Err(e.clone())),
+        });
+    }
+    let text = match resolved {
+        Ok((text, false)) => text,
+        Ok((text, true)) => {
+            let wc = wechat.lock().unwrap();
+            if let Err(e) = at_all_service::check_admin(&wc, &text.receiver) {
+                return Ok(api_error_code(CODE_NOT_ADMIN, e));
+            }
+            if let Err(e) = at_all_service::consume(&text.receiver) {
+                return Ok(api_error_code(CODE_BUDGET_EXCEEDED, e));
+            }
+            text
+        }
+        Err(e) => return Ok(api_error(format!("发送文本消息失败: {}", e))),
+    };
+    if let Err(rsp) = moderate(&text.receiver, text.msg.clone(), &dry).await {
+        return Ok(rsp);
+    }
+    let (receiver, content) = (text.receiver.clone(), text.msg.clone());
+    send_and_verify(wechat, &dry, "发送文本消息", receiver, Expect::Text(content), move |wc| wc.send_text(text)).await
+}
+
+// 图片扩展名，下载时按 content-type，base64 时按 path 的后缀
+fn image_extension(path: &str, content_type: Option<&str>) -> Result<&'static str, String> {
+    Ok(match content_type {
+        Some("image/jpeg") => "jpg",
+        Some(_) => "png",
+        None if path.ends_with(".jpg") || path.ends_with(".jpeg") => "jpg",
+        None => "png",
+    })
+}
+
+// 微信只会把 mp4 文件显示为带缩略图的视频消息，下载到的其他类型直接拒绝
+fn video_extension(_path: &str, content_type: Option<&str>) -> Result<&'static str, String> {
+    match content_type.map(|ct| ct.split(';').next().unwrap_or_default().trim()) {
+        None | Some("") | Some("video/mp4") | Some("application/octet-stream") => Ok("mp4"),
+        Some(other) => Err(format!("下载的内容不是 mp4 视频: {}", other)),
+    }
+}
+
+/// 下载视频的大小上限
+const MAX_VIDEO_DOWNLOAD_BYTES: u64 = 200 * 1024 * 1024;
+
+// 保存 base64 或 http 地址的内容，返回可以交给微信发送的本地路径，本地路径原样返回
+async fn local_send_path(
+    path: &str,
+    base64_data: &str,
+    noun: &str,
+    dir: &str,
+    extension: fn(&str, Option<&str>) -> Result<&'static str, String>,
+    max_bytes: Option<u64>,
+) -> Result<PathBuf, String> {
+    let (data, extension) = if !base64_data.is_empty() {
+        debug!("检测到base64{}数据，开始解码", noun);
+        let data = base64::decode(base64_data).map_err(|e| {
+            debug!("base64解码失败: {:?}", e);
+            "base64解码失败".to_string()
+        })?;
+        (data, extension(path, None)?)
+    } else if path.starts_with("http") {
+        debug!("开始下载{}\n", noun);
+        let mut response = get(path).await.map_err(|e| {
+            debug!("下载{}失败: {:?}", noun, e);
+            format!("下载{}失败", noun)
+        })?;
+        debug!("响应状态码: {:?}", response.status());
+        if !response.status().is_success() {
+            error!("下载{}失败，状态码: {:?}", noun, response.status());
+            return Err(format!("下载{}失败", noun));
+        }
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|val| val.to_str().ok())
+            .map(|val| val.to_string());
+        let extension = extension(path, Some(content_type.as_deref().unwrap_or_default()))?;
+        let too_large = |max: u64| format!("{}超过 {} MB", noun, max / 1024 / 1024);
+        if let (Some(max), Some(len)) = (max_bytes, response.content_length()) {
+            if len > max {
+                return Err(too_large(max));
+            }
+        }
+        // 没有 content-length 时边下载边检查大小
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| {
+            debug!("读取{}内容失败: {:?}", noun, e);
+            format!("读取{}内容失败", noun)
+        })? {
+            data.extend_from_slice(&chunk);
+            if let Some(max) = max_bytes {
+                if data.len() as u64 > max {
+                    return Err(too_large(max));
+                }
+            }
+        }
+        (data, extension)
+    } else {
+        return Ok(PathBuf::from(path));
+    };
+
+    // 使用 UUID 生成唯一的文件名
+    let local_path = PathBuf::from(format!("C:/{}/{}.{}", dir, Uuid::new_v4(), extension));
+    save_send_file(local_path, data, noun).await
+}
+
+// 把要发送的内容写到本地路径，目录不存在时创建
+async fn save_send_file(local_path: PathBuf, data: Vec<u8>, noun: &str) -> Result<PathBuf, String> {
+    if let Err(e) = fs::create_dir_all(local_path.parent().unwrap()).await {
+        debug!("创建目录失败: {:?}", e);
+        return Err("创建目录失败".to_string());
+    }
+    let mut file = File::create(&local_path).map_err(|e| {
+        debug!("创建文件失败: {:?}", e);
+        "创建文件失败".to_string()
+    })?;
+    let mut cursor = Cursor::new(data);
+    if let Err(e) = copy(&mut cursor, &mut file) {
+        debug!("保存{}失败: {:?}", noun, e);
+        return Err(format!("保存{}失败", noun));
+    }
+    debug!("保存{}成功, {:?}", noun, local_path);
+    Ok(local_path)
 }
 
 /// 发送图片
@@ -528,141 +2444,178 @@ pub async fn send_text(text: TextMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json
     post,
     tag = "WCF",
     path = "/image",
+    params(DryRunQuery),
     request_body = PathMsg,
     responses(
-        (status = 200, body = ApiResponseBool, description = "发送图片消息")
+        (status = 200, body = ApiResponseSendResult, description = "发送图片消息")
     )
 )]
-pub async fn send_image(image: PathMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    debug!("收到图片消息:\n{:?}", image);
-
-    let mut image_path = PathBuf::from(image.path.clone());
-
-    // 优先处理base64
-    if !image.base64.is_empty() {
-        let base64_data = &image.base64;
-        debug!("检测到base64图片数据，开始解码");
-        let extension = if image.path.ends_with(".jpg") || image.path.ends_with(".jpeg") {
-            "jpg"
-        } else if image.path.ends_with(".png") {
-            "png"
-        } else {
-            "png"
-        };
-        let unique_filename = Uuid::new_v4().to_string();
-        let local_image_path = PathBuf::from(format!("C:/images/{}.{}", unique_filename, extension));
-        if let Err(e) = fs::create_dir_all(local_image_path.parent().unwrap()).await {
-            debug!("创建目录失败: {:?}", e);
-            return Ok(warp::reply::json(&json!({"error": "创建目录失败"})));
-        }
-        let decoded = match base64::decode(base64_data) {
-            Ok(data) => data,
-            Err(e) => {
-                debug!("base64解码失败: {:?}", e);
-                return Ok(warp::reply::json(&json!({"error": "base64解码失败"})));
-            }
-        };
-        let mut file = match File::create(&local_image_path) {
-            Ok(f) => f,
-            Err(e) => {
-                debug!("创建文件失败: {:?}", e);
-                return Ok(warp::reply::json(&json!({"error": "创建文件失败"})));
-            }
-        };
-        let mut cursor = Cursor::new(decoded);
-        if let Err(e) = copy(&mut cursor, &mut file) {
-            debug!("保存图片失败: {:?}", e);
-            return Ok(warp::reply::json(&json!({"error": "保存图片失败"})));
-        }
-        debug!("base64图片保存成功, {:?}", local_image_path);
-        image_path = PathBuf::from(local_image_path);
-    } else if image.path.starts_with("http") {
-        // 下载图片
-        debug!("开始下载图片\n");
-        let response = match get(&image.path).await {
-            Ok(res) => res,
-            Err(e) => {
-                debug!("下载图片失败: {:?}", e);
-                return Ok(warp::reply::json(&json!({"error": "下载图片失败"})));
-            }
-        };
-        // 确认状态码
-        debug!("响应状态码: {:?}", response.status());
-        if response.status().is_success() {
-            debug!("下载图片成功\n");
-            let content_type = response
-                .headers()
-                .get("content-type")
-                .and_then(|val| val.to_str().ok())
-                .unwrap_or("image/png");
-            let extension = match content_type {
-                "image/jpeg" => "jpg",
-                "image/png" => "png",
-                _ => "png", // 默认使用png
-            };
-
-            // 使用 UUID 生成唯一的文件名
-            let unique_filename = Uuid::new_v4().to_string();
-            let local_image_path =
-                PathBuf::from(format!("C:\\images\\{}.{}", unique_filename, extension));
-
-            // 确保目录存在
-            if let Err(e) = fs::create_dir_all(local_image_path.parent().unwrap()).await {
-                debug!("创建目录失败: {:?}", e);
-                return Ok(warp::reply::json(&json!({"error": "创建目录失败"})));
-            }
-            let mut file = match File::create(&local_image_path) {
-                Ok(f) => f,
-                Err(e) => {
-                    debug!("创建文件失败: {:?}", e);
-                    return Ok(warp::reply::json(&json!({"error": "创建文件失败"})));
-                }
-            };
-            debug!("创建图片文件成功，开始获取图片内容做保存\n");
-            // 获取图片内容并保存到文件
-            let bytes = match response.bytes().await {
-                Ok(b) => b,
-                Err(e) => {
-                    debug!("读取图片内容失败: {:?}", e);
-                    return Ok(warp::reply::json(&json!({"error": "读取图片内容失败"})));
-                }
-            };
-            debug!("读取图片内容成功，开始保存图片内容\n");
-            let mut cursor = Cursor::new(bytes);
-            if let Err(e) = copy(&mut cursor, &mut file) {
-                debug!("保存图片失败: {:?}", e);
-                return Ok(warp::reply::json(&json!({"error": "保存图片失败"})));
-            }
-            debug!("保存图片内容成功, {:?}\n", local_image_path);
-            image_path = PathBuf::from(local_image_path);
-        } else {
-            error!("下载图片失败，状态码: {:?}", response.status());
-            return Ok(warp::reply::json(&json!({"error": "下载图片失败"})));
-        }
+pub async fn send_image(
+    image: PathMsg,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送图片消息", &image, check_receiver(&image.receiver).and_then(|_| check_send_path(&image.path, &image.base64))));
     }
+    debug!("收到图片消息:\n{:?}", image);
+
+    let image_path = match local_send_path(&image.path, &image.base64, "图片", "images", image_extension, None).await {
+        Ok(path) => path,
+        Err(e) => return Ok(warp::reply::json(&json!({ "error": e }))),
+    };
 
     // 更新 image 的路径
     let updated_image = PathMsg {
         path: image_path.to_string_lossy().to_string(),
         receiver: image.receiver,
         base64: String::new(),
+        filename: String::new(),
     };
 
-    wechat_api_handler!(wechat, WeChat::send_image, updated_image, "发送图片消息")
+    let receiver = updated_image.receiver.clone();
+    send_and_verify(wechat, &dry, "发送图片消息", receiver, Expect::Kind(3, None), move |wc| wc.send_image(updated_image)).await
 }
 
 /// 发送文件
+///
+/// path 为本地路径，也可以用 base64 传内容，这时 filename 是微信显示的文件名，为空时取 path 的文件名。
 #[utoipa::path(
     post,
     tag = "WCF",
     path = "/file",
+    params(DryRunQuery),
+    request_body = PathMsg,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送文件消息")
+    )
+)]
+pub async fn send_file(
+    file: PathMsg,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        let check = check_receiver(&file.receiver)
+            .and_then(|_| check_send_path(&file.path, &file.base64))
+            .and_then(|_| if file.base64.is_empty() { Ok(()) } else { base64_file_name(&file).map(|_| ()) });
+        return Ok(dry_run_reply("发送文件消息", &file, check));
+    }
+    let file = if file.base64.is_empty() {
+        file
+    } else {
+        debug!("检测到base64文件数据，开始解码");
+        let saved = match base64_file_name(&file) {
+            Ok(name) => match base64::decode(&file.base64) {
+                // 每个文件单独一个目录，保留原来的文件名
+                Ok(data) => save_send_file(PathBuf::from(format!("C:/files/{}/{}", Uuid::new_v4(), name)), data, "文件").await,
+                Err(e) => {
+                    debug!("base64解码失败: {:?}", e);
+                    Err("base64解码失败".to_string())
+                }
+            },
+            Err(e) => Err(e),
+        };
+        match saved {
+            Ok(path) => PathMsg {
+                path: path.to_string_lossy().to_string(),
+                receiver: file.receiver,
+                base64: String::new(),
+                filename: String::new(),
+            },
+            Err(e) => return Ok(api_error(e)),
+        }
+    };
+    let receiver = file.receiver.clone();
+    let expect = Expect::file(&file.path);
+    send_and_verify(wechat, &dry, "发送文件消息", receiver, expect, move |wc| wc.send_file(file)).await
+}
+
+// base64 文件的文件名，微信会显示这个名字。没有 filename 时取 path 的文件名，目录部分会被去掉
+fn base64_file_name(file: &PathMsg) -> Result<String, String> {
+    let name = if file.filename.trim().is_empty() { file.path.trim() } else { file.filename.trim() };
+    Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| "base64 文件需要 filename".to_string())
+}
+
+/// 发送视频
+///
+/// 与发送图片一样，path 可以是本地路径或 http 地址，也可以用 base64 传内容。只支持 mp4，微信会显示为带缩略图的视频消息。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/video",
+    params(DryRunQuery),
     request_body = PathMsg,
     responses(
-        (status = 200, body = ApiResponseBool, description = "发送文件消息")
+        (status = 200, body = ApiResponseSendResult, description = "发送视频消息")
+    )
+)]
+pub async fn send_video(
+    video: PathMsg,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    let check = check_receiver(&video.receiver)
+        .and_then(|_| check_send_path(&video.path, &video.base64))
+        .and_then(|_| check_video_path(&video.path, &video.base64));
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送视频消息", &video, check));
+    }
+    if let Err(e) = check {
+        return Ok(api_error(format!("发送视频消息失败: {}", e)));
+    }
+    let video_path = match local_send_path(&video.path, &video.base64, "视频", "videos", video_extension, Some(MAX_VIDEO_DOWNLOAD_BYTES)).await {
+        Ok(path) => path,
+        Err(e) => return Ok(api_error(format!("发送视频消息失败: {}", e))),
+    };
+    let updated_video = PathMsg {
+        path: video_path.to_string_lossy().to_string(),
+        receiver: video.receiver,
+        base64: String::new(),
+        filename: String::new(),
+    };
+    let receiver = updated_video.receiver.clone();
+    let expect = Expect::file(&updated_video.path);
+    send_and_verify(wechat, &dry, "发送视频消息", receiver, expect, move |wc| wc.send_video(updated_video)).await
+}
+
+/// 发送表情
+///
+/// path 为本地的 gif 或 emoji 文件，也可以用 md5 发送收到过并已缓存的表情，保留动画效果。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/emoji",
+    params(DryRunQuery),
+    request_body = EmojiMsg,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送表情消息")
     )
 )]
-pub async fn send_file(file: PathMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    wechat_api_handler!(wechat, WeChat::send_file, file, "发送文件消息")
+pub async fn send_emoji(
+    emoji: EmojiMsg,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    let path = check_receiver(&emoji.receiver).and_then(|_| emoji_path(&emoji));
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送表情消息", &emoji, path.map(|_| ())));
+    }
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => return Ok(api_error(format!("发送表情消息失败: {}", e))),
+    };
+    let msg = PathMsg {
+        path,
+        receiver: emoji.receiver,
+        base64: String::new(),
+        filename: String::new(),
+    };
+    let receiver = msg.receiver.clone();
+    send_and_verify(wechat, &dry, "发送表情消息", receiver, Expect::Kind(47, None), move |wc| wc.send_emotion(msg)).await
 }
 
 /// 发送卡片消息
@@ -670,13 +2623,157 @@ pub async fn send_file(file: PathMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json
     post,
     tag = "WCF",
     path = "/rich-text",
+    params(DryRunQuery),
     request_body = RichText,
     responses(
-        (status = 200, body = ApiResponseBool, description = "发送卡片消息")
+        (status = 200, body = ApiResponseSendResult, description = "发送卡片消息")
+    )
+)]
+pub async fn send_rich_text(
+    msg: RichText,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送卡片消息", &msg, check_receiver(&msg.receiver)));
+    }
+    if let Err(rsp) = moderate(&msg.receiver, format!("{}\n{}", msg.title, msg.digest), &dry).await {
+        return Ok(rsp);
+    }
+    let receiver = msg.receiver.clone();
+    send_and_verify(wechat, &dry, "发送卡片消息", receiver, Expect::Kind(49, None), move |wc| wc.send_rich_text(msg)).await
+}
+
+/// 发送 xml 消息
+///
+/// 直接发送拼好的 xml，用于还没有单独接口的消息类型，例如音乐卡片、视频号分享。type 为消息类型，例如 21 为音乐卡片；path 为可选的缩略图。内容不做处理，格式不对时微信端可能显示为不支持的消息。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/xml",
+    params(DryRunQuery),
+    request_body = XmlMsg,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送 xml 消息")
+    )
+)]
+pub async fn send_xml(msg: XmlMsg, dry: DryRunQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let check = check_receiver(&msg.receiver).and_then(|_| {
+        if msg.path.is_empty() || Path::new(&msg.path).exists() {
+            Ok(())
+        } else {
+            Err(format!("{} 不存在", msg.path))
+        }
+    });
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送 xml 消息", &msg, check));
+    }
+    if let Err(e) = check {
+        return Ok(api_error(format!("发送 xml 消息失败: {}", e)));
+    }
+    if let Err(rsp) = moderate(&msg.receiver, xml_text(&msg.content), &dry).await {
+        return Ok(rsp);
+    }
+    let receiver = msg.receiver.clone();
+    let expect = Expect::Kind(msg.r#type as u32, None);
+    send_and_verify(wechat, &dry, "发送 xml 消息", receiver, expect, move |wc| wc.send_xml(msg)).await
+}
+
+/// 发送名片
+///
+/// 把通讯录中的联系人以名片发给好友或群，联系人不在通讯录中时返回错误。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/contact-card",
+    params(DryRunQuery),
+    request_body = ContactCard,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送名片消息")
+    )
+)]
+pub async fn send_contact_card(
+    card: ContactCard,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送名片消息", &card, check_receiver(&card.receiver)));
+    }
+    let receiver = card.receiver.clone();
+    send_and_verify(wechat, &dry, "发送名片消息", receiver, Expect::Kind(42, None), move |wc| {
+        wc.send_contact_card(card.receiver, &card.wxid)
+    })
+    .await
+}
+
+/// 发送位置
+///
+/// 对方收到的是可以在地图中打开的位置消息，而不是地图链接。坐标使用 GCJ-02（高德、腾讯地图的坐标）。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/location",
+    params(DryRunQuery),
+    request_body = Location,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送位置消息")
+    )
+)]
+pub async fn send_location(
+    location: Location,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送位置消息", &location, check_receiver(&location.receiver)));
+    }
+    let receiver = location.receiver.clone();
+    send_and_verify(wechat, &dry, "发送位置消息", receiver, Expect::Kind(48, None), move |wc| {
+        let Location { lat, lng, label, address, receiver } = location;
+        wc.send_location(receiver, lat, lng, &label, &address)
+    })
+    .await
+}
+
+/// 把 Markdown 渲染为图片发送
+///
+/// 支持标题、列表、引用、代码块、表格和分隔线，适合发送带表格的长回复。字体由 markdown.font 配置，默认使用系统的微软雅黑。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/markdown",
+    params(DryRunQuery),
+    request_body = MarkdownMsg,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送图片消息"),
+        (status = 400, body = ApiResponseFieldErrors, description = "参数校验失败")
     )
 )]
-pub async fn send_rich_text(msg: RichText, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    wechat_api_handler!(wechat, WeChat::send_rich_text, msg, "发送卡片消息")
+pub async fn send_markdown(msg: MarkdownMsg, dry: DryRunQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送 Markdown 图片", &msg, check_receiver(&msg.receiver)));
+    }
+    if let Err(rsp) = moderate(&msg.receiver, msg.markdown.clone(), &dry).await {
+        return Ok(rsp);
+    }
+    let MarkdownMsg { markdown, receiver, width } = msg;
+    let rendered = tokio::task::spawn_blocking(move || markdown_service::render_to_file(&markdown, width))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    let path = match rendered {
+        Ok(path) => path,
+        Err(e) => return Ok(api_error(format!("发送 Markdown 图片失败: {}", e))),
+    };
+    let image = PathMsg {
+        path: path.to_string_lossy().to_string(),
+        receiver: receiver.clone(),
+        base64: String::new(),
+        filename: String::new(),
+    };
+    let rsp = send_and_verify(wechat, &dry, "发送 Markdown 图片", receiver, Expect::Kind(3, None), move |wc| wc.send_image(image)).await;
+    markdown_service::remove_later(path);
+    rsp
 }
 
 /// 拍一拍
@@ -684,12 +2781,20 @@ pub async fn send_rich_text(msg: RichText, wechat: Arc<Mutex<WeChat>>) -> Result
     post,
     tag = "WCF",
     path = "/pat",
+    params(DryRunQuery),
     request_body = PatMsg,
     responses(
         (status = 200, body = ApiResponseBool, description = "发送拍一拍消息")
     )
 )]
-pub async fn send_pat_msg(msg: PatMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+pub async fn send_pat_msg(
+    msg: PatMsg,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("发送拍一拍消息", &msg, check_room(&msg.roomid).and_then(|_| check_wxids(&msg.wxid))));
+    }
     wechat_api_handler!(wechat, WeChat::send_pat_msg, msg, "发送拍一拍消息")
 }
 
@@ -698,13 +2803,22 @@ pub async fn send_pat_msg(msg: PatMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Jso
     post,
     tag = "WCF",
     path = "/forward-msg",
+    params(DryRunQuery),
     request_body = ForwardMsg,
     responses(
-        (status = 200, body = ApiResponseBool, description = "转发消息")
+        (status = 200, body = ApiResponseSendResult, description = "转发消息")
     )
 )]
-pub async fn forward_msg(msg: ForwardMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    wechat_api_handler!(wechat, WeChat::forward_msg, msg, "转发消息")
+pub async fn forward_msg(
+    msg: ForwardMsg,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("转发消息", &msg, check_receiver(&msg.receiver)));
+    }
+    let receiver = msg.receiver.clone();
+    send_and_verify(wechat, &dry, "转发消息", receiver, Expect::Any, move |wc| wc.forward_msg(msg)).await
 }
 
 /// 保存语音
@@ -732,55 +2846,21 @@ pub async fn save_audio(msg: AudioMsg, wechat: Arc<Mutex<WeChat>>) -> Result<Jso
     )
 )]
 pub async fn save_image(msg: Image, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    let wc = wechat.lock().unwrap();
-    let handle_error = |error_message: &str| -> Result<Json, Infallible> {
-        Ok(warp::reply::json(&ApiResponse::<String> {
+    let timeout = Duration::from_secs(msg.timeout as u64);
+    match media_service::download_and_decrypt_image(&wechat, msg.id, &msg.extra, &msg.dir, timeout).await {
+        Ok(path) => Ok(warp::reply::json(&ApiResponse {
+            status: 0,
+            code: None,
+            error: None,
+            data: Some(path),
+        })),
+        Err(error) => Ok(warp::reply::json(&ApiResponse::<String> {
             status: 1,
-            error: Some(error_message.to_string()),
+            code: None,
+            error: Some(error),
             data: None,
-        }))
-    };
-
-    let att = AttachMsg {
-        id: msg.id,
-        thumb: "".to_string(),
-        extra: msg.extra.clone(),
-    };
-
-    let status = match wc.clone().download_attach(att) {
-        Ok(status) => status,
-        Err(error) => return handle_error(&error.to_string()),
-    };
-
-    if !status {
-        return handle_error("下载失败");
-    }
-
-    let mut counter = 0;
-    loop {
-        if counter >= msg.timeout {
-            break;
-        }
-        match wc.clone().decrypt_image(DecPath {
-            src: msg.extra.clone(),
-            dst: msg.dir.clone(),
-        }) {
-            Ok(path) => {
-                if path.is_empty() {
-                    counter += 1;
-                    sleep(Duration::from_secs(1));
-                    continue;
-                }
-                return Ok(warp::reply::json(&ApiResponse {
-                    status: 0,
-                    error: None,
-                    data: Some(path),
-                }));
-            }
-            Err(error) => return handle_error(&error.to_string()),
-        };
+        })),
     }
-    return handle_error("下载超时");
 }
 
 /// 保存文件
@@ -798,6 +2878,7 @@ pub async fn save_file(msg: SaveFile, wechat: Arc<Mutex<WeChat>>) -> Result<Json
     let handle_error = |error_message: &str| -> Result<Json, Infallible> {
         Ok(warp::reply::json(&ApiResponse::<String> {
             status: 1,
+            code: None,
             error: Some(error_message.to_string()),
             data: None,
         }))
@@ -820,6 +2901,7 @@ pub async fn save_file(msg: SaveFile, wechat: Arc<Mutex<WeChat>>) -> Result<Json
 
     return Ok(warp::reply::json(&ApiResponse {
         status: 0,
+        code: None,
         error: None,
         data: Some("ok".to_owned()),
     }));
@@ -840,6 +2922,8 @@ pub async fn recv_transfer(msg: Transfer, wechat: Arc<Mutex<WeChat>>) -> Result<
 }
 
 /// 执行 SQL 查询数据库
+///
+/// 配置 sql_snapshot.enabled 后，已通过 /admin/snapshot 生成快照的库在本地只读查询，不占用微信的数据库连接，但看不到快照之后的新数据。
 #[utoipa::path(
     post,
     tag = "WCF",
@@ -850,8 +2934,19 @@ pub async fn recv_transfer(msg: Transfer, wechat: Arc<Mutex<WeChat>>) -> Result<
     )
 )]
 pub async fn query_sql(msg: DbQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
-    let wechat = wechat.lock().unwrap();
-    let rsp = match wechat.clone().query_sql(msg) {
+    let snapshot = GLOBAL.get().unwrap().wechat_config.read().unwrap().sql_snapshot.enabled && db_snapshot_service::exists(&msg.db);
+    // 查询可能很慢，不能占着异步线程
+    let result = tokio::task::spawn_blocking(move || {
+        if snapshot {
+            db_snapshot_service::query(&msg.db, &msg.sql)
+        } else {
+            let wc = wechat.lock().unwrap().clone();
+            wc.query_sql(msg).map_err(|e| e.to_string())
+        }
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    let rsp = match result {
         Ok(origin) => {
             let rows = origin
                 .rows
@@ -879,12 +2974,14 @@ pub async fn query_sql(msg: DbQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json,
 
             ApiResponse {
                 status: 0,
+                code: None,
                 error: None,
                 data: Some(rows),
             }
         }
         Err(error) => ApiResponse {
             status: 1,
+            code: None,
             error: Some(error.to_string()),
             data: None,
         },
@@ -906,6 +3003,9 @@ pub async fn accept_new_friend(
     msg: Verification,
     wechat: Arc<Mutex<WeChat>>,
 ) -> Result<Json, Infallible> {
+    if let Err(rsp) = consume_budget(RiskOperation::FriendAdd, 1) {
+        return Ok(rsp);
+    }
     wechat_api_handler!(wechat, WeChat::accept_new_friend, msg, "通过好友申请")
 }
 
@@ -914,6 +3014,7 @@ pub async fn accept_new_friend(
     post,
     tag = "WCF",
     path = "/add-chatroom-member",
+    params(DryRunQuery),
     request_body = MemberMgmt,
     responses(
         (status = 200, body = ApiResponseBool, description = "添加群成员")
@@ -921,8 +3022,15 @@ pub async fn accept_new_friend(
 )]
 pub async fn add_chatroom_member(
     msg: MemberMgmt,
+    dry: DryRunQuery,
     wechat: Arc<Mutex<WeChat>>,
 ) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("添加群成员", &msg, check_room(&msg.roomid).and_then(|_| check_wxids(&msg.wxids))));
+    }
+    if let Err(rsp) = consume_budget(RiskOperation::GroupAdd, member_count(&msg.wxids)) {
+        return Ok(rsp);
+    }
     wechat_api_handler!(wechat, WeChat::add_chatroom_member, msg, "添加群成员")
 }
 
@@ -931,6 +3039,7 @@ pub async fn add_chatroom_member(
     post,
     tag = "WCF",
     path = "/invite-chatroom-member",
+    params(DryRunQuery),
     request_body = MemberMgmt,
     responses(
         (status = 200, body = ApiResponseBool, description = "邀请群成员")
@@ -938,8 +3047,15 @@ pub async fn add_chatroom_member(
 )]
 pub async fn invite_chatroom_member(
     msg: MemberMgmt,
+    dry: DryRunQuery,
     wechat: Arc<Mutex<WeChat>>,
 ) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("邀请群成员", &msg, check_room(&msg.roomid).and_then(|_| check_wxids(&msg.wxids))));
+    }
+    if let Err(rsp) = consume_budget(RiskOperation::GroupInvite, member_count(&msg.wxids)) {
+        return Ok(rsp);
+    }
     wechat_api_handler!(wechat, WeChat::invite_chatroom_member, msg, "邀请群成员")
 }
 
@@ -948,6 +3064,7 @@ pub async fn invite_chatroom_member(
     post,
     tag = "WCF",
     path = "/delete-chatroom-member",
+    params(DryRunQuery),
     request_body = MemberMgmt,
     responses(
         (status = 200, body = ApiResponseBool, description = "删除群成员")
@@ -955,8 +3072,15 @@ pub async fn invite_chatroom_member(
 )]
 pub async fn delete_chatroom_member(
     msg: MemberMgmt,
+    dry: DryRunQuery,
     wechat: Arc<Mutex<WeChat>>,
 ) -> Result<Json, Infallible> {
+    if is_dry_run(&dry) {
+        return Ok(dry_run_reply("删除群成员", &msg, check_room(&msg.roomid).and_then(|_| check_wxids(&msg.wxids))));
+    }
+    if let Err(rsp) = consume_budget(RiskOperation::GroupKick, member_count(&msg.wxids)) {
+        return Ok(rsp);
+    }
     wechat_api_handler!(wechat, WeChat::delete_chatroom_member, msg, "删除群成员")
 }
 
@@ -1017,22 +3141,27 @@ pub async fn query_room_member(
                         wxid: member.wxid,
                         name: member.name,
                         state: member.state,
+                        avatar: member.avatar,
+                        inviter: member.inviter,
                     })
                     .collect();
                 ApiResponse {
                     status: 0,
+                    code: None,
                     error: None,
                     data: Some(filtered_members),
                 }
             }
             None => ApiResponse {
                 status: 0,
+                code: None,
                 error: None,
                 data: Some(vec![]),
             },
         },
         Err(e) => ApiResponse {
             status: 1,
+            code: None,
             error: Some(e.to_string()),
             data: None,
         },
@@ -1040,172 +3169,2514 @@ pub async fn query_room_member(
     Ok(warp::reply::json(&resp))
 }
 
-/// 下载图片
+/// 查询当前账号在群里的身份
+///
+/// 群主取自 ChatRoom 表的 Reserved2，管理员取自 RoomData 成员的标志位，可用于在踢人等操作前判断权限。
 #[utoipa::path(
     get,
     tag = "WCF",
-    path = "/download-image",
+    path = "/chatroom/{roomid}/role",
     params(
-        ("id" = u64, Query, description = "消息ID"),
-        ("extra" = String, Query, description = "extra"),
-        ("dir" = String, Query, description = "存放目录"),
-        ("timeout" = u8, Query, description = "超时时间(秒)")
+        ("roomid" = String, Path, description = "群ID")
     ),
     responses(
-        (status = 200, description = "返回图片文件流", content_type = "image/*")
+        (status = 200, body = ApiResponseRoomRole, description = "群主、管理员和当前账号身份")
     )
 )]
-pub async fn download_image(params: DownloadImageParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
-    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
-        Ok(Box::new(warp::reply::with_status(
-            error_message,
-            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-        )))
+pub async fn get_room_role(roomid: String, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let result = wechat.lock().unwrap().query_room_role(roomid);
+    match result {
+        Ok(Some(role)) => Ok(api_ok(role)),
+        Ok(None) => Ok(api_error("群聊不存在")),
+        Err(e) => Ok(api_error(format!("查询群身份失败: {}", e))),
+    }
+}
+
+/// 解析消息中 @ 的群成员
+///
+/// 按群昵称匹配，依次尝试完全相同、忽略大小写、前缀和近似匹配。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/resolve-mentions",
+    request_body = MentionQuery,
+    responses(
+        (status = 200, body = ApiResponseMentions, description = "按出现顺序返回每个 @ 的匹配结果")
+    )
+)]
+pub async fn resolve_mentions(query: MentionQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let members = match wechat.lock().unwrap().query_room_member(query.roomid.clone()) {
+        Ok(Some(members)) => members,
+        Ok(None) => return Ok(api_error("群聊不存在")),
+        Err(e) => return Ok(api_error(format!("查询群成员失败: {}", e))),
     };
+    let names: Vec<(String, String)> = members.into_iter().map(|m| (m.wxid, m.name)).collect();
+    let resolved: Vec<ResolvedMention> = mention::extract(&query.text)
+        .into_iter()
+        .map(|text| match mention::best_match(&text, &names) {
+            Some((i, exact)) => ResolvedMention {
+                mention: text,
+                wxid: Some(names[i].0.clone()),
+                name: Some(names[i].1.clone()),
+                exact,
+            },
+            None => ResolvedMention {
+                mention: text,
+                wxid: None,
+                name: None,
+                exact: false,
+            },
+        })
+        .collect();
+    Ok(api_ok(resolved))
+}
 
-    let att = AttachMsg {
-        id: params.id,
-        thumb: "".to_string(),
-        extra: params.extra.clone(),
+fn room_permissions(roomid: String, wechat: &WeChat) -> RoomPermissions {
+    let owner = match wechat.query_room_role(roomid.clone()) {
+        Ok(Some(role)) => Some(role.owner),
+        _ => None,
     };
+    let global = GLOBAL.get().unwrap();
+    let wxids = global.command_permission_service.lock().unwrap().list(&roomid);
+    RoomPermissions { roomid, owner, wxids }
+}
 
-    let status = {
-        let wc = wechat.lock().unwrap();
-        match wc.clone().download_attach(att) {
-            Ok(status) => status,
-            Err(error) => return handle_error(error.to_string()),
+/// 查询群聊特权指令的授权名单
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/command-permissions",
+    params(RoomQuery),
+    responses(
+        (status = 200, body = ApiResponseRoomPermissions, description = "群主和手动授权的 wxid")
+    )
+)]
+pub async fn get_command_permissions(query: RoomQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let wechat = wechat.lock().unwrap();
+    Ok(api_ok(room_permissions(query.roomid, &wechat)))
+}
+
+/// 授权或撤销群聊特权指令
+///
+/// 踢人、群发等指令只有群主、登录账号和名单中的成员可以执行，名单会持久化保存。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/command-permissions",
+    request_body = PermissionChange,
+    responses(
+        (status = 200, body = ApiResponseRoomPermissions, description = "修改后的名单")
+    )
+)]
+pub async fn change_command_permissions(change: PermissionChange, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let wxids: Vec<String> = change
+        .wxids
+        .split(',')
+        .map(|w| w.trim().to_string())
+        .filter(|w| !w.is_empty())
+        .collect();
+    if change.roomid.is_empty() || wxids.is_empty() {
+        return Ok(api_error("roomid 和 wxids 不能为空"));
+    }
+    let result = {
+        let global = GLOBAL.get().unwrap();
+        let mut permissions = global.command_permission_service.lock().unwrap();
+        match change.action {
+            PermissionAction::Grant => permissions.grant(&change.roomid, &wxids),
+            PermissionAction::Revoke => permissions.revoke(&change.roomid, &wxids),
         }
     };
-
-    if !status {
-        return handle_error("下载失败".to_string());
+    if let Err(e) = result {
+        return Ok(api_error(format!("保存授权失败: {}", e)));
     }
+    let wechat = wechat.lock().unwrap();
+    Ok(api_ok(room_permissions(change.roomid, &wechat)))
+}
 
-    let mut counter = 0;
-    loop {
-        if counter >= params.timeout {
-            break;
-        }
-        let path = {
-            let wc = wechat.lock().unwrap();
-            match wc.clone().decrypt_image(DecPath {
-                src: params.extra.clone(),
-                dst: params.dir.clone(),
-            }) {
-                Ok(path) => path,
-                Err(error) => return handle_error(error.to_string()),
-            }
+/// 在群里发起投票
+///
+/// 群成员回复选项序号即可投票，重复投票以最后一次为准。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/polls",
+    request_body = NewPoll,
+    responses(
+        (status = 200, body = ApiResponsePoll, description = "新建的投票")
+    )
+)]
+pub async fn create_poll(poll: NewPoll, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let options: Vec<String> = poll
+        .options
+        .iter()
+        .map(|o| o.trim().to_string())
+        .filter(|o| !o.is_empty())
+        .collect();
+    if !poll.roomid.ends_with("@chatroom") || poll.question.trim().is_empty() {
+        return Ok(api_error("roomid 必须是群，question 不能为空"));
+    }
+    let global = GLOBAL.get().unwrap();
+    let (id, text) = {
+        let mut polls = global.poll_service.lock().unwrap();
+        let id = match polls.create(&poll.roomid, poll.question.trim(), options) {
+            Ok(id) => id,
+            Err(e) => return Ok(api_error(e)),
         };
-
-        if path.is_empty() {
-            counter += 1;
-            sleep(Duration::from_secs(1));
-            continue;
-        }
-        
-        // 读取文件内容
-        match tokio::fs::read(&path).await {
-            Ok(content) => {
-                // 根据文件扩展名确定 Content-Type
-                let content_type = if path.ends_with(".jpg") || path.ends_with(".jpeg") {
-                    "image/jpeg"
-                } else if path.ends_with(".png") {
-                    "image/png"
-                } else {
-                    "application/octet-stream"
-                };
-
-                // 返回文件流
-                return Ok(Box::new(warp::reply::with_header(
-                    content,
-                    "Content-Type",
-                    content_type,
-                )));
-            }
-            Err(e) => return handle_error(format!("读取文件失败: {}", e)),
-        }
+        (id, polls.announcement(id).unwrap_or_default())
+    };
+    let text = TextMsg {
+        msg: text,
+        receiver: poll.roomid,
+        aters: String::new(),
+    };
+    let sent = send_checked(wechat, text).await;
+    if let Err(e) = sent {
+        return Ok(api_error(format!("投票已创建，但发送到群里失败: {}", e)));
     }
-    return handle_error("下载超时".to_string());
+    let result = global.poll_service.lock().unwrap().result(id);
+    Ok(api_ok(result))
 }
 
-/// 下载文件
+/// 查询投票结果
 #[utoipa::path(
     get,
     tag = "WCF",
-    path = "/download-file",
+    path = "/polls/{id}",
     params(
-        ("id" = u64, Query, description = "消息ID"),
-        ("extra" = String, Query, description = "extra"),
-        ("thumb" = String, Query, description = "缩略图")
+        ("id" = u64, Path, description = "投票编号")
     ),
     responses(
-        (status = 200, description = "返回文件流", content_type = "application/octet-stream")
+        (status = 200, body = ApiResponsePoll, description = "各选项的票数和投票人")
     )
 )]
-pub async fn download_file(params: DownloadFileParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
-    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
+pub async fn get_poll(id: u64, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let result = global.poll_service.lock().unwrap().result(id);
+    match result {
+        Some(result) => Ok(api_ok(result)),
+        None => Ok(api_error("投票不存在")),
+    }
+}
+
+/// 结束投票并把汇总发到群里
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/polls/{id}/close",
+    params(
+        ("id" = u64, Path, description = "投票编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponsePoll, description = "最终结果")
+    )
+)]
+pub async fn close_poll(id: u64, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let closed = GLOBAL.get().unwrap().poll_service.lock().unwrap().close(id);
+    let (result, summary) = match closed {
+        Ok(closed) => closed,
+        Err(e) => return Ok(api_error(e)),
+    };
+    let text = TextMsg {
+        msg: summary,
+        receiver: result.roomid.clone(),
+        aters: String::new(),
+    };
+    if let Err(e) = send_checked(wechat, text).await {
+        warn!("发送投票汇总失败: {}", e);
+    }
+    Ok(api_ok(result))
+}
+
+/// 在群里发通知并统计确认
+///
+/// 群成员回复以确认词开头的消息即算确认，如“收到”“+1”。同一个群有多条通知时，确认记到最新的那条。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/announcements",
+    request_body = NewAnnouncement,
+    responses(
+        (status = 200, body = ApiResponseAckReport, description = "新建的通知")
+    )
+)]
+pub async fn create_announcement(announcement: NewAnnouncement, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if !announcement.roomid.ends_with("@chatroom") || announcement.content.trim().is_empty() {
+        return Ok(api_error("roomid 必须是群，content 不能为空"));
+    }
+    let keywords: Vec<String> = announcement
+        .keywords
+        .iter()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+    let keywords = match announcement_service::ack_keywords(keywords) {
+        Ok(keywords) => keywords,
+        Err(e) => return Ok(api_error(e)),
+    };
+    let (roomid, content) = (announcement.roomid, announcement.content.trim().to_string());
+    // 与其它自动发送一样经过暂停、审核、免打扰和发送节奏，发出去（或进入免打扰队列）后才登记
+    let result = tokio::task::spawn_blocking(move || {
+        let wc = wechat.lock().unwrap().clone();
+        let text = TextMsg {
+            msg: content.clone(),
+            receiver: roomid.clone(),
+            aters: String::new(),
+        };
+        quiet_hours_service::send_text(&wc, text).map_err(|e| format!("发送通知失败: {}", e))?;
+        let id = GLOBAL.get().unwrap().announcement_service.lock().unwrap().create(&roomid, &content, keywords)?;
+        Ok::<_, String>(ack_report(&wc, id))
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(report) => Ok(api_ok(report)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+fn ack_report(wechat: &WeChat, id: u64) -> Option<AckReport> {
+    let service = GLOBAL.get().unwrap().announcement_service.lock().unwrap();
+    let roomid = service.roomid(id)?;
+    let members = wechat.query_room_member(roomid).ok().flatten().unwrap_or_default();
+    let self_wxid = wechat.get_self_wxid().unwrap_or_default();
+    service.report(id, &members, &self_wxid)
+}
+
+/// 查询通知的确认情况
+///
+/// 列出已确认的成员和还没确认的群成员。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/announcements/{id}/acks",
+    params(
+        ("id" = u64, Path, description = "通知编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseAckReport, description = "已确认和未确认的成员")
+    )
+)]
+pub async fn get_announcement_acks(id: u64, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    match ack_report(&wechat.lock().unwrap(), id) {
+        Some(report) => Ok(api_ok(report)),
+        None => Ok(api_error("通知不存在")),
+    }
+}
+
+/// 群发通知
+///
+/// 把同一条通知发到多个群，内容中的 {room}、{admin} 按群替换为群名和群主昵称。账号是群主或管理员的群会 @所有人，和 /at-all 共用每群每天的次数和风控预算，预算不足时只发通知。逐个群返回结果，某个群失败不影响其他群。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/announce",
+    request_body = Announce,
+    responses(
+        (status = 200, body = ApiResponseAnnounce, description = "每个群的发送结果")
+    )
+)]
+pub async fn announce(announce: Announce, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let mut rooms: Vec<String> = vec![];
+    for roomid in announce.rooms.iter().map(|r| r.trim().to_string()) {
+        if !rooms.contains(&roomid) {
+            rooms.push(roomid);
+        }
+    }
+    let content = announce.content.trim().to_string();
+    let results = tokio::task::spawn_blocking(move || {
+        announce_service::announce(&wechat, &rooms, &content, announce.at_all)
+    })
+    .await;
+    match results {
+        Ok(results) => Ok(api_ok(results)),
+        Err(e) => Ok(api_error(e.to_string())),
+    }
+}
+
+/// 在群里 @所有人
+///
+/// 只有群主和管理员可以 @所有人，否则返回 NOT_ADMIN；每个群每天的次数和全部群合计的次数受预算限制，超出时返回 BUDGET_EXCEEDED。
+/// 指定 send_at 时到时间再发送，发送前会重新检查权限和预算。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/at-all",
+    request_body = AtAll,
+    responses(
+        (status = 200, body = ApiResponseAtAll, description = "发送或定时的记录")
+    )
+)]
+pub async fn at_all(request: AtAll, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let roomid = request.roomid.trim().to_string();
+    let content = request.content.trim().to_string();
+    // 定时时间写错时不能退化成立即发送
+    let send_at = match parse_time_param(&request.send_at) {
+        Ok(send_at) => send_at.and_then(|ts| {
+            use chrono::TimeZone;
+            chrono::Local.timestamp_opt(ts, 0).single()
+        }),
+        Err(e) => return Ok(api_error(format!("@所有人 失败: {}", e))),
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let wc = wechat.lock().unwrap().clone();
+        at_all_service::check_admin(&wc, &roomid).map_err(|e| api_error_code(CODE_NOT_ADMIN, e))?;
+        let global = GLOBAL.get().unwrap();
+        if let Some(at) = send_at.filter(|at| *at > chrono::Local::now()) {
+            return Ok(global.at_all_service.lock().unwrap().push(&roomid, &content, at, None));
+        }
+        at_all_service::consume(&roomid).map_err(|e| {
+            admin_notify_service::report(Incident::BudgetExceeded(e.clone()));
+            api_error_code(CODE_BUDGET_EXCEEDED, e)
+        })?;
+        let sent = quiet_hours_service::send_text(&wc, at_all_service::text(&roomid, &content));
+        Ok(global.at_all_service.lock().unwrap().push(&roomid, &content, chrono::Local::now(), Some(sent)))
+    })
+    .await;
+    match result {
+        Ok(Ok(message)) => Ok(api_ok(message)),
+        Ok(Err(rsp)) => Ok(rsp),
+        Err(e) => Ok(api_error(e.to_string())),
+    }
+}
+
+/// 查询 @所有人 记录，包括还没到时间的定时消息，最新的在前
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/at-all",
+    responses(
+        (status = 200, body = ApiResponseAtAlls, description = "@所有人 记录")
+    )
+)]
+pub async fn list_at_all(_wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    Ok(api_ok(GLOBAL.get().unwrap().at_all_service.lock().unwrap().list()))
+}
+
+// 在后台任务中发送还没送达的群
+fn start_distribution(wechat: Arc<Mutex<WeChat>>, id: u64) -> Json {
+    let job = match jobs::start("distribute") {
+        Ok(job) => job,
+        Err(running) => return api_error(format!("分发任务 #{} 正在进行，分发 #{} 可在它结束后续发", running, id)),
+    };
+    let prepared = GLOBAL.get().unwrap().distribute_service.lock().unwrap().prepare(id, job);
+    let (path, pending) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            jobs::finish(job, Err(e.clone()));
+            return api_error(e);
+        }
+    };
+    tokio::task::spawn_blocking(move || {
+        let result = distribute_service::run(&wechat, job, id, &path, &pending)
+            .map(|r| format!("送达 {}，未确认 {}，失败 {}，待发 {}", r.delivered, r.unverified, r.failed, r.pending));
+        if let Err(e) = &result {
+            log::warn!("分发 #{} 中断: {}", id, e);
+        }
+        jobs::finish(job, result);
+    });
+    let report = GLOBAL.get().unwrap().distribute_service.lock().unwrap().report(id);
+    api_ok(report)
+}
+
+/// 把文件分发到多个群
+///
+/// 在后台按配置的间隔和批次逐个群发送，每次发送都等待消息回调确认，失败的按 distribute.retries 重试，立即返回投递矩阵。发送成功但未能确认的群标记为 unverified，不会重试，以免重复发送大文件。同一时间只运行一个分发任务。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/distributions",
+    request_body = NewDistribution,
+    responses(
+        (status = 200, body = ApiResponseDistribution, description = "新建的分发任务")
+    )
+)]
+pub async fn create_distribution(distribution: NewDistribution, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let rooms: Vec<String> = distribution.rooms.iter().map(|r| r.trim().to_string()).collect();
+    let created = GLOBAL
+        .get()
+        .unwrap()
+        .distribute_service
+        .lock()
+        .unwrap()
+        .create(distribution.path.trim(), &rooms);
+    match created {
+        Ok(id) => Ok(start_distribution(wechat, id)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 查询分发任务的投递矩阵
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/distributions/{id}",
+    params(
+        ("id" = u64, Path, description = "分发编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseDistribution, description = "每个群的投递情况")
+    )
+)]
+pub async fn get_distribution(id: u64, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    match GLOBAL.get().unwrap().distribute_service.lock().unwrap().report(id) {
+        Some(report) => Ok(api_ok(report)),
+        None => Ok(api_error("分发任务不存在")),
+    }
+}
+
+/// 续发分发任务
+///
+/// 任务中断、暂停自动化或程序重启后，发送还没送达的群，失败的群重新计数重试。已送达和未确认的群不会再发。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/distributions/{id}/resume",
+    params(
+        ("id" = u64, Path, description = "分发编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseDistribution, description = "续发前的投递矩阵")
+    )
+)]
+pub async fn resume_distribution(id: u64, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if GLOBAL.get().unwrap().distribute_service.lock().unwrap().report(id).is_none() {
+        return Ok(api_error("分发任务不存在"));
+    }
+    Ok(start_distribution(wechat, id))
+}
+
+/// 批量发送文本消息
+///
+/// 在后台按顺序逐条发送，两条之间等待 interval_ms，立即返回每条消息的状态。按条数扣减批量发送预算，超出时返回 BUDGET_EXCEEDED。接收方处于免打扰时段的消息进入排队；暂停自动化后剩下的消息保持 pending，不再发送。同一时间只运行一个批量发送任务。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/batch-text",
+    request_body = NewBatch,
+    responses(
+        (status = 200, body = ApiResponseBatch, description = "新建的批次")
+    )
+)]
+pub async fn send_batch_text(batch: NewBatch, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().batch.clone();
+    if batch.messages.len() > config.max_items {
+        return Ok(api_error(format!("一次最多发送 {} 条消息", config.max_items)));
+    }
+    let job = match jobs::start("batch-text") {
+        Ok(job) => job,
+        Err(running) => return Ok(api_error(format!("批量发送任务 #{} 正在进行", running))),
+    };
+    if let Err(rsp) = consume_budget(RiskOperation::BulkSend, batch.messages.len() as u32) {
+        jobs::finish(job, Err("超出批量发送预算".to_string()));
+        return Ok(rsp);
+    }
+    let texts = batch
+        .messages
+        .into_iter()
+        .map(|t| TextMsg {
+            msg: t.msg,
+            receiver: t.receiver.trim().to_string(),
+            aters: t.aters,
+        })
+        .collect();
+    let id = batch_service::create(job, texts);
+    // 请求只能放慢，不能比配置的间隔更快
+    let interval = Duration::from_millis(batch.interval_ms.unwrap_or(0).max(config.interval_ms));
+    tokio::task::spawn_blocking(move || {
+        let result = batch_service::run(&wechat, id, interval)
+            .map(|r| format!("发送 {}，排队 {}，失败 {}，待发 {}", r.sent, r.queued, r.failed, r.pending));
+        if let Err(e) = &result {
+            log::warn!("批量发送 #{} 中断: {}", id, e);
+        }
+        jobs::finish(job, result);
+    });
+    Ok(api_ok(batch_service::report(id)))
+}
+
+/// 查询批量发送的每条消息的状态
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/batch-text/{id}",
+    params(
+        ("id" = u64, Path, description = "批次编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseBatch, description = "每条消息的发送情况")
+    )
+)]
+pub async fn get_batch_text(id: u64, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    match batch_service::report(id) {
+        Some(report) => Ok(api_ok(report)),
+        None => Ok(api_error("批次不存在")),
+    }
+}
+
+/// 查询消息模板
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/templates",
+    responses(
+        (status = 200, body = ApiResponseTemplates, description = "按名称排序的模板")
+    )
+)]
+pub async fn list_templates(_wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    Ok(api_ok(GLOBAL.get().unwrap().template_service.lock().unwrap().list()))
+}
+
+/// 保存消息模板
+///
+/// 模板中的 {{变量}} 在发送时由 /send-template 填入，返回的 vars 为模板用到的变量。设置 rich_text 时发送卡片消息，它的每个字段都可以使用变量。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/templates",
+    request_body = NewTemplate,
+    responses(
+        (status = 200, body = ApiResponseTemplate, description = "保存后的模板")
+    )
+)]
+pub async fn save_template(template: NewTemplate, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let result = GLOBAL.get().unwrap().template_service.lock().unwrap().set(
+        template.name.trim(),
+        &template.text,
+        template.rich_text,
+    );
+    match result {
+        Ok(template) => Ok(api_ok(template)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 删除消息模板
+#[utoipa::path(
+    delete,
+    tag = "WCF",
+    path = "/templates/{name}",
+    params(
+        ("name" = String, Path, description = "模板名称")
+    ),
+    responses(
+        (status = 200, body = ApiResponseBool, description = "模板是否存在")
+    )
+)]
+pub async fn delete_template(name: String) -> Result<Json, Infallible> {
+    let name = urlencoding::decode(&name).map(|n| n.into_owned()).unwrap_or(name);
+    match GLOBAL.get().unwrap().template_service.lock().unwrap().remove(&name) {
+        Ok(removed) => Ok(api_ok(removed)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 用模板发送消息
+///
+/// 填入变量后按模板发送文本或卡片消息，之后的处理与 /text、/rich-text 相同。缺少模板用到的变量时不发送。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/send-template",
+    params(DryRunQuery),
+    request_body = SendTemplate,
+    responses(
+        (status = 200, body = ApiResponseSendResult, description = "发送模板消息"),
+        (status = 400, body = ApiResponseFieldErrors, description = "参数校验失败")
+    )
+)]
+pub async fn send_template(
+    msg: SendTemplate,
+    dry: DryRunQuery,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<Json, Infallible> {
+    let template = match GLOBAL.get().unwrap().template_service.lock().unwrap().get(msg.name.trim()) {
+        Some(template) => template,
+        None => return Ok(api_error(format!("模板 {} 不存在", msg.name.trim()))),
+    };
+    let vars: HashMap<String, String> = msg
+        .vars
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::String(s) => (name, s),
+            Value::Null => (name, String::new()),
+            v => (name, v.to_string()),
+        })
+        .collect();
+    match template.fill(&vars) {
+        Ok(Filled::Text(text)) => {
+            if text.trim().is_empty() {
+                return Ok(api_error("发送模板消息失败: 生成的消息为空"));
+            }
+            let text = SendText {
+                text: TextMsg {
+                    msg: text,
+                    receiver: msg.receiver,
+                    aters: String::new(),
+                },
+                mentions: msg.mentions,
+            };
+            send_text(text, dry, wechat).await
+        }
+        Ok(Filled::RichText(rich)) => {
+            if rich.title.trim().is_empty() || rich.url.trim().is_empty() {
+                return Ok(api_error("发送模板消息失败: 卡片消息的 title 和 url 不能为空"));
+            }
+            let rich = RichText {
+                name: rich.name,
+                account: rich.account,
+                title: rich.title,
+                digest: rich.digest,
+                url: rich.url,
+                thumburl: rich.thumburl,
+                receiver: msg.receiver,
+            };
+            send_rich_text(rich, dry, wechat).await
+        }
+        Err(e) => Ok(api_error(format!("发送模板消息失败: {}", e))),
+    }
+}
+
+/// 查询身份合并
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/identities",
+    responses(
+        (status = 200, body = ApiResponseIdentities, description = "按主账号排序的身份")
+    )
+)]
+pub async fn list_identities(_wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    Ok(api_ok(GLOBAL.get().unwrap().identity_service.lock().unwrap().list()))
+}
+
+/// 合并同一个人的多个账号
+///
+/// 统计、数据导出和按发送人匹配的规则都会把别名当作主账号处理，aliases 为空时取消合并。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/identities",
+    request_body = NewIdentity,
+    responses(
+        (status = 200, body = ApiResponseIdentity, description = "保存后的身份，取消合并时为空")
+    )
+)]
+pub async fn set_identity(identity: NewIdentity, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let aliases: Vec<String> = identity.aliases.iter().map(|a| a.trim().to_string()).collect();
+    let result = GLOBAL.get().unwrap().identity_service.lock().unwrap().set(
+        identity.canonical.trim(),
+        identity.name.trim(),
+        &aliases,
+    );
+    match result {
+        Ok(identity) => Ok(api_ok(identity)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 查询群成员在 Telegram、Discord 上的身份
+///
+/// 转发消息时用于双向标注发送人：从微信转出时按 wxid 查显示名和头像，从外部平台转入时按外部用户 id 查对应的微信账号。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/bridge-identities",
+    params(BridgeQuery),
+    responses(
+        (status = 200, body = ApiResponseBridgeIdentities, description = "匹配的映射")
+    )
+)]
+pub async fn list_bridge_identities(query: BridgeQuery, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let filled = |v: &Option<String>| v.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+    let (platform, wxid, external_id) = (filled(&query.platform), filled(&query.wxid), filled(&query.external_id));
+    let bridges = GLOBAL.get().unwrap().bridge_identity_service.lock().unwrap();
+    let found = match (platform.as_deref(), wxid, external_id) {
+        (Some(platform), Some(wxid), _) => bridges.for_wxid(platform, &wxid).into_iter().collect(),
+        (Some(platform), None, Some(external_id)) => bridges.for_external(platform, &external_id).into_iter().collect(),
+        (None, Some(_), _) | (None, None, Some(_)) => return Ok(api_error("按 wxid 或 external_id 查找时需要指定 platform")),
+        (platform, None, None) => bridges.list(platform),
+    };
+    Ok(api_ok::<Vec<BridgeIdentity>>(found))
+}
+
+/// 设置群成员在外部平台上的身份
+///
+/// 同一平台上一个微信账号只对应一个外部用户，external_id 为空时删除映射。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/bridge-identities",
+    request_body = NewBridgeIdentity,
+    responses(
+        (status = 200, body = ApiResponseBridgeIdentity, description = "保存后的映射，删除时为空")
+    )
+)]
+pub async fn set_bridge_identity(identity: NewBridgeIdentity, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let identity = BridgeIdentity {
+        platform: identity.platform.trim().to_lowercase(),
+        wxid: identity.wxid.trim().to_string(),
+        external_id: identity.external_id.trim().to_string(),
+        display_name: identity.display_name.trim().to_string(),
+        avatar: identity.avatar.trim().to_string(),
+    };
+    match GLOBAL.get().unwrap().bridge_identity_service.lock().unwrap().set(identity) {
+        Ok(identity) => Ok(api_ok(identity)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 查询转发消息的对应关系
+///
+/// 转发引用回复时保持回复关系：微信消息的 quoted_msg_id 按 msg_id 查外部平台上的消息，外部平台的回复按 chat 和 external_id 查被回复的微信消息。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/bridge-threads",
+    params(ThreadQuery),
+    responses(
+        (status = 200, body = ApiResponseThreadLinks, description = "匹配的对应关系，没有时为空")
+    )
+)]
+pub async fn list_bridge_threads(query: ThreadQuery, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let platform = query.platform.trim().to_lowercase();
+    let threads = GLOBAL.get().unwrap().bridge_thread_service.lock().unwrap();
+    let found = match (query.msg_id, query.chat, query.external_id) {
+        (Some(msg_id), _, _) => threads.external_for(&platform, msg_id),
+        (None, Some(chat), Some(external_id)) => threads.wechat_for(&platform, chat.trim(), external_id.trim()),
+        _ => return Ok(api_error("需要指定 msg_id，或同时指定 chat 和 external_id")),
+    };
+    Ok(api_ok::<Vec<ThreadLink>>(found.into_iter().collect()))
+}
+
+/// 记录转发消息的对应关系
+///
+/// 消息转发到外部平台或从外部平台转入微信后调用，同一条消息再次记录时覆盖。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/bridge-threads",
+    request_body = NewThreadLink,
+    responses(
+        (status = 200, body = ApiResponseThreadLink, description = "保存的对应关系")
+    )
+)]
+pub async fn record_bridge_thread(link: NewThreadLink, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let link = ThreadLink {
+        platform: link.platform.trim().to_lowercase(),
+        msg_id: link.msg_id,
+        roomid: link.roomid.trim().to_string(),
+        chat: link.chat.trim().to_string(),
+        external_id: link.external_id.trim().to_string(),
+        created_at: chrono::Local::now(),
+    };
+    match GLOBAL.get().unwrap().bridge_thread_service.lock().unwrap().record(link.clone()) {
+        Ok(()) => Ok(api_ok(link)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 查询联系人的改名记录
+///
+/// 开启联系人变化检测后记录昵称、备注、群昵称和头像出现过的值，按时间先后排列。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/contact/{wxid}/names",
+    params(
+        ("wxid" = String, Path, description = "联系人 wxid")
+    ),
+    responses(
+        (status = 200, body = ApiResponseNameHistory, description = "改名记录，没有记录时为空")
+    )
+)]
+pub async fn get_name_history(wxid: String, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    Ok(api_ok(GLOBAL.get().unwrap().name_history_service.lock().unwrap().history(&wxid)))
+}
+
+/// 查询识别过的票据
+///
+/// 配置的联系人发来的图片会自动识别金额、日期和商户并追加到 CSV，可按发送人筛选。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/receipts",
+    params(ReceiptQuery),
+    responses(
+        (status = 200, body = ApiResponseReceipts, description = "按接收顺序排列的票据")
+    )
+)]
+pub async fn get_receipts(query: ReceiptQuery, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let sender = query.sender.filter(|s| !s.trim().is_empty());
+    let result = tokio::task::spawn_blocking(move || receipt_service::list(sender.as_deref()))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(receipts) => Ok(api_ok(receipts)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 查询群打卡统计
+///
+/// 按连续打卡天数排序，可作为排行榜使用。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/checkin",
+    params(RoomQuery),
+    responses(
+        (status = 200, body = ApiResponseCheckin, description = "每个成员的累计和连续打卡天数")
+    )
+)]
+pub async fn get_checkin_stats(query: RoomQuery, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let stats = global.checkin_service.lock().unwrap().stats(&query.roomid);
+    Ok(api_ok(stats))
+}
+
+/// 在群里发起抽奖
+///
+/// 报名期间在群里发送关键词即参与，也可以在群里用 /raffle 指令发起。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/raffles",
+    request_body = NewRaffle,
+    responses(
+        (status = 200, body = ApiResponseRaffle, description = "新建的抽奖")
+    )
+)]
+pub async fn create_raffle(raffle: NewRaffle, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if !raffle.roomid.ends_with("@chatroom") {
+        return Ok(api_error("roomid 必须是群"));
+    }
+    let created = {
+        let global = GLOBAL.get().unwrap();
+        let mut raffles = global.raffle_service.lock().unwrap();
+        raffles.create(&raffle.roomid, &raffle.keyword, raffle.count, raffle.minutes)
+    };
+    let created = match created {
+        Ok(created) => created,
+        Err(e) => return Ok(api_error(e)),
+    };
+    let raffle = created.clone();
+    let sent = tokio::task::spawn_blocking(move || {
+        let wc = wechat.lock().unwrap().clone();
+        raffle_service::announce(&wc, &raffle)
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    if let Err(e) = sent {
+        return Ok(api_error(format!("抽奖已创建，但发送到群里失败: {}", e)));
+    }
+    Ok(api_ok(created))
+}
+
+/// 查询抽奖报名和开奖结果
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/raffles/{id}",
+    params(
+        ("id" = u64, Path, description = "抽奖编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseRaffle, description = "参与名单和开奖结果")
+    )
+)]
+pub async fn get_raffle(id: u64, _wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let raffle = GLOBAL.get().unwrap().raffle_service.lock().unwrap().get(id);
+    match raffle {
+        Some(raffle) => Ok(api_ok(raffle)),
+        None => Ok(api_error("抽奖不存在")),
+    }
+}
+
+/// 开奖并把结果发到群里
+///
+/// 随机数种子会记录在结果和 data/raffle-audit.ndjson 中，可用于复核。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/raffles/{id}/draw",
+    params(
+        ("id" = u64, Path, description = "抽奖编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseRaffle, description = "开奖结果")
+    )
+)]
+pub async fn draw_raffle(id: u64, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let drawn = GLOBAL.get().unwrap().raffle_service.lock().unwrap().draw(id);
+    let raffle = match drawn {
+        Ok(raffle) => raffle,
+        Err(e) => return Ok(api_error(e)),
+    };
+    let drawn = raffle.clone();
+    let sent = tokio::task::spawn_blocking(move || {
+        let wc = wechat.lock().unwrap().clone();
+        raffle_service::announce_draw(&wc, &drawn)
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    if let Err(e) = sent {
+        warn!("发送开奖结果失败: {}", e);
+    }
+    Ok(api_ok(raffle))
+}
+
+/// 下载图片
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/download-image",
+    params(
+        ("id" = u64, Query, description = "消息ID"),
+        ("extra" = String, Query, description = "extra"),
+        ("dir" = String, Query, description = "存放目录"),
+        ("timeout" = u8, Query, description = "超时时间(秒)")
+    ),
+    responses(
+        (status = 200, description = "返回图片文件流", content_type = "image/*")
+    )
+)]
+pub async fn download_image(params: DownloadImageParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
+    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
+        Ok(Box::new(warp::reply::with_status(
+            error_message,
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )))
+    };
+
+    let timeout = Duration::from_secs(params.timeout as u64);
+    let path = match media_service::download_and_decrypt_image(&wechat, params.id, &params.extra, &params.dir, timeout).await {
+        Ok(path) => path,
+        Err(error) => return handle_error(error),
+    };
+
+    // 读取文件内容
+    match tokio::fs::read(&path).await {
+        Ok(content) => {
+            // 根据文件扩展名确定 Content-Type
+            let content_type = if path.ends_with(".jpg") || path.ends_with(".jpeg") {
+                "image/jpeg"
+            } else if path.ends_with(".png") {
+                "image/png"
+            } else {
+                "application/octet-stream"
+            };
+
+            // 返回文件流
+            Ok(Box::new(warp::reply::with_header(
+                content,
+                "Content-Type",
+                content_type,
+            )))
+        }
+        Err(e) => handle_error(format!("读取文件失败: {}", e)),
+    }
+}
+
+/// 下载文件
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/download-file",
+    params(
+        ("id" = u64, Query, description = "消息ID"),
+        ("extra" = String, Query, description = "extra"),
+        ("thumb" = String, Query, description = "缩略图")
+    ),
+    responses(
+        (status = 200, description = "返回文件流", content_type = "application/octet-stream")
+    )
+)]
+pub async fn download_file(params: DownloadFileParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
+    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
+        Ok(Box::new(warp::reply::with_status(
+            error_message,
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )))
+    };
+
+    let att = AttachMsg {
+        id: params.id,
+        thumb: params.thumb,
+        extra: params.extra.clone(),
+    };
+
+    let status = {
+        let wc = wechat.lock().unwrap();
+        match wc.clone().download_attach(att) {
+            Ok(status) => status,
+            Err(error) => return handle_error(error.to_string()),
+        }
+    };
+
+    if !status {
+        return handle_error("下载失败".to_string());
+    }
+
+    // 读取文件内容
+    match tokio::fs::read(&params.extra).await {
+        Ok(content) => {
+            let content_type = guess_content_type(&params.extra);
+
+            // 返回文件流
+            return Ok(Box::new(warp::reply::with_header(
+                content,
+                "Content-Type",
+                content_type,
+            )));
+        }
+        Err(e) => return handle_error(format!("读取文件失败: {}", e)),
+    }
+}
+
+/// 根据文件扩展名确定 Content-Type
+fn guess_content_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+
+    match extension.to_lowercase().as_str() {
+        "pdf" => "application/pdf",
+        "doc" | "docx" => "application/msword",
+        "xls" | "xlsx" => "application/vnd.ms-excel",
+        "ppt" | "pptx" => "application/vnd.ms-powerpoint",
+        "zip" => "application/zip",
+        "rar" => "application/x-rar-compressed",
+        "txt" => "text/plain",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+fn media_dir() -> String {
+    media_service::media_dir().to_string_lossy().to_string()
+}
+
+/// 按消息类型下载媒体文件，返回本地路径
+async fn fetch_media_file(
+    wechat: &Arc<Mutex<WeChat>>,
+    id: u64,
+    media: &MsgMedia,
+    timeout: Duration,
+) -> Result<String, String> {
+    match media.r#type {
+        3 => media_service::download_and_decrypt_image(wechat, id, &media.extra, &media_dir(), timeout).await,
+        34 => {
+            let wc = wechat.lock().unwrap();
+            wc.save_audio(AudioMsg { id, dir: media_dir() })
+                .map_err(|e| e.to_string())
+        }
+        43 | 49 if !media.extra.is_empty() => {
+            media_service::download_attach_file(wechat, id, &media.extra, &media.thumb, &media.extra, timeout).await
+        }
+        t => Err(format!("不支持的消息类型: {}", t)),
+    }
+}
+
+/// 根据消息 id 获取媒体文件
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/resolve-media",
+    params(
+        ("id" = u64, Query, description = "消息ID"),
+        ("timeout" = Option<u8>, Query, description = "超时时间(秒)，默认 10")
+    ),
+    responses(
+        (status = 200, description = "自动识别图片、语音、视频、文件消息并返回文件流", content_type = "application/octet-stream")
+    )
+)]
+pub async fn resolve_media(params: ResolveMediaParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
+    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
+        Ok(Box::new(warp::reply::with_status(
+            error_message,
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )))
+    };
+
+    let media = {
+        let wc = wechat.lock().unwrap();
+        match wc.query_msg_media(params.id) {
+            Ok(Some(media)) => media,
+            Ok(None) => {
+                return Ok(Box::new(warp::reply::with_status(
+                    "未找到消息".to_string(),
+                    warp::http::StatusCode::NOT_FOUND,
+                )))
+            }
+            Err(error) => return handle_error(error.to_string()),
+        }
+    };
+
+    let timeout = Duration::from_secs(params.timeout as u64);
+    let path = match fetch_media_file(&wechat, params.id, &media, timeout).await {
+        Ok(path) => path,
+        Err(error) => return handle_error(error),
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(content) => Ok(Box::new(warp::reply::with_header(
+            content,
+            "Content-Type",
+            guess_content_type(&path),
+        ))),
+        Err(e) => handle_error(format!("读取文件失败: {}", e)),
+    }
+}
+
+/// 获取图片、视频消息的缩略图
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/thumbnail",
+    params(
+        ("id" = u64, Query, description = "消息ID"),
+        ("max" = Option<u32>, Query, description = "缩略图最长边(像素)，默认 256"),
+        ("timeout" = Option<u8>, Query, description = "超时时间(秒)，默认 10")
+    ),
+    responses(
+        (status = 200, description = "返回 JPEG 缩略图", content_type = "image/jpeg")
+    )
+)]
+pub async fn get_thumbnail(params: ThumbnailParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
+    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
+        Ok(Box::new(warp::reply::with_status(
+            error_message,
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )))
+    };
+
+    let media = {
+        let wc = wechat.lock().unwrap();
+        match wc.query_msg_media(params.id) {
+            Ok(Some(media)) => media,
+            Ok(None) => {
+                return Ok(Box::new(warp::reply::with_status(
+                    "未找到消息".to_string(),
+                    warp::http::StatusCode::NOT_FOUND,
+                )))
+            }
+            Err(error) => return handle_error(error.to_string()),
+        }
+    };
+
+    let timeout = Duration::from_secs(params.timeout as u64);
+    let result = match media.r#type {
+        3 => media_service::download_and_decrypt_image(&wechat, params.id, &media.extra, &media_dir(), timeout).await,
+        43 => fetch_video_preview(&wechat, params.id, &media, false, timeout).await,
+        t => Err(format!("不支持的消息类型: {}", t)),
+    };
+    let path = match result {
+        Ok(path) => path,
+        Err(error) => return handle_error(error),
+    };
+
+    let max = params.max.clamp(16, 2048);
+    match tokio::task::spawn_blocking(move || encode_thumbnail(&path, max)).await {
+        Ok(Ok(content)) => Ok(Box::new(warp::reply::with_header(
+            content,
+            "Content-Type",
+            "image/jpeg",
+        ))),
+        Ok(Err(error)) => handle_error(error),
+        Err(e) => handle_error(format!("生成缩略图失败: {}", e)),
+    }
+}
+
+/// 等比缩放图片至最长边不超过 max，编码为 JPEG
+fn encode_thumbnail(path: &str, max: u32) -> Result<Vec<u8>, String> {
+    let img = image::open(path).map_err(|e| format!("解码图片失败: {}", e))?;
+    let thumb = img.thumbnail(max, max).to_rgb8();
+    let mut buf = Vec::new();
+    JpegEncoder::new_with_quality(&mut buf, 80)
+        .encode_image(&thumb)
+        .map_err(|e| format!("编码缩略图失败: {}", e))?;
+    Ok(buf)
+}
+
+/// 获取视频封面，封面缺失或 first_frame 为 true 时用 ffmpeg 截取首帧
+async fn fetch_video_preview(
+    wechat: &Arc<Mutex<WeChat>>,
+    id: u64,
+    media: &MsgMedia,
+    first_frame: bool,
+    timeout: Duration,
+) -> Result<String, String> {
+    if !first_frame && !media.thumb.is_empty() {
+        match media_service::download_attach_file(wechat, id, &media.extra, &media.thumb, &media.thumb, timeout).await {
+            Ok(path) => return Ok(path),
+            Err(error) => debug!("获取视频封面失败，尝试截取首帧: {}", error),
+        }
+    }
+
+    let video = media_service::download_attach_file(wechat, id, &media.extra, &media.thumb, &media.extra, timeout).await?;
+    let ffmpeg = {
+        let global = GLOBAL.get().unwrap();
+        let config = global.wechat_config.read().unwrap();
+        config
+            .ffmpeg_path
+            .clone()
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| "ffmpeg".to_string())
+    };
+    let output = PathBuf::from(media_dir()).join(format!("{}_preview.jpg", id));
+    video::extract_first_frame(&ffmpeg, Path::new(&video), &output).await?;
+    Ok(output.to_string_lossy().to_string())
+}
+
+/// 获取视频预览图
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/video-preview",
+    params(
+        ("id" = u64, Query, description = "消息ID"),
+        ("frame" = Option<bool>, Query, description = "是否强制截取首帧(需要 ffmpeg)"),
+        ("timeout" = Option<u8>, Query, description = "超时时间(秒)，默认 10")
+    ),
+    responses(
+        (status = 200, description = "返回视频封面或首帧图片，完整视频请使用 /resolve-media", content_type = "image/jpeg")
+    )
+)]
+pub async fn get_video_preview(params: VideoPreviewParams, wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
+    let handle_error = |error_message: String| -> Result<Box<dyn Reply>, Infallible> {
         Ok(Box::new(warp::reply::with_status(
             error_message,
             warp::http::StatusCode::INTERNAL_SERVER_ERROR,
         )))
     };
 
-    let att = AttachMsg {
-        id: params.id,
-        thumb: params.thumb,
-        extra: params.extra.clone(),
+    let media = {
+        let wc = wechat.lock().unwrap();
+        match wc.query_msg_media(params.id) {
+            Ok(Some(media)) if media.r#type == 43 => media,
+            Ok(Some(_)) => return handle_error("不是视频消息".to_string()),
+            Ok(None) => {
+                return Ok(Box::new(warp::reply::with_status(
+                    "未找到消息".to_string(),
+                    warp::http::StatusCode::NOT_FOUND,
+                )))
+            }
+            Err(error) => return handle_error(error.to_string()),
+        }
+    };
+
+    let timeout = Duration::from_secs(params.timeout as u64);
+    let path = match fetch_video_preview(&wechat, params.id, &media, params.frame, timeout).await {
+        Ok(path) => path,
+        Err(error) => return handle_error(error),
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(content) => Ok(Box::new(warp::reply::with_header(
+            content,
+            "Content-Type",
+            guess_content_type(&path),
+        ))),
+        Err(e) => handle_error(format!("读取文件失败: {}", e)),
+    }
+}
+
+/// 获取已缓存的表情
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/emotion/{md5}",
+    params(
+        ("md5" = String, Path, description = "表情消息中的 md5")
+    ),
+    responses(
+        (status = 200, description = "返回表情文件流", content_type = "image/*"),
+        (status = 404, description = "表情未缓存")
+    )
+)]
+pub async fn get_emotion(md5: String, _wechat: Arc<Mutex<WeChat>>) -> Result<Box<dyn Reply>, Infallible> {
+    let path = match media_service::find_cached_emotion(&md5) {
+        Some(path) => path,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+    let path = path.to_string_lossy().to_string();
+    match tokio::fs::read(&path).await {
+        Ok(content) => Ok(Box::new(warp::reply::with_header(
+            content,
+            "Content-Type",
+            guess_content_type(&path),
+        ))),
+        Err(e) => Ok(Box::new(warp::reply::with_status(
+            format!("读取文件失败: {}", e),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        ))),
+    }
+}
+
+/// 检测好友状态（是否被删除）
+///
+/// 逐个把好友拉入探测群再立即移出，拉群失败视为已被删除。
+/// 会打扰对方，且频繁操作有封号风险，请严格控制使用频率。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/check-friend-status",
+    request_body = FriendCheck,
+    responses(
+        (status = 200, body = FriendCheckReport, description = "返回每个 wxid 的检测结果")
+    )
+)]
+pub async fn check_friend_status(msg: FriendCheck, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if !msg.confirm {
+        return Ok(api_error("该操作会将好友短暂拉入群聊，可能被对方察觉并有封号风险，确认后请传 confirm: true"));
+    }
+    if !msg.roomid.ends_with("@chatroom") {
+        return Ok(api_error("roomid 不是群聊"));
+    }
+    if msg.wxids.is_empty() || msg.wxids.len() > FRIEND_CHECK_MAX {
+        return Ok(api_error(format!("wxids 数量需在 1 到 {} 之间", FRIEND_CHECK_MAX)));
+    }
+    if FRIEND_CHECK_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(api_error("已有检测任务在进行中"));
+    }
+    let _guard = FriendCheckGuard;
+
+    let flags = {
+        let wc = wechat.lock().unwrap();
+        wc.query_contact_flags()
+    };
+    let flags = match flags {
+        Ok(flags) => flags,
+        Err(error) => return Ok(api_error(format!("查询联系人失败: {}", error))),
+    };
+
+    let mut results = vec![];
+    let mut probed = false;
+    for wxid in msg.wxids {
+        if !flags.get(&wxid).map_or(false, |f| f.is_friend()) {
+            results.push(FriendStatus {
+                wxid,
+                state: FriendState::NotInContacts,
+                detail: None,
+            });
+            continue;
+        }
+        if let Err(error) = {
+            let global = GLOBAL.get().unwrap();
+            let mut guard = global.risk_guard_service.lock().unwrap();
+            guard.consume(RiskOperation::FriendCheck, 1)
+        } {
+            results.push(FriendStatus {
+                wxid,
+                state: FriendState::Unknown,
+                detail: Some(error),
+            });
+            continue;
+        }
+        if probed {
+            tokio::time::sleep(pacing::interval(FRIEND_CHECK_INTERVAL)).await;
+        }
+        probed = true;
+
+        let member = MemberMgmt {
+            roomid: msg.roomid.clone(),
+            wxids: wxid.clone(),
+        };
+        let added = {
+            let wc = wechat.lock().unwrap();
+            wc.add_chatroom_member(member.clone())
+        };
+        let status = match added {
+            Ok(true) => {
+                let removed = {
+                    let wc = wechat.lock().unwrap();
+                    wc.delete_chatroom_member(member)
+                };
+                FriendStatus {
+                    wxid,
+                    state: FriendState::Normal,
+                    detail: match removed {
+                        Ok(true) => None,
+                        _ => Some("移出探测群失败，请手动处理".to_string()),
+                    },
+                }
+            }
+            Ok(false) => FriendStatus {
+                wxid,
+                state: FriendState::Removed,
+                detail: None,
+            },
+            Err(error) => FriendStatus {
+                wxid,
+                state: FriendState::Unknown,
+                detail: Some(error.to_string()),
+            },
+        };
+        results.push(status);
+    }
+
+    Ok(api_ok(FriendCheckReport {
+        warning: "探测会短暂将好友拉入群聊，请勿频繁使用".to_string(),
+        results,
+    }))
+}
+
+/// 查询免打扰期间排队的消息
+///
+/// 免打扰时段在配置文件的 quiet_hours 中设置，期间定时汇总、群发等自动消息会排队，时段结束后依次发出。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/quiet-hours",
+    responses(
+        (status = 200, body = ApiResponseQueuedTexts, description = "排队中的消息")
+    )
+)]
+pub async fn get_quiet_queue(_wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let queued = global.quiet_hours_service.lock().unwrap().queued();
+    Ok(api_ok(queued))
+}
+
+/// 查询各会话的消息量和基线
+///
+/// 按 anomaly.window_mins 分钟一个窗口统计，消息量暴增或骤降到基线的 anomaly.factor 倍以外时通知管理员。基线按一天中的小时分别学习，返回的是当前小时的基线。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/message-volume",
+    responses(
+        (status = 200, body = ApiResponseVolumes, description = "当前窗口的消息数和学习到的基线")
+    )
+)]
+pub async fn get_message_volume(_wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let volumes = global.anomaly_service.lock().unwrap().volumes();
+    Ok(api_ok(volumes))
+}
+
+/// 查询今日剩余风控预算
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/risk-budget",
+    responses(
+        (status = 200, body = ApiResponseBudgets, description = "各项高风险操作的今日用量与上限")
+    )
+)]
+pub async fn get_risk_budget(_wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let status = global.risk_guard_service.lock().unwrap().status();
+    Ok(api_ok(status))
+}
+
+/// 健康检查
+///
+/// 返回当前登录状态、启动自检结果和看门狗的自动恢复记录，自检会检查 sdk.dll、微信版本是否与 SDK 匹配以及注入是否成功。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/health",
+    responses(
+        (status = 200, body = ApiResponseHealth, description = "健康状态")
+    )
+)]
+pub async fn get_health(wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    let logged_in = wechat.lock().unwrap().is_login_cached();
+    let preflight = preflight_service::last_report();
+    let ok = logged_in && preflight.as_ref().map_or(true, |r| r.ok);
+    let (self_heals, last_self_heal) = GLOBAL.get().unwrap().watchdog_service.lock().unwrap().status();
+    Ok(api_ok(HealthStatus {
+        ok,
+        logged_in,
+        preflight,
+        self_heals,
+        last_self_heal,
+    }))
+}
+
+/// 回放历史消息
+///
+/// 把记录下来的消息按原顺序重新送入消息处理流程（回调、socketIO、事件处理等），用于调试规则和插件。
+/// 每行一条消息，可以是原始 WxMsg 或推送格式。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/replay",
+    params(ReplayQuery),
+    request_body(content = String, content_type = "application/x-ndjson", description = "NDJSON，每行一条消息"),
+    responses(
+        (status = 200, body = ReplayReport, description = "回放条数和无法解析的行号")
+    )
+)]
+pub async fn replay_messages(query: ReplayQuery, body: warp::hyper::body::Bytes) -> Result<Json, Infallible> {
+    let text = match &query.file {
+        Some(file) => {
+            let path = match replay_service::resolve_file(file) {
+                Ok(path) => path,
+                Err(e) => return Ok(api_error(e)),
+            };
+            match fs::read_to_string(path).await {
+                Ok(text) => text,
+                Err(e) => return Ok(api_error(format!("读取文件失败: {}", e))),
+            }
+        }
+        None => match String::from_utf8(body.to_vec()) {
+            Ok(text) => text,
+            Err(_) => return Ok(api_error("请求体不是 UTF-8 文本")),
+        },
+    };
+
+    let (mut messages, skipped) = replay_service::parse_ndjson(&text);
+    if let Some(limit) = query.limit {
+        messages.truncate(limit);
+    }
+    if messages.is_empty() {
+        return Ok(api_error("没有可回放的消息"));
+    }
+    let replayed = replay_service::replay(messages, Duration::from_millis(query.interval_ms)).await;
+    Ok(api_ok(ReplayReport { replayed, skipped }))
+}
+
+fn parse_since(since: &str) -> Option<chrono::DateTime<chrono::Local>> {
+    use chrono::TimeZone;
+    if let Ok(ts) = since.parse::<i64>() {
+        return chrono::Local.timestamp_opt(ts, 0).single();
+    }
+    chrono::NaiveDateTime::parse_from_str(since, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|t| chrono::Local.from_local_datetime(&t).single())
+}
+
+// 可选的时间参数转为 unix 秒，空字符串视为未填
+fn parse_time_param(value: &Option<String>) -> Result<Option<i64>, String> {
+    match value.as_deref().filter(|v| !v.is_empty()) {
+        Some(v) => parse_since(v).map(|t| Some(t.timestamp())).ok_or(format!("时间格式不正确: {}", v)),
+        None => Ok(None),
+    }
+}
+
+/// 接收外部系统的 webhook
+///
+/// 按 hook 配置的模板把请求体转成文本消息发给配置的接收人。请求头需带 X-Webhook-Timestamp（unix 秒）和 X-Webhook-Nonce，
+/// 以及 X-Webhook-Signature，为 HMAC-SHA256("{时间戳}.{nonce}.{请求体}") 的十六进制，没有配置密钥的 hook 不接收投递。
+/// 时间戳过期或 nonce 重复的投递会被拒绝，配置了 schema 时请求体需满足该 JSON Schema。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/webhook/{name}",
+    params(
+        ("name" = String, Path, description = "hook 名称"),
+        ("X-Webhook-Timestamp" = String, Header, description = "发送时间，unix 秒"),
+        ("X-Webhook-Nonce" = String, Header, description = "每次投递不同的随机串"),
+        ("X-Webhook-Signature" = String, Header, description = "签名")
+    ),
+    request_body(content = String, content_type = "application/json", description = "外部系统的事件"),
+    responses(
+        (status = 200, body = ApiResponseBool, description = "是否已立即发送，处于免打扰时段时为 false"),
+        (status = 400, body = ApiResponseFieldErrors, description = "请求体不满足 schema 或缺少模板需要的字段"),
+        (status = 401, description = "签名错误、时间戳过期或 hook 没有密钥"),
+        (status = 404, description = "没有这个 hook"),
+        (status = 409, description = "重复投递"),
+        (status = 503, description = "发送微信消息失败，可以稍后重试")
+    )
+)]
+pub async fn receive_webhook(
+    name: String,
+    timestamp: Option<String>,
+    nonce: Option<String>,
+    signature: Option<String>,
+    body: warp::hyper::body::Bytes,
+    wechat: Arc<Mutex<WeChat>>,
+) -> Result<warp::reply::WithStatus<Json>, Infallible> {
+    let result = tokio::task::spawn_blocking(move || {
+        let delivery = webhook_service::Delivery {
+            timestamp: timestamp.as_deref(),
+            nonce: nonce.as_deref(),
+            signature: signature.as_deref(),
+            body: &body,
+        };
+        webhook_service::handle(&wechat, &name, &delivery)
+    })
+    .await
+    .unwrap_or_else(|e| Err(Rejected::Send(e.to_string())));
+    let reply = match result {
+        Ok(sent) => (api_ok(sent), StatusCode::OK),
+        Err(Rejected::UnknownHook) => (api_error("没有这个 hook"), StatusCode::NOT_FOUND),
+        Err(Rejected::NoSecret) => (api_error_code(CODE_UNAUTHORIZED, "hook 没有配置密钥"), StatusCode::UNAUTHORIZED),
+        Err(Rejected::BadSignature) => (api_error_code(CODE_UNAUTHORIZED, "签名错误"), StatusCode::UNAUTHORIZED),
+        Err(Rejected::Stale) => (
+            api_error_code(CODE_STALE_DELIVERY, "缺少时间戳或 nonce，或时间戳已过期"),
+            StatusCode::UNAUTHORIZED,
+        ),
+        Err(Rejected::Duplicate) => (api_error_code(CODE_DUPLICATE_DELIVERY, "重复投递"), StatusCode::CONFLICT),
+        Err(Rejected::Invalid(errors)) => {
+            let errors = errors.into_iter().map(|(field, message)| FieldError { field, message });
+            (invalid_fields(&InvalidFields(errors.collect())), StatusCode::BAD_REQUEST)
+        }
+        Err(Rejected::Send(e)) => {
+            (api_error(format!("发送 webhook 消息失败: {}", e)), StatusCode::SERVICE_UNAVAILABLE)
+        }
+    };
+    Ok(warp::reply::with_status(reply.0, reply.1))
+}
+
+/// 查询最近的日志
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/logs",
+    params(LogQuery),
+    responses(
+        (status = 200, body = Vec<LogEntry>, description = "按时间顺序返回匹配的日志，完整日志见程序目录下的 logs")
+    )
+)]
+pub async fn query_logs(query: LogQuery) -> Result<Json, Infallible> {
+    let level = match query.level.as_deref().map(str::parse::<log::Level>) {
+        Some(Ok(level)) => Some(level),
+        Some(Err(_)) => return Ok(api_error("level 只能是 error、warn、info、debug、trace")),
+        None => None,
+    };
+    let since = match query.since.as_deref() {
+        Some(since) => match parse_since(since) {
+            Some(since) => Some(since),
+            None => return Ok(api_error("since 格式错误")),
+        },
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(200).min(5000);
+    Ok(api_ok(log_buffer::query(level, since, query.grep.as_deref(), limit)))
+}
+
+/// 查询 SDK 版本
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/sdk-version",
+    responses(
+        (status = 200, body = SdkOverview, description = "当前版本、本机微信版本和可选的 SDK")
+    )
+)]
+pub async fn get_sdk_versions() -> Result<Json, Infallible> {
+    Ok(api_ok(SdkOverview {
+        active: sdk_service::active_version(),
+        installed_wechat: preflight_service::installed_wechat_version(),
+        versions: sdk_service::list(),
+    }))
+}
+
+/// 切换 SDK 版本
+///
+/// 本地没有时先下载并核对压缩包的 sha256，切换后需重启服务才会重新注入。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/sdk-version",
+    request_body = SdkSelect,
+    responses(
+        (status = 200, body = SdkOverview, description = "切换后的版本信息")
+    )
+)]
+pub async fn select_sdk_version(body: SdkSelect) -> Result<Json, Infallible> {
+    let version = match body.version {
+        Some(version) => version,
+        None => {
+            let installed = match preflight_service::installed_wechat_version() {
+                Some(installed) => installed,
+                None => return Ok(api_error("未检测到已安装的微信，请指定 SDK 版本")),
+            };
+            match sdk_service::match_wechat(&installed) {
+                Some(sdk) => sdk.to_string(),
+                None => return Ok(api_error(format!("没有适配微信 {} 的 SDK", installed))),
+            }
+        }
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        sdk_service::download(&version, body.sha256.as_deref())?;
+        sdk_service::select(&version)
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => get_sdk_versions().await,
+        Ok(Err(e)) => Ok(api_error(e)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 安装微信
+///
+/// 下载与当前 SDK 匹配的微信安装包并核对 sha256，可选启动安装程序并禁止自动更新。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/install-wechat",
+    request_body = InstallWechat,
+    responses(
+        (status = 200, body = InstallReport, description = "安装包路径和执行结果")
+    )
+)]
+pub async fn install_wechat(body: InstallWechat) -> Result<Json, Infallible> {
+    let version = match body.version.or_else(|| sdk_service::supported_wechat().map(str::to_string)) {
+        Some(version) => version,
+        None => return Ok(api_error("当前 SDK 没有登记适配的微信版本，请指定版本")),
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let path = wechat_installer_service::download(&version, &body.sha256)?;
+        if body.launch {
+            wechat_installer_service::launch(&path, &body.sha256)?;
+        }
+        if body.block_update {
+            wechat_installer_service::block_update()?;
+        }
+        Ok::<_, String>(InstallReport {
+            version,
+            installer: path.to_string_lossy().to_string(),
+            launched: body.launch,
+            update_blocked: body.block_update,
+        })
+    })
+    .await;
+    match result {
+        Ok(Ok(report)) => Ok(api_ok(report)),
+        Ok(Err(e)) => Ok(api_error(e)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 查询客户端版本
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/version",
+    responses(
+        (status = 200, body = VersionInfo, description = "当前版本和更新源上的最新版本")
+    )
+)]
+pub async fn get_version() -> Result<Json, Infallible> {
+    Ok(api_ok(update_service::version_info().await))
+}
+
+/// 更新客户端
+///
+/// 从配置的更新源下载新版本，安装完成后自动重启，请求返回时更新仍在进行。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/update",
+    responses(
+        (status = 200, body = ApiResponseString, description = "开始更新的目标版本")
+    )
+)]
+pub async fn update_client() -> Result<Json, Infallible> {
+    match update_service::update().await {
+        Ok(version) => Ok(api_ok(version)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 暂停所有自动发送
+///
+/// 紧急情况下使用，指令回复、群发、定时汇总和免打扰队列都会停止发送，收消息和回调推送照常进行。暂停状态重启后仍然有效。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/pause",
+    request_body = PauseRequest,
+    responses(
+        (status = 200, body = ApiResponsePause, description = "暂停后的状态")
+    )
+)]
+pub async fn pause_automation(request: PauseRequest) -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let status = global.pause_service.lock().unwrap().pause(request.reason);
+    Ok(api_ok(status))
+}
+
+/// 恢复自动发送
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/resume",
+    responses(
+        (status = 200, body = ApiResponsePause, description = "恢复后的状态")
+    )
+)]
+pub async fn resume_automation() -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let status = global.pause_service.lock().unwrap().resume();
+    Ok(api_ok(status))
+}
+
+/// 导出完整配置
+///
+/// 包含 config.json5 和指令授权等人工维护的设置，可在另一台机器上通过 /admin/config/import 导入。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/config/export",
+    responses(
+        (status = 200, body = ApiResponseConfigBundle, description = "配置导出包")
+    )
+)]
+pub async fn export_config() -> Result<Json, Infallible> {
+    match config_service::export() {
+        Ok(bundle) => Ok(api_ok(bundle)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 导入配置
+///
+/// 覆盖当前配置并立即生效，端口、回调地址等启动时读取的配置需重启服务后生效。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/config/import",
+    request_body = ConfigBundle,
+    responses(
+        (status = 200, body = ApiResponseBool, description = "是否导入成功")
+    )
+)]
+pub async fn import_config(bundle: ConfigBundle) -> Result<Json, Infallible> {
+    match config_service::import(bundle) {
+        Ok(()) => Ok(api_ok(true)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 已保存的配置方案
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/config/profiles",
+    responses(
+        (status = 200, body = ApiResponseStrings, description = "方案名称")
+    )
+)]
+pub async fn list_profiles() -> Result<Json, Infallible> {
+    Ok(api_ok(config_service::list_profiles()))
+}
+
+/// 把当前配置保存为方案，同名方案会被覆盖
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/config/profiles/{name}",
+    params(
+        ("name" = String, Path, description = "方案名称，只能包含字母、数字、- 和 _")
+    ),
+    responses(
+        (status = 200, body = ApiResponseConfigBundle, description = "保存的内容")
+    )
+)]
+pub async fn save_profile(name: String) -> Result<Json, Infallible> {
+    match config_service::save_profile(&name) {
+        Ok(bundle) => Ok(api_ok(bundle)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 切换到已保存的方案
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/config/profiles/{name}/apply",
+    params(
+        ("name" = String, Path, description = "方案名称")
+    ),
+    responses(
+        (status = 200, body = ApiResponseBool, description = "是否切换成功")
+    )
+)]
+pub async fn apply_profile(name: String) -> Result<Json, Infallible> {
+    match config_service::apply_profile(&name) {
+        Ok(()) => Ok(api_ok(true)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 检查推送地址是否可用
+///
+/// 向 http 回调发送一条测试消息（带 X-Wcf-Validate: 1 请求头），或请求 socketIO 的握手地址，报告连通性、TLS、认证和耗时，可在保存配置前使用。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/validate-sink",
+    request_body = SinkValidation,
+    responses(
+        (status = 200, body = ApiResponseSinkReports, description = "每个地址的检查结果")
+    )
+)]
+pub async fn validate_sink(validation: SinkValidation) -> Result<Json, Infallible> {
+    let url = validation.url.filter(|u| !u.is_empty());
+    Ok(api_ok(sink_validate_service::validate(validation.kind, url).await))
+}
+
+/// 测试消息规则
+///
+/// 用一条模拟的消息按优先级检查每条规则，返回是否命中、没有命中的原因（包括分组已停用、被前面的规则停止）、模板变量以及会执行的动作。动作只渲染不执行：不会发送消息、请求外部接口或运行脚本。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/rules/test",
+    request_body = RuleTest,
+    responses(
+        (status = 200, body = ApiResponseRuleTraces, description = "每条规则的测试结果")
+    )
+)]
+pub async fn test_rules(test: RuleTest) -> Result<Json, Infallible> {
+    let sender = test.sender.trim().to_string();
+    let roomid = Some(test.roomid.trim().to_string()).filter(|r| !r.is_empty());
+    let msg = WxMsg {
+        is_self: false,
+        is_group: roomid.is_some(),
+        id: 0,
+        r#type: test.kind.unwrap_or(1),
+        ts: test.at.as_deref().and_then(parse_since).unwrap_or_else(chrono::Local::now).timestamp() as u32,
+        roomid: roomid.unwrap_or_else(|| sender.clone()),
+        content: test.content,
+        sender,
+        sign: String::new(),
+        thumb: String::new(),
+        extra: String::new(),
+        xml: String::new(),
     };
+    Ok(api_ok(rule_service::simulate(&msg, test.at_me)))
+}
 
-    let status = {
-        let wc = wechat.lock().unwrap();
-        match wc.clone().download_attach(att) {
-            Ok(status) => status,
-            Err(error) => return handle_error(error.to_string()),
+/// 查询规则分组及启用状态
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/rules/groups",
+    responses(
+        (status = 200, body = ApiResponseRuleGroups, description = "配置中的规则分组")
+    )
+)]
+pub async fn list_rule_groups() -> Result<Json, Infallible> {
+    Ok(api_ok(rule_service::groups()))
+}
+
+/// 查询规则的触发计数
+///
+/// 按规则和会话统计最近一小时和启动以来的触发次数，以及因 cooldown_secs、max_per_hour 被跳过的次数。计数只保存在内存中，重启后清零。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/rules/stats",
+    responses(
+        (status = 200, body = ApiResponseRuleStats, description = "每条规则在每个会话中的计数")
+    )
+)]
+pub async fn get_rule_stats() -> Result<Json, Infallible> {
+    Ok(api_ok(rule_limit_service::stats(chrono::Local::now().timestamp())))
+}
+
+/// 查询规则变量
+///
+/// 规则通过 set、incr 动作写入的变量和计数器，按名称排列。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/rules/vars",
+    responses(
+        (status = 200, body = ApiResponseRuleVars, description = "全部规则变量")
+    )
+)]
+pub async fn list_rule_vars() -> Result<Json, Infallible> {
+    Ok(api_ok(GLOBAL.get().unwrap().rule_var_service.lock().unwrap().list()))
+}
+
+/// 删除规则变量，计数器删除后从 0 重新开始
+#[utoipa::path(
+    delete,
+    tag = "WCF",
+    path = "/rules/vars/{name}",
+    params(
+        ("name" = String, Path, description = "变量名")
+    ),
+    responses(
+        (status = 200, body = ApiResponseBool, description = "变量是否存在")
+    )
+)]
+pub async fn delete_rule_var(name: String) -> Result<Json, Infallible> {
+    let name = urlencoding::decode(&name).map(|n| n.into_owned()).unwrap_or(name);
+    match GLOBAL.get().unwrap().rule_var_service.lock().unwrap().remove(&name) {
+        Ok(removed) => Ok(api_ok(removed)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+fn set_rule_group(name: String, enabled: bool) -> Json {
+    let name = urlencoding::decode(&name).map(|n| n.into_owned()).unwrap_or(name);
+    let result = GLOBAL.get().unwrap().rule_group_service.lock().unwrap().set_enabled(&name, enabled);
+    match result {
+        Ok(()) => {
+            log::info!("规则分组 {} 已{}", name, if enabled { "启用" } else { "停用" });
+            api_ok(rule_service::groups())
         }
+        Err(e) => api_error(e),
+    }
+}
+
+/// 启用规则分组
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/rules/groups/{name}/enable",
+    params(
+        ("name" = String, Path, description = "分组名称")
+    ),
+    responses(
+        (status = 200, body = ApiResponseRuleGroups, description = "启用后的分组")
+    )
+)]
+pub async fn enable_rule_group(name: String) -> Result<Json, Infallible> {
+    Ok(set_rule_group(name, true))
+}
+
+/// 停用规则分组
+///
+/// 分组中的规则不再匹配，重启后仍然停用。配置中还没有的分组也可以先停用。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/rules/groups/{name}/disable",
+    params(
+        ("name" = String, Path, description = "分组名称")
+    ),
+    responses(
+        (status = 200, body = ApiResponseRuleGroups, description = "停用后的分组")
+    )
+)]
+pub async fn disable_rule_group(name: String) -> Result<Json, Infallible> {
+    Ok(set_rule_group(name, false))
+}
+
+/// 清理本地消息库
+///
+/// 按会话或时间范围删除已存储的消息，至少指定一个条件；media 为 true 时一并删除这些消息引用的、位于 file_dir 下的媒体文件。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/purge",
+    request_body = PurgeRequest,
+    responses(
+        (status = 200, body = ApiResponsePurge, description = "删除的消息和文件数")
+    )
+)]
+pub async fn purge_messages(request: PurgeRequest) -> Result<Json, Infallible> {
+    let filter = match (parse_time_param(&request.from), parse_time_param(&request.to)) {
+        (Ok(from), Ok(to)) => PurgeFilter {
+            roomid: request.roomid.filter(|r| !r.is_empty()),
+            from,
+            to,
+        },
+        (Err(e), _) | (_, Err(e)) => return Ok(api_error(e)),
     };
+    let media = request.media;
+    let result = tokio::task::spawn_blocking(move || {
+        let global = GLOBAL.get().unwrap();
+        let mut store = global.message_store_service.lock().unwrap();
+        let media_files = if media { store.purge_media(&filter)? } else { 0 };
+        let messages = store.purge(&filter)?;
+        Ok::<_, String>(PurgeReport { messages, media_files })
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(report) => {
+            log::warn!("已清理 {} 条消息、{} 个媒体文件", report.messages, report.media_files);
+            Ok(api_ok(report))
+        }
+        Err(e) => Ok(api_error(e)),
+    }
+}
 
-    if !status {
-        return handle_error("下载失败".to_string());
+/// 导出联系人的本地数据
+///
+/// 包括消息库中其发出的消息和与其私聊的消息、这些消息引用的 file_dir 下的媒体文件路径，以及打卡、投票、通知确认、抽奖、指令授权、免打扰队列中的记录。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/privacy/export",
+    params(SubjectQuery),
+    responses(
+        (status = 200, body = ApiResponseSubjectData, description = "与该联系人相关的数据")
+    )
+)]
+pub async fn export_subject_data(query: SubjectQuery) -> Result<Json, Infallible> {
+    if let Err(e) = wxid::check_id(&query.wxid) {
+        return Ok(api_error(format!("wxid {}", e)));
     }
+    let result = tokio::task::spawn_blocking(move || privacy_service::export(&query.wxid))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(data) => Ok(api_ok(data)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
 
-    // 读取文件内容
-    match tokio::fs::read(&params.extra).await {
-        Ok(content) => {
-            // 获取文件扩展名
-            let extension = std::path::Path::new(&params.extra)
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("");
+/// 删除联系人的本地数据
+///
+/// 范围与导出一致，媒体文件一并删除；抽奖审计文件中的 wxid 也会被去掉。操作不可恢复。
+#[utoipa::path(
+    delete,
+    tag = "WCF",
+    path = "/privacy/data",
+    params(SubjectQuery),
+    responses(
+        (status = 200, body = ApiResponseErasure, description = "各项删除的数量")
+    )
+)]
+pub async fn erase_subject_data(query: SubjectQuery) -> Result<Json, Infallible> {
+    if let Err(e) = wxid::check_id(&query.wxid) {
+        return Ok(api_error(format!("wxid {}", e)));
+    }
+    let wxid = query.wxid.clone();
+    let result = tokio::task::spawn_blocking(move || privacy_service::erase(&wxid))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(report) => {
+            log::warn!("已删除联系人 {} 的本地数据: {} 条消息、{} 个媒体文件", query.wxid, report.messages, report.media_files);
+            Ok(api_ok(report))
+        }
+        Err(e) => Ok(api_error(e)),
+    }
+}
 
-            // 根据文件扩展名确定 Content-Type
-            let content_type = match extension.to_lowercase().as_str() {
-                "pdf" => "application/pdf",
-                "doc" | "docx" => "application/msword",
-                "xls" | "xlsx" => "application/vnd.ms-excel",
-                "ppt" | "pptx" => "application/vnd.ms-powerpoint",
-                "zip" => "application/zip",
-                "rar" => "application/x-rar-compressed",
-                "txt" => "text/plain",
-                "json" => "application/json",
-                "xml" => "application/xml",
-                "html" | "htm" => "text/html",
-                "css" => "text/css",
-                "js" => "application/javascript",
-                "mp3" => "audio/mpeg",
-                "mp4" => "video/mp4",
-                "jpg" | "jpeg" => "image/jpeg",
-                "png" => "image/png",
-                "gif" => "image/gif",
-                _ => "application/octet-stream",
+/// 立即备份
+///
+/// 打包配置、data 目录下的运行状态和消息库快照（include_media 时加上 file_dir 下的媒体文件），上传到配置的 S3 兼容存储和 WebDAV，并按 keep 清理旧备份。备份不加密：其中的密钥用 DPAPI 加密，只能在同一 Windows 账号下恢复；消息库没有开启加密时聊天记录以明文上传。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/backup",
+    responses(
+        (status = 200, body = ApiResponseBackup, description = "备份文件和每个目标的上传结果")
+    )
+)]
+pub async fn run_backup() -> Result<Json, Infallible> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().backup.clone();
+    let result = tokio::task::spawn_blocking(move || backup_service::run(&config))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(report) => Ok(api_ok(report)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 导入历史消息
+///
+/// 把其它工具导出的聊天记录写入本地消息库，需要先开启 message_store。CSV 需带表头，至少包含时间、会话和内容三列，
+/// 可识别 CreateTime、StrTalker、StrContent、IsSender、Sender 等常见列名；没有消息 id 时按内容生成，重复导入不会产生重复记录。
+/// 也可以把文件放到 data/imports 目录后用 file 指定，不在该目录中的文件会被拒绝。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/import",
+    params(ImportQuery),
+    request_body(content = String, content_type = "text/csv", description = "CSV 或 NDJSON 文本"),
+    responses(
+        (status = 200, body = ApiResponseImport, description = "解析和写入的条数")
+    )
+)]
+pub async fn import_messages(query: ImportQuery, body: warp::hyper::body::Bytes) -> Result<Json, Infallible> {
+    let text = match &query.file {
+        Some(file) => {
+            let path = match import_service::resolve_file(file) {
+                Ok(path) => path,
+                Err(e) => return Ok(api_error(e)),
             };
+            match fs::read_to_string(path).await {
+                Ok(text) => text,
+                Err(e) => return Ok(api_error(format!("读取文件失败: {}", e))),
+            }
+        }
+        None => match String::from_utf8(body.to_vec()) {
+            Ok(text) => text,
+            Err(_) => return Ok(api_error("请求体不是 UTF-8 文本")),
+        },
+    };
+    let result = tokio::task::spawn_blocking(move || import_service::import(query.format, &text))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(report) => Ok(api_ok(report)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
 
-            // 返回文件流
-            return Ok(Box::new(warp::reply::with_header(
-                content,
-                "Content-Type",
-                content_type,
-            )));
+/// 从微信 MSG 库回填历史消息
+///
+/// 在后台把 MSG0.db、MSG1.db 等分库中最近 days 天的消息写入本地消息库，立即返回任务，进度通过 /admin/jobs/{id} 查询。已存在的消息不会重复写入。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/backfill",
+    params(BackfillQuery),
+    responses(
+        (status = 200, body = ApiResponseJob, description = "回填任务")
+    )
+)]
+pub async fn backfill_messages(query: BackfillQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if !GLOBAL.get().unwrap().wechat_config.read().unwrap().message_store.enabled {
+        return Ok(api_error("未开启本地消息库"));
+    }
+    let id = match jobs::start("backfill") {
+        Ok(id) => id,
+        Err(running) => return Ok(api_error(format!("回填任务 #{} 正在进行", running))),
+    };
+    let since = match query.days {
+        0 => 0,
+        days => (chrono::Local::now() - chrono::Duration::days(days as i64)).timestamp().max(0) as u32,
+    };
+    tokio::task::spawn_blocking(move || {
+        let result = import_service::backfill(&wechat, id, since).map(|n| format!("新写入 {} 条消息", n));
+        if let Err(e) = &result {
+            log::warn!("回填历史消息失败: {}", e);
         }
-        Err(e) => return handle_error(format!("读取文件失败: {}", e)),
+        jobs::finish(id, result);
+    });
+    Ok(api_ok(jobs::get(id)))
+}
+
+/// 生成微信数据库的本地快照
+///
+/// 在后台分页读取 db 的所有数据表复制到本地，立即返回任务，进度通过 /admin/jobs/{id} 查询。已有快照会被替换。
+#[utoipa::path(
+    post,
+    tag = "WCF",
+    path = "/admin/snapshot",
+    params(SnapshotQuery),
+    responses(
+        (status = 200, body = ApiResponseJob, description = "快照任务")
+    )
+)]
+pub async fn create_snapshot(query: SnapshotQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if let Err(e) = db_snapshot_service::check_name(&query.db) {
+        return Ok(api_error(e));
+    }
+    let id = match jobs::start("snapshot") {
+        Ok(id) => id,
+        Err(running) => return Ok(api_error(format!("快照任务 #{} 正在进行", running))),
+    };
+    tokio::task::spawn_blocking(move || {
+        let result = db_snapshot_service::create(&wechat, id, &query.db).map(|s| format!("{} 快照大小 {} 字节", s.db, s.size));
+        if let Err(e) = &result {
+            log::warn!("生成 {} 快照失败: {}", query.db, e);
+        }
+        jobs::finish(id, result);
+    });
+    Ok(api_ok(jobs::get(id)))
+}
+
+/// 已生成的数据库快照
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/snapshots",
+    responses(
+        (status = 200, body = ApiResponseSnapshots, description = "快照列表")
+    )
+)]
+pub async fn list_snapshots() -> Result<Json, Infallible> {
+    Ok(api_ok(db_snapshot_service::list()))
+}
+
+/// 群成员活跃时段热力图
+///
+/// 按本地消息库统计时间范围内每个成员在 0 到 23 点各小时的发言数，需开启 message_store。登录状态下会附上群昵称。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/stats/rooms/{roomid}/heatmap",
+    params(
+        ("roomid" = String, Path, description = "群ID"),
+        HeatmapQuery
+    ),
+    responses(
+        (status = 200, body = ApiResponseHeatmap, description = "成员 × 小时的消息数")
+    )
+)]
+pub async fn get_room_heatmap(roomid: String, query: HeatmapQuery, wechat: Arc<Mutex<WeChat>>) -> Result<Json, Infallible> {
+    if !GLOBAL.get().unwrap().wechat_config.read().unwrap().message_store.enabled {
+        return Ok(api_error("未开启本地消息库"));
+    }
+    let roomid = urlencoding::decode(&roomid).map(|r| r.into_owned()).unwrap_or(roomid);
+    let (from, to) = match (parse_time_param(&query.from), parse_time_param(&query.to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return Ok(api_error(e)),
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let wechat = wechat.lock().unwrap();
+        let names: HashMap<String, String> = if wechat.is_login_cached() {
+            wechat
+                .query_room_member(roomid.clone())
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|m| (m.wxid, m.name))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        drop(wechat);
+        stats_service::heatmap(&roomid, from, to, &names)
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(heatmap) => Ok(api_ok(heatmap)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 群聊词云数据
+///
+/// 对本地消息库中的文字消息分词，去掉链接、@ 提及、表情代码、单字和常见虚词后按出现次数排序，需开启 message_store。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/stats/rooms/{roomid}/wordcloud",
+    params(
+        ("roomid" = String, Path, description = "群ID"),
+        WordCloudQuery
+    ),
+    responses(
+        (status = 200, body = ApiResponseWordCloud, description = "高频词及次数")
+    )
+)]
+pub async fn get_word_cloud(roomid: String, query: WordCloudQuery) -> Result<Json, Infallible> {
+    if !GLOBAL.get().unwrap().wechat_config.read().unwrap().message_store.enabled {
+        return Ok(api_error("未开启本地消息库"));
+    }
+    let roomid = urlencoding::decode(&roomid).map(|r| r.into_owned()).unwrap_or(roomid);
+    let limit = query.limit.unwrap_or(WORD_CLOUD_LIMIT);
+    let result = tokio::task::spawn_blocking(move || stats_service::word_cloud(&roomid, query.period, limit))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(cloud) => Ok(api_ok(cloud)),
+        Err(e) => Ok(api_error(e)),
+    }
+}
+
+/// 群聊词云图片
+///
+/// 统计方式同 /stats/rooms/{roomid}/wordcloud，渲染为 800×600 的 PNG。字体由 word_cloud.font 配置，默认使用系统的微软雅黑。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/stats/rooms/{roomid}/wordcloud.png",
+    params(
+        ("roomid" = String, Path, description = "群ID"),
+        WordCloudQuery
+    ),
+    responses(
+        (status = 200, content_type = "image/png", description = "词云图片"),
+        (status = 500, description = "未开启消息库、没有可绘制的词或找不到字体")
+    )
+)]
+pub async fn get_word_cloud_image(roomid: String, query: WordCloudQuery) -> Result<Box<dyn Reply>, Infallible> {
+    if !GLOBAL.get().unwrap().wechat_config.read().unwrap().message_store.enabled {
+        return Ok(Box::new(warp::reply::with_status(
+            "未开启本地消息库".to_string(),
+            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+    let roomid = urlencoding::decode(&roomid).map(|r| r.into_owned()).unwrap_or(roomid);
+    let limit = query.limit.unwrap_or(WORD_CLOUD_LIMIT);
+    let result = tokio::task::spawn_blocking(move || word_cloud_service::render_room(&roomid, query.period, limit))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(png) => Ok(Box::new(warp::reply::with_header(png, "Content-Type", "image/png"))),
+        Err(e) => Ok(Box::new(warp::reply::with_status(e, warp::http::StatusCode::INTERNAL_SERVER_ERROR))),
+    }
+}
+
+/// 后台任务列表，最新的在前
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/jobs",
+    responses(
+        (status = 200, body = ApiResponseJobs, description = "任务列表")
+    )
+)]
+pub async fn list_jobs() -> Result<Json, Infallible> {
+    Ok(api_ok(jobs::list()))
+}
+
+/// 查询后台任务进度
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/jobs/{id}",
+    params(
+        ("id" = u64, Path, description = "任务编号")
+    ),
+    responses(
+        (status = 200, body = ApiResponseJob, description = "任务状态和进度")
+    )
+)]
+pub async fn get_job(id: u64) -> Result<Json, Infallible> {
+    match jobs::get(id) {
+        Some(job) => Ok(api_ok(job)),
+        None => Ok(api_error("任务不存在")),
     }
 }
+
+/// OpenMetrics 格式的运行指标
+///
+/// 包含按方法统计的 wcferry RPC 耗时直方图和失败次数，可直接由 Prometheus 抓取。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/metrics",
+    responses(
+        (status = 200, body = String, content_type = "application/openmetrics-text", description = "OpenMetrics 文本")
+    )
+)]
+pub async fn get_metrics() -> Result<impl Reply, Infallible> {
+    Ok(warp::reply::with_header(
+        metrics::render(),
+        "content-type",
+        "application/openmetrics-text; version=1.0.0; charset=utf-8",
+    ))
+}
+
+/// 消息管道各阶段的实时统计
+///
+/// 包括接收速率、解码失败、接收队列和事件总线积压、每个处理器的丢弃数以及每个推送目标的成功失败次数，供界面的监控面板使用。
+#[utoipa::path(
+    get,
+    tag = "WCF",
+    path = "/admin/pipeline",
+    responses(
+        (status = 200, body = ApiResponsePipeline, description = "各阶段统计")
+    )
+)]
+pub async fn get_pipeline() -> Result<Json, Infallible> {
+    let global = GLOBAL.get().unwrap();
+    let bus_depth = global.msg_event_bus.lock().unwrap().broadcaster.lock().unwrap().len();
+    let quiet_queue = global.quiet_hours_service.lock().unwrap().queued().len();
+    Ok(api_ok(pipeline::snapshot(bus_depth, quiet_queue)))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,2411 @@
+use std::path::PathBuf;
+
+use serde_json::json;
+use warp::http::StatusCode;
+
+use crate::handler::{
+    event_entity::ContactChangeKind,
+    message::{http_message_handler, payload},
+};
+use crate::service::{
+    contact_monitor_service, db_poll_service, dnd_service, file_intake_service, global_service::GLOBAL, media_service,
+    pat_service, pause_service::PauseService, pipe_service, quiet_hours_service, receipt_service, rule_service,
+};
+use crate::test_support::{ensure_global, override_config, TestApp};
+use crate::utils::{
+    dedup,
+    secret::Secret,
+    system_event::{self, SystemEvent},
+};
+use crate::wechat_config::{
+    BackupTarget, CheckinConfig, CompareOp, FileIntakeRule, HttpAction, OfficialMode, QuietHoursConfig, RuleAction, RuleConfig,
+    RuleScriptConfig, RuleWindow, ScriptAction, VarCondition, WebhookHook,
+};
+use crate::wcferry::{
+    mock::{ECHO_WXID, ROOM_ID, SELF_WXID},
+    wcf::{request::Msg as ReqMsg, Functions, TextMsg, WxMsg},
+};
+
+// 群或私聊里 wxid_mock_alice 发来的一条文本消息，其它字段用 ..text_msg() 覆盖
+fn text_msg(roomid: &str, content: &str) -> WxMsg {
+    WxMsg {
+        is_group: roomid.ends_with("@chatroom"),
+        id: 1,
+        r#type: 1,
+        roomid: roomid.to_string(),
+        content: content.to_string(),
+        sender: "wxid_mock_alice".to_string(),
+        ..Default::default()
+    }
+}
+
+// 在临时目录下写一个测试文件
+fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir().join("wcf-test").join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn text(receiver: &str, msg: &str) -> serde_json::Value {
+    json!({ "msg": msg, "receiver": receiver, "aters": "" })
+}
+
+fn path_msg(path: &str) -> serde_json::Value {
+    json!({ "path": path, "receiver": "wxid_mock_alice", "base64": "" })
+}
+
+fn member_mgmt(roomid: &str, wxids: &str) -> serde_json::Value {
+    json!({ "roomid": roomid, "wxids": wxids })
+}
+
+#[tokio::test]
+async fn docs() {
+    let app = TestApp::new();
+    let doc = app.get("/api-doc.json").await.envelope();
+    assert!(doc["paths"]["/text"].is_object());
+    let schema = app.get("/schemas/message.json").await.envelope();
+    assert!(schema.is_object());
+}
+
+#[tokio::test]
+async fn login_state() {
+    let app = TestApp::new();
+    assert_eq!(app.get("/qrcode").await.ok(), "");
+    assert_eq!(app.get("/islogin").await.ok(), true);
+    assert_eq!(app.get("/selfwxid").await.ok(), SELF_WXID);
+    assert_eq!(app.get("/userinfo").await.ok()["wxid"], SELF_WXID);
+}
+
+#[tokio::test]
+async fn not_logged_in() {
+    let app = TestApp::new();
+    app.sim.set_login(false);
+    let rsp = app.post("/text", text(ECHO_WXID, "hi")).await;
+    rsp.expect_status(StatusCode::CONFLICT);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["code"], "NOT_LOGGED_IN");
+    assert!(app.sim.outbox().iter().all(|r| r.func != Functions::FuncSendTxt as i32));
+
+    // 登录相关接口不受影响
+    assert_eq!(app.get("/islogin").await.ok(), false);
+    assert_eq!(app.get("/qrcode").await.ok(), "");
+}
+
+#[tokio::test]
+async fn health() {
+    let app = TestApp::new();
+    let health = app.get("/health").await.ok();
+    assert_eq!(health["logged_in"], true);
+    assert!(health["self_heals"].is_u64());
+
+
+    // 未登录时仍可访问
+    let app = TestApp::new();
+    app.sim.set_login(false);
+    let health = app.get("/health").await.ok();
+    assert_eq!(health["logged_in"], false);
+    assert_eq!(health["ok"], false);
+}
+
+#[tokio::test]
+async fn contacts() {
+    let app = TestApp::new();
+    let all = app.get("/contacts").await.ok();
+    assert_eq!(all["total"], 5);
+
+    let rooms = app.get("/contacts?type=room&fields=wxid").await.ok();
+    assert_eq!(rooms["total"], 1);
+    assert_eq!(rooms["contacts"][0], json!({ "wxid": ROOM_ID }));
+
+    let page = app.get("/contacts?page=2&size=2").await.ok();
+    assert_eq!(page["total"], 5);
+    assert_eq!(page["contacts"].as_array().unwrap().len(), 2);
+
+    app.get("/contacts?type=unknown").await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn friends_and_chatrooms() {
+    let app = TestApp::new();
+    app.sim.add_contact("wxid_mock_carol", "Carol");
+    app.sim.add_room("20000000002@chatroom", &[SELF_WXID, "wxid_mock_carol"]);
+
+    let friends = app.get("/friends").await.ok();
+    let friends = friends.as_array().unwrap();
+    assert!(friends.iter().any(|c| c["wxid"] == "wxid_mock_carol"));
+    assert!(friends.iter().all(|c| c["wxid"] != ROOM_ID));
+
+    let rooms = app.get("/chatrooms").await.ok();
+    assert_eq!(rooms.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn databases() {
+    let app = TestApp::new();
+    assert!(app.get("/dbs").await.ok()["names"].as_array().unwrap().len() > 0);
+    assert!(app.get("/tables/MicroMsg.db").await.ok()["tables"].is_array());
+    assert_eq!(app.get("/msg-types").await.ok()["types"]["1"], "文字");
+
+    let rows = app
+        .post("/sql", json!({ "db": "MicroMsg.db", "sql": "SELECT UserName, NickName FROM Contact" }))
+        .await
+        .ok();
+    assert_eq!(rows.as_array().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn muted_contacts() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    app.sim.add_room(&roomid, &[SELF_WXID, "wxid_mock_alice"]);
+    app.sim.set_muted(&roomid, true);
+    app.sim.set_muted("wxid_mock_alice", true);
+    let muted = app.get("/muted-contacts").await.ok();
+    assert!(muted.as_array().unwrap().contains(&json!(roomid)));
+    assert!(muted.as_array().unwrap().contains(&json!("wxid_mock_alice")));
+
+    let msg = text_msg(&roomid, "hi");
+    assert!(dnd_service::suppressed("http", &msg));
+    // 只有配置的下游遵循免打扰，配置的 unmute 优先于微信里的设置
+    assert!(!dnd_service::suppressed("pipe", &msg));
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().dnd.unmute.push(roomid.clone());
+    assert!(!dnd_service::suppressed("http", &msg));
+    assert!(!app.get("/muted-contacts").await.ok().as_array().unwrap().contains(&json!(roomid)));
+}
+
+#[tokio::test]
+async fn official_filter() {
+    let _app = TestApp::new();
+    let msg = |sender: &str| WxMsg {
+        id: 1,
+        r#type: 49,
+        roomid: sender.to_string(),
+        sender: sender.to_string(),
+        ..Default::default()
+    };
+    let human = http_message_handler::callback_urls(&msg("wxid_mock_alice"));
+    let mode = override_config(|c| &mut c.official.mode, OfficialMode::Route);
+    let _cburl = override_config(|c| &mut c.official.cburl, vec!["http://127.0.0.1:1/official".to_string()]);
+    assert_eq!(http_message_handler::callback_urls(&msg("gh_0123456789ab")), vec!["http://127.0.0.1:1/official"]);
+    assert_eq!(http_message_handler::callback_urls(&msg("notifymessage")).len(), 1);
+    assert_eq!(http_message_handler::callback_urls(&msg("wxid_mock_alice")), human);
+
+    drop(mode);
+    let _mode = override_config(|c| &mut c.official.mode, OfficialMode::Drop);
+    assert!(http_message_handler::callback_urls(&msg("gh_0123456789ab")).is_empty());
+    assert_eq!(http_message_handler::callback_urls(&msg("wxid_mock_alice")), human);
+}
+
+#[tokio::test]
+async fn system_events() {
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let msg = |r#type: u32, content: &str| WxMsg {
+        is_group: true,
+        id: 9,
+        r#type,
+        roomid: roomid.clone(),
+        content: content.to_string(),
+        sender: "wxid_mock_alice".to_string(),
+        ..Default::default()
+    };
+    let mut rx = system_event::subscribe();
+    assert!(system_event::publish(&msg(1, "加入了群聊")).is_none());
+    let join = system_event::publish(&msg(10000, "\"张三\"邀请\"李四、王五\"加入了群聊")).unwrap();
+    let expected = SystemEvent::Join {
+        inviter: Some("张三".to_string()),
+        members: vec!["李四".to_string(), "王五".to_string()],
+    };
+    assert_eq!(join.event, expected);
+    let revoke = "<sysmsg type=\"revokemsg\"><revokemsg><newmsgid>123</newmsgid><replacemsg><![CDATA[\"李四\" 撤回了一条消息]]></replacemsg></revokemsg></sysmsg>";
+    let revoke = system_event::classify(&msg(10002, revoke)).unwrap();
+    assert_eq!(revoke, SystemEvent::Revoke { msg_id: Some(123), text: "\"李四\" 撤回了一条消息".to_string() });
+    let pat = "<sysmsg type=\"pat\"><pat><fromusername>wxid_mock_alice</fromusername><pattedusername>wxid_mock_bob</pattedusername></pat></sysmsg>";
+    assert_eq!(system_event::classify(&msg(10002, pat)).unwrap().kind(), "tickle");
+    assert_eq!(system_event::classify(&msg(10000, "收到红包，请在手机上查看")).unwrap().kind(), "red_packet");
+    assert!(system_event::classify(&msg(10000, "你领取了张三的红包")).is_none());
+    assert!(system_event::classify(&msg(10000, "张三领取了你的红包，你的红包已被领完")).is_none());
+    let transfer = "<msg><appmsg><title>微信转账</title><type>2000</type><wcpayinfo><paysubtype>1</paysubtype><feedesc><![CDATA[￥0.01]]></feedesc></wcpayinfo></appmsg></msg>";
+    match system_event::classify(&msg(49, transfer)).unwrap() {
+        SystemEvent::Transfer { amount, received, .. } => {
+            assert_eq!(amount.as_deref(), Some("￥0.01"));
+            assert!(!received);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    // 订阅者只收到解析出的系统事件，其他测试也可能推送，按群过滤
+    loop {
+        let event = rx.recv().await.unwrap();
+        if event.roomid == roomid {
+            assert_eq!(event.event, expected);
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn pat_back() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let pat = |from: &str, to: &str| WxMsg {
+        is_group: true,
+        id: 10,
+        r#type: 10002,
+        roomid: roomid.clone(),
+        content: format!("<sysmsg type=\"pat\"><pat><fromusername>{}</fromusername><pattedusername>{}</pattedusername></pat></sysmsg>", from, to),
+        sender: from.to_string(),
+        ..Default::default()
+    };
+    let _pat_back = override_config(|c| &mut c.pat.pat_back, true);
+    let _reply = override_config(|c| &mut c.pat.reply, "别拍了 {from}".to_string());
+    let _rooms = override_config(|c| &mut c.pat.rooms, vec![roomid.clone()]);
+    assert!(!pat_service::handle(&app.wechat, &pat("wxid_mock_alice", "wxid_mock_bob")).unwrap());
+    assert!(pat_service::handle(&app.wechat, &pat("wxid_mock_alice", SELF_WXID)).unwrap());
+    // 冷却时间内再拍不再响应
+    assert!(!pat_service::handle(&app.wechat, &pat("wxid_mock_alice", SELF_WXID)).unwrap());
+
+    let outbox = app.sim.outbox();
+    let patted: Vec<_> = outbox
+        .iter()
+        .filter_map(|r| match &r.msg {
+            Some(ReqMsg::Pm(pm)) if pm.roomid == roomid => Some(pm.wxid.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(patted, vec!["wxid_mock_alice"]);
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::Txt(msg)) if msg.receiver == roomid && msg.msg == "别拍了 wxid_mock_alice")));
+}
+
+#[tokio::test]
+async fn refresh_pyq() {
+    let app = TestApp::new();
+    assert_eq!(app.get("/pyq?id=0").await.ok(), true);
+    app.get("/pyq").await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn send_text() {
+    let app = TestApp::new();
+    assert_eq!(app.post("/text", text(ECHO_WXID, "ping")).await.ok()["sent"], true);
+    let outbox = app.sim.outbox();
+    let sent = outbox.iter().find(|r| r.func == Functions::FuncSendTxt as i32).unwrap();
+    match &sent.msg {
+        Some(ReqMsg::Txt(msg)) => assert_eq!(msg.msg, "ping"),
+        other => panic!("unexpected request: {:?}", other),
+    }
+
+    app.post("/text", json!({ "receiver": 1 })).await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn send_text_dry_run() {
+    let app = TestApp::new();
+    let data = app.post("/text?dry_run=true", text(ECHO_WXID, "ping")).await.ok();
+    assert_eq!(data["dry_run"], true);
+    assert_eq!(data["params"]["msg"], "ping");
+    assert!(app.sim.outbox().iter().all(|r| r.func != Functions::FuncSendTxt as i32));
+
+    // 必填字段在进入接口前就被拦下
+    let rsp = app.post("/text?dry_run=true", text("", "ping")).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert!(body["error"].as_str().unwrap().contains("receiver"));
+}
+
+#[tokio::test]
+async fn send_text_mentions() {
+    let app = TestApp::new();
+    let body = json!({ "msg": "开会了", "receiver": ROOM_ID, "aters": "", "mentions": ["wxid_mock_alice", "@bob", "Alice"] });
+    app.post("/text", body).await.ok();
+    let outbox = app.sim.outbox();
+    let sent = outbox.iter().rev().find_map(|r| match &r.msg {
+        Some(ReqMsg::Txt(msg)) if msg.receiver == ROOM_ID => Some(msg.clone()),
+        _ => None,
+    });
+    let sent = sent.unwrap();
+    assert_eq!(sent.aters, "wxid_mock_alice,wxid_mock_bob");
+    assert_eq!(sent.msg, "@Alice\u{2005}@Bob\u{2005}开会了");
+
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    app.sim.add_room(&roomid, &[SELF_WXID, "wxid_mock_alice"]);
+    let body = json!({ "msg": "开会了", "receiver": roomid, "aters": "", "mentions": ["all"] });
+    let data = app.post("/text?dry_run=true", body.clone()).await.ok();
+    assert_eq!(data["params"]["aters"], "notify@all");
+    assert_eq!(data["params"]["msg"], "@所有人 开会了");
+    app.post("/text", body).await.ok();
+
+    let body = json!({ "msg": "hi", "receiver": ROOM_ID, "aters": "", "mentions": ["Bobb"] });
+    assert!(app.post("/text", body).await.err().contains("是否是 Bob"));
+    let body = json!({ "msg": "hi", "receiver": ECHO_WXID, "aters": "", "mentions": ["Bob"] });
+    app.post("/text", body).await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn send_image_and_file() {
+    let app = TestApp::new();
+    let file = temp_file("a.png", b"png");
+    let file = file.to_string_lossy();
+    assert_eq!(app.post("/image", path_msg(&file)).await.ok()["sent"], true);
+    assert_eq!(app.post("/file", path_msg(&file)).await.ok()["sent"], true);
+
+    assert_eq!(app.post("/image?dry_run=true", path_msg(&file)).await.ok()["dry_run"], true);
+    let error = app.post("/file?dry_run=true", path_msg("C:/not/exists.txt")).await.err();
+    assert!(error.contains("文件不存在"));
+}
+
+#[tokio::test]
+async fn send_file_base64() {
+    let app = TestApp::new();
+    let body = json!({ "path": "", "receiver": "wxid_mock_alice", "base64": base64::encode("周报"), "filename": "../周报.txt" });
+    assert_eq!(app.post("/file?dry_run=true", body.clone()).await.ok()["dry_run"], true);
+    let missing = json!({ "path": "", "receiver": "wxid_mock_alice", "base64": base64::encode("周报") });
+    assert!(app.post("/file?dry_run=true", missing.clone()).await.err().contains("filename"));
+    assert!(app.post("/file", missing).await.err().contains("filename"));
+
+    assert_eq!(app.post("/file", body).await.ok()["sent"], true);
+    let outbox = app.sim.outbox();
+    let sent = outbox
+        .iter()
+        .find_map(|r| match &r.msg {
+            Some(ReqMsg::File(f)) if f.path.ends_with("/周报.txt") => Some(f.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert!(sent.base64.is_empty());
+    let path = PathBuf::from(&sent.path);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "周报");
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn send_video() {
+    let app = TestApp::new();
+    let video = temp_file("a.mp4", b"mp4");
+    let video = video.to_string_lossy();
+    assert_eq!(app.post("/video", path_msg(&video)).await.ok()["sent"], true);
+    let outbox = app.sim.outbox();
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::File(f)) if f.path == video)));
+
+    let file = temp_file("a.mov", b"mov");
+    assert!(app.post("/video", path_msg(&file.to_string_lossy())).await.err().contains("mp4"));
+    assert!(app.post("/video?dry_run=true", path_msg("C:/not/exists.mp4")).await.err().contains("文件不存在"));
+
+    use warp::Filter;
+    let route = warp::path("page").map(|| warp::reply::html("<html></html>"));
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    let url = format!("http://{}/page", addr);
+    assert!(app.post("/video", path_msg(&url)).await.err().contains("不是 mp4 视频"));
+}
+
+#[tokio::test]
+async fn send_emoji() {
+    let app = TestApp::new();
+    let gif = temp_file("doge.gif", b"GIF89a");
+    let gif = gif.to_string_lossy();
+    let body = json!({ "path": gif, "receiver": "wxid_mock_alice" });
+    assert_eq!(app.post("/emoji", body).await.ok()["sent"], true);
+    let sent = |path: &str| {
+        app.sim.outbox().iter().any(|r| {
+            r.func == Functions::FuncSendEmotion as i32 && matches!(&r.msg, Some(ReqMsg::File(f)) if f.path == path)
+        })
+    };
+    assert!(sent(&gif));
+
+    // 收到过的表情按 md5 从缓存中发送
+    let md5 = uuid::Uuid::new_v4().simple().to_string();
+    let cached = media_service::emotion_dir().join(format!("{}.gif", md5));
+    std::fs::create_dir_all(cached.parent().unwrap()).unwrap();
+    std::fs::write(&cached, b"GIF89a").unwrap();
+    assert_eq!(app.post("/emoji", json!({ "md5": md5, "receiver": "wxid_mock_alice" })).await.ok()["sent"], true);
+    assert!(sent(&cached.to_string_lossy()));
+
+    let missing = json!({ "md5": "0123456789abcdef0123456789abcdef", "receiver": "wxid_mock_alice" });
+    assert!(app.post("/emoji", missing).await.err().contains("未缓存"));
+    let png = temp_file("a.png", b"png");
+    let body = json!({ "path": png.to_string_lossy(), "receiver": "wxid_mock_alice" });
+    assert!(app.post("/emoji?dry_run=true", body).await.err().contains("gif"));
+}
+
+#[tokio::test]
+async fn send_xml() {
+    let app = TestApp::new();
+    let xml = "<msg><appmsg><title>晴天</title><type>3</type><url>https://example.com/song</url></appmsg></msg>";
+    let body = json!({ "receiver": "wxid_mock_alice", "content": xml, "path": "", "type": 21 });
+    assert!(app.post("/xml?verify=true", body).await.ok()["msg_id"].is_u64());
+    let outbox = app.sim.outbox();
+    let sent = outbox.iter().find(|r| r.func == Functions::FuncSendXml as i32).unwrap();
+    match &sent.msg {
+        Some(ReqMsg::Xml(msg)) => assert_eq!((msg.content.as_str(), msg.r#type), (xml, 21)),
+        other => panic!("unexpected request: {:?}", other),
+    }
+
+    let body = json!({ "receiver": "wxid_mock_alice", "content": "<msg><appmsg></msg>", "path": "", "type": 0 });
+    let rsp = app.post("/xml", body).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["data"][0]["field"], "content");
+    assert_eq!(body["data"][1]["field"], "type");
+}
+
+#[tokio::test]
+async fn send_contact_card() {
+    let app = TestApp::new();
+    app.sim.add_contact("wxid_card_carol", "Carol & <Co>");
+    let body = json!({ "wxid": "wxid_card_carol", "receiver": "wxid_mock_alice" });
+    assert!(app.post("/contact-card?verify=true", body).await.ok()["msg_id"].is_u64());
+    let outbox = app.sim.outbox();
+    let card = outbox.iter().find_map(|r| match &r.msg {
+        Some(ReqMsg::Xml(msg)) if r.func == Functions::FuncSendXml as i32 => Some(msg.clone()),
+        _ => None,
+    });
+    let card = card.unwrap();
+    assert_eq!((card.receiver.as_str(), card.r#type), ("wxid_mock_alice", 42));
+    assert!(card.content.contains(r#"username="wxid_card_carol""#));
+    assert!(card.content.contains(r#"nickname="Carol &amp; &lt;Co&gt;""#));
+
+    let body = json!({ "wxid": "wxid_card_nobody", "receiver": "wxid_mock_alice" });
+    assert!(app.post("/contact-card", body).await.err().contains("不在通讯录中"));
+}
+
+#[tokio::test]
+async fn send_location() {
+    let app = TestApp::new();
+    let body = json!({
+        "lat": 31.2304, "lng": 121.4737, "label": "人民广场", "address": "黄浦区\"人民大道\"", "receiver": ROOM_ID
+    });
+    assert!(app.post("/location?verify=true", body).await.ok()["msg_id"].is_u64());
+    let outbox = app.sim.outbox();
+    let sent = outbox.iter().find_map(|r| match &r.msg {
+        Some(ReqMsg::Xml(msg)) if r.func == Functions::FuncSendXml as i32 => Some(msg.clone()),
+        _ => None,
+    });
+    let sent = sent.unwrap();
+    assert_eq!(sent.r#type, 48);
+    assert!(sent.content.contains(r#"x="31.2304" y="121.4737""#));
+    assert!(sent.content.contains(r#"label="黄浦区&quot;人民大道&quot;" "#));
+
+    let rsp = app.post("/location", json!({ "lat": 91.0, "lng": 0.0, "label": "", "receiver": ROOM_ID })).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["data"][0]["field"], "label");
+    assert_eq!(body["data"][1]["field"], "lat");
+}
+
+#[tokio::test]
+async fn send_markdown() {
+    let app = TestApp::new();
+    let body = json!({ "markdown": "| 名称 | 数量 |\n| --- | --- |\n| 苹果 | 3 |", "receiver": ROOM_ID });
+    let data = app.post("/markdown?dry_run=true", body.clone()).await.ok();
+    assert_eq!(data["dry_run"], true);
+    assert_eq!(data["params"]["markdown"], body["markdown"]);
+
+    let rsp = app.post("/markdown", json!({ "markdown": " ", "receiver": ROOM_ID, "width": 50 })).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let errors: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(errors["data"][0]["field"], "markdown");
+    assert_eq!(errors["data"][1]["field"], "width");
+
+    // 测试环境没有中文字体，只检查出错时的提示
+    let _font = override_config(|c| &mut c.markdown.font, "not-exists.ttf".to_string());
+    assert!(app.post("/markdown", body).await.err().contains("not-exists.ttf"));
+}
+
+#[tokio::test]
+async fn message_templates() {
+    let app = TestApp::new();
+    let name = format!("welcome-{}", uuid::Uuid::new_v4().simple());
+    let body = json!({ "name": name, "text": "欢迎 {{ who }} 加入{{team}}，{{who}} 请先看群公告 {{}}" });
+    let template = app.post("/templates", body).await.ok();
+    assert_eq!(template["vars"], json!(["who", "team"]));
+    assert!(app.get("/templates").await.ok().as_array().unwrap().iter().any(|t| t["name"] == name.as_str()));
+
+    let body = json!({ "name": name, "receiver": "wxid_mock_alice", "vars": { "who": "张三", "team": 3 } });
+    assert!(app.post("/send-template?verify=true", body).await.ok()["msg_id"].is_u64());
+    let outbox = app.sim.outbox();
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::Txt(msg)) if msg.msg == "欢迎 张三 加入3，张三 请先看群公告 {{}}")));
+
+    let rsp = app.post("/send-template", json!({ "name": name, "receiver": "wxid_mock_alice", "vars": { "who": "张三" } })).await;
+    assert!(rsp.err().contains("缺少变量 team"));
+
+    let card = format!("card-{}", uuid::Uuid::new_v4().simple());
+    let body = json!({ "name": card, "rich_text": { "title": "{{title}}", "url": "https://example.com/{{id}}", "digest": "周报" } });
+    assert_eq!(app.post("/templates", body).await.ok()["vars"], json!(["title", "id"]));
+    let body = json!({ "name": card, "receiver": ROOM_ID, "vars": { "title": "第 3 周", "id": 42 } });
+    assert!(app.post("/send-template?verify=true", body).await.ok()["msg_id"].is_u64());
+    let outbox = app.sim.outbox();
+    let sent = outbox.iter().find_map(|r| match &r.msg {
+        Some(ReqMsg::Rt(rt)) if rt.title == "第 3 周" => Some(rt.clone()),
+        _ => None,
+    });
+    assert_eq!(sent.unwrap().url, "https://example.com/42");
+
+    let rsp = app.send(warp::test::request().method("DELETE").path(&format!("/templates/{}", card))).await;
+    assert_eq!(rsp.ok(), true);
+    let rsp = app.post("/send-template", json!({ "name": card, "receiver": ROOM_ID })).await;
+    assert!(rsp.err().contains("不存在"));
+}
+
+#[tokio::test]
+async fn send_rich_text_pat_forward() {
+    let app = TestApp::new();
+    let rich = json!({
+        "name": "", "account": "", "title": "标题", "digest": "摘要",
+        "url": "https://example.com", "thumburl": "", "receiver": "wxid_mock_alice"
+    });
+    assert!(app.post("/rich-text?verify=true", rich).await.ok()["msg_id"].is_u64());
+    assert_eq!(
+        app.post("/pat", json!({ "roomid": ROOM_ID, "wxid": "wxid_mock_alice" })).await.ok(),
+        true
+    );
+    assert_eq!(
+        app.post("/forward-msg", json!({ "id": 1, "receiver": "wxid_mock_alice" })).await.ok()["sent"],
+        true
+    );
+
+    let rsp = app
+        .post("/pat?dry_run=true", json!({ "roomid": "wxid_mock_alice", "wxid": "wxid_mock_bob" }))
+        .await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert!(body["error"].as_str().unwrap().contains("roomid"));
+}
+
+#[tokio::test]
+async fn save_media() {
+    let app = TestApp::new();
+    // 模拟环境不支持语音
+    assert!(!app.post("/audio", json!({ "id": 1, "dir": "C:/" })).await.err().is_empty());
+
+    let missing = std::env::temp_dir().join("wcf-test").join("missing.dat");
+    let error = app
+        .post(
+            "/save-image",
+            json!({ "id": 1, "extra": missing.to_string_lossy(), "dir": "C:/", "timeout": 1 }),
+        )
+        .await
+        .err();
+    assert!(error.contains("超时"));
+
+    assert_eq!(
+        app.post("/save-file", json!({ "id": 1, "extra": "", "thumb": "" })).await.ok(),
+        "ok"
+    );
+}
+
+#[tokio::test]
+async fn receive_transfer_and_revoke() {
+    let app = TestApp::new();
+    let transfer = json!({ "wxid": "wxid_mock_alice", "tfid": "1", "taid": "2" });
+    assert_eq!(app.post("/receive-transfer", transfer).await.ok(), true);
+    assert_eq!(app.post("/revoke-msg?id=1", json!({})).await.ok(), true);
+}
+
+#[tokio::test]
+async fn accept_new_friend() {
+    let app = TestApp::new();
+    let verification = json!({ "v3": "v3", "v4": "v4", "scene": 30 });
+    assert_eq!(app.post("/accept-new-friend", verification).await.ok(), true);
+}
+
+#[tokio::test]
+async fn room_members() {
+    let app = TestApp::new();
+    let members = app.get(&format!("/query-room-member?roomid={}", ROOM_ID)).await.ok();
+    assert_eq!(members.as_array().unwrap().len(), 3);
+    assert!(members[0]["inviter"].is_null());
+    assert_eq!(members[1]["inviter"], SELF_WXID);
+    assert_eq!(members[1]["avatar"], "https://mock.invalid/wxid_mock_alice.jpg");
+
+    let filtered = app
+        .get(&format!("/query-room-member?roomid={}&wxids=wxid_mock_bob", ROOM_ID))
+        .await
+        .ok();
+    assert_eq!(filtered[0]["wxid"], "wxid_mock_bob");
+
+    let empty = app.get("/query-room-member?roomid=1@chatroom").await.ok();
+    assert_eq!(empty, json!([]));
+
+    assert_eq!(
+        app.post("/delete-chatroom-member", member_mgmt(ROOM_ID, "wxid_mock_bob")).await.ok(),
+        true
+    );
+    assert_eq!(
+        app.post("/add-chatroom-member", member_mgmt(ROOM_ID, ECHO_WXID)).await.ok(),
+        true
+    );
+    assert_eq!(
+        app.post("/invite-chatroom-member", member_mgmt(ROOM_ID, "wxid_mock_bob")).await.ok(),
+        true
+    );
+    // 不是好友无法拉进群
+    assert_eq!(
+        app.post("/add-chatroom-member", member_mgmt(ROOM_ID, "wxid_stranger")).await.ok(),
+        false
+    );
+
+    let rsp = app
+        .post("/invite-chatroom-member?dry_run=true", member_mgmt(ROOM_ID, ""))
+        .await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert!(body["error"].as_str().unwrap().contains("wxid"));
+}
+
+#[tokio::test]
+async fn room_member_cache() {
+    let app = TestApp::new();
+    let path = format!("/query-room-member?roomid={}", ROOM_ID);
+    assert_eq!(app.get(&path).await.ok().as_array().unwrap().len(), 3);
+
+    // 直接改模拟器里的群成员，缓存未失效前仍返回旧结果
+    app.sim.add_room(ROOM_ID, &[SELF_WXID, "wxid_mock_alice"]);
+    assert_eq!(app.get(&path).await.ok().as_array().unwrap().len(), 3);
+
+    app.sim.inject(WxMsg {
+        is_group: true,
+        id: 1,
+        r#type: 10000,
+        roomid: ROOM_ID.to_string(),
+        content: "\"Bob\"退出了群聊".to_string(),
+        ..Default::default()
+    });
+    let mut len = 0;
+    for _ in 0..50 {
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        len = app.get(&path).await.ok().as_array().unwrap().len();
+        if len == 2 {
+            break;
+        }
+    }
+    assert_eq!(len, 2);
+}
+
+#[tokio::test]
+async fn resolve_mentions() {
+    let app = TestApp::new();
+    let body = json!({ "roomid": ROOM_ID, "text": "@Alice\u{2005}@bob 开会 @Bobb @nobody @B" });
+    let resolved = app.post("/resolve-mentions", body).await.ok();
+    assert_eq!(resolved[0]["wxid"], "wxid_mock_alice");
+    assert_eq!(resolved[0]["exact"], true);
+    assert_eq!(resolved[1]["wxid"], "wxid_mock_bob");
+    assert_eq!(resolved[1]["exact"], false);
+    assert_eq!(resolved[2]["wxid"], "wxid_mock_bob");
+    assert!(resolved[3]["wxid"].is_null());
+    // 一个字的前缀不算匹配
+    assert!(resolved[4]["wxid"].is_null());
+
+    let body = json!({ "roomid": "1@chatroom", "text": "@Alice" });
+    assert!(app.post("/resolve-mentions", body).await.err().contains("不存在"));
+}
+
+#[tokio::test]
+async fn room_role() {
+    let app = TestApp::new();
+    let role = app.get(&format!("/chatroom/{}/role", ROOM_ID)).await.ok();
+    assert_eq!(role["owner"], SELF_WXID);
+    assert_eq!(role["is_owner"], true);
+    assert_eq!(role["is_admin"], true);
+
+    app.sim.add_room("20000000001@chatroom", &["wxid_mock_alice", SELF_WXID]);
+    let role = app.get("/chatroom/20000000001@chatroom/role").await.ok();
+    assert_eq!(role["is_owner"], false);
+    assert_eq!(role["is_admin"], false);
+
+    assert!(app.get("/chatroom/1@chatroom/role").await.err().contains("不存在"));
+}
+
+#[tokio::test]
+async fn command_permissions() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", std::process::id());
+    let body = json!({ "roomid": roomid, "wxids": "wxid_mock_alice, wxid_mock_bob", "action": "grant" });
+    let granted = app.post("/command-permissions", body).await.ok();
+    assert_eq!(granted["wxids"], json!(["wxid_mock_alice", "wxid_mock_bob"]));
+    assert!(granted["owner"].is_null());
+
+    let body = json!({ "roomid": roomid, "wxids": "wxid_mock_alice", "action": "revoke" });
+    app.post("/command-permissions", body).await.ok();
+    let listed = app.get(&format!("/command-permissions?roomid={}", roomid)).await.ok();
+    assert_eq!(listed["wxids"], json!(["wxid_mock_bob"]));
+
+    let listed = app.get(&format!("/command-permissions?roomid={}", ROOM_ID)).await.ok();
+    assert_eq!(listed["owner"], SELF_WXID);
+
+    let body = json!({ "roomid": roomid, "wxids": "", "action": "grant" });
+    assert!(app.post("/command-permissions", body).await.err().contains("不能为空"));
+}
+
+#[tokio::test]
+async fn announce_rooms() {
+    let app = TestApp::new();
+    let other = "20000000001@chatroom";
+    app.sim.add_room(other, &["wxid_mock_alice", SELF_WXID]);
+    let body = json!({ "rooms": [ROOM_ID, other, ROOM_ID, "1@chatroom"], "content": "{room} 今晚维护，找 {admin}" });
+    let results = app.post("/announce", body).await.ok();
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0]["at_all"], true);
+    assert_eq!(results[0]["sent"], true);
+    assert_eq!(results[1]["at_all"], false);
+    assert_eq!(results[1]["content"], format!("{} 今晚维护，找 Alice", other));
+    assert!(results[2]["error"].as_str().unwrap().contains("不存在"));
+
+    let sent: Vec<TextMsg> = app
+        .sim
+        .outbox()
+        .into_iter()
+        .filter_map(|r| match r.msg {
+            Some(ReqMsg::Txt(msg)) => Some(msg),
+            _ => None,
+        })
+        .collect();
+    let first = sent.iter().find(|m| m.receiver == ROOM_ID).unwrap();
+    assert_eq!(first.aters, "notify@all");
+    assert!(first.msg.starts_with("@所有人 "));
+    assert!(sent.iter().any(|m| m.receiver == other && m.aters.is_empty()));
+
+    let body = json!({ "rooms": [ECHO_WXID], "content": "hi" });
+    app.post("/announce", body).await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn at_all() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let other = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    app.sim.add_room(&roomid, &[SELF_WXID, "wxid_mock_alice"]);
+    app.sim.add_room(&other, &["wxid_mock_alice", SELF_WXID]);
+    let _limit = override_config(|c| &mut c.at_all.per_room_daily, 1);
+
+    let sent = app.post("/at-all", json!({ "roomid": roomid, "content": "@所有人 今晚开会" })).await.ok();
+    assert_eq!(sent["content"], "@所有人 今晚开会");
+    let outbox = app.sim.outbox();
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::Txt(msg)) if msg.receiver == roomid && msg.aters == "notify@all")));
+
+    let rsp = app.post("/at-all", json!({ "roomid": roomid, "content": "再提醒一次" })).await;
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["code"], "BUDGET_EXCEEDED");
+    let rsp = app.post("/at-all", json!({ "roomid": other, "content": "开会" })).await;
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["code"], "NOT_ADMIN");
+    assert!(body["error"].as_str().unwrap().contains("群主或管理员"));
+
+    // 定时消息只检查权限，到时间后才扣减次数
+    let body = json!({ "roomid": roomid, "content": "明天开会", "send_at": "2099-01-01 09:00:00" });
+    let scheduled = app.post("/at-all", body).await.ok();
+    assert_eq!(scheduled["state"], "scheduled");
+    assert!(app.get("/at-all").await.ok().as_array().unwrap().iter().any(|m| m["id"] == scheduled["id"]));
+    let before = app.sim.outbox().len();
+    let body = json!({ "roomid": roomid, "content": "开会", "send_at": "明天" });
+    app.post("/at-all", body).await.expect_status(StatusCode::BAD_REQUEST);
+    let body = json!({ "roomid": roomid, "content": "开会", "send_at": "2099-01-01 9点" });
+    app.post("/at-all", body).await.expect_status(StatusCode::BAD_REQUEST);
+    assert_eq!(app.sim.outbox().len(), before);
+}
+
+#[tokio::test]
+async fn distribute_file() {
+    let app = TestApp::new();
+    let _interval = override_config(|c| &mut c.distribute.interval_ms, 0);
+    let _pause = override_config(|c| &mut c.distribute.batch_pause_secs, 0);
+    let _timeout = override_config(|c| &mut c.distribute.verify_timeout_secs, 1);
+    let file = temp_file("课件.zip", b"zip");
+    let other = "20000000001@chatroom";
+    app.sim.add_room(other, &["wxid_mock_alice", SELF_WXID]);
+
+    let body = json!({ "path": file.to_string_lossy(), "rooms": [ROOM_ID, other, ROOM_ID] });
+    let created = app.post("/distributions", body).await.ok();
+    assert_eq!(created["total"], 2);
+    let job_path = format!("/admin/jobs/{}", created["job"]);
+    let mut job = app.get(&job_path).await.ok();
+    for _ in 0..50 {
+        if job["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        job = app.get(&job_path).await.ok();
+    }
+    assert_eq!(job["state"], "done", "{}", job);
+    assert_eq!(job["done"], 2);
+
+    let report = app.get(&format!("/distributions/{}", created["id"])).await.ok();
+    assert_eq!(report["delivered"], 2, "{}", report);
+    assert_eq!(report["deliveries"][0]["roomid"], ROOM_ID);
+    assert!(report["deliveries"][1]["msg_id"].is_u64());
+
+    // 都已送达，续发时没有要发的群
+    let resumed = app.post(&format!("/distributions/{}/resume", created["id"]), json!({})).await.ok();
+    assert_eq!(resumed["pending"], 0);
+    assert!(app.get("/distributions/999999").await.err().contains("不存在"));
+    let body = json!({ "path": file.with_file_name("missing.zip").to_string_lossy(), "rooms": [ROOM_ID] });
+    assert!(app.post("/distributions", body).await.err().contains("不存在"));
+}
+
+#[tokio::test]
+async fn batch_text() {
+    let app = TestApp::new();
+    let body = json!({
+        "messages": [
+            { "receiver": ECHO_WXID, "msg": "第一条", "aters": "" },
+            { "receiver": ROOM_ID, "msg": "第二条", "aters": "wxid_mock_alice" },
+        ],
+        "interval_ms": 0,
+    });
+    let created = app.post("/batch-text", body).await.ok();
+    assert_eq!(created["total"], 2);
+    let job_path = format!("/admin/jobs/{}", created["job"]);
+    let mut job = app.get(&job_path).await.ok();
+    for _ in 0..50 {
+        if job["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        job = app.get(&job_path).await.ok();
+    }
+    assert_eq!(job["state"], "done", "{}", job);
+
+    let report = app.get(&format!("/batch-text/{}", created["id"])).await.ok();
+    assert_eq!(report["sent"], 2, "{}", report);
+    assert_eq!(report["items"][1]["receiver"], ROOM_ID);
+    assert_eq!(report["items"][1]["state"], "sent");
+    let sent: Vec<_> = app
+        .sim
+        .outbox()
+        .iter()
+        .filter_map(|r| match &r.msg {
+            Some(ReqMsg::Txt(msg)) => Some(msg.msg.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(sent, ["第一条", "第二条"]);
+    assert!(app.get("/batch-text/999999").await.err().contains("不存在"));
+
+    // 每条消息单独校验，错误带上序号
+    let body = json!({ "messages": [{ "receiver": ECHO_WXID, "msg": "ok", "aters": "" }, { "receiver": "", "msg": "", "aters": "" }] });
+    let rsp = app.post("/batch-text", body).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["data"][0]["field"], "messages[1].receiver");
+}
+
+#[tokio::test]
+async fn file_intake() {
+    let app = TestApp::new();
+    let nas = std::env::temp_dir().join("wcf-test").join(uuid::Uuid::new_v4().to_string());
+    let _rules = override_config(|c| &mut c.file_intake.rules, vec![FileIntakeRule {
+        rooms: vec![ROOM_ID.to_string()],
+        senders: vec![],
+        extensions: vec!["PDF".to_string()],
+        target: BackupTarget::Local {
+            dir: nas.to_string_lossy().to_string(),
+        },
+        link_base: "https://nas.example.com/intake".to_string(),
+        reply: "已保存 {name}：{link}".to_string(),
+    }]);
+    let received = |path: &PathBuf| WxMsg {
+        is_group: true,
+        id: 42,
+        r#type: 49,
+        roomid: ROOM_ID.to_string(),
+        sender: "wxid_mock_alice".to_string(),
+        extra: path.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    assert!(file_intake_service::find_rule(&received(&temp_file("notes.txt", b"txt"))).is_none());
+
+    let msg = received(&temp_file("报名表.pdf", b"pdf"));
+    let rule = file_intake_service::find_rule(&msg).unwrap();
+    let link = file_intake_service::intake(&app.wechat, &msg, rule).await.unwrap();
+    assert!(link.starts_with("https://nas.example.com/intake/"));
+    let stored: Vec<_> = std::fs::read_dir(&nas).unwrap().flatten().collect();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(std::fs::read(stored[0].path()).unwrap(), b"pdf");
+    let replied = app.sim.outbox().into_iter().any(|r| match r.msg {
+        Some(ReqMsg::Txt(msg)) => msg.receiver == ROOM_ID && msg.msg.starts_with("已保存 报名表.pdf：https://"),
+        _ => false,
+    });
+    assert!(replied);
+}
+
+#[tokio::test]
+async fn receipts() {
+    let app = TestApp::new();
+    let csv = temp_file("receipts.csv", b"");
+    let _senders = override_config(|c| &mut c.receipt.senders, vec!["wxid_mock_alice".to_string()]);
+    let _csv = override_config(|c| &mut c.receipt.csv, csv.to_string_lossy().to_string());
+    // 模拟器的文字识别直接返回文件内容
+    let image = temp_file("receipt.jpg", "某某超市有限公司\n2024年3月5日\n小计 ¥15.00\n实付 ¥12.50".as_bytes());
+    let msg = WxMsg {
+        id: 7,
+        r#type: 3,
+        roomid: "wxid_mock_alice".to_string(),
+        sender: "wxid_mock_alice".to_string(),
+        ..Default::default()
+    };
+    assert!(receipt_service::wanted(&msg));
+    let receipt = receipt_service::extract(&app.wechat, &msg, &image.to_string_lossy()).unwrap();
+    assert_eq!(receipt.amount, Some(12.5));
+    assert_eq!(receipt.date.as_deref(), Some("2024-03-05"));
+    assert_eq!(receipt.vendor.as_deref(), Some("某某超市有限公司"));
+
+    let listed = app.get("/receipts?sender=wxid_mock_alice").await.ok();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["amount"], 12.5);
+    assert!(app.get("/receipts?sender=wxid_mock_bob").await.ok().as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn announcement_acks() {
+    let app = TestApp::new();
+    let body = json!({ "roomid": ROOM_ID, "content": "明天 9 点开会", "keywords": ["收到", "+1"] });
+    let created = app.post("/announcements", body).await.ok();
+    let id = created["id"].as_u64().unwrap();
+    assert_eq!(created["pending"].as_array().unwrap().len(), 2);
+
+    let reply = |sender: &str, content: &str| WxMsg {
+        sender: sender.to_string(),
+        ..text_msg(ROOM_ID, content)
+    };
+    {
+        let mut announcements = GLOBAL.get().unwrap().announcement_service.lock().unwrap();
+        announcements.record(&reply("wxid_mock_bob", "好"));
+        announcements.record(&reply("wxid_mock_alice", " 收到！"));
+        announcements.record(&reply("wxid_mock_alice", "+1"));
+    }
+    let report = app.get(&format!("/announcements/{}/acks", id)).await.ok();
+    assert_eq!(report["acked"][0]["wxid"], "wxid_mock_alice");
+    assert_eq!(report["acked"][0]["keyword"], "收到");
+    assert_eq!(report["acked"].as_array().unwrap().len(), 1);
+    assert_eq!(report["pending"][0]["wxid"], "wxid_mock_bob");
+    assert!(app.get("/announcements/999999/acks").await.err().contains("不存在"));
+}
+
+#[tokio::test]
+async fn polls() {
+    let app = TestApp::new();
+    let body = json!({ "roomid": ROOM_ID, "question": "吃什么", "options": ["火锅", "烧烤"] });
+    let poll = app.post("/polls", body).await.ok();
+    let id = poll["id"].as_u64().unwrap();
+    assert_eq!(poll["total"], 0);
+
+    let vote = |sender: &str, content: &str| WxMsg {
+        sender: sender.to_string(),
+        ..text_msg(ROOM_ID, content)
+    };
+    // 测试中没有订阅消息处理器，直接计票
+    {
+        let mut polls = GLOBAL.get().unwrap().poll_service.lock().unwrap();
+        polls.record(&vote("wxid_mock_alice", "1"));
+        polls.record(&vote("wxid_mock_bob", "1"));
+        polls.record(&vote("wxid_mock_bob", "烧烤"));
+        polls.record(&vote("wxid_mock_alice", "随便"));
+    }
+    let result = app.get(&format!("/polls/{}", id)).await.ok();
+    assert_eq!(result["total"], 2);
+    assert_eq!(result["options"][0]["voters"], json!(["wxid_mock_alice"]));
+    assert_eq!(result["options"][1]["count"], 1);
+
+    let closed = app.post(&format!("/polls/{}/close", id), json!({})).await.ok();
+    assert_eq!(closed["closed"], true);
+    let summaries: Vec<String> = app
+        .sim
+        .outbox()
+        .into_iter()
+        .filter_map(|r| match r.msg {
+            Some(ReqMsg::Txt(msg)) => Some(msg.msg),
+            _ => None,
+        })
+        .collect();
+    assert!(summaries.iter().any(|m| m.contains("已结束") && m.contains("烧烤 - 1 票")));
+    assert!(app.post(&format!("/polls/{}/close", id), json!({})).await.err().contains("已结束"));
+
+    let body = json!({ "roomid": ROOM_ID, "question": "吃什么", "options": ["火锅"] });
+    assert!(app.post("/polls", body).await.err().contains("两个选项"));
+}
+
+#[tokio::test]
+async fn checkin() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let config = CheckinConfig {
+        rooms: vec![roomid.clone()],
+        ..Default::default()
+    };
+    let msg = |sender: &str, content: &str| WxMsg {
+        sender: sender.to_string(),
+        ..text_msg(&roomid, content)
+    };
+    {
+        let mut checkin = GLOBAL.get().unwrap().checkin_service.lock().unwrap();
+        assert!(checkin.record(&msg("wxid_mock_alice", "今日打卡"), &config));
+        assert!(!checkin.record(&msg("wxid_mock_alice", "打卡"), &config));
+        assert!(!checkin.record(&msg("wxid_mock_bob", "早上好"), &config));
+        let own = WxMsg {
+            is_self: true,
+            ..msg(SELF_WXID, "打卡")
+        };
+        assert!(!checkin.record(&own, &config));
+    }
+    let stats = app.get(&format!("/checkin?roomid={}", roomid)).await.ok();
+    assert_eq!(stats.as_array().unwrap().len(), 1);
+    assert_eq!(stats[0]["wxid"], "wxid_mock_alice");
+    assert_eq!(stats[0]["streak"], 1);
+    assert_eq!(stats[0]["today"], true);
+
+    // 合并身份后两个账号的打卡算作同一个人
+    let main = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let alt = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    app.post("/identities", json!({ "canonical": main, "aliases": [alt] })).await.ok();
+    {
+        let mut checkin = GLOBAL.get().unwrap().checkin_service.lock().unwrap();
+        assert!(checkin.record(&msg(&main, "打卡"), &config));
+        assert!(checkin.record(&msg(&alt, "打卡"), &config));
+    }
+    let stats = app.get(&format!("/checkin?roomid={}", roomid)).await.ok();
+    assert_eq!(stats.as_array().unwrap().len(), 2);
+    assert!(stats.as_array().unwrap().iter().any(|s| s["wxid"] == json!(main) && s["days"] == 1));
+}
+
+#[tokio::test]
+async fn raffle() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let body = json!({ "roomid": roomid, "keyword": "抽奖", "count": 1, "minutes": 10 });
+    let raffle = app.post("/raffles", body).await.ok();
+    let id = raffle["id"].as_u64().unwrap();
+
+    let entry = |sender: &str, content: &str| WxMsg {
+        sender: sender.to_string(),
+        ..text_msg(&roomid, content)
+    };
+    {
+        let mut raffles = GLOBAL.get().unwrap().raffle_service.lock().unwrap();
+        raffles.record(&entry("wxid_mock_alice", "抽奖"));
+        raffles.record(&entry("wxid_mock_bob", " 抽奖 "));
+        raffles.record(&entry("wxid_mock_carol", "我也要抽奖"));
+    }
+    let raffle = app.get(&format!("/raffles/{}", id)).await.ok();
+    assert_eq!(raffle["participants"], json!(["wxid_mock_alice", "wxid_mock_bob"]));
+    assert!(raffle["draw"].is_null());
+
+    let drawn = app.post(&format!("/raffles/{}/draw", id), json!({})).await.ok();
+    let winners = drawn["draw"]["winners"].as_array().unwrap();
+    assert_eq!(winners.len(), 1);
+    assert!(drawn["draw"]["seed"].is_u64());
+    assert!(app.post(&format!("/raffles/{}/draw", id), json!({})).await.err().contains("开过奖"));
+}
+
+#[tokio::test]
+async fn quiet_hours() {
+    let app = TestApp::new();
+    let quiet = format!("wxid_quiet_{}", uuid::Uuid::new_v4().simple());
+    let now = chrono::Local::now();
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().quiet_hours.push(QuietHoursConfig {
+        targets: vec![quiet.clone()],
+        start: (now - chrono::Duration::hours(1)).format("%H:%M").to_string(),
+        end: (now + chrono::Duration::hours(1)).format("%H:%M").to_string(),
+    });
+    let msg = |receiver: &str| TextMsg {
+        msg: "晚安".to_string(),
+        receiver: receiver.to_string(),
+        aters: String::new(),
+    };
+    {
+        let wechat = app.wechat.lock().unwrap();
+        assert_eq!(quiet_hours_service::send_text(&wechat, msg(&quiet)), Ok(false));
+        assert_eq!(quiet_hours_service::send_text(&wechat, msg(ECHO_WXID)), Ok(true));
+    }
+    let queued = app.get("/quiet-hours").await.ok();
+    assert!(queued.as_array().unwrap().iter().any(|q| q["receiver"] == quiet.as_str()));
+    assert_eq!(app.sim.outbox().iter().filter(|r| r.func == Functions::FuncSendTxt as i32).count(), 1);
+}
+
+#[tokio::test]
+async fn pause() {
+    // 用单独的实例测试，不切换全局的暂停开关，避免影响并行的其它测试；状态文件写在测试的临时工作目录
+    ensure_global();
+    let mut pause = PauseService::new();
+    assert!(pause.pause("test".to_string()).paused);
+    assert!(pause.check().unwrap_err().contains("暂停"));
+    assert!(!pause.resume().paused);
+    assert_eq!(pause.check(), Ok(()));
+}
+
+#[tokio::test]
+async fn config_bundle() {
+    let app = TestApp::new();
+    let bundle = app.get("/admin/config/export").await.ok();
+    assert_eq!(bundle["format"], 1);
+    assert_eq!(bundle["config"]["mock"], true);
+
+    let name = format!("test-{}", uuid::Uuid::new_v4().simple());
+    app.post(&format!("/admin/config/profiles/{}", name), json!({})).await.ok();
+    let profiles = app.get("/admin/config/profiles").await.ok();
+    assert!(profiles.as_array().unwrap().iter().any(|p| p == name.as_str()));
+    assert!(app.post("/admin/config/profiles/a.b", json!({})).await.err().contains("不合法"));
+    assert!(app.post("/admin/config/profiles/missing/apply", json!({})).await.err().contains("不存在"));
+
+    // 校验失败时不会写入任何内容
+    let mut bad = bundle.clone();
+    bad["config"]["http_server_port"] = json!("x");
+    assert!(app.post("/admin/config/import", bad).await.err().contains("不合法"));
+    let mut bad = bundle;
+    bad["state"] = json!({ "risk_guard": {} });
+    assert!(app.post("/admin/config/import", bad).await.err().contains("未知"));
+}
+
+#[tokio::test]
+async fn validate_sink() {
+    let app = TestApp::new();
+    let reports = app.post("/admin/validate-sink", json!({ "url": "http://127.0.0.1:1/hook" })).await.ok();
+    assert_eq!(reports[0]["kind"], "http");
+    assert_eq!(reports[0]["reachable"], false);
+    assert_eq!(reports[0]["ok"], false);
+    assert!(reports[0]["tls"].is_null());
+
+    // 测试配置里没有回调和 socketIO 地址
+    let reports = app.post("/admin/validate-sink", json!({})).await.ok();
+    assert_eq!(reports, json!([]));
+}
+
+#[tokio::test]
+async fn metrics() {
+    let app = TestApp::new();
+    app.get("/islogin").await.ok();
+    let reply = app.get("/metrics").await;
+    reply.expect_status(StatusCode::OK);
+    assert!(reply.content_type.starts_with("application/openmetrics-text"));
+    let text = String::from_utf8(reply.body.to_vec()).unwrap();
+    assert!(text.contains("wcf_rpc_duration_seconds_bucket{method=\"FUNC_IS_LOGIN\",le=\"+Inf\"}"));
+    assert!(text.contains("wcf_rpc_errors_total{method=\"FUNC_IS_LOGIN\"}"));
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn pipeline() {
+    let app = TestApp::new();
+    app.sim.receive_text("wxid_mock_alice", ROOM_ID, "hi");
+    let mut stats = app.get("/admin/pipeline").await.ok();
+    for _ in 0..50 {
+        if stats["receive"]["received"].as_u64().unwrap() > 0 && stats["receive"]["queue_depth"] == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        stats = app.get("/admin/pipeline").await.ok();
+    }
+    assert!(stats["receive"]["per_minute"].as_u64().unwrap() > 0);
+    assert_eq!(stats["receive"]["queue_depth"], 0);
+    assert!(stats["handlers"].is_array());
+    assert!(stats["sinks"].is_array());
+    assert!(stats["quiet_queue"].is_u64());
+}
+
+#[tokio::test]
+async fn message_volume() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let msg = text_msg(&roomid, "hi");
+    {
+        let mut anomaly = GLOBAL.get().unwrap().anomaly_service.lock().unwrap();
+        anomaly.record(&msg);
+        anomaly.record(&msg);
+    }
+    let volumes = app.get("/message-volume").await.ok();
+    let room = volumes.as_array().unwrap().iter().find(|v| v["roomid"] == roomid.as_str()).cloned().unwrap();
+    assert_eq!(room["current"], 2);
+    assert_eq!(room["samples"], 0);
+    assert!(volumes.as_array().unwrap().iter().any(|v| v["roomid"] == "*"));
+}
+
+#[tokio::test]
+async fn pipe_json_rpc() {
+    let app = TestApp::new();
+    let mut service = warp::service(crate::endpoints::get_routes(app.wechat.clone()));
+    let reply = pipe_service::handle_line(&mut service, r#"{"jsonrpc":"2.0","id":1,"method":"GET /islogin"}"#).await;
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["result"]["data"], true);
+
+    let line = json!({ "jsonrpc": "2.0", "id": 2, "method": "POST /text", "params": text(ECHO_WXID, "hi") });
+    let reply = pipe_service::handle_line(&mut service, &line.to_string()).await;
+    assert_eq!(reply["result"]["data"]["sent"], true);
+
+    let reply = pipe_service::handle_line(&mut service, "not json").await;
+    assert_eq!(reply["error"]["code"], -32700);
+    let reply = pipe_service::handle_line(&mut service, r#"{"id":3,"method":"GET /not-exists"}"#).await;
+    assert_eq!(reply["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn body_validation() {
+    let app = TestApp::new();
+    let rsp = app.post("/text", text("", "")).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["code"], "INVALID_FIELDS");
+    assert_eq!(body["data"][0]["field"], "receiver");
+    assert_eq!(body["data"][1]["field"], "msg");
+
+    let rsp = app.post("/pat", json!({ "roomid": "wxid_mock_alice", "wxid": "wxid_mock_bob" })).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["data"][0]["field"], "roomid");
+
+    let sql = "SELECT 1 ".repeat(10 * 1024);
+    let rsp = app.post("/sql", json!({ "db": "MicroMsg.db", "sql": sql })).await;
+    rsp.expect_status(StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["code"], "PAYLOAD_TOO_LARGE");
+
+    // 没有 Content-Length 的请求体也返回 JSON 错误
+    let rsp = app.send(warp::test::request().method("POST").path("/text")).await;
+    rsp.expect_status(StatusCode::LENGTH_REQUIRED);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["code"], "LENGTH_REQUIRED");
+}
+
+#[tokio::test]
+async fn id_format() {
+    let app = TestApp::new();
+    let rsp = app.post("/text", text("张三", "hi")).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert!(body["data"][0]["message"].as_str().unwrap().contains("昵称"));
+
+    let rsp = app.post("/add-chatroom-member", member_mgmt(ROOM_ID, &format!("wxid_mock_bob,{}", ROOM_ID))).await;
+    rsp.expect_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_slice(&rsp.body).unwrap();
+    assert_eq!(body["data"][0]["field"], "wxids");
+    assert!(body["error"].as_str().unwrap().contains("群 id"));
+
+    assert_eq!(app.post("/text", text("filehelper", "hi")).await.ok()["sent"], true);
+    assert_eq!(app.post("/text", text("12345678@openim", "hi")).await.ok()["sent"], true);
+    assert_eq!(app.post("/text", text("qq1234", "hi")).await.ok()["sent"], true);
+}
+
+#[tokio::test]
+async fn send_verify() {
+    let app = TestApp::new();
+    let content = format!("verify {}", uuid::Uuid::new_v4());
+    let data = app.post("/text?verify=true", text("wxid_mock_alice", &content)).await.ok();
+    assert_eq!(data["sent"], true);
+    assert_eq!(data["verified"], true);
+    assert!(data["msg_id"].as_u64().is_some());
+
+    // 不确认时默认不等待回调
+    let data = app.post("/text", text("wxid_mock_alice", &content)).await.ok();
+    assert_eq!(data["verified"], false);
+    assert_eq!(data["msg_id"], json!(null));
+
+    // 同一会话并发发送时按内容认出各自的消息
+    let other = format!("verify {}", uuid::Uuid::new_v4());
+    let (a, b) = tokio::join!(
+        app.post("/text?verify=true", text("wxid_mock_alice", &content)),
+        app.post("/text?verify=true", text("wxid_mock_alice", &other))
+    );
+    assert_ne!(a.ok()["msg_id"], b.ok()["msg_id"]);
+}
+
+#[tokio::test]
+async fn moderation() {
+    let app = TestApp::new();
+    // 关键词只在本测试中出现，不影响并行的其它测试
+    let keyword = uuid::Uuid::new_v4().simple().to_string();
+    let _enabled = override_config(|c| &mut c.moderation.enabled, true);
+    let _keywords = override_config(|c| &mut c.moderation.keywords, vec![keyword.clone()]);
+    let _token = override_config(|c| &mut c.moderation.override_token, Secret::new("reviewed"));
+    let risky = format!("包含 {} 的内容", keyword.to_uppercase());
+    let rsp = app.post("/text", text("wxid_mock_alice", &risky)).await;
+    let body = rsp.envelope();
+    assert_eq!(body["code"], "CONTENT_BLOCKED");
+    assert!(app.sim.outbox().iter().all(|r| r.func != Functions::FuncSendTxt as i32));
+
+    // xml 消息审核其中的标题、链接等文字
+    let xml = format!("<msg><appmsg><title>{}</title><type>5</type></appmsg></msg>", risky);
+    let body = json!({ "receiver": "wxid_mock_alice", "content": xml, "path": "", "type": 49 });
+    assert_eq!(app.post("/xml", body).await.envelope()["code"], "CONTENT_BLOCKED");
+
+    let data = app.post("/text?override_token=reviewed", text("wxid_mock_alice", &risky)).await.ok();
+    assert_eq!(data["sent"], true);
+}
+
+// 开启消息库，所有测试共用一个临时文件
+fn enable_message_store() {
+    let mut config = GLOBAL.get().unwrap().wechat_config.write().unwrap();
+    config.message_store.enabled = true;
+    if config.message_store.path.is_empty() {
+        let dir = std::env::temp_dir().join("wcf-test").join(uuid::Uuid::new_v4().to_string());
+        config.message_store.path = dir.join("messages.db").to_string_lossy().to_string();
+    }
+}
+
+fn store_message(roomid: &str, sender: &str) {
+    enable_message_store();
+    let global = GLOBAL.get().unwrap();
+    let msg = WxMsg {
+        id: rand::random::<u32>() as u64,
+        ts: 1_600_000_000,
+        sender: sender.to_string(),
+        ..text_msg(roomid, "hi")
+    };
+    global.message_store_service.lock().unwrap().insert(&msg).unwrap();
+}
+
+#[tokio::test]
+async fn purge() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    store_message(&roomid, "wxid_mock_alice");
+    store_message(&roomid, "wxid_mock_bob");
+
+    assert!(app.post("/admin/purge", json!({})).await.err().contains("roomid"));
+    let err = app.post("/admin/purge", json!({ "roomid": roomid, "from": "yesterday" })).await.err();
+    assert!(err.contains("yesterday"));
+
+    let report = app.post("/admin/purge", json!({ "roomid": roomid })).await.ok();
+    assert_eq!(report["messages"], 2);
+    assert_eq!(report["media_files"], 0);
+    let report = app.post("/admin/purge", json!({ "roomid": roomid })).await.ok();
+    assert_eq!(report["messages"], 0);
+}
+
+#[tokio::test]
+async fn privacy() {
+    let app = TestApp::new();
+    let wxid = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    store_message(&roomid, &wxid);
+    store_message(&wxid, &wxid);
+    store_message(&roomid, "wxid_mock_alice");
+    GLOBAL.get().unwrap().command_permission_service.lock().unwrap().grant(&roomid, &[wxid.clone()]).unwrap();
+
+    let data = app.get(&format!("/privacy/export?wxid={}", wxid)).await.ok();
+    assert_eq!(data["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(data["state"]["command_permissions"], json!([roomid]));
+
+    let report = app.delete(&format!("/privacy/data?wxid={}", wxid)).await.ok();
+    assert_eq!(report["messages"], 2);
+    assert_eq!(report["state"]["command_permissions"], 1);
+    let data = app.get(&format!("/privacy/export?wxid={}", wxid)).await.ok();
+    assert!(data["messages"].as_array().unwrap().is_empty());
+    assert!(data["state"]["command_permissions"].as_array().unwrap().is_empty());
+
+    // 群里其他人的消息不受影响
+    let report = app.post("/admin/purge", json!({ "roomid": roomid })).await.ok();
+    assert_eq!(report["messages"], 1);
+    assert!(app.get("/privacy/export?wxid=bad%20id").await.err().contains("wxid"));
+}
+
+#[tokio::test]
+async fn identities() {
+    let app = TestApp::new();
+    let main = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let alt = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    store_message(&roomid, &main);
+    store_message(&roomid, &alt);
+    store_message(&roomid, &alt);
+
+    let body = json!({ "canonical": main, "name": "张三", "aliases": [alt] });
+    let identity = app.post("/identities", body).await.ok();
+    assert_eq!(identity["aliases"], json!([alt]));
+    assert!(app.get("/identities").await.ok().as_array().unwrap().contains(&identity));
+    // 一个账号只能属于一个身份
+    let other = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let body = json!({ "canonical": other, "aliases": [alt] });
+    assert!(app.post("/identities", body).await.err().contains("已属于"));
+
+    let heatmap = app.get(&format!("/stats/rooms/{}/heatmap", roomid)).await.ok();
+    assert_eq!(heatmap["members"].as_array().unwrap().len(), 1);
+    assert_eq!(heatmap["members"][0]["wxid"], main.as_str());
+    assert_eq!(heatmap["members"][0]["name"], "张三");
+    assert_eq!(heatmap["members"][0]["total"], 3);
+
+    // 用别名导出也包含主账号的数据
+    let data = app.get(&format!("/privacy/export?wxid={}", alt)).await.ok();
+    assert_eq!(data["aliases"], json!([main]));
+    assert_eq!(data["messages"].as_array().unwrap().len(), 3);
+    let report = app.delete(&format!("/privacy/data?wxid={}", main)).await.ok();
+    assert_eq!(report["messages"], 3);
+    assert_eq!(report["state"]["identities"], 1);
+    assert!(!app.get("/identities").await.ok().as_array().unwrap().contains(&identity));
+}
+
+#[tokio::test]
+async fn bridge_identities() {
+    let app = TestApp::new();
+    let main = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let alias = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let external_id = uuid::Uuid::new_v4().simple().to_string();
+    app.post("/identities", json!({ "canonical": main, "aliases": [alias] })).await.ok();
+
+    // 用别名设置时按主账号保存，两个方向都能查到
+    let body = json!({ "platform": "Telegram", "wxid": alias, "external_id": external_id, "display_name": "小王" });
+    let saved = app.post("/bridge-identities", body).await.ok();
+    assert_eq!(saved["wxid"], main);
+    assert_eq!(saved["platform"], "telegram");
+    let found = app.get(&format!("/bridge-identities?platform=telegram&wxid={}", alias)).await.ok();
+    assert_eq!(found[0]["display_name"], "小王");
+    let found = app.get(&format!("/bridge-identities?platform=telegram&external_id={}", external_id)).await.ok();
+    assert_eq!(found[0]["wxid"], main);
+    assert!(app.get(&format!("/bridge-identities?platform=discord&wxid={}", main)).await.ok().as_array().unwrap().is_empty());
+
+    let other = json!({ "platform": "telegram", "wxid": "wxid_mock_bob", "external_id": external_id });
+    assert!(app.post("/bridge-identities", other).await.err().contains("已对应"));
+    let body = json!({ "platform": "wechat", "wxid": main, "external_id": "1" });
+    app.post("/bridge-identities", body).await.expect_status(StatusCode::BAD_REQUEST);
+
+    let removed = json!({ "platform": "telegram", "wxid": main, "external_id": "" });
+    assert!(app.post("/bridge-identities", removed).await.ok().is_null());
+    let found = app.get(&format!("/bridge-identities?platform=telegram&external_id={}", external_id)).await.ok();
+    assert!(found.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn bridge_threads() {
+    let app = TestApp::new();
+    let chat = uuid::Uuid::new_v4().simple().to_string();
+    let quoted = WxMsg {
+        is_group: true,
+        id: 2,
+        r#type: 49,
+        roomid: ROOM_ID.to_string(),
+        content: "<msg><appmsg><title>好的</title><type>57</type><refermsg><svrid>778899</svrid></refermsg></appmsg></msg>" .to_string(),
+        sender: "wxid_mock_alice".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(payload::parse_quoted_msg_id(&quoted), Some(778899));
+
+    let link = json!({ "platform": "Telegram", "msg_id": 778899, "roomid": ROOM_ID, "chat": chat, "external_id": "42" });
+    assert_eq!(app.post("/bridge-threads", link).await.ok()["platform"], "telegram");
+    let found = app.get("/bridge-threads?platform=telegram&msg_id=778899").await.ok();
+    assert_eq!(found[0]["external_id"], "42");
+    let found = app.get(&format!("/bridge-threads?platform=telegram&chat={}&external_id=42", chat)).await.ok();
+    assert_eq!(found[0]["msg_id"], 778899);
+    assert!(app.get("/bridge-threads?platform=discord&msg_id=778899").await.ok().as_array().unwrap().is_empty());
+
+    // 同一条微信消息再次转发时覆盖
+    let link = json!({ "platform": "telegram", "msg_id": 778899, "roomid": ROOM_ID, "chat": chat, "external_id": "43" });
+    app.post("/bridge-threads", link).await.ok();
+    let found = app.get("/bridge-threads?platform=telegram&msg_id=778899").await.ok();
+    assert_eq!(found[0]["external_id"], "43");
+    let link = json!({ "platform": "telegram", "msg_id": 0, "roomid": ROOM_ID, "chat": chat, "external_id": "44" });
+    app.post("/bridge-threads", link).await.expect_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn webhook_gateway() {
+    use hmac::{Hmac, Mac};
+    let app = TestApp::new();
+    let name = uuid::Uuid::new_v4().simple().to_string();
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().webhook.hooks.push(WebhookHook {
+        name: name.clone(),
+        secret: Secret::new("s3cret"),
+        receiver: ECHO_WXID.to_string(),
+        template: "{repo} 有新提交：{commit.message}".to_string(),
+        schema: Some(json!({
+            "type": "object",
+            "required": ["repo", "commit"],
+            "properties": {
+                "repo": { "type": "string", "minLength": 1 },
+                "commit": { "type": "object", "required": ["message"], "properties": { "message": { "type": "string" } } }
+            }
+        })),
+    });
+    let deliver = |body: serde_json::Value, nonce: &str, timestamp: i64, secret: &str| {
+        let body = body.to_string();
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.{}.{}", timestamp, nonce, body).as_bytes());
+        let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        warp::test::request()
+            .method("POST")
+            .path(&format!("/webhook/{}", name))
+            .header("x-webhook-timestamp", timestamp.to_string())
+            .header("x-webhook-nonce", nonce)
+            .header("x-webhook-signature", format!("sha256={}", signature))
+            .body(body)
+    };
+    let now = chrono::Local::now().timestamp();
+    let event = json!({ "repo": "wcf", "commit": { "message": "修复登录" } });
+
+    assert_eq!(app.send(deliver(event.clone(), "n1", now, "s3cret")).await.ok(), true);
+    let outbox = app.sim.outbox();
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::Txt(msg)) if msg.msg == "wcf 有新提交：修复登录")));
+
+    // 重放同一个 nonce、过期的时间戳和错误的签名都会被拒绝
+    app.send(deliver(event.clone(), "n1", now, "s3cret")).await.expect_status(StatusCode::CONFLICT);
+    app.send(deliver(event.clone(), "n2", now - 3600, "s3cret")).await.expect_status(StatusCode::UNAUTHORIZED);
+    app.send(deliver(event.clone(), "n3", now, "wrong")).await.expect_status(StatusCode::UNAUTHORIZED);
+
+    let rsp = app.send(deliver(json!({ "repo": "", "commit": {} }), "n4", now, "s3cret")).await;
+    let body: serde_json::Value = serde_json::from_slice(&rsp.expect_status(StatusCode::BAD_REQUEST).body).unwrap();
+    let mut fields: Vec<_> = body["data"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+    fields.sort();
+    assert_eq!(fields, vec!["$.commit.message", "$.repo"]);
+    let unknown = warp::test::request().method("POST").path("/webhook/not-configured").body("{}");
+    app.send(unknown).await.expect_status(StatusCode::NOT_FOUND);
+
+    // 密钥为空（例如解密失败）时不接收投递
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().webhook.hooks.iter_mut().find(|h| h.name == name).unwrap().secret =
+        Secret::default();
+    app.send(deliver(event.clone(), "n5", now, "")).await.expect_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rule_http_action() {
+    use warp::Filter;
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let received = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let seen = received.clone();
+    let route = warp::path!("bot" / String)
+        .and(warp::body::bytes())
+        .map(move |city: String, body: warp::hyper::body::Bytes| {
+            let city = urlencoding::decode(&city).unwrap().into_owned();
+            seen.lock().unwrap().push((city, String::from_utf8_lossy(&body).to_string()));
+            warp::reply::json(&json!({ "reply": "晴" }))
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let mut headers = std::collections::BTreeMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.push(RuleConfig {
+        name: "天气".to_string(),
+        rooms: vec![roomid.clone()],
+        pattern: r"^天气 (?P<city>.+)$".to_string(),
+        actions: vec![RuleAction::Http(HttpAction {
+            method: "POST".to_string(),
+            url: format!("http://{}/bot/{{city}}", addr),
+            headers,
+            body: r#"{"q": "{content}", "from": "{sender}"}"#.to_string(),
+            reply: true,
+            timeout_secs: 5,
+        })],
+        ..Default::default()
+    });
+    let msg = |content: &str| WxMsg {
+        id: 20,
+        ..text_msg(&roomid, content)
+    };
+    let run = |msg: WxMsg| {
+        let wechat = app.wechat.clone();
+        tokio::task::spawn_blocking(move || rule_service::handle(&wechat, &msg))
+    };
+    assert_eq!(run(msg("你好")).await.unwrap(), 0);
+    assert_eq!(run(msg("天气 上海 \"浦东\"")).await.unwrap(), 1);
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].0, "上海 \"浦东\"");
+    let body: serde_json::Value = serde_json::from_str(&received[0].1).unwrap();
+    assert_eq!(body["q"], "天气 上海 \"浦东\"");
+    assert_eq!(body["from"], "wxid_mock_alice");
+    let outbox = app.sim.outbox();
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::Txt(msg)) if msg.receiver == roomid && msg.msg == "晴")));
+}
+
+#[tokio::test]
+async fn rule_simulation() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let name = format!("查询-{}", uuid::Uuid::new_v4().simple());
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.push(RuleConfig {
+        name: name.clone(),
+        rooms: vec![roomid.clone()],
+        pattern: r"^查 (?P<id>\d+)$".to_string(),
+        actions: vec![
+            RuleAction::Reply {
+                text: "正在查询 {id}".to_string(),
+            },
+            RuleAction::Http(HttpAction {
+                method: "get".to_string(),
+                url: "http://127.0.0.1:9/orders/{id}".to_string(),
+                headers: Default::default(),
+                body: "{sender}".to_string(),
+                reply: true,
+                timeout_secs: 1,
+            }),
+            RuleAction::Script(ScriptAction {
+                script: "ops.sh".to_string(),
+                args: vec![],
+                reply: true,
+                timeout_secs: 1,
+            }),
+        ],
+        ..Default::default()
+    });
+    let trace = |traces: serde_json::Value| {
+        let traces = traces.as_array().unwrap().clone();
+        traces.into_iter().find(|t| t["rule"] == name.as_str()).unwrap()
+    };
+
+    let body = json!({ "content": "查 42", "sender": "wxid_mock_alice", "roomid": roomid });
+    let hit = trace(app.post("/rules/test", body).await.ok());
+    assert_eq!(hit["matched"], true, "{}", hit);
+    assert_eq!(hit["vars"]["id"], "42");
+    assert_eq!(hit["actions"][0]["target"], "正在查询 42");
+    assert_eq!(hit["actions"][1]["method"], "GET");
+    assert_eq!(hit["actions"][1]["target"], "http://127.0.0.1:9/orders/42");
+    assert_eq!(hit["actions"][1]["body"], "wxid_mock_alice");
+    // 脚本动作没有限定 senders 时会被拦下
+    assert_eq!(hit["actions"][2]["type"], "script");
+    assert!(hit["actions"][2]["blocked"].is_string());
+
+    let body = json!({ "content": "查 abc", "sender": "wxid_mock_alice", "roomid": roomid });
+    let miss = trace(app.post("/rules/test", body).await.ok());
+    assert_eq!(miss["matched"], false);
+    assert!(miss["reason"].as_str().unwrap().contains("不匹配"));
+    let body = json!({ "content": "查 42", "sender": "wxid_mock_alice" });
+    let private = trace(app.post("/rules/test", body).await.ok());
+    assert!(private["reason"].as_str().unwrap().contains("rooms"));
+    // 只渲染不执行
+    assert!(app.sim.outbox().iter().all(|r| r.func != Functions::FuncSendTxt as i32));
+}
+
+#[tokio::test]
+async fn rule_priorities_and_groups() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let group = format!("值班-{}", uuid::Uuid::new_v4().simple());
+    let rule = |name: &str, priority: i32, group: &str, stop: bool| RuleConfig {
+        name: format!("{}-{}", name, roomid),
+        rooms: vec![roomid.clone()],
+        actions: vec![RuleAction::Reply { text: name.to_string() }],
+        priority,
+        group: group.to_string(),
+        stop,
+        ..Default::default()
+    };
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.extend([
+        rule("兜底", 0, "", false),
+        rule("值班", 10, &group, true),
+        rule("告警", 5, "", false),
+    ]);
+    // 本测试中的规则按返回顺序的 (名称, 是否命中)
+    async fn traces(app: &TestApp, roomid: &str) -> Vec<(String, bool)> {
+        let body = json!({ "content": "出故障了", "sender": "wxid_mock_alice", "roomid": roomid });
+        let traces = app.post("/rules/test", body).await.ok();
+        traces
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|t| t["rule"].as_str().unwrap().ends_with(roomid))
+            .map(|t| (t["rule"].as_str().unwrap().split('-').next().unwrap().to_string(), t["matched"] == true))
+            .collect()
+    }
+    // 按优先级匹配，值班命中后停止
+    let expected = |names: [(&str, bool); 3]| names.map(|(n, m)| (n.to_string(), m)).to_vec();
+    assert_eq!(traces(&app, &roomid).await, expected([("值班", true), ("告警", false), ("兜底", false)]));
+
+    let path = format!("/rules/groups/{}/disable", urlencoding::encode(&group));
+    let groups = app.post(&path, json!({})).await.ok();
+    let listed = groups.as_array().unwrap().iter().find(|g| g["name"] == group.as_str()).cloned().unwrap();
+    assert_eq!(listed["enabled"], false);
+    assert_eq!(traces(&app, &roomid).await, expected([("值班", false), ("告警", true), ("兜底", true)]));
+
+    let wechat = app.wechat.clone();
+    let msg = WxMsg {
+        id: 22,
+        ..text_msg(&roomid, "出故障了")
+    };
+    assert_eq!(tokio::task::spawn_blocking(move || rule_service::handle(&wechat, &msg)).await.unwrap(), 2);
+    let path = format!("/rules/groups/{}/enable", urlencoding::encode(&group));
+    app.post(&path, json!({})).await.ok();
+    assert_eq!(traces(&app, &roomid).await, expected([("值班", true), ("告警", false), ("兜底", false)]));
+}
+
+#[tokio::test]
+async fn rule_rate_limits() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let rule = |name: &str, cooldown_secs: u64, max_per_hour: u32| RuleConfig {
+        name: format!("{}-{}", name, roomid),
+        rooms: vec![roomid.clone()],
+        pattern: format!("^{}$", name),
+        actions: vec![RuleAction::Reply { text: "收到".to_string() }],
+        cooldown_secs,
+        max_per_hour,
+        ..Default::default()
+    };
+    GLOBAL
+        .get()
+        .unwrap()
+        .wechat_config
+        .write()
+        .unwrap()
+        .rules
+        .extend([rule("签到", 0, 2), rule("天气", 3600, 0)]);
+    let run = |content: &str| {
+        let wechat = app.wechat.clone();
+        let msg = WxMsg {
+            id: 23,
+            ..text_msg(&roomid, content)
+        };
+        tokio::task::spawn_blocking(move || rule_service::handle(&wechat, &msg))
+    };
+    // 每小时最多 2 次
+    for expected in [1, 1, 0] {
+        assert_eq!(run("签到").await.unwrap(), expected);
+    }
+    // 冷却期间不再触发
+    assert_eq!(run("天气").await.unwrap(), 1);
+    assert_eq!(run("天气").await.unwrap(), 0);
+
+    let stats = app.get("/rules/stats").await.ok();
+    let stat = |name: &str| {
+        let rule = format!("{}-{}", name, roomid);
+        stats.as_array().unwrap().iter().find(|s| s["rule"] == rule.as_str()).cloned().unwrap()
+    };
+    assert_eq!((stat("签到")["last_hour"].clone(), stat("签到")["suppressed"].clone()), (json!(2), json!(1)));
+    assert_eq!(stat("天气")["total"], 1);
+    assert!(stat("天气")["last_fired_at"].is_string());
+
+    let body = json!({ "content": "天气", "sender": "wxid_mock_alice", "roomid": roomid });
+    let traces = app.post("/rules/test", body).await.ok();
+    let rule = format!("天气-{}", roomid);
+    let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == rule.as_str()).cloned().unwrap();
+    assert!(trace["throttled"].as_str().unwrap().contains("冷却"));
+}
+
+#[tokio::test]
+async fn rule_time_windows() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let name = format!("下班后-{}", uuid::Uuid::new_v4().simple());
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.push(RuleConfig {
+        name: name.clone(),
+        rooms: vec![roomid.clone()],
+        actions: vec![RuleAction::Reply {
+            text: "已下班，明天回复".to_string(),
+        }],
+        windows: vec![RuleWindow {
+            start: "18:00".to_string(),
+            end: "09:00".to_string(),
+            weekdays: vec![1, 2, 3, 4, 5],
+        }],
+        ..Default::default()
+    });
+    // 2024-01-01 是周一
+    for (at, expected) in [
+        ("2024-01-01 20:00:00", true),
+        ("2024-01-02 08:00:00", true),
+        ("2024-01-01 12:00:00", false),
+        ("2024-01-06 20:00:00", false),
+        ("2024-01-01 08:00:00", false),
+    ] {
+        let body = json!({ "content": "在吗", "sender": "wxid_mock_alice", "roomid": roomid, "at": at });
+        let traces = app.post("/rules/test", body).await.ok();
+        let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == name.as_str()).cloned().unwrap();
+        assert_eq!(trace["matched"], expected, "{} {}", at, trace);
+    }
+}
+
+#[tokio::test]
+async fn rule_condition_expressions() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let name = format!("帮助-{}", uuid::Uuid::new_v4().simple());
+    let condition = serde_json::from_value(json!({
+        "all": [
+            { "room": [roomid] },
+            { "any": [{ "is_at_me": true }, { "regex": "^(?P<cmd>帮助|help)$" }] },
+            { "not": { "sender": ["wxid_mock_bot"] } }
+        ]
+    }))
+    .unwrap();
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.push(RuleConfig {
+        name: name.clone(),
+        actions: vec![RuleAction::Reply {
+            text: "有什么可以帮你".to_string(),
+        }],
+        condition: Some(condition),
+        ..Default::default()
+    });
+    for (content, sender, extra, expected) in [
+        ("帮助", "wxid_mock_alice", json!({}), true),
+        ("在吗", "wxid_mock_alice", json!({ "at_me": true }), true),
+        ("在吗", "wxid_mock_alice", json!({}), false),
+        ("帮助", "wxid_mock_bot", json!({}), false),
+        // 没有 type 或媒体条件时只处理文本消息
+        ("帮助", "wxid_mock_alice", json!({ "type": 3 }), false),
+    ] {
+        let mut body = json!({ "content": content, "sender": sender, "roomid": roomid });
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        let traces = app.post("/rules/test", body.clone()).await.ok();
+        let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == name.as_str()).cloned().unwrap();
+        assert_eq!(trace["matched"], expected, "{} {}", body, trace);
+        if content == "帮助" && expected {
+            assert_eq!(trace["vars"]["cmd"], "帮助");
+        }
+    }
+
+    // 真实消息按 atuserlist 判断是否 @ 了自己
+    let msg = WxMsg {
+        id: 25,
+        xml: format!("<msgsource><atuserlist>{}</atuserlist></msgsource>", SELF_WXID),
+        ..text_msg(&roomid, "@机器人\u{2005}在吗")
+    };
+    let wechat = app.wechat.clone();
+    assert_eq!(tokio::task::spawn_blocking(move || rule_service::handle(&wechat, &msg)).await.unwrap(), 1);
+    let outbox = app.sim.outbox();
+    assert!(outbox.iter().any(|r| matches!(&r.msg, Some(ReqMsg::Txt(msg)) if msg.receiver == roomid && msg.msg == "有什么可以帮你")));
+}
+
+#[tokio::test]
+async fn rule_media_conditions() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let rule = |name: &str, reply: &str, condition: serde_json::Value| RuleConfig {
+        name: format!("{}-{}", name, suffix),
+        actions: vec![RuleAction::Reply { text: reply.to_string() }],
+        condition: Some(serde_json::from_value(condition).unwrap()),
+        ..Default::default()
+    };
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.extend([
+        rule(
+            "文件",
+            "收到 {extension} 文件，{size} 字节",
+            json!({ "all": [{ "room": [roomid] }, { "extension": ["pdf", ".DOCX"] }, { "size": { "max": 1048576 } }] }),
+        ),
+        rule("长视频", "视频 {duration} 秒", json!({ "all": [{ "room": [roomid] }, { "duration": { "min": 60 } }] })),
+        rule("图片", "收到图片", json!({ "all": [{ "room": [roomid] }, { "is_image": true }] })),
+    ]);
+    let file = |name: &str, size: u64| {
+        format!(
+            "<msg><appmsg><title>{}</title><type>6</type><appattach><totallen>{}</totallen></appattach></appmsg></msg>",
+            name, size
+        )
+    };
+    let video = |secs: u64| format!("<msg><videomsg length=\"40960\" playlength=\"{}\" /></msg>", secs);
+    for (kind, content, rule, expected) in [
+        (49, file("报告.pdf", 2048), "文件", Some("收到 pdf 文件，2048 字节")),
+        (49, file("方案.docx", 4096), "文件", Some("收到 docx 文件，4096 字节")),
+        (49, file("报告.pdf", 5 * 1048576), "文件", None),
+        (49, file("setup.exe", 2048), "文件", None),
+        (43, video(75), "长视频", Some("视频 75 秒")),
+        (43, video(10), "长视频", None),
+        (3, "<msg><img length=\"12345\" /></msg>".to_string(), "图片", Some("收到图片")),
+        (43, video(75), "图片", None),
+    ] {
+        let body = json!({ "content": content, "sender": "wxid_mock_alice", "roomid": roomid, "type": kind });
+        let traces = app.post("/rules/test", body.clone()).await.ok();
+        let name = format!("{}-{}", rule, suffix);
+        let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == name.as_str()).cloned().unwrap();
+        match expected {
+            Some(reply) => assert_eq!(trace["actions"][0]["target"], reply, "{} {}", body, trace),
+            None => assert_eq!(trace["matched"], false, "{} {}", body, trace),
+        }
+    }
+
+    // 超出大小时说明原因
+    let body = json!({ "content": file("报告.pdf", 5 * 1048576), "sender": "wxid_mock_alice", "roomid": roomid, "type": 49 });
+    let traces = app.post("/rules/test", body).await.ok();
+    let name = format!("文件-{}", suffix);
+    let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == name.as_str()).cloned().unwrap();
+    assert!(trace["reason"].as_str().unwrap().contains("大小"), "{}", trace);
+}
+
+#[tokio::test]
+async fn rule_vars() {
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let name = format!("常见问题-{}", uuid::Uuid::new_v4().simple());
+    let counter = format!("asked_{}", roomid);
+    GLOBAL.get().unwrap().wechat_config.write().unwrap().rules.push(RuleConfig {
+        name: name.clone(),
+        rooms: vec![roomid.clone()],
+        pattern: "^怎么报销$".to_string(),
+        actions: vec![
+            RuleAction::Incr {
+                var: "asked_{roomid}".to_string(),
+                by: 1,
+            },
+            RuleAction::Reply {
+                text: "这个问题已经被问了 {var.asked_{roomid}} 次".to_string(),
+            },
+            RuleAction::Set {
+                var: "last_asker_{roomid}".to_string(),
+                value: "{sender}".to_string(),
+            },
+        ],
+        when: vec![VarCondition {
+            var: "asked_{roomid}".to_string(),
+            op: CompareOp::Lt,
+            value: "2".to_string(),
+        }],
+        ..Default::default()
+    });
+    let msg = WxMsg {
+        id: 24,
+        ..text_msg(&roomid, "怎么报销")
+    };
+    let run = || {
+        let (wechat, msg) = (app.wechat.clone(), msg.clone());
+        tokio::task::spawn_blocking(move || rule_service::handle(&wechat, &msg))
+    };
+    // 次数小于 2 时才回复
+    assert_eq!(run().await.unwrap(), 1);
+    assert_eq!(run().await.unwrap(), 1);
+    assert_eq!(run().await.unwrap(), 0);
+
+    let outbox = app.sim.outbox();
+    let replies: Vec<_> = outbox
+        .iter()
+        .filter_map(|r| match &r.msg {
+            Some(ReqMsg::Txt(msg)) if msg.receiver == roomid => Some(msg.msg.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(replies, ["这个问题已经被问了 1 次", "这个问题已经被问了 2 次"]);
+
+    let vars = app.get("/rules/vars").await.ok();
+    let value = |var: &str| vars.as_array().unwrap().iter().find(|v| v["name"] == var).map(|v| v["value"].clone());
+    assert_eq!(value(&counter), Some(json!("2")));
+    assert_eq!(value(&format!("last_asker_{}", roomid)), Some(json!("wxid_mock_alice")));
+
+    let body = json!({ "content": "怎么报销", "sender": "wxid_mock_alice", "roomid": roomid });
+    let traces = app.post("/rules/test", body).await.ok();
+    let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == name.as_str()).cloned().unwrap();
+    assert!(trace["reason"].as_str().unwrap().contains(&counter), "{}", trace);
+
+    // 删除后重新计数
+    let rsp = app.send(warp::test::request().method("DELETE").path(&format!("/rules/vars/{}", urlencoding::encode(&counter)))).await;
+    assert_eq!(rsp.ok(), true);
+    let body = json!({ "content": "怎么报销", "sender": "wxid_mock_alice", "roomid": roomid });
+    let traces = app.post("/rules/test", body).await.ok();
+    let trace = traces.as_array().unwrap().iter().find(|t| t["rule"] == name.as_str()).cloned().unwrap();
+    assert_eq!(trace["actions"][0]["body"], "1");
+    assert_eq!(trace["actions"][1]["target"], "这个问题已经被问了 1 次");
+}
+
+#[tokio::test]
+async fn rule_script_action() {
+    let dir = std::env::temp_dir().join("wcf-test").join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.with_file_name("outside.sh"), "#!/bin/sh\n").unwrap();
+    let mut config = RuleScriptConfig {
+        enabled: false,
+        dir: dir.to_string_lossy().to_string(),
+    };
+    let action = |script: &str| ScriptAction {
+        script: script.to_string(),
+        args: vec!["--stats".to_string()],
+        reply: true,
+        timeout_secs: 5,
+    };
+    let msg = WxMsg {
+        id: 21,
+        ..text_msg(ROOM_ID, "磁盘")
+    };
+    assert!(rule_service::exec(&config, &action("ops.sh"), &msg).unwrap_err().contains("没有开启"));
+    config.enabled = true;
+    // 不能跳出脚本目录
+    assert!(rule_service::exec(&config, &action("../outside.sh"), &msg).unwrap_err().contains("不在"));
+    assert!(rule_service::resolve_script(&config.dir, "missing.sh").is_err());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let script = dir.join("ops.sh");
+        std::fs::write(&script, "#!/bin/sh\necho \"$1 $(cat) $WCF_SENDER\"\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let output = rule_service::exec(&config, &action("ops.sh"), &msg).unwrap();
+        assert_eq!(output.as_deref(), Some("--stats 磁盘 wxid_mock_alice"));
+
+        std::fs::write(&script, "#!/bin/sh\nsleep 5\n").unwrap();
+        let slow = ScriptAction {
+            timeout_secs: 1,
+            ..action("ops.sh")
+        };
+        assert!(rule_service::exec(&config, &slow, &msg).unwrap_err().contains("已终止"));
+    }
+}
+
+#[tokio::test]
+async fn name_history() {
+    let app = TestApp::new();
+    let wxid = format!("wxid_{}", uuid::Uuid::new_v4().simple());
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    app.sim.add_contact(&wxid, "旧名字");
+    app.sim.add_room(&roomid, &[SELF_WXID, wxid.as_str()]);
+    let (snapshot, changes) = contact_monitor_service::scan(&app.wechat, None).unwrap();
+    assert!(changes.iter().all(|c| c.wxid != wxid));
+
+    app.sim.add_contact(&wxid, "新名字");
+    app.wechat.lock().unwrap().invalidate_room(&roomid);
+    let (_, changes) = contact_monitor_service::scan(&app.wechat, Some(&snapshot)).unwrap();
+    let mut renamed: Vec<_> = changes
+        .iter()
+        .filter(|c| c.wxid == wxid && c.kind == ContactChangeKind::Renamed)
+        .map(|c| (c.roomid.clone(), c.old_name.clone(), c.name.clone()))
+        .collect();
+    renamed.sort();
+    let old = Some("旧名字".to_string());
+    assert_eq!(renamed, vec![(None, old.clone(), "新名字".to_string()), (Some(roomid.clone()), old, "新名字".to_string())]);
+
+    let names = app.get(&format!("/contact/{}/names", wxid)).await.ok();
+    let values: Vec<_> = names
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|r| r["field"] == "name")
+        .map(|r| r["value"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(values, vec!["旧名字", "新名字"]);
+    assert!(names.as_array().unwrap().iter().any(|r| r["field"] == "avatar"));
+}
+
+#[tokio::test]
+async fn import_history() {
+    let app = TestApp::new();
+    enable_message_store();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let csv = format!(
+        "\u{feff}CreateTime,StrTalker,IsSender,Sender,StrContent\n\
+         1600000000,{room},0,wxid_mock_alice,你好\n\
+         yesterday,{room},0,wxid_mock_alice,坏行\n\
+         2020-09-13 20:26:41,{room},1,,\"收到, 谢谢\"\n",
+        room = roomid
+    );
+    let import = || warp::test::request().method("POST").path("/admin/import?format=csv").body(csv.clone());
+    let report = app.send(import()).await.ok();
+    assert_eq!(report["parsed"], 2);
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["skipped"], json!([3]));
+    // 重复导入不会写入重复记录
+    assert_eq!(app.send(import()).await.ok()["imported"], 0);
+
+    let missing = warp::test::request().method("POST").path("/admin/import?format=csv").body("time,content\n1,hi\n");
+    assert!(app.send(missing).await.err().contains("roomid"));
+
+    // 本地文件只能从 data/imports 导入
+    let name = format!("{}.csv", uuid::Uuid::new_v4().simple());
+    std::fs::create_dir_all("data/imports").unwrap();
+    std::fs::write(format!("data/imports/{}", name), &csv).unwrap();
+    std::fs::write("data/outside.csv", &csv).unwrap();
+    let from_file = |file: &str| {
+        let path = format!("/admin/import?format=csv&file={}", file);
+        warp::test::request().method("POST").path(&path).body("")
+    };
+    let report = app.send(from_file(&name)).await.ok();
+    assert_eq!(report["parsed"], 2);
+    assert_eq!(report["imported"], 0);
+    assert!(app.send(from_file("../outside.csv")).await.err().contains("不在导入目录"));
+    let report = app.post("/admin/purge", json!({ "roomid": roomid })).await.ok();
+    assert_eq!(report["messages"], 2);
+}
+
+#[tokio::test]
+async fn backfill() {
+    let app = TestApp::new();
+    enable_message_store();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    let now = chrono::Local::now().timestamp() as u32;
+    for (id, ts) in [(1u64, now), (2, now - 60), (3, now - 10 * 86400)] {
+        app.sim.add_history(WxMsg {
+            id: rand::random::<u32>() as u64 * 10 + id,
+            ts,
+            ..text_msg(&roomid, &format!("历史消息 {}", id))
+        });
+    }
+
+    let job = app.post("/admin/backfill?days=3", json!({})).await.ok();
+    let path = format!("/admin/jobs/{}", job["id"]);
+    let mut job = job;
+    for _ in 0..50 {
+        if job["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        job = app.get(&path).await.ok();
+    }
+    assert_eq!(job["state"], "done", "{}", job);
+    assert_eq!(job["total"], 2);
+    assert_eq!(job["done"], 2);
+    assert!(app.get("/admin/jobs").await.ok().as_array().unwrap().iter().any(|j| j["id"] == job["id"]));
+
+    let report = app.post("/admin/purge", json!({ "roomid": roomid })).await.ok();
+    assert_eq!(report["messages"], 2);
+}
+
+#[tokio::test]
+async fn backup() {
+    let app = TestApp::new();
+    let _targets = override_config(
+        |c| &mut c.backup.targets,
+        vec![BackupTarget::Webdav {
+            url: "http://127.0.0.1:9/backups".to_string(),
+            username: String::new(),
+            password: Default::default(),
+        }],
+    );
+    let report = app.post("/admin/backup", json!({})).await.ok();
+    let archive = PathBuf::from(report["archive"].as_str().unwrap());
+    assert!(archive.exists());
+    assert!(report["size"].as_u64().unwrap() > 0);
+    assert_eq!(report["targets"][0]["ok"], false);
+    assert!(report["targets"][0]["error"].is_string());
+}
+
+#[tokio::test]
+async fn db_poll() {
+    let app = TestApp::new();
+    let now = chrono::Local::now().timestamp() as u32;
+    let base = rand::random::<u32>() as u64 * 10;
+    for (id, ts) in [(1u64, now - 60), (2, now - 30), (3, now)] {
+        app.sim.add_history(WxMsg {
+            id: base + id,
+            ts,
+            ..text_msg("wxid_mock_alice", &format!("漏收的消息 {}", id))
+        });
+    }
+    // 第 2 条已经从回调收到
+    assert!(dedup::first_seen(base + 2));
+
+    let mut cursors = db_poll_service::Cursors::new();
+    assert_eq!(db_poll_service::poll_once(&app.wechat, &mut cursors, now - 120, now - 20), Ok(1));
+    assert_eq!(db_poll_service::poll_once(&app.wechat, &mut cursors, now - 120, now - 20), Ok(0));
+    // 等待期过后补发第 3 条，回调再送达时被去重
+    assert_eq!(db_poll_service::poll_once(&app.wechat, &mut cursors, now - 120, now), Ok(1));
+    assert!(!dedup::first_seen(base + 3));
+}
+
+#[tokio::test]
+async fn sql_snapshot() {
+    let app = TestApp::new();
+    {
+        let mut config = GLOBAL.get().unwrap().wechat_config.write().unwrap();
+        config.sql_snapshot.enabled = true;
+        if config.sql_snapshot.dir.is_empty() {
+            let dir = std::env::temp_dir().join("wcf-test").join(uuid::Uuid::new_v4().to_string());
+            config.sql_snapshot.dir = dir.to_string_lossy().to_string();
+        }
+    }
+    assert!(app.post("/admin/snapshot?db=../config.json5", json!({})).await.err().contains("无效"));
+
+    let mut job = app.post("/admin/snapshot?db=MicroMsg.db", json!({})).await.ok();
+    for _ in 0..50 {
+        if job["state"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        job = app.get(&format!("/admin/jobs/{}", job["id"])).await.ok();
+    }
+    assert_eq!(job["state"], "done", "{}", job);
+    assert_eq!(job["done"], 4);
+    let snapshots = app.get("/admin/snapshots").await.ok();
+    assert!(snapshots.as_array().unwrap().iter().any(|s| s["db"] == "MicroMsg.db"));
+
+    // 模拟环境的 RPC 查询忽略条件返回全部联系人，快照上按 SQL 执行
+    let sql = "SELECT NickName, VerifyFlag FROM Contact WHERE UserName = 'wxid_mock_alice'";
+    let rows = app.post("/sql", json!({ "db": "MicroMsg.db", "sql": sql })).await.ok();
+    assert_eq!(rows, json!([{ "NickName": "Alice", "VerifyFlag": 0 }]));
+    let err = app.post("/sql", json!({ "db": "MicroMsg.db", "sql": "DELETE FROM Contact" })).await.err();
+    assert!(err.contains("readonly"), "{}", err);
+}
+
+#[tokio::test]
+async fn room_heatmap() {
+    use chrono::{TimeZone, Timelike};
+
+    let app = TestApp::new();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    store_message(&roomid, "wxid_mock_alice");
+    store_message(&roomid, "wxid_mock_alice");
+    store_message(&roomid, "wxid_mock_bob");
+    let hour = chrono::Local.timestamp_opt(1_600_000_000, 0).unwrap().hour() as usize;
+
+    let path = format!("/stats/rooms/{}/heatmap", roomid);
+    let heatmap = app.get(&path).await.ok();
+    assert_eq!(heatmap["total"], 3);
+    assert_eq!(heatmap["hours"][hour], 3);
+    assert_eq!(heatmap["members"][0]["wxid"], "wxid_mock_alice");
+    assert_eq!(heatmap["members"][0]["hours"][hour], 2);
+    assert_eq!(heatmap["members"][1]["total"], 1);
+
+    let heatmap = app.get(&format!("{}?from=1600000001", path)).await.ok();
+    assert_eq!(heatmap["total"], 0);
+    assert!(app.get(&format!("{}?to=yesterday", path)).await.err().contains("时间格式"));
+}
+
+#[tokio::test]
+async fn word_cloud() {
+    let app = TestApp::new();
+    enable_message_store();
+    let roomid = format!("{}@chatroom", uuid::Uuid::new_v4().simple());
+    for content in ["Rust 真好用[微笑]", "rust 发布了 https://rust-lang.org @Alice", "我们"] {
+        let msg = WxMsg {
+            id: rand::random::<u32>() as u64,
+            ts: 1_600_000_000,
+            ..text_msg(&roomid, content)
+        };
+        GLOBAL.get().unwrap().message_store_service.lock().unwrap().insert(&msg).unwrap();
+    }
+
+    let path = format!("/stats/rooms/{}/wordcloud", roomid);
+    assert_eq!(app.get(&path).await.ok()["messages"], 0);
+    let cloud = app.get(&format!("{}?period=all&limit=5", path)).await.ok();
+    assert_eq!(cloud["messages"], 3);
+    assert_eq!(cloud["words"][0], json!({ "word": "rust", "count": 2 }));
+    let words: Vec<&str> = cloud["words"].as_array().unwrap().iter().map(|w| w["word"].as_str().unwrap()).collect();
+    assert!(words.len() <= 5);
+    for noise in ["微笑", "alice", "https", "我们"] {
+        assert!(!words.contains(&noise), "{:?}", words);
+    }
+
+    // 测试环境没有中文字体，只检查出错时的提示
+    let _font = override_config(|c| &mut c.word_cloud.font, "not-exists.ttf".to_string());
+    let rsp = app.get(&format!("/stats/rooms/{}/wordcloud.png?period=all", roomid)).await;
+    rsp.expect_status(StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(String::from_utf8_lossy(&rsp.body).contains("not-exists.ttf"));
+    let rsp = app.get(&format!("/stats/rooms/{}/wordcloud.png", roomid)).await;
+    assert!(String::from_utf8_lossy(&rsp.body).contains("没有可绘制的词"));
+}
+
+#[tokio::test]
+async fn download_failures() {
+    let app = TestApp::new();
+    app.get("/download-image?id=1&extra=C:/not/exists.dat&dir=C:/&timeout=1")
+        .await
+        .expect_status(StatusCode::INTERNAL_SERVER_ERROR);
+    app.get("/download-file?id=1&extra=C:/not/exists.dat&thumb=")
+        .await
+        .expect_status(StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn resolve_media() {
+    let app = TestApp::new();
+    let file = temp_file("report.pdf", b"%PDF");
+    app.sim.add_media(42, 49, &file.to_string_lossy(), "");
+
+    let reply = app.get("/resolve-media?id=42").await;
+    reply.expect_status(StatusCode::OK);
+    assert_eq!(reply.content_type, "application/pdf");
+    assert_eq!(&reply.body[..], b"%PDF");
+
+    app.get("/resolve-media?id=404").await.expect_status(StatusCode::NOT_FOUND);
+    app.get("/thumbnail?id=404").await.expect_status(StatusCode::NOT_FOUND);
+    app.get("/video-preview?id=404").await.expect_status(StatusCode::NOT_FOUND);
+    app.get("/video-preview?id=42").await.expect_status(StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn emotion_not_cached() {
+    let app = TestApp::new();
+    app.get("/emotion/0123456789abcdef0123456789abcdef")
+        .await
+        .expect_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn check_friend_status() {
+    let app = TestApp::new();
+    let error = app
+        .post("/check-friend-status", json!({ "roomid": ROOM_ID, "wxids": ["wxid_mock_alice"] }))
+        .await
+        .err();
+    assert!(error.contains("confirm"));
+
+    let report = app
+        .post(
+            "/check-friend-status",
+            json!({ "roomid": ROOM_ID, "wxids": ["wxid_mock_alice", "wxid_stranger"], "confirm": true }),
+        )
+        .await
+        .ok();
+    assert_eq!(report["results"][0]["state"], "normal");
+    assert_eq!(report["results"][1]["state"], "not_in_contacts");
+}
+
+#[tokio::test]
+async fn risk_budget() {
+    let app = TestApp::new();
+    let budgets = app.get("/risk-budget").await.ok();
+    assert_eq!(budgets.as_array().unwrap().len(), 7);
+}
+
+#[tokio::test]
+async fn replay() {
+    let app = TestApp::new();
+    let ndjson = [
+        json!({ "is_self": false, "is_group": false, "id": 1, "type": 1, "ts": 0, "roomid": "wxid_mock_alice",
+                "content": "hi", "sender": "wxid_mock_alice", "sign": "", "thumb": "", "extra": "", "xml": "" })
+        .to_string(),
+        "not json".to_string(),
+        json!({ "schema_version": 2, "message": { "is_self": true, "is_group": false, "id": 2, "type": 1, "ts": 0,
+                "roomid": "wxid_mock_alice", "content": "hello", "sender": SELF_WXID, "sign": "", "thumb": "",
+                "extra": "", "xml": "" } })
+        .to_string(),
+    ]
+    .join("\n");
+    let report = app
+        .send(warp::test::request().method("POST").path("/admin/replay").body(ndjson))
+        .await
+        .ok();
+    assert_eq!(report["replayed"], 2);
+    assert_eq!(report["skipped"], json!([2]));
+
+    let error = app
+        .send(warp::test::request().method("POST").path("/admin/replay").body(""))
+        .await
+        .err();
+    assert!(error.contains("没有可回放的消息"));
+
+    // 只能读取消息记录目录中的文件
+    std::fs::create_dir_all("data/tap").unwrap();
+    std::fs::write("data/outside.ndjson", "").unwrap();
+    let error = app
+        .send(warp::test::request().method("POST").path("/admin/replay?file=../outside.ndjson").body(""))
+        .await
+        .err();
+    assert!(error.contains("不在消息记录目录"), "{}", error);
+}
+
+#[tokio::test]
+async fn logs() {
+    let app = TestApp::new();
+    crate::utils::log_buffer::push(chrono::Local::now(), log::Level::Warn, "route-test-marker".to_string());
+    let entries = app.get("/admin/logs?level=warn&grep=route-test-marker").await.ok();
+    assert_eq!(entries[0]["message"], "route-test-marker");
+
+    assert!(app.get("/admin/logs?level=loud").await.err().contains("level"));
+    assert!(app.get("/admin/logs?since=yesterday").await.err().contains("since"));
+}
+
+#[tokio::test]
+async fn sdk_version() {
+    let app = TestApp::new();
+    let overview = app.get("/admin/sdk-version").await.ok();
+    assert!(overview["versions"].as_array().unwrap().iter().any(|v| v["version"] == overview["active"]));
+
+    let err = app.post("/admin/sdk-version", json!({ "version": "../evil" })).await.err();
+    assert!(err.contains("不合法"), "{}", err);
+    let err = app.post("/admin/sdk-version", json!({ "version": "1..2" })).await.err();
+    assert!(err.contains("不合法"), "{}", err);
+    // 没有校验值时不下载
+    let err = app.post("/admin/sdk-version", json!({ "version": "1.2.3" })).await.err();
+    assert!(err.contains("sha256"), "{}", err);
+}
+
+#[tokio::test]
+async fn install_wechat_rejects_bad_version() {
+    let app = TestApp::new();
+    let err = app.post("/admin/install-wechat", json!({ "version": "3.9;calc" })).await.err();
+    assert!(err.contains("不合法"), "{}", err);
+    let err = app.post("/admin/install-wechat", json!({ "version": "3.9.12.51" })).await.err();
+    assert!(err.contains("sha256"), "{}", err);
+}
+
+#[tokio::test]
+async fn client_version() {
+    let app = TestApp::new();
+    let version = app.get("/admin/version").await.ok();
+    assert_eq!(version["current"], env!("CARGO_PKG_VERSION"));
+    assert!(version["latest"].is_null());
+    assert!(app.post("/admin/update", json!({})).await.err().contains("更新源"));
+}
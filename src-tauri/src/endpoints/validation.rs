@@ -0,0 +1,458 @@
+//! 请求体校验：限制请求体大小，检查必填字段和格式，逐字段返回错误信息。
+
+use quickxml_to_serde::{xml_string_to_json, Config};
+use serde::{de::DeserializeOwned, Serialize};
+use utoipa::ToSchema;
+use warp::{filters::BoxedFilter, Filter};
+
+use super::{parse_since, Announce, AtAll, ContactCard, EmojiMsg, FriendCheck, Image, Location, MarkdownMsg, MentionQuery, NewAnnouncement, NewBatch, NewBridgeIdentity, NewDistribution, NewIdentity, NewPoll, NewRaffle, NewTemplate, NewThreadLink, PermissionChange, RuleTest, SaveFile, SendTemplate, SendText};
+use crate::service::bridge_identity_service;
+use crate::utils::wxid;
+use crate::wcferry::wcf::{
+    AudioMsg, DbQuery, ForwardMsg, MemberMgmt, PatMsg, PathMsg, RichText, TextMsg, Transfer, Verification, XmlMsg,
+};
+
+/// 请求体默认上限
+pub const DEFAULT_BODY_LIMIT: u64 = 1024 * 1024;
+
+/// Markdown 的字数上限，再长的内容渲染出来也看不清
+const MAX_MARKDOWN_CHARS: usize = 20000;
+
+// 需要单独设置上限的接口
+const BODY_LIMITS: &[(&str, u64)] = &[("sql", 64 * 1024), ("admin/config/import", 16 * 1024 * 1024)];
+
+pub fn body_limit(path: &str) -> u64 {
+    BODY_LIMITS
+        .iter()
+        .find(|(p, _)| *p == path)
+        .map_or(DEFAULT_BODY_LIMIT, |(_, limit)| *limit)
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct InvalidFields(pub Vec<FieldError>);
+
+impl InvalidFields {
+    /// 拼成一句话放在 error 中
+    pub fn summary(&self) -> String {
+        self.0
+            .iter()
+            .map(|e| format!("{} {}", e.field, e.message))
+            .collect::<Vec<_>>()
+            .join("；")
+    }
+}
+
+impl warp::reject::Reject for InvalidFields {}
+
+/// 收集校验错误
+#[derive(Default)]
+pub struct Errors(Vec<FieldError>);
+
+impl Errors {
+    pub fn push(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    pub fn required(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.push(field, "不能为空");
+        }
+    }
+
+    pub fn check(&mut self, field: &str, result: Result<(), String>) {
+        if let Err(e) = result {
+            self.push(field, e);
+        }
+    }
+
+    /// 任意会话 id
+    pub fn receiver(&mut self, field: &str, value: &str) {
+        self.check(field, wxid::check_id(value).map(|_| ()));
+    }
+
+    pub fn roomid(&mut self, field: &str, value: &str) {
+        self.check(field, wxid::check_room(value));
+    }
+
+    /// 个人 wxid
+    pub fn user(&mut self, field: &str, value: &str) {
+        self.check(field, wxid::check_user(value));
+    }
+
+    /// 逗号分隔的多个个人 wxid
+    pub fn users(&mut self, field: &str, value: &str) {
+        self.check(field, wxid::check_users(value));
+    }
+
+    pub fn id(&mut self, field: &str, value: u64) {
+        if value == 0 {
+            self.push(field, "不能为 0");
+        }
+    }
+}
+
+/// 请求体的字段校验
+pub trait Validate {
+    fn validate(&self, errors: &mut Errors);
+}
+
+/// 限制大小后解析 JSON 请求体并校验
+pub fn json_body<T>(path: &'static str) -> BoxedFilter<(T,)>
+where
+    T: DeserializeOwned + Validate + Send + 'static,
+{
+    warp::body::content_length_limit(body_limit(path))
+        .and(warp::body::json())
+        .and_then(|body: T| async move {
+            let mut errors = Errors::default();
+            body.validate(&mut errors);
+            if errors.0.is_empty() {
+                Ok(body)
+            } else {
+                Err(warp::reject::custom(InvalidFields(errors.0)))
+            }
+        })
+        .boxed()
+}
+
+impl Validate for TextMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.receiver("receiver", &self.receiver);
+        errors.required("msg", &self.msg);
+        if !self.aters.is_empty() && self.aters != "notify@all" {
+            errors.users("aters", &self.aters);
+        }
+    }
+}
+
+impl Validate for SendText {
+    fn validate(&self, errors: &mut Errors) {
+        self.text.validate(errors);
+        if !self.mentions.is_empty() && !self.text.receiver.ends_with("@chatroom") {
+            errors.push("mentions", "只有群消息可以 @ 成员");
+        }
+    }
+}
+
+impl Validate for NewTemplate {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("name", &self.name);
+        if self.name.contains('/') {
+            errors.push("name", "不能包含 /");
+        }
+        match &self.rich_text {
+            Some(rich) => {
+                errors.required("rich_text.title", &rich.title);
+                errors.required("rich_text.url", &rich.url);
+            }
+            None => errors.required("text", &self.text),
+        }
+    }
+}
+
+impl Validate for SendTemplate {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("name", &self.name);
+        errors.receiver("receiver", &self.receiver);
+        if !self.mentions.is_empty() && !self.receiver.ends_with("@chatroom") {
+            errors.push("mentions", "只有群消息可以 @ 成员");
+        }
+        for (name, value) in &self.vars {
+            if value.is_array() || value.is_object() {
+                errors.push(&format!("vars.{}", name), "应为字符串、数字或布尔值");
+            }
+        }
+    }
+}
+
+impl Validate for MarkdownMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("markdown", &self.markdown);
+        if self.markdown.chars().count() > MAX_MARKDOWN_CHARS {
+            errors.push("markdown", format!("不能超过 {} 字", MAX_MARKDOWN_CHARS));
+        }
+        errors.receiver("receiver", &self.receiver);
+        if let Some(width) = self.width {
+            if !(200..=2000).contains(&width) {
+                errors.push("width", "应在 200 到 2000 之间");
+            }
+        }
+    }
+}
+
+impl Validate for ContactCard {
+    fn validate(&self, errors: &mut Errors) {
+        errors.user("wxid", &self.wxid);
+        errors.receiver("receiver", &self.receiver);
+    }
+}
+
+impl Validate for Location {
+    fn validate(&self, errors: &mut Errors) {
+        errors.receiver("receiver", &self.receiver);
+        errors.required("label", &self.label);
+        if !(-90.0..=90.0).contains(&self.lat) {
+            errors.push("lat", "应在 -90 到 90 之间");
+        }
+        if !(-180.0..=180.0).contains(&self.lng) {
+            errors.push("lng", "应在 -180 到 180 之间");
+        }
+    }
+}
+
+impl Validate for XmlMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.receiver("receiver", &self.receiver);
+        errors.required("content", &self.content);
+        if !self.content.trim().is_empty()
+            && xml_string_to_json(self.content.clone(), &Config::new_with_defaults()).is_err()
+        {
+            errors.push("content", "不是有效的 xml");
+        }
+        if self.r#type == 0 {
+            errors.push("type", "不能为 0");
+        }
+    }
+}
+
+impl Validate for PathMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.receiver("receiver", &self.receiver);
+        if self.path.trim().is_empty() && self.base64.is_empty() {
+            errors.push("path", "path 和 base64 至少填一个");
+        }
+    }
+}
+
+impl Validate for RichText {
+    fn validate(&self, errors: &mut Errors) {
+        errors.receiver("receiver", &self.receiver);
+        errors.required("title", &self.title);
+        errors.required("url", &self.url);
+    }
+}
+
+impl Validate for PatMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        errors.user("wxid", &self.wxid);
+    }
+}
+
+impl Validate for ForwardMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.id("id", self.id);
+        errors.receiver("receiver", &self.receiver);
+    }
+}
+
+impl Validate for AudioMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.id("id", self.id);
+        errors.required("dir", &self.dir);
+    }
+}
+
+impl Validate for Image {
+    fn validate(&self, errors: &mut Errors) {
+        errors.id("id", self.id);
+        errors.required("dir", &self.dir);
+    }
+}
+
+impl Validate for SaveFile {
+    fn validate(&self, errors: &mut Errors) {
+        errors.id("id", self.id);
+    }
+}
+
+impl Validate for Transfer {
+    fn validate(&self, errors: &mut Errors) {
+        errors.user("wxid", &self.wxid);
+        errors.required("tfid", &self.tfid);
+        errors.required("taid", &self.taid);
+    }
+}
+
+impl Validate for DbQuery {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("db", &self.db);
+        errors.required("sql", &self.sql);
+    }
+}
+
+impl Validate for Verification {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("v3", &self.v3);
+        errors.required("v4", &self.v4);
+    }
+}
+
+impl Validate for MemberMgmt {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        errors.users("wxids", &self.wxids);
+    }
+}
+
+impl Validate for FriendCheck {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        if self.wxids.is_empty() {
+            errors.push("wxids", "不能为空");
+        }
+        for wxid in &self.wxids {
+            errors.user("wxids", wxid);
+        }
+    }
+}
+
+impl Validate for MentionQuery {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+    }
+}
+
+impl Validate for PermissionChange {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+    }
+}
+
+impl Validate for NewAnnouncement {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        errors.required("content", &self.content);
+    }
+}
+
+impl Validate for Announce {
+    fn validate(&self, errors: &mut Errors) {
+        if self.rooms.is_empty() {
+            errors.push("rooms", "不能为空");
+        }
+        for roomid in &self.rooms {
+            errors.roomid("rooms", roomid);
+        }
+        errors.required("content", &self.content);
+    }
+}
+
+impl Validate for AtAll {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        errors.required("content", &self.content);
+        if let Some(send_at) = self.send_at.as_deref().filter(|t| !t.is_empty()) {
+            if parse_since(send_at).is_none() {
+                errors.push("send_at", "应为 unix 秒或 YYYY-MM-DD HH:MM:SS");
+            }
+        }
+    }
+}
+
+impl Validate for NewDistribution {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("path", &self.path);
+        if self.rooms.is_empty() {
+            errors.push("rooms", "不能为空");
+        }
+        for roomid in &self.rooms {
+            errors.roomid("rooms", roomid);
+        }
+    }
+}
+
+impl Validate for NewBatch {
+    fn validate(&self, errors: &mut Errors) {
+        if self.messages.is_empty() {
+            errors.push("messages", "不能为空");
+        }
+        for (i, text) in self.messages.iter().enumerate() {
+            errors.receiver(&format!("messages[{}].receiver", i), &text.receiver);
+            errors.required(&format!("messages[{}].msg", i), &text.msg);
+            if text.aters == "notify@all" {
+                // @所有人 需要检查权限和单独的预算，请使用 /at-all
+                errors.push(&format!("messages[{}].aters", i), "批量发送不支持 @所有人");
+            } else if !text.aters.is_empty() {
+                errors.users(&format!("messages[{}].aters", i), &text.aters);
+            }
+        }
+    }
+}
+
+impl Validate for RuleTest {
+    fn validate(&self, errors: &mut Errors) {
+        errors.required("content", &self.content);
+        errors.user("sender", &self.sender);
+        if !self.roomid.trim().is_empty() {
+            errors.roomid("roomid", &self.roomid);
+        }
+        if let Some(at) = self.at.as_deref().filter(|a| !a.is_empty()) {
+            if parse_since(at).is_none() {
+                errors.push("at", "应为 unix 秒或 YYYY-MM-DD HH:MM:SS");
+            }
+        }
+    }
+}
+
+impl Validate for EmojiMsg {
+    fn validate(&self, errors: &mut Errors) {
+        errors.receiver("receiver", &self.receiver);
+        if self.path.trim().is_empty() && self.md5.trim().is_empty() {
+            errors.push("path", "path 和 md5 至少填一个");
+        }
+    }
+}
+
+impl Validate for NewIdentity {
+    fn validate(&self, errors: &mut Errors) {
+        errors.user("canonical", &self.canonical);
+        for alias in &self.aliases {
+            errors.user("aliases", alias);
+        }
+    }
+}
+
+impl Validate for NewBridgeIdentity {
+    fn validate(&self, errors: &mut Errors) {
+        if !bridge_identity_service::PLATFORMS.contains(&self.platform.trim().to_lowercase().as_str()) {
+            errors.push("platform", format!("应为 {}", bridge_identity_service::PLATFORMS.join("、")));
+        }
+        errors.user("wxid", &self.wxid);
+    }
+}
+
+impl Validate for NewThreadLink {
+    fn validate(&self, errors: &mut Errors) {
+        if !bridge_identity_service::PLATFORMS.contains(&self.platform.trim().to_lowercase().as_str()) {
+            errors.push("platform", format!("应为 {}", bridge_identity_service::PLATFORMS.join("、")));
+        }
+        errors.id("msg_id", self.msg_id);
+        errors.receiver("roomid", &self.roomid);
+        errors.required("chat", &self.chat);
+        errors.required("external_id", &self.external_id);
+    }
+}
+
+impl Validate for NewPoll {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        errors.required("question", &self.question);
+    }
+}
+
+impl Validate for NewRaffle {
+    fn validate(&self, errors: &mut Errors) {
+        errors.roomid("roomid", &self.roomid);
+        errors.required("keyword", &self.keyword);
+    }
+}
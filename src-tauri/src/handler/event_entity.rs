@@ -1,15 +1,75 @@
 use async_trait::async_trait;
+use serde::Serialize;
 
 use crate::wcferry::wcf;
 
 #[derive(Clone)]
 pub enum Event {
     ClientMessage(wcf::WxMsg),
+    ContactChanged(Vec<ContactChange>),
+    SessionKicked(SessionKick),
     StartUp(),
     Shutdown(),
 }
 
+/// 联系人变化类型
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactChangeKind {
+    Added,
+    Removed,
+    Renamed,
+    /// 更换了头像
+    Avatar,
+}
+
+/// 联系人变化事件
+#[derive(Clone, Debug, Serialize)]
+pub struct ContactChange {
+    pub kind: ContactChangeKind,
+    pub wxid: String,
+    /// 当前昵称，删除时为最后一次记录的昵称
+    pub name: String,
+    /// 当前备注
+    pub remark: String,
+    /// 改名前的昵称、备注或群昵称
+    pub old_name: Option<String>,
+    /// 群昵称变化时为所在的群
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roomid: Option<String>,
+    /// 头像变化时为新旧头像地址
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_avatar: Option<String>,
+}
+
+/// 账号下线原因
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionKickKind {
+    /// 在其他设备上登录
+    ElsewhereLogin,
+    /// 被强制退出
+    ForcedLogout,
+}
+
+/// 账号被挤下线或强制退出的系统通知
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionKick {
+    pub kind: SessionKickKind,
+    /// 通知原文
+    pub content: String,
+    pub ts: u32,
+}
+
 #[async_trait]
 pub trait EventHandler {
     async fn handle(&mut self, event: Event);
-}
\ No newline at end of file
+
+    /// 处理器名称，用于统计
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+}
@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::global_service::GLOBAL,
+};
+
+/// 记录群成员对通知的确认回复
+pub struct AnnouncementMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for AnnouncementMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let global = GLOBAL.get().unwrap();
+            global.announcement_service.lock().unwrap().record(msg);
+        }
+    }
+}
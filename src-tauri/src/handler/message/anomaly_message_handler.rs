@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::global_service::GLOBAL,
+};
+
+/// 统计每个会话的消息量
+pub struct AnomalyMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for AnomalyMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let global = GLOBAL.get().unwrap();
+            global.anomaly_service.lock().unwrap().record(msg);
+        }
+    }
+}
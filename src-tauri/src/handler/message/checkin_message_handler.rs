@@ -0,0 +1,24 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::global_service::GLOBAL,
+};
+
+/// 记录群里的打卡消息
+pub struct CheckinMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for CheckinMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let global = GLOBAL.get().unwrap();
+            let config = global.wechat_config.read().unwrap().checkin.clone();
+            if global.checkin_service.lock().unwrap().record(msg, &config) {
+                log::debug!("[{}] {} 在 {} 打卡", self.id, msg.sender, msg.roomid);
+            }
+        }
+    }
+}
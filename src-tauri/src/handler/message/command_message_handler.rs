@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::{command_service, global_service::GLOBAL},
+};
+
+/// 识别以前缀开头的文本消息并执行对应的群聊指令
+pub struct CommandMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for CommandMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            // 自己发的消息不当作指令，避免回复再次触发指令
+            if msg.is_self || msg.r#type != 1 {
+                return;
+            }
+            let global = GLOBAL.get().unwrap();
+            let config = global.wechat_config.read().unwrap().command.clone();
+            if !config.enabled || config.prefix.is_empty() || !msg.content.trim().starts_with(&config.prefix) {
+                return;
+            }
+            let wechat = match global.wechat_service.lock().unwrap().wechat.clone() {
+                Some(wechat) => wechat,
+                None => return,
+            };
+            log::debug!("[{}] 收到指令：{}", self.id, msg.content);
+            let msg = msg.clone();
+            tokio::task::spawn_blocking(move || command_service::dispatch(wechat, msg, &config.prefix));
+        }
+    }
+}
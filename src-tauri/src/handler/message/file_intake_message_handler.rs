@@ -0,0 +1,36 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::{file_intake_service, global_service::GLOBAL},
+};
+
+/// 收到匹配规则的文件时转存到外部存储并回复链接
+pub struct FileIntakeMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for FileIntakeMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let rule = match file_intake_service::find_rule(msg) {
+                Some(rule) => rule,
+                None => return,
+            };
+            let global = GLOBAL.get().unwrap();
+            let wechat = match global.wechat_service.lock().unwrap().wechat.clone() {
+                Some(wechat) => wechat,
+                None => return,
+            };
+            log::debug!("[{}] 转存文件：{}", self.id, msg.extra);
+            let msg = msg.clone();
+            // 下载大文件较慢，不阻塞后续消息
+            tokio::spawn(async move {
+                if let Err(e) = file_intake_service::intake(&wechat, &msg, rule).await {
+                    log::warn!("转存 {} 发来的文件失败: {}", msg.sender, e);
+                }
+            });
+        }
+    }
+}
@@ -1,12 +1,22 @@
 use async_trait::async_trait;
 
 use crate::{
-    handler::event_entity::{Event, EventHandler},
-    service::global_service::GLOBAL,
+    handler::{
+        event_entity::{Event, EventHandler},
+        message::payload::build_payload,
+    },
+    service::{
+        admin_notify_service::{self, Incident},
+        dnd_service,
+        global_service::GLOBAL,
+    },
+    utils::{contact, pipeline, redact},
+    wcferry::wcf::WxMsg,
+    wechat_config::OfficialMode,
 };
 
 use regex::Regex;
-use serde_json::json;
+use serde_json::{json, Value};
 
 /// 配置 http 回调地址后，将调用设置的url，
 pub struct HttpMessageHandler {
@@ -18,14 +28,19 @@ impl EventHandler for HttpMessageHandler {
     async fn handle(&mut self, event: Event) {
         if let Event::ClientMessage(ref msg) = event {
             let global = GLOBAL.get().unwrap();
-            let (cburl, msg_filter_regexp) = {
+            let (msg_filter_regexp, payload_version) = {
                 let config = global.wechat_config.read().unwrap();
-                (config.cburl.clone(), config.msg_filter_regexp.clone())
+                (config.msg_filter_regexp.clone(), config.payload_versions.http)
             };
+            let cburl = callback_urls(msg);
             if cburl.is_empty() {
                 log::debug!("未配置回调地址，跳过处理");
                 return;
             }
+            if dnd_service::suppressed("http", msg) {
+                log::debug!("会话 {} 已设置免打扰，跳过转发", msg.roomid);
+                return;
+            }
             // 仅对文本消息做过滤，其他消息也默认转发，如好友消息，红包消息，链接消息等
             if msg.r#type == 1 {
                 if let Some(ref regex_str) = msg_filter_regexp {
@@ -38,24 +53,66 @@ impl EventHandler for HttpMessageHandler {
                     log::debug!("未配置正则过滤，所有消息转发")
                 }
             }
-            for url in cburl {
-                log::debug!("http服务 {} 回调地址为: {:?}", self.id, url.clone());
-                if !url.starts_with("http") {
-                    log::error!("http 转发消息失败，回调地址不合法");
-                    continue;
-                }
+            let msg = redact::redact_msg(msg);
+            let payload = build_payload(&msg, payload_version).await;
+            self.post_to_callbacks(cburl, payload);
+        }
+        if let Event::ContactChanged(ref changes) = event {
+            let global = GLOBAL.get().unwrap();
+            let cburl = global.wechat_config.read().unwrap().cburl.clone();
+            self.post_to_callbacks(cburl, json!({"event": "contact_changed", "changes": changes}));
+        }
+        if let Event::SessionKicked(ref kick) = event {
+            let global = GLOBAL.get().unwrap();
+            let cburl = global.wechat_config.read().unwrap().cburl.clone();
+            self.post_to_callbacks(cburl, json!({"event": "session_kicked", "kick": kick}));
+        }
+    }
+}
 
-                let res = ureq::post(&url).send_json(json!(&msg));
-                match res {
-                    Ok(rsp) => {
-                        if rsp.status() != 200 {
-                            log::error!("转发消息失败，状态码: {}", rsp.status());
-                        }
-                        log::debug!("{}", rsp.into_string().unwrap());
-                    }
-                    Err(e) => {
-                        log::error!("转发消息失败：{}", e);
-                    }
+/// 消息的回调地址，公众号和服务通知按 official 配置丢弃或改推到单独的地址
+pub fn callback_urls(msg: &WxMsg) -> Vec<String> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap();
+    if !contact::is_official_msg(msg) {
+        return config.cburl.clone();
+    }
+    match config.official.mode {
+        OfficialMode::Forward => config.cburl.clone(),
+        OfficialMode::Drop => vec![],
+        OfficialMode::Route => config.official.cburl.clone(),
+    }
+}
+
+impl HttpMessageHandler {
+    fn post_to_callbacks(&self, cburl: Vec<String>, payload: Value) {
+        for url in cburl {
+            log::debug!("http服务 {} 回调地址为: {:?}", self.id, url.clone());
+            if !url.starts_with("http") {
+                log::error!("http 转发消息失败，回调地址不合法");
+                continue;
+            }
+
+            // 4xx、5xx 由 ureq 作为错误返回，这里还要排除 3xx 等非 2xx 的响应
+            let res = ureq::post(&url).send_json(payload.clone()).map_err(|e| e.to_string()).and_then(|rsp| {
+                if (200..300).contains(&rsp.status()) {
+                    Ok(rsp)
+                } else {
+                    Err(format!("状态码 {}", rsp.status()))
+                }
+            });
+            match res {
+                Ok(rsp) => {
+                    pipeline::delivered("http", true);
+                    admin_notify_service::sink_recovered("http");
+                    log::debug!("{}", rsp.into_string().unwrap_or_default());
+                }
+                Err(e) => {
+                    log::error!("转发消息失败：{}", e);
+                    pipeline::delivered("http", false);
+                    admin_notify_service::report(Incident::SinkFailure {
+                        sink: "http".to_string(),
+                        error: format!("{}: {}", url, e),
+                    });
                 }
             }
         }
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 
-use crate::{handler::event_entity::{Event, EventHandler}, service::global_service::GLOBAL};
+use crate::{handler::event_entity::{Event, EventHandler}, service::global_service::GLOBAL, utils::redact};
 
 /// 日志打印
 pub struct LogMessageHandler {
@@ -15,7 +15,7 @@ impl EventHandler for LogMessageHandler {
             let wechat_config = global.wechat_config.read().unwrap();
             let show = wechat_config.front_msg_show.clone();
             if show {
-                log::info!("日志处理器 {} -- 接收到信息: {:?}", self.id, msg);
+                log::info!("日志处理器 {} -- 接收到信息: {:?}", self.id, redact::redact_msg(msg));
             }
         }
     }
@@ -2,4 +2,18 @@ pub mod log_message_handler;
 pub mod console_message_handler;
 pub mod http_message_handler;
 pub mod socketio_message_handler;
-pub mod event_message_handler;
\ No newline at end of file
+pub mod event_message_handler;
+pub mod payload;
+pub mod tap_message_handler;
+pub mod command_message_handler;
+pub mod poll_message_handler;
+pub mod announcement_message_handler;
+pub mod file_intake_message_handler;
+pub mod receipt_message_handler;
+pub mod pat_message_handler;
+pub mod rule_message_handler;
+pub mod checkin_message_handler;
+pub mod raffle_message_handler;
+pub mod anomaly_message_handler;
+pub mod session_message_handler;
+pub mod store_message_handler;
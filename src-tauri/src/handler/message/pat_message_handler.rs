@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::{global_service::GLOBAL, pat_service},
+};
+
+/// 系统消息的类型，拍一拍属于此类
+const MSG_TYPE_SYSMSG: u32 = 10002;
+
+/// 被拍一拍时拍回去或回复
+pub struct PatMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for PatMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            if msg.r#type != MSG_TYPE_SYSMSG || !msg.content.contains("type=\"pat\"") {
+                return;
+            }
+            let global = GLOBAL.get().unwrap();
+            let wechat = match global.wechat_service.lock().unwrap().wechat.clone() {
+                Some(wechat) => wechat,
+                None => return,
+            };
+            log::debug!("[{}] 拍一拍：{}", self.id, msg.id);
+            let msg = msg.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = pat_service::handle(&wechat, &msg) {
+                    log::warn!("响应拍一拍失败: {}", e);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use prost::Message;
+use quickxml_to_serde::{xml_string_to_json, Config};
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use crate::{
+    service::media_service,
+    utils::compression,
+    wcferry::wcf,
+    wechat_config::{PayloadVersion, StreamEncoding},
+};
+
+/// v1 格式的推送消息
+#[derive(Serialize, ToSchema)]
+pub struct MessagePayloadV1 {
+    /// 原始消息字段
+    #[serde(flatten)]
+    pub message: wcf::WxMsg,
+    /// 表情等媒体的本地访问地址，解析不出时没有这个字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_url: Option<String>,
+    /// 固定为 1
+    pub schema_version: u8,
+}
+
+/// v2 格式的推送消息
+#[derive(Serialize, ToSchema)]
+pub struct MessagePayloadV2 {
+    /// 固定为 2
+    pub schema_version: u8,
+    /// 原始消息
+    pub message: wcf::WxMsg,
+    /// 解析后的扩展信息
+    pub enrichment: Enrichment,
+}
+
+#[derive(Serialize, ToSchema, Default)]
+pub struct Enrichment {
+    /// 表情等媒体的本地访问地址
+    pub media_url: Option<String>,
+    /// 消息中 @ 的 wxid 列表
+    pub at_user_list: Vec<String>,
+    /// 引用回复时被引用消息的 id
+    pub quoted_msg_id: Option<u64>,
+}
+
+/// 构建推送给回调、socketIO 等下游的消息内容
+///
+/// v1 与加版本号之前的格式相同，原始字段加上 media_url，另外增加 schema_version
+pub async fn build_payload(msg: &wcf::WxMsg, version: PayloadVersion) -> Value {
+    match version {
+        PayloadVersion::V1 => json!(MessagePayloadV1 {
+            message: msg.clone(),
+            media_url: media_service::resolve_emotion_url(msg).await,
+            schema_version: 1,
+        }),
+        PayloadVersion::V2 => json!(MessagePayloadV2 {
+            schema_version: 2,
+            message: msg.clone(),
+            enrichment: Enrichment {
+                media_url: media_service::resolve_emotion_url(msg).await,
+                at_user_list: parse_at_user_list(&msg.xml),
+                quoted_msg_id: parse_quoted_msg_id(msg),
+            },
+        }),
+    }
+}
+
+/// 编码后的推送内容
+pub enum EncodedPayload {
+    Json(Value),
+    Binary(Vec<u8>),
+}
+
+/// 按下游配置的编码格式编码消息
+pub fn encode_payload(
+    msg: &wcf::WxMsg,
+    payload: Value,
+    encoding: StreamEncoding,
+) -> Result<EncodedPayload, String> {
+    match encoding {
+        StreamEncoding::Json => Ok(EncodedPayload::Json(payload)),
+        StreamEncoding::Protobuf => Ok(EncodedPayload::Binary(msg.encode_to_vec())),
+        StreamEncoding::Msgpack => rmp_serde::to_vec_named(&payload)
+            .map(EncodedPayload::Binary)
+            .map_err(|e| format!("MessagePack 编码失败: {}", e)),
+    }
+}
+
+/// 以 zlib 压缩推送内容，json 按 UTF-8 文本压缩，结果均为二进制帧
+pub fn compress_payload(encoded: EncodedPayload) -> Result<EncodedPayload, String> {
+    let bytes = match encoded {
+        EncodedPayload::Json(value) => value.to_string().into_bytes(),
+        EncodedPayload::Binary(bytes) => bytes,
+    };
+    compression::deflate(&bytes)
+        .map(EncodedPayload::Binary)
+        .map_err(|e| format!("压缩失败: {}", e))
+}
+
+/// 解析消息 xml 中 msgsource.atuserlist
+pub fn parse_at_user_list(xml: &str) -> Vec<String> {
+    if xml.is_empty() {
+        return vec![];
+    }
+    let json = match xml_string_to_json(xml.to_string(), &Config::new_with_defaults()) {
+        Ok(json) => json,
+        Err(_) => return vec![],
+    };
+    json.get("msgsource")
+        .and_then(|source| source.get("atuserlist"))
+        .and_then(|list| list.as_str())
+        .map(|list| {
+            list.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 引用回复的消息内容中 refermsg.svrid 为被引用消息的 id
+pub fn parse_quoted_msg_id(msg: &wcf::WxMsg) -> Option<u64> {
+    if msg.r#type != 49 || !msg.content.contains("<refermsg>") {
+        return None;
+    }
+    let json = xml_string_to_json(msg.content.clone(), &Config::new_with_defaults()).ok()?;
+    let svrid = json.get("msg")?.get("appmsg")?.get("refermsg")?.get("svrid")?;
+    svrid.as_u64().or_else(|| svrid.as_str()?.trim().parse().ok())
+}
+
+/// 图片、语音、视频和文件消息 xml 中的媒体信息，没有的项为空
+#[derive(Debug, Default, PartialEq)]
+pub struct MediaInfo {
+    /// 文件的扩展名，小写，不带点
+    pub extension: Option<String>,
+    /// 大小，单位字节
+    pub size: Option<u64>,
+    /// 语音、视频的时长，单位秒
+    pub duration: Option<u64>,
+}
+
+fn xml_number(value: Option<&Value>) -> Option<u64> {
+    let value = value?;
+    value.as_u64().or_else(|| value.as_str()?.trim().parse().ok())
+}
+
+// 纯数字的文本会被解析成数字，空元素会被解析成对象
+fn xml_text(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+pub fn parse_media_info(msg: &wcf::WxMsg) -> MediaInfo {
+    let json = match xml_string_to_json(msg.content.clone(), &Config::new_with_defaults()) {
+        Ok(json) => json,
+        Err(_) => return MediaInfo::default(),
+    };
+    let node = |name: &str| json.get("msg").and_then(|m| m.get(name));
+    match msg.r#type {
+        3 => MediaInfo {
+            size: xml_number(node("img").and_then(|i| i.get("@length"))),
+            ..Default::default()
+        },
+        34 => MediaInfo {
+            size: xml_number(node("voicemsg").and_then(|v| v.get("@length"))),
+            // voicelength 的单位是毫秒
+            duration: xml_number(node("voicemsg").and_then(|v| v.get("@voicelength"))).map(|ms| (ms + 500) / 1000),
+            ..Default::default()
+        },
+        43 => MediaInfo {
+            size: xml_number(node("videomsg").and_then(|v| v.get("@length"))),
+            duration: xml_number(node("videomsg").and_then(|v| v.get("@playlength"))),
+            ..Default::default()
+        },
+        // appmsg 的 type 为 6 时是文件，卡片、链接等没有附件
+        49 if xml_number(node("appmsg").and_then(|a| a.get("type"))) == Some(6) => {
+            let appmsg = node("appmsg");
+            let attach = appmsg.and_then(|a| a.get("appattach"));
+            let title = xml_text(appmsg.and_then(|a| a.get("title")));
+            // 没有 fileext 时从文件名取
+            let extension = xml_text(attach.and_then(|a| a.get("fileext")))
+                .or_else(|| Some(std::path::Path::new(&title?).extension()?.to_string_lossy().to_string()));
+            MediaInfo {
+                extension: extension.map(|e| e.trim_start_matches('.').to_lowercase()),
+                size: xml_number(attach.and_then(|a| a.get("totallen"))),
+                duration: None,
+            }
+        }
+        _ => MediaInfo::default(),
+    }
+}
+
+/// 推送消息的 JSON Schema，由上面的类型生成
+pub fn message_json_schema() -> Value {
+    let mut defs = serde_json::Map::new();
+    for (name, schema) in [
+        wcf::WxMsg::schema(),
+        Enrichment::schema(),
+        MessagePayloadV1::schema(),
+        MessagePayloadV2::schema(),
+    ] {
+        defs.insert(name.to_string(), json!(schema));
+    }
+    let doc = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": "/schemas/message.json",
+        "title": "WCF 推送消息",
+        "description": "http 回调、socketIO 推送的消息，按 schema_version 区分格式",
+        "oneOf": [
+            { "$ref": "#/$defs/MessagePayloadV1" },
+            { "$ref": "#/$defs/MessagePayloadV2" }
+        ],
+        "$defs": defs,
+    });
+    // utoipa 生成的是 OpenAPI 引用，改写为独立文档内的引用
+    let text = doc.to_string().replace("#/components/schemas/", "#/$defs/");
+    serde_json::from_str(&text).unwrap_or(doc)
+}
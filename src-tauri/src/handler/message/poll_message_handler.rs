@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::global_service::GLOBAL,
+};
+
+/// 把群里的回复计入进行中的投票
+pub struct PollMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for PollMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let global = GLOBAL.get().unwrap();
+            global.poll_service.lock().unwrap().record(msg);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::global_service::GLOBAL,
+};
+
+/// 把发送抽奖关键词的群成员加入报名名单
+pub struct RaffleMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for RaffleMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let global = GLOBAL.get().unwrap();
+            global.raffle_service.lock().unwrap().record(msg);
+        }
+    }
+}
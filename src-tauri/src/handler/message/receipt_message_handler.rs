@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::{global_service::GLOBAL, receipt_service},
+};
+
+/// 识别指定联系人发来的票据照片
+pub struct ReceiptMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for ReceiptMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            if !receipt_service::wanted(msg) {
+                return;
+            }
+            let global = GLOBAL.get().unwrap();
+            let wechat = match global.wechat_service.lock().unwrap().wechat.clone() {
+                Some(wechat) => wechat,
+                None => return,
+            };
+            log::debug!("[{}] 识别票据：{}", self.id, msg.id);
+            let msg = msg.clone();
+            tokio::spawn(async move {
+                if let Err(e) = receipt_service::process(&wechat, &msg).await {
+                    log::warn!("识别 {} 发来的票据失败: {}", msg.sender, e);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::{global_service::GLOBAL, rule_service},
+};
+
+/// 按配置的规则处理消息，没有 type 或媒体条件的规则只处理文本消息
+pub struct RuleMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for RuleMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            if msg.is_self {
+                return;
+            }
+            let global = GLOBAL.get().unwrap();
+            if global.wechat_config.read().unwrap().rules.is_empty() {
+                return;
+            }
+            let wechat = match global.wechat_service.lock().unwrap().wechat.clone() {
+                Some(wechat) => wechat,
+                None => return,
+            };
+            log::debug!("[{}] 规则：{}", self.id, msg.id);
+            let msg = msg.clone();
+            tokio::task::spawn_blocking(move || rule_service::handle(&wechat, &msg));
+        }
+    }
+}
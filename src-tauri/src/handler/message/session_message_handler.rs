@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use tauri::Emitter;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::{
+        admin_notify_service::{self, Incident},
+        global_service::{APP_HANDLE, GLOBAL},
+    },
+    utils::event_log::{self, EventLevel},
+};
+
+/// 账号被挤下线后通知管理员，按配置刷新登录二维码推送到界面
+pub struct SessionMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for SessionMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::SessionKicked(ref kick) = event {
+            log::warn!("[{}] 账号已下线({:?}): {}", self.id, kick.kind, kick.content);
+            admin_notify_service::report(Incident::SessionKicked(kick.content.clone()));
+            event_log::write(EventLevel::Warning, &format!("账号已下线: {}", kick.content));
+
+            let global = GLOBAL.get().unwrap();
+            if !global.wechat_config.read().unwrap().session.relogin {
+                return;
+            }
+            let wechat = match global.wechat_service.lock().unwrap().wechat.clone() {
+                Some(wechat) => wechat,
+                None => return,
+            };
+            let qrcode = tokio::task::spawn_blocking(move || wechat.lock().unwrap().refresh_qrcode().map_err(|e| e.to_string()))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+            match qrcode {
+                Ok(url) => {
+                    log::info!("请扫码重新登录: {}", url);
+                    if let Some(handle) = APP_HANDLE.get() {
+                        let _ = handle.emit("login-qrcode", url);
+                    }
+                }
+                Err(e) => log::error!("刷新登录二维码失败: {}", e),
+            }
+        }
+    }
+}
@@ -1,6 +1,5 @@
 use async_trait::async_trait;
-use serde_json::json;
-use crate::{handler::event_entity::{Event, EventHandler}, service::global_service::GLOBAL};
+use crate::{handler::{event_entity::{Event, EventHandler}, message::payload::{build_payload, compress_payload, encode_payload, EncodedPayload}}, service::{dnd_service, global_service::GLOBAL}, utils::{contact, redact}, wechat_config::OfficialMode};
 
 // 控制台日志打印
 pub struct SocketIOMessageHandler {
@@ -11,10 +10,49 @@ pub struct SocketIOMessageHandler {
 impl EventHandler for SocketIOMessageHandler {
     async fn handle(&mut self, event: Event) {
         if let Event::ClientMessage(ref msg) = event {
+            let official = GLOBAL.get().unwrap().wechat_config.read().unwrap().official.mode;
+            if official != OfficialMode::Forward && contact::is_official_msg(msg) {
+                log::debug!("公众号消息不推送到 socketIO: {}", msg.sender);
+                return;
+            }
+            if dnd_service::suppressed("socketio", msg) {
+                log::debug!("会话 {} 已设置免打扰，跳过推送", msg.roomid);
+                return;
+            }
             let global = GLOBAL.get().unwrap();
+            let (payload_version, encoding, compress) = {
+                let config = global.wechat_config.read().unwrap();
+                (config.payload_versions.socketio, config.socketio_encoding, config.socketio_compress)
+            };
+            let msg = redact::redact_msg(msg);
+            let payload = build_payload(&msg, payload_version).await;
+            let encoded = match encode_payload(&msg, payload, encoding).and_then(|encoded| {
+                if compress { compress_payload(encoded) } else { Ok(encoded) }
+            }) {
+                Ok(encoded) => encoded,
+                Err(e) => {
+                    log::error!("socketIO 消息编码失败: {}", e);
+                    return;
+                }
+            };
             let socket_arc = global.socketio_service.clone();
             let mut client  = socket_arc.lock().unwrap();
-            client.send_msg_to_server(json!(msg));
+            match encoded {
+                EncodedPayload::Json(value) => client.send_msg_to_server(value),
+                EncodedPayload::Binary(bytes) => client.send_msg_to_server(bytes),
+            }
+        }
+        if let Event::ContactChanged(ref changes) = event {
+            let global = GLOBAL.get().unwrap();
+            let socket_arc = global.socketio_service.clone();
+            let mut client  = socket_arc.lock().unwrap();
+            client.send_event_to_server("CONTACT", serde_json::json!(changes));
+        }
+        if let Event::SessionKicked(ref kick) = event {
+            let global = GLOBAL.get().unwrap();
+            let socket_arc = global.socketio_service.clone();
+            let mut client  = socket_arc.lock().unwrap();
+            client.send_event_to_server("SESSION", serde_json::json!(kick));
         }
     }
 }
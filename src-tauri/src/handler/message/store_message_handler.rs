@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+
+use crate::{handler::event_entity::{Event, EventHandler}, service::global_service::GLOBAL};
+
+/// 写入本地消息库
+pub struct StoreMessageHandler {
+    pub id: String,
+}
+
+#[async_trait]
+impl EventHandler for StoreMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let global = GLOBAL.get().unwrap();
+            let mut store = global.message_store_service.lock().unwrap();
+            if let Err(e) = store.insert(msg) {
+                log::warn!("消息处理器 {} -- {}", self.id, e);
+            }
+        }
+    }
+}
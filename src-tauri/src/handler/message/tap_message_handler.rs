@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::{
+    handler::event_entity::{Event, EventHandler},
+    service::global_service::GLOBAL,
+    utils::{pipeline, redact, rotating_file::RotatingFile},
+    wechat_config::TapConfig,
+};
+
+/// 把收到的每条原始消息追加写入 NDJSON 文件，相当于黑匣子，可配合 /admin/replay 回放
+pub struct TapMessageHandler {
+    pub id: String,
+    writer: Option<(TapConfig, RotatingFile)>,
+}
+
+impl TapMessageHandler {
+    pub fn new(id: String) -> Self {
+        TapMessageHandler { id, writer: None }
+    }
+}
+
+pub fn tap_dir(config: &TapConfig) -> PathBuf {
+    if config.dir.is_empty() {
+        PathBuf::from(".").join("data").join("tap")
+    } else {
+        PathBuf::from(&config.dir)
+    }
+}
+
+#[async_trait]
+impl EventHandler for TapMessageHandler {
+    async fn handle(&mut self, event: Event) {
+        if let Event::ClientMessage(ref msg) = event {
+            let config = {
+                let global = GLOBAL.get().unwrap();
+                let wechat_config = global.wechat_config.read().unwrap();
+                wechat_config.tap.clone()
+            };
+            if !config.enabled {
+                self.writer = None;
+                return;
+            }
+            // 配置变化后重新打开文件
+            if self.writer.as_ref().map_or(true, |(c, _)| *c != config) {
+                let writer = RotatingFile::new(
+                    tap_dir(&config),
+                    "messages",
+                    config.max_size_mb * 1024 * 1024,
+                    config.retention_days,
+                );
+                self.writer = Some((config, writer));
+            }
+            let line = match serde_json::to_string(&*redact::redact_msg(msg)) {
+                Ok(line) => line,
+                Err(e) => {
+                    log::warn!("消息序列化失败: {}", e);
+                    return;
+                }
+            };
+            if let Some((_, writer)) = self.writer.as_mut() {
+                let written = writer.write_line(&line);
+                pipeline::delivered("tap", written.is_ok());
+                if let Err(e) = written {
+                    log::warn!("写入消息记录失败: {}", e);
+                }
+            }
+        }
+    }
+}
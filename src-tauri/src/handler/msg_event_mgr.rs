@@ -2,6 +2,8 @@ use std::sync::{Arc, Mutex};
 
 use tokio::{sync::broadcast, task};
 
+use crate::utils::pipeline;
+
 use super::event_entity::{Event, EventHandler};
 
 pub struct MsgEventBus {
@@ -18,16 +20,18 @@ impl MsgEventBus {
     pub fn subscribe(&mut self, mut handler: Box<dyn EventHandler + Send + Sync>) {
          let broadcast = self.broadcaster.lock().unwrap();
          let mut rx = broadcast.subscribe();
+         let name = handler.name();
          task::spawn(async move {
             loop {
               match rx.recv().await {
                   Ok(msg) => {
+                    pipeline::handled(name, rx.len());
                     handler.handle(msg).await
                   },
                   Err(broadcast::error::RecvError::Closed) => break,
                   Err(broadcast::error::RecvError::Lagged(msg)) => {
                     println!("客户端丢失了消息: {:?}", msg);
-                    () 
+                    pipeline::lagged(name, msg);
                   },
               }
             }
@@ -3,7 +3,21 @@ use std::sync::{Arc, Mutex};
 use async_trait::async_trait;
 use log::info;
 
-use crate::{handler::event_entity::{Event, EventHandler},  service::global_service::GLOBAL, wcferry::WeChat};
+use crate::{handler::event_entity::{Event, EventHandler},  service::{global_service::GLOBAL, preflight_service}, wcferry::WeChat};
+
+// 配置了 mock 且以 mock feature 编译时使用模拟器
+fn new_wechat() -> WeChat {
+    let mock = GLOBAL.get().unwrap().wechat_config.read().unwrap().mock;
+    #[cfg(feature = "mock")]
+    if mock {
+        return WeChat::new_mock(Arc::new(crate::wcferry::mock::Simulator::new()));
+    }
+    #[cfg(not(feature = "mock"))]
+    if mock {
+        log::warn!("未以 mock feature 编译，忽略 mock 配置");
+    }
+    WeChat::new(true)
+}
 
 // 启动事件发布后，开启对应的所有服务
 // wechat 客户端
@@ -22,11 +36,22 @@ impl EventHandler for HttpServerHandler {
 
             let global = GLOBAL.get().unwrap();
 
+            // 启动自检，缺少 sdk.dll 时无法注入
+            let mock = global.wechat_config.read().unwrap().mock;
+            let mut report = preflight_service::check_environment(mock);
+            if !report.sdk_found {
+                log::error!("启动自检未通过，服务未启动");
+                preflight_service::publish(report);
+                return;
+            }
+
             // 初始化 wechat_client 服务
             let wechat_service_arc = global.wechat_service.clone();
             let mut wechat_service = wechat_service_arc.lock().unwrap();
-            let wechat = Arc::new(Mutex::new(WeChat::new(true)));
+            let wechat = Arc::new(Mutex::new(new_wechat()));
             wechat_service.wechat = Some(wechat.clone());
+            preflight_service::check_injection(&mut report, &wechat);
+            preflight_service::publish(report);
 
             // 初始化 http_server 服务
             let wechat_config = global.wechat_config.read().unwrap();
@@ -36,9 +61,63 @@ impl EventHandler for HttpServerHandler {
             info!("服务启动，监听 http://{}:{}", "0.0.0.0", port);
             info!("浏览器访问 http://localhost:{}/swagger/ 查看文档", port);
 
+            // 初始化命名管道接口
+            if wechat_config.pipe.enabled {
+                let mut pipe_service = global.pipe_service.lock().unwrap();
+                pipe_service.start(wechat.clone(), wechat_config.pipe.name.clone());
+            }
+
             // 初始化 socketio 服务
             let mut socket_service = global.socketio_service.lock().unwrap();
-            socket_service.start(wechat_config.wsurl.clone())
+            socket_service.start(wechat_config.wsurl.clone());
+
+            // 初始化联系人变化检测
+            let mut contact_monitor_service = global.contact_monitor_service.lock().unwrap();
+            contact_monitor_service.start(wechat.clone(), wechat_config.contact_diff_interval);
+
+            // 初始化心跳上报
+            let mut heartbeat_service = global.heartbeat_service.lock().unwrap();
+            heartbeat_service.start(wechat.clone(), wechat_config.heartbeat.url.clone(), wechat_config.heartbeat.interval);
+
+            // 初始化管理员通知
+            let mut admin_notify_service = global.admin_notify_service.lock().unwrap();
+            admin_notify_service.start(wechat.clone());
+
+            // 初始化打卡汇总
+            let mut checkin_service = global.checkin_service.lock().unwrap();
+            checkin_service.start(wechat.clone());
+
+            // 初始化定时 @所有人
+            let mut at_all_service = global.at_all_service.lock().unwrap();
+            at_all_service.start(wechat.clone());
+
+            // 初始化免打扰队列
+            let mut quiet_hours_service = global.quiet_hours_service.lock().unwrap();
+            quiet_hours_service.start(wechat.clone());
+
+            // 初始化消息量异常检测
+            let mut anomaly_service = global.anomaly_service.lock().unwrap();
+            anomaly_service.start(wechat.clone());
+
+            // 初始化消息接收看门狗
+            let mut watchdog_service = global.watchdog_service.lock().unwrap();
+            watchdog_service.start(wechat.clone());
+
+            // 初始化消息库定时清理
+            let mut message_store_service = global.message_store_service.lock().unwrap();
+            message_store_service.start();
+
+            // 初始化定时备份
+            let mut backup_service = global.backup_service.lock().unwrap();
+            backup_service.start();
+
+            // 初始化消息数据库轮询
+            let mut db_poll_service = global.db_poll_service.lock().unwrap();
+            db_poll_service.start(wechat.clone());
+
+            // 初始化定时词云
+            let mut word_cloud_service = global.word_cloud_service.lock().unwrap();
+            word_cloud_service.start(wechat.clone());
         }
         
         if let Event::Shutdown() = event {
@@ -57,9 +136,61 @@ impl EventHandler for HttpServerHandler {
                 }
             }
 
+            // 关闭命名管道接口
+            let mut pipe_service = global.pipe_service.lock().unwrap();
+            pipe_service.stop();
+
             // 关闭 socketio 服务
             let mut socket_service = global.socketio_service.lock().unwrap();
             socket_service.stop();
+
+            // 关闭联系人变化检测
+            let mut contact_monitor_service = global.contact_monitor_service.lock().unwrap();
+            contact_monitor_service.stop();
+
+            // 关闭心跳上报
+            let mut heartbeat_service = global.heartbeat_service.lock().unwrap();
+            heartbeat_service.stop();
+
+            // 关闭管理员通知
+            let mut admin_notify_service = global.admin_notify_service.lock().unwrap();
+            admin_notify_service.stop();
+
+            // 关闭打卡汇总
+            let mut checkin_service = global.checkin_service.lock().unwrap();
+            checkin_service.stop();
+
+            // 关闭定时 @所有人
+            let mut at_all_service = global.at_all_service.lock().unwrap();
+            at_all_service.stop();
+
+            // 关闭免打扰队列
+            let mut quiet_hours_service = global.quiet_hours_service.lock().unwrap();
+            quiet_hours_service.stop();
+
+            // 关闭消息量异常检测
+            let mut anomaly_service = global.anomaly_service.lock().unwrap();
+            anomaly_service.stop();
+
+            // 关闭消息接收看门狗
+            let mut watchdog_service = global.watchdog_service.lock().unwrap();
+            watchdog_service.stop();
+
+            // 关闭消息库定时清理
+            let mut message_store_service = global.message_store_service.lock().unwrap();
+            message_store_service.stop();
+
+            // 关闭定时备份
+            let mut backup_service = global.backup_service.lock().unwrap();
+            backup_service.stop();
+
+            // 关闭消息数据库轮询
+            let mut db_poll_service = global.db_poll_service.lock().unwrap();
+            db_poll_service.stop();
+
+            // 关闭定时词云
+            let mut word_cloud_service = global.word_cloud_service.lock().unwrap();
+            word_cloud_service.stop();
         }
     }
 }
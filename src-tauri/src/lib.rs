@@ -4,15 +4,18 @@ use chrono::Local;
 use handler::event_entity::Event;
 use local_ip_address::local_ip;
 use log::{info, Level, LevelFilter, Log, Metadata, Record};
-use service::global_service::{initialize_global, GLOBAL};
+use service::config_service;
+use service::global_service::{initialize_global, APP_HANDLE, GLOBAL};
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
 use wechat_config::WechatConfig;
-use std::fs::{self, File};
+use std::fs;
 use std::io::Write;
 use std::ptr;
 use std::sync::{Arc, Mutex};
 use tauri::{command, App, AppHandle, Emitter, Manager, Window, WindowEvent};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use utils::{event_log, log_buffer};
 use winapi::{
     shared::winerror::ERROR_ALREADY_EXISTS,
     um::{
@@ -27,9 +30,14 @@ mod wcferry;
 mod service;
 mod wechat_config;
 mod handler;
+mod utils;
+#[cfg(test)]
+mod test_support;
 
 struct FrontendLogger {
     app_handle: tauri::AppHandle,
+    // 按天滚动的日志文件
+    file: Option<Mutex<RollingFileAppender>>,
 }
 
 impl Log for FrontendLogger {
@@ -39,19 +47,32 @@ impl Log for FrontendLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
+            let now = Local::now();
             let msg = format!(
                 "{} [{}] {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                now.format("%Y-%m-%d %H:%M:%S"),
                 record.level(),
                 record.args()
             );
+            if let Some(file) = &self.file {
+                let mut file = file.lock().unwrap();
+                let _ = writeln!(file, "{}", msg);
+            }
+            log_buffer::push(now, record.level(), record.args().to_string());
             self.app_handle.emit("log-message", msg).unwrap();
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
 }
 
+// 保留最近几天的日志文件
+const LOG_KEEP_FILES: usize = 7;
+
 struct AppState {
     http_server_running: bool,
 }
@@ -78,21 +99,7 @@ async fn is_http_server_running(
 fn save_wechat_config(
     config: WechatConfig,
 ) -> Result<bool, String> {
-    // 定义文件路径
-    let file_path = ".\\config.json5";
-
-    // 尝试创建并写入文件
-    let mut file = File::create(&file_path).map_err(|e| e.to_string())?;
-    let json_str = serde_json::to_string(&config).unwrap();
-    file.write_all(json_str.as_bytes())
-        .map_err(|e| e.to_string())?;
-    let global = GLOBAL.get().unwrap();
-    let mut wechat_config_lock = global.wechat_config.write().unwrap();
-    wechat_config_lock.cburl = config.cburl.clone();
-    wechat_config_lock.wsurl = config.wsurl.clone();
-    wechat_config_lock.file_dir = config.file_dir.clone();
-    wechat_config_lock.http_server_port = config.http_server_port.clone();
-    wechat_config_lock.front_msg_show = config.front_msg_show.clone();
+    config_service::save_config(&config)?;
     info!("Wechat configuration update {:?}", serde_json::to_string(&config));
     Ok(true)
 }
@@ -153,6 +160,7 @@ async fn stop_server(state: tauri::State<'_, Arc<Mutex<AppState>>>) -> Result<()
 #[command]
 async fn confirm_exit(app_handle: tauri::AppHandle) {
     let _ = stop_server(app_handle.state()).await;
+    event_log::clean_exit();
     std::process::exit(0);
 }
 
@@ -206,9 +214,23 @@ fn handle_system_windows_event(window: &Window, event: &WindowEvent) {
 }
 
 fn init_log(handle: AppHandle) {
-    log::set_boxed_logger(Box::new(FrontendLogger { app_handle: handle }))
+    let file = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("wcfrust")
+        .filename_suffix("log")
+        .max_log_files(LOG_KEEP_FILES)
+        .build(".\\logs");
+    // 日志文件打不开时仍然输出到界面，等日志初始化后再记下原因
+    let (file, file_error) = match file {
+        Ok(file) => (Some(Mutex::new(file)), None),
+        Err(e) => (None, Some(e)),
+    };
+    log::set_boxed_logger(Box::new(FrontendLogger { app_handle: handle, file }))
         .map(|()| log::set_max_level(LevelFilter::Info))
         .expect("Failed to initialize logger");
+    if let Some(e) = file_error {
+        log::error!("日志文件初始化失败，日志只显示在界面上: {}", e);
+    }
 }
 
 
@@ -237,9 +259,12 @@ pub fn run() {
         // .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_updater::Builder::new().build())
         .setup(|app| {
             init_log(app.app_handle().clone());
+            let _ = APP_HANDLE.set(app.app_handle().clone());
             initialize_global();
+            event_log::init();
             // app.get_window("main").unwrap().open_devtools();
 
             
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use log::{info, warn};
+use tokio::task::JoinHandle;
+
+use crate::{
+    service::{global_service::GLOBAL, moderation_service},
+    utils::event_log::{self, EventLevel},
+    wcferry::{wcf::TextMsg, WeChat},
+};
+
+/// 登录状态检测间隔
+const LOGIN_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 需要通知管理员的异常
+#[derive(Debug, Clone)]
+pub enum Incident {
+    /// 下游推送失败，sink 为 http、socketio 等
+    SinkFailure { sink: String, error: String },
+    /// 微信掉线
+    LoginLost,
+    /// 账号在其他设备登录或被强制退出，内容为系统通知原文
+    SessionKicked(String),
+    /// 风控预算用尽
+    BudgetExceeded(String),
+    /// 会话消息量偏离基线，roomid 为 * 时表示全部会话
+    VolumeAnomaly { roomid: String, count: u64, baseline: f64 },
+    /// 自动发送的内容未通过审核
+    ContentFlagged { receiver: String, reason: String },
+}
+
+impl Incident {
+    // 同一类异常共用节流计时
+    fn key(&self) -> String {
+        match self {
+            Incident::SinkFailure { sink, .. } => format!("sink:{}", sink),
+            Incident::LoginLost | Incident::SessionKicked(_) => "login".to_string(),
+            Incident::BudgetExceeded(_) => "budget".to_string(),
+            Incident::VolumeAnomaly { roomid, .. } => format!("volume:{}", roomid),
+            Incident::ContentFlagged { .. } => "moderation".to_string(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Incident::SinkFailure { sink, error } => format!("{} 推送连续失败: {}", sink, error),
+            Incident::LoginLost => "微信已掉线".to_string(),
+            Incident::SessionKicked(content) => format!("账号已下线: {}", content),
+            Incident::BudgetExceeded(detail) => format!("风控预算用尽: {}", detail),
+            Incident::VolumeAnomaly { roomid, count, baseline } => {
+                let target = if roomid == "*" { "全部会话" } else { roomid.as_str() };
+                let trend = if *count as f64 > *baseline { "暴增" } else { "骤降" };
+                format!("{} 消息量{}: 本时段 {} 条，平时约 {:.1} 条", target, trend, count, baseline)
+            }
+            Incident::ContentFlagged { receiver, reason } => format!("发送到 {} 的内容未通过审核: {}", receiver, reason),
+        }
+    }
+}
+
+/** 把内部异常以微信消息通知管理员，同类异常按配置的间隔节流 */
+pub struct AdminNotifyService {
+    pub handle: Option<JoinHandle<()>>,
+    wechat: Option<Arc<Mutex<WeChat>>>,
+    // 各下游连续失败次数
+    failures: HashMap<String, u32>,
+    last_sent: HashMap<String, Instant>,
+    // 节流期间被合并的次数
+    suppressed: HashMap<String, u32>,
+    // 掉线期间无法发送，登录恢复后补发
+    lost_at: Option<String>,
+    // 收到的下线通知原文
+    lost_reason: Option<String>,
+}
+
+impl AdminNotifyService {
+    pub fn new() -> Self {
+        AdminNotifyService {
+            handle: None,
+            wechat: None,
+            failures: HashMap::new(),
+            last_sent: HashMap::new(),
+            suppressed: HashMap::new(),
+            lost_at: None,
+            lost_reason: None,
+        }
+    }
+
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        self.wechat = Some(wechat.clone());
+        self.handle = Some(tokio::spawn(async move {
+            let mut was_login = true;
+            loop {
+                tokio::time::sleep(LOGIN_CHECK_INTERVAL).await;
+                let wc = wechat.clone();
+                let login = tokio::task::spawn_blocking(move || wc.lock().unwrap().is_login().unwrap_or(false))
+                    .await
+                    .unwrap_or(false);
+                if was_login != login {
+                    if login {
+                        event_log::write(EventLevel::Info, "微信已恢复登录");
+                    } else {
+                        event_log::write(EventLevel::Error, "微信已掉线");
+                    }
+                    let global = GLOBAL.get().unwrap();
+                    let mut service = global.admin_notify_service.lock().unwrap();
+                    if login {
+                        service.login_restored();
+                    } else {
+                        service.report(Incident::LoginLost);
+                    }
+                }
+                was_login = login;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+        self.wechat = None;
+    }
+
+    /// 记录一次异常，达到阈值且不在节流期内时通知管理员
+    pub fn report(&mut self, incident: Incident) {
+        let (admin, throttle, threshold) = {
+            let global = GLOBAL.get().unwrap();
+            let config = global.wechat_config.read().unwrap();
+            let notify = &config.admin_notify;
+            (notify.wxid.clone(), notify.throttle_secs, notify.sink_failure_threshold)
+        };
+        if admin.is_empty() {
+            return;
+        }
+        let key = incident.key();
+        match &incident {
+            Incident::SinkFailure { .. } => {
+                let count = self.failures.entry(key.clone()).or_insert(0);
+                *count += 1;
+                if *count < threshold.max(1) {
+                    return;
+                }
+            }
+            Incident::LoginLost => {
+                // 掉线后无法发消息，记下时间等恢复后再发
+                self.lost_at = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+                warn!("微信已掉线，恢复登录后将通知管理员");
+                return;
+            }
+            Incident::SessionKicked(content) => {
+                self.lost_at.get_or_insert_with(|| Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+                self.lost_reason = Some(content.clone());
+                return;
+            }
+            Incident::BudgetExceeded(_) | Incident::VolumeAnomaly { .. } | Incident::ContentFlagged { .. } => {}
+        }
+
+        let throttled = self
+            .last_sent
+            .get(&key)
+            .map_or(false, |t| t.elapsed() < Duration::from_secs(throttle));
+        if throttled {
+            *self.suppressed.entry(key).or_insert(0) += 1;
+            return;
+        }
+        let mut text = format!("[告警] {}", incident.describe());
+        if let Some(count) = self.suppressed.remove(&key) {
+            text.push_str(&format!("\n期间另有 {} 次同类异常", count));
+        }
+        if let Incident::SinkFailure { .. } = incident {
+            text.push_str(&format!("\n已连续失败 {} 次", self.failures.get(&key).copied().unwrap_or(0)));
+        }
+        self.last_sent.insert(key, Instant::now());
+        self.send(admin, text);
+    }
+
+    /// 下游恢复后清零失败计数
+    pub fn sink_recovered(&mut self, sink: &str) {
+        self.failures.remove(&format!("sink:{}", sink));
+    }
+
+    fn login_restored(&mut self) {
+        if let Some(lost_at) = self.lost_at.take() {
+            let admin = {
+                let global = GLOBAL.get().unwrap();
+                let config = global.wechat_config.read().unwrap();
+                config.admin_notify.wxid.clone()
+            };
+            if admin.is_empty() {
+                return;
+            }
+            let mut text = format!(
+                "[告警] 微信于 {} 掉线，已于 {} 恢复登录",
+                lost_at,
+                Local::now().format("%Y-%m-%d %H:%M:%S")
+            );
+            if let Some(reason) = self.lost_reason.take() {
+                text.push_str(&format!("\n下线原因: {}", reason));
+            }
+            self.send(admin, text);
+        }
+    }
+
+    // 在独立线程中发送，避免与调用方持有的锁互相等待
+    fn send(&self, admin: String, text: String) {
+        let wechat = match &self.wechat {
+            Some(wechat) => wechat.clone(),
+            None => return,
+        };
+        info!("通知管理员: {}", text);
+        std::thread::spawn(move || {
+            let wc = wechat.lock().unwrap().clone();
+            let text = TextMsg {
+                msg: text,
+                receiver: admin,
+                aters: String::new(),
+            };
+            if let Err(e) = moderation_service::send_text(&wc, text) {
+                warn!("通知管理员失败: {}", e);
+            }
+        });
+    }
+}
+
+/// 上报异常
+pub fn report(incident: Incident) {
+    if let Some(global) = GLOBAL.get() {
+        global.admin_notify_service.lock().unwrap().report(incident);
+    }
+}
+
+/// 下游推送成功
+pub fn sink_recovered(sink: &str) {
+    if let Some(global) = GLOBAL.get() {
+        global.admin_notify_service.lock().unwrap().sink_recovered(sink);
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use log::warn;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{at_all_service, quiet_hours_service},
+    utils::pacing,
+    wcferry::{wcf::TextMsg, WeChat},
+};
+
+// 两个群之间的基础间隔，实际间隔由 pacing 加入抖动
+const ROOM_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 一个群的发送结果
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct AnnounceResult {
+    pub roomid: String,
+    /// 替换变量后实际发送的内容
+    pub content: String,
+    /// 是否 @ 了所有人，只有群主或管理员才会 @
+    pub at_all: bool,
+    /// 是否已发出，处于免打扰时段排队时为 false
+    pub sent: bool,
+    pub error: Option<String>,
+}
+
+// 替换 {room}、{admin} 变量
+fn fill(template: &str, room: &str, admin: &str) -> String {
+    template.replace("{room}", room).replace("{admin}", admin)
+}
+
+// 向一个群发送，返回实际内容、是否 @所有人、是否已发出
+fn send_one(
+    wechat: &WeChat,
+    roomid: &str,
+    template: &str,
+    at_all: bool,
+    names: &HashMap<String, String>,
+) -> Result<(String, bool, bool), (String, String)> {
+    let role = match wechat.query_room_role(roomid.to_string()) {
+        Ok(Some(role)) => role,
+        Ok(None) => return Err((String::new(), "群不存在".to_string())),
+        Err(e) => return Err((String::new(), e.to_string())),
+    };
+    let members = wechat.query_room_member(roomid.to_string()).ok().flatten().unwrap_or_default();
+    // 群昵称优先，没有时用联系人昵称
+    let admin = members
+        .iter()
+        .find(|m| m.wxid == role.owner && !m.name.is_empty())
+        .map(|m| m.name.clone())
+        .or_else(|| names.get(&role.owner).cloned())
+        .unwrap_or_else(|| role.owner.clone());
+    let room = names.get(roomid).cloned().unwrap_or_else(|| roomid.to_string());
+    let content = fill(template, &room, &admin);
+    // 与 /at-all 共用每群每天的次数和风控预算，不足时只发通知不 @
+    let at_all = at_all
+        && role.is_admin
+        && match at_all_service::consume(roomid) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("{} 不 @所有人: {}", roomid, e);
+                false
+            }
+        };
+    let text = if at_all {
+        at_all_service::text(roomid, &content)
+    } else {
+        TextMsg {
+            msg: content.clone(),
+            receiver: roomid.to_string(),
+            aters: String::new(),
+        }
+    };
+    match quiet_hours_service::send_text(wechat, text) {
+        Ok(sent) => Ok((content, at_all, sent)),
+        Err(e) => Err((content, e)),
+    }
+}
+
+/// 把同一条通知发到多个群，每个群单独替换变量，账号是群主或管理员时 @所有人，
+/// 某个群失败不影响其他群
+pub fn announce(wechat: &Arc<Mutex<WeChat>>, rooms: &[String], template: &str, at_all: bool) -> Vec<AnnounceResult> {
+    let contacts = wechat.lock().unwrap().get_contacts();
+    let names: HashMap<String, String> = match contacts {
+        Ok(contacts) => contacts.contacts.into_iter().map(|c| (c.wxid, c.name)).collect(),
+        Err(e) => {
+            warn!("获取联系人失败，群名和群主昵称将使用 wxid: {}", e);
+            HashMap::new()
+        }
+    };
+    rooms
+        .iter()
+        .enumerate()
+        .map(|(i, roomid)| {
+            if i > 0 {
+                thread::sleep(pacing::interval(ROOM_INTERVAL));
+            }
+            // 先复制再发送，群之间等待和发送时都不占着微信的锁
+            let wc = wechat.lock().unwrap().clone();
+            announce_result(roomid, send_one(&wc, roomid, template, at_all, &names))
+        })
+        .collect()
+}
+
+// 把一个群的发送结果转成接口返回的结构
+fn announce_result(roomid: &str, result: Result<(String, bool, bool), (String, String)>) -> AnnounceResult {
+    match result {
+        Ok((content, at_all, sent)) => AnnounceResult {
+            roomid: roomid.to_string(),
+            content,
+            at_all,
+            sent,
+            error: None,
+        },
+        Err((content, e)) => AnnounceResult {
+            roomid: roomid.to_string(),
+            content,
+            at_all: false,
+            sent: false,
+            error: Some(e),
+        },
+    }
+}
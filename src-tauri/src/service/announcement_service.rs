@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    service::global_service::GLOBAL,
+    utils::state_store,
+    wcferry::{wcf::WxMsg, RoomMember},
+};
+
+const STATE_NAME: &str = "announcements";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Receipt {
+    keyword: String,
+    at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Announcement {
+    id: u64,
+    roomid: String,
+    content: String,
+    keywords: Vec<String>,
+    // wxid -> 第一次确认
+    acks: BTreeMap<String, Receipt>,
+    created_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct AnnouncementState {
+    next_id: u64,
+    announcements: HashMap<u64, Announcement>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct Ack {
+    pub wxid: String,
+    /// 群昵称，查不到时为空
+    pub name: String,
+    /// 回复中匹配到的确认词
+    pub keyword: String,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub at: DateTime<Local>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct Pending {
+    pub wxid: String,
+    pub name: String,
+}
+
+/// 通知的确认情况
+#[derive(Serialize, ToSchema, Clone)]
+pub struct AckReport {
+    pub id: u64,
+    pub roomid: String,
+    pub content: String,
+    pub keywords: Vec<String>,
+    /// 已确认的成员，按确认时间排序
+    pub acked: Vec<Ack>,
+    /// 还没确认的群成员，不含自己
+    pub pending: Vec<Pending>,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub created_at: DateTime<Local>,
+}
+
+// 去掉首尾空白和标点后，以确认词开头即算确认
+fn matched<'a>(keywords: &'a [String], content: &str) -> Option<&'a String> {
+    let content = content
+        .trim()
+        .trim_matches(|c: char| (c.is_ascii_punctuation() && c != '+') || "！。～，".contains(c))
+        .to_lowercase();
+    keywords
+        .iter()
+        .find(|k| !k.is_empty() && content.starts_with(&k.to_lowercase()))
+}
+
+/// 通知的确认词，没有指定时使用配置的默认值
+pub fn ack_keywords(keywords: Vec<String>) -> Result<Vec<String>, String> {
+    let keywords = if keywords.is_empty() {
+        GLOBAL.get().unwrap().wechat_config.read().unwrap().announcement.ack_keywords.clone()
+    } else {
+        keywords
+    };
+    if keywords.is_empty() {
+        return Err("至少需要一个确认词".to_string());
+    }
+    Ok(keywords)
+}
+
+/** 群通知确认：发出通知后统计成员回复的“收到”“+1”等确认词，列出还没确认的人 */
+pub struct AnnouncementService {
+    state: AnnouncementState,
+}
+
+impl AnnouncementService {
+    pub fn new() -> Self {
+        AnnouncementService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 登记一条通知，keywords 为空时使用配置的确认词，返回编号
+    pub fn create(&mut self, roomid: &str, content: &str, keywords: Vec<String>) -> Result<u64, String> {
+        let keywords = ack_keywords(keywords)?;
+        self.state.next_id += 1;
+        let id = self.state.next_id;
+        self.state.announcements.insert(
+            id,
+            Announcement {
+                id,
+                roomid: roomid.to_string(),
+                content: content.to_string(),
+                keywords,
+                acks: BTreeMap::new(),
+                created_at: Local::now(),
+            },
+        );
+        self.save()?;
+        Ok(id)
+    }
+
+    /// 确认情况，members 为当前群成员，用于列出未确认的人和显示群昵称
+    pub fn report(&self, id: u64, members: &[RoomMember], self_wxid: &str) -> Option<AckReport> {
+        let announcement = self.state.announcements.get(&id)?;
+        let names: HashMap<&str, &str> = members.iter().map(|m| (m.wxid.as_str(), m.name.as_str())).collect();
+        let name = |wxid: &str| names.get(wxid).map(|n| n.to_string()).unwrap_or_default();
+        let mut acked: Vec<Ack> = announcement
+            .acks
+            .iter()
+            .map(|(wxid, receipt)| Ack {
+                wxid: wxid.clone(),
+                name: name(wxid),
+                keyword: receipt.keyword.clone(),
+                at: receipt.at,
+            })
+            .collect();
+        acked.sort_by_key(|a| a.at);
+        let pending = members
+            .iter()
+            .filter(|m| m.wxid != self_wxid && !announcement.acks.contains_key(&m.wxid))
+            .map(|m| Pending {
+                wxid: m.wxid.clone(),
+                name: m.name.clone(),
+            })
+            .collect();
+        Some(AckReport {
+            id: announcement.id,
+            roomid: announcement.roomid.clone(),
+            content: announcement.content.clone(),
+            keywords: announcement.keywords.clone(),
+            acked,
+            pending,
+            created_at: announcement.created_at,
+        })
+    }
+
+    pub fn roomid(&self, id: u64) -> Option<String> {
+        self.state.announcements.get(&id).map(|a| a.roomid.clone())
+    }
+
+    /// 该 wxid 确认过的通知编号
+    pub fn acked_by(&self, wxid: &str) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .state
+            .announcements
+            .values()
+            .filter(|a| a.acks.contains_key(wxid))
+            .map(|a| a.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// 删除该 wxid 的确认记录，返回删除的条数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let removed = self
+            .state
+            .announcements
+            .values_mut()
+            .filter(|a| a.acks.remove(wxid).is_some())
+            .count();
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// 群消息是对通知的确认时记下来，同一个群有多条通知时记到最新的那条
+    pub fn record(&mut self, msg: &WxMsg) {
+        if msg.r#type != 1 || !msg.is_group || msg.is_self {
+            return;
+        }
+        let announcement = self
+            .state
+            .announcements
+            .values_mut()
+            .filter(|a| a.roomid == msg.roomid)
+            .max_by_key(|a| a.id);
+        let announcement = match announcement {
+            Some(announcement) => announcement,
+            None => return,
+        };
+        if announcement.acks.contains_key(&msg.sender) {
+            return;
+        }
+        if let Some(keyword) = matched(&announcement.keywords, &msg.content) {
+            let receipt = Receipt {
+                keyword: keyword.clone(),
+                at: Local::now(),
+            };
+            announcement.acks.insert(msg.sender.clone(), receipt);
+            if let Err(e) = self.save() {
+                log::warn!("保存通知确认失败: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, Timelike};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{
+        admin_notify_service::{self, Incident},
+        global_service::GLOBAL,
+    },
+    utils::state_store,
+    wcferry::{wcf::WxMsg, WeChat},
+    wechat_config::AnomalyConfig,
+};
+
+const STATE_NAME: &str = "anomaly_baseline";
+
+/// 所有会话合计使用的键
+const TOTAL: &str = "*";
+
+/// 基线的平滑系数，越小越不容易被单个窗口带偏
+const ALPHA: f64 = 0.1;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+struct Baseline {
+    // 每个窗口的平均消息数
+    mean: f64,
+    // 已学习的窗口数
+    samples: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BaselineState {
+    // 会话 -> 一天 24 个小时各自的基线，深夜和白天的消息量差别很大，不能共用一个
+    hours: HashMap<String, Vec<Baseline>>,
+}
+
+impl BaselineState {
+    fn get(&self, roomid: &str, hour: usize) -> Baseline {
+        self.hours.get(roomid).and_then(|h| h.get(hour)).copied().unwrap_or_default()
+    }
+
+    fn get_mut(&mut self, roomid: &str, hour: usize) -> &mut Baseline {
+        let hours = self.hours.entry(roomid.to_string()).or_default();
+        hours.resize(24, Baseline::default());
+        &mut hours[hour]
+    }
+}
+
+fn current_hour() -> usize {
+    Local::now().hour() as usize
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct RoomVolume {
+    /// 群 id 或联系人 wxid，* 为全部会话合计
+    pub roomid: String,
+    /// 当前窗口已收到的消息数
+    pub current: u64,
+    /// 当前小时每个窗口的平均消息数
+    pub baseline: f64,
+    /// 当前小时已学习的窗口数
+    pub samples: u32,
+}
+
+// 本窗口消息数明显偏离基线时返回 true
+fn is_anomaly(count: u64, baseline: &Baseline, config: &AnomalyConfig) -> bool {
+    if baseline.samples < config.learn_windows {
+        return false;
+    }
+    let count = count as f64;
+    let spike = count >= config.min_messages as f64 && count > baseline.mean * config.factor;
+    let drop = baseline.mean >= config.min_messages as f64 && count * config.factor < baseline.mean;
+    spike || drop
+}
+
+/** 按会话统计每个时间窗口的消息量，与学习到的基线相差过大时通知管理员 */
+pub struct AnomalyService {
+    state: BaselineState,
+    current: HashMap<String, u64>,
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl AnomalyService {
+    pub fn new() -> Self {
+        AnomalyService {
+            state: state_store::load(STATE_NAME),
+            current: HashMap::new(),
+            handle: None,
+        }
+    }
+
+    pub fn record(&mut self, msg: &WxMsg) {
+        if msg.is_self {
+            return;
+        }
+        let roomid = if msg.is_group { &msg.roomid } else { &msg.sender };
+        *self.current.entry(roomid.clone()).or_insert(0) += 1;
+        *self.current.entry(TOTAL.to_string()).or_insert(0) += 1;
+    }
+
+    /// 全部会话合计在当前小时的每窗口平均消息数，还没学习过时为空
+    pub fn total_baseline(&self) -> Option<f64> {
+        Some(self.state.get(TOTAL, current_hour())).filter(|b| b.samples > 0).map(|b| b.mean)
+    }
+
+    pub fn volumes(&self) -> Vec<RoomVolume> {
+        let hour = current_hour();
+        let rooms: BTreeSet<&String> = self.state.hours.keys().chain(self.current.keys()).collect();
+        rooms
+            .into_iter()
+            .map(|roomid| {
+                let baseline = self.state.get(roomid, hour);
+                RoomVolume {
+                    roomid: roomid.clone(),
+                    current: self.current.get(roomid).copied().unwrap_or(0),
+                    baseline: baseline.mean,
+                    samples: baseline.samples,
+                }
+            })
+            .collect()
+    }
+
+    // 结束一个窗口：与窗口所在小时的基线比较，找出异常的会话并更新该小时的基线
+    fn close_window(&mut self, config: &AnomalyConfig, hour: usize) -> Vec<Incident> {
+        let current = std::mem::take(&mut self.current);
+        let mut rooms: BTreeSet<String> = self.state.hours.keys().cloned().collect();
+        rooms.extend(current.keys().cloned());
+        rooms.insert(TOTAL.to_string());
+
+        let mut incidents = Vec::new();
+        for roomid in rooms {
+            let count = current.get(&roomid).copied().unwrap_or(0);
+            let baseline = self.state.get_mut(&roomid, hour);
+            if is_anomaly(count, baseline, config) {
+                incidents.push(Incident::VolumeAnomaly {
+                    roomid,
+                    count,
+                    baseline: baseline.mean,
+                });
+            }
+            baseline.mean = if baseline.samples == 0 {
+                count as f64
+            } else {
+                baseline.mean * (1.0 - ALPHA) + count as f64 * ALPHA
+            };
+            baseline.samples = baseline.samples.saturating_add(1);
+        }
+        if let Err(e) = state_store::save(STATE_NAME, &self.state) {
+            warn!("保存消息量基线失败: {}", e);
+        }
+
+        // 全部会话一起骤降多半是消息钩子失效，只报合计那一条
+        let total_drop = incidents
+            .iter()
+            .any(|i| matches!(i, Incident::VolumeAnomaly { roomid, count, baseline } if roomid == TOTAL && (*count as f64) < *baseline));
+        if total_drop {
+            incidents.retain(|i| matches!(i, Incident::VolumeAnomaly { roomid, .. } if roomid == TOTAL));
+        }
+        incidents
+    }
+
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().anomaly.clone();
+                // 窗口按开始时所在的小时归类
+                let hour = current_hour();
+                tokio::time::sleep(Duration::from_secs(config.window_mins.max(1) * 60)).await;
+                // 掉线期间没有消息是正常的，由掉线通知处理
+                let wc = wechat.clone();
+                let login = tokio::task::spawn_blocking(move || wc.lock().unwrap().is_login().unwrap_or(false))
+                    .await
+                    .unwrap_or(false);
+                let incidents = {
+                    let global = GLOBAL.get().unwrap();
+                    let mut service = global.anomaly_service.lock().unwrap();
+                    if !login {
+                        service.current.clear();
+                        continue;
+                    }
+                    service.close_window(&config, hour)
+                };
+                // 关闭时照常学习基线，只是不告警，开启后马上就能用
+                if !config.enabled {
+                    continue;
+                }
+                for incident in incidents {
+                    info!("检测到消息量异常: {:?}", incident);
+                    admin_notify_service::report(incident);
+                }
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("消息量异常检测已停止");
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, quiet_hours_service, risk_guard_service::RiskOperation},
+    utils::state_store,
+    wcferry::{wcf::TextMsg, WeChat},
+};
+
+const STATE_NAME: &str = "at_all";
+
+/// 最多保留的已结束记录数，超出后丢弃最早的
+const HISTORY_SIZE: usize = 200;
+
+const AT_ALL: &str = "@所有人";
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AtAllState {
+    /// 等待定时发送
+    Scheduled,
+    Sending,
+    /// 已发出
+    Sent,
+    /// 处于免打扰时段，已进入排队
+    Queued,
+    Failed,
+}
+
+/// 一条 @所有人 消息
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct AtAllMessage {
+    pub id: u64,
+    pub roomid: String,
+    /// 实际发送的内容，以 @所有人 开头
+    pub content: String,
+    /// 发送时间，立即发送时为提交时间
+    #[schema(value_type = String, example = "2024-01-01T20:00:00+08:00")]
+    pub send_at: DateTime<Local>,
+    pub state: AtAllState,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct AtAllStore {
+    next_id: u64,
+    // 当天每个群已 @所有人 的次数
+    day: Option<NaiveDate>,
+    used: HashMap<String, u32>,
+    messages: Vec<AtAllMessage>,
+}
+
+/// 加上 @所有人 前缀，内容里已经写了的不重复添加
+pub fn text(roomid: &str, content: &str) -> TextMsg {
+    let content = content.trim();
+    let content = content.strip_prefix(AT_ALL).unwrap_or(content).trim_start();
+    TextMsg {
+        msg: format!("{} {}", AT_ALL, content),
+        receiver: roomid.to_string(),
+        aters: "notify@all".to_string(),
+    }
+}
+
+/// 只有群主和管理员才能 @所有人
+pub fn check_admin(wechat: &WeChat, roomid: &str) -> Result<(), String> {
+    match wechat.query_room_role(roomid.to_string()).map_err(|e| e.to_string())? {
+        Some(role) if role.is_admin => Ok(()),
+        Some(_) => Err(format!("当前账号不是 {} 的群主或管理员，无法 @所有人", roomid)),
+        None => Err(format!("群 {} 不存在", roomid)),
+    }
+}
+
+/// 扣减该群当天的次数和风控预算，不足时返回错误；检查和扣减在同一把锁内，并发时不会超出上限
+pub fn consume(roomid: &str) -> Result<(), String> {
+    let global = GLOBAL.get().unwrap();
+    let limit = global.wechat_config.read().unwrap().at_all.per_room_daily;
+    let mut service = global.at_all_service.lock().unwrap();
+    if limit > 0 && service.used(roomid) >= limit {
+        return Err(format!("{} 今日 @所有人 次数已达上限（上限 {}）", roomid, limit));
+    }
+    global.risk_guard_service.lock().unwrap().consume(RiskOperation::AtAll, 1)?;
+    service.add_used(roomid);
+    Ok(())
+}
+
+// 定时发送时重新检查权限和预算
+fn deliver(wechat: &WeChat, roomid: &str, content: &str) -> Result<bool, String> {
+    check_admin(wechat, roomid)?;
+    consume(roomid)?;
+    quiet_hours_service::send_text(wechat, text(roomid, content))
+}
+
+/** 在群里 @所有人，可以定时发送 */
+pub struct AtAllService {
+    state: AtAllStore,
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl AtAllService {
+    pub fn new() -> Self {
+        let mut service = AtAllService {
+            state: state_store::load(STATE_NAME),
+            handle: None,
+        };
+        service.recover();
+        service
+    }
+
+    // 上次退出时正在发送的消息不知道是否已发出，标记为失败而不是重发，避免重复 @所有人
+    fn recover(&mut self) {
+        let mut recovered = 0;
+        for message in self.state.messages.iter_mut().filter(|m| m.state == AtAllState::Sending) {
+            message.state = AtAllState::Failed;
+            message.error = Some("程序退出时正在发送，不确定是否已发出".to_string());
+            recovered += 1;
+        }
+        if recovered > 0 {
+            warn!("{} 条定时 @所有人 在上次退出时正在发送，已标记为失败", recovered);
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = state_store::save(STATE_NAME, &self.state) {
+            warn!("保存 @所有人 记录失败: {}", e);
+        }
+    }
+
+    // 跨天后清零
+    fn roll_day(&mut self) {
+        let today = Local::now().date_naive();
+        if self.state.day != Some(today) {
+            self.state.day = Some(today);
+            self.state.used.clear();
+        }
+    }
+
+    fn used(&mut self, roomid: &str) -> u32 {
+        self.roll_day();
+        self.state.used.get(roomid).copied().unwrap_or(0)
+    }
+
+    fn add_used(&mut self, roomid: &str) {
+        self.roll_day();
+        *self.state.used.entry(roomid.to_string()).or_default() += 1;
+        self.save();
+    }
+
+    /// 记录一条消息，返回带编号的记录
+    pub fn push(
+        &mut self,
+        roomid: &str,
+        content: &str,
+        send_at: DateTime<Local>,
+        result: Option<Result<bool, String>>,
+    ) -> AtAllMessage {
+        self.state.next_id += 1;
+        let (state, error) = match result {
+            None => (AtAllState::Scheduled, None),
+            Some(Ok(true)) => (AtAllState::Sent, None),
+            Some(Ok(false)) => (AtAllState::Queued, None),
+            Some(Err(e)) => (AtAllState::Failed, Some(e)),
+        };
+        let message = AtAllMessage {
+            id: self.state.next_id,
+            roomid: roomid.to_string(),
+            content: text(roomid, content).msg,
+            send_at,
+            state,
+            error,
+        };
+        self.state.messages.push(message.clone());
+        let finished = |m: &AtAllMessage| m.state != AtAllState::Scheduled && m.state != AtAllState::Sending;
+        if self.state.messages.iter().filter(|m| finished(m)).count() > HISTORY_SIZE {
+            if let Some(index) = self.state.messages.iter().position(finished) {
+                self.state.messages.remove(index);
+            }
+        }
+        self.save();
+        message
+    }
+
+    /// 全部记录，最新的在前
+    pub fn list(&self) -> Vec<AtAllMessage> {
+        self.state.messages.iter().rev().cloned().collect()
+    }
+
+    // 取出到时间的定时消息，标记为发送中，避免重复发送
+    fn take_due(&mut self) -> Vec<AtAllMessage> {
+        let now = Local::now();
+        let mut due = vec![];
+        for message in self.state.messages.iter_mut() {
+            if message.state == AtAllState::Scheduled && message.send_at <= now {
+                message.state = AtAllState::Sending;
+                due.push(message.clone());
+            }
+        }
+        due
+    }
+
+    fn finish(&mut self, id: u64, result: Result<bool, String>) {
+        if let Some(message) = self.state.messages.iter_mut().find(|m| m.id == id) {
+            match result {
+                Ok(sent) => message.state = if sent { AtAllState::Sent } else { AtAllState::Queued },
+                Err(e) => {
+                    message.state = AtAllState::Failed;
+                    message.error = Some(e);
+                }
+            }
+        }
+        self.save();
+    }
+
+    /// 发送到时间的定时消息，返回处理的条数
+    pub fn send_due(wechat: &Arc<Mutex<WeChat>>) -> usize {
+        let due = GLOBAL.get().unwrap().at_all_service.lock().unwrap().take_due();
+        for message in &due {
+            let wc = wechat.lock().unwrap().clone();
+            let result = deliver(&wc, &message.roomid, &message.content);
+            match &result {
+                Ok(_) => info!("已发送定时 @所有人 {} 到 {}", message.id, message.roomid),
+                Err(e) => warn!("定时 @所有人 {} 发送失败: {}", message.id, e),
+            }
+            GLOBAL.get().unwrap().at_all_service.lock().unwrap().finish(message.id, result);
+        }
+        due.len()
+    }
+
+    // 启动定时发送
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                let wc = wechat.clone();
+                let _ = tokio::task::spawn_blocking(move || AtAllService::send_due(&wc)).await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("定时 @所有人 已停止");
+        }
+    }
+}
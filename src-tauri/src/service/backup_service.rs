@@ -0,0 +1,524 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{Local, Utc};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    service::{db_snapshot_service, global_service::GLOBAL, media_service, message_store_service::MessageStoreService},
+    utils::digest,
+    wechat_config::{BackupConfig, BackupTarget},
+};
+
+/// 备份文件名前缀，清理远端旧备份时只处理这个前缀的文件
+const ARCHIVE_PREFIX: &str = "wcf-backup-";
+
+/// 上传、列目录和删除的超时时间
+const TIMEOUT: Duration = Duration::from_secs(600);
+/// 单个文件上传后 S3 预签名地址的有效期，SigV4 最长 7 天
+const PRESIGN_EXPIRES: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct TargetResult {
+    /// 目标地址
+    pub target: String,
+    pub ok: bool,
+    /// 按保留份数删除的旧备份数
+    pub removed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct BackupReport {
+    /// 本地备份文件
+    pub archive: String,
+    /// 文件大小，单位字节
+    pub size: u64,
+    pub targets: Vec<TargetResult>,
+}
+
+fn backup_dir() -> PathBuf {
+    PathBuf::from(".").join("data").join("backups")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn add_file(zip: &mut ZipWriter<File>, name: &str, path: &Path) -> Result<(), String> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut file = File::open(path).map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    zip.start_file(name.replace('\\', "/"), options).map_err(|e| e.to_string())?;
+    io::copy(&mut file, zip).map_err(|e| format!("压缩 {} 失败: {}", path.display(), e))?;
+    Ok(())
+}
+
+// 把目录下的文件以 prefix 为前缀加入压缩包，跳过 skip 中的路径
+fn add_dir(zip: &mut ZipWriter<File>, prefix: &str, dir: &Path, skip: &[PathBuf]) -> Result<(), String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if skip.iter().any(|s| path.starts_with(s)) {
+            continue;
+        }
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        if path.is_dir() {
+            add_dir(zip, &name, &path, skip)?;
+        } else if path.extension().map_or(false, |e| e == "tmp") {
+            // 状态文件写到一半的临时文件
+            continue;
+        } else if let Err(e) = add_file(zip, &name, &path) {
+            warn!("跳过文件: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// 打包配置、运行状态、消息库和（可选的）媒体文件
+///
+/// 密钥和敏感配置用 DPAPI 加密，只能在同一 Windows 账号下恢复，换电脑恢复时需要重新填写。
+/// 备份本身不加密：消息库没有开启加密时聊天记录以明文上传，远端存储需要自行控制访问权限。
+pub fn create_archive(config: &BackupConfig) -> Result<PathBuf, String> {
+    let dir = backup_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("创建目录失败: {}", e))?;
+    let name = format!("{}{}.zip", ARCHIVE_PREFIX, Local::now().format("%Y%m%d-%H%M%S"));
+    let path = dir.join(&name);
+    let file = File::create(&path).map_err(|e| format!("创建备份文件失败: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+
+    let config_file = PathBuf::from(".\\config.json5");
+    if config_file.exists() {
+        add_file(&mut zip, "config.json5", &config_file)?;
+    }
+
+    // 消息库单独复制一份一致的快照，不直接打包正在写入的文件
+    let store = MessageStoreService::path();
+    // 微信数据库快照可以随时重新生成，不打包
+    let mut skip = vec![dir.clone(), db_snapshot_service::snapshot_dir()];
+    if store.exists() {
+        let snapshot = dir.join(format!("{}.db", name));
+        GLOBAL.get().unwrap().message_store_service.lock().unwrap().snapshot(&snapshot)?;
+        let added = add_file(&mut zip, "messages.db", &snapshot);
+        let _ = fs::remove_file(&snapshot);
+        added?;
+        for suffix in ["", "-journal", "-wal", "-shm"] {
+            skip.push(PathBuf::from(format!("{}{}", store.display(), suffix)));
+        }
+    }
+    add_dir(&mut zip, "data", &PathBuf::from(".").join("data"), &skip)?;
+
+    let file_dir = GLOBAL.get().unwrap().wechat_config.read().unwrap().file_dir.clone();
+    if config.include_media && !file_dir.is_empty() {
+        add_dir(&mut zip, "media", &media_service::media_dir(), &skip)?;
+    }
+    zip.finish().map_err(|e| format!("写入备份文件失败: {}", e))?;
+    Ok(path)
+}
+
+fn target_name(target: &BackupTarget) -> String {
+    match target {
+        BackupTarget::S3 { endpoint, bucket, prefix, .. } => {
+            format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, prefix)
+        }
+        BackupTarget::Webdav { url, .. } => url.clone(),
+        BackupTarget::Local { dir } => dir.clone(),
+    }
+}
+
+// 上传的内容，备份文件直接从磁盘流式读取，不整个读进内存
+struct Body {
+    reader: Box<dyn Read + Send>,
+    len: u64,
+    sha256: String,
+}
+
+impl Body {
+    // S3 签名需要先算出整个文件的 sha256，这里分块读一遍文件，上传时再读一遍
+    fn file(path: &Path) -> Result<Self, String> {
+        let sha256 = digest::sha256_file(path)?;
+        let file = File::open(path).map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+        let len = file.metadata().map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?.len();
+        Ok(Body {
+            reader: Box::new(file),
+            len,
+            sha256,
+        })
+    }
+}
+
+// S3 兼容存储，使用 path-style 地址和 SigV4 签名
+struct S3<'a> {
+    endpoint: &'a str,
+    region: &'a str,
+    bucket: &'a str,
+    access_key: &'a str,
+    secret_key: &'a str,
+}
+
+impl S3<'_> {
+    fn uri(&self, key: &str) -> String {
+        let mut uri = format!("/{}", self.bucket);
+        if !key.is_empty() {
+            let encoded: Vec<String> = key.split('/').map(|s| urlencoding::encode(s).to_string()).collect();
+            uri = format!("{}/{}", uri, encoded.join("/"));
+        }
+        uri
+    }
+
+    fn endpoint_host(&self) -> (&str, &str) {
+        let endpoint = self.endpoint.trim_end_matches('/');
+        (endpoint, endpoint.split("://").nth(1).unwrap_or(endpoint))
+    }
+
+    // 依次派生签名密钥，最后一轮得到签名
+    fn sign(&self, date: &str, canonical: &str, amz_date: &str, scope: &str) -> String {
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical.as_bytes())));
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, self.region, "s3", "aws4_request", to_sign.as_str()] {
+            let mut mac = Hmac::<Sha256>::new_from_slice(&key).unwrap();
+            mac.update(part.as_bytes());
+            key = mac.finalize().into_bytes().to_vec();
+        }
+        hex(&key)
+    }
+
+    // payload_hash 为请求体的 sha256，签名时用到
+    fn request(&self, method: &str, key: &str, query: &str, payload_hash: &str) -> ureq::Request {
+        let (endpoint, host) = self.endpoint_host();
+        let uri = self.uri(key);
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, uri, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            self.sign(&date, &canonical, &amz_date, &scope)
+        );
+        let url = if query.is_empty() {
+            format!("{}{}", endpoint, uri)
+        } else {
+            format!("{}{}?{}", endpoint, uri, query)
+        };
+        ureq::request(method, &url)
+            .timeout(TIMEOUT)
+            .set("x-amz-date", &amz_date)
+            .set("x-amz-content-sha256", payload_hash)
+            .set("Authorization", &authorization)
+    }
+
+    // 预签名的下载地址，桶不公开时也能访问，expires 秒后失效
+    fn presign(&self, key: &str, expires: Duration) -> String {
+        let (endpoint, host) = self.endpoint_host();
+        let uri = self.uri(key);
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        // 参数需按名称排序
+        let query = format!(
+            "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+            urlencoding::encode(&format!("{}/{}", self.access_key, scope)),
+            amz_date,
+            expires.as_secs()
+        );
+        let canonical = format!("GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", uri, query, host);
+        format!(
+            "{}{}?{}&X-Amz-Signature={}",
+            endpoint,
+            uri,
+            query,
+            self.sign(&date, &canonical, &amz_date, &scope)
+        )
+    }
+
+    fn put(&self, key: &str, body: Body) -> Result<(), String> {
+        self.request("PUT", key, "", &body.sha256)
+            .set("Content-Length", &body.len.to_string())
+            .send(body.reader)
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let query = format!("list-type=2&prefix={}", urlencoding::encode(prefix));
+        let body = self
+            .request("GET", "", &query, &hex(&Sha256::digest(b"")))
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())?;
+        let re = Regex::new(r"<Key>([^<]+)</Key>").unwrap();
+        Ok(re.captures_iter(&body).map(|c| c[1].to_string()).collect())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.request("DELETE", key, "", &hex(&Sha256::digest(b""))).call().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+// WebDAV 目录，url 为存放备份的目录地址
+struct WebDav {
+    url: String,
+    auth: Option<String>,
+}
+
+impl WebDav {
+    fn new(url: &str, username: &str, password: &str) -> Self {
+        let auth = (!username.is_empty())
+            .then(|| format!("Basic {}", base64::encode(format!("{}:{}", username, password))));
+        WebDav {
+            url: format!("{}/", url.trim_end_matches('/')),
+            auth,
+        }
+    }
+
+    fn request(&self, method: &str, name: &str) -> ureq::Request {
+        let request = ureq::request(method, &format!("{}{}", self.url, urlencoding::encode(name))).timeout(TIMEOUT);
+        match &self.auth {
+            Some(auth) => request.set("Authorization", auth),
+            None => request,
+        }
+    }
+
+    fn put(&self, name: &str, body: Body) -> Result<(), String> {
+        // 目录不存在时先创建，已存在会返回 405，忽略即可
+        let _ = self.request("MKCOL", "").call();
+        self.request("PUT", name)
+            .set("Content-Length", &body.len.to_string())
+            .send(body.reader)
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, String> {
+        let body = self
+            .request("PROPFIND", "")
+            .set("Depth", "1")
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())?;
+        let re = Regex::new(r"(?i)<(?:\w+:)?href>([^<]+)</(?:\w+:)?href>").unwrap();
+        Ok(re
+            .captures_iter(&body)
+            .filter_map(|c| {
+                let href = c[1].trim_end_matches('/').rsplit('/').next()?.to_string();
+                Some(urlencoding::decode(&href).map(|n| n.into_owned()).unwrap_or(href))
+            })
+            .collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        self.request("DELETE", name).call().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+// 写入本地目录，返回文件路径
+fn put_local(dir: &str, name: &str, mut body: Body) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("创建目录 {} 失败: {}", dir, e))?;
+    let path = Path::new(dir).join(name);
+    File::create(&path)
+        .and_then(|mut file| io::copy(&mut body.reader, &mut file))
+        .map_err(|e| format!("写入 {} 失败: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// 把磁盘上的文件流式上传到目标，返回访问地址：S3 为 7 天内有效的预签名 URL，WebDAV 为文件 URL，本地目录为文件路径
+pub fn put_file(target: &BackupTarget, name: &str, path: &Path) -> Result<String, String> {
+    match target {
+        BackupTarget::S3 {
+            endpoint,
+            region,
+            bucket,
+            prefix,
+            access_key,
+            secret_key,
+        } => {
+            let s3 = S3 {
+                endpoint,
+                region,
+                bucket,
+                access_key,
+                secret_key: secret_key.expose(),
+            };
+            let key = format!("{}{}", prefix, name);
+            s3.put(&key, Body::file(path)?)?;
+            Ok(s3.presign(&key, PRESIGN_EXPIRES))
+        }
+        BackupTarget::Webdav { url, username, password } => {
+            let dav = WebDav::new(url, username, password.expose());
+            dav.put(name, Body::file(path)?)?;
+            Ok(format!("{}{}", dav.url, urlencoding::encode(name)))
+        }
+        BackupTarget::Local { dir } => Ok(put_local(dir, name, Body::file(path)?)?.to_string_lossy().to_string()),
+    }
+}
+
+// 除最新的 keep 份外都删除，keep 为 0 时全部保留
+fn expired(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    if keep == 0 {
+        return Vec::new();
+    }
+    names.retain(|n| n.rsplit('/').next().map_or(false, |f| f.starts_with(ARCHIVE_PREFIX) && f.ends_with(".zip")));
+    names.sort();
+    let count = names.len().saturating_sub(keep);
+    names.truncate(count);
+    names
+}
+
+// 上传到一个目标并清理旧备份，返回删除的份数
+fn upload(target: &BackupTarget, name: &str, archive: &Path, keep: usize) -> Result<usize, String> {
+    let mut removed = 0;
+    match target {
+        BackupTarget::S3 {
+            endpoint,
+            region,
+            bucket,
+            prefix,
+            access_key,
+            secret_key,
+        } => {
+            let s3 = S3 {
+                endpoint,
+                region,
+                bucket,
+                access_key,
+                secret_key: secret_key.expose(),
+            };
+            s3.put(&format!("{}{}", prefix, name), Body::file(archive)?)?;
+            for key in expired(s3.list(&format!("{}{}", prefix, ARCHIVE_PREFIX))?, keep) {
+                match s3.delete(&key) {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("删除旧备份 {} 失败: {}", key, e),
+                }
+            }
+        }
+        BackupTarget::Webdav { url, username, password } => {
+            let dav = WebDav::new(url, username, password.expose());
+            dav.put(name, Body::file(archive)?)?;
+            for file in expired(dav.list()?, keep) {
+                match dav.delete(&file) {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("删除旧备份 {} 失败: {}", file, e),
+                }
+            }
+        }
+        BackupTarget::Local { dir } => {
+            put_local(dir, name, Body::file(archive)?)?;
+            let names: Vec<String> = fs::read_dir(dir)
+                .map(|entries| entries.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect())
+                .unwrap_or_default();
+            for file in expired(names, keep) {
+                match fs::remove_file(Path::new(dir).join(&file)) {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("删除旧备份 {} 失败: {}", file, e),
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// 创建一份备份，上传到所有目标并按保留份数清理本地和远端的旧备份
+pub fn run(config: &BackupConfig) -> Result<BackupReport, String> {
+    let path = create_archive(config)?;
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    let size = fs::metadata(&path).map_err(|e| format!("读取备份文件失败: {}", e))?.len();
+
+    let targets = config
+        .targets
+        .iter()
+        .map(|target| {
+            let (ok, removed, error) = match upload(target, &name, &path, config.keep) {
+                Ok(removed) => (true, removed, None),
+                Err(e) => {
+                    warn!("备份上传到 {} 失败: {}", target_name(target), e);
+                    (false, 0, Some(e))
+                }
+            };
+            TargetResult {
+                target: target_name(target),
+                ok,
+                removed,
+                error,
+            }
+        })
+        .collect();
+
+    let local: Vec<String> = fs::read_dir(backup_dir())
+        .map(|entries| entries.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect())
+        .unwrap_or_default();
+    for old in expired(local, config.keep) {
+        let _ = fs::remove_file(backup_dir().join(old));
+    }
+
+    info!("已创建备份 {}，{} 字节", path.display(), size);
+    Ok(BackupReport {
+        archive: path.to_string_lossy().to_string(),
+        size,
+        targets,
+    })
+}
+
+/** 定时备份：按间隔打包数据并上传到 S3 兼容存储、WebDAV 或本地目录 */
+pub struct BackupService {
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl BackupService {
+    pub fn new() -> Self {
+        BackupService { handle: None }
+    }
+
+    pub fn start(&mut self) {
+        self.stop();
+        let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().backup.clone();
+        if !config.enabled {
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_hours.max(1) * 60 * 60);
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().backup.clone();
+                match tokio::task::spawn_blocking(move || run(&config)).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => warn!("定时备份失败: {}", e),
+                    Err(e) => warn!("定时备份失败: {}", e),
+                }
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("定时备份已停止");
+        }
+    }
+}
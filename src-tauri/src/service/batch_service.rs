@@ -0,0 +1,158 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{pause_service, quiet_hours_service},
+    utils::{jobs, pacing},
+    wcferry::{wcf::TextMsg, WeChat},
+};
+
+/// 最多保留的批次数，超出后丢弃最早的
+const CAPACITY: usize = 20;
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemState {
+    /// 还没发送
+    Pending,
+    Sent,
+    /// 接收方处于免打扰时段，已进入排队
+    Queued,
+    Failed,
+}
+
+/// 一条消息的发送情况
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct BatchItem {
+    pub receiver: String,
+    pub msg: String,
+    pub state: ItemState,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct BatchReport {
+    pub id: u64,
+    /// 后台任务编号，进度也可以通过 /admin/jobs/{id} 查询
+    pub job: u64,
+    pub total: usize,
+    pub sent: usize,
+    pub queued: usize,
+    pub failed: usize,
+    pub pending: usize,
+    /// 按提交顺序排列
+    pub items: Vec<BatchItem>,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub created_at: DateTime<Local>,
+}
+
+struct Batch {
+    id: u64,
+    job: u64,
+    texts: Vec<TextMsg>,
+    items: Vec<BatchItem>,
+    created_at: DateTime<Local>,
+}
+
+impl Batch {
+    fn report(&self) -> BatchReport {
+        let count = |state: ItemState| self.items.iter().filter(|i| i.state == state).count();
+        BatchReport {
+            id: self.id,
+            job: self.job,
+            total: self.items.len(),
+            sent: count(ItemState::Sent),
+            queued: count(ItemState::Queued),
+            failed: count(ItemState::Failed),
+            pending: count(ItemState::Pending),
+            items: self.items.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+struct Registry {
+    next_id: u64,
+    batches: Vec<Batch>,
+}
+
+static BATCHES: Mutex<Registry> = Mutex::new(Registry { next_id: 1, batches: Vec::new() });
+
+/// 登记一批消息，返回批次编号
+pub fn create(job: u64, texts: Vec<TextMsg>) -> u64 {
+    let mut registry = BATCHES.lock().unwrap();
+    let id = registry.next_id;
+    registry.next_id += 1;
+    let items = texts
+        .iter()
+        .map(|t| BatchItem {
+            receiver: t.receiver.clone(),
+            msg: t.msg.clone(),
+            state: ItemState::Pending,
+            error: None,
+        })
+        .collect();
+    registry.batches.push(Batch {
+        id,
+        job,
+        texts,
+        items,
+        created_at: Local::now(),
+    });
+    if registry.batches.len() > CAPACITY {
+        registry.batches.remove(0);
+    }
+    id
+}
+
+pub fn report(id: u64) -> Option<BatchReport> {
+    BATCHES.lock().unwrap().batches.iter().find(|b| b.id == id).map(|b| b.report())
+}
+
+fn update(id: u64, index: usize, result: Result<bool, String>) {
+    let mut registry = BATCHES.lock().unwrap();
+    if let Some(item) = registry.batches.iter_mut().find(|b| b.id == id).and_then(|b| b.items.get_mut(index)) {
+        match result {
+            Ok(true) => item.state = ItemState::Sent,
+            Ok(false) => item.state = ItemState::Queued,
+            Err(e) => {
+                item.state = ItemState::Failed;
+                item.error = Some(e);
+            }
+        }
+    }
+}
+
+/// 在后台任务中按顺序发送，每条之间间隔 interval；暂停自动化时剩下的保留待发状态
+pub fn run(wechat: &Arc<Mutex<WeChat>>, id: u64, interval: Duration) -> Result<BatchReport, String> {
+    let (job, texts) = {
+        let registry = BATCHES.lock().unwrap();
+        let batch = registry.batches.iter().find(|b| b.id == id).ok_or("批次不存在")?;
+        (batch.job, batch.texts.clone())
+    };
+    jobs::set_total(job, texts.len() as u64);
+    for (i, text) in texts.into_iter().enumerate() {
+        if i > 0 {
+            std::thread::sleep(pacing::interval(interval));
+        }
+        pause_service::check()?;
+        let receiver = text.receiver.clone();
+        let wc = wechat.lock().unwrap().clone();
+        let result = quiet_hours_service::send_text(&wc, text);
+        if let Err(e) = &result {
+            warn!("批量发送 #{} 发往 {} 失败: {}", id, receiver, e);
+        }
+        update(id, i, result);
+        jobs::advance(job, 1);
+    }
+    let report = report(id).ok_or("批次不存在")?;
+    info!("批量发送 #{} 完成：发送 {}，排队 {}，失败 {}", id, report.sent, report.queued, report.failed);
+    Ok(report)
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{service::global_service::GLOBAL, utils::state_store};
+
+const STATE_NAME: &str = "bridge_identities";
+
+/// 支持转发的外部平台
+pub const PLATFORMS: &[&str] = &["telegram", "discord"];
+
+/// 群成员在外部平台上的身份
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BridgeIdentity {
+    /// telegram 或 discord
+    pub platform: String,
+    /// 微信账号，合并了身份时为主账号
+    pub wxid: String,
+    /// 外部平台的用户 id
+    pub external_id: String,
+    /// 转发到外部平台时显示的名字，为空时使用群昵称
+    pub display_name: String,
+    /// 转发到外部平台时使用的头像地址，为空时使用微信头像
+    pub avatar: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BridgeIdentityState {
+    identities: Vec<BridgeIdentity>,
+}
+
+/** 微信群成员与 Telegram、Discord 用户的对应关系，转发消息时双向标注发送人 */
+pub struct BridgeIdentityService {
+    state: BridgeIdentityState,
+}
+
+// 合并了身份时按主账号保存
+fn canonical(wxid: &str) -> String {
+    GLOBAL.get().unwrap().identity_service.lock().unwrap().canonical(wxid)
+}
+
+impl BridgeIdentityService {
+    pub fn new() -> Self {
+        BridgeIdentityService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 全部映射，platform 不为空时只返回该平台的
+    pub fn list(&self, platform: Option<&str>) -> Vec<BridgeIdentity> {
+        self.state
+            .identities
+            .iter()
+            .filter(|i| platform.map_or(true, |p| i.platform == p))
+            .cloned()
+            .collect()
+    }
+
+    /// 保存映射，external_id 为空时删除；同一平台上一个外部账号只能对应一个微信账号
+    pub fn set(&mut self, identity: BridgeIdentity) -> Result<Option<BridgeIdentity>, String> {
+        let identity = BridgeIdentity {
+            wxid: canonical(&identity.wxid),
+            ..identity
+        };
+        if let Some(taken) = self.state.identities.iter().find(|i| {
+            i.platform == identity.platform && i.external_id == identity.external_id && i.wxid != identity.wxid
+        }) {
+            return Err(format!("{} 上的 {} 已对应 {}", taken.platform, taken.external_id, taken.wxid));
+        }
+        self.state
+            .identities
+            .retain(|i| !(i.platform == identity.platform && i.wxid == identity.wxid));
+        let saved = if identity.external_id.is_empty() {
+            None
+        } else {
+            self.state.identities.push(identity.clone());
+            Some(identity)
+        };
+        self.save()?;
+        Ok(saved)
+    }
+
+    /// 从微信转发出去时，发送人在外部平台上的身份
+    pub fn for_wxid(&self, platform: &str, wxid: &str) -> Option<BridgeIdentity> {
+        let wxid = canonical(wxid);
+        self.state
+            .identities
+            .iter()
+            .find(|i| i.platform == platform && i.wxid == wxid)
+            .cloned()
+    }
+
+    /// 从外部平台转发进来时，发送人对应的微信账号
+    pub fn for_external(&self, platform: &str, external_id: &str) -> Option<BridgeIdentity> {
+        self.state
+            .identities
+            .iter()
+            .find(|i| i.platform == platform && i.external_id == external_id)
+            .cloned()
+    }
+
+    /// 该微信账号在各平台上的映射
+    pub fn of(&self, wxid: &str) -> Vec<BridgeIdentity> {
+        self.state.identities.iter().filter(|i| i.wxid == wxid).cloned().collect()
+    }
+
+    /// 删除该微信账号的全部映射，返回删除的条数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let before = self.state.identities.len();
+        self.state.identities.retain(|i| i.wxid != wxid);
+        let removed = before - self.state.identities.len();
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+}
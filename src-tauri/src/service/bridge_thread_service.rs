@@ -0,0 +1,81 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::utils::state_store;
+
+const STATE_NAME: &str = "bridge_threads";
+
+/// 最多保留的对应关系数，超出后丢弃最早的
+const MAX_LINKS: usize = 5000;
+
+/// 一条微信消息与外部平台消息的对应关系
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ThreadLink {
+    /// telegram 或 discord
+    pub platform: String,
+    /// 微信消息 id
+    pub msg_id: u64,
+    /// 微信消息所在的群或联系人
+    pub roomid: String,
+    /// 外部平台的会话 id
+    pub chat: String,
+    /// 外部平台的消息 id
+    pub external_id: String,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub created_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BridgeThreadState {
+    links: Vec<ThreadLink>,
+}
+
+/** 转发引用回复时，用来把被引用的微信消息映射到外部平台的消息，保持回复关系 */
+pub struct BridgeThreadService {
+    state: BridgeThreadState,
+}
+
+impl BridgeThreadService {
+    pub fn new() -> Self {
+        BridgeThreadService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 记录对应关系，同一平台上同一条微信消息或外部消息只保留最新的一条
+    pub fn record(&mut self, link: ThreadLink) -> Result<(), String> {
+        self.state.links.retain(|l| {
+            l.platform != link.platform
+                || (l.msg_id != link.msg_id && !(l.chat == link.chat && l.external_id == link.external_id))
+        });
+        self.state.links.push(link);
+        if self.state.links.len() > MAX_LINKS {
+            let excess = self.state.links.len() - MAX_LINKS;
+            self.state.links.drain(..excess);
+        }
+        self.save()
+    }
+
+    /// 转发到外部平台时，被引用的微信消息对应的外部消息
+    pub fn external_for(&self, platform: &str, msg_id: u64) -> Option<ThreadLink> {
+        self.state
+            .links
+            .iter()
+            .find(|l| l.platform == platform && l.msg_id == msg_id)
+            .cloned()
+    }
+
+    /// 从外部平台转发进来时，被回复的外部消息对应的微信消息
+    pub fn wechat_for(&self, platform: &str, chat: &str, external_id: &str) -> Option<ThreadLink> {
+        self.state
+            .links
+            .iter()
+            .find(|l| l.platform == platform && l.chat == chat && l.external_id == external_id)
+            .cloned()
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDate, NaiveTime};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, quiet_hours_service},
+    utils::state_store,
+    wcferry::{
+        wcf::{TextMsg, WxMsg},
+        WeChat,
+    },
+    wechat_config::CheckinConfig,
+};
+
+const STATE_NAME: &str = "checkin";
+
+/// 汇总里展示的连续打卡排行人数
+const LEADERBOARD_SIZE: usize = 5;
+
+#[derive(Serialize, Deserialize, Default)]
+struct CheckinState {
+    // roomid -> wxid -> 打卡日期
+    rooms: HashMap<String, HashMap<String, BTreeSet<NaiveDate>>>,
+    // 最近一次发送每日汇总的日期
+    last_summary: Option<NaiveDate>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct CheckinStat {
+    pub wxid: String,
+    /// 累计打卡天数
+    pub days: usize,
+    /// 连续打卡天数，今天还没打卡时算到昨天
+    pub streak: usize,
+    /// 今天是否已打卡
+    pub today: bool,
+    #[schema(value_type = String, example = "2024-01-01")]
+    pub last: NaiveDate,
+}
+
+fn streak(dates: &BTreeSet<NaiveDate>, today: NaiveDate) -> usize {
+    let mut day = if dates.contains(&today) { today } else { today.pred_opt().unwrap() };
+    let mut count = 0;
+    while dates.contains(&day) {
+        count += 1;
+        day = day.pred_opt().unwrap();
+    }
+    count
+}
+
+/** 统计群成员每天发送打卡关键词的情况，并在每天固定时间把汇总发到群里 */
+pub struct CheckinService {
+    state: CheckinState,
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl CheckinService {
+    pub fn new() -> Self {
+        CheckinService {
+            state: state_store::load(STATE_NAME),
+            handle: None,
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 消息是打卡时记录，自己发的不算，返回是否为今天第一次打卡
+    pub fn record(&mut self, msg: &WxMsg, config: &CheckinConfig) -> bool {
+        if msg.r#type != 1 || msg.is_self || config.keyword.is_empty() || !config.rooms.contains(&msg.roomid) {
+            return false;
+        }
+        if !msg.content.contains(&config.keyword) {
+            return false;
+        }
+        let first = self
+            .state
+            .rooms
+            .entry(msg.roomid.clone())
+            .or_default()
+            .entry(msg.sender.clone())
+            .or_default()
+            .insert(Local::now().date_naive());
+        if first {
+            if let Err(e) = self.save() {
+                warn!("保存打卡记录失败: {}", e);
+            }
+        }
+        first
+    }
+
+    /// 群里每个人的打卡情况，同一个人的多个账号合并计算，按连续天数、累计天数排序
+    pub fn stats(&self, roomid: &str) -> Vec<CheckinStat> {
+        let today = Local::now().date_naive();
+        // 主账号 -> 打卡日期
+        let mut merged: HashMap<String, BTreeSet<NaiveDate>> = HashMap::new();
+        if let Some(members) = self.state.rooms.get(roomid) {
+            let identities = GLOBAL.get().unwrap().identity_service.lock().unwrap();
+            for (wxid, dates) in members {
+                merged.entry(identities.canonical(wxid)).or_default().extend(dates);
+            }
+        }
+        let mut stats: Vec<CheckinStat> = merged
+            .into_iter()
+            .filter_map(|(wxid, dates)| {
+                Some(CheckinStat {
+                    days: dates.len(),
+                    streak: streak(&dates, today),
+                    today: dates.contains(&today),
+                    last: *dates.iter().next_back()?,
+                    wxid,
+                })
+            })
+            .collect();
+        stats.sort_by(|a, b| b.streak.cmp(&a.streak).then(b.days.cmp(&a.days)).then(a.wxid.cmp(&b.wxid)));
+        stats
+    }
+
+    /// 该 wxid 在各群的打卡日期
+    pub fn dates_of(&self, wxid: &str) -> HashMap<String, Vec<NaiveDate>> {
+        self.state
+            .rooms
+            .iter()
+            .filter_map(|(roomid, members)| Some((roomid.clone(), members.get(wxid)?.iter().copied().collect())))
+            .collect()
+    }
+
+    /// 删除该 wxid 的打卡记录，返回删除的天数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let removed: usize = self
+            .state
+            .rooms
+            .values_mut()
+            .filter_map(|members| members.remove(wxid))
+            .map(|dates| dates.len())
+            .sum();
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    // 到了汇总时间且今天还没发过时返回 true，并记下今天已发送
+    fn summary_due(&mut self, summary_time: &str) -> bool {
+        let time = match NaiveTime::parse_from_str(summary_time, "%H:%M") {
+            Ok(time) => time,
+            Err(_) => return false,
+        };
+        let now = Local::now();
+        if now.time() < time || self.state.last_summary == Some(now.date_naive()) {
+            return false;
+        }
+        self.state.last_summary = Some(now.date_naive());
+        if let Err(e) = self.save() {
+            warn!("保存打卡记录失败: {}", e);
+        }
+        true
+    }
+
+    // 启动每日汇总
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().checkin.clone();
+                if config.rooms.is_empty() || config.summary_time.is_empty() {
+                    continue;
+                }
+                let due = {
+                    let global = GLOBAL.get().unwrap();
+                    let mut checkin = global.checkin_service.lock().unwrap();
+                    checkin.summary_due(&config.summary_time)
+                };
+                if !due {
+                    continue;
+                }
+                let wc = wechat.clone();
+                let _ = tokio::task::spawn_blocking(move || {
+                    for roomid in &config.rooms {
+                        send_summary(&wc, roomid);
+                    }
+                })
+                .await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("打卡汇总已停止");
+        }
+    }
+}
+
+fn send_summary(wechat: &Arc<Mutex<WeChat>>, roomid: &str) {
+    let stats = GLOBAL.get().unwrap().checkin_service.lock().unwrap().stats(roomid);
+    let wc = wechat.lock().unwrap().clone();
+    // 汇总里显示群昵称，查不到时显示 wxid
+    let names: HashMap<String, String> = wc
+        .query_room_member(roomid.to_string())
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| (m.wxid, m.name))
+        .collect();
+    let name = |wxid: &str| names.get(wxid).cloned().unwrap_or_else(|| wxid.to_string());
+
+    let checked: Vec<String> = stats.iter().filter(|s| s.today).map(|s| name(&s.wxid)).collect();
+    let mut lines = vec![format!("今日打卡 {} 人", checked.len())];
+    if !checked.is_empty() {
+        lines.push(checked.join("、"));
+    }
+    let top: Vec<String> = stats
+        .iter()
+        .filter(|s| s.streak > 0)
+        .take(LEADERBOARD_SIZE)
+        .enumerate()
+        .map(|(i, s)| format!("{}. {} 连续 {} 天", i + 1, name(&s.wxid), s.streak))
+        .collect();
+    if !top.is_empty() {
+        lines.push("连续打卡排行：".to_string());
+        lines.extend(top);
+    }
+    let sent = quiet_hours_service::send_text(
+        &wc,
+        TextMsg {
+            msg: lines.join("\n"),
+            receiver: roomid.to_string(),
+            aters: String::new(),
+        },
+    );
+    if let Err(e) = sent {
+        warn!("发送打卡汇总到 {} 失败: {}", roomid, e);
+    }
+}
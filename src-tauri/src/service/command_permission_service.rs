@@ -0,0 +1,102 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{utils::state_store, wcferry::WeChat};
+
+const STATE_NAME: &str = "command_permissions";
+
+#[derive(Serialize, Deserialize, Default)]
+struct PermissionState {
+    // roomid -> 允许执行特权指令的 wxid
+    rooms: HashMap<String, BTreeSet<String>>,
+}
+
+/** 按群维护可以执行特权指令（踢人、群发等）的名单，群主和登录账号本身始终有权限 */
+pub struct CommandPermissionService {
+    state: PermissionState,
+}
+
+impl CommandPermissionService {
+    pub fn new() -> Self {
+        CommandPermissionService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 群里手动授权的 wxid
+    pub fn list(&self, roomid: &str) -> Vec<String> {
+        self.state
+            .rooms
+            .get(roomid)
+            .map(|wxids| wxids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn grant(&mut self, roomid: &str, wxids: &[String]) -> Result<(), String> {
+        self.state
+            .rooms
+            .entry(roomid.to_string())
+            .or_default()
+            .extend(wxids.iter().cloned());
+        self.save()
+    }
+
+    pub fn revoke(&mut self, roomid: &str, wxids: &[String]) -> Result<(), String> {
+        if let Some(allowed) = self.state.rooms.get_mut(roomid) {
+            for wxid in wxids {
+                allowed.remove(wxid);
+            }
+            if allowed.is_empty() {
+                self.state.rooms.remove(roomid);
+            }
+        }
+        self.save()
+    }
+
+    /// 已授权该 wxid 的群
+    pub fn rooms_of(&self, wxid: &str) -> Vec<String> {
+        let mut rooms: Vec<String> = self
+            .state
+            .rooms
+            .iter()
+            .filter(|(_, wxids)| wxids.contains(wxid))
+            .map(|(roomid, _)| roomid.clone())
+            .collect();
+        rooms.sort();
+        rooms
+    }
+
+    /// 从所有群的授权名单中移除该 wxid，返回涉及的群数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let rooms = self.rooms_of(wxid);
+        if rooms.is_empty() {
+            return Ok(0);
+        }
+        for allowed in self.state.rooms.values_mut() {
+            allowed.remove(wxid);
+        }
+        self.state.rooms.retain(|_, allowed| !allowed.is_empty());
+        self.save()?;
+        Ok(rooms.len())
+    }
+
+    /// 是否可以在该群执行特权指令
+    pub fn is_allowed(&self, wechat: &WeChat, roomid: &str, wxid: &str) -> bool {
+        if self.state.rooms.get(roomid).map_or(false, |w| w.contains(wxid)) {
+            return true;
+        }
+        if wechat.get_self_wxid().map_or(false, |me| me == wxid) {
+            return true;
+        }
+        // 群主自动拥有权限
+        match wechat.query_room_role(roomid.to_string()) {
+            Ok(Some(role)) => role.owner == wxid,
+            _ => false,
+        }
+    }
+}
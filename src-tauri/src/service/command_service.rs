@@ -0,0 +1,274 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use log::{info, warn};
+use quickxml_to_serde::{xml_string_to_json, Config};
+
+use crate::{
+    service::{
+        global_service::GLOBAL, moderation_service, pause_service, quiet_hours_service, raffle_service,
+        risk_guard_service::RiskOperation,
+    },
+    utils::{mention, pacing},
+    wcferry::{
+        wcf::{MemberMgmt, TextMsg, WxMsg},
+        WeChat,
+    },
+};
+
+/// 群发时两条消息的间隔
+const BROADCAST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 一次指令调用
+pub struct CommandContext<'a> {
+    pub wechat: &'a WeChat,
+    pub msg: &'a WxMsg,
+    /// 指令名之后的文本
+    pub args: String,
+}
+
+/// 群聊指令
+pub struct Command {
+    pub name: &'static str,
+    pub desc: &'static str,
+    /// 特权指令只有群主和授权名单中的成员可以执行
+    pub privileged: bool,
+    pub run: fn(&CommandContext) -> Result<String, String>,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        desc: "查看可用指令",
+        privileged: false,
+        run: help,
+    },
+    Command {
+        name: "kick",
+        desc: "移出 @ 的成员",
+        privileged: true,
+        run: kick,
+    },
+    Command {
+        name: "raffle",
+        desc: "发起抽奖：关键词 中奖人数 报名分钟数",
+        privileged: true,
+        run: raffle,
+    },
+    Command {
+        name: "draw",
+        desc: "给本群最新的抽奖开奖",
+        privileged: true,
+        run: draw,
+    },
+    Command {
+        name: "broadcast",
+        desc: "把后面的文字发到所有群",
+        privileged: true,
+        run: broadcast,
+    },
+];
+
+/// 拆出指令名和参数，不是指令时返回 None
+pub fn parse(prefix: &str, content: &str) -> Option<(String, String)> {
+    let rest = content.trim().strip_prefix(prefix)?;
+    let mut parts = rest.splitn(2, char::is_whitespace);
+    let name = parts.next().filter(|n| !n.is_empty())?.to_lowercase();
+    let args = parts.next().unwrap_or_default().trim().to_string();
+    Some((name, args))
+}
+
+fn consume(operation: RiskOperation, count: u32) -> Result<(), String> {
+    let global = GLOBAL.get().unwrap();
+    let mut guard = global.risk_guard_service.lock().unwrap();
+    guard.consume(operation, count)
+}
+
+// 消息 xml 中 msgsource.atuserlist 记录了被 @ 的 wxid
+fn at_user_list(xml: &str) -> Vec<String> {
+    let json = match xml_string_to_json(xml.to_string(), &Config::new_with_defaults()) {
+        Ok(json) => json,
+        Err(_) => return vec![],
+    };
+    json.get("msgsource")
+        .and_then(|s| s.get("atuserlist"))
+        .and_then(|l| l.as_str())
+        .unwrap_or_default()
+        .split(',')
+        .map(|w| w.trim().to_string())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn help(_ctx: &CommandContext) -> Result<String, String> {
+    let prefix = GLOBAL.get().unwrap().wechat_config.read().unwrap().command.prefix.clone();
+    // 回复不能以前缀开头
+    let mut lines = vec![format!("可用指令（以 {} 开头）：", prefix)];
+    lines.extend(
+        COMMANDS
+            .iter()
+            .map(|c| format!("{}{} {}{}", prefix, c.name, c.desc, if c.privileged { "（需授权）" } else { "" })),
+    );
+    Ok(lines.join("\n"))
+}
+
+fn kick(ctx: &CommandContext) -> Result<String, String> {
+    let roomid = &ctx.msg.roomid;
+    let me = ctx.wechat.get_self_wxid().map_err(|e| e.to_string())?;
+    let mut targets = at_user_list(&ctx.msg.xml);
+    if targets.is_empty() {
+        // 没有 atuserlist 时按群昵称匹配
+        let members = ctx
+            .wechat
+            .query_room_member(roomid.clone())
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default();
+        let names: Vec<(String, String)> = members.into_iter().map(|m| (m.wxid, m.name)).collect();
+        // 移出成员不可撤销，昵称必须能唯一确定一个人
+        for m in mention::extract(&ctx.args) {
+            let i = mention::unique_match(&m, &names).ok_or_else(|| format!("找不到唯一匹配 {} 的成员，请直接 @ 对方", m))?;
+            targets.push(names[i].0.clone());
+        }
+    }
+    targets.retain(|w| *w != me && *w != ctx.msg.sender);
+    targets.sort();
+    targets.dedup();
+    if targets.is_empty() {
+        return Err("请 @ 要移出的成员".to_string());
+    }
+    consume(RiskOperation::GroupKick, targets.len() as u32)?;
+    let ok = ctx
+        .wechat
+        .delete_chatroom_member(MemberMgmt {
+            roomid: roomid.clone(),
+            wxids: targets.join(","),
+        })
+        .map_err(|e| e.to_string())?;
+    if ok {
+        Ok(format!("已移出 {} 人", targets.len()))
+    } else {
+        Err("移出失败".to_string())
+    }
+}
+
+fn broadcast(ctx: &CommandContext) -> Result<String, String> {
+    if ctx.args.is_empty() {
+        return Err("请在指令后写上要群发的内容".to_string());
+    }
+    let rooms: Vec<String> = ctx
+        .wechat
+        .get_contacts()
+        .map_err(|e| e.to_string())?
+        .contacts
+        .into_iter()
+        .map(|c| c.wxid)
+        .filter(|w| w.ends_with("@chatroom") && *w != ctx.msg.roomid)
+        .collect();
+    consume(RiskOperation::BulkSend, rooms.len() as u32)?;
+    let (mut sent, mut queued) = (0, 0);
+    for room in &rooms {
+        // 群发途中暂停时立即停下
+        if let Err(e) = pause_service::check() {
+            return Err(format!("已发送到 {} 个群后中止: {}", sent, e));
+        }
+        let text = TextMsg {
+            msg: ctx.args.clone(),
+            receiver: room.clone(),
+            aters: String::new(),
+        };
+        match quiet_hours_service::send_text(ctx.wechat, text) {
+            Ok(true) => sent += 1,
+            Ok(false) => queued += 1,
+            Err(e) => warn!("群发到 {} 失败: {}", room, e),
+        }
+        thread::sleep(pacing::interval(BROADCAST_INTERVAL));
+    }
+    if queued > 0 {
+        return Ok(format!("已发送到 {}/{} 个群，{} 个群处于免打扰时段，稍后发送", sent, rooms.len(), queued));
+    }
+    Ok(format!("已发送到 {}/{} 个群", sent, rooms.len()))
+}
+
+fn raffle(ctx: &CommandContext) -> Result<String, String> {
+    let usage = "用法：关键词 中奖人数 报名分钟数";
+    let parts: Vec<&str> = ctx.args.split_whitespace().collect();
+    let (keyword, count, minutes) = match parts.as_slice() {
+        [keyword, count, minutes] => (*keyword, count.parse().map_err(|_| usage)?, minutes.parse().map_err(|_| usage)?),
+        _ => return Err(usage.to_string()),
+    };
+    let created = {
+        let global = GLOBAL.get().unwrap();
+        let mut raffles = global.raffle_service.lock().unwrap();
+        raffles.create(&ctx.msg.roomid, keyword, count, minutes)?
+    };
+    raffle_service::announce(ctx.wechat, &created)?;
+    Ok(format!("抽奖 #{} 已创建", created.id))
+}
+
+fn draw(ctx: &CommandContext) -> Result<String, String> {
+    let drawn = {
+        let global = GLOBAL.get().unwrap();
+        let mut raffles = global.raffle_service.lock().unwrap();
+        let id = raffles.latest_open(&ctx.msg.roomid).ok_or("本群没有进行中的抽奖")?;
+        raffles.draw(id)?
+    };
+    raffle_service::announce_draw(ctx.wechat, &drawn)?;
+    Ok(format!("抽奖 #{} 已开奖，种子 {}", drawn.id, drawn.draw.map(|d| d.seed).unwrap_or_default()))
+}
+
+/// 处理一条消息，是指令时执行并把结果回复到原会话
+pub fn dispatch(wechat: Arc<Mutex<WeChat>>, msg: WxMsg, prefix: &str) {
+    if msg.is_self {
+        return;
+    }
+    let (name, args) = match parse(prefix, &msg.content) {
+        Some(parsed) => parsed,
+        None => return,
+    };
+    let command = match COMMANDS.iter().find(|c| c.name == name) {
+        Some(command) => command,
+        None => return,
+    };
+    if let Err(e) = pause_service::check() {
+        info!("忽略指令 {}: {}", name, e);
+        return;
+    }
+    // 群发耗时较长，复制一份连接，不长时间占用锁
+    let wc = wechat.lock().unwrap().clone();
+    let sender = msg.sender.clone();
+    let reply = if command.privileged && !msg.roomid.ends_with("@chatroom") {
+        Err("该指令只能在群里使用".to_string())
+    } else if command.privileged && !is_allowed(&wc, &msg.roomid, &sender) {
+        Err("没有权限".to_string())
+    } else {
+        info!("{} 执行指令 {} {}", sender, name, args);
+        (command.run)(&CommandContext {
+            wechat: &wc,
+            msg: &msg,
+            args,
+        })
+    };
+    let text = match reply {
+        Ok(text) => text,
+        Err(e) => format!("指令 {} 失败: {}", name, e),
+    };
+    let reply = TextMsg {
+        msg: text,
+        receiver: msg.roomid.clone(),
+        aters: String::new(),
+    };
+    if let Err(e) = moderation_service::send_text(&wc, reply) {
+        warn!("指令 {} 的回复未发送: {}", name, e);
+    }
+}
+
+// 同一个人的任一账号有权限即可
+fn is_allowed(wechat: &WeChat, roomid: &str, wxid: &str) -> bool {
+    let global = GLOBAL.get().unwrap();
+    let wxids = global.identity_service.lock().unwrap().wxids(wxid);
+    let permissions = global.command_permission_service.lock().unwrap();
+    wxids.iter().any(|w| permissions.is_allowed(wechat, roomid, w))
+}
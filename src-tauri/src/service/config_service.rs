@@ -0,0 +1,141 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use chrono::{DateTime, Local};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{
+        command_permission_service::CommandPermissionService, global_service::GLOBAL,
+        rule_group_service::RuleGroupService, template_service::TemplateService,
+    },
+    utils::state_store,
+    wechat_config::WechatConfig,
+};
+
+/// 导出包格式版本
+const BUNDLE_FORMAT: u32 = 1;
+
+/// 随配置一起导出的状态文件，只包含人工维护的设置，不包含计数、队列等运行数据
+const CONFIG_STATES: &[&str] = &["command_permissions", "rule_groups", "templates"];
+
+const CONFIG_PATH: &str = ".\\config.json5";
+
+/// 完整配置的导出包
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct ConfigBundle {
+    pub format: u32,
+    /// 导出时的客户端版本
+    pub client_version: String,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub exported_at: DateTime<Local>,
+    /// config.json5 的内容
+    #[schema(value_type = Object)]
+    pub config: Value,
+    /// 状态文件名 -> 内容
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub state: BTreeMap<String, Value>,
+}
+
+/// 写入 config.json5 并替换内存中的配置
+pub fn save_config(config: &WechatConfig) -> Result<(), String> {
+    let text = serde_json::to_string(config).map_err(|e| e.to_string())?;
+    fs::write(CONFIG_PATH, text).map_err(|e| format!("写入配置文件失败: {}", e))?;
+    let global = GLOBAL.get().unwrap();
+    *global.wechat_config.write().unwrap() = config.clone();
+    Ok(())
+}
+
+pub fn export() -> Result<ConfigBundle, String> {
+    let config = {
+        let global = GLOBAL.get().unwrap();
+        let config = global.wechat_config.read().unwrap();
+        serde_json::to_value(&*config).map_err(|e| e.to_string())?
+    };
+    let state = CONFIG_STATES
+        .iter()
+        .map(|name| (name.to_string(), state_store::load::<Value>(name)))
+        .filter(|(_, value)| !value.is_null())
+        .collect();
+    Ok(ConfigBundle {
+        format: BUNDLE_FORMAT,
+        client_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: Local::now(),
+        config,
+        state,
+    })
+}
+
+/// 导入配置包，先全部校验通过再写入
+pub fn import(bundle: ConfigBundle) -> Result<(), String> {
+    if bundle.format > BUNDLE_FORMAT {
+        return Err(format!("不支持的导出包格式 {}，请先升级客户端", bundle.format));
+    }
+    // 旧版本导出的包缺少新字段时按默认值补齐
+    let config: WechatConfig =
+        serde_json::from_value(bundle.config).map_err(|e| format!("配置内容不合法: {}", e))?;
+    if let Some(name) = bundle.state.keys().find(|k| !CONFIG_STATES.contains(&k.as_str())) {
+        return Err(format!("未知的状态文件: {}", name));
+    }
+    save_config(&config)?;
+    for (name, value) in &bundle.state {
+        state_store::save(name, value)?;
+    }
+    // 重新读取导入的状态
+    let global = GLOBAL.get().unwrap();
+    *global.command_permission_service.lock().unwrap() = CommandPermissionService::new();
+    *global.rule_group_service.lock().unwrap() = RuleGroupService::new();
+    *global.template_service.lock().unwrap() = TemplateService::new();
+    info!("已导入配置，来自客户端 {}，导出于 {}", bundle.client_version, bundle.exported_at);
+    Ok(())
+}
+
+fn profile_dir() -> PathBuf {
+    PathBuf::from(".").join("data").join("profiles")
+}
+
+fn profile_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("配置方案名称不合法: {}", name));
+    }
+    Ok(profile_dir().join(format!("{}.json", name)))
+}
+
+/// 已保存的配置方案名称
+pub fn list_profiles() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(profile_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+                .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// 把当前配置保存为方案
+pub fn save_profile(name: &str) -> Result<ConfigBundle, String> {
+    let path = profile_path(name)?;
+    let bundle = export()?;
+    fs::create_dir_all(profile_dir()).map_err(|e| format!("创建目录失败: {}", e))?;
+    let text = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    fs::write(&path, text).map_err(|e| format!("保存配置方案失败: {}", e))?;
+    Ok(bundle)
+}
+
+/// 切换到已保存的方案
+pub fn apply_profile(name: &str) -> Result<(), String> {
+    let path = profile_path(name)?;
+    let text = fs::read_to_string(&path).map_err(|_| format!("配置方案不存在: {}", name))?;
+    let bundle: ConfigBundle = serde_json::from_str(&text).map_err(|e| format!("配置方案已损坏: {}", e))?;
+    import(bundle)?;
+    info!("已切换到配置方案 {}", name);
+    Ok(())
+}
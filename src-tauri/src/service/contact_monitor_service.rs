@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use tokio::task::JoinHandle;
+
+use crate::{
+    handler::event_entity::{ContactChange, ContactChangeKind, Event},
+    service::{
+        global_service::GLOBAL,
+        name_history_service::{NameField, Observation},
+    },
+    utils::contact::{self, ContactKind},
+    wcferry::WeChat,
+};
+
+/// 联系人快照中的一项
+#[derive(Clone, PartialEq, Eq)]
+pub struct ContactSnapshot {
+    name: String,
+    remark: String,
+}
+
+/** 定期对比通讯录，发布新增、删除、改名和更换头像事件 */
+pub struct ContactMonitorService {
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl ContactMonitorService {
+    pub fn new() -> Self {
+        ContactMonitorService { handle: None }
+    }
+
+    // 启动服务，interval 为 0 时不启用
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>, interval: u64) {
+        self.stop();
+        if interval == 0 {
+            return;
+        }
+        info!("联系人变化检测启动，间隔 {} 秒", interval);
+        self.handle = Some(tokio::spawn(async move {
+            let mut previous: Option<HashMap<String, ContactSnapshot>> = None;
+            loop {
+                let wc = wechat.clone();
+                let prev = previous.clone();
+                match tokio::task::spawn_blocking(move || scan(&wc, prev.as_ref())).await {
+                    Ok(Ok((current, changes))) => {
+                        if !changes.is_empty() {
+                            debug!("检测到联系人变化: {:?}", changes);
+                            let global = GLOBAL.get().unwrap();
+                            let event_bus = global.msg_event_bus.lock().unwrap();
+                            event_bus.send_message(Event::ContactChanged(changes));
+                        }
+                        previous = Some(current);
+                    }
+                    Ok(Err(e)) => warn!("获取联系人快照失败: {}", e),
+                    Err(e) => warn!("获取联系人快照失败: {}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("联系人变化检测已停止");
+        }
+    }
+}
+
+// 只记录好友和群聊，删除好友后 Contact 表中仍有记录，需结合标志位判断。
+// 每次调用单独加锁，扫描期间其它接口不用等整个快照完成
+fn take_snapshot(wechat: &Arc<Mutex<WeChat>>) -> Result<(HashMap<String, ContactSnapshot>, Vec<Observation>), String> {
+    if !wechat.lock().unwrap().is_login().map_err(|e| e.to_string())? {
+        return Err("微信未登录".to_string());
+    }
+    let flags = wechat.lock().unwrap().query_contact_flags().map_err(|e| e.to_string())?;
+    let contacts = wechat.lock().unwrap().get_contacts().map_err(|e| e.to_string())?;
+    let snapshot: HashMap<String, ContactSnapshot> = contacts
+        .contacts
+        .into_iter()
+        .filter(|c| match contact::classify(&c.wxid) {
+            ContactKind::Friend => flags.get(&c.wxid).map_or(false, |f| f.is_friend()),
+            ContactKind::Room => true,
+            _ => false,
+        })
+        .map(|c| {
+            (
+                c.wxid,
+                ContactSnapshot {
+                    name: c.name,
+                    remark: c.remark,
+                },
+            )
+        })
+        .collect();
+
+    let observe = |wxid: &str, field: NameField, roomid: &str, value: &str| Observation {
+        wxid: wxid.to_string(),
+        field,
+        roomid: roomid.to_string(),
+        value: value.to_string(),
+    };
+    let mut observed = vec![];
+    let mut friends = vec![];
+    for (wxid, c) in snapshot.iter() {
+        observed.push(observe(wxid, NameField::Name, "", &c.name));
+        observed.push(observe(wxid, NameField::Remark, "", &c.remark));
+        if contact::classify(wxid) == ContactKind::Room {
+            // 群成员列表有缓存，群昵称的变化最迟在缓存过期后发现；某个群查询失败时跳过，不影响其它群
+            let members = wechat.lock().unwrap().query_room_member(wxid.clone());
+            match members {
+                Ok(members) => {
+                    for m in members.unwrap_or_default() {
+                        observed.push(observe(&m.wxid, NameField::RoomName, wxid, &m.name));
+                    }
+                }
+                Err(e) => warn!("查询群 {} 的成员失败，跳过: {}", wxid, e),
+            }
+        } else {
+            friends.push(wxid.clone());
+        }
+    }
+    match wechat.lock().unwrap().query_avatars(&friends) {
+        Ok(avatars) => {
+            for (wxid, url) in avatars.iter() {
+                observed.push(observe(wxid, NameField::Avatar, "", url));
+            }
+        }
+        Err(e) => warn!("查询头像失败，本次不检查头像变化: {}", e),
+    }
+    Ok((snapshot, observed))
+}
+
+/// 获取一次快照，与上一次快照对比出新增和删除，与改名记录对比出昵称、备注、群昵称和头像的变化
+pub fn scan(
+    wechat: &Arc<Mutex<WeChat>>,
+    previous: Option<&HashMap<String, ContactSnapshot>>,
+) -> Result<(HashMap<String, ContactSnapshot>, Vec<ContactChange>), String> {
+    let (current, observed) = take_snapshot(wechat)?;
+    let mut changes = previous.map(|p| diff(p, &current)).unwrap_or_default();
+    let global = GLOBAL.get().unwrap();
+    let mut history = global.name_history_service.lock().unwrap();
+    for (o, old) in history.observe(&observed)? {
+        let (name, remark) = current
+            .get(&o.wxid)
+            .map_or((String::new(), String::new()), |c| (c.name.clone(), c.remark.clone()));
+        let mut change = ContactChange {
+            kind: ContactChangeKind::Renamed,
+            wxid: o.wxid.clone(),
+            name,
+            remark,
+            old_name: Some(old.clone()),
+            roomid: None,
+            avatar: None,
+            old_avatar: None,
+        };
+        match o.field {
+            NameField::Name | NameField::Remark => {}
+            NameField::RoomName => {
+                change.name = o.value.clone();
+                change.roomid = Some(o.roomid.clone());
+            }
+            NameField::Avatar => {
+                change.kind = ContactChangeKind::Avatar;
+                change.old_name = None;
+                change.avatar = Some(o.value.clone());
+                change.old_avatar = Some(old);
+            }
+        }
+        changes.push(change);
+    }
+    Ok((current, changes))
+}
+
+fn diff(
+    previous: &HashMap<String, ContactSnapshot>,
+    current: &HashMap<String, ContactSnapshot>,
+) -> Vec<ContactChange> {
+    let change = |kind: ContactChangeKind, wxid: &String, c: &ContactSnapshot| ContactChange {
+        kind,
+        wxid: wxid.clone(),
+        name: c.name.clone(),
+        remark: c.remark.clone(),
+        old_name: None,
+        roomid: None,
+        avatar: None,
+        old_avatar: None,
+    };
+    let mut changes = vec![];
+    for (wxid, now) in current.iter() {
+        if !previous.contains_key(wxid) {
+            changes.push(change(ContactChangeKind::Added, wxid, now));
+        }
+    }
+    for (wxid, before) in previous.iter() {
+        if !current.contains_key(wxid) {
+            changes.push(change(ContactChangeKind::Removed, wxid, before));
+        }
+    }
+    changes
+}
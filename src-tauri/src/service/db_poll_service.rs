@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{info, warn};
+use tokio::task::JoinHandle;
+
+use crate::{
+    handler::event_entity::Event,
+    service::global_service::GLOBAL,
+    utils::dedup,
+    wcferry::WeChat,
+};
+
+/// 每次从一个分库读取的条数
+const POLL_PAGE: usize = 200;
+
+/// 每个 MSG 分库已经检查到的 localId
+pub type Cursors = HashMap<String, i64>;
+
+/// 读取各分库中 localId 之后、入库时间在 since 和 until 之间的消息，
+/// 回调没有送达过的发布到消息总线，返回补发的条数
+pub fn poll_once(wechat: &Arc<Mutex<WeChat>>, cursors: &mut Cursors, since: u32, until: u32) -> Result<usize, String> {
+    // 未登录时读不到消息库
+    if !wechat.lock().unwrap().is_login().unwrap_or(false) {
+        return Ok(0);
+    }
+    let dbs = wechat.lock().unwrap().msg_dbs().map_err(|e| e.to_string())?;
+    let mut published = 0;
+    for db in dbs {
+        let after = cursors.entry(db.clone()).or_insert(0);
+        loop {
+            let page = wechat
+                .lock()
+                .unwrap()
+                .query_history(&db, since, *after, POLL_PAGE)
+                .map_err(|e| format!("读取 {} 失败: {}", db, e))?;
+            let full = page.len() == POLL_PAGE;
+            let mut pending = false;
+            for (local_id, msg) in page {
+                // 还在等待回调送达，下次再检查
+                if msg.ts > until {
+                    pending = true;
+                    break;
+                }
+                *after = local_id;
+                if !dedup::first_seen(msg.id) {
+                    continue;
+                }
+                let global = GLOBAL.get().unwrap();
+                let event_bus = global.msg_event_bus.lock().unwrap();
+                event_bus.send_message(Event::ClientMessage(msg));
+                published += 1;
+            }
+            if pending || !full {
+                break;
+            }
+        }
+    }
+    Ok(published)
+}
+
+/** 数据库轮询：接收回调不可靠时定期检查 MSG 库中的新消息，去重后送入同一消息处理流程 */
+pub struct DbPollService {
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl DbPollService {
+    pub fn new() -> Self {
+        DbPollService { handle: None }
+    }
+
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().db_poll.clone();
+        if !config.enabled {
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_secs.max(1));
+        // 只补收启动之后的消息，更早的用回填接口
+        let since = chrono::Local::now().timestamp() as u32;
+        self.handle = Some(tokio::spawn(async move {
+            let cursors = Arc::new(Mutex::new(Cursors::new()));
+            loop {
+                tokio::time::sleep(interval).await;
+                let grace = GLOBAL.get().unwrap().wechat_config.read().unwrap().db_poll.grace_secs;
+                let until = (chrono::Local::now().timestamp() as u32).saturating_sub(grace as u32);
+                let wc = wechat.clone();
+                let cursors = cursors.clone();
+                let result = tokio::task::spawn_blocking(move || poll_once(&wc, &mut cursors.lock().unwrap(), since, until))
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()));
+                match result {
+                    Ok(0) => {}
+                    Ok(n) => warn!("轮询消息库补收 {} 条回调未送达的消息", n),
+                    Err(e) => warn!("轮询消息库失败: {}", e),
+                }
+            }
+        }));
+        info!("消息数据库轮询已开启，间隔 {} 秒", interval.as_secs());
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("消息数据库轮询已停止");
+        }
+    }
+}
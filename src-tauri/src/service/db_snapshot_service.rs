@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use rusqlite::{types::Value, Connection, OpenFlags};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    service::global_service::GLOBAL,
+    utils::jobs,
+    wcferry::{wcf, WeChat},
+};
+
+/// 复制时每次从微信读取的行数
+const COPY_PAGE: usize = 1000;
+
+/// 一份本地快照
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct SnapshotInfo {
+    /// 微信数据库名，如 MSG0.db
+    pub db: String,
+    pub size: u64,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub created_at: DateTime<Local>,
+}
+
+/// 快照目录，未配置时为程序目录下的 data/snapshots
+pub fn snapshot_dir() -> PathBuf {
+    let dir = GLOBAL.get().unwrap().wechat_config.read().unwrap().sql_snapshot.dir.clone();
+    if dir.is_empty() {
+        PathBuf::from(".").join("data").join("snapshots")
+    } else {
+        PathBuf::from(dir)
+    }
+}
+
+/// 只接受文件名，避免写到快照目录以外
+pub fn check_name(db: &str) -> Result<(), String> {
+    if db.is_empty() || db.contains(['/', '\\']) || db.contains("..") {
+        return Err(format!("数据库名无效: {}", db));
+    }
+    Ok(())
+}
+
+fn snapshot_path(db: &str) -> Result<PathBuf, String> {
+    check_name(db)?;
+    Ok(snapshot_dir().join(db))
+}
+
+fn info_of(path: &Path) -> Option<SnapshotInfo> {
+    let meta = fs::metadata(path).ok()?;
+    Some(SnapshotInfo {
+        db: path.file_name()?.to_string_lossy().to_string(),
+        size: meta.len(),
+        created_at: meta.modified().ok()?.into(),
+    })
+}
+
+/// 已有的快照
+pub fn list() -> Vec<SnapshotInfo> {
+    let mut snapshots: Vec<SnapshotInfo> = fs::read_dir(snapshot_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.extension().map_or(false, |ext| ext == "db"))
+                .filter_map(|p| info_of(&p))
+                .collect()
+        })
+        .unwrap_or_default();
+    snapshots.sort_by(|a, b| a.db.cmp(&b.db));
+    snapshots
+}
+
+/// 是否已有该库的快照
+pub fn exists(db: &str) -> bool {
+    snapshot_path(db).map_or(false, |p| p.is_file())
+}
+
+// wcf 字段类型：1 整数、2 浮点、3 文本、4 二进制、5 空
+fn to_value(field: wcf::DbField) -> Value {
+    let text = || String::from_utf8_lossy(&field.content).to_string();
+    match field.r#type {
+        1 => text().parse().map_or(Value::Null, Value::Integer),
+        2 => text().parse().map_or(Value::Null, Value::Real),
+        4 => Value::Blob(field.content),
+        5 => Value::Null,
+        _ => Value::Text(text()),
+    }
+}
+
+fn to_field(column: &str, value: Value) -> wcf::DbField {
+    let (r#type, content) = match value {
+        Value::Integer(n) => (1, n.to_string().into_bytes()),
+        Value::Real(f) => (2, f.to_string().into_bytes()),
+        Value::Text(s) => (3, s.into_bytes()),
+        Value::Blob(b) => (4, b),
+        Value::Null => (5, vec![]),
+    };
+    wcf::DbField {
+        r#type,
+        column: column.to_string(),
+        content,
+    }
+}
+
+// 分页时的排序，保证翻页之间行的顺序不变；WITHOUT ROWID 的表没有 rowid，按主键顺序存放，扫描顺序本身是稳定的
+fn page_order(table_sql: &str) -> &'static str {
+    if table_sql.to_uppercase().contains("WITHOUT ROWID") {
+        ""
+    } else {
+        " ORDER BY rowid"
+    }
+}
+
+// 分页读取一张表写入本地库，返回复制的行数
+fn copy_table(
+    wechat: &Arc<Mutex<WeChat>>,
+    conn: &Connection,
+    db: &str,
+    table: &wcf::DbTable,
+    job: u64,
+) -> Result<u64, String> {
+    let (table, order) = (table.name.as_str(), page_order(&table.sql));
+    let mut copied = 0;
+    loop {
+        let rows = wechat
+            .lock()
+            .unwrap()
+            .query_sql(wcf::DbQuery {
+                db: db.to_string(),
+                sql: format!("SELECT * FROM \"{}\"{} LIMIT {} OFFSET {}", table, order, COPY_PAGE, copied),
+            })
+            .map_err(|e| format!("读取 {}.{} 失败: {}", db, table, e))?
+            .rows;
+        let count = rows.len();
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        for row in rows {
+            let columns: Vec<String> = row.fields.iter().map(|f| format!("\"{}\"", f.column)).collect();
+            let sql = format!(
+                "INSERT INTO \"{}\" ({}) VALUES ({})",
+                table,
+                columns.join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            let values: Vec<Value> = row.fields.into_iter().map(to_value).collect();
+            tx.execute(&sql, rusqlite::params_from_iter(values)).map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        copied += count as u64;
+        jobs::advance(job, count as u64);
+        if count < COPY_PAGE {
+            return Ok(copied);
+        }
+    }
+}
+
+/// 通过 RPC 分页把微信数据库 db 复制为本地只读快照，已有快照会被替换
+pub fn create(wechat: &Arc<Mutex<WeChat>>, job: u64, db: &str) -> Result<SnapshotInfo, String> {
+    let path = snapshot_path(db)?;
+    fs::create_dir_all(snapshot_dir()).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("tmp");
+    let _ = fs::remove_file(&tmp);
+
+    let tables = wechat.lock().unwrap().get_tables(db.to_string()).map_err(|e| e.to_string())?.tables;
+    if tables.is_empty() {
+        return Err(format!("{} 中没有数据表", db));
+    }
+    let conn = Connection::open(&tmp).map_err(|e| e.to_string())?;
+    let mut copied = 0;
+    for table in &tables {
+        // 全文索引等虚拟表依赖微信内部的扩展，跳过
+        if let Err(e) = conn.execute_batch(&table.sql) {
+            warn!("跳过 {}.{}: {}", db, table.name, e);
+            continue;
+        }
+        copied += copy_table(wechat, &conn, db, table, job)?;
+    }
+    drop(conn);
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    info!("已生成 {} 的快照，{} 张表 {} 行", db, tables.len(), copied);
+    info_of(&path).ok_or_else(|| "读取快照信息失败".to_string())
+}
+
+/// 在快照上只读执行查询，结果与 RPC 查询格式一致
+pub fn query(db: &str, sql: &str) -> Result<wcf::DbRows, String> {
+    let path = snapshot_path(db)?;
+    if !path.is_file() {
+        return Err(format!("{} 还没有快照", db));
+    }
+    let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(|c| c.to_string()).collect();
+    let rows = stmt
+        .query_map([], |row| {
+            let mut fields = Vec::with_capacity(columns.len());
+            for (i, column) in columns.iter().enumerate() {
+                fields.push(to_field(column, row.get::<_, Value>(i)?));
+            }
+            Ok(wcf::DbRow { fields })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(wcf::DbRows { rows })
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, pause_service},
+    utils::{jobs, pacing, send_log::{self, Expect}, state_store},
+    wcferry::{wcf::PathMsg, WeChat},
+};
+
+const STATE_NAME: &str = "distributions";
+
+/// 一个群的投递状态
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    /// 还没发送，或任务中断后待续发
+    Pending,
+    /// 已在消息回调中确认
+    Delivered,
+    /// 不确定是否已发出（超时未在回调中看到，或接口调用中途出错），为避免重复发大文件不再重试
+    Unverified,
+    /// 重试后仍然失败
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct Delivery {
+    pub roomid: String,
+    pub state: DeliveryState,
+    /// 累计发送次数，续发时会重新计数
+    pub attempts: u32,
+    /// 确认后的消息 id
+    pub msg_id: Option<u64>,
+    pub error: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub updated_at: Option<DateTime<Local>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Distribution {
+    id: u64,
+    path: String,
+    // 按提交顺序发送
+    rooms: Vec<String>,
+    deliveries: BTreeMap<String, Delivery>,
+    job: Option<u64>,
+    created_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DistributeState {
+    next_id: u64,
+    distributions: HashMap<u64, Distribution>,
+}
+
+/// 分发任务的投递矩阵
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct DistributionReport {
+    pub id: u64,
+    pub path: String,
+    /// 最近一次运行的后台任务编号，进度通过 /admin/jobs/{id} 查询
+    pub job: Option<u64>,
+    pub total: usize,
+    pub delivered: usize,
+    pub unverified: usize,
+    pub failed: usize,
+    pub pending: usize,
+    /// 按提交顺序排列的每个群的投递情况
+    pub deliveries: Vec<Delivery>,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub created_at: DateTime<Local>,
+}
+
+impl Distribution {
+    fn report(&self) -> DistributionReport {
+        let deliveries: Vec<Delivery> = self.rooms.iter().filter_map(|r| self.deliveries.get(r).cloned()).collect();
+        let count = |state: DeliveryState| deliveries.iter().filter(|d| d.state == state).count();
+        DistributionReport {
+            id: self.id,
+            path: self.path.clone(),
+            job: self.job,
+            total: deliveries.len(),
+            delivered: count(DeliveryState::Delivered),
+            unverified: count(DeliveryState::Unverified),
+            failed: count(DeliveryState::Failed),
+            pending: count(DeliveryState::Pending),
+            deliveries,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/** 文件分发：把一个大文件按批发到多个群，每次发送都等回调确认，失败重试，进度落盘以便中断后续发 */
+pub struct DistributeService {
+    state: DistributeState,
+}
+
+impl DistributeService {
+    pub fn new() -> Self {
+        DistributeService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = state_store::save(STATE_NAME, &self.state) {
+            warn!("保存分发进度失败: {}", e);
+        }
+    }
+
+    /// 登记一次分发，返回编号
+    pub fn create(&mut self, path: &str, rooms: &[String]) -> Result<u64, String> {
+        if !Path::new(path).is_file() {
+            return Err(format!("文件不存在: {}", path));
+        }
+        let mut ordered: Vec<String> = vec![];
+        for roomid in rooms {
+            if !ordered.contains(roomid) {
+                ordered.push(roomid.clone());
+            }
+        }
+        if ordered.is_empty() {
+            return Err("rooms 不能为空".to_string());
+        }
+        self.state.next_id += 1;
+        let id = self.state.next_id;
+        let deliveries = ordered
+            .iter()
+            .map(|roomid| {
+                let delivery = Delivery {
+                    roomid: roomid.clone(),
+                    state: DeliveryState::Pending,
+                    attempts: 0,
+                    msg_id: None,
+                    error: None,
+                    updated_at: None,
+                };
+                (roomid.clone(), delivery)
+            })
+            .collect();
+        self.state.distributions.insert(
+            id,
+            Distribution {
+                id,
+                path: path.to_string(),
+                rooms: ordered,
+                deliveries,
+                job: None,
+                created_at: Local::now(),
+            },
+        );
+        self.save();
+        Ok(id)
+    }
+
+    pub fn report(&self, id: u64) -> Option<DistributionReport> {
+        self.state.distributions.get(&id).map(|d| d.report())
+    }
+
+    /// 准备续发：失败的群重新排队，返回文件路径和待发的群
+    pub fn prepare(&mut self, id: u64, job: u64) -> Result<(String, Vec<String>), String> {
+        let distribution = self.state.distributions.get_mut(&id).ok_or("分发任务不存在")?;
+        distribution.job = Some(job);
+        for delivery in distribution.deliveries.values_mut() {
+            if delivery.state == DeliveryState::Failed {
+                delivery.state = DeliveryState::Pending;
+                delivery.attempts = 0;
+            }
+        }
+        let pending = distribution
+            .rooms
+            .iter()
+            .filter(|r| distribution.deliveries.get(*r).map_or(false, |d| d.state == DeliveryState::Pending))
+            .cloned()
+            .collect();
+        let path = distribution.path.clone();
+        self.save();
+        Ok((path, pending))
+    }
+
+    fn update(&mut self, id: u64, roomid: &str, f: impl FnOnce(&mut Delivery)) {
+        let delivery = self
+            .state
+            .distributions
+            .get_mut(&id)
+            .and_then(|d| d.deliveries.get_mut(roomid));
+        if let Some(delivery) = delivery {
+            f(delivery);
+            delivery.updated_at = Some(Local::now());
+        }
+        self.save();
+    }
+}
+
+// 一次发送的结果
+enum Outcome {
+    /// 在回调中看到了，带消息 id
+    Delivered(u64),
+    /// 不确定是否已发出（超时未见回调，或接口调用中途出错），不再重试以免重复发送
+    Unknown(String),
+    /// 微信明确返回失败，可以重试
+    Failed(String),
+}
+
+// 发送一次并按文件名等待回调确认；没开消息接收时无法确认
+fn send_once(wechat: &Arc<Mutex<WeChat>>, path: &str, roomid: &str, timeout: Duration) -> Outcome {
+    let since = send_log::mark();
+    let (sent, listening) = {
+        let wechat = wechat.lock().unwrap();
+        let sent = wechat.send_file(PathMsg {
+            path: path.to_string(),
+            receiver: roomid.to_string(),
+            base64: String::new(),
+            filename: String::new(),
+        });
+        (sent, wechat.listening.load(Ordering::Relaxed))
+    };
+    let unknown = match sent {
+        Ok(true) => "未能在消息回调中确认".to_string(),
+        Ok(false) => return Outcome::Failed("发送失败".to_string()),
+        // 请求可能已经送达，只是没拿到回复
+        Err(e) => format!("发送结果未知: {}", e),
+    };
+    if !listening {
+        return Outcome::Unknown(unknown);
+    }
+    match send_log::wait(roomid, &Expect::file(path), since, timeout) {
+        Some(msg_id) => Outcome::Delivered(msg_id),
+        None => Outcome::Unknown(unknown),
+    }
+}
+
+/// 在后台任务中依次发送 prepare 返回的群，每批之间暂停，单个群失败按配置重试
+pub fn run(wechat: &Arc<Mutex<WeChat>>, job: u64, id: u64, path: &str, pending: &[String]) -> Result<DistributionReport, String> {
+    let global = GLOBAL.get().unwrap();
+    let config = global.wechat_config.read().unwrap().distribute.clone();
+    jobs::set_total(job, pending.len() as u64);
+    let timeout = Duration::from_secs(config.verify_timeout_secs.max(1));
+    for (i, roomid) in pending.iter().enumerate() {
+        if i > 0 {
+            let pause = if i % config.batch_size.max(1) == 0 {
+                Duration::from_secs(config.batch_pause_secs)
+            } else {
+                Duration::from_millis(config.interval_ms)
+            };
+            std::thread::sleep(pacing::interval(pause));
+        }
+        let mut attempt = 0;
+        loop {
+            // 暂停自动化时剩下的群保留待发状态，恢复后可续发
+            pause_service::check()?;
+            attempt += 1;
+            let result = send_once(wechat, path, roomid, timeout);
+            let retry = matches!(result, Outcome::Failed(_)) && attempt <= config.retries;
+            global.distribute_service.lock().unwrap().update(id, roomid, |d| {
+                d.attempts += 1;
+                match &result {
+                    Outcome::Delivered(msg_id) => {
+                        d.state = DeliveryState::Delivered;
+                        d.msg_id = Some(*msg_id);
+                        d.error = None;
+                    }
+                    Outcome::Unknown(e) => {
+                        d.state = DeliveryState::Unverified;
+                        d.error = Some(e.clone());
+                    }
+                    Outcome::Failed(e) if retry => d.error = Some(e.clone()),
+                    Outcome::Failed(e) => {
+                        d.state = DeliveryState::Failed;
+                        d.error = Some(e.clone());
+                    }
+                }
+            });
+            if !retry {
+                break;
+            }
+            warn!("分发 #{} 发往 {} 失败，第 {} 次重试", id, roomid, attempt);
+            std::thread::sleep(pacing::interval(Duration::from_millis(config.interval_ms)));
+        }
+        jobs::advance(job, 1);
+    }
+    let report = global.distribute_service.lock().unwrap().report(id).ok_or("分发任务不存在")?;
+    info!(
+        "分发 #{} 完成：送达 {}，未确认 {}，失败 {}",
+        id, report.delivered, report.unverified, report.failed
+    );
+    Ok(report)
+}
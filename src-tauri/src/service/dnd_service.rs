@@ -0,0 +1,87 @@
+use std::{
+    collections::HashSet,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::{
+    service::global_service::GLOBAL,
+    wcferry::{wcf::WxMsg, WeChat},
+};
+
+// 微信里设置了免打扰的会话及读取时间，过期后推送消息时重新读取
+static MUTED: Mutex<Option<(Instant, HashSet<String>)>> = Mutex::new(None);
+
+fn read(wechat: &WeChat) -> Result<HashSet<String>, String> {
+    let flags = wechat.query_contact_flags().map_err(|e| e.to_string())?;
+    let muted: HashSet<String> = flags
+        .into_iter()
+        .filter(|(wxid, flags)| flags.is_muted(wxid))
+        .map(|(wxid, _)| wxid)
+        .collect();
+    *MUTED.lock().unwrap() = Some((Instant::now(), muted.clone()));
+    Ok(muted)
+}
+
+// 过期后在后台线程重新读取，当前消息沿用上一次的结果；读取前先更新时间，失败也要等下一个周期再试
+fn muted_in_wechat() -> HashSet<String> {
+    let global = GLOBAL.get().unwrap();
+    let ttl = Duration::from_secs(global.wechat_config.read().unwrap().dnd.refresh_secs);
+    let mut cached = MUTED.lock().unwrap();
+    let muted = cached.as_ref().map(|(_, muted)| muted.clone()).unwrap_or_default();
+    if matches!(cached.as_ref(), Some((at, _)) if at.elapsed() < ttl) {
+        return muted;
+    }
+    *cached = Some((Instant::now(), muted.clone()));
+    drop(cached);
+
+    let wechat = global.wechat_service.lock().unwrap().wechat.clone();
+    thread::spawn(move || {
+        let refreshed = match wechat {
+            Some(wechat) => {
+                let wc = wechat.lock().unwrap().clone();
+                read(&wc)
+            }
+            None => Err("微信未启动".to_string()),
+        };
+        if let Err(e) = refreshed {
+            warn!("读取免打扰设置失败: {}", e);
+        }
+    });
+    muted
+}
+
+/// 会话是否免打扰，配置中的 mute、unmute 优先于微信里的设置
+pub fn is_muted(wxid: &str) -> bool {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().dnd.clone();
+    if config.unmute.iter().any(|w| w == wxid) {
+        return false;
+    }
+    if config.mute.iter().any(|w| w == wxid) {
+        return true;
+    }
+    muted_in_wechat().contains(wxid)
+}
+
+/// 从联系人库重新读取免打扰设置，返回合并配置后免打扰的会话
+pub fn refresh(wechat: &WeChat) -> Result<Vec<String>, String> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().dnd.clone();
+    let mut muted: Vec<String> = read(wechat)?
+        .into_iter()
+        .chain(config.mute)
+        .filter(|w| !config.unmute.contains(w))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    muted.sort();
+    Ok(muted)
+}
+
+/// 该下游是否应跳过这条消息
+pub fn suppressed(sink: &str, msg: &WxMsg) -> bool {
+    let respected = GLOBAL.get().unwrap().wechat_config.read().unwrap().dnd.sinks.iter().any(|s| s == sink);
+    respected && is_muted(&msg.roomid)
+}
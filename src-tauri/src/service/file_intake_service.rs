@@ -0,0 +1,97 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::Local;
+use log::info;
+
+use crate::{
+    service::{backup_service, global_service::GLOBAL, identity_service, media_service, quiet_hours_service},
+    wcferry::{
+        wcf::{TextMsg, WxMsg},
+        WeChat,
+    },
+    wechat_config::FileIntakeRule,
+};
+
+/// 文件消息的类型
+const MSG_TYPE_FILE: u32 = 49;
+
+/// 收到的文件消息的文件名，其他消息返回 None
+pub fn file_name(msg: &WxMsg) -> Option<String> {
+    if msg.r#type != MSG_TYPE_FILE || msg.is_self || msg.extra.is_empty() {
+        return None;
+    }
+    // 卡片、链接等 49 类型消息没有附件，extra 为空或没有扩展名
+    let path = Path::new(&msg.extra);
+    path.extension()?;
+    Some(path.file_name()?.to_string_lossy().to_string())
+}
+
+fn matches(rule: &FileIntakeRule, msg: &WxMsg, name: &str) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    (rule.rooms.is_empty() || rule.rooms.contains(&msg.roomid))
+        && (rule.senders.is_empty() || identity_service::contains(&rule.senders, &msg.sender))
+        && (rule.extensions.is_empty()
+            || rule
+                .extensions
+                .iter()
+                .any(|e| e.trim_start_matches('.').to_lowercase() == extension))
+}
+
+/// 第一条匹配的规则
+pub fn find_rule(msg: &WxMsg) -> Option<FileIntakeRule> {
+    let name = file_name(msg)?;
+    let rules = GLOBAL.get().unwrap().wechat_config.read().unwrap().file_intake.rules.clone();
+    rules.into_iter().find(|r| matches(r, msg, &name))
+}
+
+fn link(rule: &FileIntakeRule, uploaded: String, name: &str) -> String {
+    if rule.link_base.is_empty() {
+        uploaded
+    } else {
+        format!("{}/{}", rule.link_base.trim_end_matches('/'), urlencoding::encode(name))
+    }
+}
+
+/// 下载文件并上传到规则的目标，按 reply 回复链接，返回链接
+pub async fn intake(wechat: &Arc<Mutex<WeChat>>, msg: &WxMsg, rule: FileIntakeRule) -> Result<String, String> {
+    let name = file_name(msg).ok_or("不是文件消息")?;
+    let timeout = GLOBAL.get().unwrap().wechat_config.read().unwrap().file_intake.timeout_secs;
+    let path = media_service::download_attach_file(
+        wechat,
+        msg.id,
+        &msg.extra,
+        &msg.thumb,
+        &msg.extra,
+        Duration::from_secs(timeout.max(1)),
+    )
+    .await?;
+    let wechat = wechat.clone();
+    let msg = msg.clone();
+    tokio::task::spawn_blocking(move || {
+        // 加上时间前缀，避免同名文件互相覆盖
+        let stored = format!("{}-{}", Local::now().format("%Y%m%d%H%M%S"), name);
+        let uploaded = backup_service::put_file(&rule.target, &stored, Path::new(&path))?;
+        let link = link(&rule, uploaded, &stored);
+        info!("已转存 {} 发来的文件 {}: {}", msg.sender, name, link);
+        if !rule.reply.is_empty() {
+            let reply = rule.reply.replace("{name}", &name).replace("{link}", &link);
+            let text = TextMsg {
+                msg: reply,
+                receiver: msg.roomid.clone(),
+                aters: String::new(),
+            };
+            let wc = wechat.lock().unwrap().clone();
+            quiet_hours_service::send_text(&wc, text)?;
+        }
+        Ok(link)
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()))
+}
@@ -2,9 +2,9 @@ use std::{fs, sync::{Arc, Mutex, OnceLock, RwLock}};
 
 use rand::Rng;
 
-use crate::{handler::{message::{event_message_handler::EventMessageHandler, http_message_handler::HttpMessageHandler, log_message_handler::LogMessageHandler, socketio_message_handler::SocketIOMessageHandler}, msg_event_mgr::MsgEventBus, startup::service_handler::HttpServerHandler, startup_event_mgr::StartUpEventBus}, service::http_server_service::HttpServerService, wechat_config::WechatConfig};
+use crate::{handler::{message::{announcement_message_handler::AnnouncementMessageHandler, anomaly_message_handler::AnomalyMessageHandler, checkin_message_handler::CheckinMessageHandler, command_message_handler::CommandMessageHandler, event_message_handler::EventMessageHandler, file_intake_message_handler::FileIntakeMessageHandler, pat_message_handler::PatMessageHandler, store_message_handler::StoreMessageHandler, poll_message_handler::PollMessageHandler, raffle_message_handler::RaffleMessageHandler, receipt_message_handler::ReceiptMessageHandler, rule_message_handler::RuleMessageHandler, session_message_handler::SessionMessageHandler, http_message_handler::HttpMessageHandler, log_message_handler::LogMessageHandler, socketio_message_handler::SocketIOMessageHandler, tap_message_handler::TapMessageHandler}, msg_event_mgr::MsgEventBus, startup::service_handler::HttpServerHandler, startup_event_mgr::StartUpEventBus}, service::http_server_service::HttpServerService, utils::secret, wechat_config::WechatConfig};
 
-use super::{socketio_service::SocketIOService, wechat_service::WechatService};
+use super::{admin_notify_service::AdminNotifyService, announcement_service::AnnouncementService, anomaly_service::AnomalyService, at_all_service::AtAllService, backup_service::BackupService, bridge_identity_service::BridgeIdentityService, bridge_thread_service::BridgeThreadService, checkin_service::CheckinService, command_permission_service::CommandPermissionService, contact_monitor_service::ContactMonitorService, db_poll_service::DbPollService, distribute_service::DistributeService, heartbeat_service::HeartbeatService, identity_service::IdentityService, message_store_service::MessageStoreService, name_history_service::NameHistoryService, pause_service::PauseService, pipe_service::PipeService, poll_service::PollService, quiet_hours_service::QuietHoursService, raffle_service::RaffleService, risk_guard_service::RiskGuardService, rule_group_service::RuleGroupService, rule_var_service::RuleVarService, socketio_service::SocketIOService, template_service::TemplateService, watchdog_service::WatchdogService, wechat_service::WechatService, word_cloud_service::WordCloudService};
 
 
 // 全局参数结构
@@ -14,10 +14,39 @@ pub struct GlobalState {
   pub startup_event_bus: Arc<Mutex<StartUpEventBus>>,
   pub wechat_service: Arc<Mutex<WechatService>>,
   pub http_server_service: Arc<Mutex<HttpServerService>>,
-  pub socketio_service: Arc<Mutex<SocketIOService>>
+  pub socketio_service: Arc<Mutex<SocketIOService>>,
+  pub contact_monitor_service: Arc<Mutex<ContactMonitorService>>,
+  pub risk_guard_service: Arc<Mutex<RiskGuardService>>,
+  pub heartbeat_service: Arc<Mutex<HeartbeatService>>,
+  pub admin_notify_service: Arc<Mutex<AdminNotifyService>>,
+  pub command_permission_service: Arc<Mutex<CommandPermissionService>>,
+  pub poll_service: Arc<Mutex<PollService>>,
+  pub checkin_service: Arc<Mutex<CheckinService>>,
+  pub raffle_service: Arc<Mutex<RaffleService>>,
+  pub quiet_hours_service: Arc<Mutex<QuietHoursService>>,
+  pub pause_service: Arc<Mutex<PauseService>>,
+  pub anomaly_service: Arc<Mutex<AnomalyService>>,
+  pub watchdog_service: Arc<Mutex<WatchdogService>>,
+  pub pipe_service: Arc<Mutex<PipeService>>,
+  pub message_store_service: Arc<Mutex<MessageStoreService>>,
+  pub backup_service: Arc<Mutex<BackupService>>,
+  pub db_poll_service: Arc<Mutex<DbPollService>>,
+  pub word_cloud_service: Arc<Mutex<WordCloudService>>,
+  pub announcement_service: Arc<Mutex<AnnouncementService>>,
+  pub distribute_service: Arc<Mutex<DistributeService>>,
+  pub identity_service: Arc<Mutex<IdentityService>>,
+  pub name_history_service: Arc<Mutex<NameHistoryService>>,
+  pub at_all_service: Arc<Mutex<AtAllService>>,
+  pub bridge_identity_service: Arc<Mutex<BridgeIdentityService>>,
+  pub bridge_thread_service: Arc<Mutex<BridgeThreadService>>,
+  pub rule_group_service: Arc<Mutex<RuleGroupService>>,
+  pub rule_var_service: Arc<Mutex<RuleVarService>>,
+  pub template_service: Arc<Mutex<TemplateService>>,
 }
 // 全局变量
 pub static GLOBAL: OnceLock<Arc<GlobalState>> = OnceLock::new();
+// 用于在后台服务中弹出对话框
+pub static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
 
 
 // 初始化全局变量
@@ -78,18 +107,126 @@ pub fn initialize_global() {
   });
   msg_event_bus.subscribe(socket_io_handler);
 
+  // 原始消息记录
+  let tap_handler = Box::new(TapMessageHandler::new(rng.gen::<u32>().to_string()));
+  msg_event_bus.subscribe(tap_handler);
+
+  // 群聊指令
+  let command_handler = Box::new(CommandMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(command_handler);
+
+  // 群投票计票
+  let poll_handler = Box::new(PollMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(poll_handler);
+
+  // 群通知确认
+  let announcement_handler = Box::new(AnnouncementMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(announcement_handler);
+
+  // 收到的文件自动转存
+  let file_intake_handler = Box::new(FileIntakeMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(file_intake_handler);
+
+  // 票据识别
+  let receipt_handler = Box::new(ReceiptMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(receipt_handler);
+
+  // 拍一拍回应
+  let pat_handler = Box::new(PatMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(pat_handler);
+
+  // 消息规则
+  let rule_handler = Box::new(RuleMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(rule_handler);
+
+  // 群打卡
+  let checkin_handler = Box::new(CheckinMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(checkin_handler);
+
+  // 抽奖报名
+  let raffle_handler = Box::new(RaffleMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(raffle_handler);
+
+  // 消息量异常检测
+  let anomaly_handler = Box::new(AnomalyMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(anomaly_handler);
+
+  // 账号下线处理
+  let session_handler = Box::new(SessionMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(session_handler);
+
+  // 写入本地消息库
+  let store_handler = Box::new(StoreMessageHandler {
+    id: rng.gen::<u32>().to_string(),
+  });
+  msg_event_bus.subscribe(store_handler);
+
 
   log::info!("-------------------微信消息监听初始化 结束--------------------------------");
 
-  let global_state: GlobalState = GlobalState {
+  let global_state = new_global_state(wechat_config, msg_event_bus, startup_event_bus);
+  let _ = GLOBAL.set(Arc::new(global_state));
+}
+
+// 组装全局状态，测试中也用它构造不带处理器的全局变量
+pub fn new_global_state(wechat_config: WechatConfig, msg_event_bus: MsgEventBus, startup_event_bus: StartUpEventBus) -> GlobalState {
+  GlobalState {
     wechat_config: RwLock::new(wechat_config),
     msg_event_bus: Arc::new(Mutex::new(msg_event_bus)),
     startup_event_bus: Arc::new(Mutex::new(startup_event_bus)),
     wechat_service: Arc::new(Mutex::new(WechatService::new(None))),
     http_server_service:  Arc::new(Mutex::new(HttpServerService::new())),
-    socketio_service: Arc::new(Mutex::new(SocketIOService::new()))
-  };
-  let _ = GLOBAL.set(Arc::new(global_state));
+    socketio_service: Arc::new(Mutex::new(SocketIOService::new())),
+    contact_monitor_service: Arc::new(Mutex::new(ContactMonitorService::new())),
+    risk_guard_service: Arc::new(Mutex::new(RiskGuardService::new())),
+    heartbeat_service: Arc::new(Mutex::new(HeartbeatService::new())),
+    admin_notify_service: Arc::new(Mutex::new(AdminNotifyService::new())),
+    command_permission_service: Arc::new(Mutex::new(CommandPermissionService::new())),
+    poll_service: Arc::new(Mutex::new(PollService::new())),
+    checkin_service: Arc::new(Mutex::new(CheckinService::new())),
+    raffle_service: Arc::new(Mutex::new(RaffleService::new())),
+    quiet_hours_service: Arc::new(Mutex::new(QuietHoursService::new())),
+    pause_service: Arc::new(Mutex::new(PauseService::new())),
+    anomaly_service: Arc::new(Mutex::new(AnomalyService::new())),
+    watchdog_service: Arc::new(Mutex::new(WatchdogService::new())),
+    pipe_service: Arc::new(Mutex::new(PipeService::new())),
+    message_store_service: Arc::new(Mutex::new(MessageStoreService::new())),
+    backup_service: Arc::new(Mutex::new(BackupService::new())),
+    db_poll_service: Arc::new(Mutex::new(DbPollService::new())),
+    word_cloud_service: Arc::new(Mutex::new(WordCloudService::new())),
+    announcement_service: Arc::new(Mutex::new(AnnouncementService::new())),
+    distribute_service: Arc::new(Mutex::new(DistributeService::new())),
+    identity_service: Arc::new(Mutex::new(IdentityService::new())),
+    name_history_service: Arc::new(Mutex::new(NameHistoryService::new())),
+    at_all_service: Arc::new(Mutex::new(AtAllService::new())),
+    bridge_identity_service: Arc::new(Mutex::new(BridgeIdentityService::new())),
+    bridge_thread_service: Arc::new(Mutex::new(BridgeThreadService::new())),
+    rule_group_service: Arc::new(Mutex::new(RuleGroupService::new())),
+    rule_var_service: Arc::new(Mutex::new(RuleVarService::new())),
+    template_service: Arc::new(Mutex::new(TemplateService::new())),
+  }
 }
 
 
@@ -104,5 +241,16 @@ fn init_config() -> WechatConfig {
   let file_str = fs::read_to_string(&file_path).unwrap();
 
   let wechat_config: WechatConfig = serde_json::from_str(&file_str).unwrap();
+
+  // 旧配置里的敏感字段是明文，重新保存一次完成加密
+  if secret::take_migration() {
+    match serde_json::to_string(&wechat_config) {
+      Ok(text) => match fs::write(&file_path, text) {
+        Ok(()) => log::info!("已加密配置中的敏感字段"),
+        Err(e) => log::warn!("加密配置中的敏感字段失败: {}", e),
+      },
+      Err(e) => log::warn!("加密配置中的敏感字段失败: {}", e),
+    }
+  }
   wechat_config
 }
\ No newline at end of file
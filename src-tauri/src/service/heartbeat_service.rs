@@ -0,0 +1,87 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+use crate::{service::global_service::GLOBAL, wcferry::WeChat};
+
+/** 定时向外部监控地址上报运行状态，主机宕机后监控方收不到心跳即可告警 */
+pub struct HeartbeatService {
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl HeartbeatService {
+    pub fn new() -> Self {
+        HeartbeatService { handle: None }
+    }
+
+    // 启动服务，url 为空时不启用
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>, url: String, interval: u64) {
+        self.stop();
+        if url.is_empty() {
+            return;
+        }
+        if !url.starts_with("http") {
+            warn!("心跳地址不合法: {}", url);
+            return;
+        }
+        let interval = interval.max(5);
+        info!("心跳上报启动，间隔 {} 秒", interval);
+        let started = Instant::now();
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                let wc = wechat.clone();
+                let url = url.clone();
+                let uptime = started.elapsed().as_secs();
+                let result = tokio::task::spawn_blocking(move || {
+                    let status = collect_status(&wc, uptime);
+                    ureq::post(&url)
+                        .timeout(Duration::from_secs(10))
+                        .send_json(status)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                })
+                .await;
+                match result {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("心跳上报失败: {}", e),
+                    Err(e) => warn!("心跳上报失败: {}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("心跳上报已停止");
+        }
+    }
+}
+
+fn collect_status(wechat: &Arc<Mutex<WeChat>>, uptime: u64) -> Value {
+    let (login, wxid) = {
+        let wc = wechat.lock().unwrap();
+        let login = wc.is_login().unwrap_or(false);
+        let wxid = if login { wc.get_self_wxid().unwrap_or_default() } else { String::new() };
+        (login, wxid)
+    };
+    let global = GLOBAL.get().unwrap();
+    let msg_queue = global.msg_event_bus.lock().unwrap().broadcaster.lock().unwrap().len();
+    json!({
+        "status": if login { "ok" } else { "logged_out" },
+        "login": login,
+        "wxid": wxid,
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime": uptime,
+        "queues": {
+            "msg_event_bus": msg_queue,
+        },
+        "timestamp": chrono::Local::now().timestamp(),
+    })
+}
@@ -1,8 +1,9 @@
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use std::sync::{Arc, Mutex};
 use tokio::sync::oneshot;
 
 use crate::endpoints;
+use crate::service::global_service::GLOBAL;
 use crate::wcferry::WeChat;
 
 pub struct HttpServerService {
@@ -21,7 +22,14 @@ impl HttpServerService {
     pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>, port: u16) -> Result<(), String> {
         info!("HttpServerService 启动");
 
-        let host = [0,0,0,0];
+        let (local_only, no_token) = {
+            let config = GLOBAL.get().unwrap().wechat_config.read().unwrap();
+            (config.local_only, config.api_token.is_empty())
+        };
+        if local_only && no_token {
+            warn!("本地模式下未配置 api_token，所有接口请求都会被拒绝");
+        }
+        let host = if local_only { [127, 0, 0, 1] } else { [0, 0, 0, 0] };
 
         self.wechat = Some(wechat.clone());
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
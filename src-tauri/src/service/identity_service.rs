@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{service::global_service::GLOBAL, utils::state_store};
+
+const STATE_NAME: &str = "identities";
+
+/// 同一个人的多个微信号
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Identity {
+    /// 主账号，统计、导出和规则中都归到这个 wxid 下
+    pub canonical: String,
+    /// 显示名，为空时使用群昵称
+    pub name: String,
+    /// 其他账号，不含主账号
+    pub aliases: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct IdentityState {
+    // canonical -> 身份
+    identities: BTreeMap<String, Identity>,
+}
+
+/** 身份合并：把同一个人的多个 wxid 映射到一个主账号 */
+pub struct IdentityService {
+    state: IdentityState,
+}
+
+impl IdentityService {
+    pub fn new() -> Self {
+        IdentityService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    pub fn list(&self) -> Vec<Identity> {
+        self.state.identities.values().cloned().collect()
+    }
+
+    /// 设置主账号的别名，aliases 为空时删除该身份；一个 wxid 只能属于一个身份
+    pub fn set(&mut self, canonical: &str, name: &str, aliases: &[String]) -> Result<Option<Identity>, String> {
+        let mut ordered: Vec<String> = vec![];
+        for alias in aliases {
+            if alias != canonical && !ordered.contains(alias) {
+                ordered.push(alias.clone());
+            }
+        }
+        for identity in self.state.identities.values().filter(|i| i.canonical != canonical) {
+            if identity.aliases.iter().any(|a| a == canonical) {
+                return Err(format!("{} 已是 {} 的别名", canonical, identity.canonical));
+            }
+            if let Some(taken) = ordered.iter().find(|a| **a == identity.canonical || identity.aliases.contains(a)) {
+                return Err(format!("{} 已属于 {}", taken, identity.canonical));
+            }
+        }
+        let identity = if ordered.is_empty() {
+            self.state.identities.remove(canonical);
+            None
+        } else {
+            let identity = Identity {
+                canonical: canonical.to_string(),
+                name: name.to_string(),
+                aliases: ordered,
+            };
+            self.state.identities.insert(canonical.to_string(), identity.clone());
+            Some(identity)
+        };
+        self.save()?;
+        Ok(identity)
+    }
+
+    /// 包含该 wxid 的身份
+    pub fn find(&self, wxid: &str) -> Option<&Identity> {
+        self.state
+            .identities
+            .values()
+            .find(|i| i.canonical == wxid || i.aliases.iter().any(|a| a == wxid))
+    }
+
+    /// 主账号，没有合并时返回自身
+    pub fn canonical(&self, wxid: &str) -> String {
+        self.find(wxid).map_or_else(|| wxid.to_string(), |i| i.canonical.clone())
+    }
+
+    /// 同一个人的全部 wxid，主账号在前
+    pub fn wxids(&self, wxid: &str) -> Vec<String> {
+        match self.find(wxid) {
+            Some(identity) => std::iter::once(identity.canonical.clone())
+                .chain(identity.aliases.iter().cloned())
+                .collect(),
+            None => vec![wxid.to_string()],
+        }
+    }
+
+    /// 删除包含该 wxid 的身份，返回删除的条数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let canonical = match self.find(wxid) {
+            Some(identity) => identity.canonical.clone(),
+            None => return Ok(0),
+        };
+        self.state.identities.remove(&canonical);
+        self.save()?;
+        Ok(1)
+    }
+}
+
+/// 名单中是否有该 wxid 或同一个人的其他账号，用于按发送人匹配的规则
+pub fn contains(list: &[String], wxid: &str) -> bool {
+    if list.iter().any(|w| w == wxid) {
+        return true;
+    }
+    let identities = GLOBAL.get().unwrap().identity_service.lock().unwrap();
+    identities.wxids(wxid).iter().any(|w| list.contains(w))
+}
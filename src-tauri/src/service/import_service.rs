@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, replay_service},
+    utils::jobs,
+    wcferry::{wcf::WxMsg, WeChat},
+};
+
+/// 回填时每次从 MSG 库读取的条数
+const BACKFILL_PAGE: usize = 1000;
+
+/// 导入文件格式
+#[derive(Debug, Deserialize, Serialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// 带表头的 CSV，列名兼容常见聊天记录导出工具
+    Csv,
+    /// 每行一条 WxMsg 或推送格式，与回放接口相同
+    Ndjson,
+}
+
+#[derive(Serialize, ToSchema, Debug, Default)]
+pub struct ImportReport {
+    /// 解析出的消息数
+    pub parsed: usize,
+    /// 新写入的条数，已存在的消息不会重复写入
+    pub imported: usize,
+    /// 无法解析的行号，从 1 开始
+    pub skipped: Vec<usize>,
+}
+
+fn import_dir() -> PathBuf {
+    PathBuf::from(".").join("data").join("imports")
+}
+
+/// 要导入的本地文件，只能是 data/imports 中的文件，file 为相对该目录的路径
+pub fn resolve_file(file: &str) -> Result<PathBuf, String> {
+    let dir = import_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| format!("创建导入目录 {} 失败: {}", dir.display(), e))?;
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("导入目录 {} 不可用: {}", dir.display(), e))?;
+    let path = dir
+        .join(Path::new(file))
+        .canonicalize()
+        .map_err(|e| format!("文件 {} 不可用: {}", file, e))?;
+    if !path.starts_with(&dir) || !path.is_file() {
+        return Err(format!("文件 {} 不在导入目录 {} 中", file, dir.display()));
+    }
+    Ok(path)
+}
+
+// 每个字段可以使用的列名，不区分大小写
+const COLUMNS: &[(&str, &[&str])] = &[
+    ("id", &["id", "msgsvrid", "msg_id", "msgid"]),
+    ("ts", &["ts", "createtime", "create_time", "time", "strtime"]),
+    ("roomid", &["roomid", "strtalker", "talker", "talkerid", "chat"]),
+    ("sender", &["sender", "from"]),
+    ("is_self", &["is_self", "issender"]),
+    ("type", &["type", "msg_type"]),
+    ("content", &["content", "strcontent", "message", "text"]),
+];
+
+// unix 秒、毫秒或 2024-01-01 12:00:00
+fn parse_time(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Ok(ts) = value.parse::<u64>() {
+        let secs = if ts > 100_000_000_000 { ts / 1000 } else { ts };
+        return u32::try_from(secs).ok();
+    }
+    let time = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok()?;
+    u32::try_from(Local.from_local_datetime(&time).earliest()?.timestamp()).ok()
+}
+
+fn parse_bool(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "是")
+}
+
+// 没有消息 id 时按内容生成，重复导入同一份文件不会产生重复记录
+fn synthetic_id(msg: &WxMsg) -> u64 {
+    let digest = Sha256::digest(format!("{}\n{}\n{}\n{}", msg.ts, msg.roomid, msg.sender, msg.content));
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes) & i64::MAX as u64
+}
+
+/// 解析 CSV，至少需要时间、会话和内容三列
+pub fn parse_csv(text: &str) -> Result<(Vec<WxMsg>, Vec<usize>), String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(text.trim_start_matches('\u{feff}').as_bytes());
+    let headers = reader.headers().map_err(|e| format!("读取表头失败: {}", e))?.clone();
+    let index: HashMap<&str, usize> = COLUMNS
+        .iter()
+        .filter_map(|(field, names)| {
+            let column = headers.iter().position(|h| names.contains(&h.trim().to_lowercase().as_str()))?;
+            Some((*field, column))
+        })
+        .collect();
+    let missing: Vec<&str> = ["ts", "roomid", "content"].into_iter().filter(|f| !index.contains_key(f)).collect();
+    if !missing.is_empty() {
+        return Err(format!("缺少列: {}", missing.join(", ")));
+    }
+
+    let mut messages = vec![];
+    let mut skipped = vec![];
+    for (i, record) in reader.records().enumerate() {
+        // 表头占第 1 行
+        let line = i + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(_) => {
+                skipped.push(line);
+                continue;
+            }
+        };
+        let get = |field: &str| index.get(field).and_then(|&c| record.get(c)).unwrap_or_default().to_string();
+        let ts = match parse_time(&get("ts")) {
+            Some(ts) => ts,
+            None => {
+                skipped.push(line);
+                continue;
+            }
+        };
+        let roomid = get("roomid");
+        if roomid.is_empty() {
+            skipped.push(line);
+            continue;
+        }
+        let is_self = parse_bool(&get("is_self"));
+        let mut sender = get("sender");
+        if sender.is_empty() && !is_self && !roomid.ends_with("@chatroom") {
+            sender = roomid.clone();
+        }
+        let mut msg = WxMsg {
+            is_self,
+            is_group: roomid.ends_with("@chatroom"),
+            id: get("id").trim().parse().unwrap_or(0),
+            r#type: get("type").trim().parse().unwrap_or(1),
+            ts,
+            roomid,
+            content: get("content"),
+            sender,
+            sign: String::new(),
+            thumb: String::new(),
+            extra: String::new(),
+            xml: String::new(),
+        };
+        if msg.id == 0 {
+            msg.id = synthetic_id(&msg);
+        }
+        messages.push(msg);
+    }
+    Ok((messages, skipped))
+}
+
+/// 解析并写入本地消息库
+pub fn import(format: ImportFormat, text: &str) -> Result<ImportReport, String> {
+    let (messages, skipped) = match format {
+        ImportFormat::Csv => parse_csv(text)?,
+        ImportFormat::Ndjson => replay_service::parse_ndjson(text),
+    };
+    if messages.is_empty() {
+        return Err("没有可导入的消息".to_string());
+    }
+    let imported = GLOBAL.get().unwrap().message_store_service.lock().unwrap().insert_many(&messages)?;
+    log::info!("已导入 {} 条历史消息，共解析 {} 条", imported, messages.len());
+    Ok(ImportReport {
+        parsed: messages.len(),
+        imported,
+        skipped,
+    })
+}
+
+/// 把 MSG 分库中 since（unix 秒）以后的消息写入本地消息库，进度记录在任务 job 上，返回新写入的条数
+pub fn backfill(wechat: &Arc<Mutex<WeChat>>, job: u64, since: u32) -> Result<usize, String> {
+    let dbs = wechat.lock().unwrap().msg_dbs().map_err(|e| e.to_string())?;
+    let mut total = 0;
+    for db in &dbs {
+        total += wechat.lock().unwrap().count_history(db, since).map_err(|e| e.to_string())?;
+    }
+    jobs::set_total(job, total);
+
+    let mut imported = 0;
+    for db in &dbs {
+        let mut after = 0;
+        loop {
+            // 每页单独加锁，回填期间其它接口照常可用
+            let page = wechat
+                .lock()
+                .unwrap()
+                .query_history(db, since, after, BACKFILL_PAGE)
+                .map_err(|e| format!("读取 {} 失败: {}", db, e))?;
+            let last = match page.last() {
+                Some((local_id, _)) => *local_id,
+                None => break,
+            };
+            let messages: Vec<WxMsg> = page
+                .into_iter()
+                .map(|(_, mut msg)| {
+                    if msg.id == 0 {
+                        msg.id = synthetic_id(&msg);
+                    }
+                    msg
+                })
+                .collect();
+            imported += GLOBAL.get().unwrap().message_store_service.lock().unwrap().insert_many(&messages)?;
+            jobs::advance(job, messages.len() as u64);
+            if messages.len() < BACKFILL_PAGE {
+                break;
+            }
+            after = last;
+        }
+    }
+    log::info!("已从 {} 个消息分库回填 {} 条消息", dbs.len(), imported);
+    Ok(imported)
+}
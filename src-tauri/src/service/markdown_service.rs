@@ -0,0 +1,394 @@
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{ImageOutputFormat, Rgba, RgbaImage};
+use log::warn;
+
+use crate::{
+    service::{global_service::GLOBAL, media_service, word_cloud_service},
+    utils::markdown::{self, Block, Span},
+};
+
+const PADDING: f32 = 24.0;
+const FONT_SIZE: f32 = 20.0;
+const CODE_SIZE: f32 = 17.0;
+/// 行高与字号的比例
+const LINE_HEIGHT: f32 = 1.5;
+/// 表格单元格、代码块的内边距
+const CELL_PADDING: f32 = 8.0;
+/// 每级列表缩进
+const INDENT: f32 = 24.0;
+
+/// 发送后删除图片前等待的时间，微信要在这之前读完文件
+const KEEP_AFTER_SEND: Duration = Duration::from_secs(60);
+/// 超过这个时间的渲染图片视为残留，下次渲染时删除
+const STALE_AFTER: Duration = Duration::from_secs(3600);
+
+const TEXT: [u8; 3] = [0x24, 0x29, 0x2f];
+const MUTED: [u8; 3] = [0x57, 0x60, 0x6a];
+const LINK: [u8; 3] = [0x09, 0x69, 0xda];
+const BORDER: [u8; 3] = [0xd0, 0xd7, 0xde];
+const SHADE: [u8; 3] = [0xf6, 0xf8, 0xfa];
+
+enum Op {
+    Text {
+        x: f32,
+        baseline: f32,
+        text: String,
+        size: f32,
+        color: [u8; 3],
+        bold: bool,
+    },
+    Fill {
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        color: [u8; 3],
+    },
+}
+
+// 一行中样式相同的一段
+struct Piece {
+    text: String,
+    width: f32,
+    style: Span,
+}
+
+struct Layout<'a> {
+    font: &'a FontVec,
+    width: f32,
+    y: f32,
+    ops: Vec<Op>,
+}
+
+impl Layout<'_> {
+    fn advance(&self, c: char, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.h_advance(scaled.glyph_id(c))
+    }
+
+    fn measure(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.advance(c, size)).sum()
+    }
+
+    // 按宽度逐字折行，保留原有的换行
+    fn wrap(&self, spans: &[Span], size: f32, max_width: f32) -> Vec<Vec<Piece>> {
+        let mut lines: Vec<Vec<Piece>> = vec![vec![]];
+        let mut x = 0.0;
+        for span in spans {
+            for c in span.text.chars() {
+                if c == '\n' {
+                    lines.push(vec![]);
+                    x = 0.0;
+                    continue;
+                }
+                let w = self.advance(c, size);
+                if x + w > max_width && x > 0.0 {
+                    lines.push(vec![]);
+                    x = 0.0;
+                    if c == ' ' {
+                        continue;
+                    }
+                }
+                let line = lines.last_mut().unwrap();
+                match line.last_mut() {
+                    Some(piece) if (piece.style.bold, piece.style.code, piece.style.link) == (span.bold, span.code, span.link) => {
+                        piece.text.push(c);
+                        piece.width += w;
+                    }
+                    _ => line.push(Piece {
+                        text: c.to_string(),
+                        width: w,
+                        style: Span { text: String::new(), ..span.clone() },
+                    }),
+                }
+                x += w;
+            }
+        }
+        lines
+    }
+
+    // 从 y 开始画折好的行，返回画完后的 y
+    fn draw_lines(&mut self, lines: Vec<Vec<Piece>>, x: f32, mut y: f32, size: f32, color: [u8; 3], bold: bool) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let line_height = size * LINE_HEIGHT;
+        let baseline_offset = (line_height - (scaled.ascent() - scaled.descent())) / 2.0 + scaled.ascent();
+        for line in lines {
+            let mut caret = x;
+            for piece in line {
+                if piece.style.code {
+                    self.ops.push(Op::Fill {
+                        x: caret,
+                        y: y + 2.0,
+                        w: piece.width,
+                        h: line_height - 4.0,
+                        color: SHADE,
+                    });
+                }
+                self.ops.push(Op::Text {
+                    x: caret,
+                    baseline: y + baseline_offset,
+                    text: piece.text,
+                    size,
+                    color: if piece.style.link { LINK } else { color },
+                    bold: bold || piece.style.bold,
+                });
+                caret += piece.width;
+            }
+            y += line_height;
+        }
+        y
+    }
+
+    fn text(&mut self, spans: &[Span], x: f32, size: f32, color: [u8; 3], bold: bool) {
+        let lines = self.wrap(spans, size, self.width - PADDING - x);
+        self.y = self.draw_lines(lines, x, self.y, size, color, bold);
+    }
+
+    fn fill(&mut self, x: f32, y: f32, w: f32, h: f32, color: [u8; 3]) {
+        self.ops.push(Op::Fill { x, y, w, h, color });
+    }
+
+    fn block(&mut self, block: &Block) {
+        let content = self.width - PADDING * 2.0;
+        match block {
+            Block::Heading(level, spans) => {
+                let size = [32.0, 28.0, 24.0, 22.0, 20.0, 20.0][level.saturating_sub(1).min(5)];
+                self.text(spans, PADDING, size, TEXT, true);
+                if *level <= 2 {
+                    self.fill(PADDING, self.y + 2.0, content, 1.0, BORDER);
+                    self.y += 4.0;
+                }
+            }
+            Block::Paragraph(spans) => self.text(spans, PADDING, FONT_SIZE, TEXT, false),
+            Block::Quote(spans) => {
+                let top = self.y;
+                self.text(spans, PADDING + 16.0, FONT_SIZE, MUTED, false);
+                self.fill(PADDING, top, 4.0, self.y - top, BORDER);
+            }
+            Block::Item(depth, marker, spans) => {
+                let x = PADDING + *depth as f32 * INDENT;
+                let marker_width = self.measure(marker, FONT_SIZE) + 8.0;
+                let marker = vec![Span {
+                    text: marker.clone(),
+                    ..Span::default()
+                }];
+                let lines = self.wrap(&marker, FONT_SIZE, f32::MAX);
+                self.draw_lines(lines, x, self.y, FONT_SIZE, TEXT, false);
+                // 折行后与第一行的文字对齐
+                self.text(spans, x + marker_width, FONT_SIZE, TEXT, false);
+            }
+            Block::Code(code) => {
+                let top = self.y;
+                let spans: Vec<Span> = vec![Span {
+                    text: code.join("\n"),
+                    ..Span::default()
+                }];
+                let lines = self.wrap(&spans, CODE_SIZE, content - CELL_PADDING * 2.0);
+                let height = lines.len() as f32 * CODE_SIZE * LINE_HEIGHT + CELL_PADDING * 2.0;
+                self.fill(PADDING, top, content, height, SHADE);
+                self.draw_lines(lines, PADDING + CELL_PADDING, top + CELL_PADDING, CODE_SIZE, TEXT, false);
+                self.y = top + height;
+            }
+            Block::Table(rows) => self.table(rows, content),
+            Block::Rule => {
+                self.fill(PADDING, self.y + FONT_SIZE / 2.0, content, 2.0, BORDER);
+                self.y += FONT_SIZE;
+            }
+        }
+    }
+
+    fn table(&mut self, rows: &[Vec<Vec<Span>>], content: f32) {
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        // 按内容的宽度分配列宽，放不下时按比例缩小
+        let mut widths = vec![CELL_PADDING * 4.0; columns];
+        for row in rows {
+            for (i, cell) in row.iter().enumerate() {
+                let text: String = cell.iter().map(|s| s.text.as_str()).collect();
+                let natural = self.measure(&text, FONT_SIZE) + CELL_PADDING * 2.0;
+                widths[i] = widths[i].max(natural);
+            }
+        }
+        let total: f32 = widths.iter().sum();
+        if total > content {
+            for w in widths.iter_mut() {
+                *w = (*w * content / total).max(CELL_PADDING * 4.0);
+            }
+        }
+        let table_width: f32 = widths.iter().sum();
+        let top = self.y;
+        for (r, row) in rows.iter().enumerate() {
+            let cells: Vec<Vec<Vec<Piece>>> = (0..columns)
+                .map(|i| {
+                    let cell = row.get(i).map(Vec::as_slice).unwrap_or_default();
+                    self.wrap(cell, FONT_SIZE, widths[i] - CELL_PADDING * 2.0)
+                })
+                .collect();
+            let height = cells.iter().map(Vec::len).max().unwrap_or(1) as f32 * FONT_SIZE * LINE_HEIGHT + CELL_PADDING * 2.0;
+            if r == 0 {
+                self.fill(PADDING, self.y, table_width, height, SHADE);
+            }
+            let mut x = PADDING;
+            for (i, lines) in cells.into_iter().enumerate() {
+                self.draw_lines(lines, x + CELL_PADDING, self.y + CELL_PADDING, FONT_SIZE, TEXT, r == 0);
+                x += widths[i];
+            }
+            self.fill(PADDING, self.y, table_width + 1.0, 1.0, BORDER);
+            self.y += height;
+        }
+        // 外框和竖线
+        self.fill(PADDING, self.y, table_width + 1.0, 1.0, BORDER);
+        let mut x = PADDING;
+        for w in std::iter::once(0.0).chain(widths.iter().copied()) {
+            x += w;
+            self.fill(x, top, 1.0, self.y - top + 1.0, BORDER);
+        }
+    }
+}
+
+fn draw_text(canvas: &mut RgbaImage, font: &FontVec, op: &Op) {
+    let (x, baseline, text, size, color, bold) = match op {
+        Op::Text {
+            x,
+            baseline,
+            text,
+            size,
+            color,
+            bold,
+        } => (*x, *baseline, text, *size, *color, *bold),
+        Op::Fill { .. } => return,
+    };
+    let scale = PxScale::from(size);
+    let scaled = font.as_scaled(scale);
+    // 没有粗体字体，错开一点重复画一次
+    let offsets: &[f32] = if bold { &[0.0, (size / 24.0).max(0.6)] } else { &[0.0] };
+    for offset in offsets {
+        let mut caret = x + offset;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+            caret += scaled.h_advance(id);
+            let outline = match font.outline_glyph(glyph) {
+                Some(outline) => outline,
+                None => continue,
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= canvas.width() as i32 || py >= canvas.height() as i32 {
+                    return;
+                }
+                let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+                let a = coverage.clamp(0.0, 1.0);
+                for (channel, target) in pixel.0.iter_mut().zip(color) {
+                    *channel = (*channel as f32 * (1.0 - a) + target as f32 * a) as u8;
+                }
+            });
+        }
+    }
+}
+
+fn fill(canvas: &mut RgbaImage, x: f32, y: f32, w: f32, h: f32, color: [u8; 3]) {
+    let (x0, y0) = (x.max(0.0) as u32, y.max(0.0) as u32);
+    let x1 = ((x + w).max(0.0) as u32).min(canvas.width());
+    let y1 = ((y + h).max(0.0) as u32).min(canvas.height());
+    for py in y0..y1 {
+        for px in x0..x1 {
+            canvas.put_pixel(px, py, Rgba([color[0], color[1], color[2], 255]));
+        }
+    }
+}
+
+/// 把 Markdown 渲染为 PNG，高度随内容变化，超过 max_height 的部分会被截掉
+pub fn render(text: &str, width: u32, max_height: u32, font_path: &str) -> Result<Vec<u8>, String> {
+    let blocks = markdown::parse(text);
+    if blocks.is_empty() {
+        return Err("内容为空".to_string());
+    }
+    let font = word_cloud_service::load_font(font_path)?;
+    let mut layout = Layout {
+        font: &font,
+        width: width as f32,
+        y: PADDING,
+        ops: vec![],
+    };
+    for (i, block) in blocks.iter().enumerate() {
+        // 相邻的列表项靠得近一些
+        layout.y += match (i.checked_sub(1).map(|p| &blocks[p]), block) {
+            (None, _) => 0.0,
+            (Some(Block::Item(..)), Block::Item(..)) => 4.0,
+            _ => FONT_SIZE * 0.6,
+        };
+        layout.block(block);
+    }
+    let height = (layout.y + PADDING).ceil() as u32;
+    if height > max_height {
+        warn!("Markdown 图片高度 {} 超过上限 {}，已截断", height, max_height);
+    }
+    let mut canvas = RgbaImage::from_pixel(width, height.min(max_height), Rgba([255, 255, 255, 255]));
+    // 先画背景和线，再画文字
+    for op in &layout.ops {
+        if let Op::Fill { x, y, w, h, color } = op {
+            fill(&mut canvas, *x, *y, *w, *h, *color);
+        }
+    }
+    for op in &layout.ops {
+        draw_text(&mut canvas, &font, op);
+    }
+    let mut png = Vec::new();
+    canvas
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|e| format!("生成图片失败: {}", e))?;
+    Ok(png)
+}
+
+// 删除之前没来得及删掉的渲染图片，例如发送后程序退出了
+fn sweep(dir: &Path) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map_or(false, |t| t.elapsed().map_or(false, |age| age > STALE_AFTER));
+        if name.starts_with("markdown-") && name.ends_with(".png") && stale {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// 按 markdown 配置渲染并保存到文件目录，返回图片路径；width 为空时使用配置的宽度。
+/// 图片发送后用 remove_later 删除
+pub fn render_to_file(text: &str, width: Option<u32>) -> Result<PathBuf, String> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().markdown.clone();
+    let png = render(text, width.unwrap_or(config.width), config.max_height, &config.font)?;
+    let dir = media_service::media_dir();
+    sweep(&dir);
+    let path = dir.join(format!("markdown-{}.png", uuid::Uuid::new_v4().simple()));
+    fs::write(&path, png).map_err(|e| format!("保存图片失败: {}", e))?;
+    Ok(path)
+}
+
+/// 等微信读完后在后台删除渲染的图片
+pub fn remove_later(path: PathBuf) {
+    thread::spawn(move || {
+        thread::sleep(KEEP_AFTER_SEND);
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("删除 {} 失败: {}", path.display(), e);
+        }
+    });
+}
@@ -0,0 +1,227 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use local_ip_address::local_ip;
+use quickxml_to_serde::{xml_string_to_json, Config};
+use serde_json::Value;
+
+use crate::{
+    service::global_service::GLOBAL,
+    utils::file_watcher,
+    wcferry::{
+        wcf::{self, AttachMsg, DecPath},
+        WeChat,
+    },
+};
+
+/// 表情消息类型
+const MSG_TYPE_EMOTION: u32 = 47;
+
+const EMOTION_EXTENSIONS: [&str; 4] = ["gif", "png", "jpg", "webp"];
+
+/// 媒体文件存放目录，未配置 file_dir 时使用系统临时目录
+pub fn media_dir() -> PathBuf {
+    let global = GLOBAL.get().unwrap();
+    let file_dir = global.wechat_config.read().unwrap().file_dir.clone();
+    if file_dir.is_empty() {
+        std::env::temp_dir()
+    } else {
+        PathBuf::from(file_dir)
+    }
+}
+
+/// 表情缓存目录
+pub fn emotion_dir() -> PathBuf {
+    media_dir().join("emotion")
+}
+
+/// md5 只允许十六进制字符，避免拼接出任意路径
+pub fn is_valid_md5(md5: &str) -> bool {
+    !md5.is_empty() && md5.len() <= 64 && md5.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// 查找已缓存的表情文件
+pub fn find_cached_emotion(md5: &str) -> Option<PathBuf> {
+    if !is_valid_md5(md5) {
+        return None;
+    }
+    let dir = emotion_dir();
+    EMOTION_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", md5, ext)))
+        .find(|p| p.exists())
+}
+
+/// 解析表情消息中的 md5 和 cdnurl
+pub fn parse_emotion(content: &str) -> Option<(String, String)> {
+    let json = xml_string_to_json(content.to_string(), &Config::new_with_defaults()).ok()?;
+    let emoji = json.get("msg")?.get("emoji")?;
+    let md5 = attr(emoji, "md5")?;
+    let cdnurl = attr(emoji, "cdnurl")?;
+    if !is_valid_md5(&md5) || !cdnurl.starts_with("http") {
+        return None;
+    }
+    Some((md5, cdnurl))
+}
+
+fn attr(node: &Value, name: &str) -> Option<String> {
+    match node.get(format!("@{}", name))? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// 下载并缓存表情，已缓存则直接返回
+pub async fn cache_emotion(md5: &str, cdnurl: &str) -> Result<PathBuf, String> {
+    if let Some(path) = find_cached_emotion(md5) {
+        return Ok(path);
+    }
+
+    let response = reqwest::get(cdnurl)
+        .await
+        .map_err(|e| format!("下载表情失败: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("下载表情失败，状态码: {}", response.status()));
+    }
+    let extension = match response
+        .headers()
+        .get("content-type")
+        .and_then(|val| val.to_str().ok())
+        .unwrap_or("image/gif")
+    {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/webp" => "webp",
+        _ => "gif",
+    };
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("读取表情内容失败: {}", e))?;
+
+    let dir = emotion_dir();
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("创建目录失败: {}", e))?;
+    // 先写临时文件再改名，避免并发下载时读到半个文件
+    let path = dir.join(format!("{}.{}", md5, extension));
+    let tmp = dir.join(format!("{}.{}.tmp", md5, uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp, &bytes)
+        .await
+        .map_err(|e| format!("保存表情失败: {}", e))?;
+    tokio::fs::rename(&tmp, &path)
+        .await
+        .map_err(|e| format!("保存表情失败: {}", e))?;
+    Ok(path)
+}
+
+/// 本机 http 服务上的表情访问地址
+pub fn emotion_url(md5: &str) -> String {
+    let global = GLOBAL.get().unwrap();
+    let port = global.wechat_config.read().unwrap().http_server_port;
+    let host = local_ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string());
+    format!("http://{}:{}/emotion/{}", host, port, md5)
+}
+
+/// 如果是表情消息，缓存表情并返回可访问的地址
+pub async fn resolve_emotion_url(msg: &wcf::WxMsg) -> Option<String> {
+    if msg.r#type != MSG_TYPE_EMOTION {
+        return None;
+    }
+    let (md5, cdnurl) = parse_emotion(&msg.content)?;
+    match cache_emotion(&md5, &cdnurl).await {
+        Ok(_) => Some(emotion_url(&md5)),
+        Err(e) => {
+            log::warn!("缓存表情失败: {}", e);
+            None
+        }
+    }
+}
+
+/// 下载附件（视频、文件）并等待 target 落盘，返回 target
+pub async fn download_attach_file(
+    wechat: &Arc<Mutex<WeChat>>,
+    id: u64,
+    extra: &str,
+    thumb: &str,
+    target: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    if Path::new(target).exists() {
+        return Ok(target.to_string());
+    }
+
+    let att = AttachMsg {
+        id,
+        thumb: thumb.to_string(),
+        extra: extra.to_string(),
+    };
+
+    let status = {
+        let wc = wechat.lock().unwrap();
+        wc.download_attach(att).map_err(|e| e.to_string())?
+    };
+    if !status {
+        return Err("下载失败".to_string());
+    }
+
+    if !file_watcher::wait_for_file(Path::new(target), timeout).await? {
+        return Err("下载超时".to_string());
+    }
+    Ok(target.to_string())
+}
+
+/// 下载并解密图片，返回解密后的文件路径
+///
+/// 通过监听附件目录等待加密文件落盘，不在等待期间持有 WeChat 锁
+pub async fn download_and_decrypt_image(
+    wechat: &Arc<Mutex<WeChat>>,
+    id: u64,
+    extra: &str,
+    dir: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    let att = AttachMsg {
+        id,
+        thumb: "".to_string(),
+        extra: extra.to_string(),
+    };
+
+    let status = {
+        let wc = wechat.lock().unwrap();
+        wc.download_attach(att).map_err(|e| e.to_string())?
+    };
+    if !status {
+        return Err("下载失败".to_string());
+    }
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    if !file_watcher::wait_for_file(Path::new(extra), timeout).await? {
+        return Err("下载超时".to_string());
+    }
+
+    // 文件刚落盘时可能尚未写完，解密失败则短暂重试直至超时
+    loop {
+        let path = {
+            let wc = wechat.lock().unwrap();
+            wc.decrypt_image(DecPath {
+                src: extra.to_string(),
+                dst: dir.to_string(),
+            })
+            .map_err(|e| e.to_string())?
+        };
+        if !path.is_empty() {
+            return Ok(path);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err("下载超时".to_string());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
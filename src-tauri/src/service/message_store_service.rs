@@ -0,0 +1,485 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use rusqlite::{params_from_iter, types::Value, Connection, Row};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, media_service},
+    utils::{redact, secret::Secret, state_store},
+    wcferry::wcf::WxMsg,
+    wechat_config::MessageStoreConfig,
+};
+
+/// 数据库密钥的状态文件名，密钥用 DPAPI 加密保存
+const KEY_STATE: &str = "message_store_key";
+
+/// 按保留期限清理的间隔
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    ts INTEGER NOT NULL,
+    roomid TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    is_self INTEGER NOT NULL,
+    is_group INTEGER NOT NULL,
+    type INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    xml TEXT NOT NULL,
+    extra TEXT NOT NULL,
+    thumb TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_messages_room_ts ON messages (roomid, ts);";
+
+const INSERT_SQL: &str =
+    "INTO messages (id, ts, roomid, sender, is_self, is_group, type, content, xml, extra, thumb)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+
+const SELECT_SQL: &str = "SELECT id, ts, roomid, sender, is_self, is_group, type, content, xml, extra, thumb FROM messages";
+
+fn row_msg(row: &Row) -> rusqlite::Result<WxMsg> {
+    Ok(WxMsg {
+        id: row.get::<_, i64>(0)? as u64,
+        ts: row.get(1)?,
+        roomid: row.get(2)?,
+        sender: row.get(3)?,
+        is_self: row.get(4)?,
+        is_group: row.get(5)?,
+        r#type: row.get(6)?,
+        content: row.get(7)?,
+        xml: row.get(8)?,
+        extra: row.get(9)?,
+        thumb: row.get(10)?,
+        sign: String::new(),
+    })
+}
+
+fn insert_params(msg: &WxMsg) -> Vec<Value> {
+    vec![
+        Value::Integer(msg.id as i64),
+        Value::Integer(msg.ts as i64),
+        Value::Text(msg.roomid.clone()),
+        Value::Text(msg.sender.clone()),
+        Value::Integer(msg.is_self as i64),
+        Value::Integer(msg.is_group as i64),
+        Value::Integer(msg.r#type as i64),
+        Value::Text(msg.content.clone()),
+        Value::Text(msg.xml.clone()),
+        Value::Text(msg.extra.clone()),
+        Value::Text(msg.thumb.clone()),
+    ]
+}
+
+/// 清理条件，时间为 unix 秒，范围为 [from, to)
+#[derive(Debug, Default, Clone)]
+pub struct PurgeFilter {
+    pub roomid: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl PurgeFilter {
+    fn is_empty(&self) -> bool {
+        self.roomid.is_none() && self.from.is_none() && self.to.is_none()
+    }
+
+    // WHERE 条件和参数，调用前需确认条件不为空
+    fn clause(&self) -> (String, Vec<Value>) {
+        let mut clauses = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(roomid) = &self.roomid {
+            clauses.push("roomid = ?");
+            values.push(Value::Text(roomid.clone()));
+        }
+        if let Some(from) = self.from {
+            clauses.push("ts >= ?");
+            values.push(Value::Integer(from));
+        }
+        if let Some(to) = self.to {
+            clauses.push("ts < ?");
+            values.push(Value::Integer(to));
+        }
+        (clauses.join(" AND "), values)
+    }
+}
+
+#[derive(Serialize, ToSchema, Default, Clone)]
+pub struct PurgeReport {
+    /// 删除的消息数
+    pub messages: usize,
+    /// 删除的媒体文件数
+    pub media_files: usize,
+}
+
+fn db_path(config: &MessageStoreConfig) -> PathBuf {
+    if config.path.is_empty() {
+        PathBuf::from(".").join("data").join("messages.db")
+    } else {
+        PathBuf::from(&config.path)
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StoreKey {
+    key: Secret,
+}
+
+// 读取数据库密钥，只有密钥文件不存在时才随机生成；文件存在但读不出密钥时报错，重新生成会让已有的加密库无法打开
+fn store_key() -> Result<String, String> {
+    match state_store::try_load::<StoreKey>(KEY_STATE)? {
+        Some(state) if !state.key.is_empty() => return Ok(state.key.expose().to_string()),
+        Some(_) => {
+            return Err("消息库密钥无法解密（可能来自其它电脑或用户），请恢复原来的密钥文件".to_string());
+        }
+        None => {}
+    }
+    let bytes: [u8; 32] = rand::random();
+    let key: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    state_store::save(
+        KEY_STATE,
+        &StoreKey {
+            key: Secret::new(key.clone()),
+        },
+    )?;
+    info!("已生成消息库密钥");
+    Ok(key)
+}
+
+// 打开加密的数据库，原来是明文库时先导出为加密库再替换
+fn open_encrypted(path: &Path, key: &str) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(|e| format!("打开消息库失败: {}", e))?;
+    conn.pragma_update(None, "key", key)
+        .map_err(|e| format!("设置密钥失败: {}", e))?;
+    if conn
+        .query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .is_ok()
+    {
+        return Ok(conn);
+    }
+    drop(conn);
+
+    let plain = Connection::open(path).map_err(|e| format!("打开消息库失败: {}", e))?;
+    plain
+        .query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|_| "消息库密钥不正确".to_string())?;
+    let encrypted = path.with_extension("db.encrypting");
+    let _ = fs::remove_file(&encrypted);
+    plain
+        .execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            rusqlite::params![encrypted.to_string_lossy(), key],
+        )
+        .and_then(|_| plain.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(())))
+        .and_then(|_| plain.execute("DETACH DATABASE encrypted", []))
+        .map_err(|e| format!("加密消息库失败: {}", e))?;
+    drop(plain);
+    fs::rename(&encrypted, path).map_err(|e| format!("替换消息库失败: {}", e))?;
+    info!("已将消息库 {} 转为加密存储", path.display());
+
+    let conn = Connection::open(path).map_err(|e| format!("打开消息库失败: {}", e))?;
+    conn.pragma_update(None, "key", key)
+        .map_err(|e| format!("设置密钥失败: {}", e))?;
+    Ok(conn)
+}
+
+/// 媒体归档目录，未配置 file_dir 时媒体在系统临时目录中，不做清理
+fn media_archive() -> Option<PathBuf> {
+    let configured = !GLOBAL
+        .get()?
+        .wechat_config
+        .read()
+        .unwrap()
+        .file_dir
+        .is_empty();
+    configured.then(media_service::media_dir)
+}
+
+/** 本地消息库：把收到的消息写入 SQLite，按保留期限定时清理消息和媒体文件 */
+pub struct MessageStoreService {
+    conn: Option<(PathBuf, bool, Connection)>,
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl MessageStoreService {
+    pub fn new() -> Self {
+        MessageStoreService {
+            conn: None,
+            handle: None,
+        }
+    }
+
+    fn config() -> MessageStoreConfig {
+        GLOBAL
+            .get()
+            .unwrap()
+            .wechat_config
+            .read()
+            .unwrap()
+            .message_store
+            .clone()
+    }
+
+    /// 数据库文件路径
+    pub fn path() -> PathBuf {
+        db_path(&Self::config())
+    }
+
+    // 按需打开数据库，配置的路径或加密选项变化后重新打开
+    fn connection(&mut self) -> Result<&Connection, String> {
+        let config = Self::config();
+        let path = db_path(&config);
+        if self.conn.as_ref().map_or(true, |(p, encrypted, _)| {
+            *p != path || *encrypted != config.encrypted
+        }) {
+            self.conn = None;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|e| format!("创建目录失败: {}", e))?;
+            }
+            let conn = if config.encrypted {
+                open_encrypted(&path, &store_key()?)?
+            } else {
+                Connection::open(&path).map_err(|e| format!("打开消息库失败: {}", e))?
+            };
+            conn.execute_batch(SCHEMA)
+                .map_err(|e| format!("初始化消息库失败: {}", e))?;
+            self.conn = Some((path, config.encrypted, conn));
+        }
+        Ok(&self.conn.as_ref().unwrap().2)
+    }
+
+    /// 已存储的消息数
+    pub fn count(&mut self) -> Result<u64, String> {
+        let conn = self.connection()?;
+        conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))
+            .map_err(|e| format!("查询消息库失败: {}", e))
+    }
+
+    /// 写入一条消息，已脱敏；未开启时忽略
+    pub fn insert(&mut self, msg: &WxMsg) -> Result<(), String> {
+        if !Self::config().enabled {
+            return Ok(());
+        }
+        let conn = self.connection()?;
+        conn.execute(
+            &format!("INSERT OR REPLACE {}", INSERT_SQL),
+            params_from_iter(insert_params(&redact::redact_msg(msg))),
+        )
+        .map_err(|e| format!("写入消息库失败: {}", e))?;
+        Ok(())
+    }
+
+    /// 批量导入历史消息，已存在的 id 保持不变，返回新写入的条数
+    pub fn insert_many(&mut self, messages: &[WxMsg]) -> Result<usize, String> {
+        if !Self::config().enabled {
+            return Err("未开启本地消息库".to_string());
+        }
+        let conn = self.connection()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("写入消息库失败: {}", e))?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx
+                .prepare(&format!("INSERT OR IGNORE {}", INSERT_SQL))
+                .map_err(|e| format!("写入消息库失败: {}", e))?;
+            for msg in messages {
+                inserted += stmt
+                    .execute(params_from_iter(insert_params(&redact::redact_msg(msg))))
+                    .map_err(|e| format!("写入消息库失败: {}", e))?;
+            }
+        }
+        tx.commit().map_err(|e| format!("写入消息库失败: {}", e))?;
+        Ok(inserted)
+    }
+
+    /// 按条件删除消息，条件不能全为空
+    pub fn purge(&mut self, filter: &PurgeFilter) -> Result<usize, String> {
+        if filter.is_empty() {
+            return Err("至少指定 roomid 或时间范围".to_string());
+        }
+        let (clause, values) = filter.clause();
+        let sql = format!("DELETE FROM messages WHERE {}", clause);
+        let conn = self.connection()?;
+        conn.execute(&sql, params_from_iter(values))
+            .map_err(|e| format!("清理消息库失败: {}", e))
+    }
+
+    /// 与联系人相关的消息：其发出的消息，以及与其私聊的全部消息
+    pub fn messages_of(&mut self, wxid: &str) -> Result<Vec<WxMsg>, String> {
+        let conn = self.connection()?;
+        let mut stmt = conn
+            .prepare(&format!("{} WHERE sender = ?1 OR roomid = ?1 ORDER BY ts", SELECT_SQL))
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        let rows = stmt
+            .query_map([wxid], row_msg)
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("查询消息库失败: {}", e))
+    }
+
+    /// 会话中每个发送者在本地时间各小时的消息数，返回 (发送者, 小时, 条数)，时间范围为 [from, to)
+    pub fn hourly_activity(&mut self, roomid: &str, from: Option<i64>, to: Option<i64>) -> Result<Vec<(String, u32, u64)>, String> {
+        let conn = self.connection()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT sender, CAST(strftime('%H', ts, 'unixepoch', 'localtime') AS INTEGER) AS hour, COUNT(*)
+                 FROM messages WHERE roomid = ?1 AND ts >= ?2 AND ts < ?3 GROUP BY sender, hour",
+            )
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        let rows = stmt
+            .query_map(
+                rusqlite::params![roomid, from.unwrap_or(0), to.unwrap_or(i64::MAX)],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? as u64)),
+            )
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("查询消息库失败: {}", e))
+    }
+
+    /// 会话中 from 以后的文字消息内容
+    pub fn texts(&mut self, roomid: &str, from: Option<i64>) -> Result<Vec<String>, String> {
+        let conn = self.connection()?;
+        let mut stmt = conn
+            .prepare("SELECT content FROM messages WHERE roomid = ?1 AND ts >= ?2 AND type = 1")
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        let rows = stmt
+            .query_map(rusqlite::params![roomid, from.unwrap_or(0)], |row| row.get(0))
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("查询消息库失败: {}", e))
+    }
+
+    /// 删除与联系人相关的消息，范围同 messages_of
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let conn = self.connection()?;
+        conn.execute(
+            "DELETE FROM messages WHERE sender = ?1 OR roomid = ?1",
+            [wxid],
+        )
+        .map_err(|e| format!("清理消息库失败: {}", e))
+    }
+
+    /// 消息引用的、位于媒体归档目录下的文件
+    pub fn archived_media(messages: &[WxMsg]) -> Vec<PathBuf> {
+        let dir = match media_archive().and_then(|d| d.canonicalize().ok()) {
+            Some(dir) => dir,
+            None => return Vec::new(),
+        };
+        let mut files: Vec<PathBuf> = messages
+            .iter()
+            .flat_map(|m| [&m.extra, &m.thumb])
+            .filter(|p| !p.is_empty())
+            .filter_map(|p| Path::new(p).canonicalize().ok())
+            .filter(|p| p.starts_with(&dir) && p.is_file())
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// 把数据库完整复制到 dest，写入中的数据也能得到一致的副本；加密库的副本仍用同一密钥加密
+    pub fn snapshot(&mut self, dest: &Path) -> Result<(), String> {
+        let _ = fs::remove_file(dest);
+        let conn = self.connection()?;
+        conn.execute("VACUUM INTO ?1", [dest.to_string_lossy()])
+            .map_err(|e| format!("复制消息库失败: {}", e))?;
+        Ok(())
+    }
+
+    /// 删除范围内的消息引用的、位于媒体归档目录下的文件，目录下的其它文件不受影响；需在删除消息之前调用
+    pub fn purge_media(&mut self, filter: &PurgeFilter) -> Result<usize, String> {
+        if filter.is_empty() {
+            return Err("至少指定 roomid 或时间范围".to_string());
+        }
+        let (clause, values) = filter.clause();
+        let conn = self.connection()?;
+        let mut stmt = conn
+            .prepare(&format!("{} WHERE {} AND (extra != '' OR thumb != '')", SELECT_SQL, clause))
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        let messages = stmt
+            .query_map(params_from_iter(values), row_msg)
+            .map_err(|e| format!("查询消息库失败: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("查询消息库失败: {}", e))?;
+        Ok(Self::archived_media(&messages)
+            .iter()
+            .filter(|file| fs::remove_file(file).is_ok())
+            .count())
+    }
+
+    // 按保留期限清理一次
+    fn apply_retention(&mut self, config: &MessageStoreConfig) -> PurgeReport {
+        let mut report = PurgeReport::default();
+        let now = unix_now();
+        // 先清理媒体，删除消息后就不知道它们引用了哪些文件
+        if config.media_retention_days > 0 {
+            let filter = PurgeFilter {
+                to: Some(now - config.media_retention_days as i64 * 86400),
+                ..Default::default()
+            };
+            match self.purge_media(&filter) {
+                Ok(count) => report.media_files = count,
+                Err(e) => warn!("按保留期限清理媒体文件失败: {}", e),
+            }
+        }
+        if config.enabled && config.retention_days > 0 {
+            let filter = PurgeFilter {
+                to: Some(now - config.retention_days as i64 * 86400),
+                ..Default::default()
+            };
+            match self.purge(&filter) {
+                Ok(count) => report.messages = count,
+                Err(e) => warn!("按保留期限清理消息失败: {}", e),
+            }
+        }
+        report
+    }
+
+    pub fn start(&mut self) {
+        self.stop();
+        // 第一次使用时消息库是空的，提示可以回填历史消息
+        let config = Self::config();
+        if config.enabled && self.count().map_or(false, |n| n == 0) {
+            info!("本地消息库为空，可调用 POST /admin/backfill?days=30 导入最近的历史消息");
+        }
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                let report = tokio::task::spawn_blocking(|| {
+                    let config = Self::config();
+                    let global = GLOBAL.get().unwrap();
+                    let mut store = global.message_store_service.lock().unwrap();
+                    store.apply_retention(&config)
+                })
+                .await
+                .unwrap_or_default();
+                if report.messages > 0 || report.media_files > 0 {
+                    info!(
+                        "已按保留期限清理 {} 条消息、{} 个媒体文件",
+                        report.messages, report.media_files
+                    );
+                }
+                tokio::time::sleep(PURGE_INTERVAL).await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("消息库定时清理已停止");
+        }
+    }
+}
@@ -1,4 +1,55 @@
 pub mod global_service;
 pub mod http_server_service;
 pub mod wechat_service;
-pub mod socketio_service;
\ No newline at end of file
+pub mod socketio_service;
+pub mod media_service;
+pub mod contact_monitor_service;
+pub mod risk_guard_service;
+pub mod replay_service;
+pub mod heartbeat_service;
+pub mod admin_notify_service;
+pub mod preflight_service;
+pub mod sdk_service;
+pub mod wechat_installer_service;
+pub mod update_service;
+pub mod command_permission_service;
+pub mod command_service;
+pub mod poll_service;
+pub mod checkin_service;
+pub mod raffle_service;
+pub mod quiet_hours_service;
+pub mod pause_service;
+pub mod config_service;
+pub mod sink_validate_service;
+pub mod anomaly_service;
+pub mod watchdog_service;
+pub mod pipe_service;
+pub mod moderation_service;
+pub mod message_store_service;
+pub mod privacy_service;
+pub mod backup_service;
+pub mod import_service;
+pub mod db_poll_service;
+pub mod db_snapshot_service;
+pub mod stats_service;
+pub mod word_cloud_service;
+pub mod announcement_service;
+pub mod announce_service;
+pub mod distribute_service;
+pub mod file_intake_service;
+pub mod receipt_service;
+pub mod identity_service;
+pub mod dnd_service;
+pub mod pat_service;
+pub mod name_history_service;
+pub mod at_all_service;
+pub mod bridge_identity_service;
+pub mod bridge_thread_service;
+pub mod webhook_service;
+pub mod rule_service;
+pub mod batch_service;
+pub mod rule_group_service;
+pub mod rule_limit_service;
+pub mod rule_var_service;
+pub mod template_service;
+pub mod markdown_service;
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use log::warn;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    service::{
+        admin_notify_service::{self, Incident},
+        global_service::GLOBAL,
+    },
+    utils::pacing,
+    wcferry::{wcf::TextMsg, WeChat},
+    wechat_config::{ModerationAction, ModerationConfig},
+};
+
+/// 审核接口的超时时间
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 审核接口的返回：{"flagged": true, "reason": "..."}
+#[derive(Deserialize)]
+struct ApiVerdict {
+    flagged: bool,
+    #[serde(default)]
+    reason: String,
+}
+
+// 命中关键词或审核接口认为有风险时返回原因
+fn review(config: &ModerationConfig, text: &str) -> Result<Option<String>, String> {
+    let lowered = text.to_lowercase();
+    if let Some(word) = config
+        .keywords
+        .iter()
+        .find(|w| !w.is_empty() && lowered.contains(&w.to_lowercase()))
+    {
+        return Ok(Some(format!("命中关键词 {}", word)));
+    }
+    if config.api_url.is_empty() {
+        return Ok(None);
+    }
+    let mut request = ureq::post(&config.api_url).timeout(TIMEOUT);
+    if !config.api_key.is_empty() {
+        request = request.set("Authorization", &format!("Bearer {}", config.api_key.expose()));
+    }
+    let verdict: ApiVerdict = request
+        .send_json(json!({ "text": text }))
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| e.to_string())?;
+    Ok(verdict.flagged.then(|| {
+        if verdict.reason.is_empty() {
+            "审核接口判定有风险".to_string()
+        } else {
+            verdict.reason
+        }
+    }))
+}
+
+/// 自动发送前审核内容，action 为 block 时拦下有风险的内容，为 flag 时只通知管理员
+///
+/// override_token 与配置一致时跳过审核，用于人工确认过的内容。
+pub fn check(receiver: &str, text: &str, override_token: Option<&str>) -> Result<(), String> {
+    let config = {
+        let global = GLOBAL.get().unwrap();
+        let config = global.wechat_config.read().unwrap();
+        config.moderation.clone()
+    };
+    if !config.enabled {
+        return Ok(());
+    }
+    if config.override_token.matches(override_token) {
+        warn!("已使用审核豁免令牌发送到 {}", receiver);
+        return Ok(());
+    }
+    let reason = match review(&config, text) {
+        Ok(Some(reason)) => reason,
+        Ok(None) => return Ok(()),
+        Err(e) if config.fail_closed => return Err(format!("内容审核失败: {}", e)),
+        Err(e) => {
+            warn!("内容审核失败，按通过处理: {}", e);
+            return Ok(());
+        }
+    };
+    warn!("发送到 {} 的内容未通过审核: {}", receiver, reason);
+    admin_notify_service::report(Incident::ContentFlagged {
+        receiver: receiver.to_string(),
+        reason: reason.clone(),
+    });
+    match config.action {
+        ModerationAction::Block => Err(format!("内容未通过审核: {}", reason)),
+        ModerationAction::Flag => Ok(()),
+    }
+}
+
+/// 程序自己发出的文本最终都经过这里：审核通过后按节奏发送
+///
+/// 审核可能要调用外部接口，wechat 应是复制出来的连接，不要在持有微信的锁时调用。
+pub fn send_text(wechat: &WeChat, text: TextMsg) -> Result<bool, String> {
+    check(&text.receiver, &text.msg, None)?;
+    pacing::before_send(&text.msg);
+    match wechat.send_text(text) {
+        Ok(true) => Ok(true),
+        Ok(false) => Err("发送失败".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
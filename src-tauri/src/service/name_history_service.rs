@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::utils::state_store;
+
+const STATE_NAME: &str = "name_history";
+
+/// 每个联系人最多保留的记录数，超出后丢弃最早的
+const MAX_RECORDS: usize = 200;
+
+/// 最多记录的联系人数，群成员很多时超出后丢弃最久没有变化的联系人
+const MAX_CONTACTS: usize = 20000;
+
+/// 记录的字段
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NameField {
+    /// 微信昵称
+    Name,
+    /// 自己设置的备注
+    Remark,
+    /// 群昵称
+    RoomName,
+    /// 小头像地址
+    Avatar,
+}
+
+/// 某个字段出现过的一个值
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct NameRecord {
+    pub field: NameField,
+    /// 群昵称所在的群，其他字段为空
+    pub roomid: String,
+    pub value: String,
+    /// 第一次看到这个值的时间
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub seen_at: DateTime<Local>,
+}
+
+/// 一次检查中看到的值
+pub struct Observation {
+    pub wxid: String,
+    pub field: NameField,
+    pub roomid: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct NameHistoryState {
+    // wxid -> 按时间排列的记录
+    history: BTreeMap<String, Vec<NameRecord>>,
+}
+
+/** 联系人昵称、备注、群昵称和头像的变化记录 */
+pub struct NameHistoryService {
+    state: NameHistoryState,
+}
+
+impl NameHistoryService {
+    pub fn new() -> Self {
+        NameHistoryService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 与上次记录的值对比，变化时追加记录并返回旧值；第一次看到的值只记录
+    pub fn observe<'a>(&mut self, observed: &'a [Observation]) -> Result<Vec<(&'a Observation, String)>, String> {
+        let mut changed = vec![];
+        let mut dirty = false;
+        for o in observed.iter().filter(|o| !o.value.is_empty()) {
+            let records = self.state.history.entry(o.wxid.clone()).or_default();
+            let last = records
+                .iter()
+                .rev()
+                .find(|r| r.field == o.field && r.roomid == o.roomid)
+                .map(|r| r.value.clone());
+            if last.as_deref() == Some(o.value.as_str()) {
+                continue;
+            }
+            records.push(NameRecord {
+                field: o.field,
+                roomid: o.roomid.clone(),
+                value: o.value.clone(),
+                seen_at: Local::now(),
+            });
+            if records.len() > MAX_RECORDS {
+                records.remove(0);
+            }
+            dirty = true;
+            if let Some(old) = last {
+                changed.push((o, old));
+            }
+        }
+        if dirty {
+            self.prune();
+            self.save()?;
+        }
+        Ok(changed)
+    }
+
+    // 联系人超出上限时，按最后一条记录的时间丢弃最早的
+    fn prune(&mut self) {
+        let excess = self.state.history.len().saturating_sub(MAX_CONTACTS);
+        if excess == 0 {
+            return;
+        }
+        let mut latest: Vec<(Option<DateTime<Local>>, String)> = self
+            .state
+            .history
+            .iter()
+            .map(|(wxid, records)| (records.last().map(|r| r.seen_at), wxid.clone()))
+            .collect();
+        latest.sort();
+        for (_, wxid) in latest.into_iter().take(excess) {
+            self.state.history.remove(&wxid);
+        }
+    }
+
+    /// 联系人的全部记录，按时间先后排列
+    pub fn history(&self, wxid: &str) -> Vec<NameRecord> {
+        self.state.history.get(wxid).cloned().unwrap_or_default()
+    }
+
+    /// 删除联系人的记录，返回删除的条数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let removed = self.state.history.remove(wxid).map_or(0, |r| r.len());
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::{
+    service::{global_service::GLOBAL, pause_service, quiet_hours_service},
+    utils::system_event::{self, SystemEvent},
+    wcferry::{
+        wcf::{PatMsg, TextMsg, WxMsg},
+        WeChat,
+    },
+};
+
+// (会话, 拍的人) -> 上次响应的时间
+static LAST: Mutex<Option<HashMap<(String, String), Instant>>> = Mutex::new(None);
+
+/// 拍一拍消息中拍的人，只有拍的是自己时返回
+pub fn patted_by(msg: &WxMsg, self_wxid: &str) -> Option<String> {
+    match system_event::classify(msg)? {
+        SystemEvent::Tickle { from, to } if to == self_wxid && from != self_wxid => Some(from),
+        _ => None,
+    }
+}
+
+// 冷却时间内已响应过时返回 false
+fn first_in_cooldown(roomid: &str, from: &str, cooldown: Duration) -> bool {
+    let mut last = LAST.lock().unwrap();
+    let last = last.get_or_insert_with(HashMap::new);
+    let key = (roomid.to_string(), from.to_string());
+    if last.get(&key).map_or(false, |at| at.elapsed() < cooldown) {
+        return false;
+    }
+    last.insert(key, Instant::now());
+    true
+}
+
+/// 被拍时按配置拍回去或回复，返回是否做了响应
+pub fn handle(wechat: &Arc<Mutex<WeChat>>, msg: &WxMsg) -> Result<bool, String> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().pat.clone();
+    if !config.pat_back && config.reply.is_empty() {
+        return Ok(false);
+    }
+    if !config.rooms.is_empty() && !config.rooms.contains(&msg.roomid) {
+        return Ok(false);
+    }
+    let wc = wechat.lock().unwrap().clone();
+    let me = wc.get_self_wxid().map_err(|e| e.to_string())?;
+    let from = match patted_by(msg, &me) {
+        Some(from) => from,
+        None => return Ok(false),
+    };
+    pause_service::check()?;
+    if !first_in_cooldown(&msg.roomid, &from, Duration::from_secs(config.cooldown_secs)) {
+        return Ok(false);
+    }
+    info!("{} 在 {} 拍了拍自己", from, msg.roomid);
+    if config.pat_back {
+        let pat = PatMsg {
+            roomid: msg.roomid.clone(),
+            wxid: from.clone(),
+        };
+        wc.send_pat_msg(pat).map_err(|e| e.to_string())?;
+    }
+    if !config.reply.is_empty() {
+        let text = TextMsg {
+            msg: config.reply.replace("{from}", &from),
+            receiver: msg.roomid.clone(),
+            aters: String::new(),
+        };
+        quiet_hours_service::send_text(&wc, text)?;
+    }
+    Ok(true)
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{service::global_service::GLOBAL, utils::state_store};
+
+const STATE_NAME: &str = "pause";
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct PauseStatus {
+    /// 是否已暂停自动发送
+    pub paused: bool,
+    /// 暂停原因
+    pub reason: String,
+    #[schema(value_type = Option<String>, example = "2024-01-01T12:00:00+08:00")]
+    pub since: Option<DateTime<Local>>,
+}
+
+/** 紧急开关：暂停后所有自动发送（指令回复、群发、定时汇总、排队消息等）都会被拦下，收消息和存储照常进行 */
+pub struct PauseService {
+    status: PauseStatus,
+}
+
+impl PauseService {
+    pub fn new() -> Self {
+        // 暂停状态持久化，避免重启后又开始刷屏
+        PauseService {
+            status: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = state_store::save(STATE_NAME, &self.status) {
+            warn!("保存暂停状态失败: {}", e);
+        }
+    }
+
+    pub fn status(&self) -> PauseStatus {
+        self.status.clone()
+    }
+
+    pub fn pause(&mut self, reason: String) -> PauseStatus {
+        if !self.status.paused {
+            warn!("已暂停所有自动发送: {}", reason);
+            self.status = PauseStatus {
+                paused: true,
+                reason,
+                since: Some(Local::now()),
+            };
+            self.save();
+        }
+        self.status()
+    }
+
+    /// 暂停时返回错误
+    pub fn check(&self) -> Result<(), String> {
+        if self.status.paused {
+            return Err("自动发送已暂停".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> PauseStatus {
+        if self.status.paused {
+            info!("已恢复自动发送");
+            self.status = PauseStatus::default();
+            self.save();
+        }
+        self.status()
+    }
+}
+
+/// 自动发送前调用，暂停时返回错误
+pub fn check() -> Result<(), String> {
+    GLOBAL.get().unwrap().pause_service.lock().unwrap().check()
+}
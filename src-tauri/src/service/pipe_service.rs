@@ -0,0 +1,303 @@
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use warp::hyper::{
+    body::{to_bytes, Body},
+    service::Service,
+    Request, Response,
+};
+
+use crate::wcferry::WeChat;
+
+// JSON-RPC 错误码
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+/// 接口返回非 2xx 时使用的错误码，data 中带上 http 状态码和响应内容
+const HTTP_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    /// 形如 "GET /contacts"、"POST /text"，与 http 接口一一对应
+    method: String,
+    /// POST 时作为请求体，GET 时转成查询参数
+    #[serde(default)]
+    params: Value,
+    /// 配置了 api_token 时需要带上
+    #[serde(default)]
+    token: Option<String>,
+}
+
+fn rpc_error(id: Value, code: i64, message: impl Into<String>, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message.into() });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+fn query_string(params: &Value) -> Result<String, String> {
+    let map = match params {
+        Value::Null => return Ok(String::new()),
+        Value::Object(map) => map,
+        _ => return Err("GET 请求的 params 必须是对象".to_string()),
+    };
+    let pairs: Vec<String> = map
+        .iter()
+        .map(|(k, v)| {
+            let v = match v {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            format!("{}={}", urlencoding::encode(k), urlencoding::encode(&v))
+        })
+        .collect();
+    Ok(if pairs.is_empty() { String::new() } else { format!("?{}", pairs.join("&")) })
+}
+
+fn build_request(rpc: &RpcRequest) -> Result<Request<Body>, String> {
+    let (verb, path) = rpc.method.split_once(' ').ok_or("method 格式应为 \"GET /path\"")?;
+    let verb = verb.to_uppercase();
+    let mut builder = Request::builder();
+    if let Some(token) = &rpc.token {
+        builder = builder.header("x-api-token", token.as_str());
+    }
+    let request = match verb.as_str() {
+        "GET" | "DELETE" => builder
+            .method(verb.as_str())
+            .uri(format!("{}{}", path, query_string(&rpc.params)?))
+            .body(Body::empty()),
+        "POST" | "PUT" => {
+            // 接口按 content-length 限制请求体大小
+            let body = rpc.params.to_string();
+            builder
+                .method(verb.as_str())
+                .uri(path)
+                .header("content-type", "application/json")
+                .header("content-length", body.len())
+                .body(Body::from(body))
+        }
+        _ => return Err(format!("不支持的请求方法: {}", verb)),
+    };
+    request.map_err(|e| e.to_string())
+}
+
+/// 处理一行 JSON-RPC 请求，转成对应的 http 接口调用，返回一行响应
+pub async fn handle_line<S>(service: &mut S, line: &str) -> Value
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let rpc: RpcRequest = match serde_json::from_str::<Value>(line) {
+        Err(e) => return rpc_error(Value::Null, PARSE_ERROR, e.to_string(), None),
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(rpc) => rpc,
+            Err(e) => return rpc_error(Value::Null, INVALID_REQUEST, e.to_string(), None),
+        },
+    };
+    let request = match build_request(&rpc) {
+        Ok(request) => request,
+        Err(e) => return rpc_error(rpc.id, INVALID_REQUEST, e, None),
+    };
+    let response = match service.call(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    };
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = to_bytes(response.into_body()).await.unwrap_or_default();
+    // 图片等二进制响应用 base64 返回
+    let result = serde_json::from_slice::<Value>(&body)
+        .unwrap_or_else(|_| json!({ "content_type": content_type, "base64": base64::encode(&body) }));
+    if status.is_success() {
+        json!({ "jsonrpc": "2.0", "id": rpc.id, "result": result })
+    } else if status.as_u16() == 404 {
+        rpc_error(rpc.id, METHOD_NOT_FOUND, format!("接口不存在: {}", rpc.method), None)
+    } else {
+        let data = json!({ "status": status.as_u16(), "body": result });
+        rpc_error(rpc.id, HTTP_ERROR, status.to_string(), Some(data))
+    }
+}
+
+/// 连接出错后的重试间隔，连续出错时逐次翻倍
+#[cfg(windows)]
+const CONNECT_BACKOFF_MIN: std::time::Duration = std::time::Duration::from_millis(100);
+#[cfg(windows)]
+const CONNECT_BACKOFF_MAX: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 管道的访问权限：只允许 SYSTEM、管理员和创建者本人，拒绝网络登录的用户
+#[cfg(windows)]
+const PIPE_SDDL: &str = "D:P(D;;GA;;;NU)(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;OW)";
+
+#[cfg(windows)]
+struct PipeSecurity(winapi::um::winnt::PSECURITY_DESCRIPTOR);
+
+// 安全描述符只在监听任务内使用，创建后不再修改
+#[cfg(windows)]
+unsafe impl Send for PipeSecurity {}
+
+#[cfg(windows)]
+impl PipeSecurity {
+    fn new() -> Result<Self, String> {
+        use winapi::{
+            shared::sddl::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+            um::errhandlingapi::GetLastError,
+        };
+
+        let sddl: Vec<u16> = PIPE_SDDL.encode_utf16().chain(std::iter::once(0)).collect();
+        let mut descriptor = std::ptr::null_mut();
+        let ok = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(format!("错误码 {}", unsafe { GetLastError() }));
+        }
+        Ok(PipeSecurity(descriptor))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PipeSecurity {
+    fn drop(&mut self) {
+        unsafe { winapi::um::winbase::LocalFree(self.0) };
+    }
+}
+
+#[cfg(windows)]
+fn create_pipe(
+    name: &str,
+    first: bool,
+    security: &PipeSecurity,
+) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeServer> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+    use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+
+    let mut attrs = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: security.0,
+        bInheritHandle: 0,
+    };
+    unsafe {
+        ServerOptions::new()
+            .first_pipe_instance(first)
+            .reject_remote_clients(true)
+            .create_with_security_attributes_raw(name, &mut attrs as *mut _ as *mut std::ffi::c_void)
+    }
+}
+
+/** 命名管道接口：在不允许开放 TCP 端口的环境下，通过本机命名管道以 JSON-RPC 调用所有 http 接口 */
+pub struct PipeService {
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl PipeService {
+    pub fn new() -> Self {
+        PipeService { handle: None }
+    }
+
+    #[cfg(windows)]
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>, name: String) {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::task::JoinSet;
+
+        use crate::endpoints;
+
+        self.stop();
+        let service = warp::service(endpoints::get_routes(wechat));
+        self.handle = Some(tokio::spawn(async move {
+            let security = match PipeSecurity::new() {
+                Ok(security) => security,
+                Err(e) => {
+                    warn!("创建命名管道 {} 的访问权限失败: {}", name, e);
+                    return;
+                }
+            };
+            let mut server = match create_pipe(&name, true, &security) {
+                Ok(server) => server,
+                Err(e) => {
+                    warn!("创建命名管道 {} 失败: {}", name, e);
+                    return;
+                }
+            };
+            info!("命名管道接口已启动: {}", name);
+            // 连接任务放在这里，接口停止时随本任务一起被取消
+            let mut clients = JoinSet::new();
+            let mut backoff = CONNECT_BACKOFF_MIN;
+            loop {
+                while clients.try_join_next().is_some() {}
+                if let Err(e) = server.connect().await {
+                    warn!("命名管道连接失败，{:?} 后重试: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(CONNECT_BACKOFF_MAX);
+                    // 出错的实例可能已不可用，换一个新的
+                    server = match create_pipe(&name, false, &security) {
+                        Ok(next) => next,
+                        Err(e) => {
+                            warn!("创建命名管道 {} 失败: {}", name, e);
+                            return;
+                        }
+                    };
+                    continue;
+                }
+                backoff = CONNECT_BACKOFF_MIN;
+                // 先创建下一个实例再处理当前连接，避免客户端连不上
+                let client = std::mem::replace(
+                    &mut server,
+                    match create_pipe(&name, false, &security) {
+                        Ok(next) => next,
+                        Err(e) => {
+                            warn!("创建命名管道 {} 失败: {}", name, e);
+                            return;
+                        }
+                    },
+                );
+                let mut service = service.clone();
+                clients.spawn(async move {
+                    let (reader, mut writer) = tokio::io::split(client);
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        let reply = handle_line(&mut service, &line).await;
+                        let mut text = reply.to_string();
+                        text.push('\n');
+                        if writer.write_all(text.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        }));
+    }
+
+    #[cfg(not(windows))]
+    pub fn start(&mut self, _wechat: Arc<Mutex<WeChat>>, _name: String) {
+        warn!("命名管道接口仅支持 Windows");
+    }
+
+    /// 停止监听，已建立的连接也会一并断开
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("命名管道接口已停止");
+        }
+    }
+}
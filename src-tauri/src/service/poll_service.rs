@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{utils::state_store, wcferry::wcf::WxMsg};
+
+const STATE_NAME: &str = "polls";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Poll {
+    id: u64,
+    roomid: String,
+    question: String,
+    options: Vec<String>,
+    // wxid -> 选项下标，重复投票以最后一次为准
+    votes: BTreeMap<String, usize>,
+    closed: bool,
+    created_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PollState {
+    next_id: u64,
+    polls: HashMap<u64, Poll>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct OptionResult {
+    /// 选项内容
+    pub option: String,
+    /// 票数
+    pub count: usize,
+    /// 投票的 wxid
+    pub voters: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct PollResult {
+    pub id: u64,
+    pub roomid: String,
+    pub question: String,
+    pub options: Vec<OptionResult>,
+    /// 参与人数
+    pub total: usize,
+    pub closed: bool,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub created_at: DateTime<Local>,
+}
+
+impl Poll {
+    fn result(&self) -> PollResult {
+        let options = self
+            .options
+            .iter()
+            .enumerate()
+            .map(|(i, option)| {
+                let voters: Vec<String> = self
+                    .votes
+                    .iter()
+                    .filter(|(_, v)| **v == i)
+                    .map(|(w, _)| w.clone())
+                    .collect();
+                OptionResult {
+                    option: option.clone(),
+                    count: voters.len(),
+                    voters,
+                }
+            })
+            .collect();
+        PollResult {
+            id: self.id,
+            roomid: self.roomid.clone(),
+            question: self.question.clone(),
+            options,
+            total: self.votes.len(),
+            closed: self.closed,
+            created_at: self.created_at,
+        }
+    }
+
+    // 回复选项序号或完整的选项内容都算投票
+    fn choice(&self, content: &str) -> Option<usize> {
+        let content = content.trim();
+        if let Ok(n) = content.parse::<usize>() {
+            return (1..=self.options.len()).contains(&n).then(|| n - 1);
+        }
+        self.options.iter().position(|o| o == content)
+    }
+}
+
+/** 群投票：发起后统计群里回复的选项，结束时发出汇总 */
+pub struct PollService {
+    state: PollState,
+}
+
+impl PollService {
+    pub fn new() -> Self {
+        PollService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 发起投票，返回编号
+    pub fn create(&mut self, roomid: &str, question: &str, options: Vec<String>) -> Result<u64, String> {
+        if options.len() < 2 {
+            return Err("至少需要两个选项".to_string());
+        }
+        self.state.next_id += 1;
+        let id = self.state.next_id;
+        self.state.polls.insert(
+            id,
+            Poll {
+                id,
+                roomid: roomid.to_string(),
+                question: question.to_string(),
+                options,
+                votes: BTreeMap::new(),
+                closed: false,
+                created_at: Local::now(),
+            },
+        );
+        self.save()?;
+        Ok(id)
+    }
+
+    /// 发起投票时发到群里的文字
+    pub fn announcement(&self, id: u64) -> Option<String> {
+        let poll = self.state.polls.get(&id)?;
+        let mut lines = vec![format!("投票 #{}：{}", poll.id, poll.question)];
+        for (i, option) in poll.options.iter().enumerate() {
+            lines.push(format!("{}. {}", i + 1, option));
+        }
+        lines.push("回复序号参与投票".to_string());
+        Some(lines.join("\n"))
+    }
+
+    pub fn result(&self, id: u64) -> Option<PollResult> {
+        self.state.polls.get(&id).map(Poll::result)
+    }
+
+    /// 结束投票，返回结果和要发到群里的汇总
+    pub fn close(&mut self, id: u64) -> Result<(PollResult, String), String> {
+        let poll = self.state.polls.get_mut(&id).ok_or("投票不存在")?;
+        if poll.closed {
+            return Err("投票已结束".to_string());
+        }
+        poll.closed = true;
+        let result = poll.result();
+        self.save()?;
+        let mut lines = vec![format!("投票 #{} 已结束：{}", result.id, result.question)];
+        for (i, option) in result.options.iter().enumerate() {
+            lines.push(format!("{}. {} - {} 票", i + 1, option.option, option.count));
+        }
+        lines.push(format!("共 {} 人参与", result.total));
+        Ok((result, lines.join("\n")))
+    }
+
+    /// 该 wxid 投过的票：投票编号、问题和所选选项
+    pub fn votes_of(&self, wxid: &str) -> Vec<(u64, String, String)> {
+        let mut votes: Vec<(u64, String, String)> = self
+            .state
+            .polls
+            .values()
+            .filter_map(|p| {
+                let choice = *p.votes.get(wxid)?;
+                Some((p.id, p.question.clone(), p.options.get(choice)?.clone()))
+            })
+            .collect();
+        votes.sort();
+        votes
+    }
+
+    /// 删除该 wxid 的投票，返回删除的票数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let removed = self
+            .state
+            .polls
+            .values_mut()
+            .filter(|p| p.votes.remove(wxid).is_some())
+            .count();
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// 群消息是对进行中投票的回复时记一票
+    pub fn record(&mut self, msg: &WxMsg) {
+        if msg.r#type != 1 || !msg.is_group || msg.is_self {
+            return;
+        }
+        // 同一个群有多个投票时记到最新的那个
+        let poll = self
+            .state
+            .polls
+            .values_mut()
+            .filter(|p| !p.closed && p.roomid == msg.roomid)
+            .max_by_key(|p| p.id);
+        let poll = match poll {
+            Some(poll) => poll,
+            None => return,
+        };
+        if let Some(choice) = poll.choice(&msg.content) {
+            poll.votes.insert(msg.sender.clone(), choice);
+            if let Err(e) = self.save() {
+                log::warn!("保存投票失败: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::Serialize;
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::APP_HANDLE, sdk_service},
+    utils::event_log::{self, EventLevel},
+    wcferry::WeChat,
+};
+
+static LAST_REPORT: RwLock<Option<PreflightReport>> = RwLock::new(None);
+
+/// 启动自检结果
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct PreflightReport {
+    /// 没有发现问题
+    pub ok: bool,
+    /// 模拟模式下跳过环境检查
+    pub mock: bool,
+    /// 客户端版本
+    pub client_version: String,
+    /// 当前使用的 SDK 版本
+    pub sdk_version: String,
+    /// SDK 适配的微信版本，未登记的 SDK 为空
+    pub supported_wechat: String,
+    /// 本机安装的微信版本，读取失败时为空
+    pub installed_wechat: Option<String>,
+    /// sdk.dll 是否存在
+    pub sdk_found: bool,
+    /// 注入后 RPC 是否可用
+    pub injected: bool,
+    /// 发现的问题
+    pub issues: Vec<String>,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub checked_at: DateTime<Local>,
+}
+
+impl PreflightReport {
+    fn issue(&mut self, issue: String) {
+        warn!("启动自检: {}", issue);
+        self.ok = false;
+        self.issues.push(issue);
+    }
+}
+
+/// 注入前检查 sdk.dll 和微信版本
+pub fn check_environment(mock: bool) -> PreflightReport {
+    let mut report = PreflightReport {
+        ok: true,
+        mock,
+        client_version: env!("CARGO_PKG_VERSION").to_string(),
+        sdk_version: sdk_service::active_version(),
+        supported_wechat: sdk_service::supported_wechat().unwrap_or_default().to_string(),
+        installed_wechat: None,
+        sdk_found: true,
+        injected: false,
+        issues: vec![],
+        checked_at: Local::now(),
+    };
+    if mock {
+        return report;
+    }
+
+    let dll = WeChat::sdk_dll_path();
+    if !dll.exists() {
+        report.sdk_found = false;
+        report.issue(format!("找不到 {}", dll.display()));
+    }
+
+    report.installed_wechat = installed_wechat_version();
+    match report.installed_wechat.clone() {
+        Some(version) if !report.supported_wechat.is_empty() && version != report.supported_wechat => {
+            let mut issue = format!("微信版本 {} 与 SDK 适配的 {} 不一致，可能注入失败", version, report.supported_wechat);
+            if let Some(sdk) = sdk_service::match_wechat(&version) {
+                issue.push_str(&format!("，可通过 /admin/sdk-version 切换到 SDK {}", sdk));
+            }
+            report.issue(issue);
+        }
+        Some(_) => {}
+        None => report.issue("未检测到已安装的微信".to_string()),
+    }
+    report
+}
+
+/// 注入后检查 RPC 是否可用
+pub fn check_injection(report: &mut PreflightReport, wechat: &Arc<Mutex<WeChat>>) {
+    report.checked_at = Local::now();
+    match wechat.lock().unwrap().is_login() {
+        Ok(_) => report.injected = true,
+        Err(e) => report.issue(format!("注入失败，无法调用微信接口: {}", e)),
+    }
+}
+
+/// 保存结果，有问题时弹窗提示
+pub fn publish(report: PreflightReport) {
+    if report.ok {
+        info!("启动自检通过");
+    } else {
+        event_log::write(EventLevel::Error, &format!("启动自检未通过: {}", report.issues.join("; ")));
+        if let Some(handle) = APP_HANDLE.get() {
+            handle
+                .dialog()
+                .message(report.issues.join("\n"))
+                .title("启动自检")
+                .kind(MessageDialogKind::Warning)
+                .show(|_| {});
+        }
+    }
+    *LAST_REPORT.write().unwrap() = Some(report);
+}
+
+/// 最近一次自检结果，服务未启动时为 None
+pub fn last_report() -> Option<PreflightReport> {
+    LAST_REPORT.read().unwrap().clone()
+}
+
+// 微信安装时把版本号写在注册表里，高 4 位是标志位，其余每字节一段
+#[cfg(windows)]
+pub fn installed_wechat_version() -> Option<String> {
+    use winapi::um::winreg::{RegGetValueA, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueA(
+            HKEY_CURRENT_USER,
+            "Software\\Tencent\\WeChat\0".as_ptr() as *const i8,
+            "Version\0".as_ptr() as *const i8,
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            &mut value as *mut u32 as *mut _,
+            &mut size,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    let value = value & 0x0FFF_FFFF;
+    Some(format!(
+        "{}.{}.{}.{}",
+        (value >> 24) & 0xFF,
+        (value >> 16) & 0xFF,
+        (value >> 8) & 0xFF,
+        value & 0xFF
+    ))
+}
+
+#[cfg(not(windows))]
+pub fn installed_wechat_version() -> Option<String> {
+    None
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashSet},
+    fs,
+};
+
+use log::warn;
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, message_store_service::MessageStoreService, receipt_service},
+    wcferry::wcf::WxMsg,
+};
+
+/// 与某个联系人相关的本地数据
+#[derive(Serialize, ToSchema)]
+pub struct SubjectData {
+    pub wxid: String,
+    /// 合并到同一身份的其他账号，数据一并导出
+    pub aliases: Vec<String>,
+    /// 消息库中其发出的消息和与其私聊的消息
+    pub messages: Vec<WxMsg>,
+    /// 这些消息引用的、位于 file_dir 下的媒体文件
+    pub media: Vec<String>,
+    /// 各项运行状态中的记录，按状态名分组
+    #[schema(value_type = Object)]
+    pub state: BTreeMap<String, Value>,
+}
+
+/// 删除结果，state 为每项状态删除的记录数
+#[derive(Serialize, ToSchema, Default)]
+pub struct ErasureReport {
+    pub messages: usize,
+    pub media_files: usize,
+    pub state: BTreeMap<String, usize>,
+}
+
+/// 导出与联系人相关的消息、媒体文件路径和运行状态，合并了身份时包含同一个人的全部账号
+pub fn export(wxid: &str) -> Result<SubjectData, String> {
+    let global = GLOBAL.get().unwrap();
+    let (wxids, identity) = {
+        let identities = global.identity_service.lock().unwrap();
+        (identities.wxids(wxid), identities.find(wxid).cloned())
+    };
+    let mut data = SubjectData {
+        wxid: wxid.to_string(),
+        aliases: wxids.iter().filter(|w| *w != wxid).cloned().collect(),
+        messages: vec![],
+        media: vec![],
+        state: BTreeMap::new(),
+    };
+    data.state.insert("identities".to_string(), json!(identity.into_iter().collect::<Vec<_>>()));
+    let mut seen = HashSet::new();
+    for wxid in &wxids {
+        let one = export_one(wxid)?;
+        // 两个账号之间的私聊会被导出两次
+        data.messages.extend(one.messages.into_iter().filter(|m| seen.insert(m.id)));
+        for file in one.media {
+            if !data.media.contains(&file) {
+                data.media.push(file);
+            }
+        }
+        for (name, value) in one.state {
+            match data.state.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+                Entry::Occupied(mut entry) => {
+                    if let (Value::Array(merged), Value::Array(items)) = (entry.get_mut(), value) {
+                        merged.extend(items);
+                    }
+                }
+            }
+        }
+    }
+    data.messages.sort_by_key(|m| m.ts);
+    Ok(data)
+}
+
+fn export_one(wxid: &str) -> Result<SubjectData, String> {
+    let global = GLOBAL.get().unwrap();
+    let messages = global.message_store_service.lock().unwrap().messages_of(wxid)?;
+    let media = MessageStoreService::archived_media(&messages)
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+    let mut state = BTreeMap::new();
+    let rooms = global.command_permission_service.lock().unwrap().rooms_of(wxid);
+    state.insert("command_permissions".to_string(), json!(rooms));
+    let dates = global.checkin_service.lock().unwrap().dates_of(wxid);
+    state.insert("checkin".to_string(), json!(dates));
+    let votes: Vec<Value> = global
+        .poll_service
+        .lock()
+        .unwrap()
+        .votes_of(wxid)
+        .into_iter()
+        .map(|(id, question, option)| json!({ "id": id, "question": question, "option": option }))
+        .collect();
+    state.insert("polls".to_string(), json!(votes));
+    let acks = global.announcement_service.lock().unwrap().acked_by(wxid);
+    state.insert("announcements".to_string(), json!(acks));
+    let raffles: Vec<Value> = global
+        .raffle_service
+        .lock()
+        .unwrap()
+        .joined_by(wxid)
+        .iter()
+        .map(|r| {
+            let won = r.draw.as_ref().map_or(false, |d| d.winners.iter().any(|w| w == wxid));
+            json!({ "id": r.id, "roomid": r.roomid, "keyword": r.keyword, "won": won })
+        })
+        .collect();
+    state.insert("raffles".to_string(), json!(raffles));
+    let queued: Vec<_> = global
+        .quiet_hours_service
+        .lock()
+        .unwrap()
+        .queued()
+        .into_iter()
+        .filter(|q| q.receiver == wxid)
+        .collect();
+    state.insert("quiet_queue".to_string(), json!(queued));
+    state.insert("receipts".to_string(), json!(receipt_service::list(Some(wxid))?));
+    let names = global.name_history_service.lock().unwrap().history(wxid);
+    state.insert("name_history".to_string(), json!(names));
+    let bridged = global.bridge_identity_service.lock().unwrap().of(wxid);
+    state.insert("bridge_identities".to_string(), json!(bridged));
+
+    Ok(SubjectData {
+        wxid: wxid.to_string(),
+        aliases: vec![],
+        messages,
+        media,
+        state,
+    })
+}
+
+/// 删除与联系人相关的全部本地数据，范围与 export 一致，最后删除身份合并记录
+pub fn erase(wxid: &str) -> Result<ErasureReport, String> {
+    let global = GLOBAL.get().unwrap();
+    let wxids = global.identity_service.lock().unwrap().wxids(wxid);
+    let mut report = ErasureReport::default();
+    for wxid in &wxids {
+        let one = erase_one(wxid)?;
+        report.messages += one.messages;
+        report.media_files += one.media_files;
+        for (name, removed) in one.state {
+            *report.state.entry(name).or_default() += removed;
+        }
+    }
+    let removed = global.identity_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("identities".to_string(), removed);
+    Ok(report)
+}
+
+fn erase_one(wxid: &str) -> Result<ErasureReport, String> {
+    let global = GLOBAL.get().unwrap();
+    let mut report = ErasureReport::default();
+    {
+        let mut store = global.message_store_service.lock().unwrap();
+        let messages = store.messages_of(wxid)?;
+        for file in MessageStoreService::archived_media(&messages) {
+            match fs::remove_file(&file) {
+                Ok(()) => report.media_files += 1,
+                Err(e) => warn!("删除媒体文件 {} 失败: {}", file.display(), e),
+            }
+        }
+        report.messages = store.forget(wxid)?;
+    }
+    let removed = global.command_permission_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("command_permissions".to_string(), removed);
+    let removed = global.checkin_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("checkin".to_string(), removed);
+    let removed = global.poll_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("polls".to_string(), removed);
+    let removed = global.announcement_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("announcements".to_string(), removed);
+    let removed = global.raffle_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("raffles".to_string(), removed);
+    let removed = global.quiet_hours_service.lock().unwrap().forget(wxid);
+    report.state.insert("quiet_queue".to_string(), removed);
+    let removed = receipt_service::forget(wxid)?;
+    report.state.insert("receipts".to_string(), removed);
+    let removed = global.name_history_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("name_history".to_string(), removed);
+    let removed = global.bridge_identity_service.lock().unwrap().forget(wxid)?;
+    report.state.insert("bridge_identities".to_string(), removed);
+    Ok(report)
+}
@@ -0,0 +1,173 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, moderation_service, pause_service},
+    utils::{pacing, state_store, time_window},
+    wcferry::{wcf::TextMsg, WeChat},
+    wechat_config::QuietHoursConfig,
+};
+
+const STATE_NAME: &str = "quiet_queue";
+
+/// 免打扰期间排队的消息
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct QueuedText {
+    pub receiver: String,
+    pub msg: String,
+    pub aters: String,
+    /// 预计发送时间
+    #[schema(value_type = String, example = "2024-01-01T07:00:00+08:00")]
+    pub until: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct QueueState {
+    queue: Vec<QueuedText>,
+}
+
+// 当前处于免打扰时段时返回时段结束时间，多个时段同时生效时取最晚的
+fn quiet_until(rules: &[QuietHoursConfig], receiver: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    rules
+        .iter()
+        .filter(|r| r.targets.iter().any(|t| t == "*" || t == receiver))
+        .filter_map(|r| {
+            let (start, end) = time_window::parse(&r.start, &r.end)?;
+            let start_date = time_window::started_on(start, end, now.naive_local())?;
+            // 跨天的时段在第二天结束
+            let end_date = if start < end { start_date } else { start_date.succ_opt()? };
+            end_date.and_time(end).and_local_timezone(Local).earliest()
+        })
+        .max()
+}
+
+/** 免打扰：自动发送的消息在免打扰时段内排队，时段结束后依次发出 */
+pub struct QuietHoursService {
+    state: QueueState,
+    pub handle: Option<JoinHandle<()>>,
+}
+
+impl QuietHoursService {
+    pub fn new() -> Self {
+        QuietHoursService {
+            state: state_store::load(STATE_NAME),
+            handle: None,
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = state_store::save(STATE_NAME, &self.state) {
+            warn!("保存免打扰队列失败: {}", e);
+        }
+    }
+
+    pub fn queued(&self) -> Vec<QueuedText> {
+        self.state.queue.clone()
+    }
+
+    /// 删除发给该联系人的排队消息，返回删除条数
+    pub fn forget(&mut self, receiver: &str) -> usize {
+        let before = self.state.queue.len();
+        self.state.queue.retain(|q| q.receiver != receiver);
+        let removed = before - self.state.queue.len();
+        if removed > 0 {
+            self.save();
+        }
+        removed
+    }
+
+    // 取出已经不在免打扰时段内的消息
+    fn take_due(&mut self, rules: &[QuietHoursConfig]) -> Vec<QueuedText> {
+        let now = Local::now();
+        let (due, rest): (Vec<QueuedText>, Vec<QueuedText>) = self
+            .state
+            .queue
+            .drain(..)
+            .partition(|q| quiet_until(rules, &q.receiver, now).is_none());
+        self.state.queue = rest;
+        if !due.is_empty() {
+            self.save();
+        }
+        due
+    }
+
+    // 启动队列发送
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                // 暂停期间消息留在队列里，恢复后再发
+                if pause_service::check().is_err() {
+                    continue;
+                }
+                let global = GLOBAL.get().unwrap();
+                let rules = global.wechat_config.read().unwrap().quiet_hours.clone();
+                let due = global.quiet_hours_service.lock().unwrap().take_due(&rules);
+                if due.is_empty() {
+                    continue;
+                }
+                info!("免打扰结束，发送排队的 {} 条消息", due.len());
+                let wc = wechat.clone();
+                let _ = tokio::task::spawn_blocking(move || {
+                    // 复制一份连接，发送间隔里不占用锁
+                    let wc = wc.lock().unwrap().clone();
+                    for q in due {
+                        let text = TextMsg {
+                            msg: q.msg,
+                            receiver: q.receiver.clone(),
+                            aters: q.aters,
+                        };
+                        if let Err(e) = moderation_service::send_text(&wc, text) {
+                            warn!("发送排队消息到 {} 失败: {}", q.receiver, e);
+                        }
+                        std::thread::sleep(pacing::interval(Duration::from_secs(1)));
+                    }
+                })
+                .await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("免打扰队列已停止");
+        }
+    }
+}
+
+/// 自动发送文本消息，接收方处于免打扰时段时排队，返回是否已立即发送
+///
+/// 定时汇总、群发等非人工触发的发送都应经过这里。
+pub fn send_text(wechat: &WeChat, text: TextMsg) -> Result<bool, String> {
+    pause_service::check()?;
+    let global = GLOBAL.get().unwrap();
+    let until = {
+        let config = global.wechat_config.read().unwrap();
+        quiet_until(&config.quiet_hours, &text.receiver, Local::now())
+    };
+    if let Some(until) = until {
+        // 排队前先审核，有问题时调用方能立即知道
+        moderation_service::check(&text.receiver, &text.msg, None)?;
+        info!("{} 处于免打扰时段，消息排队到 {}", text.receiver, until.format("%H:%M"));
+        let mut service = global.quiet_hours_service.lock().unwrap();
+        service.state.queue.push(QueuedText {
+            receiver: text.receiver,
+            msg: text.msg,
+            aters: text.aters,
+            until,
+        });
+        service.save();
+        return Ok(false);
+    }
+    moderation_service::send_text(wechat, text)
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Duration, Local};
+use log::{info, warn};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    service::moderation_service,
+    utils::state_store,
+    wcferry::{
+        wcf::{TextMsg, WxMsg},
+        WeChat,
+    },
+};
+
+const STATE_NAME: &str = "raffles";
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct Draw {
+    /// 随机数种子，按排序后的参与名单和种子，用 rand_chacha 的 ChaCha20Rng 可以复现抽奖结果
+    pub seed: u64,
+    pub winners: Vec<String>,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub drawn_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct Raffle {
+    pub id: u64,
+    pub roomid: String,
+    /// 参与关键词
+    pub keyword: String,
+    /// 中奖人数
+    pub count: usize,
+    /// 参与者 wxid
+    pub participants: BTreeSet<String>,
+    /// 截止报名时间
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub ends_at: DateTime<Local>,
+    /// 开奖结果，未开奖时为空
+    pub draw: Option<Draw>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RaffleState {
+    next_id: u64,
+    raffles: HashMap<u64, Raffle>,
+}
+
+fn audit_path() -> PathBuf {
+    PathBuf::from(".").join("data").join("raffle-audit.ndjson")
+}
+
+// 每次开奖追加一行到审计文件，便于事后核对
+fn audit(raffle: &Raffle) {
+    let path = audit_path();
+    let line = match serde_json::to_string(raffle) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!("抽奖记录序列化失败: {}", e);
+            return;
+        }
+    };
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = written {
+        warn!("写入抽奖审计记录失败: {}", e);
+    }
+}
+
+// 删除联系人数据时重写审计文件，去掉其中的 wxid
+fn scrub_audit(wxid: &str) -> Result<(), String> {
+    let path = audit_path();
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => return Ok(()),
+    };
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        match serde_json::from_str::<Raffle>(line) {
+            Ok(mut raffle) => {
+                raffle.participants.remove(wxid);
+                if let Some(draw) = raffle.draw.as_mut() {
+                    draw.winners.retain(|w| w != wxid);
+                }
+                lines.push(serde_json::to_string(&raffle).map_err(|e| e.to_string())?);
+            }
+            Err(_) => lines.push(line.to_string()),
+        }
+    }
+    lines.push(String::new());
+    fs::write(&path, lines.join("\n")).map_err(|e| format!("重写抽奖审计记录失败: {}", e))
+}
+
+/** 抽奖：报名期间发送关键词的群成员参与，开奖时随机抽出中奖者 */
+pub struct RaffleService {
+    state: RaffleState,
+}
+
+impl RaffleService {
+    pub fn new() -> Self {
+        RaffleService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    /// 发起抽奖，minutes 为报名时长
+    pub fn create(&mut self, roomid: &str, keyword: &str, count: usize, minutes: u32) -> Result<Raffle, String> {
+        if keyword.trim().is_empty() || count == 0 || minutes == 0 {
+            return Err("关键词不能为空，中奖人数和报名时长必须大于 0".to_string());
+        }
+        self.state.next_id += 1;
+        let raffle = Raffle {
+            id: self.state.next_id,
+            roomid: roomid.to_string(),
+            keyword: keyword.trim().to_string(),
+            count,
+            participants: BTreeSet::new(),
+            ends_at: Local::now() + Duration::minutes(minutes as i64),
+            draw: None,
+        };
+        self.state.raffles.insert(raffle.id, raffle.clone());
+        self.save()?;
+        Ok(raffle)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Raffle> {
+        self.state.raffles.get(&id).cloned()
+    }
+
+    /// 群里最新一个还没开奖的抽奖
+    pub fn latest_open(&self, roomid: &str) -> Option<u64> {
+        self.state
+            .raffles
+            .values()
+            .filter(|r| r.roomid == roomid && r.draw.is_none())
+            .map(|r| r.id)
+            .max()
+    }
+
+    /// 报名期间发送关键词即参与
+    pub fn record(&mut self, msg: &WxMsg) {
+        if msg.r#type != 1 || !msg.is_group || msg.is_self {
+            return;
+        }
+        let now = Local::now();
+        let mut joined = false;
+        for raffle in self.state.raffles.values_mut() {
+            if raffle.roomid != msg.roomid || raffle.draw.is_some() || now > raffle.ends_at {
+                continue;
+            }
+            if msg.content.trim() == raffle.keyword {
+                joined |= raffle.participants.insert(msg.sender.clone());
+            }
+        }
+        if joined {
+            if let Err(e) = self.save() {
+                warn!("保存抽奖记录失败: {}", e);
+            }
+        }
+    }
+
+    /// 该 wxid 参与过的抽奖
+    pub fn joined_by(&self, wxid: &str) -> Vec<Raffle> {
+        let mut raffles: Vec<Raffle> = self
+            .state
+            .raffles
+            .values()
+            .filter(|r| r.participants.contains(wxid))
+            .cloned()
+            .collect();
+        raffles.sort_by_key(|r| r.id);
+        raffles
+    }
+
+    /// 从参与名单、中奖名单和审计文件中移除该 wxid，返回涉及的抽奖数
+    pub fn forget(&mut self, wxid: &str) -> Result<usize, String> {
+        let mut removed = 0;
+        for raffle in self.state.raffles.values_mut() {
+            if raffle.participants.remove(wxid) {
+                removed += 1;
+            }
+            if let Some(draw) = raffle.draw.as_mut() {
+                draw.winners.retain(|w| w != wxid);
+            }
+        }
+        if removed > 0 {
+            self.save()?;
+        }
+        scrub_audit(wxid)?;
+        Ok(removed)
+    }
+
+    /// 开奖，不到截止时间也可以提前开奖
+    pub fn draw(&mut self, id: u64) -> Result<Raffle, String> {
+        let raffle = self.state.raffles.get_mut(&id).ok_or("抽奖不存在")?;
+        if raffle.draw.is_some() {
+            return Err("已经开过奖了".to_string());
+        }
+        let seed: u64 = rand::thread_rng().gen();
+        let participants: Vec<&String> = raffle.participants.iter().collect();
+        // StdRng 的算法可能随 rand 版本变化，固定用 ChaCha20 才能按种子复现开奖结果
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let winners = participants
+            .choose_multiple(&mut rng, raffle.count)
+            .map(|w| (*w).clone())
+            .collect();
+        raffle.draw = Some(Draw {
+            seed,
+            winners,
+            drawn_at: Local::now(),
+        });
+        let raffle = raffle.clone();
+        self.save()?;
+        info!("抽奖 #{} 开奖，种子 {}，{} 人参与", raffle.id, seed, raffle.participants.len());
+        audit(&raffle);
+        Ok(raffle)
+    }
+}
+
+/// 把发起抽奖的消息发到群里
+pub fn announce(wechat: &WeChat, raffle: &Raffle) -> Result<bool, String> {
+    let msg = format!(
+        "抽奖 #{} 开始！{} 前在群里发送「{}」即可参与，将抽出 {} 人",
+        raffle.id,
+        raffle.ends_at.format("%H:%M"),
+        raffle.keyword,
+        raffle.count
+    );
+    let text = TextMsg {
+        msg,
+        receiver: raffle.roomid.clone(),
+        aters: String::new(),
+    };
+    moderation_service::send_text(wechat, text)
+}
+
+/// 把开奖结果发到群里并 @ 中奖者
+pub fn announce_draw(wechat: &WeChat, raffle: &Raffle) -> Result<bool, String> {
+    let winners = raffle.draw.as_ref().map(|d| d.winners.clone()).unwrap_or_default();
+    let msg = if winners.is_empty() {
+        format!("抽奖 #{} 开奖：没有人参与", raffle.id)
+    } else {
+        // @ 时显示群昵称，查不到时显示 wxid
+        let names: HashMap<String, String> = wechat
+            .query_room_member(raffle.roomid.clone())
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|m| (m.wxid, m.name))
+            .collect();
+        let mentions: Vec<String> = winners
+            .iter()
+            .map(|w| format!("@{}", names.get(w).unwrap_or(w)))
+            .collect();
+        format!(
+            "抽奖 #{} 开奖，{} 人参与，中奖的是：{}",
+            raffle.id,
+            raffle.participants.len(),
+            mentions.join(" ")
+        )
+    };
+    let text = TextMsg {
+        msg,
+        receiver: raffle.roomid.clone(),
+        aters: winners.join(","),
+    };
+    moderation_service::send_text(wechat, text)
+}
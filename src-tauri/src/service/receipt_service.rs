@@ -0,0 +1,302 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    service::{global_service::GLOBAL, identity_service, media_service},
+    wcferry::{wcf::WxMsg, WeChat},
+};
+
+/// 图片消息的类型
+const MSG_TYPE_IMAGE: u32 = 3;
+
+// 按优先级排列的金额关键字，实付优先于合计
+const AMOUNT_KEYWORDS: &[&str] = &["实付", "实收", "合计", "总计", "应付", "金额", "total", "amount"];
+
+// 含有这些字的行多半是商户名
+const VENDOR_HINTS: &[&str] = &["公司", "有限", "超市", "商店", "餐厅", "酒店", "药房", "店"];
+
+// 读写 CSV 时加锁，避免同时追加
+static CSV_LOCK: Mutex<()> = Mutex::new(());
+
+/// 从票据中提取的字段，识别不出的为空
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct ReceiptFields {
+    pub amount: Option<f64>,
+    /// 开票日期，格式 YYYY-MM-DD
+    pub date: Option<String>,
+    pub vendor: Option<String>,
+}
+
+/// 一张票据的识别结果
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct Receipt {
+    pub msg_id: u64,
+    pub sender: String,
+    pub roomid: String,
+    pub amount: Option<f64>,
+    pub date: Option<String>,
+    pub vendor: Option<String>,
+    /// 识别出的全部文字
+    pub text: String,
+    /// 解密后的图片路径
+    pub image: String,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub received_at: DateTime<Local>,
+}
+
+fn csv_path() -> PathBuf {
+    let path = GLOBAL.get().unwrap().wechat_config.read().unwrap().receipt.csv.clone();
+    if path.is_empty() {
+        PathBuf::from(".").join("data").join("receipts.csv")
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// 配置的联系人发来的图片需要识别
+pub fn wanted(msg: &WxMsg) -> bool {
+    if msg.r#type != MSG_TYPE_IMAGE || msg.is_self {
+        return false;
+    }
+    let senders = GLOBAL.get().unwrap().wechat_config.read().unwrap().receipt.senders.clone();
+    identity_service::contains(&senders, &msg.sender)
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+fn amount(text: &str) -> Option<f64> {
+    static KEYWORD: OnceLock<Regex> = OnceLock::new();
+    static CURRENCY: OnceLock<Regex> = OnceLock::new();
+    let keyword = regex(
+        &KEYWORD,
+        r"(?i)(实付|实收|合计|总计|应付|金额|total|amount)[^\d\n]{0,8}?(\d+(?:\.\d{1,2})?)",
+    );
+    let found: Vec<(usize, f64)> = keyword
+        .captures_iter(text)
+        .filter_map(|c| {
+            let rank = AMOUNT_KEYWORDS.iter().position(|k| *k == c[1].to_lowercase())?;
+            Some((rank, c[2].parse().ok()?))
+        })
+        .collect();
+    if let Some((_, amount)) = found.iter().min_by_key(|(rank, _)| *rank) {
+        return Some(*amount);
+    }
+    // 没有关键字时取带货币符号的最大金额
+    let currency = regex(&CURRENCY, r"[¥￥]\s*(\d+(?:\.\d{1,2})?)");
+    currency
+        .captures_iter(text)
+        .filter_map(|c| c[1].parse::<f64>().ok())
+        .reduce(f64::max)
+}
+
+fn date(text: &str) -> Option<String> {
+    static DATE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&DATE, r"(20\d{2})\s*[-/.年]\s*(\d{1,2})\s*[-/.月]\s*(\d{1,2})");
+    re.captures_iter(text).find_map(|c| {
+        let date = NaiveDate::from_ymd_opt(c[1].parse().ok()?, c[2].parse().ok()?, c[3].parse().ok()?)?;
+        Some(date.format("%Y-%m-%d").to_string())
+    })
+}
+
+fn vendor(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+    lines
+        .iter()
+        .find(|l| VENDOR_HINTS.iter().any(|h| l.contains(h)))
+        // 没有提示词时取第一行有文字的内容，票据抬头通常是商户名
+        .or_else(|| lines.iter().find(|l| l.chars().any(|c| c.is_alphabetic())))
+        .map(|l| l.to_string())
+}
+
+/// 内置规则：按关键字和常见格式提取金额、日期和商户
+pub fn parse_builtin(text: &str) -> ReceiptFields {
+    ReceiptFields {
+        amount: amount(text),
+        date: date(text),
+        vendor: vendor(text),
+    }
+}
+
+// 调用外部解析程序，识别出的文字写入标准输入，从标准输出读取 JSON，超过 timeout 后终止
+fn parse_external(program: &str, text: &str, timeout: Duration) -> Result<ReceiptFields, String> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("启动解析程序 {} 失败: {}", program, e))?;
+    let deadline = Instant::now() + timeout;
+    if let Some(mut stdin) = child.stdin.take() {
+        let text = text.to_string();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(text.as_bytes());
+        });
+    }
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("解析程序运行超过 {} 秒，已终止", timeout.as_secs()));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let output = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(format!("解析程序退出码 {}", status));
+    }
+    serde_json::from_slice(&output).map_err(|e| format!("解析程序输出不是有效的 JSON: {}", e))
+}
+
+/// 提取票据字段，配置了外部解析程序时优先使用，失败后退回内置规则
+pub fn parse(text: &str) -> ReceiptFields {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().receipt.clone();
+    if !config.parser.is_empty() {
+        match parse_external(&config.parser, text, Duration::from_secs(config.parser_timeout_secs.max(1))) {
+            Ok(fields) => return fields,
+            Err(e) => warn!("外部票据解析失败，改用内置规则: {}", e),
+        }
+    }
+    parse_builtin(text)
+}
+
+/// 识别图片文字，微信还在识别时稍后重试直到超时
+pub fn ocr(wechat: &Arc<Mutex<WeChat>>, image: &str, timeout: Duration) -> Result<String, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let result = wechat.lock().unwrap().exec_ocr(image.to_string()).map_err(|e| e.to_string())?;
+        if result.status == 0 {
+            return Ok(result.result);
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("文字识别失败，状态 {}", result.status));
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// 追加一条记录，文件不存在时先写表头
+pub fn append(receipt: &Receipt) -> Result<(), String> {
+    let path = csv_path();
+    let _guard = CSV_LOCK.lock().unwrap();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let is_new = fs::metadata(&path).map_or(true, |m| m.len() == 0);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("打开 {} 失败: {}", path.display(), e))?;
+    let mut writer = csv::WriterBuilder::new().has_headers(is_new).from_writer(file);
+    writer.serialize(receipt).map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())
+}
+
+fn read_all() -> Result<Vec<Receipt>, String> {
+    let path = csv_path();
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let mut reader = csv::Reader::from_path(&path).map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    // 手工编辑过的文件可能有坏行，跳过而不是让整个列表都读不出来
+    Ok(reader
+        .deserialize()
+        .filter_map(|row| {
+            row.map_err(|e| warn!("跳过 {} 中无法解析的记录: {}", path.display(), e)).ok()
+        })
+        .collect())
+}
+
+/// 已记录的票据，sender 不为空时只返回其发来的
+pub fn list(sender: Option<&str>) -> Result<Vec<Receipt>, String> {
+    let _guard = CSV_LOCK.lock().unwrap();
+    let mut receipts = read_all()?;
+    if let Some(sender) = sender {
+        receipts.retain(|r| r.sender == sender);
+    }
+    Ok(receipts)
+}
+
+/// 删除某人发来的票据记录，返回删除的条数
+pub fn forget(wxid: &str) -> Result<usize, String> {
+    let _guard = CSV_LOCK.lock().unwrap();
+    let receipts = read_all()?;
+    let kept: Vec<&Receipt> = receipts.iter().filter(|r| r.sender != wxid).collect();
+    let removed = receipts.len() - kept.len();
+    if removed == 0 {
+        return Ok(0);
+    }
+    let path = csv_path();
+    let tmp = path.with_extension("tmp");
+    let mut writer = csv::Writer::from_path(&tmp).map_err(|e| e.to_string())?;
+    for receipt in kept {
+        writer.serialize(receipt).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    drop(writer);
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    Ok(removed)
+}
+
+/// 识别已解密的票据图片并追加到 CSV
+pub fn extract(wechat: &Arc<Mutex<WeChat>>, msg: &WxMsg, image: &str) -> Result<Receipt, String> {
+    let timeout = GLOBAL.get().unwrap().wechat_config.read().unwrap().receipt.timeout_secs;
+    let text = ocr(wechat, image, Duration::from_secs(timeout.max(1)))?;
+    let fields = parse(&text);
+    let receipt = Receipt {
+        msg_id: msg.id,
+        sender: msg.sender.clone(),
+        roomid: msg.roomid.clone(),
+        amount: fields.amount,
+        date: fields.date,
+        vendor: fields.vendor,
+        text,
+        image: image.to_string(),
+        received_at: Local::now(),
+    };
+    append(&receipt)?;
+    info!("已识别 {} 发来的票据: {:?} {:?} {:?}", msg.sender, receipt.vendor, receipt.date, receipt.amount);
+    Ok(receipt)
+}
+
+/// 下载并解密图片消息后识别
+pub async fn process(wechat: &Arc<Mutex<WeChat>>, msg: &WxMsg) -> Result<Receipt, String> {
+    let timeout = GLOBAL.get().unwrap().wechat_config.read().unwrap().receipt.timeout_secs;
+    let dir = media_service::media_dir().join("receipts");
+    let image = media_service::download_and_decrypt_image(
+        wechat,
+        msg.id,
+        &msg.extra,
+        &dir.to_string_lossy(),
+        Duration::from_secs(timeout.max(1)),
+    )
+    .await?;
+    let wechat = wechat.clone();
+    let msg = msg.clone();
+    tokio::task::spawn_blocking(move || extract(&wechat, &msg, &image))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))
+}
@@ -0,0 +1,81 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::{event_entity::Event, message::tap_message_handler},
+    service::global_service::GLOBAL,
+    wcferry::wcf::WxMsg,
+};
+
+/// 回放结果
+#[derive(Serialize, ToSchema, Debug, Default)]
+pub struct ReplayReport {
+    /// 已送入消息处理流程的条数
+    pub replayed: usize,
+    /// 无法解析的行号，从 1 开始
+    pub skipped: Vec<usize>,
+}
+
+/// 要回放的消息记录文件，只能是 tap.dir 中的文件，file 为相对该目录的路径
+pub fn resolve_file(file: &str) -> Result<PathBuf, String> {
+    let config = GLOBAL.get().unwrap().wechat_config.read().unwrap().tap.clone();
+    let dir = tap_message_handler::tap_dir(&config);
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("消息记录目录 {} 不可用: {}", dir.display(), e))?;
+    let path = dir
+        .join(Path::new(file))
+        .canonicalize()
+        .map_err(|e| format!("文件 {} 不可用: {}", file, e))?;
+    if !path.starts_with(&dir) || !path.is_file() {
+        return Err(format!("文件 {} 不在消息记录目录 {} 中", file, dir.display()));
+    }
+    Ok(path)
+}
+
+/// 解析 NDJSON，每行可以是原始 WxMsg，也可以是 v1、v2 推送格式
+pub fn parse_ndjson(text: &str) -> (Vec<WxMsg>, Vec<usize>) {
+    let mut messages = vec![];
+    let mut skipped = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parsed = serde_json::from_str::<Value>(line).ok().and_then(|mut value| {
+            // v2 格式中原始消息在 message 字段里
+            if let Some(message) = value.get_mut("message").filter(|m| m.is_object()) {
+                value = message.take();
+            }
+            serde_json::from_value::<WxMsg>(value).ok()
+        });
+        match parsed {
+            Some(msg) => messages.push(msg),
+            None => skipped.push(index + 1),
+        }
+    }
+    (messages, skipped)
+}
+
+/// 按顺序把消息重新发布到消息总线，interval 为相邻两条之间的间隔
+pub async fn replay(messages: Vec<WxMsg>, interval: Duration) -> usize {
+    let total = messages.len();
+    info!("开始回放 {} 条消息", total);
+    for (index, msg) in messages.into_iter().enumerate() {
+        if index > 0 && !interval.is_zero() {
+            tokio::time::sleep(interval).await;
+        }
+        let global = GLOBAL.get().unwrap();
+        let event_bus = global.msg_event_bus.lock().unwrap();
+        event_bus.send_message(Event::ClientMessage(msg));
+    }
+    info!("回放结束，共 {} 条", total);
+    total
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{service::global_service::GLOBAL, utils::state_store};
+
+const STATE_NAME: &str = "risk_budget";
+
+/// 需要控制频率的高风险操作
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskOperation {
+    /// 通过好友申请
+    FriendAdd,
+    /// 直接拉人进群
+    GroupAdd,
+    /// 邀请进群
+    GroupInvite,
+    /// 踢出群聊
+    GroupKick,
+    /// 批量发送的消息条数
+    BulkSend,
+    /// 好友状态探测
+    FriendCheck,
+    /// 在群里 @所有人
+    AtAll,
+}
+
+impl RiskOperation {
+    pub const ALL: [RiskOperation; 7] = [
+        RiskOperation::FriendAdd,
+        RiskOperation::GroupAdd,
+        RiskOperation::GroupInvite,
+        RiskOperation::GroupKick,
+        RiskOperation::BulkSend,
+        RiskOperation::FriendCheck,
+        RiskOperation::AtAll,
+    ];
+
+    pub fn desc(&self) -> &'static str {
+        match self {
+            RiskOperation::FriendAdd => "通过好友申请",
+            RiskOperation::GroupAdd => "拉人进群",
+            RiskOperation::GroupInvite => "邀请进群",
+            RiskOperation::GroupKick => "踢出群聊",
+            RiskOperation::BulkSend => "批量发送",
+            RiskOperation::FriendCheck => "好友状态探测",
+            RiskOperation::AtAll => "@所有人",
+        }
+    }
+}
+
+/// 某项操作的今日预算
+#[derive(Serialize, ToSchema, Debug)]
+pub struct BudgetStatus {
+    pub operation: RiskOperation,
+    pub used: u32,
+    pub limit: u32,
+    pub remaining: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BudgetState {
+    day: Option<NaiveDate>,
+    used: HashMap<RiskOperation, u32>,
+}
+
+/** 按天统计高风险操作次数，超出配置的上限后拒绝执行 */
+pub struct RiskGuardService {
+    state: BudgetState,
+}
+
+impl RiskGuardService {
+    pub fn new() -> Self {
+        RiskGuardService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn limit(operation: RiskOperation) -> Option<u32> {
+        let global = GLOBAL.get().unwrap();
+        let config = global.wechat_config.read().unwrap();
+        let budgets = &config.risk_guard;
+        if !budgets.enabled {
+            return None;
+        }
+        Some(match operation {
+            RiskOperation::FriendAdd => budgets.friend_add,
+            RiskOperation::GroupAdd => budgets.group_add,
+            RiskOperation::GroupInvite => budgets.group_invite,
+            RiskOperation::GroupKick => budgets.group_kick,
+            RiskOperation::BulkSend => budgets.bulk_send,
+            RiskOperation::FriendCheck => budgets.friend_check,
+            RiskOperation::AtAll => budgets.at_all,
+        })
+    }
+
+    // 跨天后清零
+    fn roll_day(&mut self) {
+        let today = Local::now().date_naive();
+        if self.state.day != Some(today) {
+            self.state.day = Some(today);
+            self.state.used.clear();
+        }
+    }
+
+    /// 扣减预算，不足时返回错误且不扣减
+    pub fn consume(&mut self, operation: RiskOperation, count: u32) -> Result<(), String> {
+        self.roll_day();
+        let limit = match Self::limit(operation) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let used = self.state.used.get(&operation).copied().unwrap_or(0);
+        if used.saturating_add(count) > limit {
+            return Err(format!(
+                "今日{}次数已达上限（已用 {}，上限 {}，本次 {}）",
+                operation.desc(),
+                used,
+                limit,
+                count
+            ));
+        }
+        self.state.used.insert(operation, used + count);
+        if let Err(e) = state_store::save(STATE_NAME, &self.state) {
+            log::warn!("保存风控预算失败: {}", e);
+        }
+        Ok(())
+    }
+
+    /// 各项操作今日剩余预算
+    pub fn status(&mut self) -> Vec<BudgetStatus> {
+        self.roll_day();
+        RiskOperation::ALL
+            .iter()
+            .map(|op| {
+                let used = self.state.used.get(op).copied().unwrap_or(0);
+                let limit = Self::limit(*op).unwrap_or(u32::MAX);
+                BudgetStatus {
+                    operation: *op,
+                    used,
+                    limit,
+                    remaining: limit.saturating_sub(used),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::state_store;
+
+const STATE_NAME: &str = "rule_groups";
+
+#[derive(Serialize, Deserialize, Default)]
+struct RuleGroupState {
+    disabled: BTreeSet<String>,
+}
+
+/** 记录停用的规则分组，同一分组的规则一起启用或停用，没有分组的规则始终生效 */
+pub struct RuleGroupService {
+    state: RuleGroupState,
+}
+
+impl RuleGroupService {
+    pub fn new() -> Self {
+        RuleGroupService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    pub fn is_enabled(&self, group: &str) -> bool {
+        group.is_empty() || !self.state.disabled.contains(group)
+    }
+
+    pub fn set_enabled(&mut self, group: &str, enabled: bool) -> Result<(), String> {
+        if enabled {
+            self.state.disabled.remove(group);
+        } else {
+            self.state.disabled.insert(group.to_string());
+        }
+        self.save()
+    }
+}
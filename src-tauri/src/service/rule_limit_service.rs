@@ -0,0 +1,106 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Local, TimeZone};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::wechat_config::RuleConfig;
+
+const HOUR_SECS: i64 = 3600;
+
+#[derive(Default)]
+struct FireLog {
+    // 最近一小时内触发的时间戳
+    recent: VecDeque<i64>,
+    total: u64,
+    suppressed: u64,
+    last: Option<i64>,
+}
+
+impl FireLog {
+    fn prune(&mut self, now: i64) {
+        while self.recent.front().map_or(false, |ts| now - ts >= HOUR_SECS) {
+            self.recent.pop_front();
+        }
+    }
+}
+
+// (规则名, 会话) -> 触发记录，重启后清零
+static FIRES: Mutex<Option<HashMap<(String, String), FireLog>>> = Mutex::new(None);
+
+/// 一条规则在一个会话中的触发计数
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct RuleStats {
+    pub rule: String,
+    pub roomid: String,
+    /// 最近一小时触发的次数
+    pub last_hour: usize,
+    /// 启动以来触发的次数
+    pub total: u64,
+    /// 因冷却或次数上限被跳过的次数
+    pub suppressed: u64,
+    #[schema(value_type = Option<String>, example = "2024-01-01T12:00:00+08:00")]
+    pub last_fired_at: Option<DateTime<Local>>,
+}
+
+fn key(rule: &RuleConfig, roomid: &str) -> (String, String) {
+    (rule.name.clone(), roomid.to_string())
+}
+
+/// 规则在会话中处于冷却或已达到每小时上限时返回原因，不做记录
+pub fn throttled(rule: &RuleConfig, roomid: &str, now: i64) -> Option<String> {
+    let mut fires = FIRES.lock().unwrap();
+    let log = fires.get_or_insert_with(HashMap::new).get_mut(&key(rule, roomid))?;
+    log.prune(now);
+    if let Some(last) = log.last {
+        let left = rule.cooldown_secs as i64 - (now - last);
+        if left > 0 {
+            return Some(format!("冷却中，还剩 {} 秒", left));
+        }
+    }
+    if rule.max_per_hour > 0 && log.recent.len() >= rule.max_per_hour as usize {
+        return Some(format!("最近一小时已触发 {} 次", log.recent.len()));
+    }
+    None
+}
+
+/// 记录一次触发
+pub fn fired(rule: &RuleConfig, roomid: &str, now: i64) {
+    let mut fires = FIRES.lock().unwrap();
+    let log = fires.get_or_insert_with(HashMap::new).entry(key(rule, roomid)).or_default();
+    log.prune(now);
+    log.recent.push_back(now);
+    log.total += 1;
+    log.last = Some(now);
+}
+
+/// 记录一次被跳过的触发
+pub fn suppressed(rule: &RuleConfig, roomid: &str) {
+    let mut fires = FIRES.lock().unwrap();
+    fires.get_or_insert_with(HashMap::new).entry(key(rule, roomid)).or_default().suppressed += 1;
+}
+
+/// 全部计数，按规则名和会话排列
+pub fn stats(now: i64) -> Vec<RuleStats> {
+    let mut fires = FIRES.lock().unwrap();
+    let mut stats: Vec<RuleStats> = fires
+        .get_or_insert_with(HashMap::new)
+        .iter_mut()
+        .map(|((rule, roomid), log)| {
+            log.prune(now);
+            RuleStats {
+                rule: rule.clone(),
+                roomid: roomid.clone(),
+                last_hour: log.recent.len(),
+                total: log.total,
+                suppressed: log.suppressed,
+                last_fired_at: log.last.and_then(|ts| Local.timestamp_opt(ts, 0).single()),
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| (&a.rule, &a.roomid).cmp(&(&b.rule, &b.roomid)));
+    stats
+}
@@ -0,0 +1,735 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{Datelike, Local, TimeZone};
+use log::{info, warn};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::message::payload,
+    service::{global_service::GLOBAL, identity_service, quiet_hours_service, rule_limit_service, rule_var_service},
+    utils::time_window,
+    wcferry::{
+        wcf::{TextMsg, WxMsg},
+        WeChat,
+    },
+    wechat_config::{
+        Bounds, CompareOp, Condition, HttpAction, RuleAction, RuleConfig, RuleScriptConfig, RuleWindow, ScriptAction,
+        VarCondition,
+    },
+};
+
+/// 发回会话的响应最多保留的字数
+const MAX_REPLY_CHARS: usize = 2000;
+
+/// 文本消息类型，没有 type 或媒体条件的规则只处理文本消息
+const MSG_TYPE_TEXT: u32 = 1;
+const MSG_TYPE_IMAGE: u32 = 3;
+
+/// 规则对一条消息的测试结果
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct RuleTrace {
+    pub rule: String,
+    pub group: String,
+    pub priority: i32,
+    pub matched: bool,
+    /// 没有命中的原因
+    pub reason: Option<String>,
+    /// 命中但因冷却或次数上限不会执行的原因
+    pub throttled: Option<String>,
+    /// 命中时模板中可以使用的变量，规则变量按需读取，只列出动作设置过的
+    pub vars: BTreeMap<String, String>,
+    /// 命中时会执行的动作，按顺序排列
+    pub actions: Vec<ActionPreview>,
+}
+
+/// 规则分组及其中的规则
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct RuleGroup {
+    pub name: String,
+    pub enabled: bool,
+    pub rules: Vec<String>,
+}
+
+/// 动作渲染后的内容，不会真的执行
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct ActionPreview {
+    /// reply、http、script、set 或 incr
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// 回复的内容、请求的地址、脚本路径或变量名
+    pub target: String,
+    /// http 动作的请求方法
+    pub method: Option<String>,
+    /// http 动作的请求体，或变量设置后的值
+    pub body: Option<String>,
+    /// 执行时会被拦下的原因
+    pub blocked: Option<String>,
+}
+
+// 消息时间落在任一时段内，并且时段开始的那天在 weekdays 中
+fn in_windows(windows: &[RuleWindow], ts: i64) -> bool {
+    let now = match Local.timestamp_opt(ts, 0).single() {
+        Some(now) => now.naive_local(),
+        None => return false,
+    };
+    windows.iter().any(|w| {
+        time_window::parse(&w.start, &w.end)
+            .and_then(|(start, end)| time_window::started_on(start, end, now))
+            .map_or(false, |day| w.weekdays.is_empty() || w.weekdays.contains(&day.weekday().number_from_monday()))
+    })
+}
+
+// 两边都是数字时按数值比较，否则按字符串比较
+fn compare(actual: &str, op: CompareOp, expected: &str) -> bool {
+    let number = |v: &str| if v.is_empty() { Some(0.0) } else { v.parse::<f64>().ok() };
+    let ordering = match (number(actual), expected.parse::<f64>().ok()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b),
+        _ => Some(actual.cmp(expected)),
+    };
+    match (op, ordering) {
+        (CompareOp::Eq, o) => o == Some(std::cmp::Ordering::Equal),
+        (CompareOp::Ne, o) => o != Some(std::cmp::Ordering::Equal),
+        (_, None) => false,
+        (CompareOp::Gt, Some(o)) => o.is_gt(),
+        (CompareOp::Ge, Some(o)) => o.is_ge(),
+        (CompareOp::Lt, Some(o)) => o.is_lt(),
+        (CompareOp::Le, Some(o)) => o.is_le(),
+    }
+}
+
+// 不满足时返回原因
+fn check_condition(cond: &VarCondition, vars: &HashMap<String, String>) -> Result<(), String> {
+    let name = render(&cond.var, vars, raw);
+    let actual = lookup(vars, &format!("var.{}", name)).unwrap_or_default();
+    let expected = render(&cond.value, vars, raw);
+    if compare(&actual, cond.op, &expected) {
+        Ok(())
+    } else {
+        Err(format!("变量 {} 的值 \"{}\" 不满足 {:?} \"{}\"", name, actual, cond.op, expected))
+    }
+}
+
+// 条件本身或其中嵌套的条件是否满足 f
+fn has(cond: &Condition, f: fn(&Condition) -> bool) -> bool {
+    f(cond)
+        || match cond {
+            Condition::All(items) | Condition::Any(items) => items.iter().any(|c| has(c, f)),
+            Condition::Not(inner) => has(inner, f),
+            _ => false,
+        }
+}
+
+fn rule_has(rule: &RuleConfig, f: fn(&Condition) -> bool) -> bool {
+    rule.condition.as_ref().map_or(false, |c| has(c, f))
+}
+
+// 会用到非文本消息的条件
+fn media_condition(cond: &Condition) -> bool {
+    matches!(
+        cond,
+        Condition::Type(_) | Condition::IsImage(_) | Condition::Extension(_) | Condition::Size(_) | Condition::Duration(_)
+    )
+}
+
+fn within(value: Option<u64>, bounds: &Bounds, what: &str) -> Result<(), String> {
+    let value = value.ok_or_else(|| format!("消息没有{}", what))?;
+    if bounds.min.map_or(true, |min| value >= min) && bounds.max.map_or(true, |max| value <= max) {
+        Ok(())
+    } else {
+        Err(format!("{} {} 不在范围内", what, value))
+    }
+}
+
+// 不满足时返回原因；满足的 regex 条件的分组放入模板变量，not 中的不放
+fn eval(
+    cond: &Condition,
+    rule: &RuleConfig,
+    msg: &WxMsg,
+    at_me: bool,
+    vars: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    match cond {
+        Condition::All(items) => items.iter().try_for_each(|c| eval(c, rule, msg, at_me, vars)),
+        Condition::Any(items) => {
+            let mut reasons = vec![];
+            for c in items {
+                let mut branch = vars.clone();
+                match eval(c, rule, msg, at_me, &mut branch) {
+                    Ok(()) => {
+                        *vars = branch;
+                        return Ok(());
+                    }
+                    Err(reason) => reasons.push(reason),
+                }
+            }
+            Err(format!("any 中的条件都不满足: {}", reasons.join("；")))
+        }
+        Condition::Not(inner) => match eval(inner, rule, msg, at_me, &mut vars.clone()) {
+            Ok(()) => Err("满足了 not 中的条件".to_string()),
+            Err(_) => Ok(()),
+        },
+        Condition::Sender(senders) if !identity_service::contains(senders, &msg.sender) => {
+            Err(format!("发送人 {} 不在 sender 中", msg.sender))
+        }
+        Condition::Room(rooms) if !rooms.contains(&msg.roomid) => Err(format!("会话 {} 不在 room 中", msg.roomid)),
+        Condition::Type(types) if !types.contains(&msg.r#type) => Err(format!("消息类型 {} 不在 type 中", msg.r#type)),
+        Condition::Regex(pattern) => captures(&rule.name, pattern, msg, vars),
+        Condition::Time(window) if !in_windows(std::slice::from_ref(window), msg.ts as i64) => {
+            Err(format!("不在时段 {}-{} 内", window.start, window.end))
+        }
+        Condition::IsAtMe(expected) if *expected != at_me => {
+            Err(if at_me { "消息 @ 了自己" } else { "消息没有 @ 自己" }.to_string())
+        }
+        Condition::IsImage(expected) if *expected != (msg.r#type == MSG_TYPE_IMAGE) => {
+            Err(if *expected { "不是图片消息" } else { "是图片消息" }.to_string())
+        }
+        Condition::Extension(extensions) => match payload::parse_media_info(msg).extension {
+            Some(ext) if extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&ext)) => Ok(()),
+            Some(ext) => Err(format!("扩展名 {} 不在 extension 中", ext)),
+            None => Err("不是文件消息".to_string()),
+        },
+        Condition::Size(bounds) => within(payload::parse_media_info(msg).size, bounds, "大小"),
+        Condition::Duration(bounds) => within(payload::parse_media_info(msg).duration, bounds, "时长"),
+        _ => Ok(()),
+    }
+}
+
+/// 命中规则时的模板变量：content、sender、roomid、id、正则的分组以及 var.名称 形式的规则变量，
+/// 媒体消息还有 extension、size、duration（能解析出来时）
+pub fn matched(rule: &RuleConfig, msg: &WxMsg, at_me: bool) -> Option<HashMap<String, String>> {
+    check(rule, msg, at_me).ok()
+}
+
+// 没有命中时返回原因
+fn check(rule: &RuleConfig, msg: &WxMsg, at_me: bool) -> Result<HashMap<String, String>, String> {
+    if msg.r#type != MSG_TYPE_TEXT && !rule_has(rule, media_condition) {
+        return Err(format!("没有 type 或媒体条件，不处理类型为 {} 的消息", msg.r#type));
+    }
+    if !rule.rooms.is_empty() && !rule.rooms.contains(&msg.roomid) {
+        return Err(format!("会话 {} 不在 rooms 中", msg.roomid));
+    }
+    if !rule.senders.is_empty() && !identity_service::contains(&rule.senders, &msg.sender) {
+        return Err(format!("发送人 {} 不在 senders 中", msg.sender));
+    }
+    if !rule.windows.is_empty() && !in_windows(&rule.windows, msg.ts as i64) {
+        return Err("不在生效时段内".to_string());
+    }
+    let mut vars = HashMap::from([
+        ("content".to_string(), msg.content.clone()),
+        ("sender".to_string(), msg.sender.clone()),
+        ("roomid".to_string(), msg.roomid.clone()),
+        ("id".to_string(), msg.id.to_string()),
+    ]);
+    if msg.r#type != MSG_TYPE_TEXT {
+        let media = payload::parse_media_info(msg);
+        if let Some(ext) = media.extension {
+            vars.insert("extension".to_string(), ext);
+        }
+        if let Some(size) = media.size {
+            vars.insert("size".to_string(), size.to_string());
+        }
+        if let Some(duration) = media.duration {
+            vars.insert("duration".to_string(), duration.to_string());
+        }
+    }
+    if !rule.pattern.is_empty() {
+        captures(&rule.name, &rule.pattern, msg, &mut vars)?;
+    }
+    if let Some(cond) = &rule.condition {
+        eval(cond, rule, msg, at_me, &mut vars)?;
+    }
+    for cond in &rule.when {
+        check_condition(cond, &vars)?;
+    }
+    Ok(vars)
+}
+
+/// 缓存的正则个数上限，修改配置留下的旧正则超过后整体清空
+const MAX_CACHED_REGEXES: usize = 512;
+
+// 编译过的正则，同一个 pattern 只编译一次，无效的也记下来，避免每条消息都重新编译、重复告警
+static REGEXES: Mutex<Option<HashMap<String, Result<Regex, String>>>> = Mutex::new(None);
+
+fn regex(rule: &str, pattern: &str) -> Result<Regex, String> {
+    let mut guard = REGEXES.lock().unwrap();
+    let cache = guard.get_or_insert_with(HashMap::new);
+    if let Some(compiled) = cache.get(pattern) {
+        return compiled.clone();
+    }
+    if cache.len() >= MAX_CACHED_REGEXES {
+        cache.clear();
+    }
+    let compiled = Regex::new(pattern).map_err(|e| {
+        warn!("规则 {} 的正则无效: {}", rule, e);
+        format!("正则无效: {}", e)
+    });
+    cache.insert(pattern.to_string(), compiled.clone());
+    compiled
+}
+
+// 正则匹配，分组放入模板变量
+fn captures(rule: &str, pattern: &str, msg: &WxMsg, vars: &mut HashMap<String, String>) -> Result<(), String> {
+    let re = regex(rule, pattern)?;
+    let caps = re
+        .captures(&msg.content)
+        .ok_or_else(|| format!("内容不匹配 {}", pattern))?;
+    for (i, name) in re.capture_names().enumerate() {
+        if let Some(m) = caps.get(i) {
+            vars.insert(i.to_string(), m.as_str().to_string());
+            if let Some(name) = name {
+                vars.insert(name.to_string(), m.as_str().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+// 模板变量，var.名称 形式的规则变量不在 vars 中时才去读取，不用每条消息都复制全部变量
+fn lookup(vars: &HashMap<String, String>, name: &str) -> Option<String> {
+    if let Some(value) = vars.get(name) {
+        return Some(value.clone());
+    }
+    let var = name.strip_prefix("var.")?;
+    GLOBAL.get().unwrap().rule_var_service.lock().unwrap().get(var)
+}
+
+// 与开头的 { 配对的 } 的位置
+fn closing(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 把模板中的 {变量} 替换为转义后的值，变量名中可以再引用变量，例如 {var.asked_{roomid}}；不认识的花括号原样保留
+pub fn render(template: &str, vars: &HashMap<String, String>, escape: fn(&str) -> String) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = closing(rest).and_then(|end| {
+            let inner = &rest[1..end];
+            let name = if inner.contains('{') { render(inner, vars, raw) } else { inner.to_string() };
+            lookup(vars, &name).map(|v| (end, v))
+        });
+        match value {
+            Some((end, v)) => {
+                text.push_str(&escape(&v));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn raw(v: &str) -> String {
+    v.to_string()
+}
+
+fn url_escape(v: &str) -> String {
+    urlencoding::encode(v).into_owned()
+}
+
+// 放进 JSON 字符串里的值，去掉两边的引号
+fn json_escape(v: &str) -> String {
+    let quoted = Value::String(v.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+// 响应为 JSON 时取 reply 字段或字符串本身，否则使用原文
+fn reply_text(body: &str) -> String {
+    let text = match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(o)) => o.get("reply").and_then(Value::as_str).unwrap_or_default().to_string(),
+        Ok(Value::String(s)) => s,
+        _ => body.to_string(),
+    };
+    text.trim().chars().take(MAX_REPLY_CHARS).collect()
+}
+
+// 渲染后的请求地址和请求体
+fn request(action: &HttpAction, vars: &HashMap<String, String>, msg: &WxMsg) -> Result<(String, String), String> {
+    let url = render(&action.url, vars, url_escape);
+    let is_json = action
+        .headers
+        .iter()
+        .any(|(k, v)| k.eq_ignore_ascii_case("content-type") && v.contains("json"));
+    let body = if action.body.is_empty() {
+        serde_json::to_string(msg).map_err(|e| e.to_string())?
+    } else if is_json {
+        render(&action.body, vars, json_escape)
+    } else {
+        render(&action.body, vars, raw)
+    };
+    Ok((url, body))
+}
+
+/// 调用外部接口，返回要发回会话的内容
+pub fn call(action: &HttpAction, vars: &HashMap<String, String>, msg: &WxMsg) -> Result<Option<String>, String> {
+    let (url, body) = request(action, vars, msg)?;
+    let mut request =
+        ureq::request(&action.method.to_uppercase(), &url).timeout(Duration::from_secs(action.timeout_secs));
+    if action.body.is_empty() {
+        request = request.set("Content-Type", "application/json");
+    }
+    for (name, value) in &action.headers {
+        // 去掉换行，避免变量里的内容注入其他请求头
+        let value = render(value, vars, raw).replace(['\r', '\n'], " ");
+        request = request.set(name, &value);
+    }
+    let response = match request.send_string(&body) {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, _)) => return Err(format!("{} 返回 {}", url, code)),
+        Err(e) => return Err(format!("请求 {} 失败: {}", url, e)),
+    };
+    if !action.reply {
+        return Ok(None);
+    }
+    let text = reply_text(&response.into_string().map_err(|e| e.to_string())?);
+    Ok(Some(text).filter(|t| !t.is_empty()))
+}
+
+/// 找到脚本目录下的脚本，解析符号链接后仍须在目录内
+pub fn resolve_script(dir: &str, script: &str) -> Result<PathBuf, String> {
+    if dir.trim().is_empty() {
+        return Err("没有配置 rule_scripts.dir".to_string());
+    }
+    let dir = Path::new(dir)
+        .canonicalize()
+        .map_err(|e| format!("脚本目录 {} 不可用: {}", dir, e))?;
+    let path = dir
+        .join(script)
+        .canonicalize()
+        .map_err(|e| format!("脚本 {} 不可用: {}", script, e))?;
+    if !path.starts_with(&dir) || !path.is_file() {
+        return Err(format!("脚本 {} 不在 {} 中", script, dir.display()));
+    }
+    Ok(path)
+}
+
+// 传给脚本的环境变量，Windows 上缺少 SystemRoot、TEMP 等时很多程序无法启动
+const PASS_ENV: [&str; 8] = ["PATH", "PATHEXT", "SystemRoot", "SystemDrive", "windir", "TEMP", "TMP", "ComSpec"];
+
+/// 运行脚本：消息内容从标准输入传入，会话信息放在 WCF_ 开头的环境变量中，返回要发回会话的标准输出
+pub fn exec(config: &RuleScriptConfig, action: &ScriptAction, msg: &WxMsg) -> Result<Option<String>, String> {
+    if !config.enabled {
+        return Err("没有开启 rule_scripts".to_string());
+    }
+    let path = resolve_script(&config.dir, &action.script)?;
+    // 不经过 shell，也不继承本程序的环境变量，只保留运行程序必需的几个
+    let mut command = Command::new(&path);
+    command.env_clear();
+    for name in PASS_ENV {
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    let mut child = command
+        .args(&action.args)
+        .current_dir(path.parent().unwrap_or(Path::new(".")))
+        .env("WCF_SENDER", &msg.sender)
+        .env("WCF_ROOMID", &msg.roomid)
+        .env("WCF_MSG_ID", msg.id.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("启动脚本 {} 失败: {}", action.script, e))?;
+    // 先开始计时，标准输入在单独的线程中写入，脚本不读输入时也不会卡住
+    let deadline = Instant::now() + Duration::from_secs(action.timeout_secs);
+    if let Some(mut stdin) = child.stdin.take() {
+        let content = msg.content.clone();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(content.as_bytes());
+        });
+    }
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("脚本 {} 运行超过 {} 秒，已终止", action.script, action.timeout_secs));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let output = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(format!("脚本 {} 退出码 {}", action.script, status.code().unwrap_or(-1)));
+    }
+    if !action.reply {
+        return Ok(None);
+    }
+    let text: String = String::from_utf8_lossy(&output).trim().chars().take(MAX_REPLY_CHARS).collect();
+    Ok(Some(text).filter(|t| !t.is_empty()))
+}
+
+// 脚本动作执行前的检查，与 run 中的一致
+fn script_blocked(rule: &RuleConfig, config: &RuleScriptConfig) -> Option<String> {
+    if !config.enabled {
+        Some("没有开启 rule_scripts".to_string())
+    } else if rule.senders.is_empty() {
+        Some("脚本动作需要限定 senders".to_string())
+    } else {
+        None
+    }
+}
+
+fn preview(
+    rule: &RuleConfig,
+    action: &RuleAction,
+    vars: &mut HashMap<String, String>,
+    msg: &WxMsg,
+    scripts: &RuleScriptConfig,
+) -> ActionPreview {
+    let mut preview = ActionPreview {
+        kind: String::new(),
+        target: String::new(),
+        method: None,
+        body: None,
+        blocked: None,
+    };
+    match action {
+        RuleAction::Reply { text } => {
+            preview.kind = "reply".to_string();
+            preview.target = render(text, vars, raw);
+        }
+        RuleAction::Http(http) => {
+            preview.kind = "http".to_string();
+            preview.method = Some(http.method.to_uppercase());
+            match request(http, vars, msg) {
+                Ok((url, body)) => {
+                    preview.target = url;
+                    preview.body = Some(body);
+                }
+                Err(e) => preview.blocked = Some(e),
+            }
+        }
+        RuleAction::Set { var, value } => {
+            preview.kind = "set".to_string();
+            preview.target = render(var, vars, raw);
+            let value = render(value, vars, raw);
+            // 后面的动作看到的是设置后的值
+            vars.insert(format!("var.{}", preview.target), value.clone());
+            preview.body = Some(value);
+        }
+        RuleAction::Incr { var, by } => {
+            preview.kind = "incr".to_string();
+            preview.target = render(var, vars, raw);
+            let key = format!("var.{}", preview.target);
+            match rule_var_service::incremented(lookup(vars, &key).as_deref(), *by) {
+                Ok(value) => {
+                    vars.insert(key, value.to_string());
+                    preview.body = Some(value.to_string());
+                }
+                Err(e) => preview.blocked = Some(e),
+            }
+        }
+        RuleAction::Script(script) => {
+            preview.kind = "script".to_string();
+            preview.target = script.script.clone();
+            preview.blocked = script_blocked(rule, scripts);
+            if preview.blocked.is_none() {
+                match resolve_script(&scripts.dir, &script.script) {
+                    Ok(path) => preview.target = path.to_string_lossy().to_string(),
+                    Err(e) => preview.blocked = Some(e),
+                }
+            }
+        }
+    }
+    preview
+}
+
+// 按优先级排列，优先级相同的保持配置中的顺序
+fn ordered(mut rules: Vec<RuleConfig>) -> Vec<RuleConfig> {
+    rules.sort_by_key(|r| std::cmp::Reverse(r.priority));
+    rules
+}
+
+fn group_enabled(group: &str) -> bool {
+    GLOBAL.get().unwrap().rule_group_service.lock().unwrap().is_enabled(group)
+}
+
+/// 用一条消息测试全部规则，at_me 表示消息是否 @ 了自己；只渲染动作不执行，自己发的消息不会触发规则
+pub fn simulate(msg: &WxMsg, at_me: bool) -> Vec<RuleTrace> {
+    let (rules, scripts) = {
+        let config = GLOBAL.get().unwrap().wechat_config.read().unwrap();
+        (ordered(config.rules.clone()), config.rule_scripts.clone())
+    };
+    let mut stopped_by: Option<String> = None;
+    let mut traces = vec![];
+    for rule in &rules {
+        let result = match &stopped_by {
+            Some(name) => Err(format!("规则 {} 命中后停止匹配", name)),
+            None if !group_enabled(&rule.group) => Err(format!("分组 {} 已停用", rule.group)),
+            None => check(rule, msg, at_me),
+        };
+        let mut trace = RuleTrace {
+            rule: rule.name.clone(),
+            group: rule.group.clone(),
+            priority: rule.priority,
+            matched: false,
+            reason: None,
+            throttled: None,
+            vars: BTreeMap::new(),
+            actions: vec![],
+        };
+        match result {
+            Ok(mut vars) => {
+                trace.matched = true;
+                trace.throttled = rule_limit_service::throttled(rule, &msg.roomid, msg.ts as i64);
+                trace.actions = rule.actions.iter().map(|a| preview(rule, a, &mut vars, msg, &scripts)).collect();
+                trace.vars = vars.into_iter().collect();
+                if rule.stop {
+                    stopped_by = Some(rule.name.clone());
+                }
+            }
+            Err(reason) => trace.reason = Some(reason),
+        }
+        traces.push(trace);
+    }
+    traces
+}
+
+// 设置或累加变量，同时更新本次执行的模板变量
+fn update_var(action: &RuleAction, vars: &mut HashMap<String, String>) -> Result<(), String> {
+    let global = GLOBAL.get().unwrap();
+    let (name, value) = match action {
+        RuleAction::Set { var, value } => {
+            let name = render(var, vars, raw);
+            let value = render(value, vars, raw);
+            global.rule_var_service.lock().unwrap().set(&name, &value);
+            (name, value)
+        }
+        RuleAction::Incr { var, by } => {
+            let name = render(var, vars, raw);
+            let value = global.rule_var_service.lock().unwrap().incr(&name, *by)?;
+            (name, value.to_string())
+        }
+        _ => return Ok(()),
+    };
+    vars.insert(format!("var.{}", name), value);
+    Ok(())
+}
+
+fn run(wechat: &Arc<Mutex<WeChat>>, rule: &RuleConfig, mut vars: HashMap<String, String>, msg: &WxMsg) {
+    let scripts = GLOBAL.get().unwrap().wechat_config.read().unwrap().rule_scripts.clone();
+    for action in &rule.actions {
+        let reply = match action {
+            RuleAction::Reply { text } => Ok(Some(render(text, &vars, raw))),
+            RuleAction::Http(http) => call(http, &vars, msg),
+            RuleAction::Set { .. } | RuleAction::Incr { .. } => update_var(action, &mut vars).map(|_| None),
+            // 脚本在本机执行，只允许明确指定的发送人触发
+            RuleAction::Script(script) => match script_blocked(rule, &scripts) {
+                Some(e) => Err(e),
+                None => exec(&scripts, script, msg),
+            },
+        };
+        let text = match reply {
+            Ok(Some(text)) => text,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("规则 {} 执行失败: {}", rule.name, e);
+                continue;
+            }
+        };
+        let text = TextMsg {
+            msg: text,
+            receiver: msg.roomid.clone(),
+            aters: String::new(),
+        };
+        let wc = wechat.lock().unwrap().clone();
+        if let Err(e) = quiet_hours_service::send_text(&wc, text) {
+            warn!("规则 {} 回复失败: {}", rule.name, e);
+        }
+    }
+}
+
+// 消息 xml 的 atuserlist 中有自己的 wxid
+fn is_at_me(wechat: &Arc<Mutex<WeChat>>, msg: &WxMsg) -> bool {
+    let me = wechat.lock().unwrap().get_self_wxid().unwrap_or_default();
+    !me.is_empty() && payload::parse_at_user_list(&msg.xml).contains(&me)
+}
+
+/// 按优先级执行命中的规则，返回执行了的规则数；停用分组中的规则跳过，命中设置了 stop 的规则后不再匹配。
+/// 自己发的消息不处理，避免回复触发规则
+pub fn handle(wechat: &Arc<Mutex<WeChat>>, msg: &WxMsg) -> usize {
+    if msg.is_self {
+        return 0;
+    }
+    let rules = ordered(GLOBAL.get().unwrap().wechat_config.read().unwrap().rules.clone());
+    // 只有用到 is_at_me 条件时才查询自己的 wxid
+    let at_me = rules.iter().any(|r| rule_has(r, |c| matches!(c, Condition::IsAtMe(_)))) && is_at_me(wechat, msg);
+    let mut hits = 0;
+    for rule in rules.iter().filter(|r| group_enabled(&r.group)) {
+        if let Some(vars) = matched(rule, msg, at_me) {
+            let now = chrono::Local::now().timestamp();
+            // 被限流的规则仍然算命中，stop 照常生效，避免后面的规则代替它回复
+            match rule_limit_service::throttled(rule, &msg.roomid, now) {
+                Some(reason) => {
+                    info!("消息 {} 命中规则 {}，跳过: {}", msg.id, rule.name, reason);
+                    rule_limit_service::suppressed(rule, &msg.roomid);
+                }
+                None => {
+                    info!("消息 {} 命中规则 {}", msg.id, rule.name);
+                    rule_limit_service::fired(rule, &msg.roomid, now);
+                    run(wechat, rule, vars, msg);
+                    hits += 1;
+                }
+            }
+            if rule.stop {
+                break;
+            }
+        }
+    }
+    hits
+}
+
+/// 配置中出现过的分组
+pub fn groups() -> Vec<RuleGroup> {
+    let rules = GLOBAL.get().unwrap().wechat_config.read().unwrap().rules.clone();
+    let mut groups: Vec<RuleGroup> = vec![];
+    for rule in rules.iter().filter(|r| !r.group.is_empty()) {
+        match groups.iter_mut().find(|g| g.name == rule.group) {
+            Some(group) => group.rules.push(rule.name.clone()),
+            None => groups.push(RuleGroup {
+                name: rule.group.clone(),
+                enabled: group_enabled(&rule.group),
+                rules: vec![rule.name.clone()],
+            }),
+        }
+    }
+    groups
+}
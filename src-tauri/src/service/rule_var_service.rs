@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, thread, time::Duration};
+
+use chrono::Local;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{service::global_service::GLOBAL, utils::state_store, wechat_config::RuleVarConfig};
+
+const STATE_NAME: &str = "rule_vars";
+
+/// 规则修改变量后延迟保存的时间，期间的多次修改合并为一次写入
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// 规则变量
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct RuleVar {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RuleVarState {
+    vars: BTreeMap<String, String>,
+    // 变量最近一次修改的时间戳，用于清理长期不用的变量
+    #[serde(default)]
+    updated: BTreeMap<String, i64>,
+}
+
+/** 规则可以读写的变量和计数器，模板中用 {var.名称} 引用，重启后保留 */
+pub struct RuleVarService {
+    state: RuleVarState,
+    // 已经有线程等着保存
+    save_pending: bool,
+}
+
+impl RuleVarService {
+    pub fn new() -> Self {
+        let mut state: RuleVarState = state_store::load(STATE_NAME);
+        // 旧版本没有修改时间，从现在算起
+        let now = Local::now().timestamp();
+        for name in state.vars.keys() {
+            state.updated.entry(name.clone()).or_insert(now);
+        }
+        RuleVarService {
+            state,
+            save_pending: false,
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    // 规则触发很频繁，延迟一会儿再清理过期变量并保存
+    fn save_later(&mut self) {
+        if self.save_pending {
+            return;
+        }
+        self.save_pending = true;
+        thread::spawn(|| {
+            thread::sleep(SAVE_DELAY);
+            let global = GLOBAL.get().unwrap();
+            let config = global.wechat_config.read().unwrap().rule_vars.clone();
+            let mut service = global.rule_var_service.lock().unwrap();
+            service.save_pending = false;
+            service.prune(&config);
+            if let Err(e) = service.save() {
+                warn!("保存规则变量失败: {}", e);
+            }
+        });
+    }
+
+    // 删除超过 ttl_days 没有修改的变量，数量超过 max_vars 时再删除最久没有修改的
+    fn prune(&mut self, config: &RuleVarConfig) {
+        let state = &mut self.state;
+        if config.ttl_days > 0 {
+            let oldest = Local::now().timestamp() - (config.ttl_days * 24 * 3600) as i64;
+            state.updated.retain(|_, at| *at >= oldest);
+        }
+        if config.max_vars > 0 && state.updated.len() > config.max_vars {
+            let mut by_age: Vec<(i64, String)> = state.updated.iter().map(|(name, at)| (*at, name.clone())).collect();
+            by_age.sort();
+            for (_, name) in by_age.into_iter().take(state.updated.len() - config.max_vars) {
+                state.updated.remove(&name);
+            }
+        }
+        let updated = &state.updated;
+        state.vars.retain(|name, _| updated.contains_key(name));
+    }
+
+    // 在内存中修改，稍后保存
+    fn put(&mut self, name: &str, value: String) {
+        self.state.vars.insert(name.to_string(), value);
+        self.state.updated.insert(name.to_string(), Local::now().timestamp());
+        self.save_later();
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.state.vars.get(name).cloned()
+    }
+
+    pub fn list(&self) -> Vec<RuleVar> {
+        self.state
+            .vars
+            .iter()
+            .map(|(name, value)| RuleVar {
+                name: name.clone(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.put(name, value.to_string());
+    }
+
+    /// 计数器加上 by，不存在时从 0 开始，返回新的值
+    pub fn incr(&mut self, name: &str, by: i64) -> Result<i64, String> {
+        let value = incremented(self.state.vars.get(name).map(String::as_str), by)?;
+        self.put(name, value.to_string());
+        Ok(value)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<bool, String> {
+        let removed = self.state.vars.remove(name).is_some();
+        self.state.updated.remove(name);
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+}
+
+/// 计数器加上 by 之后的值，当前值不是整数时返回错误
+pub fn incremented(current: Option<&str>, by: i64) -> Result<i64, String> {
+    let current = match current.filter(|v| !v.is_empty()) {
+        Some(v) => v.parse::<i64>().map_err(|_| format!("变量的值 {} 不是整数", v))?,
+        None => 0,
+    };
+    Ok(current.saturating_add(by))
+}
@@ -0,0 +1,227 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::utils::{digest, state_store};
+
+const STATE_NAME: &str = "sdk";
+
+/// 随程序分发的 SDK 版本
+pub const BUNDLED_SDK_VERSION: &str = "39.5.1";
+
+/// SDK 版本与适配的微信版本，新版本在前
+pub const KNOWN_SDKS: &[(&str, &str)] = &[
+    ("39.5.1", "3.9.12.51"),
+    ("39.4.5", "3.9.12.17"),
+    ("39.3.5", "3.9.11.25"),
+    ("39.2.4", "3.9.10.27"),
+];
+
+/// 下载地址，{version} 替换为 SDK 版本
+const DOWNLOAD_URL: &str = "https://github.com/lich0821/WeChatFerry/releases/download/v{version}/v{version}.zip";
+
+/// 压缩包里需要的文件
+const SDK_FILES: &[&str] = &["sdk.dll", "spy.dll", "spy_debug.dll"];
+
+#[derive(Serialize, Deserialize, Default)]
+struct SdkSelection {
+    // 为空时使用随程序分发的版本
+    version: String,
+    // 下载校验通过后记录的各个 dll 的 SHA-256，加载前再核对一次
+    #[serde(default)]
+    hashes: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct SdkVersion {
+    /// SDK 版本
+    pub version: String,
+    /// 适配的微信版本
+    pub wechat: String,
+    /// 本地是否已有
+    pub installed: bool,
+    /// 是否为当前使用的版本
+    pub active: bool,
+}
+
+fn bundled_dir() -> PathBuf {
+    std::env::current_dir().unwrap().join("src\\wcferry\\lib")
+}
+
+fn versions_dir() -> PathBuf {
+    bundled_dir().join("versions")
+}
+
+/// 版本号只能是点分隔的数字，避免拼出 versions 目录以外的路径
+pub fn is_valid_version(version: &str) -> bool {
+    !version.is_empty() && version.split('.').all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn version_dir(version: &str) -> PathBuf {
+    if version == BUNDLED_SDK_VERSION {
+        bundled_dir()
+    } else {
+        versions_dir().join(version)
+    }
+}
+
+fn is_installed(version: &str) -> bool {
+    is_valid_version(version) && version_dir(version).join("sdk.dll").exists()
+}
+
+// 随程序分发的版本不校验，其它版本的文件需与下载时记录的一致
+fn verify_installed(version: &str, selection: &SdkSelection) -> Result<(), String> {
+    if version == BUNDLED_SDK_VERSION {
+        return Ok(());
+    }
+    let hashes = selection
+        .hashes
+        .get(version)
+        .ok_or_else(|| format!("SDK {} 没有校验记录，请删除后重新下载", version))?;
+    let dir = version_dir(version);
+    for (name, expected) in hashes {
+        digest::check(name, expected, &digest::sha256_file(&dir.join(name))?)?;
+    }
+    Ok(())
+}
+
+/// 当前选择的 SDK 版本，文件校验不通过时使用随程序分发的版本
+pub fn active_version() -> String {
+    let selection: SdkSelection = state_store::load(STATE_NAME);
+    if selection.version.is_empty() || !is_installed(&selection.version) {
+        return BUNDLED_SDK_VERSION.to_string();
+    }
+    match verify_installed(&selection.version, &selection) {
+        Ok(()) => selection.version,
+        Err(e) => {
+            warn!("不加载 SDK {}: {}", selection.version, e);
+            BUNDLED_SDK_VERSION.to_string()
+        }
+    }
+}
+
+/// 当前 SDK 所在目录
+pub fn active_dir() -> PathBuf {
+    version_dir(&active_version())
+}
+
+/// 当前 SDK 适配的微信版本，未登记的版本返回 None
+pub fn supported_wechat() -> Option<&'static str> {
+    let active = active_version();
+    KNOWN_SDKS.iter().find(|(sdk, _)| *sdk == active).map(|(_, wechat)| *wechat)
+}
+
+/// 与指定微信版本匹配的 SDK
+pub fn match_wechat(wechat: &str) -> Option<&'static str> {
+    KNOWN_SDKS.iter().find(|(_, w)| *w == wechat).map(|(sdk, _)| *sdk)
+}
+
+/// 已登记和本地已有的版本
+pub fn list() -> Vec<SdkVersion> {
+    let active = active_version();
+    let mut versions: Vec<SdkVersion> = KNOWN_SDKS
+        .iter()
+        .map(|(sdk, wechat)| SdkVersion {
+            version: sdk.to_string(),
+            wechat: wechat.to_string(),
+            installed: is_installed(sdk),
+            active: *sdk == active,
+        })
+        .collect();
+    // 手动放入 versions 目录的版本
+    if let Ok(entries) = fs::read_dir(versions_dir()) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if versions.iter().all(|v| v.version != name) && is_installed(&name) {
+                versions.push(SdkVersion {
+                    active: name == active,
+                    version: name,
+                    wechat: String::new(),
+                    installed: true,
+                });
+            }
+        }
+    }
+    versions
+}
+
+/// 下载指定版本到 versions 目录，已存在时直接返回。sha256 为压缩包的校验值，下载时必填
+pub fn download(version: &str, sha256: Option<&str>) -> Result<(), String> {
+    if !is_valid_version(version) {
+        return Err(format!("版本号不合法: {}", version));
+    }
+    if is_installed(version) {
+        return Ok(());
+    }
+    let sha256 = sha256
+        .filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| format!("本地没有 SDK {}，下载需要提供压缩包的 sha256", version))?;
+    let url = DOWNLOAD_URL.replace("{version}", version);
+    info!("下载 SDK {}: {}", version, url);
+    let rsp = ureq::get(&url)
+        .timeout(Duration::from_secs(300))
+        .call()
+        .map_err(|e| format!("下载失败: {}", e))?;
+    let mut bytes = vec![];
+    rsp.into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("下载失败: {}", e))?;
+    digest::check(&format!("SDK {} 压缩包", version), sha256, &digest::sha256_hex(&bytes))?;
+    let hashes = extract(&bytes, &version_dir(version))?;
+    let mut selection: SdkSelection = state_store::load(STATE_NAME);
+    selection.hashes.insert(version.to_string(), hashes);
+    state_store::save(STATE_NAME, &selection)
+}
+
+// 只取需要的 dll，忽略压缩包内的目录结构，返回各个文件的 SHA-256
+fn extract(bytes: &[u8], dir: &Path) -> Result<BTreeMap<String, String>, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("解压失败: {}", e))?;
+    let tmp = PathBuf::from(format!("{}.tmp", dir.display()));
+    let _ = fs::remove_dir_all(&tmp);
+    fs::create_dir_all(&tmp).map_err(|e| format!("创建目录失败: {}", e))?;
+    let mut hashes = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| format!("解压失败: {}", e))?;
+        let name = match Path::new(file.name()).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if !SDK_FILES.contains(&name.as_str()) {
+            continue;
+        }
+        let mut out = fs::File::create(tmp.join(&name)).map_err(|e| format!("写入 {} 失败: {}", name, e))?;
+        std::io::copy(&mut file, &mut out).map_err(|e| format!("写入 {} 失败: {}", name, e))?;
+        hashes.insert(name.clone(), digest::sha256_file(&tmp.join(&name))?);
+    }
+    if !tmp.join("sdk.dll").exists() {
+        let _ = fs::remove_dir_all(&tmp);
+        return Err("压缩包中没有 sdk.dll".to_string());
+    }
+    let _ = fs::remove_dir_all(dir);
+    fs::rename(&tmp, dir).map_err(|e| format!("保存 SDK 失败: {}", e))?;
+    Ok(hashes)
+}
+
+/// 切换 SDK 版本，重启服务后生效
+pub fn select(version: &str) -> Result<(), String> {
+    if !is_valid_version(version) {
+        return Err(format!("版本号不合法: {}", version));
+    }
+    if !is_installed(version) {
+        return Err(format!("SDK {} 不存在，请先下载", version));
+    }
+    let mut selection: SdkSelection = state_store::load(STATE_NAME);
+    verify_installed(version, &selection)?;
+    selection.version = if version == BUNDLED_SDK_VERSION { String::new() } else { version.to_string() };
+    state_store::save(STATE_NAME, &selection)?;
+    info!("已切换到 SDK {}，重启服务后生效", version);
+    Ok(())
+}
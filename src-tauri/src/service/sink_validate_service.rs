@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    handler::message::payload::build_payload,
+    service::global_service::GLOBAL,
+    wcferry::wcf::WxMsg,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 测试推送时带上的请求头，下游可据此忽略测试消息
+pub const VALIDATE_HEADER: &str = "X-Wcf-Validate";
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    /// http 回调
+    Http,
+    /// socketIO 服务
+    Socketio,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct SinkReport {
+    pub kind: SinkKind,
+    pub url: String,
+    /// 是否连上了目标
+    pub reachable: bool,
+    /// TLS 握手是否成功，非 https 时为空
+    pub tls: Option<bool>,
+    /// 认证是否通过，返回 401/403 时为 false，没收到响应时为空
+    pub auth: Option<bool>,
+    /// http 状态码
+    pub status: Option<u16>,
+    /// 耗时，单位毫秒
+    pub latency_ms: u64,
+    /// 整体是否可用
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+fn sample_message() -> WxMsg {
+    WxMsg {
+        is_self: false,
+        is_group: false,
+        id: 0,
+        r#type: 1,
+        ts: chrono::Local::now().timestamp() as u32,
+        roomid: "filehelper".to_string(),
+        content: "这是一条测试消息".to_string(),
+        sender: "filehelper".to_string(),
+        sign: String::new(),
+        thumb: String::new(),
+        extra: String::new(),
+        xml: String::new(),
+    }
+}
+
+fn is_tls_error(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["tls", "certificate", "handshake", "ssl"].iter().any(|k| error.contains(k))
+}
+
+// 发出请求并按结果填写报告
+fn probe(kind: SinkKind, url: &str, request: impl FnOnce() -> Result<ureq::Response, ureq::Error>) -> SinkReport {
+    let https = url.starts_with("https://");
+    let started = Instant::now();
+    let result = request();
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let mut report = SinkReport {
+        kind,
+        url: url.to_string(),
+        reachable: false,
+        tls: None,
+        auth: None,
+        status: None,
+        latency_ms,
+        ok: false,
+        error: None,
+    };
+    match result {
+        Ok(rsp) => {
+            report.reachable = true;
+            report.tls = https.then_some(true);
+            report.auth = Some(true);
+            report.status = Some(rsp.status());
+            report.ok = true;
+        }
+        Err(ureq::Error::Status(code, _)) => {
+            report.reachable = true;
+            report.tls = https.then_some(true);
+            report.auth = Some(code != 401 && code != 403);
+            report.status = Some(code);
+            report.error = Some(format!("返回状态码 {}", code));
+        }
+        Err(ureq::Error::Transport(e)) => {
+            let error = e.to_string();
+            if https && is_tls_error(&error) {
+                // 能握手失败说明地址是通的
+                report.reachable = true;
+                report.tls = Some(false);
+            }
+            report.error = Some(error);
+        }
+    }
+    report
+}
+
+/// 向 http 回调发送一条测试消息
+pub fn validate_http(url: &str, payload: &Value) -> SinkReport {
+    probe(SinkKind::Http, url, || {
+        ureq::post(url)
+            .timeout(TIMEOUT)
+            .set(VALIDATE_HEADER, "1")
+            .send_json(payload.clone())
+    })
+}
+
+/// 请求 socketIO 的握手地址，确认服务可用
+pub fn validate_socketio(url: &str) -> SinkReport {
+    let handshake = format!("{}/socket.io/?EIO=4&transport=polling", url.trim_end_matches('/'));
+    probe(SinkKind::Socketio, url, || {
+        ureq::get(&handshake).timeout(TIMEOUT).set(VALIDATE_HEADER, "1").call()
+    })
+}
+
+/// 检查指定地址，未指定时检查配置中的所有回调和 socketIO 地址
+pub async fn validate(kind: SinkKind, url: Option<String>) -> Vec<SinkReport> {
+    let (targets, version) = {
+        let global = GLOBAL.get().unwrap();
+        let config = global.wechat_config.read().unwrap();
+        let targets: Vec<(SinkKind, String)> = match url {
+            Some(url) => vec![(kind, url)],
+            None => config
+                .cburl
+                .iter()
+                .map(|u| (SinkKind::Http, u.clone()))
+                .chain(Some(config.wsurl.clone()).filter(|u| !u.is_empty()).map(|u| (SinkKind::Socketio, u)))
+                .collect(),
+        };
+        (targets, config.payload_versions.http)
+    };
+    let payload = build_payload(&sample_message(), version).await;
+    tokio::task::spawn_blocking(move || {
+        targets
+            .iter()
+            .map(|(kind, url)| match kind {
+                SinkKind::Http => validate_http(url, &payload),
+                SinkKind::Socketio => validate_socketio(url),
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
@@ -5,7 +5,14 @@ use log::{debug, info};
 use serde_json::{json, Value};
 use tokio::sync::Mutex;
 
-use crate::{service::global_service::GLOBAL, wcferry::wcf};
+use crate::{
+    service::{
+        admin_notify_service::{self, Incident},
+        global_service::GLOBAL,
+    },
+    utils::pipeline,
+    wcferry::wcf,
+};
 
 use futures_util::FutureExt;
 use rust_socketio::{
@@ -141,12 +148,37 @@ impl SocketIOService {
         Ok(())
     }
 
-    // 发送消息到服务器端
-    pub fn send_msg_to_server(&mut self, payload: Value) {
+    // 发送自定义事件到服务器端
+    pub fn send_event_to_server(&mut self, event: &'static str, payload: Value) {
+        let task_msg = self.socketio_client.clone();
+        tokio::spawn(async move {
+            if let Some(ref client) = *task_msg.lock().await {
+                if let Err(e) = client.emit(event, payload).await {
+                    log::error!("socketIO 发送 {} 事件失败: {}", event, e);
+                }
+            }
+        });
+    }
+
+    // 发送消息到服务器端，json 以文本发送，protobuf/msgpack 以二进制发送
+    pub fn send_msg_to_server<P: Into<Payload> + Send + 'static>(&mut self, payload: P) {
         let task_msg = self.socketio_client.clone();
         tokio::spawn(async move {
             if let Some(ref client) = *task_msg.lock().await {
-                client.emit("MSG", payload).await.expect("Server unreachable");
+                match client.emit("MSG", payload).await {
+                    Ok(()) => {
+                        pipeline::delivered("socketio", true);
+                        admin_notify_service::sink_recovered("socketio");
+                    }
+                    Err(e) => {
+                        pipeline::delivered("socketio", false);
+                        log::error!("socketIO 推送消息失败: {}", e);
+                        admin_notify_service::report(Incident::SinkFailure {
+                            sink: "socketio".to_string(),
+                            error: e.to_string(),
+                        });
+                    }
+                }
             }
         });
     }
@@ -0,0 +1,187 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::OnceLock,
+};
+
+use chrono::{Duration, Local, TimeZone};
+use jieba_rs::Jieba;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::service::global_service::GLOBAL;
+
+// 分词器加载词典较慢，全局只初始化一次
+static JIEBA: OnceLock<Jieba> = OnceLock::new();
+
+// 高频但没有意义的词
+const STOP_WORDS: &[&str] = &[
+    "一个", "一下", "不是", "什么", "他们", "你们", "可以", "哈哈", "哈哈哈", "因为", "大家", "就是", "已经", "我们", "所以",
+    "没有", "然后", "现在", "真的", "知道", "自己", "还是", "这个", "那个", "这样", "怎么", "感觉", "觉得", "其实", "应该",
+];
+
+/// 群成员的活跃时段
+#[derive(Serialize, ToSchema, Debug)]
+pub struct MemberActivity {
+    /// 合并了多个账号时为主账号
+    pub wxid: String,
+    /// 身份的显示名或群内昵称，未登录或已退群时为空
+    pub name: String,
+    /// 合并到该成员的其他账号
+    pub aliases: Vec<String>,
+    pub total: u64,
+    /// 0 到 23 点各小时的消息数，按本地时间
+    pub hours: Vec<u64>,
+}
+
+/// 群活跃度热力图，行为成员、列为小时
+#[derive(Serialize, ToSchema, Debug)]
+pub struct RoomHeatmap {
+    pub roomid: String,
+    /// 统计起始时间（含），unix 秒，为空表示不限
+    pub from: Option<i64>,
+    /// 统计结束时间（不含）
+    pub to: Option<i64>,
+    pub total: u64,
+    /// 全群各小时合计
+    pub hours: Vec<u64>,
+    /// 按消息数从多到少
+    pub members: Vec<MemberActivity>,
+}
+
+/// 按本地消息库统计群内每个成员每小时的发言数，同一个人的多个账号合并计算，names 为 wxid 到群昵称的映射
+pub fn heatmap(roomid: &str, from: Option<i64>, to: Option<i64>, names: &HashMap<String, String>) -> Result<RoomHeatmap, String> {
+    let global = GLOBAL.get().unwrap();
+    let rows = global.message_store_service.lock().unwrap().hourly_activity(roomid, from, to)?;
+    let identities = global.identity_service.lock().unwrap();
+    let mut hours = vec![0; 24];
+    // 主账号 -> (各小时消息数, 其他账号)
+    let mut members: HashMap<String, (Vec<u64>, BTreeSet<String>)> = HashMap::new();
+    for (sender, hour, count) in rows {
+        let hour = (hour as usize).min(23);
+        hours[hour] += count;
+        let canonical = identities.canonical(&sender);
+        let member = members.entry(canonical.clone()).or_insert_with(|| (vec![0; 24], BTreeSet::new()));
+        member.0[hour] += count;
+        if sender != canonical {
+            member.1.insert(sender);
+        }
+    }
+    let mut members: Vec<MemberActivity> = members
+        .into_iter()
+        .map(|(wxid, (hours, aliases))| {
+            let name = identities
+                .find(&wxid)
+                .map(|i| i.name.clone())
+                .filter(|n| !n.is_empty())
+                .or_else(|| std::iter::once(&wxid).chain(&aliases).find_map(|w| names.get(w).cloned()))
+                .unwrap_or_default();
+            MemberActivity {
+                name,
+                aliases: aliases.into_iter().collect(),
+                total: hours.iter().sum(),
+                wxid,
+                hours,
+            }
+        })
+        .collect();
+    members.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.wxid.cmp(&b.wxid)));
+    Ok(RoomHeatmap {
+        roomid: roomid.to_string(),
+        from,
+        to,
+        total: hours.iter().sum(),
+        hours,
+        members,
+    })
+}
+
+/// 词云统计的时间范围
+#[derive(Debug, Deserialize, Serialize, ToSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WordPeriod {
+    /// 今天 0 点以来
+    #[default]
+    Day,
+    /// 最近 7 天
+    Week,
+    /// 最近 30 天
+    Month,
+    /// 全部消息
+    All,
+}
+
+impl WordPeriod {
+    /// 起始时间，unix 秒
+    fn since(self) -> Option<i64> {
+        let now = Local::now();
+        match self {
+            WordPeriod::Day => {
+                let midnight = now.date_naive().and_hms_opt(0, 0, 0)?;
+                Local.from_local_datetime(&midnight).earliest().map(|t| t.timestamp())
+            }
+            WordPeriod::Week => Some((now - Duration::days(7)).timestamp()),
+            WordPeriod::Month => Some((now - Duration::days(30)).timestamp()),
+            WordPeriod::All => None,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Debug, PartialEq, Eq)]
+pub struct WordCount {
+    pub word: String,
+    pub count: u64,
+}
+
+/// 群聊高频词
+#[derive(Serialize, ToSchema, Debug)]
+pub struct WordCloud {
+    pub roomid: String,
+    pub period: WordPeriod,
+    /// 参与统计的文字消息数
+    pub messages: usize,
+    /// 按出现次数从多到少
+    pub words: Vec<WordCount>,
+}
+
+// 去掉链接、@ 提及和 [微笑] 这类表情代码后分词
+fn tokens(jieba: &Jieba, text: &str) -> Vec<String> {
+    static NOISE: OnceLock<Regex> = OnceLock::new();
+    let noise = NOISE.get_or_init(|| Regex::new(r"https?://\S+|@\S+|\[[^\[\]]{1,8}\]").unwrap());
+    let text = noise.replace_all(text, " ");
+    jieba
+        .cut(&text, true)
+        .into_iter()
+        .map(|w| w.trim().to_lowercase())
+        // 单字多为虚词，纯数字和标点也不计入
+        .filter(|w| w.chars().count() >= 2 && w.chars().any(|c| c.is_alphabetic()))
+        .filter(|w| !STOP_WORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// 统计本地消息库中群聊文字消息的高频词，最多返回 limit 个
+pub fn word_cloud(roomid: &str, period: WordPeriod, limit: usize) -> Result<WordCloud, String> {
+    let texts = GLOBAL
+        .get()
+        .unwrap()
+        .message_store_service
+        .lock()
+        .unwrap()
+        .texts(roomid, period.since())?;
+    let jieba = JIEBA.get_or_init(Jieba::new);
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    for text in &texts {
+        for word in tokens(jieba, text) {
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+    let mut words: Vec<WordCount> = counts.into_iter().map(|(word, count)| WordCount { word, count }).collect();
+    words.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+    words.truncate(limit);
+    Ok(WordCloud {
+        roomid: roomid.to_string(),
+        period,
+        messages: texts.len(),
+        words,
+    })
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::utils::state_store;
+
+const STATE_NAME: &str = "templates";
+
+/// 卡片消息的字段，都可以包含 {{变量}}
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RichTemplate {
+    /// 显示名字
+    pub name: String,
+    /// 公众号 id
+    pub account: String,
+    #[schema(example = "{{title}}")]
+    pub title: String,
+    pub digest: String,
+    pub url: String,
+    /// 缩略图
+    pub thumburl: String,
+}
+
+impl RichTemplate {
+    fn fields(&self) -> [&String; 6] {
+        [&self.name, &self.account, &self.title, &self.digest, &self.url, &self.thumburl]
+    }
+}
+
+/// 命名的消息模板
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct MessageTemplate {
+    pub name: String,
+    /// 文本消息的内容，设置了 rich_text 时不使用
+    #[schema(example = "欢迎 {{name}} 加入 {{team}}")]
+    pub text: String,
+    /// 设置后发送卡片消息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rich_text: Option<RichTemplate>,
+    /// 模板中用到的变量，按出现的顺序
+    pub vars: Vec<String>,
+}
+
+/// 填好变量后要发送的内容
+pub enum Filled {
+    Text(String),
+    RichText(RichTemplate),
+}
+
+impl MessageTemplate {
+    /// 填入变量，缺少变量时返回错误
+    pub fn fill(&self, vars: &HashMap<String, String>) -> Result<Filled, String> {
+        let missing: Vec<&str> = self.vars.iter().map(String::as_str).filter(|v| !vars.contains_key(*v)).collect();
+        if !missing.is_empty() {
+            return Err(format!("缺少变量 {}", missing.join("、")));
+        }
+        Ok(match &self.rich_text {
+            Some(rich) => Filled::RichText(RichTemplate {
+                name: fill(&rich.name, vars),
+                account: fill(&rich.account, vars),
+                title: fill(&rich.title, vars),
+                digest: fill(&rich.digest, vars),
+                url: fill(&rich.url, vars),
+                thumburl: fill(&rich.thumburl, vars),
+            }),
+            None => Filled::Text(fill(&self.text, vars)),
+        })
+    }
+}
+
+// 模板中每个 {{名称}} 的位置和名称，名称两边的空格忽略
+fn scan(template: &str) -> Vec<(Range<usize>, &str)> {
+    let mut found = vec![];
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{").map(|s| offset + s) {
+        let end = match template[start + 2..].find("}}") {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+        let name = template[start + 2..end].trim();
+        if name.is_empty() || name.contains('{') {
+            offset = start + 1;
+        } else {
+            found.push((start..end + 2, name));
+            offset = end + 2;
+        }
+    }
+    found
+}
+
+/// 模板中用到的变量，去掉重复的
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for (_, name) in scan(template) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// 把 {{名称}} 替换为变量的值，没有提供的变量原样保留
+pub fn fill(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut text = String::new();
+    let mut last = 0;
+    for (range, name) in scan(template) {
+        if let Some(value) = vars.get(name) {
+            text.push_str(&template[last..range.start]);
+            text.push_str(value);
+            last = range.end;
+        }
+    }
+    text.push_str(&template[last..]);
+    text
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct TemplateState {
+    templates: BTreeMap<String, MessageTemplate>,
+}
+
+/** 服务端保存的消息模板，发送时由 /send-template 填入变量 */
+pub struct TemplateService {
+    state: TemplateState,
+}
+
+impl TemplateService {
+    pub fn new() -> Self {
+        TemplateService {
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        state_store::save(STATE_NAME, &self.state)
+    }
+
+    pub fn list(&self) -> Vec<MessageTemplate> {
+        self.state.templates.values().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<MessageTemplate> {
+        self.state.templates.get(name).cloned()
+    }
+
+    /// 保存模板，同名的会被覆盖
+    pub fn set(&mut self, name: &str, text: &str, rich_text: Option<RichTemplate>) -> Result<MessageTemplate, String> {
+        let vars = match &rich_text {
+            Some(rich) => {
+                let mut vars: Vec<String> = vec![];
+                for var in rich.fields().into_iter().flat_map(|f| placeholders(f)) {
+                    if !vars.contains(&var) {
+                        vars.push(var);
+                    }
+                }
+                vars
+            }
+            None => placeholders(text),
+        };
+        let template = MessageTemplate {
+            name: name.to_string(),
+            text: text.to_string(),
+            rich_text,
+            vars,
+        };
+        self.state.templates.insert(name.to_string(), template.clone());
+        self.save()?;
+        Ok(template)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<bool, String> {
+        let removed = self.state.templates.remove(name).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+}
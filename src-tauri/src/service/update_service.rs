@@ -0,0 +1,86 @@
+use log::{error, info};
+use serde::Serialize;
+use tauri_plugin_updater::{Update, UpdaterExt};
+use utoipa::ToSchema;
+
+use crate::service::global_service::{APP_HANDLE, GLOBAL};
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct VersionInfo {
+    /// 当前版本
+    pub current: String,
+    /// 更新源地址
+    pub feed: String,
+    /// 更新源上的最新版本，未配置更新源或已是最新时为空
+    pub latest: Option<String>,
+    /// 更新说明
+    pub notes: Option<String>,
+}
+
+// 按配置的更新源检查新版本
+async fn check() -> Result<Option<Update>, String> {
+    let (feed, pubkey) = {
+        let global = GLOBAL.get().unwrap();
+        let config = global.wechat_config.read().unwrap();
+        (config.updater.feed.clone(), config.updater.pubkey.clone())
+    };
+    if feed.is_empty() {
+        return Err("未配置更新源".to_string());
+    }
+    // 没有公钥就无法校验更新包的签名，不能安装来源不明的程序
+    if pubkey.trim().is_empty() {
+        return Err("未配置更新包签名公钥 updater.pubkey，拒绝更新".to_string());
+    }
+    let handle = APP_HANDLE.get().ok_or("界面未启动，无法更新")?;
+    let url = tauri::Url::parse(&feed).map_err(|e| format!("更新源地址不合法: {}", e))?;
+    let updater = handle
+        .updater_builder()
+        .endpoints(vec![url])
+        .map_err(|e| e.to_string())?
+        .pubkey(pubkey)
+        .build()
+        .map_err(|e| e.to_string())?;
+    updater.check().await.map_err(|e| format!("检查更新失败: {}", e))
+}
+
+/// 当前版本和更新源上的最新版本
+pub async fn version_info() -> VersionInfo {
+    let feed = GLOBAL.get().unwrap().wechat_config.read().unwrap().updater.feed.clone();
+    let mut info = VersionInfo {
+        current: env!("CARGO_PKG_VERSION").to_string(),
+        feed: feed.clone(),
+        latest: None,
+        notes: None,
+    };
+    if feed.is_empty() {
+        return info;
+    }
+    match check().await {
+        Ok(Some(update)) => {
+            info.latest = Some(update.version.clone());
+            info.notes = update.body.clone();
+        }
+        Ok(None) => {}
+        Err(e) => error!("{}", e),
+    }
+    info
+}
+
+/// 有新版本时在后台下载安装并重启，返回新版本号
+pub async fn update() -> Result<String, String> {
+    let update = check().await?.ok_or("已是最新版本")?;
+    let version = update.version.clone();
+    info!("开始更新到 {}", version);
+    tokio::spawn(async move {
+        match update.download_and_install(|_, _| {}, || {}).await {
+            Ok(()) => {
+                info!("更新安装完成，重启中");
+                if let Some(handle) = APP_HANDLE.get() {
+                    handle.restart();
+                }
+            }
+            Err(e) => error!("更新失败: {}", e),
+        }
+    });
+    Ok(version)
+}
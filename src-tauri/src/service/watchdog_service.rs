@@ -0,0 +1,131 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use log::{error, info, warn};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{service::global_service::GLOBAL, utils::pipeline, wcferry::WeChat};
+
+/// 检查间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 静默时长内按基线至少应收到这么多条消息，才认为是接收出了问题
+const MIN_EXPECTED: f64 = 3.0;
+
+/// 关闭接收后等待旧的接收线程退出，需大于消息通道的接收超时
+const RESTART_DELAY: Duration = Duration::from_secs(6);
+
+/// 一次自动恢复的记录
+#[derive(Serialize, ToSchema, Clone)]
+pub struct SelfHeal {
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub at: DateTime<Local>,
+    /// 恢复前已连续多少分钟没有收到消息
+    pub idle_mins: u64,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/** 看门狗：登录状态下长时间收不到消息且平时有消息时，重建消息接收 */
+pub struct WatchdogService {
+    pub handle: Option<JoinHandle<()>>,
+    heals: u32,
+    last: Option<SelfHeal>,
+}
+
+// 关闭再重新开启消息接收
+fn restart_recv(wechat: &Arc<Mutex<WeChat>>) -> Result<(), String> {
+    wechat.lock().unwrap().disable_recv_msg().map_err(|e| e.to_string())?;
+    std::thread::sleep(RESTART_DELAY);
+    match wechat.lock().unwrap().enable_recv_msg() {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("启用消息接收失败".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl WatchdogService {
+    pub fn new() -> Self {
+        WatchdogService {
+            handle: None,
+            heals: 0,
+            last: None,
+        }
+    }
+
+    /// 自动恢复次数和最近一次记录
+    pub fn status(&self) -> (u32, Option<SelfHeal>) {
+        (self.heals, self.last.clone())
+    }
+
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        let started = Instant::now();
+        self.handle = Some(tokio::spawn(async move {
+            // 最近一次恢复的时间，同一段静默里只尝试一次
+            let mut healed_at: Option<Instant> = None;
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                let (silent_mins, window_mins, baseline) = {
+                    let global = GLOBAL.get().unwrap();
+                    let config = global.wechat_config.read().unwrap();
+                    let baseline = global.anomaly_service.lock().unwrap().total_baseline();
+                    (config.watchdog.silent_mins, config.anomaly.window_mins.max(1), baseline)
+                };
+                if silent_mins == 0 {
+                    continue;
+                }
+                let silent = Duration::from_secs(silent_mins * 60);
+                let idle = pipeline::idle().unwrap_or_else(|| started.elapsed());
+                if idle < silent || healed_at.map_or(false, |t| t.elapsed() < silent) {
+                    continue;
+                }
+                // 平时本来就没什么消息时不处理
+                let expected = baseline.unwrap_or(0.0) * silent_mins as f64 / window_mins as f64;
+                if expected < MIN_EXPECTED {
+                    continue;
+                }
+                let wc = wechat.clone();
+                let login = tokio::task::spawn_blocking(move || wc.lock().unwrap().is_login().unwrap_or(false))
+                    .await
+                    .unwrap_or(false);
+                if !login {
+                    continue;
+                }
+
+                let idle_mins = idle.as_secs() / 60;
+                warn!("已 {} 分钟没有收到消息，平时约 {:.0} 条，尝试重建消息接收", idle_mins, expected);
+                let wc = wechat.clone();
+                let result = tokio::task::spawn_blocking(move || restart_recv(&wc))
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()));
+                healed_at = Some(Instant::now());
+                match &result {
+                    Ok(()) => info!("消息接收已重建"),
+                    Err(e) => error!("重建消息接收失败: {}", e),
+                }
+                let global = GLOBAL.get().unwrap();
+                let mut service = global.watchdog_service.lock().unwrap();
+                service.heals += 1;
+                service.last = Some(SelfHeal {
+                    at: Local::now(),
+                    idle_mins,
+                    ok: result.is_ok(),
+                    error: result.err(),
+                });
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("消息接收看门狗已停止");
+        }
+    }
+}
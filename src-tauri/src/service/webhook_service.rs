@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::{
+    service::{global_service::GLOBAL, quiet_hours_service},
+    utils::{
+        json_schema::{self, SchemaError},
+        state_store,
+    },
+    wcferry::{wcf::TextMsg, WeChat},
+    wechat_config::WebhookHook,
+};
+
+const STATE_NAME: &str = "webhook_nonces";
+/// 记录的 nonce 上限，超出时丢掉最早的
+const MAX_NONCES: usize = 10000;
+
+// 时间窗口内收到过的 hook -> nonce -> 时间戳，过期的在收到新请求时清理。保存到文件，重启后也不能重放
+#[derive(Serialize, Deserialize, Default)]
+struct NonceState {
+    nonces: HashMap<String, HashMap<String, i64>>,
+}
+
+static NONCES: Mutex<Option<NonceState>> = Mutex::new(None);
+
+/// 拒绝 webhook 请求的原因
+#[derive(Debug, PartialEq)]
+pub enum Rejected {
+    /// 没有配置这个 hook
+    UnknownHook,
+    /// hook 没有密钥（未填写或解密失败），不接收投递
+    NoSecret,
+    /// 缺少签名或签名不对
+    BadSignature,
+    /// 缺少时间戳或 nonce，或时间戳与本机时间相差太多
+    Stale,
+    /// nonce 已经用过，是重复投递
+    Duplicate,
+    /// 请求体不是 JSON、不满足 schema 或缺少模板需要的字段
+    Invalid(Vec<SchemaError>),
+    /// 发送微信消息失败
+    Send(String),
+}
+
+/// 请求头中与投递相关的值
+pub struct Delivery<'a> {
+    pub timestamp: Option<&'a str>,
+    pub nonce: Option<&'a str>,
+    pub signature: Option<&'a str>,
+    pub body: &'a [u8],
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim().strip_prefix("sha256=").unwrap_or(text.trim());
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// 签名为 HMAC-SHA256("{timestamp}.{nonce}.{body}") 的十六进制，可以带 sha256= 前缀
+pub fn verify_signature(secret: &str, timestamp: &str, nonce: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match decode_hex(signature) {
+        Some(signature) => signature,
+        None => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}.", timestamp, nonce).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// 第一次见到的 nonce 记下来返回 true，窗口外的记录顺便清掉
+fn first_use(hook: &str, nonce: &str, timestamp: i64, now: i64, max_skew: i64) -> bool {
+    let mut state = NONCES.lock().unwrap();
+    let state = state.get_or_insert_with(|| state_store::load(STATE_NAME));
+    for nonces in state.nonces.values_mut() {
+        nonces.retain(|_, ts| (now - *ts).abs() <= max_skew);
+    }
+    state.nonces.retain(|_, nonces| !nonces.is_empty());
+    let nonces = state.nonces.entry(hook.to_string()).or_default();
+    if nonces.contains_key(nonce) {
+        return false;
+    }
+    if nonces.len() >= MAX_NONCES {
+        let oldest = nonces.iter().min_by_key(|(_, ts)| **ts).map(|(n, _)| n.clone());
+        if let Some(oldest) = oldest {
+            nonces.remove(&oldest);
+        }
+    }
+    nonces.insert(nonce.to_string(), timestamp);
+    if let Err(e) = state_store::save(STATE_NAME, state) {
+        warn!("保存 webhook nonce 失败: {}", e);
+    }
+    true
+}
+
+/// 按 {a.b} 取请求体中的字段填入模板，字段不存在或不是简单值时返回错误
+pub fn render(template: &str, body: &Value) -> Result<String, Vec<SchemaError>> {
+    let mut text = String::new();
+    let mut errors = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        text.push_str(&rest[..start]);
+        let path = &rest[start + 1..end];
+        let field = path.split('.').try_fold(body, |v, key| match v {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => v.get(key),
+        });
+        match field {
+            Some(Value::String(s)) => text.push_str(s),
+            Some(v @ (Value::Number(_) | Value::Bool(_))) => text.push_str(&v.to_string()),
+            _ => errors.push((format!("$.{}", path), "模板需要该字段".to_string())),
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    if errors.is_empty() {
+        Ok(text)
+    } else {
+        Err(errors)
+    }
+}
+
+// 依次检查时间戳、签名、nonce 和请求体，通过后返回要发送的消息
+fn accept(hook: &WebhookHook, delivery: &Delivery, max_skew: i64) -> Result<TextMsg, Rejected> {
+    // 没有密钥的 hook 谁都能调用，一律拒绝
+    if hook.secret.is_empty() {
+        warn!("webhook {} 没有密钥，拒绝投递", hook.name);
+        return Err(Rejected::NoSecret);
+    }
+    let now = Local::now().timestamp();
+    let raw_timestamp = delivery.timestamp.map(str::trim).ok_or(Rejected::Stale)?;
+    let timestamp = raw_timestamp.parse::<i64>().map_err(|_| Rejected::Stale)?;
+    if (now - timestamp).abs() > max_skew {
+        return Err(Rejected::Stale);
+    }
+    let nonce = delivery.nonce.map(str::trim).filter(|n| !n.is_empty()).ok_or(Rejected::Stale)?;
+    let signature = delivery.signature.ok_or(Rejected::BadSignature)?;
+    if !verify_signature(hook.secret.expose(), raw_timestamp, nonce, delivery.body, signature) {
+        return Err(Rejected::BadSignature);
+    }
+    // 签名通过后才记录 nonce，伪造的请求不会占用
+    if !first_use(&hook.name, nonce, timestamp, now, max_skew) {
+        return Err(Rejected::Duplicate);
+    }
+    let body: Value = serde_json::from_slice(delivery.body)
+        .map_err(|e| Rejected::Invalid(vec![("$".to_string(), format!("不是有效的 JSON: {}", e))]))?;
+    if let Some(schema) = &hook.schema {
+        let errors = json_schema::validate(schema, &body);
+        if !errors.is_empty() {
+            return Err(Rejected::Invalid(errors));
+        }
+    }
+    let msg = render(&hook.template, &body).map_err(Rejected::Invalid)?;
+    if msg.trim().is_empty() {
+        return Err(Rejected::Invalid(vec![("$".to_string(), "生成的消息为空".to_string())]));
+    }
+    Ok(TextMsg {
+        msg,
+        receiver: hook.receiver.clone(),
+        aters: String::new(),
+    })
+}
+
+/// 处理一次投递，校验通过后发送到 hook 配置的接收人，返回是否已立即发送
+pub fn handle(wechat: &Arc<Mutex<WeChat>>, name: &str, delivery: &Delivery) -> Result<bool, Rejected> {
+    let (hook, max_skew) = {
+        let config = GLOBAL.get().unwrap().wechat_config.read().unwrap();
+        let hook = config.webhook.hooks.iter().find(|h| h.name == name).cloned();
+        (hook.ok_or(Rejected::UnknownHook)?, config.webhook.max_skew_secs as i64)
+    };
+    let text = accept(&hook, delivery, max_skew)?;
+    info!("收到 webhook {}，发送到 {}", name, text.receiver);
+    // 发送前会按节奏停顿，不占用连接的锁
+    let wc = wechat.lock().unwrap().clone();
+    quiet_hours_service::send_text(&wc, text).map_err(Rejected::Send)
+}
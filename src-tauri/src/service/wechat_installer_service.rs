@@ -0,0 +1,134 @@
+use std::{fs, io::Read, path::PathBuf, process::Command, time::Duration};
+
+use log::{info, warn};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{service::sdk_service, utils::digest};
+
+/// 安装包下载地址，{version} 替换为微信版本
+const DOWNLOAD_URL: &str =
+    "https://github.com/tom-snow/wechat-windows-versions/releases/download/v{version}/WeChatSetup-{version}.exe";
+
+/// 安装包大小下限，小于它视为下载到了错误页面
+const MIN_INSTALLER_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct InstallReport {
+    /// 微信版本
+    pub version: String,
+    /// 安装包路径
+    pub installer: String,
+    /// 是否已启动安装程序
+    pub launched: bool,
+    /// 是否已禁止自动更新
+    pub update_blocked: bool,
+}
+
+fn installer_path(version: &str) -> PathBuf {
+    PathBuf::from(".")
+        .join("data")
+        .join("installers")
+        .join(format!("WeChatSetup-{}.exe", version))
+}
+
+/// 下载安装包并核对 sha256，已下载过时核对后直接返回路径
+pub fn download(version: &str, sha256: &str) -> Result<PathBuf, String> {
+    if !sdk_service::is_valid_version(version) {
+        return Err(format!("版本号不合法: {}", version));
+    }
+    if sha256.trim().is_empty() {
+        return Err("需要提供安装包的 sha256".to_string());
+    }
+    let path = installer_path(version);
+    if path.exists() {
+        verify(&path, sha256)?;
+        return Ok(path);
+    }
+    let url = DOWNLOAD_URL.replace("{version}", version);
+    info!("下载微信安装包 {}: {}", version, url);
+    let rsp = ureq::get(&url)
+        .timeout(Duration::from_secs(1800))
+        .call()
+        .map_err(|e| format!("下载失败: {}", e))?;
+    let mut bytes = vec![];
+    rsp.into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("下载失败: {}", e))?;
+    if bytes.len() < MIN_INSTALLER_SIZE {
+        return Err(format!("安装包大小异常: {} 字节", bytes.len()));
+    }
+    digest::check("安装包", sha256, &digest::sha256_hex(&bytes))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+    let tmp = path.with_extension("exe.tmp");
+    fs::write(&tmp, bytes).map_err(|e| format!("保存安装包失败: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("保存安装包失败: {}", e))?;
+    Ok(path)
+}
+
+fn verify(path: &PathBuf, sha256: &str) -> Result<(), String> {
+    digest::check("安装包", sha256, &digest::sha256_file(path)?)
+}
+
+/// 核对 sha256 后启动安装程序，不等待安装完成
+pub fn launch(path: &PathBuf, sha256: &str) -> Result<(), String> {
+    verify(path, sha256)?;
+    Command::new(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("启动安装程序失败: {}", e))
+}
+
+/// 禁止微信自动更新：清除待更新标记，并用只读文件占住更新包目录
+pub fn block_update() -> Result<(), String> {
+    clear_update_flag()?;
+    let appdata = std::env::var("APPDATA").map_err(|_| "找不到 APPDATA 目录".to_string())?;
+    let update = PathBuf::from(appdata).join("Tencent").join("WeChat").join("update");
+    if update.is_dir() {
+        fs::remove_dir_all(&update).map_err(|e| format!("删除更新目录失败: {}", e))?;
+    }
+    if !update.exists() {
+        if let Some(dir) = update.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+        fs::write(&update, b"").map_err(|e| format!("创建占位文件失败: {}", e))?;
+    }
+    let mut permissions = fs::metadata(&update).map_err(|e| e.to_string())?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&update, permissions).map_err(|e| format!("设置只读失败: {}", e))?;
+    info!("已禁止微信自动更新");
+    Ok(())
+}
+
+// 微信把待安装的更新记在注册表 NeedUpdateType 里
+#[cfg(windows)]
+fn clear_update_flag() -> Result<(), String> {
+    use winapi::um::{
+        winnt::REG_DWORD,
+        winreg::{RegSetKeyValueA, HKEY_CURRENT_USER},
+    };
+
+    let value: u32 = 0;
+    let status = unsafe {
+        RegSetKeyValueA(
+            HKEY_CURRENT_USER,
+            "Software\\Tencent\\WeChat\0".as_ptr() as *const i8,
+            "NeedUpdateType\0".as_ptr() as *const i8,
+            REG_DWORD,
+            &value as *const u32 as *const _,
+            std::mem::size_of::<u32>() as u32,
+        )
+    };
+    if status != 0 {
+        warn!("写入注册表失败: {}", status);
+        return Err(format!("写入注册表失败: {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn clear_update_flag() -> Result<(), String> {
+    Err("仅支持 Windows".to_string())
+}
@@ -1,5 +1,6 @@
 use std::sync::{Arc, Mutex};
 
+use crate::service::moderation_service;
 use crate::wcferry::{wcf::{self, RichText}, WeChat};
 
 pub struct WechatService {
@@ -30,11 +31,13 @@ impl WechatService {
     "".to_string()
   }
 
-  // 发送文本信息
+  // 发送文本信息，经过内容审核，审核时不占用微信的锁
   pub fn send_text(&mut self, content: wcf::TextMsg) {
     if let Some(wc) = &self.wechat {
-      let wcc = wc.lock().unwrap();
-      let _ = wcc.send_text(content);
+      let wcc = wc.lock().unwrap().clone();
+      if let Err(e) = moderation_service::send_text(&wcc, content) {
+        log::warn!("发送文本信息失败: {}", e);
+      }
     }
   }
   
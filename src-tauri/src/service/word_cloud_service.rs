@@ -0,0 +1,259 @@
+use std::{
+    fs,
+    io::Cursor,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use chrono::{Local, NaiveDate, NaiveTime};
+use image::{ImageOutputFormat, Rgba, RgbaImage};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{
+    service::{
+        global_service::GLOBAL,
+        media_service, pause_service,
+        stats_service::{self, WordCount, WordPeriod},
+    },
+    utils::state_store,
+    wcferry::{wcf::PathMsg, WeChat},
+};
+
+const STATE_NAME: &str = "word_cloud";
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+
+/// 最小、最大字号
+const MIN_SIZE: f32 = 16.0;
+const MAX_SIZE: f32 = 96.0;
+
+/// 未配置字体时依次尝试的系统字体
+const DEFAULT_FONTS: &[&str] = &[
+    r"C:\Windows\Fonts\msyh.ttc",
+    r"C:\Windows\Fonts\simhei.ttf",
+    r"C:\Windows\Fonts\simsun.ttc",
+];
+
+const COLORS: &[[u8; 3]] = &[
+    [0x1f, 0x77, 0xb4],
+    [0xff, 0x7f, 0x0e],
+    [0x2c, 0xa0, 0x2c],
+    [0xd6, 0x27, 0x28],
+    [0x94, 0x67, 0xbd],
+    [0x8c, 0x56, 0x4b],
+    [0xe3, 0x77, 0xc2],
+    [0x17, 0xbe, 0xcf],
+];
+
+#[derive(Serialize, Deserialize, Default)]
+struct WordCloudState {
+    // 最近一次定时发送的日期
+    last_sent: Option<NaiveDate>,
+}
+
+/// 读取字体文件，为空时依次尝试系统的中文字体
+pub fn load_font(path: &str) -> Result<FontVec, String> {
+    let candidates: Vec<&str> = if path.is_empty() { DEFAULT_FONTS.to_vec() } else { vec![path] };
+    for candidate in &candidates {
+        if let Ok(data) = fs::read(candidate) {
+            // ttc 字体集取第一个字体
+            return FontVec::try_from_vec_and_index(data, 0).map_err(|e| format!("解析字体 {} 失败: {}", candidate, e));
+        }
+    }
+    Err(format!("找不到字体文件: {}", candidates.join(", ")))
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+impl Rect {
+    fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w && self.y < other.y + other.h && other.y < self.y + self.h
+    }
+}
+
+// 从中心沿螺旋线寻找第一个不与已放置的词重叠的位置
+fn place(w: f32, h: f32, placed: &[Rect]) -> Option<Rect> {
+    let (cx, cy) = (WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+    let ratio = HEIGHT as f32 / WIDTH as f32;
+    for step in 0..4000 {
+        let angle = step as f32 * 0.1;
+        let radius = 2.0 * angle;
+        let rect = Rect {
+            x: cx + radius * angle.cos() - w / 2.0,
+            y: cy + radius * angle.sin() * ratio - h / 2.0,
+            w,
+            h,
+        };
+        if rect.x < 0.0 || rect.y < 0.0 || rect.x + w > WIDTH as f32 || rect.y + h > HEIGHT as f32 {
+            continue;
+        }
+        if placed.iter().all(|p| !p.overlaps(&rect)) {
+            return Some(rect);
+        }
+    }
+    None
+}
+
+fn draw_word(canvas: &mut RgbaImage, font: &FontVec, scale: PxScale, text: &str, x: f32, baseline: f32, color: [u8; 3]) {
+    let scaled = font.as_scaled(scale);
+    let mut caret = x;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+        let outline = match font.outline_glyph(glyph) {
+            Some(outline) => outline,
+            None => continue,
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px < 0 || py < 0 || px >= WIDTH as i32 || py >= HEIGHT as i32 {
+                return;
+            }
+            let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+            let a = coverage.clamp(0.0, 1.0);
+            for (channel, target) in pixel.0.iter_mut().zip(color) {
+                *channel = (*channel as f32 * (1.0 - a) + target as f32 * a) as u8;
+            }
+        });
+    }
+}
+
+/// 把高频词画成 PNG，次数越多字越大，放不下的词会被略过
+pub fn render(words: &[WordCount], font_path: &str) -> Result<Vec<u8>, String> {
+    if words.is_empty() {
+        return Err("没有可绘制的词".to_string());
+    }
+    let font = load_font(font_path)?;
+    let max = words.iter().map(|w| w.count).max().unwrap_or(1).max(1) as f32;
+    let mut canvas = RgbaImage::from_pixel(WIDTH, HEIGHT, Rgba([255, 255, 255, 255]));
+    let mut placed = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let size = MIN_SIZE + (MAX_SIZE - MIN_SIZE) * (word.count as f32 / max).sqrt();
+        let scale = PxScale::from(size);
+        let scaled = font.as_scaled(scale);
+        let width: f32 = word.word.chars().map(|c| scaled.h_advance(scaled.glyph_id(c))).sum();
+        let height = scaled.ascent() - scaled.descent();
+        // 留出一点间距
+        let rect = match place(width + 4.0, height + 2.0, &placed) {
+            Some(rect) => rect,
+            None => continue,
+        };
+        placed.push(rect);
+        draw_word(&mut canvas, &font, scale, &word.word, rect.x + 2.0, rect.y + 1.0 + scaled.ascent(), COLORS[i % COLORS.len()]);
+    }
+    let mut png = Vec::new();
+    canvas
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|e| format!("生成图片失败: {}", e))?;
+    Ok(png)
+}
+
+/// 统计群聊高频词并渲染为 PNG
+pub fn render_room(roomid: &str, period: WordPeriod, limit: usize) -> Result<Vec<u8>, String> {
+    let font = GLOBAL.get().unwrap().wechat_config.read().unwrap().word_cloud.font.clone();
+    let cloud = stats_service::word_cloud(roomid, period, limit)?;
+    render(&cloud.words, &font)
+}
+
+// 生成词云图片并发到群里
+fn send_to_room(wechat: &Arc<Mutex<WeChat>>, roomid: &str, period: WordPeriod, limit: usize) -> Result<(), String> {
+    pause_service::check()?;
+    let png = render_room(roomid, period, limit)?;
+    let path = media_service::media_dir().join(format!("wordcloud-{}-{}.png", roomid, Local::now().format("%Y%m%d")));
+    fs::write(&path, png).map_err(|e| format!("保存词云图片失败: {}", e))?;
+    let sent = wechat.lock().unwrap().send_image(PathMsg {
+        path: path.to_string_lossy().to_string(),
+        receiver: roomid.to_string(),
+        base64: String::new(),
+        filename: String::new(),
+    });
+    match sent {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("发送失败".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/** 每天固定时间把群词云图片发到配置的群里 */
+pub struct WordCloudService {
+    pub handle: Option<JoinHandle<()>>,
+    state: WordCloudState,
+}
+
+impl WordCloudService {
+    pub fn new() -> Self {
+        WordCloudService {
+            handle: None,
+            state: state_store::load(STATE_NAME),
+        }
+    }
+
+    // 到了发送时间且今天还没发过时返回 true，并记下今天已发送
+    fn due(&mut self, time: &str) -> bool {
+        let time = match NaiveTime::parse_from_str(time, "%H:%M") {
+            Ok(time) => time,
+            Err(_) => return false,
+        };
+        let now = Local::now();
+        if now.time() < time || self.state.last_sent == Some(now.date_naive()) {
+            return false;
+        }
+        self.state.last_sent = Some(now.date_naive());
+        if let Err(e) = state_store::save(STATE_NAME, &self.state) {
+            warn!("保存词云发送记录失败: {}", e);
+        }
+        true
+    }
+
+    pub fn start(&mut self, wechat: Arc<Mutex<WeChat>>) {
+        self.stop();
+        self.handle = Some(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                let global = GLOBAL.get().unwrap();
+                let config = global.wechat_config.read().unwrap().word_cloud.clone();
+                if config.rooms.is_empty() || config.time.is_empty() {
+                    continue;
+                }
+                if !global.word_cloud_service.lock().unwrap().due(&config.time) {
+                    continue;
+                }
+                let wc = wechat.clone();
+                let _ = tokio::task::spawn_blocking(move || {
+                    for roomid in &config.rooms {
+                        match send_to_room(&wc, roomid, config.period, config.limit) {
+                            Ok(()) => info!("已发送 {} 的词云", roomid),
+                            Err(e) => warn!("发送 {} 的词云失败: {}", roomid, e),
+                        }
+                    }
+                })
+                .await;
+            }
+        }));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            info!("定时词云已停止");
+        }
+    }
+}
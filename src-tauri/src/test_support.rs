@@ -0,0 +1,157 @@
+//! 路由测试工具：基于模拟后端挂载 `get_routes`，提供请求构造和响应断言。
+
+use std::sync::{Arc, Mutex, Once};
+
+use serde_json::{json, Value};
+use warp::{http::StatusCode, hyper::body::Bytes, test::RequestBuilder};
+
+use crate::{
+    endpoints,
+    handler::{msg_event_mgr::MsgEventBus, startup_event_mgr::StartUpEventBus},
+    service::global_service::{new_global_state, GLOBAL},
+    wcferry::{mock::Simulator, WeChat},
+    wechat_config::WechatConfig,
+};
+
+static INIT: Once = Once::new();
+
+// 测试配置：不推送、不限流、文件落在临时目录
+fn test_config() -> WechatConfig {
+    let file_dir = std::env::temp_dir().join("wcf-test");
+    serde_json::from_value(json!({
+        "cburl": [],
+        "http_server_port": 0,
+        "wsurl": "",
+        "file_dir": file_dir.to_string_lossy(),
+        "front_msg_show": false,
+        "msg_filter_regexp": null,
+        "risk_guard": { "enabled": false },
+        "mock": true,
+    }))
+    .unwrap()
+}
+
+/// 全局变量只能设置一次，所有测试共用同一份配置，消息总线上没有订阅者
+///
+/// 工作目录切到临时目录，各服务写到 ./data 下的状态不会留在源码目录里。
+pub fn ensure_global() {
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join("wcf-test").join(format!("run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+        let state = new_global_state(test_config(), MsgEventBus::new(), StartUpEventBus::new());
+        let _ = GLOBAL.set(Arc::new(state));
+    });
+}
+
+/// 临时修改一项全局配置，返回值离开作用域时改回原值，测试失败时也会改回
+///
+/// 只用于开关类的配置；rules 等列表请追加带唯一名字的项，避免覆盖并行测试的修改。
+pub fn override_config<T>(field: fn(&mut WechatConfig) -> &mut T, value: T) -> ConfigOverride<T> {
+    ensure_global();
+    let mut config = GLOBAL.get().unwrap().wechat_config.write().unwrap();
+    let previous = std::mem::replace(field(&mut config), value);
+    ConfigOverride {
+        field,
+        previous: Some(previous),
+    }
+}
+
+pub struct ConfigOverride<T> {
+    field: fn(&mut WechatConfig) -> &mut T,
+    previous: Option<T>,
+}
+
+impl<T> Drop for ConfigOverride<T> {
+    fn drop(&mut self) {
+        let previous = match self.previous.take() {
+            Some(previous) => previous,
+            None => return,
+        };
+        let mut config = match GLOBAL.get().unwrap().wechat_config.write() {
+            Ok(config) => config,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *(self.field)(&mut config) = previous;
+    }
+}
+
+/// 一个独立的模拟微信和对应的路由
+pub struct TestApp {
+    pub sim: Arc<Simulator>,
+    pub wechat: Arc<Mutex<WeChat>>,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        ensure_global();
+        let sim = Arc::new(Simulator::new());
+        let wechat = Arc::new(Mutex::new(WeChat::new_mock(sim.clone())));
+        TestApp { sim, wechat }
+    }
+
+    pub async fn send(&self, req: RequestBuilder) -> Reply {
+        let routes = endpoints::get_routes(self.wechat.clone());
+        let rsp = req.reply(&routes).await;
+        Reply {
+            status: rsp.status(),
+            content_type: rsp
+                .headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            body: rsp.into_body(),
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> Reply {
+        self.send(warp::test::request().method("GET").path(path)).await
+    }
+
+    pub async fn post(&self, path: &str, body: Value) -> Reply {
+        self.send(warp::test::request().method("POST").path(path).json(&body))
+            .await
+    }
+
+    pub async fn delete(&self, path: &str) -> Reply {
+        self.send(warp::test::request().method("DELETE").path(path)).await
+    }
+}
+
+/// 路由返回的原始响应
+pub struct Reply {
+    pub status: StatusCode,
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+impl Reply {
+    /// 解析为 ApiResponse 包装
+    pub fn envelope(&self) -> Value {
+        assert_eq!(self.status, StatusCode::OK, "响应: {:?}", self.body);
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("不是 json: {} {:?}", e, self.body))
+    }
+
+    /// 断言成功并返回 data
+    pub fn ok(&self) -> Value {
+        let envelope = self.envelope();
+        assert_eq!(envelope["status"], 0, "响应: {}", envelope);
+        assert!(envelope["error"].is_null(), "响应: {}", envelope);
+        envelope["data"].clone()
+    }
+
+    /// 断言失败并返回 error
+    pub fn err(&self) -> String {
+        let envelope = self.envelope();
+        assert_eq!(envelope["status"], 1, "响应: {}", envelope);
+        assert!(envelope["data"].is_null(), "响应: {}", envelope);
+        envelope["error"].as_str().unwrap_or_default().to_string()
+    }
+
+    /// 断言返回指定的 http 状态码
+    pub fn expect_status(&self, status: StatusCode) -> &Self {
+        assert_eq!(self.status, status, "响应: {:?}", self.body);
+        self
+    }
+}
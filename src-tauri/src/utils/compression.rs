@@ -0,0 +1,111 @@
+use std::io::Write;
+
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use warp::{
+    filters::BoxedFilter,
+    http::{header, HeaderValue},
+    hyper::{body, Body, Response},
+    Filter, Reply,
+};
+
+use crate::service::global_service::GLOBAL;
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+/// 为路由增加响应压缩，根据请求头 Accept-Encoding 选择 gzip 或 deflate
+pub fn compressed<R>(filter: BoxedFilter<(R,)>) -> BoxedFilter<(Box<dyn Reply>,)>
+where
+    R: Reply + 'static,
+{
+    filter
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .then(|reply: R, accept_encoding: Option<String>| async move {
+            let (enabled, min_size) = {
+                let global = GLOBAL.get().unwrap();
+                let config = global.wechat_config.read().unwrap();
+                (config.http_compression.enabled, config.http_compression.min_size)
+            };
+            let response = reply.into_response();
+            let encoding = match accept_encoding.as_deref().and_then(choose_encoding) {
+                Some(encoding) if enabled => encoding,
+                _ => return Box::new(response) as Box<dyn Reply>,
+            };
+            Box::new(compress_response(response, encoding, min_size).await) as Box<dyn Reply>
+        })
+        .boxed()
+}
+
+fn choose_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let accepts = |name: &str| {
+        accept_encoding.split(',').any(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap_or("").trim();
+            let rejected = parts.any(|p| p.trim().replace(' ', "") == "q=0");
+            coding.eq_ignore_ascii_case(name) && !rejected
+        })
+    };
+    if accepts("gzip") {
+        Some(Encoding::Gzip)
+    } else if accepts("deflate") {
+        Some(Encoding::Deflate)
+    } else {
+        None
+    }
+}
+
+async fn compress_response(response: Response<Body>, encoding: Encoding, min_size: usize) -> Response<Body> {
+    if response.headers().contains_key(header::CONTENT_ENCODING) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::error!("读取响应内容失败: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    if bytes.len() < min_size {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    let (compressed, name) = match encoding {
+        Encoding::Gzip => (gzip(&bytes), "gzip"),
+        Encoding::Deflate => (deflate(&bytes), "deflate"),
+    };
+    let compressed = match compressed {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("压缩响应失败: {}", e);
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, HeaderValue::from_static(name));
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Response::from_parts(parts, Body::from(compressed))
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// zlib 格式的 deflate，HTTP 与 socketIO 帧压缩共用
+pub fn deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
@@ -0,0 +1,63 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::wcferry::wcf::WxMsg;
+
+/// 微信内置的系统账号
+const SYSTEM_ACCOUNTS: [&str; 12] = [
+    "filehelper",
+    "fmessage",
+    "floatbottle",
+    "medianote",
+    "newsapp",
+    "weixin",
+    "qmessage",
+    "qqmail",
+    "tmessage",
+    "qqsafe",
+    "mphelper",
+    "notifymessage",
+];
+
+// 服务通知、订阅号折叠会话等非真人会话
+const SERVICE_ACCOUNTS: [&str; 5] = [
+    "notifymessage",
+    "mphelper",
+    "weixin",
+    "brandsessionholder",
+    "brandservicesessionholder",
+];
+
+/// 联系人类型
+#[derive(Debug, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactKind {
+    /// 好友
+    Friend,
+    /// 群聊
+    Room,
+    /// 公众号、服务号
+    Official,
+    /// 文件传输助手等系统账号
+    System,
+}
+
+/// 根据 wxid 判断联系人类型
+pub fn classify(wxid: &str) -> ContactKind {
+    if wxid.ends_with("@chatroom") {
+        ContactKind::Room
+    } else if wxid.starts_with("gh_") {
+        ContactKind::Official
+    } else if SYSTEM_ACCOUNTS.contains(&wxid) {
+        ContactKind::System
+    } else {
+        ContactKind::Friend
+    }
+}
+
+/// 是否是公众号或服务通知发来的消息
+pub fn is_official_msg(msg: &WxMsg) -> bool {
+    [&msg.sender, &msg.roomid]
+        .iter()
+        .any(|id| classify(id) == ContactKind::Official || SERVICE_ACCOUNTS.contains(&id.as_str()))
+}
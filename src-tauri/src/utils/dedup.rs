@@ -0,0 +1,37 @@
+//! 消息去重：同一条消息可能同时从接收回调和数据库轮询两条路径到达，只放行第一次。
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::Mutex,
+};
+
+/// 记住最近的消息数，超出后遗忘最早的
+const CAPACITY: usize = 5000;
+
+struct Seen {
+    order: VecDeque<u64>,
+    ids: BTreeSet<u64>,
+}
+
+static SEEN: Mutex<Seen> = Mutex::new(Seen {
+    order: VecDeque::new(),
+    ids: BTreeSet::new(),
+});
+
+/// 第一次见到该消息 id 时返回 true，id 为 0 的消息无法判断，总是放行
+pub fn first_seen(id: u64) -> bool {
+    if id == 0 {
+        return true;
+    }
+    let mut seen = SEEN.lock().unwrap();
+    if !seen.ids.insert(id) {
+        return false;
+    }
+    seen.order.push_back(id);
+    if seen.order.len() > CAPACITY {
+        if let Some(oldest) = seen.order.pop_front() {
+            seen.ids.remove(&oldest);
+        }
+    }
+    true
+}
@@ -0,0 +1,27 @@
+use std::{fs::File, io, path::Path};
+
+use sha2::{Digest, Sha256};
+
+/// 内容的 SHA-256，十六进制小写
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 文件的 SHA-256，按块读取，不把整个文件读进内存
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// 校验值一致时返回 Ok，忽略大小写和 sha256: 前缀
+pub fn check(what: &str, expected: &str, actual: &str) -> Result<(), String> {
+    let expected = expected.trim();
+    let expected = expected.strip_prefix("sha256:").unwrap_or(expected);
+    if expected.eq_ignore_ascii_case(actual) {
+        Ok(())
+    } else {
+        Err(format!("{} 的 SHA-256 校验失败，期望 {}，实际 {}", what, expected, actual))
+    }
+}
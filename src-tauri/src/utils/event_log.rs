@@ -0,0 +1,106 @@
+use std::{fs, path::PathBuf};
+
+use crate::service::global_service::GLOBAL;
+
+/// 事件日志中显示的来源名称
+#[cfg(windows)]
+const SOURCE: &str = "WcfRust";
+
+#[derive(Debug, Clone, Copy)]
+pub enum EventLevel {
+    Error,
+    Warning,
+    Info,
+}
+
+fn enabled() -> bool {
+    // 崩溃时锁可能已中毒，不能再 unwrap
+    GLOBAL
+        .get()
+        .and_then(|global| global.wechat_config.read().ok().map(|config| config.event_log))
+        .unwrap_or(false)
+}
+
+/// 把关键事件写入 Windows 事件日志，配置 event_log 未开启时不写
+pub fn write(level: EventLevel, message: &str) {
+    if !enabled() {
+        return;
+    }
+    if let Err(e) = report(level, message) {
+        log::warn!("写入 Windows 事件日志失败: {}", e);
+    }
+}
+
+#[cfg(windows)]
+fn report(level: EventLevel, message: &str) -> Result<(), String> {
+    use winapi::um::{
+        errhandlingapi::GetLastError,
+        winbase::{DeregisterEventSource, RegisterEventSourceW, ReportEventW},
+        winnt::{EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE},
+    };
+
+    let wide = |s: &str| s.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let source = wide(SOURCE);
+    let text = wide(message);
+    let kind = match level {
+        EventLevel::Error => EVENTLOG_ERROR_TYPE,
+        EventLevel::Warning => EVENTLOG_WARNING_TYPE,
+        EventLevel::Info => EVENTLOG_INFORMATION_TYPE,
+    };
+    unsafe {
+        let handle = RegisterEventSourceW(std::ptr::null(), source.as_ptr());
+        if handle.is_null() {
+            return Err(format!("错误码 {}", GetLastError()));
+        }
+        let mut strings = [text.as_ptr()];
+        let ok = ReportEventW(
+            handle,
+            kind,
+            0,
+            1,
+            std::ptr::null_mut(),
+            1,
+            0,
+            strings.as_mut_ptr(),
+            std::ptr::null_mut(),
+        );
+        DeregisterEventSource(handle);
+        if ok == 0 {
+            return Err(format!("错误码 {}", GetLastError()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn report(_level: EventLevel, _message: &str) -> Result<(), String> {
+    Err("仅支持 Windows".to_string())
+}
+
+fn marker_path() -> PathBuf {
+    PathBuf::from(".").join("data").join("running")
+}
+
+/// 启动时调用：上次没有正常退出时记录一条恢复事件，并在崩溃时写入事件日志
+pub fn init() {
+    let marker = marker_path();
+    if marker.exists() {
+        log::warn!("上次运行未正常退出，已重新启动");
+        write(EventLevel::Warning, "上次运行未正常退出，已重新启动");
+    }
+    if let Some(dir) = marker.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(&marker, std::process::id().to_string());
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        write(EventLevel::Error, &format!("程序异常: {}", info));
+        default_hook(info);
+    }));
+}
+
+/// 正常退出前调用
+pub fn clean_exit() {
+    let _ = fs::remove_file(marker_path());
+}
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{recommended_watcher, Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+/// 等待文件落盘，文件已存在时立即返回
+///
+/// 监听目标文件所在目录（目录尚未创建时监听最近的已存在上级目录），
+/// 超时后返回 `Ok(false)`
+pub async fn wait_for_file(path: &Path, timeout: Duration) -> Result<bool, String> {
+    if path.exists() {
+        return Ok(true);
+    }
+
+    let (watch_dir, mode) = match nearest_existing_dir(path) {
+        Some(dir) if Some(dir.as_path()) == path.parent() => (dir, RecursiveMode::NonRecursive),
+        Some(dir) => (dir, RecursiveMode::Recursive),
+        None => return Err(format!("无效的文件路径: {}", path.display())),
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+    let mut watcher = recommended_watcher(move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .map_err(|e| format!("创建文件监听失败: {}", e))?;
+    watcher
+        .watch(&watch_dir, mode)
+        .map_err(|e| format!("监听目录失败: {}", e))?;
+
+    // 注册监听前文件可能已经落盘
+    if path.exists() {
+        return Ok(true);
+    }
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(())) => {
+                if path.exists() {
+                    return Ok(true);
+                }
+            }
+            Ok(None) | Err(_) => return Ok(path.exists()),
+        }
+    }
+}
+
+fn nearest_existing_dir(path: &Path) -> Option<PathBuf> {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir.is_dir() {
+            return Some(dir.to_path_buf());
+        }
+        current = dir.parent();
+    }
+    None
+}
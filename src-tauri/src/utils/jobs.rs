@@ -0,0 +1,110 @@
+//! 后台任务登记：耗时的管理操作在后台线程里执行，立即返回任务编号，进度通过任务接口查询。
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// 最多保留的任务数，超出后丢弃最早结束的
+const CAPACITY: usize = 50;
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct Job {
+    pub id: u64,
+    /// 任务类型，如 backfill
+    pub kind: String,
+    pub state: JobState,
+    /// 已处理数量
+    pub done: u64,
+    /// 总数，未知时为空
+    pub total: Option<u64>,
+    /// 结束时的结果或错误信息
+    pub message: String,
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub started_at: DateTime<Local>,
+    #[schema(value_type = Option<String>)]
+    pub finished_at: Option<DateTime<Local>>,
+}
+
+struct Registry {
+    next_id: u64,
+    jobs: Vec<Job>,
+}
+
+static JOBS: Mutex<Registry> = Mutex::new(Registry { next_id: 1, jobs: Vec::new() });
+
+fn update(id: u64, f: impl FnOnce(&mut Job)) {
+    let mut registry = JOBS.lock().unwrap();
+    if let Some(job) = registry.jobs.iter_mut().find(|j| j.id == id) {
+        f(job);
+    }
+}
+
+/// 登记一个任务，同类任务还在运行时返回 Err 和它的编号
+pub fn start(kind: &str) -> Result<u64, u64> {
+    let mut registry = JOBS.lock().unwrap();
+    if let Some(running) = registry.jobs.iter().find(|j| j.kind == kind && j.state == JobState::Running) {
+        return Err(running.id);
+    }
+    let id = registry.next_id;
+    registry.next_id += 1;
+    registry.jobs.push(Job {
+        id,
+        kind: kind.to_string(),
+        state: JobState::Running,
+        done: 0,
+        total: None,
+        message: String::new(),
+        started_at: Local::now(),
+        finished_at: None,
+    });
+    if registry.jobs.len() > CAPACITY {
+        if let Some(index) = registry.jobs.iter().position(|j| j.state != JobState::Running) {
+            registry.jobs.remove(index);
+        }
+    }
+    Ok(id)
+}
+
+pub fn set_total(id: u64, total: u64) {
+    update(id, |job| job.total = Some(total));
+}
+
+/// 累加已处理数量
+pub fn advance(id: u64, count: u64) {
+    update(id, |job| job.done += count);
+}
+
+pub fn finish(id: u64, result: Result<String, String>) {
+    update(id, |job| {
+        job.finished_at = Some(Local::now());
+        match result {
+            Ok(message) => {
+                job.state = JobState::Done;
+                job.message = message;
+            }
+            Err(message) => {
+                job.state = JobState::Failed;
+                job.message = message;
+            }
+        }
+    });
+}
+
+pub fn get(id: u64) -> Option<Job> {
+    JOBS.lock().unwrap().jobs.iter().find(|j| j.id == id).cloned()
+}
+
+/// 所有任务，最新的在前
+pub fn list() -> Vec<Job> {
+    JOBS.lock().unwrap().jobs.iter().rev().cloned().collect()
+}
@@ -0,0 +1,140 @@
+//! JSON Schema 校验，支持常用的关键字：type、enum、const、required、properties、additionalProperties、
+//! items、minItems、maxItems、minLength、maxLength、pattern、minimum、maximum。
+
+use regex::Regex;
+use serde_json::Value;
+
+/// 校验失败的位置和原因，位置形如 $.data.items[0]
+pub type SchemaError = (String, String);
+
+/// 返回全部不满足的地方，为空表示通过
+pub fn validate(schema: &Value, value: &Value) -> Vec<SchemaError> {
+    let mut errors = vec![];
+    check(schema, value, "$", &mut errors);
+    errors
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    let actual = type_name(value);
+    actual == expected || (expected == "number" && actual == "integer")
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // true 或 false 形式的 schema
+        Value::Bool(false) => return errors.push((path.to_string(), "不允许出现".to_string())),
+        _ => return,
+    };
+    let mut fail = |message: String| errors.push((path.to_string(), message));
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|t| type_matches(t, value)) {
+            // 类型不对时不再检查其他关键字
+            return fail(format!("应为 {}，实际为 {}", types.join(" 或 "), type_name(value)));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            fail(format!("应为 {} 之一", Value::Array(allowed.clone())));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            fail(format!("应为 {}", expected));
+        }
+    }
+
+    match value {
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    fail(format!("长度不能少于 {}", min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    fail(format!("长度不能超过 {}", max));
+                }
+            }
+            if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                match Regex::new(pattern) {
+                    Ok(re) if !re.is_match(s) => fail(format!("不匹配 {}", pattern)),
+                    Ok(_) => {}
+                    Err(e) => fail(format!("schema 中的 pattern 无效: {}", e)),
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < min {
+                    fail(format!("不能小于 {}", min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > max {
+                    fail(format!("不能大于 {}", max));
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if len < min {
+                    fail(format!("至少需要 {} 项", min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if len > max {
+                    fail(format!("最多 {} 项", max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !fields.contains_key(name) {
+                        errors.push((format!("{}.{}", path, name), "不能为空".to_string()));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, field) in fields {
+                let field_path = format!("{}.{}", path, name);
+                match properties.and_then(|p| p.get(name)) {
+                    Some(field_schema) => check(field_schema, field, &field_path, errors),
+                    None => {
+                        if let Some(extra) = schema.get("additionalProperties") {
+                            check(extra, field, &field_path, errors);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
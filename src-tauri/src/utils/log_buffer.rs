@@ -0,0 +1,47 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{DateTime, Local};
+use log::Level;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// 内存中保留的日志条数
+const CAPACITY: usize = 5000;
+
+static BUFFER: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct LogEntry {
+    #[schema(value_type = String, example = "2024-01-01T12:00:00+08:00")]
+    pub time: DateTime<Local>,
+    pub level: String,
+    pub message: String,
+}
+
+pub fn push(time: DateTime<Local>, level: Level, message: String) {
+    let mut buffer = BUFFER.lock().unwrap();
+    if buffer.len() >= CAPACITY {
+        buffer.pop_front();
+    }
+    buffer.push_back(LogEntry {
+        time,
+        level: level.to_string(),
+        message,
+    });
+}
+
+/// 按级别（含更严重的级别）、起始时间、关键字过滤，返回最近的 limit 条
+pub fn query(level: Option<Level>, since: Option<DateTime<Local>>, grep: Option<&str>, limit: usize) -> Vec<LogEntry> {
+    let buffer = BUFFER.lock().unwrap();
+    let mut entries: Vec<LogEntry> = buffer
+        .iter()
+        .rev()
+        .filter(|e| level.map_or(true, |l| e.level.parse::<Level>().map_or(true, |el| el <= l)))
+        .filter(|e| since.map_or(true, |s| e.time >= s))
+        .filter(|e| grep.map_or(true, |g| e.message.contains(g)))
+        .take(limit)
+        .cloned()
+        .collect();
+    entries.reverse();
+    entries
+}
@@ -0,0 +1,282 @@
+//! 简单的 Markdown 解析，支持标题、段落、列表、引用、代码块、表格和分隔线，行内支持粗体、删除线、行内代码和链接。
+//! 聊天中的换行一般是有意的，段落内的换行会保留。
+
+/// 一段样式相同的文字
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub code: bool,
+    pub link: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// 级别 1 到 6
+    Heading(usize, Vec<Span>),
+    Paragraph(Vec<Span>),
+    Quote(Vec<Span>),
+    /// 缩进层级、项目符号或序号、内容
+    Item(usize, String, Vec<Span>),
+    /// 代码块的每一行
+    Code(Vec<String>),
+    /// 每行的每个单元格，第一行是表头
+    Table(Vec<Vec<Vec<Span>>>),
+    Rule,
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_end_matches('#').trim_end()))
+    } else {
+        None
+    }
+}
+
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|m| chars.iter().all(|c| c == m))
+}
+
+// 列表项的缩进层级、符号和内容
+fn item(line: &str) -> Option<(usize, String, &str)> {
+    let indent: usize = line
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    let rest = line.trim_start();
+    let depth = indent / 2;
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(text) = rest.strip_prefix(bullet) {
+            return Some((depth, "•".to_string(), text.trim()));
+        }
+    }
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && digits <= 9 {
+        let after = &rest[digits..];
+        if after.starts_with(". ") || after.starts_with(") ") {
+            return Some((depth, format!("{}.", &rest[..digits]), after[2..].trim()));
+        }
+    }
+    None
+}
+
+fn cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(str::trim).collect()
+}
+
+// 表头下面形如 | --- | :-: | 的分隔行
+fn is_table_separator(line: &str) -> bool {
+    line.contains('-')
+        && cells(line).iter().all(|c| {
+            let c = c.trim_start_matches(':').trim_end_matches(':');
+            !c.is_empty() && c.chars().all(|ch| ch == '-')
+        })
+}
+
+fn fence(line: &str) -> Option<&'static str> {
+    let line = line.trim_start();
+    ["```", "~~~"].into_iter().find(|f| line.starts_with(f))
+}
+
+pub fn parse(text: &str) -> Vec<Block> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut blocks = vec![];
+    let mut paragraph: Vec<&str> = vec![];
+    let mut quote: Vec<&str> = vec![];
+    let flush = |lines: &mut Vec<&str>, blocks: &mut Vec<Block>, quoted: bool| {
+        if !lines.is_empty() {
+            let spans = inline(&lines.join("\n"));
+            blocks.push(if quoted { Block::Quote(spans) } else { Block::Paragraph(spans) });
+            lines.clear();
+        }
+    };
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed.strip_prefix('>') {
+            flush(&mut paragraph, &mut blocks, false);
+            quote.push(rest.strip_prefix(' ').unwrap_or(rest));
+            i += 1;
+            continue;
+        }
+        flush(&mut quote, &mut blocks, true);
+        if let Some(marker) = fence(line) {
+            flush(&mut paragraph, &mut blocks, false);
+            let mut code = vec![];
+            i += 1;
+            while i < lines.len() && !lines[i].trim_start().starts_with(marker) {
+                code.push(lines[i].replace('\t', "    "));
+                i += 1;
+            }
+            blocks.push(Block::Code(code));
+        } else if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks, false);
+        } else if let Some((level, title)) = heading(trimmed) {
+            flush(&mut paragraph, &mut blocks, false);
+            blocks.push(Block::Heading(level, inline(title)));
+        } else if is_rule(trimmed) {
+            flush(&mut paragraph, &mut blocks, false);
+            blocks.push(Block::Rule);
+        } else if trimmed.contains('|') && lines.get(i + 1).map_or(false, |next| is_table_separator(next)) {
+            flush(&mut paragraph, &mut blocks, false);
+            let mut rows = vec![cells(line).into_iter().map(inline).collect::<Vec<_>>()];
+            i += 2;
+            while i < lines.len() && lines[i].contains('|') && !lines[i].trim().is_empty() {
+                rows.push(cells(lines[i]).into_iter().map(inline).collect());
+                i += 1;
+            }
+            blocks.push(Block::Table(rows));
+            continue;
+        } else if let Some((depth, marker, text)) = item(line) {
+            flush(&mut paragraph, &mut blocks, false);
+            blocks.push(Block::Item(depth, marker, inline(text)));
+        } else {
+            paragraph.push(trimmed);
+        }
+        i += 1;
+    }
+    flush(&mut quote, &mut blocks, true);
+    flush(&mut paragraph, &mut blocks, false);
+    blocks
+}
+
+fn push(spans: &mut Vec<Span>, text: &str, style: &Span) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.bold == style.bold && last.code == style.code && last.link == style.link => {
+            last.text.push_str(text)
+        }
+        _ => spans.push(Span {
+            text: text.to_string(),
+            ..style.clone()
+        }),
+    }
+}
+
+/// 解析行内样式，链接显示为文字加上括号中的地址
+pub fn inline(text: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut style = Span::default();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' && rest.len() > 1 {
+            let escaped = rest[1..].chars().next().unwrap();
+            push(&mut spans, &escaped.to_string(), &style);
+            rest = &rest[1 + escaped.len_utf8()..];
+        } else if c == '`' {
+            match rest[1..].find('`') {
+                Some(end) => {
+                    let code = Span { code: true, ..style.clone() };
+                    push(&mut spans, &rest[1..1 + end], &code);
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    push(&mut spans, "`", &style);
+                    rest = &rest[1..];
+                }
+            }
+        } else if rest.starts_with("**") || rest.starts_with("__") {
+            style.bold = !style.bold;
+            rest = &rest[2..];
+        } else if rest.starts_with("~~") {
+            rest = &rest[2..];
+        } else if let Some((label, url, len)) = link(rest) {
+            let linked = Span { link: true, ..style.clone() };
+            push(&mut spans, label, &linked);
+            if !url.is_empty() && url != label {
+                push(&mut spans, &format!(" ({})", url), &style);
+            }
+            rest = &rest[len..];
+        } else {
+            push(&mut spans, &rest[..c.len_utf8()], &style);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    spans
+}
+
+// [文字](地址) 或 ![说明](地址)，返回文字、地址和占用的长度
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let start = if text.starts_with("![") { 2 } else if text.starts_with('[') { 1 } else { return None };
+    let close = start + text[start..].find("](")?;
+    let end = close + 2 + text[close + 2..].find(')')?;
+    Some((&text[start..close], text[close + 2..end].trim(), end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Span {
+        Span {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn linked(text: &str) -> Span {
+        Span {
+            link: true,
+            ..plain(text)
+        }
+    }
+
+    #[test]
+    fn tables() {
+        let blocks = parse("| 名称 | 数量 |\n| --- | :-: |\n| 苹果 | 3 |\n\n合计");
+        let row = |a: &str, b: &str| vec![vec![plain(a)], vec![plain(b)]];
+        assert_eq!(
+            blocks,
+            [Block::Table(vec![row("名称", "数量"), row("苹果", "3")]), Block::Paragraph(vec![plain("合计")])]
+        );
+        // 没有分隔行的不是表格
+        assert_eq!(parse("a | b"), [Block::Paragraph(vec![plain("a | b")])]);
+    }
+
+    #[test]
+    fn fences() {
+        let blocks = parse("```rust\nfn main() {}\n\tx\n```\n后文");
+        assert_eq!(
+            blocks,
+            [Block::Code(vec!["fn main() {}".to_string(), "    x".to_string()]), Block::Paragraph(vec![plain("后文")])]
+        );
+        // 没有结束标记时到末尾为止，其中的 markdown 不解析
+        assert_eq!(parse("~~~\n# 标题"), [Block::Code(vec!["# 标题".to_string()])]);
+    }
+
+    #[test]
+    fn lists() {
+        let blocks = parse("- 一\n  * 二\n3. 三\n4) 四");
+        assert_eq!(
+            blocks,
+            [
+                Block::Item(0, "•".to_string(), vec![plain("一")]),
+                Block::Item(1, "•".to_string(), vec![plain("二")]),
+                Block::Item(0, "3.".to_string(), vec![plain("三")]),
+                Block::Item(0, "4.".to_string(), vec![plain("四")]),
+            ]
+        );
+        assert_eq!(parse("---"), [Block::Rule]);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            inline("见 [文档](https://a.b) 和 ![图](x.png)"),
+            [plain("见 "), linked("文档"), plain(" (https://a.b) 和 "), linked("图"), plain(" (x.png)")]
+        );
+        // 文字就是地址时不重复显示
+        assert_eq!(inline("[https://a.b](https://a.b)"), [linked("https://a.b")]);
+        assert_eq!(inline("[没有地址]"), [plain("[没有地址]")]);
+    }
+}
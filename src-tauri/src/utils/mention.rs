@@ -0,0 +1,141 @@
+/// 微信 @ 昵称后面跟的分隔符（四分之一字宽空格）
+const MENTION_END: char = '\u{2005}';
+/// 前缀匹配时较短一方至少要有的字数，一个字的前缀几乎能匹配任何人
+const MIN_PREFIX_CHARS: usize = 2;
+
+/// 从文本中取出所有 @ 后的昵称，优先以 U+2005 结尾，否则到空白为止
+pub fn extract(text: &str) -> Vec<String> {
+    let mut mentions = vec![];
+    let mut rest = text;
+    while let Some(pos) = rest.find('@') {
+        rest = &rest[pos + 1..];
+        let end = rest
+            .find(MENTION_END)
+            .filter(|&e| !rest[..e].contains(['\n', '@']))
+            .or_else(|| rest.find(char::is_whitespace))
+            .unwrap_or(rest.len());
+        let name = rest[..end].trim();
+        if !name.is_empty() {
+            mentions.push(name.to_string());
+        }
+        rest = &rest[end..];
+    }
+    mentions
+}
+
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+// 两者互为前缀，且较短的一方不少于 MIN_PREFIX_CHARS 个字
+fn is_prefix(lower: &str, name: &str) -> bool {
+    let name = name.to_lowercase();
+    let shorter = lower.chars().count().min(name.chars().count());
+    shorter >= MIN_PREFIX_CHARS && (lower.starts_with(&name) || name.starts_with(lower))
+}
+
+/// 在候选 (wxid, 昵称) 中查找昵称，返回 (下标, 是否精确匹配)
+///
+/// 依次尝试：完全相同、忽略大小写、互为前缀（取最长）、编辑距离不超过长度的三分之一。
+pub fn best_match(mention: &str, names: &[(String, String)]) -> Option<(usize, bool)> {
+    if let Some(i) = names.iter().position(|(_, n)| n == mention) {
+        return Some((i, true));
+    }
+    let lower = mention.to_lowercase();
+    if let Some(i) = names.iter().position(|(_, n)| n.to_lowercase() == lower) {
+        return Some((i, false));
+    }
+    // 昵称后直接跟了正文，或者只写了昵称开头
+    let prefix = names
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, n))| is_prefix(&lower, n))
+        .max_by_key(|(_, (_, n))| n.chars().count());
+    if let Some((i, _)) = prefix {
+        return Some((i, false));
+    }
+    let chars: Vec<char> = lower.chars().collect();
+    names
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, n))| !n.is_empty())
+        .map(|(i, (_, n))| {
+            let n: Vec<char> = n.to_lowercase().chars().collect();
+            (i, distance(&chars, &n), n.len().max(chars.len()))
+        })
+        .filter(|(_, d, len)| *d * 3 <= *len)
+        .min_by_key(|(_, d, _)| *d)
+        .map(|(i, _, _)| (i, false))
+}
+
+/// 踢人等不可撤销的操作用的严格匹配：完全相同、忽略大小写相同或前缀匹配，且只能有一个候选，不做模糊匹配
+pub fn unique_match(mention: &str, names: &[(String, String)]) -> Option<usize> {
+    let lower = mention.to_lowercase();
+    let only = |found: Vec<usize>| if found.len() == 1 { Some(found[0]) } else { None };
+    let exact: Vec<usize> = (0..names.len()).filter(|&i| names[i].1 == mention).collect();
+    if !exact.is_empty() {
+        return only(exact);
+    }
+    let same: Vec<usize> = (0..names.len()).filter(|&i| names[i].1.to_lowercase() == lower).collect();
+    if !same.is_empty() {
+        return only(same);
+    }
+    only((0..names.len()).filter(|&i| is_prefix(&lower, &names[i].1)).collect())
+}
+
+/// 群成员 (wxid, 群昵称) 中要 @ 的人
+#[derive(Debug, Default, PartialEq)]
+pub struct Targets {
+    /// 按给出的顺序去重后的成员下标
+    pub members: Vec<usize>,
+    /// 是否 @所有人
+    pub all: bool,
+}
+
+/// 按 wxid 或群昵称查找要 @ 的人，all 或 所有人 表示 @所有人
+///
+/// 昵称只接受完全相同或忽略大小写相同的，找不到时在错误中给出最接近的成员。
+pub fn targets(wanted: &[String], names: &[(String, String)]) -> Result<Targets, String> {
+    let mut targets = Targets::default();
+    for want in wanted {
+        let want = want.trim().trim_start_matches('@').trim();
+        if want.is_empty() {
+            continue;
+        }
+        if want.eq_ignore_ascii_case("all") || want == "所有人" {
+            targets.all = true;
+            continue;
+        }
+        let lower = want.to_lowercase();
+        let found = names
+            .iter()
+            .position(|(wxid, _)| wxid == want)
+            .or_else(|| names.iter().position(|(_, n)| n == want))
+            .or_else(|| names.iter().position(|(_, n)| n.to_lowercase() == lower));
+        match found {
+            Some(i) if !targets.members.contains(&i) => targets.members.push(i),
+            Some(_) => {}
+            None => {
+                return Err(match best_match(want, names) {
+                    Some((i, _)) => format!("群里找不到 {}，是否是 {}（{}）", want, names[i].1, names[i].0),
+                    None => format!("群里找不到 {}", want),
+                })
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// 消息开头的 @昵称，每个后面跟 U+2005
+pub fn prefix(names: &[&str]) -> String {
+    names.iter().map(|n| format!("@{}{}", n, MENTION_END)).collect()
+}
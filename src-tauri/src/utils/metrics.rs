@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+
+/// 延迟直方图的桶上限，单位秒
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    // 每个桶的累计计数，与 BUCKETS 一一对应
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (i, le) in BUCKETS.iter().enumerate() {
+            if seconds <= *le {
+                self.buckets[i] += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+struct RpcStats {
+    latency: Histogram,
+    errors: u64,
+}
+
+static RPC: Mutex<BTreeMap<&'static str, RpcStats>> = Mutex::new(BTreeMap::new());
+
+/// 记录一次 RPC 调用
+pub fn observe_rpc(method: &'static str, elapsed: Duration, ok: bool) {
+    let mut rpc = RPC.lock().unwrap();
+    let stats = rpc.entry(method).or_default();
+    stats.latency.observe(elapsed.as_secs_f64());
+    if !ok {
+        stats.errors += 1;
+    }
+}
+
+/// 以 OpenMetrics 文本格式输出所有指标
+pub fn render() -> String {
+    let rpc = RPC.lock().unwrap();
+    let mut out = String::new();
+    let _ = writeln!(out, "# TYPE wcf_rpc_duration_seconds histogram");
+    let _ = writeln!(out, "# UNIT wcf_rpc_duration_seconds seconds");
+    let _ = writeln!(out, "# HELP wcf_rpc_duration_seconds wcferry RPC 调用耗时");
+    for (method, stats) in rpc.iter() {
+        let h = &stats.latency;
+        for (le, count) in BUCKETS.iter().zip(h.buckets.iter()) {
+            let _ = writeln!(out, "wcf_rpc_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}", method, le, count);
+        }
+        let _ = writeln!(out, "wcf_rpc_duration_seconds_bucket{{method=\"{}\",le=\"+Inf\"}} {}", method, h.count);
+        let _ = writeln!(out, "wcf_rpc_duration_seconds_sum{{method=\"{}\"}} {}", method, h.sum);
+        let _ = writeln!(out, "wcf_rpc_duration_seconds_count{{method=\"{}\"}} {}", method, h.count);
+    }
+    let _ = writeln!(out, "# TYPE wcf_rpc_errors counter");
+    let _ = writeln!(out, "# HELP wcf_rpc_errors wcferry RPC 调用失败次数");
+    for (method, stats) in rpc.iter() {
+        let _ = writeln!(out, "wcf_rpc_errors_total{{method=\"{}\"}} {}", method, stats.errors);
+    }
+    out.push_str("# EOF\n");
+    out
+}
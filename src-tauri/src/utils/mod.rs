@@ -0,0 +1,23 @@
+pub mod file_watcher;
+pub mod video;
+pub mod compression;
+pub mod contact;
+pub mod state_store;
+pub mod rotating_file;
+pub mod log_buffer;
+pub mod mention;
+pub mod secret;
+pub mod metrics;
+pub mod pipeline;
+pub mod event_log;
+pub mod wxid;
+pub mod send_log;
+pub mod pacing;
+pub mod redact;
+pub mod jobs;
+pub mod dedup;
+pub mod system_event;
+pub mod json_schema;
+pub mod time_window;
+pub mod markdown;
+pub mod digest;
@@ -0,0 +1,65 @@
+//! 发送节奏：自动回复前按消息长度停顿，批量操作的间隔加入随机抖动，并按小时调整快慢。
+
+use std::time::Duration;
+
+use chrono::{Local, Timelike};
+use rand::Rng;
+
+use crate::{service::global_service::GLOBAL, wechat_config::PacingConfig};
+
+fn config() -> Option<PacingConfig> {
+    let global = GLOBAL.get()?;
+    let config = global.wechat_config.read().unwrap().pacing.clone();
+    config.enabled.then(|| sanitize(config))
+}
+
+// 配置里的比例可能是 NaN 或超出范围，换成能用的值
+fn sanitize(mut config: PacingConfig) -> PacingConfig {
+    config.jitter = if config.jitter.is_finite() { config.jitter.clamp(0.0, 1.0) } else { 0.0 };
+    for activity in config.hourly.iter_mut() {
+        *activity = if activity.is_finite() { activity.clamp(0.1, 10.0) } else { 1.0 };
+    }
+    config.max_delay_ms = config.max_delay_ms.max(config.min_delay_ms);
+    config
+}
+
+// 当前小时的活跃度，越低发得越慢；未配置时为 1
+fn activity(config: &PacingConfig, hour: usize) -> f64 {
+    config.hourly.get(hour).copied().unwrap_or(1.0).clamp(0.1, 10.0)
+}
+
+// 在 base 上下浮动 jitter 比例
+fn jittered(base: f64, jitter: f64) -> Duration {
+    let jitter = if jitter.is_finite() { jitter.clamp(0.0, 1.0) } else { 0.0 };
+    let factor = if jitter > 0.0 {
+        rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+    } else {
+        1.0
+    };
+    Duration::from_millis((base * factor).max(0.0) as u64)
+}
+
+/// 发送一条文本前的停顿，与消息长度成正比
+pub fn typing_delay(config: &PacingConfig, msg: &str, hour: usize) -> Duration {
+    let chars = msg.chars().count() as u64;
+    let base = config.min_delay_ms.saturating_add(chars.saturating_mul(config.ms_per_char)).min(config.max_delay_ms);
+    jittered(base as f64 / activity(config, hour), config.jitter)
+}
+
+/// 自动发送文本前调用，未开启时立即返回；会阻塞当前线程，调用时不要持有 WeChat 的锁
+pub fn before_send(msg: &str) {
+    if let Some(config) = config() {
+        std::thread::sleep(typing_delay(&config, msg, Local::now().hour() as usize));
+    }
+}
+
+/// 批量操作中两次调用的间隔，开启后加入随机抖动并按小时调整
+pub fn interval(base: Duration) -> Duration {
+    match config() {
+        Some(config) => {
+            let hour = Local::now().hour() as usize;
+            jittered(base.as_millis() as f64 / activity(&config, hour), config.jitter)
+        }
+        None => base,
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// 计算接收速率的时间窗口
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+static RECEIVED: AtomicU64 = AtomicU64::new(0);
+static PARSE_ERRORS: AtomicU64 = AtomicU64::new(0);
+static ENQUEUED: AtomicU64 = AtomicU64::new(0);
+static DEQUEUED: AtomicU64 = AtomicU64::new(0);
+static ENQUEUE_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+// 最近一个窗口内收到消息的时间
+static RECENT: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+static HANDLERS: Mutex<BTreeMap<&'static str, HandlerCounters>> = Mutex::new(BTreeMap::new());
+static SINKS: Mutex<BTreeMap<&'static str, SinkCounters>> = Mutex::new(BTreeMap::new());
+
+#[derive(Default, Clone, Copy)]
+struct HandlerCounters {
+    handled: u64,
+    dropped: u64,
+    depth: usize,
+}
+
+#[derive(Default, Clone, Copy)]
+struct SinkCounters {
+    delivered: u64,
+    failed: u64,
+}
+
+/// 接收阶段：从 wcferry 读取消息并放入接收队列
+#[derive(Serialize, ToSchema, Clone)]
+pub struct ReceiveStage {
+    /// 累计收到的消息数
+    pub received: u64,
+    /// 最近一分钟收到的消息数
+    pub per_minute: usize,
+    /// 解码失败次数
+    pub parse_errors: u64,
+    /// 接收队列中等待转发的消息数
+    pub queue_depth: u64,
+    /// 入队失败丢弃的消息数
+    pub dropped: u64,
+}
+
+/// 消息处理器的事件队列
+#[derive(Serialize, ToSchema, Clone)]
+pub struct HandlerStage {
+    pub name: String,
+    /// 已处理的事件数
+    pub handled: u64,
+    /// 最近一次取事件时队列里剩余的事件数
+    pub queue_depth: usize,
+    /// 处理不过来被丢弃的事件数
+    pub dropped: u64,
+}
+
+/// 推送目标
+#[derive(Serialize, ToSchema, Clone)]
+pub struct SinkStage {
+    pub name: String,
+    pub delivered: u64,
+    pub failed: u64,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct PipelineStats {
+    pub receive: ReceiveStage,
+    /// 事件总线中尚未被所有处理器取走的事件数
+    pub bus_depth: usize,
+    pub handlers: Vec<HandlerStage>,
+    pub sinks: Vec<SinkStage>,
+    /// 免打扰时段排队中的消息数
+    pub quiet_queue: usize,
+}
+
+/// 记录一条收到的消息
+pub fn received() {
+    RECEIVED.fetch_add(1, Ordering::Relaxed);
+    let now = Instant::now();
+    let mut recent = RECENT.lock().unwrap();
+    recent.push_back(now);
+    while recent.front().map_or(false, |t| now.duration_since(*t) > RATE_WINDOW) {
+        recent.pop_front();
+    }
+}
+
+/// 距离最近一次收到消息的时间，启动后还没收到过消息时为空
+pub fn idle() -> Option<Duration> {
+    RECENT.lock().unwrap().back().map(|t| t.elapsed())
+}
+
+pub fn parse_error() {
+    PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 记录消息放入接收队列的结果
+pub fn enqueued(ok: bool) {
+    if ok {
+        ENQUEUED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        ENQUEUE_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn dequeued() {
+    DEQUEUED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 记录处理器取到一个事件，depth 为取完后队列里剩余的数量
+pub fn handled(name: &'static str, depth: usize) {
+    let mut handlers = HANDLERS.lock().unwrap();
+    let counters = handlers.entry(name).or_default();
+    counters.handled += 1;
+    counters.depth = depth;
+}
+
+/// 记录处理器落后太多被丢弃的事件
+pub fn lagged(name: &'static str, count: u64) {
+    HANDLERS.lock().unwrap().entry(name).or_default().dropped += count;
+}
+
+/// 记录一次推送结果
+pub fn delivered(sink: &'static str, ok: bool) {
+    let mut sinks = SINKS.lock().unwrap();
+    let counters = sinks.entry(sink).or_default();
+    if ok {
+        counters.delivered += 1;
+    } else {
+        counters.failed += 1;
+    }
+}
+
+/// 当前各阶段的统计
+pub fn snapshot(bus_depth: usize, quiet_queue: usize) -> PipelineStats {
+    let now = Instant::now();
+    let per_minute = RECENT
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|t| now.duration_since(**t) <= RATE_WINDOW)
+        .count();
+    let enqueued = ENQUEUED.load(Ordering::Relaxed);
+    let dequeued = DEQUEUED.load(Ordering::Relaxed);
+    PipelineStats {
+        receive: ReceiveStage {
+            received: RECEIVED.load(Ordering::Relaxed),
+            per_minute,
+            parse_errors: PARSE_ERRORS.load(Ordering::Relaxed),
+            queue_depth: enqueued.saturating_sub(dequeued),
+            dropped: ENQUEUE_DROPPED.load(Ordering::Relaxed),
+        },
+        bus_depth,
+        handlers: HANDLERS
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, c)| HandlerStage {
+                name: name.to_string(),
+                handled: c.handled,
+                queue_depth: c.depth,
+                dropped: c.dropped,
+            })
+            .collect(),
+        sinks: SINKS
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, c)| SinkStage {
+                name: name.to_string(),
+                delivered: c.delivered,
+                failed: c.failed,
+            })
+            .collect(),
+        quiet_queue,
+    }
+}
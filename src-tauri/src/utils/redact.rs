@@ -0,0 +1,95 @@
+//! 敏感信息脱敏：消息落盘或推送给下游前，把手机号、身份证号等替换掉。
+
+use std::{borrow::Cow, sync::Mutex};
+
+use log::warn;
+use regex::{Captures, Regex};
+
+use crate::{service::global_service::GLOBAL, wcferry::wcf::WxMsg, wechat_config::RedactionConfig};
+
+// 前后不能紧挨数字，避免截取长数字串的一部分
+const PHONE: &str = r"(?P<pre>^|\D)1[3-9]\d{9}(?P<post>\D|$)";
+const ID_CARD: &str =
+    r"(?P<pre>^|\D)[1-9]\d{5}(?:18|19|20)\d{2}(?:0[1-9]|1[0-2])(?:0[1-9]|[12]\d|3[01])\d{3}[\dXx](?P<post>\D|$)";
+const BANK_CARD: &str = r"(?P<pre>^|\D)[1-9]\d{15,18}(?P<post>\D|$)";
+const EMAIL: &str = r"(?P<pre>)[\w.+-]+@[\w-]+(?:\.[\w-]+)+(?P<post>)";
+
+struct Compiled {
+    config: RedactionConfig,
+    rules: Vec<Regex>,
+}
+
+// 按配置编译好的规则，配置变化后重新编译
+static RULES: Mutex<Option<Compiled>> = Mutex::new(None);
+
+fn compile(config: &RedactionConfig) -> Vec<Regex> {
+    let mut sources: Vec<String> = Vec::new();
+    if config.phone {
+        sources.push(PHONE.to_string());
+    }
+    if config.id_card {
+        sources.push(ID_CARD.to_string());
+    }
+    if config.bank_card {
+        sources.push(BANK_CARD.to_string());
+    }
+    if config.email {
+        sources.push(EMAIL.to_string());
+    }
+    // 自定义规则整体替换
+    sources.extend(config.patterns.iter().map(|p| format!("(?P<pre>)(?:{})(?P<post>)", p)));
+    sources
+        .iter()
+        .filter_map(|source| match Regex::new(source) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                warn!("脱敏规则 {} 无效: {}", source, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 按给定规则脱敏一段文本
+pub fn redact_with<'a>(rules: &[Regex], mask: &str, text: &'a str) -> Cow<'a, str> {
+    let mut result = Cow::Borrowed(text);
+    for rule in rules {
+        // 相邻的两个号码共用分隔符，替换一次后再补一次
+        for _ in 0..2 {
+            let replaced = match rule.replace_all(&result, |c: &Captures| format!("{}{}{}", &c["pre"], mask, &c["post"])) {
+                Cow::Owned(replaced) => replaced,
+                Cow::Borrowed(_) => break,
+            };
+            result = Cow::Owned(replaced);
+        }
+    }
+    result
+}
+
+/// 按全局配置脱敏消息的文本和 xml，未开启时原样返回
+pub fn redact_msg(msg: &WxMsg) -> Cow<'_, WxMsg> {
+    let config = match GLOBAL.get() {
+        Some(global) => global.wechat_config.read().unwrap().redaction.clone(),
+        None => return Cow::Borrowed(msg),
+    };
+    if !config.enabled {
+        return Cow::Borrowed(msg);
+    }
+    let mut cache = RULES.lock().unwrap();
+    if cache.as_ref().map_or(true, |c| c.config != config) {
+        *cache = Some(Compiled {
+            rules: compile(&config),
+            config: config.clone(),
+        });
+    }
+    let rules = &cache.as_ref().unwrap().rules;
+    let content = redact_with(rules, &config.mask, &msg.content);
+    let xml = redact_with(rules, &config.mask, &msg.xml);
+    if matches!((&content, &xml), (Cow::Borrowed(_), Cow::Borrowed(_))) {
+        return Cow::Borrowed(msg);
+    }
+    let mut redacted = msg.clone();
+    redacted.content = content.into_owned();
+    redacted.xml = xml.into_owned();
+    Cow::Owned(redacted)
+}
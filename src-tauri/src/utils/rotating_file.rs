@@ -0,0 +1,107 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{Local, NaiveDate};
+
+struct Current {
+    day: NaiveDate,
+    index: u32,
+    file: File,
+    size: u64,
+}
+
+/// 按天和大小滚动的追加写文件，文件名形如 messages-20240101.0.ndjson
+pub struct RotatingFile {
+    dir: PathBuf,
+    prefix: String,
+    max_size: u64,
+    retention_days: u64,
+    current: Option<Current>,
+}
+
+impl RotatingFile {
+    pub fn new(dir: PathBuf, prefix: &str, max_size: u64, retention_days: u64) -> Self {
+        RotatingFile {
+            dir,
+            prefix: prefix.to_string(),
+            max_size,
+            retention_days,
+            current: None,
+        }
+    }
+
+    fn path(&self, day: NaiveDate, index: u32) -> PathBuf {
+        self.dir
+            .join(format!("{}-{}.{}.ndjson", self.prefix, day.format("%Y%m%d"), index))
+    }
+
+    // 打开当天第一个未写满的文件
+    fn open(&mut self, day: NaiveDate, mut index: u32) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        loop {
+            let path = self.path(day, index);
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if self.max_size > 0 && size >= self.max_size {
+                index += 1;
+                continue;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            self.current = Some(Current { day, index, file, size });
+            return Ok(());
+        }
+    }
+
+    // 删除超过保留天数的文件，0 为不清理
+    fn cleanup(&self) {
+        if self.retention_days == 0 {
+            return;
+        }
+        let keep = Duration::from_secs(self.retention_days * 24 * 3600);
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(&format!("{}-", self.prefix)) || !name.ends_with(".ndjson") {
+                continue;
+            }
+            let expired = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| SystemTime::now().duration_since(t).ok())
+                .map_or(false, |age| age > keep);
+            if expired {
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    log::warn!("删除过期文件 {} 失败: {}", name, e);
+                }
+            }
+        }
+    }
+
+    /// 追加一行，必要时切换到新文件
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let today = Local::now().date_naive();
+        let rotate = match &self.current {
+            None => Some(0),
+            Some(c) if c.day != today => Some(0),
+            Some(c) if self.max_size > 0 && c.size >= self.max_size => Some(c.index + 1),
+            Some(_) => None,
+        };
+        if let Some(index) = rotate {
+            self.current = None;
+            self.open(today, index)?;
+            self.cleanup();
+        }
+        let current = self.current.as_mut().unwrap();
+        current.file.write_all(line.as_bytes())?;
+        current.file.write_all(b"\n")?;
+        current.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// 加密后的值带上这个前缀，没有前缀的是旧配置里的明文
+const PREFIX: &str = "dpapi:";
+
+// 读取配置时遇到明文，需要重新保存一次完成加密
+static NEEDS_MIGRATION: AtomicBool = AtomicBool::new(false);
+
+/// 配置里的敏感字段（API Key、密码等），写入文件时用 DPAPI 加密，只有本机当前用户能解密
+///
+/// Debug 输出会打码，避免写进日志。
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 校验调用方给的令牌，空密钥不匹配任何值
+    pub fn matches(&self, given: Option<&str>) -> bool {
+        match given {
+            Some(given) => !self.0.is_empty() && constant_time_eq(self.0.as_bytes(), given.as_bytes()),
+            None => false,
+        }
+    }
+}
+
+/// 比较耗时与内容无关，避免逐字节猜出令牌；长度不同时直接返回
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(diff) == 0
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "\"\"")
+        } else {
+            write!(f, "\"******\"")
+        }
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_empty() {
+            return serializer.serialize_str("");
+        }
+        match protect(self.0.as_bytes()) {
+            Ok(data) => serializer.serialize_str(&format!("{}{}", PREFIX, base64::encode(data))),
+            Err(e) if cfg!(windows) => Err(serde::ser::Error::custom(format!("加密失败: {}", e))),
+            // 其它系统没有 DPAPI，只能保存明文
+            Err(_) => serializer.serialize_str(&self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let data = match text.strip_prefix(PREFIX) {
+            Some(data) => data,
+            None => {
+                if !text.is_empty() && cfg!(windows) {
+                    NEEDS_MIGRATION.store(true, Ordering::Relaxed);
+                }
+                return Ok(Secret(text));
+            }
+        };
+        let data = base64::decode(data).map_err(de::Error::custom)?;
+        match unprotect(&data) {
+            Ok(plain) => String::from_utf8(plain).map(Secret).map_err(de::Error::custom),
+            Err(e) => {
+                // 从其它机器导入的配置无法解密，清空后需要重新填写
+                log::warn!("敏感配置解密失败，已清空，请重新填写: {}", e);
+                Ok(Secret::default())
+            }
+        }
+    }
+}
+
+/// 本次读取的配置中是否有明文的敏感字段，调用后重置
+pub fn take_migration() -> bool {
+    NEEDS_MIGRATION.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(windows)]
+fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+    use winapi::um::{dpapi::CryptProtectData, wincrypt::DATA_BLOB};
+
+    let mut input = DATA_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_ptr() as *mut u8,
+    };
+    let mut output = DATA_BLOB {
+        cbData: 0,
+        pbData: std::ptr::null_mut(),
+    };
+    let ok = unsafe {
+        CryptProtectData(
+            &mut input,
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+            &mut output,
+        )
+    };
+    take_blob(ok, output)
+}
+
+#[cfg(windows)]
+fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+    use winapi::um::{dpapi::CryptUnprotectData, wincrypt::DATA_BLOB};
+
+    let mut input = DATA_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_ptr() as *mut u8,
+    };
+    let mut output = DATA_BLOB {
+        cbData: 0,
+        pbData: std::ptr::null_mut(),
+    };
+    let ok = unsafe {
+        CryptUnprotectData(
+            &mut input,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+            &mut output,
+        )
+    };
+    take_blob(ok, output)
+}
+
+// 复制 DPAPI 返回的数据并释放
+#[cfg(windows)]
+fn take_blob(ok: i32, output: winapi::um::wincrypt::DATA_BLOB) -> Result<Vec<u8>, String> {
+    use winapi::um::{errhandlingapi::GetLastError, winbase::LocalFree};
+
+    if ok == 0 {
+        return Err(format!("DPAPI 错误码 {}", unsafe { GetLastError() }));
+    }
+    let data = unsafe { std::slice::from_raw_parts(output.pbData, output.cbData as usize) }.to_vec();
+    unsafe { LocalFree(output.pbData as *mut _) };
+    Ok(data)
+}
+
+#[cfg(not(windows))]
+fn protect(_data: &[u8]) -> Result<Vec<u8>, String> {
+    Err("仅支持 Windows".to_string())
+}
+
+#[cfg(not(windows))]
+fn unprotect(_data: &[u8]) -> Result<Vec<u8>, String> {
+    Err("仅支持 Windows".to_string())
+}
@@ -0,0 +1,119 @@
+//! 发送记录：收集消息回调里自己发出的消息，用来确认接口调用后消息确实发了出去，并拿到消息 id。
+
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::wcferry::wcf::WxMsg;
+
+/// 最多保留的记录数
+const CAPACITY: usize = 200;
+
+struct Sent {
+    seq: u64,
+    roomid: String,
+    msg_type: u32,
+    content: String,
+    id: u64,
+}
+
+/// 在回调中认出刚发出的那条消息的依据，避免同一会话并发发送时拿到别的消息的 id
+pub enum Expect {
+    /// 文本消息，比较去掉开头 @ 后的内容
+    Text(String),
+    /// 按消息类型匹配，给出 name 时内容中还须包含它（如文件名）
+    Kind(u32, Option<String>),
+    /// 类型不固定（如转发），只比较会话
+    Any,
+}
+
+impl Expect {
+    /// 通过发送文件接口发出的消息：mp4 显示为视频，其它为文件消息，内容中带有文件名
+    pub fn file(path: &str) -> Expect {
+        if path.to_lowercase().ends_with(".mp4") {
+            Expect::Kind(43, None)
+        } else {
+            let name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_string());
+            Expect::Kind(49, name)
+        }
+    }
+
+    fn matches(&self, sent: &Sent) -> bool {
+        match self {
+            Expect::Text(text) => sent.msg_type == 1 && body(&sent.content) == body(text),
+            Expect::Kind(msg_type, name) => {
+                sent.msg_type == *msg_type && name.as_ref().map_or(true, |n| sent.content.contains(n.as_str()))
+            }
+            Expect::Any => true,
+        }
+    }
+}
+
+// 去掉开头的 @昵称 和 @所有人，回调里的昵称可能与发送时拼的不同
+fn body(text: &str) -> &str {
+    let mut rest = text.trim_start();
+    while rest.starts_with('@') {
+        match rest.find(|c: char| c == '\u{2005}' || c == ' ') {
+            Some(end) => rest = rest[end..].trim_start_matches(|c: char| c == '\u{2005}' || c == ' '),
+            None => break,
+        }
+    }
+    rest.trim_end()
+}
+
+struct SendLog {
+    next_seq: u64,
+    recent: VecDeque<Sent>,
+}
+
+static LOG: Mutex<SendLog> = Mutex::new(SendLog {
+    next_seq: 0,
+    recent: VecDeque::new(),
+});
+static ARRIVED: Condvar = Condvar::new();
+
+/// 消息回调收到自己发出的消息时调用
+pub fn record(msg: &WxMsg) {
+    if !msg.is_self {
+        return;
+    }
+    let mut log = LOG.lock().unwrap();
+    let seq = log.next_seq;
+    log.next_seq += 1;
+    log.recent.push_back(Sent {
+        seq,
+        roomid: msg.roomid.clone(),
+        msg_type: msg.r#type,
+        content: msg.content.clone(),
+        id: msg.id,
+    });
+    if log.recent.len() > CAPACITY {
+        log.recent.pop_front();
+    }
+    ARRIVED.notify_all();
+}
+
+/// 发送前调用，之后只匹配此刻以后的记录
+pub fn mark() -> u64 {
+    LOG.lock().unwrap().next_seq
+}
+
+/// 等待发给 roomid 且符合 expect 的消息出现在回调中；超时返回 None
+pub fn wait(roomid: &str, expect: &Expect, since: u64, timeout: Duration) -> Option<u64> {
+    let deadline = Instant::now() + timeout;
+    let mut log = LOG.lock().unwrap();
+    loop {
+        let found = log
+            .recent
+            .iter()
+            .find(|s| s.seq >= since && s.roomid == roomid && expect.matches(s));
+        if let Some(sent) = found {
+            return Some(sent.id);
+        }
+        let left = deadline.checked_duration_since(Instant::now())?;
+        log = ARRIVED.wait_timeout(log, left).unwrap().0;
+    }
+}
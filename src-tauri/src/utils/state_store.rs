@@ -0,0 +1,45 @@
+use std::{fs, path::PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// 运行状态以 json 文件保存在程序目录的 data 下，与 config.json5 分开
+fn state_path(name: &str) -> PathBuf {
+    PathBuf::from(".").join("data").join(format!("{}.json", name))
+}
+
+/// 读取状态，文件不存在或解析失败时返回默认值
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = state_path(name);
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+            log::warn!("状态文件 {} 解析失败，使用默认值: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// 读取状态，文件不存在时返回 None，读取或解析失败时返回错误而不是默认值
+pub fn try_load<T: DeserializeOwned>(name: &str) -> Result<Option<T>, String> {
+    let path = state_path(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("读取状态文件 {} 失败: {}", path.display(), e))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("状态文件 {} 解析失败: {}", path.display(), e))
+}
+
+/// 保存状态，先写临时文件再替换，避免写一半时崩溃导致文件损坏
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let path = state_path(name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+    let text = serde_json::to_string_pretty(value).map_err(|e| format!("序列化失败: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("写入状态文件失败: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("写入状态文件失败: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,241 @@
+//! 系统消息分类：把撤回、进群、踢人、改群名、红包、转账、拍一拍等消息解析成带类型的事件，
+//! 单独推送到 /events，不和普通聊天消息混在一起。
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::wcferry::wcf::WxMsg;
+
+/// 订阅者处理不过来时最多积压的事件数
+const CAPACITY: usize = 256;
+
+const MSG_TYPE_APP: u32 = 49;
+const MSG_TYPE_SYSTEM: u32 = 10000;
+const MSG_TYPE_SYSMSG: u32 = 10002;
+
+// appmsg 中转账和红包的 type
+const APP_TYPE_TRANSFER: &str = "2000";
+const APP_TYPE_RED_PACKET: &str = "2001";
+
+/// 系统消息的类型和解析出的内容，成员均为消息中显示的昵称
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SystemEvent {
+    /// 撤回消息，msg_id 为被撤回的消息
+    Revoke { msg_id: Option<u64>, text: String },
+    /// 成员进群，扫码进群时 inviter 为分享二维码的人
+    Join { inviter: Option<String>, members: Vec<String> },
+    /// 成员被移出群聊
+    Kick { operator: Option<String>, members: Vec<String> },
+    /// 成员退出群聊
+    Leave { members: Vec<String> },
+    /// 修改群名，自己修改时 operator 为空
+    Rename { operator: Option<String>, name: String },
+    /// 收到红包
+    RedPacket { sender: String },
+    /// 转账，received 表示是对方确认收款的通知
+    Transfer { sender: String, amount: Option<String>, received: bool },
+    /// 拍一拍，均为 wxid
+    Tickle { from: String, to: String },
+}
+
+/// 推送到 /events 的事件
+#[derive(Serialize, Clone, Debug)]
+pub struct TypedEvent {
+    pub msg_id: u64,
+    pub roomid: String,
+    pub ts: u32,
+    #[serde(flatten)]
+    pub event: SystemEvent,
+}
+
+impl SystemEvent {
+    /// 事件名，作为 SSE 的 event 字段
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SystemEvent::Revoke { .. } => "revoke",
+            SystemEvent::Join { .. } => "join",
+            SystemEvent::Kick { .. } => "kick",
+            SystemEvent::Leave { .. } => "leave",
+            SystemEvent::Rename { .. } => "rename",
+            SystemEvent::RedPacket { .. } => "red_packet",
+            SystemEvent::Transfer { .. } => "transfer",
+            SystemEvent::Tickle { .. } => "tickle",
+        }
+    }
+}
+
+fn channel() -> &'static broadcast::Sender<TypedEvent> {
+    static CHANNEL: OnceLock<broadcast::Sender<TypedEvent>> = OnceLock::new();
+    CHANNEL.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+pub fn subscribe() -> broadcast::Receiver<TypedEvent> {
+    channel().subscribe()
+}
+
+/// 是系统消息时推送给订阅者，返回解析出的事件
+pub fn publish(msg: &WxMsg) -> Option<TypedEvent> {
+    let event = TypedEvent {
+        msg_id: msg.id,
+        roomid: msg.roomid.clone(),
+        ts: msg.ts,
+        event: classify(msg)?,
+    };
+    // 没有订阅者时发送会失败，忽略即可
+    let _ = channel().send(event.clone());
+    Some(event)
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+// xml 中某个标签的文字，去掉 CDATA
+fn tag(xml: &str, name: &str) -> Option<String> {
+    let start = format!("<{}>", name);
+    let end = format!("</{}>", name);
+    let from = xml.find(&start)? + start.len();
+    let to = from + xml[from..].find(&end)?;
+    let text = xml[from..to].trim();
+    let text = text
+        .strip_prefix("<![CDATA[")
+        .and_then(|t| t.strip_suffix("]]>"))
+        .unwrap_or(text);
+    Some(text.trim().to_string())
+}
+
+// 引号中的昵称，多个人用顿号分隔
+fn quoted(text: &str) -> Vec<String> {
+    static QUOTED: OnceLock<Regex> = OnceLock::new();
+    regex(&QUOTED, r#"["“]([^"”]+)["”]"#)
+        .captures_iter(text)
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+fn names(quoted: &str) -> Vec<String> {
+    quoted.split('、').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect()
+}
+
+// 电脑版收到红包时的提示，如“收到红包，请在手机上查看”“收到一个微信红包”
+fn is_red_packet_notice(content: &str) -> bool {
+    static RED_PACKET: OnceLock<Regex> = OnceLock::new();
+    regex(&RED_PACKET, r"^\s*(?:\[|【)?收到(?:一个)?(?:微信)?红包").is_match(content)
+}
+
+// 10000 类型的提示，涉及自己时显示为“你”
+fn classify_notice(msg: &WxMsg) -> Option<SystemEvent> {
+    let content = msg.content.as_str();
+    let parts = quoted(content);
+    if content.contains("修改群名为") {
+        let name = content.rsplit("修改群名为").next()?;
+        let name = name.trim().trim_matches(|c| c == '“' || c == '”' || c == '"');
+        let operator = if content.starts_with('你') { None } else { parts.first().cloned() };
+        return Some(SystemEvent::Rename {
+            operator,
+            name: name.to_string(),
+        });
+    }
+    if content.contains("加入了群聊") || content.contains("加入群聊") {
+        return match parts.as_slice() {
+            // "张三"通过扫描"李四"分享的二维码加入群聊
+            [member, inviter] if content.contains("二维码") => Some(SystemEvent::Join {
+                inviter: Some(inviter.clone()),
+                members: names(member),
+            }),
+            // "张三"邀请你加入了群聊
+            [inviter] if content.contains("邀请你") => Some(SystemEvent::Join {
+                inviter: Some(inviter.clone()),
+                members: vec!["你".to_string()],
+            }),
+            // "张三"邀请"李四、王五"加入了群聊
+            [inviter, members] => Some(SystemEvent::Join {
+                inviter: Some(inviter.clone()),
+                members: names(members),
+            }),
+            // 你邀请"李四"加入了群聊
+            [members] => Some(SystemEvent::Join {
+                inviter: None,
+                members: names(members),
+            }),
+            _ => None,
+        };
+    }
+    if content.contains("移出了群聊") || content.contains("移出群聊") {
+        return match parts.as_slice() {
+            // "李四"被"张三"移出群聊
+            [members, operator] => Some(SystemEvent::Kick {
+                operator: Some(operator.clone()),
+                members: names(members),
+            }),
+            // 你被"张三"移出群聊
+            [operator] if content.starts_with("你被") => Some(SystemEvent::Kick {
+                operator: Some(operator.clone()),
+                members: vec!["你".to_string()],
+            }),
+            // 你将"李四"移出了群聊
+            [members] => Some(SystemEvent::Kick {
+                operator: None,
+                members: names(members),
+            }),
+            _ => None,
+        };
+    }
+    if content.contains("退出了群聊") {
+        return parts.first().map(|m| SystemEvent::Leave { members: names(m) });
+    }
+    // 收到红包，请在手机上查看；“你领取了张三的红包”“张三领取了你的红包”等领取通知不是新红包
+    if is_red_packet_notice(content) {
+        return Some(SystemEvent::RedPacket {
+            sender: msg.sender.clone(),
+        });
+    }
+    None
+}
+
+// 10002 类型的 sysmsg
+fn classify_sysmsg(content: &str) -> Option<SystemEvent> {
+    if content.contains("type=\"revokemsg\"") {
+        return Some(SystemEvent::Revoke {
+            msg_id: tag(content, "newmsgid").and_then(|id| id.parse().ok()),
+            text: tag(content, "replacemsg").unwrap_or_default(),
+        });
+    }
+    if content.contains("type=\"pat\"") {
+        return Some(SystemEvent::Tickle {
+            from: tag(content, "fromusername")?,
+            to: tag(content, "pattedusername")?,
+        });
+    }
+    None
+}
+
+/// 解析系统消息，普通聊天消息返回 None
+pub fn classify(msg: &WxMsg) -> Option<SystemEvent> {
+    match msg.r#type {
+        MSG_TYPE_SYSTEM => classify_notice(msg),
+        MSG_TYPE_SYSMSG => classify_sysmsg(&msg.content),
+        MSG_TYPE_APP => {
+            let app_type = tag(&msg.content, "type")?;
+            if app_type == APP_TYPE_RED_PACKET {
+                Some(SystemEvent::RedPacket {
+                    sender: msg.sender.clone(),
+                })
+            } else if app_type == APP_TYPE_TRANSFER {
+                // paysubtype 为 1 是发起转账，3 是对方已收款
+                Some(SystemEvent::Transfer {
+                    sender: msg.sender.clone(),
+                    amount: tag(&msg.content, "feedesc"),
+                    received: tag(&msg.content, "paysubtype").as_deref() == Some("3"),
+                })
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,28 @@
+//! 每天的 HH:MM - HH:MM 时段，按本机时区计算，结束早于开始时表示跨天，例如 23:00 - 07:00。
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+pub fn parse(start: &str, end: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let start = NaiveTime::parse_from_str(start, "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end, "%H:%M").ok()?;
+    Some((start, end))
+}
+
+/// now 落在时段内时返回时段开始的那一天，跨天时段的后半段属于前一天；开始和结束相同时视为空时段
+pub fn started_on(start: NaiveTime, end: NaiveTime, now: NaiveDateTime) -> Option<NaiveDate> {
+    let time = now.time();
+    let today = now.date();
+    if start < end {
+        (time >= start && time < end).then_some(today)
+    } else if start > end {
+        if time >= start {
+            Some(today)
+        } else if time < end {
+            today.pred_opt()
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::Path;
+
+use tokio::process::Command;
+
+/// 调用 ffmpeg 截取视频首帧
+pub async fn extract_first_frame(ffmpeg: &str, video: &Path, output: &Path) -> Result<(), String> {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-y", "-loglevel", "error", "-i"])
+        .arg(video)
+        .args(["-frames:v", "1"])
+        .arg(output);
+    // 不弹出控制台窗口
+    #[cfg(windows)]
+    cmd.creation_flags(0x08000000);
+
+    let status = cmd
+        .status()
+        .await
+        .map_err(|e| format!("调用 ffmpeg 失败: {}", e))?;
+    if !status.success() {
+        return Err(format!("ffmpeg 截取首帧失败: {}", status));
+    }
+    Ok(())
+}
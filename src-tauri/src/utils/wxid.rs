@@ -0,0 +1,91 @@
+//! wxid、群 id、公众号 id 的格式检查，调用 RPC 前给出明确的错误，而不是只返回 false。
+
+use super::contact::{classify, ContactKind};
+
+/// 企业微信联系人 id 的后缀
+const OPENIM_SUFFIX: &str = "@openim";
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+// 早期注册的微信号可能以数字开头、长度不足 6 位或带点，这里只排除明显不是 id 的输入（如中文昵称）
+fn is_legacy_char(c: char) -> bool {
+    is_id_char(c) || c == '.'
+}
+
+// 检查公共部分：不能为空、不能带空白、不能是昵称
+fn check_chars(id: &str) -> Result<(), String> {
+    if id.is_empty() {
+        return Err("不能为空".to_string());
+    }
+    if id.chars().any(char::is_whitespace) {
+        return Err(format!("{:?} 含有空白字符", id));
+    }
+    if id.contains(',') {
+        return Err(format!("{:?} 含有逗号，多个 id 请分开传", id));
+    }
+    Ok(())
+}
+
+/// 检查任意会话 id（好友、群、公众号、系统账号），返回其类型
+pub fn check_id(id: &str) -> Result<ContactKind, String> {
+    check_chars(id)?;
+    let kind = classify(id);
+    let valid = match kind {
+        ContactKind::Room => {
+            let prefix = id.trim_end_matches("@chatroom");
+            !prefix.is_empty() && prefix.chars().all(is_id_char)
+        }
+        ContactKind::Official => id.len() > 3 && id[3..].chars().all(is_id_char),
+        ContactKind::System => true,
+        ContactKind::Friend => {
+            if let Some(rest) = id.strip_prefix("wxid_") {
+                !rest.is_empty() && rest.chars().all(is_id_char)
+            } else if let Some(prefix) = id.strip_suffix(OPENIM_SUFFIX) {
+                !prefix.is_empty() && prefix.chars().all(is_id_char)
+            } else {
+                id.chars().all(is_legacy_char)
+            }
+        }
+    };
+    if valid {
+        Ok(kind)
+    } else {
+        Err(format!(
+            "{:?} 格式不对，应为 wxid、微信号、@openim 结尾的企业微信 id、@chatroom 结尾的群 id 或 gh_ 开头的公众号，不能是昵称或备注",
+            id
+        ))
+    }
+}
+
+/// 检查群 id
+pub fn check_room(id: &str) -> Result<(), String> {
+    match check_id(id)? {
+        ContactKind::Room => Ok(()),
+        _ => Err(format!("{} 不是群聊，群 id 应以 @chatroom 结尾", id)),
+    }
+}
+
+/// 检查个人 wxid，群和公众号不能作为群成员
+pub fn check_user(id: &str) -> Result<(), String> {
+    match check_id(id)? {
+        ContactKind::Friend => Ok(()),
+        ContactKind::Room => Err(format!("{} 是群 id，这里需要个人 wxid", id)),
+        ContactKind::Official => Err(format!("{} 是公众号，这里需要个人 wxid", id)),
+        ContactKind::System => Err(format!("{} 是系统账号，这里需要个人 wxid", id)),
+    }
+}
+
+/// 检查逗号分隔的多个个人 wxid
+pub fn check_users(wxids: &str) -> Result<(), String> {
+    let mut count = 0;
+    for wxid in wxids.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+        check_user(wxid)?;
+        count += 1;
+    }
+    if count == 0 {
+        return Err("不能为空".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+/// MSG 表中 BytesExtra 字段的结构
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BytesExtra {
+    #[prost(message, optional, tag = "1")]
+    pub property: ::core::option::Option<bytes_extra::Property>,
+    #[prost(message, repeated, tag = "3")]
+    pub extras: ::prost::alloc::vec::Vec<bytes_extra::Extra>,
+}
+/// Nested message and enum types in `BytesExtra`.
+pub mod bytes_extra {
+    #[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Property {
+        #[prost(int32, tag = "1")]
+        pub field_1: i32,
+        #[prost(int32, tag = "2")]
+        pub field_2: i32,
+    }
+    /// type 1: 群消息发送者 wxid；type 3: 缩略图路径；type 4: 原图/视频/文件路径
+    #[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Extra {
+        #[prost(int32, tag = "1")]
+        pub r#type: i32,
+        #[prost(string, tag = "2")]
+        pub value: ::prost::alloc::string::String,
+    }
+}
@@ -0,0 +1,553 @@
+//! 内存模拟的微信后端，不依赖 sdk.dll 和真实微信，用于联调和集成测试。
+//!
+//! 模拟器在 `send_cmd` 这一层接管命令，所以上层接口、消息处理器和推送逻辑都走真实代码。
+//! 给 [`ECHO_WXID`] 发文本会原样收到一条回复，可以用来测试消息回环。
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc::SyncSender, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::Path;
+
+use prost::Message;
+
+use crate::utils::pipeline;
+
+use super::{
+    bytesextra::{self, bytes_extra},
+    roomdata,
+    wcf::{self, request::Msg as ReqMsg, response::Msg as RspMsg, Functions, WxMsg},
+};
+
+/// 模拟登录账号
+pub const SELF_WXID: &str = "wxid_mock_self";
+/// 回声账号，发给它的文本消息会被原样发回
+pub const ECHO_WXID: &str = "wxid_mock_echo";
+/// 默认的模拟群
+pub const ROOM_ID: &str = "10000000001@chatroom";
+
+struct SimState {
+    contacts: Vec<wcf::RpcContact>,
+    // roomid -> 成员 wxid
+    rooms: HashMap<String, Vec<String>>,
+    outbox: Vec<wcf::Request>,
+    // MsgSvrID -> (消息类型, BytesExtra)
+    media: HashMap<u64, (u32, Vec<u8>)>,
+    // MSG 库中的历史消息，下标加 1 为 localId
+    history: Vec<WxMsg>,
+    // 设置了消息免打扰的会话
+    muted: HashSet<String>,
+    next_id: u64,
+    logged_in: bool,
+    sender: Option<SyncSender<WxMsg>>,
+}
+
+pub struct Simulator {
+    state: Mutex<SimState>,
+}
+
+fn contact(wxid: &str, name: &str) -> wcf::RpcContact {
+    wcf::RpcContact {
+        wxid: wxid.to_string(),
+        code: String::new(),
+        remark: String::new(),
+        name: name.to_string(),
+        country: String::new(),
+        province: String::new(),
+        city: String::new(),
+        gender: 0,
+    }
+}
+
+fn field(column: &str, content: impl Into<Vec<u8>>) -> wcf::DbField {
+    wcf::DbField {
+        r#type: 0,
+        column: column.to_string(),
+        content: content.into(),
+    }
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or_default()
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new()
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        let contacts = vec![
+            contact(ECHO_WXID, "回声"),
+            contact("wxid_mock_alice", "Alice"),
+            contact("wxid_mock_bob", "Bob"),
+            contact("filehelper", "文件传输助手"),
+        ];
+        let mut rooms = HashMap::new();
+        rooms.insert(
+            ROOM_ID.to_string(),
+            vec![
+                SELF_WXID.to_string(),
+                "wxid_mock_alice".to_string(),
+                "wxid_mock_bob".to_string(),
+            ],
+        );
+        Simulator {
+            state: Mutex::new(SimState {
+                contacts,
+                rooms,
+                outbox: vec![],
+                media: HashMap::new(),
+                history: vec![],
+                muted: HashSet::new(),
+                next_id: 1,
+                logged_in: true,
+                sender: None,
+            }),
+        }
+    }
+
+    /// 新增联系人
+    pub fn add_contact(&self, wxid: &str, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.contacts.retain(|c| c.wxid != wxid);
+        state.contacts.push(contact(wxid, name));
+    }
+
+    /// 新增群聊
+    pub fn add_room(&self, roomid: &str, members: &[&str]) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .rooms
+            .insert(roomid.to_string(), members.iter().map(|m| m.to_string()).collect());
+    }
+
+    /// 设置或取消会话的消息免打扰
+    pub fn set_muted(&self, wxid: &str, muted: bool) {
+        let mut state = self.state.lock().unwrap();
+        if muted {
+            state.muted.insert(wxid.to_string());
+        } else {
+            state.muted.remove(wxid);
+        }
+    }
+
+    /// 往 MSG 库里写入一条历史消息
+    pub fn add_history(&self, msg: WxMsg) {
+        self.state.lock().unwrap().history.push(msg);
+    }
+
+    /// 登记一条带附件的消息，extra、thumb 为本地绝对路径
+    pub fn add_media(&self, id: u64, r#type: u32, extra: &str, thumb: &str) {
+        let value = bytesextra::BytesExtra {
+            property: None,
+            extras: vec![
+                bytes_extra::Extra {
+                    r#type: 3,
+                    value: thumb.to_string(),
+                },
+                bytes_extra::Extra {
+                    r#type: 4,
+                    value: extra.to_string(),
+                },
+            ],
+        };
+        let mut state = self.state.lock().unwrap();
+        state.media.insert(id, (r#type, value.encode_to_vec()));
+    }
+
+    /// 切换登录状态，模拟掉线
+    pub fn set_login(&self, logged_in: bool) {
+        self.state.lock().unwrap().logged_in = logged_in;
+    }
+
+    /// 已发出的命令，按发送顺序排列
+    pub fn outbox(&self) -> Vec<wcf::Request> {
+        self.state.lock().unwrap().outbox.clone()
+    }
+
+    /// 模拟收到一条文本消息，roomid 为空时为私聊
+    pub fn receive_text(&self, sender: &str, roomid: &str, content: &str) {
+        let mut state = self.state.lock().unwrap();
+        let msg = Self::text_msg(&mut state, false, sender, roomid, content);
+        Self::deliver(&state, msg);
+    }
+
+    /// 模拟收到任意消息
+    pub fn inject(&self, msg: WxMsg) {
+        let state = self.state.lock().unwrap();
+        Self::deliver(&state, msg);
+    }
+
+    pub(super) fn attach(&self, tx: SyncSender<WxMsg>) {
+        self.state.lock().unwrap().sender = Some(tx);
+    }
+
+    pub(super) fn detach(&self) {
+        self.state.lock().unwrap().sender = None;
+    }
+
+    fn deliver(state: &SimState, msg: WxMsg) {
+        // 未开启消息接收时直接丢弃，与真实环境一致
+        if let Some(tx) = &state.sender {
+            pipeline::received();
+            pipeline::enqueued(tx.try_send(msg).is_ok());
+        }
+    }
+
+    fn echo_self(state: &mut SimState, receiver: &str, msg_type: u32) {
+        Self::echo_self_with(state, receiver, msg_type, "");
+    }
+
+    fn echo_self_with(state: &mut SimState, receiver: &str, msg_type: u32, content: &str) {
+        let mut msg = Self::text_msg(state, true, SELF_WXID, receiver, content);
+        msg.r#type = msg_type;
+        Self::deliver(state, msg);
+    }
+
+    fn text_msg(state: &mut SimState, is_self: bool, sender: &str, roomid: &str, content: &str) -> WxMsg {
+        let id = state.next_id;
+        state.next_id += 1;
+        WxMsg {
+            is_self,
+            is_group: roomid.ends_with("@chatroom"),
+            id,
+            r#type: 1,
+            ts: now(),
+            roomid: if roomid.is_empty() { sender.to_string() } else { roomid.to_string() },
+            content: content.to_string(),
+            sender: sender.to_string(),
+            sign: String::new(),
+            thumb: String::new(),
+            extra: String::new(),
+            xml: String::new(),
+        }
+    }
+
+    pub(super) fn handle(&self, req: wcf::Request) -> Result<Option<RspMsg>, Box<dyn std::error::Error>> {
+        let func = Functions::from_i32(req.func).unwrap_or(Functions::FuncReserved);
+        let mut state = self.state.lock().unwrap();
+        let rsp = match (func, req.msg.clone()) {
+            (Functions::FuncIsLogin, _) => RspMsg::Status(state.logged_in as i32),
+            (Functions::FuncGetSelfWxid, _) => RspMsg::Str(SELF_WXID.to_string()),
+            (Functions::FuncRefreshQrcode, _) => RspMsg::Str(String::new()),
+            (Functions::FuncGetUserInfo, _) => RspMsg::Ui(wcf::UserInfo {
+                wxid: SELF_WXID.to_string(),
+                name: "模拟账号".to_string(),
+                mobile: String::new(),
+                home: std::env::temp_dir().join("wcf-mock").to_string_lossy().to_string(),
+                alias: String::new(),
+            }),
+            (Functions::FuncGetContacts, _) => {
+                let mut contacts = state.contacts.clone();
+                let mut rooms: Vec<_> = state.rooms.keys().cloned().collect();
+                rooms.sort();
+                contacts.extend(rooms.iter().map(|r| contact(r, r)));
+                RspMsg::Contacts(wcf::RpcContacts { contacts })
+            }
+            (Functions::FuncGetDbNames, _) => RspMsg::Dbs(wcf::DbNames {
+                names: vec!["MicroMsg.db".to_string(), "MSG0.db".to_string()],
+            }),
+            (Functions::FuncGetDbTables, Some(ReqMsg::Str(db))) if db == "MicroMsg.db" => RspMsg::Tables(wcf::DbTables {
+                tables: vec![wcf::DbTable {
+                    name: "Contact".to_string(),
+                    sql: "CREATE TABLE Contact(UserName TEXT PRIMARY KEY, NickName TEXT, VerifyFlag INTEGER, Type INTEGER)"
+                        .to_string(),
+                }],
+            }),
+            (Functions::FuncGetDbTables, _) => RspMsg::Tables(wcf::DbTables { tables: vec![] }),
+            (Functions::FuncGetMsgTypes, _) => RspMsg::Types(wcf::MsgTypes {
+                types: HashMap::from([(1, "文字".to_string()), (3, "图片".to_string()), (49, "文件".to_string())]),
+            }),
+            (Functions::FuncExecDbQuery, Some(ReqMsg::Query(query))) => RspMsg::Rows(Self::query(&state, &query.sql)),
+            (Functions::FuncEnableRecvTxt, _) | (Functions::FuncDisableRecvTxt, _) => RspMsg::Status(0),
+            (Functions::FuncSendTxt, Some(ReqMsg::Txt(text))) => {
+                let sent = Self::text_msg(&mut state, true, SELF_WXID, &text.receiver, &text.msg);
+                Self::deliver(&state, sent);
+                if text.receiver == ECHO_WXID {
+                    let echo = Self::text_msg(&mut state, false, ECHO_WXID, "", &text.msg);
+                    Self::deliver(&state, echo);
+                }
+                RspMsg::Status(0)
+            }
+            // 与真实环境一致，自己发出的图片、文件、卡片和转发也会出现在消息回调中
+            (Functions::FuncSendImg, Some(ReqMsg::File(f))) | (Functions::FuncSendFile, Some(ReqMsg::File(f))) => {
+                // mp4 文件会作为视频消息发出
+                let msg_type = if func == Functions::FuncSendImg {
+                    3
+                } else if f.path.to_lowercase().ends_with(".mp4") {
+                    43
+                } else {
+                    49
+                };
+                // 文件消息的内容是带文件名的 appmsg
+                let content = match msg_type {
+                    49 => {
+                        let name = Path::new(&f.path).file_name().unwrap_or_default().to_string_lossy();
+                        format!("<msg><appmsg><title>{}</title><type>6</type></appmsg></msg>", name)
+                    }
+                    _ => String::new(),
+                };
+                Self::echo_self_with(&mut state, &f.receiver, msg_type, &content);
+                RspMsg::Status(0)
+            }
+            (Functions::FuncSendEmotion, Some(ReqMsg::File(f))) => {
+                Self::echo_self(&mut state, &f.receiver, 47);
+                RspMsg::Status(0)
+            }
+            (Functions::FuncSendXml, Some(ReqMsg::Xml(x))) => {
+                Self::echo_self(&mut state, &x.receiver, x.r#type as u32);
+                RspMsg::Status(0)
+            }
+            (Functions::FuncSendRichTxt, Some(ReqMsg::Rt(rt))) => {
+                Self::echo_self(&mut state, &rt.receiver, 49);
+                RspMsg::Status(0)
+            }
+            (Functions::FuncForwardMsg, Some(ReqMsg::Fm(fm))) => {
+                Self::echo_self(&mut state, &fm.receiver, 1);
+                RspMsg::Status(1)
+            }
+            (Functions::FuncSendImg, _)
+            | (Functions::FuncSendFile, _)
+            | (Functions::FuncSendXml, _)
+            | (Functions::FuncSendEmotion, _)
+            | (Functions::FuncSendRichTxt, _)
+            | (Functions::FuncRefreshPyq, _)
+            | (Functions::FuncDownloadAttach, _) => RspMsg::Status(0),
+            (Functions::FuncSendPatMsg, _)
+            | (Functions::FuncForwardMsg, _)
+            | (Functions::FuncAcceptFriend, _)
+            | (Functions::FuncRecvTransfer, _)
+            | (Functions::FuncRevokeMsg, _) => RspMsg::Status(1),
+            // 把文本文件的内容当作识别结果
+            (Functions::FuncExecOcr, Some(ReqMsg::Str(path))) => match std::fs::read_to_string(path) {
+                Ok(result) => RspMsg::Ocr(wcf::OcrMsg { status: 0, result }),
+                Err(_) => RspMsg::Ocr(wcf::OcrMsg {
+                    status: -1,
+                    result: String::new(),
+                }),
+            },
+            (Functions::FuncAddRoomMembers, Some(ReqMsg::M(m))) | (Functions::FuncInvRoomMembers, Some(ReqMsg::M(m))) => {
+                let known: Vec<String> = state.contacts.iter().map(|c| c.wxid.clone()).collect();
+                let wxids: Vec<String> = m.wxids.split(',').map(|w| w.trim().to_string()).collect();
+                if wxids.iter().any(|w| !known.contains(w)) {
+                    RspMsg::Status(0)
+                } else {
+                    let members = state.rooms.entry(m.roomid.clone()).or_default();
+                    for wxid in wxids {
+                        if !members.contains(&wxid) {
+                            members.push(wxid);
+                        }
+                    }
+                    RspMsg::Status(1)
+                }
+            }
+            (Functions::FuncDelRoomMembers, Some(ReqMsg::M(m))) => match state.rooms.get_mut(&m.roomid) {
+                Some(members) => {
+                    let wxids: Vec<&str> = m.wxids.split(',').map(|w| w.trim()).collect();
+                    members.retain(|w| !wxids.contains(&w.as_str()));
+                    RspMsg::Status(1)
+                }
+                None => RspMsg::Status(0),
+            },
+            _ => return Err(format!("模拟环境不支持该命令: {}", func.as_str_name()).into()),
+        };
+        state.outbox.push(req);
+        Ok(Some(rsp))
+    }
+
+    // 只模拟本项目用到的几条查询，其余返回空结果
+    fn query(state: &SimState, sql: &str) -> wcf::DbRows {
+        let rows = if sql.contains("FROM Contact") {
+            let row = |wxid: &str, name: &str| {
+                let muted = state.muted.contains(wxid);
+                let contact_type = if muted && !wxid.ends_with("@chatroom") { 3 | 512 } else { 3 };
+                wcf::DbRow {
+                    fields: vec![
+                        field("UserName", wxid.to_string()),
+                        field("NickName", name.to_string()),
+                        field("VerifyFlag", "0"),
+                        field("Type", contact_type.to_string()),
+                        field("ChatRoomNotify", if muted { "0" } else { "1" }),
+                    ],
+                }
+            };
+            let mut rows: Vec<wcf::DbRow> = state.contacts.iter().map(|c| row(&c.wxid, &c.name)).collect();
+            // 只补上设置了免打扰的群，不影响其他查询的结果
+            let mut rooms: Vec<&String> = state.rooms.keys().filter(|r| state.muted.contains(*r)).collect();
+            rooms.sort();
+            rows.extend(rooms.into_iter().map(|r| row(r, r)));
+            rows
+        } else if sql.contains("FROM MSG WHERE CreateTime >=") {
+            let number = |key: &str| -> i64 {
+                sql.split(key)
+                    .nth(1)
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_default()
+            };
+            let since = number(">= ");
+            let history = state
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, msg)| (i as i64 + 1, msg))
+                .filter(|(_, msg)| msg.ts as i64 >= since);
+            if sql.starts_with("SELECT COUNT(*)") {
+                vec![wcf::DbRow {
+                    fields: vec![field("n", history.count().to_string())],
+                }]
+            } else {
+                let after = number("localId > ");
+                history
+                    .filter(|(local_id, _)| *local_id > after)
+                    .take(number("LIMIT ") as usize)
+                    .map(|(local_id, msg)| {
+                        let sender = bytesextra::BytesExtra {
+                            property: None,
+                            extras: vec![bytes_extra::Extra {
+                                r#type: 1,
+                                value: msg.sender.clone(),
+                            }],
+                        };
+                        wcf::DbRow {
+                            fields: vec![
+                                field("localId", local_id.to_string()),
+                                field("MsgSvrID", (msg.id as i64).to_string()),
+                                field("Type", msg.r#type.to_string()),
+                                field("IsSender", if msg.is_self { "1" } else { "0" }),
+                                field("CreateTime", msg.ts.to_string()),
+                                field("StrTalker", msg.roomid.clone()),
+                                field("StrContent", msg.content.clone()),
+                                field("BytesExtra", sender.encode_to_vec()),
+                            ],
+                        }
+                    })
+                    .collect()
+            }
+        } else if let Some(id) = sql.strip_prefix("SELECT Type, BytesExtra FROM MSG WHERE MsgSvrID = ") {
+            let id = id.trim().parse::<i64>().unwrap_or_default() as u64;
+            state
+                .media
+                .get(&id)
+                .map(|(t, extra)| wcf::DbRow {
+                    fields: vec![field("Type", t.to_string()), field("BytesExtra", extra.clone())],
+                })
+                .into_iter()
+                .collect()
+        } else if sql.contains("FROM ContactHeadImgUrl WHERE usrName IN") {
+            state
+                .contacts
+                .iter()
+                .filter(|c| sql.contains(&format!("'{}'", c.wxid)))
+                .map(|c| wcf::DbRow {
+                    fields: vec![
+                        field("usrName", c.wxid.clone()),
+                        field("smallHeadImgUrl", format!("https://mock.invalid/{}.jpg", c.wxid)),
+                    ],
+                })
+                .collect()
+        } else if sql.contains("from ChatRoom where ChatRoomName") {
+            state
+                .rooms
+                .iter()
+                .filter(|(roomid, _)| sql.contains(&format!("'{}'", roomid)))
+                .map(|(_, members)| {
+                    // 第一个成员作为群主，其余成员都由群主邀请
+                    let owner = members.first().cloned().unwrap_or_default();
+                    // 每个成员同时写入 RoomMember 和 RoomMemberExt 的字段
+                    let mut data = vec![];
+                    for (i, w) in members.iter().enumerate() {
+                        let mut member = roomdata::room_data::RoomMember {
+                            wxid: w.clone(),
+                            name: None,
+                            state: 0,
+                        }
+                        .encode_to_vec();
+                        if i > 0 {
+                            let ext = roomdata::room_data_ext::RoomMemberExt {
+                                wxid: w.clone(),
+                                inviter: Some(owner.clone()),
+                            };
+                            member.extend(ext.encode_to_vec());
+                        }
+                        prost::encoding::bytes::encode(1, &member, &mut data);
+                    }
+                    wcf::DbRow {
+                        fields: vec![field("RoomData", data), field("Reserved2", owner)],
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        wcf::DbRows { rows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn send_text(sim: &Simulator, receiver: &str, msg: &str) {
+        let req = wcf::Request {
+            func: Functions::FuncSendTxt.into(),
+            msg: Some(ReqMsg::Txt(wcf::TextMsg {
+                msg: msg.to_string(),
+                receiver: receiver.to_string(),
+                aters: String::new(),
+            })),
+        };
+        assert_eq!(sim.handle(req).unwrap(), Some(RspMsg::Status(0)));
+    }
+
+    #[test]
+    fn echo_account_replies() {
+        let sim = Simulator::new();
+        let (tx, rx) = mpsc::sync_channel(10);
+        sim.attach(tx);
+        send_text(&sim, ECHO_WXID, "ping");
+
+        let sent = rx.try_recv().unwrap();
+        assert!(sent.is_self);
+        let echo = rx.try_recv().unwrap();
+        assert!(!echo.is_self);
+        assert_eq!(echo.sender, ECHO_WXID);
+        assert_eq!(echo.content, "ping");
+        assert_eq!(sim.outbox().len(), 1);
+
+        sim.receive_text("wxid_mock_alice", ROOM_ID, "hi");
+        let received = rx.try_recv().unwrap();
+        assert!(received.is_group);
+        assert_eq!(received.roomid, ROOM_ID);
+
+        sim.detach();
+        sim.inject(received);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn room_members_change() {
+        let sim = Simulator::new();
+        let req = wcf::Request {
+            func: Functions::FuncDelRoomMembers.into(),
+            msg: Some(ReqMsg::M(wcf::MemberMgmt {
+                roomid: ROOM_ID.to_string(),
+                wxids: "wxid_mock_bob".to_string(),
+            })),
+        };
+        assert_eq!(sim.handle(req).unwrap(), Some(RspMsg::Status(1)));
+        let rows = Simulator::query(
+            &sim.state.lock().unwrap(),
+            &format!("select * from ChatRoom where ChatRoomName = '{}'", ROOM_ID),
+        );
+        let data = roomdata::RoomData::decode(rows.rows[0].fields[0].content.as_slice()).unwrap();
+        assert_eq!(data.members.len(), 2);
+    }
+}
@@ -6,16 +6,26 @@ use std::collections::HashMap;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     mpsc::{self, Receiver, SyncSender},
-    Arc,
+    Arc, Mutex,
 };
 use std::{
     env,
     thread::{self, sleep},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 const CMD_URL: &'static str = "tcp://127.0.0.1:10086";
 const MSG_URL: &'static str = "tcp://127.0.0.1:10087";
+/// 登录状态缓存时间
+const LOGIN_CACHE_TTL: Duration = Duration::from_secs(3);
+/// 群成员缓存时间，收到进群退群消息时会提前失效
+const ROOM_CACHE_TTL: Duration = Duration::from_secs(600);
+/// 群成员变动的系统消息关键字
+const MEMBER_CHANGE_KEYWORDS: [&str; 4] = ["加入了群聊", "加入群聊", "移出了群聊", "退出了群聊"];
+/// 账号在其他设备登录的系统通知关键字
+const ELSEWHERE_LOGIN_KEYWORDS: [&str; 3] = ["在其他设备上登录", "在另一台设备上登录", "已在其他设备登录"];
+/// 账号被强制退出的系统通知关键字
+const FORCED_LOGOUT_KEYWORDS: [&str; 3] = ["被强制退出", "强制下线", "已退出登录"];
 
 pub mod wcf {
     include!("wcf.rs");
@@ -25,9 +35,20 @@ pub mod roomdata {
     include!("roomdata.rs");
 }
 
+pub mod bytesextra {
+    include!("bytesextra.rs");
+}
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
 use wcf::{request::Msg as ReqMsg, response::Msg as RspMsg, Functions, WxMsg};
 
-use crate::{handler::event_entity::Event, service::global_service::GLOBAL};
+use crate::{
+    handler::event_entity::{Event, SessionKick, SessionKickKind},
+    service::{global_service::GLOBAL, sdk_service},
+    utils::{dedup, metrics, pipeline, send_log, system_event},
+};
 
 #[macro_export]
 macro_rules! create_request {
@@ -104,12 +125,123 @@ macro_rules! execute_wcf_command {
     }};
 }
 
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// 名片消息的内容，格式与微信客户端发出的一致
+fn contact_card_xml(contact: &wcf::RpcContact) -> String {
+    format!(
+        r#"<?xml version="1.0"?><msg bigheadimgurl="" smallheadimgurl="" username="{}" nickname="{}" fullpy="" shortpy="" alias="{}" imagestatus="3" scene="17" province="{}" city="{}" sign="" sex="{}" certflag="0" certinfo="" brandIconUrl="" brandHomeUrl="" brandSubscriptConfigUrl="" brandFlags="0" regionCode="{}" />"#,
+        escape_xml(&contact.wxid),
+        escape_xml(&contact.name),
+        escape_xml(&contact.code),
+        escape_xml(&contact.province),
+        escape_xml(&contact.city),
+        contact.gender,
+        escape_xml(&contact.country),
+    )
+}
+
+#[derive(Clone)]
 pub struct RoomMember {
     /// 微信ID
     pub wxid: String,
     /// 群内昵称
     pub name: String,
     pub state: i32,
+    /// 小头像地址
+    pub avatar: Option<String>,
+    /// 邀请人 wxid
+    pub inviter: Option<String>,
+}
+
+// 进群、退群、被移出群聊的系统消息
+fn is_member_change(msg: &WxMsg) -> bool {
+    msg.r#type == 10000
+        && msg.roomid.ends_with("@chatroom")
+        && MEMBER_CHANGE_KEYWORDS.iter().any(|k| msg.content.contains(k))
+}
+
+// 账号下线的系统通知，群消息里的同样文字不算
+fn session_kick(msg: &WxMsg) -> Option<SessionKick> {
+    if msg.is_group || !(msg.r#type == 10000 || msg.r#type == 10002) {
+        return None;
+    }
+    let kind = if ELSEWHERE_LOGIN_KEYWORDS.iter().any(|k| msg.content.contains(k)) {
+        SessionKickKind::ElsewhereLogin
+    } else if FORCED_LOGOUT_KEYWORDS.iter().any(|k| msg.content.contains(k)) {
+        SessionKickKind::ForcedLogout
+    } else {
+        return None;
+    };
+    Some(SessionKick {
+        kind,
+        content: msg.content.clone(),
+        ts: msg.ts,
+    })
+}
+
+/// RoomData 成员 state 中表示群管理员的位
+const ROOM_ADMIN_FLAG: i32 = 2048;
+
+/// Contact.Type 中表示好友消息免打扰的位
+const CONTACT_MUTE_FLAG: i64 = 512;
+
+/// 当前账号在群里的身份
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug, Clone)]
+pub struct RoomRole {
+    /// 群ID
+    pub roomid: String,
+    /// 群主 wxid
+    pub owner: String,
+    /// 管理员 wxid
+    pub admins: Vec<String>,
+    /// 当前账号是否为群主
+    pub is_owner: bool,
+    /// 当前账号是否为管理员，群主也算
+    pub is_admin: bool,
+}
+
+/// Contact 表中的标志位
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ContactFlags {
+    /// 非 0 表示公众号、企业号等认证账号
+    pub verify_flag: i64,
+    /// 联系人类型位，最低位为 1 表示在通讯录中
+    pub contact_type: i64,
+    /// 群消息提醒，0 表示群设置了消息免打扰，好友为空
+    pub chat_room_notify: Option<i64>,
+}
+
+impl ContactFlags {
+    /// 是否是通讯录中的普通好友
+    pub fn is_friend(&self) -> bool {
+        self.verify_flag == 0 && self.contact_type & 1 == 1
+    }
+
+    /// 是否在微信里设置了消息免打扰，群看 ChatRoomNotify，好友看 Type 的免打扰位
+    pub fn is_muted(&self, wxid: &str) -> bool {
+        if wxid.ends_with("@chatroom") {
+            self.chat_room_notify == Some(0)
+        } else {
+            self.contact_type & CONTACT_MUTE_FLAG != 0
+        }
+    }
+}
+
+/// 消息中附件的位置信息
+pub struct MsgMedia {
+    /// 消息类型
+    pub r#type: u32,
+    /// 原图/视频/文件路径
+    pub extra: String,
+    /// 缩略图路径
+    pub thumb: String,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
@@ -132,19 +264,30 @@ pub struct SelfInfo {
 
 #[derive(Debug)]
 pub struct WeChat {
-    pub dll: Arc<Library>,
+    pub dll: Option<Arc<Library>>,
     pub listening: Arc<AtomicBool>,
     pub cmd_socket: nng::Socket,
     pub msg_socket: Option<nng::Socket>,
+    /// 最近一次查询到的登录状态
+    login_cache: Arc<Mutex<Option<(Instant, bool)>>>,
+    /// 群成员缓存，roomid -> (查询时间, 成员)
+    room_cache: Arc<Mutex<HashMap<String, (Instant, Vec<RoomMember>)>>>,
+    /// 模拟模式下由模拟器处理所有命令
+    #[cfg(any(test, feature = "mock"))]
+    pub sim: Option<Arc<mock::Simulator>>,
 }
 
 impl Clone for WeChat {
     fn clone(&self) -> Self {
         WeChat {
-            dll: Arc::clone(&self.dll),
+            dll: self.dll.clone(),
             listening: Arc::clone(&self.listening),
             cmd_socket: self.cmd_socket.clone(),
             msg_socket: self.msg_socket.clone(),
+            login_cache: Arc::clone(&self.login_cache),
+            room_cache: Arc::clone(&self.room_cache),
+            #[cfg(any(test, feature = "mock"))]
+            sim: self.sim.clone(),
         }
     }
 }
@@ -156,18 +299,24 @@ impl Default for WeChat {
 }
 
 impl WeChat {
+    /// 当前选择的 sdk.dll 路径
+    pub fn sdk_dll_path() -> std::path::PathBuf {
+        sdk_service::active_dir().join("sdk.dll")
+    }
+
     pub fn new(debug: bool) -> Self {
-        let dll_path = env::current_dir()
-            .unwrap()
-            .join("src\\wcferry\\lib\\sdk.dll");
-        let dll = unsafe { Library::new(dll_path).unwrap() };
+        let dll = unsafe { Library::new(WeChat::sdk_dll_path()).unwrap() };
         let _ = WeChat::start(&dll, debug);
         let cmd_socket = WeChat::connect(&CMD_URL).unwrap();
         let mut wc = WeChat {
-            dll: Arc::new(dll),
+            dll: Some(Arc::new(dll)),
             listening: Arc::new(AtomicBool::new(false)),
             cmd_socket,
             msg_socket: None,
+            login_cache: Arc::new(Mutex::new(None)),
+            room_cache: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(any(test, feature = "mock"))]
+            sim: None,
         };
         info!("注入成功");
         /* while !wc.clone().is_login().unwrap() {
@@ -224,6 +373,23 @@ impl WeChat {
         wc
     }
 
+    /// 使用内存模拟器代替真实微信，不加载 sdk.dll
+    #[cfg(any(test, feature = "mock"))]
+    pub fn new_mock(sim: Arc<mock::Simulator>) -> Self {
+        let mut wc = WeChat {
+            dll: None,
+            listening: Arc::new(AtomicBool::new(false)),
+            cmd_socket: nng::Socket::new(nng::Protocol::Pair1).unwrap(),
+            msg_socket: None,
+            login_cache: Arc::new(Mutex::new(None)),
+            room_cache: Arc::new(Mutex::new(HashMap::new())),
+            sim: Some(sim),
+        };
+        info!("已启用模拟模式");
+        let _ = wc.enable_recv_msg();
+        wc
+    }
+
     fn start(dll: &Library, debug: bool) -> Result<(), Box<dyn std::error::Error>> {
         type WxInitSDK = unsafe extern "C" fn(bool, i32) -> i32;
         let wx_init_sdk: Symbol<WxInitSDK> = unsafe { dll.get(b"WxInitSDK")? };
@@ -257,8 +423,12 @@ impl WeChat {
         }
         self.cmd_socket.close();
 
+        let dll = match &self.dll {
+            Some(dll) => dll,
+            None => return Ok(()),
+        };
         type WxDestroySDK = unsafe extern "C" fn() -> i32;
-        let wx_destroy_sdk: Symbol<WxDestroySDK> = unsafe { self.dll.get(b"WxDestroySDK")? };
+        let wx_destroy_sdk: Symbol<WxDestroySDK> = unsafe { dll.get(b"WxDestroySDK")? };
 
         let result = unsafe { wx_destroy_sdk() };
         if result != 0 {
@@ -269,6 +439,18 @@ impl WeChat {
     }
 
     fn send_cmd(&self, req: wcf::Request) -> Result<Option<RspMsg>, Box<dyn std::error::Error>> {
+        let method = Functions::from_i32(req.func).unwrap_or(Functions::FuncReserved).as_str_name();
+        let started = Instant::now();
+        let rsp = self.call(req);
+        metrics::observe_rpc(method, started.elapsed(), rsp.is_ok());
+        rsp
+    }
+
+    fn call(&self, req: wcf::Request) -> Result<Option<RspMsg>, Box<dyn std::error::Error>> {
+        #[cfg(any(test, feature = "mock"))]
+        if let Some(sim) = &self.sim {
+            return sim.handle(req);
+        }
         let mut buf = Vec::with_capacity(req.encoded_len());
         try_cmd!(req.encode(&mut buf), "编码失败");
         let msg = nng::Message::from(&buf[..]);
@@ -287,6 +469,19 @@ impl WeChat {
         execute_wcf_command!(self, Functions::FuncIsLogin, Status 1, "获取登录状态")
     }
 
+    /// 带短时缓存的登录状态，查询失败按未登录处理
+    pub fn is_login_cached(&self) -> bool {
+        let mut cache = self.login_cache.lock().unwrap();
+        if let Some((at, login)) = *cache {
+            if at.elapsed() < LOGIN_CACHE_TTL {
+                return login;
+            }
+        }
+        let login = self.is_login().unwrap_or(false);
+        *cache = Some((Instant::now(), login));
+        login
+    }
+
     pub fn get_self_wxid(&self) -> Result<String, Box<dyn std::error::Error>> {
         execute_wcf_command!(self, Functions::FuncGetSelfWxid, Str, "获取 wxid ")
     }
@@ -366,16 +561,20 @@ impl WeChat {
                         let rsp = match wcf::Response::decode(buf.as_slice()) {
                             Ok(rsp) => rsp,
                             Err(e) => {
+                                pipeline::parse_error();
                                 warn!("消息解码失败: {}", e);
                                 break;
                             }
                         };
                         if let Some(RspMsg::Wxmsg(msg)) = rsp.msg {
+                            pipeline::received();
                             match tx.send(msg) {
                                 Ok(_) => {
+                                    pipeline::enqueued(true);
                                     debug!("消息入队成功");
                                 }
                                 Err(e) => {
+                                    pipeline::enqueued(false);
                                     error!("消息入队失败: {}", e);
                                 }
                             }
@@ -401,10 +600,26 @@ impl WeChat {
             while wechat.listening.load(Ordering::Relaxed) {
                 match rx.recv() {
                     Ok(msg) => {
+                        pipeline::dequeued();
+                        // 数据库轮询已经补发过的消息不再重复处理
+                        if !dedup::first_seen(msg.id) {
+                            debug!("消息 {} 已处理过，跳过", msg.id);
+                            continue;
+                        }
+                        send_log::record(&msg);
+                        system_event::publish(&msg);
+                        if is_member_change(&msg) {
+                            wechat.invalidate_room(&msg.roomid);
+                        }
                         // 发送到消息监听器中
                         let global = GLOBAL.get().unwrap();
                         let event_bus = global.msg_event_bus.lock().unwrap();
+                        let kick = session_kick(&msg);
                         let _ = event_bus.send_message(Event::ClientMessage(msg.clone()));
+                        if let Some(kick) = kick {
+                            warn!("账号已下线: {}", kick.content);
+                            event_bus.send_message(Event::SessionKicked(kick));
+                        }
                     }
                     Err(e) => {
                         error!("消息出队失败: {}", e);
@@ -426,6 +641,14 @@ impl WeChat {
             RspMsg::Status(status) => {
                 if status == 0 {
                     let (tx, rx) = mpsc::sync_channel::<wcf::WxMsg>(100);
+                    #[cfg(any(test, feature = "mock"))]
+                    if let Some(sim) = &self.sim {
+                        sim.attach(tx);
+                        self.listening.store(true, Ordering::Relaxed);
+                        let mut wc = self.clone();
+                        thread::spawn(move || forward_msg(&mut wc, rx));
+                        return Ok(true);
+                    }
                     self.msg_socket = Some(WeChat::connect(MSG_URL).unwrap());
                     self.listening.store(true, Ordering::Relaxed);
                     let mut wc1 = self.clone();
@@ -456,6 +679,10 @@ impl WeChat {
             RspMsg::Status(status) => {
                 // TODO: 处理状态码
                 self.msg_socket.take().map(|s| s.close());
+                #[cfg(any(test, feature = "mock"))]
+                if let Some(sim) = &self.sim {
+                    sim.detach();
+                }
                 self.listening.store(false, Ordering::Relaxed);
                 return Ok(status);
             }
@@ -485,6 +712,61 @@ impl WeChat {
         execute_wcf_command!(self, Functions::FuncSendFile, ReqMsg::File(file), Status 0, "发送文件消息")
     }
 
+    /// 视频通过发送文件的接口发送，mp4 文件会显示为带缩略图的视频消息
+    pub fn send_video(&self, video: wcf::PathMsg) -> Result<bool, Box<dyn std::error::Error>> {
+        execute_wcf_command!(self, Functions::FuncSendFile, ReqMsg::File(video), Status 0, "发送视频消息")
+    }
+
+    /// 发送本地表情文件，gif 会显示为动态表情
+    pub fn send_emotion(&self, emotion: wcf::PathMsg) -> Result<bool, Box<dyn std::error::Error>> {
+        execute_wcf_command!(self, Functions::FuncSendEmotion, ReqMsg::File(emotion), Status 0, "发送表情消息")
+    }
+
+    /// 发送任意 xml 消息，type 为消息类型，path 为缩略图等附带的本地文件
+    pub fn send_xml(&self, xml: wcf::XmlMsg) -> Result<bool, Box<dyn std::error::Error>> {
+        execute_wcf_command!(self, Functions::FuncSendXml, ReqMsg::Xml(xml), Status 0, "发送 xml 消息")
+    }
+
+    /// 把通讯录中的联系人以名片发送给 receiver
+    pub fn send_contact_card(&self, receiver: String, wxid: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let contact = self
+            .get_contacts()?
+            .contacts
+            .into_iter()
+            .find(|c| c.wxid == wxid)
+            .ok_or_else(|| format!("联系人 {} 不在通讯录中", wxid))?;
+        self.send_xml(wcf::XmlMsg {
+            receiver,
+            content: contact_card_xml(&contact),
+            path: String::new(),
+            r#type: 42,
+        })
+    }
+
+    /// 发送位置，lat、lng 为 GCJ-02 坐标，label 为地点名称，address 为详细地址
+    pub fn send_location(
+        &self,
+        receiver: String,
+        lat: f64,
+        lng: f64,
+        label: &str,
+        address: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let content = format!(
+            r#"<?xml version="1.0"?><msg><location x="{}" y="{}" scale="15" label="{}" maptype="roadmap" poiname="{}" poiid="" /></msg>"#,
+            lat,
+            lng,
+            escape_xml(address),
+            escape_xml(label),
+        );
+        self.send_xml(wcf::XmlMsg {
+            receiver,
+            content,
+            path: String::new(),
+            r#type: 48,
+        })
+    }
+
     pub fn send_rich_text(&self, msg: wcf::RichText) -> Result<bool, Box<dyn std::error::Error>> {
         execute_wcf_command!(self, Functions::FuncSendRichTxt, ReqMsg::Rt(msg), Status 0, "发送卡片消息")
     }
@@ -517,6 +799,11 @@ impl WeChat {
         )
     }
 
+    /// 识别图片中的文字，path 为已解密的图片；status 非 0 表示微信还在识别，需要稍后重试
+    pub fn exec_ocr(&self, path: String) -> Result<wcf::OcrMsg, Box<dyn std::error::Error>> {
+        execute_wcf_command!(self, Functions::FuncExecOcr, ReqMsg::Str(path), Ocr, "识别图片文字")
+    }
+
     pub fn download_attach(&self, msg: wcf::AttachMsg) -> Result<bool, Box<dyn std::error::Error>> {
         execute_wcf_command!(self, Functions::FuncDownloadAttach, ReqMsg::Att(msg), Status 0, "下载附件")
     }
@@ -546,30 +833,64 @@ impl WeChat {
         &self,
         msg: wcf::MemberMgmt,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        execute_wcf_command!(self, Functions::FuncAddRoomMembers, ReqMsg::M(msg), Status 1, "添加群成员")
+        let roomid = msg.roomid.clone();
+        let changed = execute_wcf_command!(self, Functions::FuncAddRoomMembers, ReqMsg::M(msg), Status 1, "添加群成员");
+        self.invalidate_room(&roomid);
+        changed
     }
 
     pub fn invite_chatroom_member(
         &self,
         msg: wcf::MemberMgmt,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        execute_wcf_command!(self, Functions::FuncInvRoomMembers, ReqMsg::M(msg), Status 1, "邀请群成员")
+        let roomid = msg.roomid.clone();
+        let changed = execute_wcf_command!(self, Functions::FuncInvRoomMembers, ReqMsg::M(msg), Status 1, "邀请群成员");
+        self.invalidate_room(&roomid);
+        changed
     }
 
     pub fn delete_chatroom_member(
         &self,
         msg: wcf::MemberMgmt,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        execute_wcf_command!(self, Functions::FuncDelRoomMembers, ReqMsg::M(msg), Status 1, "删除群成员")
+        let roomid = msg.roomid.clone();
+        let changed = execute_wcf_command!(self, Functions::FuncDelRoomMembers, ReqMsg::M(msg), Status 1, "删除群成员");
+        self.invalidate_room(&roomid);
+        changed
     }
 
     pub fn revoke_msg(&self, id: u64) -> Result<bool, Box<dyn std::error::Error>> {
         execute_wcf_command!(self, Functions::FuncRevokeMsg, ReqMsg::Ui64(id), Status 1, "撤回消息")
     }
 
+    /// 清除群成员缓存。增删成员要在调用完成后清除，否则调用期间读到的旧成员会被重新缓存
+    pub fn invalidate_room(&self, room_id: &str) {
+        self.room_cache.lock().unwrap().remove(room_id);
+    }
+
+    /// 查询群成员，结果会缓存一段时间
     pub fn query_room_member(
         &self,
         room_id: String,
+    ) -> Result<Option<Vec<RoomMember>>, Box<dyn std::error::Error>> {
+        if let Some((at, members)) = self.room_cache.lock().unwrap().get(&room_id) {
+            if at.elapsed() < ROOM_CACHE_TTL {
+                return Ok(Some(members.clone()));
+            }
+        }
+        let members = self.fetch_room_member(room_id.clone())?;
+        if let Some(members) = &members {
+            self.room_cache
+                .lock()
+                .unwrap()
+                .insert(room_id, (Instant::now(), members.clone()));
+        }
+        Ok(members)
+    }
+
+    fn fetch_room_member(
+        &self,
+        room_id: String,
     ) -> Result<Option<Vec<RoomMember>>, Box<dyn std::error::Error>> {
         let query = wcf::DbQuery {
             db: String::from("MicroMsg.db"),
@@ -596,6 +917,20 @@ impl WeChat {
                 debug!("roomdata field content:{:?}", field.content);
                 let room_data = roomdata::RoomData::decode(field.content.as_slice())?;
                 debug!("群聊：{} 总计：{}人", room_id, room_data.members.len());
+                let inviters: HashMap<String, String> = roomdata::RoomDataExt::decode(field.content.as_slice())
+                    .map(|ext| {
+                        ext.members
+                            .into_iter()
+                            .filter_map(|m| m.inviter.filter(|i| !i.is_empty()).map(|i| (m.wxid, i)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let all_wxids: Vec<String> = room_data.members.iter().map(|m| m.wxid.clone()).collect();
+                // 头像只是附加信息，查询失败时不影响成员列表
+                let avatars = self.query_avatars(&all_wxids).unwrap_or_else(|e| {
+                    warn!("查询群 {} 成员头像失败: {}", room_id, e);
+                    HashMap::new()
+                });
     
                 // 构建需要查询昵称的wxid列表
                 let wxids: Vec<_> = room_data.members
@@ -654,6 +989,8 @@ impl WeChat {
                             }
                         }
                         RoomMember {
+                            avatar: avatars.get(&member.wxid).cloned(),
+                            inviter: inviters.get(&member.wxid).cloned(),
                             wxid: member.wxid,
                             name: member.name.unwrap_or_default(),
                             state: member.state,
@@ -667,6 +1004,279 @@ impl WeChat {
     
         Ok(None)
     }
+
+    /// 批量查询小头像地址
+    pub fn query_avatars(&self, wxids: &[String]) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+        if wxids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let params = wxids
+            .iter()
+            .map(|id| format!("'{}'", id.replace("'", "''")))
+            .collect::<Vec<_>>()
+            .join(",");
+        let query = wcf::DbQuery {
+            db: String::from("MicroMsg.db"),
+            sql: format!(
+                "SELECT usrName, smallHeadImgUrl FROM ContactHeadImgUrl WHERE usrName IN ({})",
+                params
+            ),
+        };
+        let rows: Result<wcf::DbRows, Box<dyn std::error::Error>> = execute_wcf_command!(
+            self,
+            Functions::FuncExecDbQuery,
+            ReqMsg::Query(query),
+            Rows,
+            "查询头像"
+        );
+        let avatars = rows?
+            .rows
+            .into_iter()
+            .filter_map(|row| {
+                let mut wxid = None;
+                let mut url = None;
+                for field in row.fields {
+                    match field.column.as_str() {
+                        "usrName" => wxid = Some(String::from_utf8_lossy(&field.content).to_string()),
+                        "smallHeadImgUrl" => url = Some(String::from_utf8_lossy(&field.content).to_string()),
+                        _ => {}
+                    }
+                }
+                wxid.zip(url.filter(|u| !u.is_empty()))
+            })
+            .collect();
+        Ok(avatars)
+    }
+
+    /// 查询群主和管理员，群不存在时返回 None
+    pub fn query_room_role(&self, room_id: String) -> Result<Option<RoomRole>, Box<dyn std::error::Error>> {
+        let query = wcf::DbQuery {
+            db: String::from("MicroMsg.db"),
+            sql: format!(
+                "SELECT RoomData, Reserved2 from ChatRoom where ChatRoomName = '{}'",
+                room_id.replace("'", "''")
+            ),
+        };
+        let rows: Result<wcf::DbRows, Box<dyn std::error::Error>> = execute_wcf_command!(
+            self,
+            Functions::FuncExecDbQuery,
+            ReqMsg::Query(query),
+            Rows,
+            "查询群身份"
+        );
+        let row = match rows?.rows.into_iter().next() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let mut owner = String::new();
+        let mut admins = vec![];
+        for field in row.fields {
+            match field.column.as_str() {
+                // Reserved2 存放群主 wxid
+                "Reserved2" => owner = String::from_utf8_lossy(&field.content).to_string(),
+                "RoomData" => {
+                    let room_data = roomdata::RoomData::decode(field.content.as_slice())?;
+                    admins = room_data
+                        .members
+                        .into_iter()
+                        .filter(|m| m.state & ROOM_ADMIN_FLAG != 0)
+                        .map(|m| m.wxid)
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        let self_wxid = self.get_self_wxid()?;
+        let is_owner = !owner.is_empty() && owner == self_wxid;
+        let is_admin = is_owner || admins.contains(&self_wxid);
+        Ok(Some(RoomRole {
+            roomid: room_id,
+            owner,
+            admins,
+            is_owner,
+            is_admin,
+        }))
+    }
+
+    /// 查询联系人的 VerifyFlag、Type 和 ChatRoomNotify 标志位
+    pub fn query_contact_flags(&self) -> Result<HashMap<String, ContactFlags>, Box<dyn std::error::Error>> {
+        let query = wcf::DbQuery {
+            db: String::from("MicroMsg.db"),
+            sql: String::from("SELECT UserName, VerifyFlag, Type, ChatRoomNotify FROM Contact"),
+        };
+        let rows: Result<wcf::DbRows, Box<dyn std::error::Error>> = execute_wcf_command!(
+            self,
+            Functions::FuncExecDbQuery,
+            ReqMsg::Query(query),
+            Rows,
+            "查询联系人标志位"
+        );
+        let flags = rows?
+            .rows
+            .into_iter()
+            .filter_map(|row| {
+                let mut username = None;
+                let mut flags = ContactFlags::default();
+                for field in row.fields {
+                    let value = String::from_utf8_lossy(&field.content).to_string();
+                    match field.column.as_str() {
+                        "UserName" => username = Some(value),
+                        "VerifyFlag" => flags.verify_flag = value.parse().unwrap_or_default(),
+                        "Type" => flags.contact_type = value.parse().unwrap_or_default(),
+                        "ChatRoomNotify" => flags.chat_room_notify = value.parse().ok(),
+                        _ => {}
+                    }
+                }
+                username.map(|u| (u, flags))
+            })
+            .collect();
+        Ok(flags)
+    }
+
+    /// 根据消息 id 从 MSG 库中查询附件路径
+    pub fn query_msg_media(&self, id: u64) -> Result<Option<MsgMedia>, Box<dyn std::error::Error>> {
+        let user_info: Result<wcf::UserInfo, Box<dyn std::error::Error>> =
+            execute_wcf_command!(self, Functions::FuncGetUserInfo, Ui, "获取用户信息");
+        let home = user_info?.home;
+        let dbs = self.get_dbs()?;
+        for db in dbs.names.into_iter().filter(|name| is_msg_db(name)) {
+            let query = wcf::DbQuery {
+                db,
+                // MsgSvrID 在库里是有符号的 64 位整数，超过 i64::MAX 的 id 存的是负数
+                sql: format!("SELECT Type, BytesExtra FROM MSG WHERE MsgSvrID = {}", id as i64),
+            };
+            let rows: Result<wcf::DbRows, Box<dyn std::error::Error>> = execute_wcf_command!(
+                self,
+                Functions::FuncExecDbQuery,
+                ReqMsg::Query(query),
+                Rows,
+                "查询消息附件"
+            );
+            let db_rows = rows?.rows;
+            let row = match db_rows.first() {
+                Some(row) => row,
+                None => continue,
+            };
+
+            let mut media = MsgMedia {
+                r#type: 0,
+                extra: String::new(),
+                thumb: String::new(),
+            };
+            for field in row.fields.iter() {
+                if field.column.eq("Type") {
+                    media.r#type = String::from_utf8_lossy(&field.content).parse().unwrap_or_default();
+                } else if field.column.eq("BytesExtra") {
+                    let bytes_extra = bytesextra::BytesExtra::decode(field.content.as_slice())?;
+                    for extra in bytes_extra.extras {
+                        match extra.r#type {
+                            3 => media.thumb = join_home(&home, &extra.value),
+                            4 => media.extra = join_home(&home, &extra.value),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            return Ok(Some(media));
+        }
+        Ok(None)
+    }
+
+    /// 消息分库名，如 MSG0.db、MSG1.db
+    pub fn msg_dbs(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.get_dbs()?.names.into_iter().filter(|name| is_msg_db(name)).collect())
+    }
+
+    /// 分库中 since（unix 秒）以后的消息数
+    pub fn count_history(&self, db: &str, since: u32) -> Result<u64, Box<dyn std::error::Error>> {
+        let rows = self.query_sql(wcf::DbQuery {
+            db: db.to_string(),
+            sql: format!("SELECT COUNT(*) AS n FROM MSG WHERE CreateTime >= {}", since),
+        })?;
+        Ok(rows
+            .rows
+            .first()
+            .and_then(|row| row.fields.first())
+            .and_then(|field| String::from_utf8_lossy(&field.content).parse().ok())
+            .unwrap_or_default())
+    }
+
+    /// 按 localId 顺序读取分库中 since 以后、localId 大于 after 的消息，返回 (localId, 消息)
+    pub fn query_history(
+        &self,
+        db: &str,
+        since: u32,
+        after: i64,
+        limit: usize,
+    ) -> Result<Vec<(i64, wcf::WxMsg)>, Box<dyn std::error::Error>> {
+        let user_info: Result<wcf::UserInfo, Box<dyn std::error::Error>> =
+            execute_wcf_command!(self, Functions::FuncGetUserInfo, Ui, "获取用户信息");
+        let user_info = user_info?;
+        let rows = self.query_sql(wcf::DbQuery {
+            db: db.to_string(),
+            sql: format!(
+                "SELECT localId, MsgSvrID, Type, IsSender, CreateTime, StrTalker, StrContent, BytesExtra \
+                 FROM MSG WHERE CreateTime >= {} AND localId > {} ORDER BY localId LIMIT {}",
+                since, after, limit
+            ),
+        })?;
+        let mut messages = Vec::with_capacity(rows.rows.len());
+        for row in rows.rows {
+            let mut local_id = 0;
+            let mut msg = wcf::WxMsg::default();
+            let mut sender = String::new();
+            for field in row.fields {
+                let text = || String::from_utf8_lossy(&field.content).to_string();
+                match field.column.as_str() {
+                    "localId" => local_id = text().parse().unwrap_or_default(),
+                    "MsgSvrID" => msg.id = text().parse::<i64>().unwrap_or_default() as u64,
+                    "Type" => msg.r#type = text().parse().unwrap_or_default(),
+                    "IsSender" => msg.is_self = text() == "1",
+                    "CreateTime" => msg.ts = text().parse().unwrap_or_default(),
+                    "StrTalker" => msg.roomid = text(),
+                    "StrContent" => msg.content = text(),
+                    "BytesExtra" => {
+                        let bytes_extra = bytesextra::BytesExtra::decode(field.content.as_slice()).unwrap_or_default();
+                        for extra in bytes_extra.extras {
+                            match extra.r#type {
+                                1 => sender = extra.value,
+                                3 => msg.thumb = join_home(&user_info.home, &extra.value),
+                                4 => msg.extra = join_home(&user_info.home, &extra.value),
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            msg.is_group = msg.roomid.ends_with("@chatroom");
+            // 私聊和自己发的消息 BytesExtra 中没有发送者
+            msg.sender = if msg.is_self {
+                user_info.wxid.clone()
+            } else if sender.is_empty() {
+                msg.roomid.clone()
+            } else {
+                sender
+            };
+            messages.push((local_id, msg));
+        }
+        Ok(messages)
+    }
+}
+
+/// 是否是 MSG0.db、MSG1.db 这类消息分库
+fn is_msg_db(name: &str) -> bool {
+    name.strip_prefix("MSG")
+        .and_then(|rest| rest.strip_suffix(".db"))
+        .map_or(false, |n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// BytesExtra 中的路径是相对微信文件目录的
+fn join_home(home: &str, value: &str) -> String {
+    if value.is_empty() || std::path::Path::new(value).is_absolute() {
+        return value.to_string();
+    }
+    std::path::Path::new(home).join(value).to_string_lossy().to_string()
 }
 
 #[cfg(test)]
@@ -675,4 +1285,18 @@ mod tests {
 
     #[test]
     fn test_query_room_member() {}
+
+    #[test]
+    fn test_session_kick() {
+        let mut msg = WxMsg {
+            r#type: 10000,
+            content: "你的微信帐号于 12:00 在其他设备上登录".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(session_kick(&msg).unwrap().kind, SessionKickKind::ElsewhereLogin);
+        msg.content = "你已被强制退出登录".to_string();
+        assert_eq!(session_kick(&msg).unwrap().kind, SessionKickKind::ForcedLogout);
+        msg.is_group = true;
+        assert!(session_kick(&msg).is_none());
+    }
 }
@@ -33,3 +33,23 @@ pub mod room_data {
         pub state: i32,
     }
 }
+/// 与 RoomData 共用字段编号，只解析成员的邀请人，解析失败不影响 RoomData
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RoomDataExt {
+    #[prost(message, repeated, tag = "1")]
+    pub members: ::prost::alloc::vec::Vec<room_data_ext::RoomMemberExt>,
+}
+/// Nested message and enum types in `RoomDataExt`.
+pub mod room_data_ext {
+    #[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct RoomMemberExt {
+        #[prost(string, tag = "1")]
+        pub wxid: ::prost::alloc::string::String,
+        #[prost(string, optional, tag = "4")]
+        pub inviter: ::core::option::Option<::prost::alloc::string::String>,
+    }
+}
@@ -195,6 +195,10 @@ pub struct PathMsg {
     /// base64图片数据，可选
     #[prost(string, tag = "3")]
     pub base64: ::prost::alloc::string::String,
+    /// base64 文件的文件名，发送文件时使用，可选
+    #[prost(string, tag = "4")]
+    #[serde(default)]
+    pub filename: ::prost::alloc::string::String,
 }
 #[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 #[allow(clippy::derive_partial_eq_without_eq)]
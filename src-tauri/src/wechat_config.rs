@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::{service::stats_service::WordPeriod, utils::secret::Secret};
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct WechatConfig {
     // http 回调地址
@@ -14,4 +18,1120 @@ pub struct WechatConfig {
     pub front_msg_show: bool,
     // 消息正则白名单过滤
     pub msg_filter_regexp: Option<String>,
+    // ffmpeg 可执行文件路径，为空时从 PATH 中查找
+    #[serde(default)]
+    pub ffmpeg_path: Option<String>,
+    // 各下游推送的消息格式版本
+    #[serde(default)]
+    pub payload_versions: PayloadVersions,
+    // socketIO 推送的编码格式
+    #[serde(default)]
+    pub socketio_encoding: StreamEncoding,
+    // socketIO 推送是否以 zlib 压缩后的二进制帧发送
+    #[serde(default)]
+    pub socketio_compress: bool,
+    // http 响应压缩
+    #[serde(default)]
+    pub http_compression: HttpCompression,
+    // 联系人变化检测间隔，单位秒，0 为不启用
+    #[serde(default)]
+    pub contact_diff_interval: u64,
+    // 高风险操作每日上限
+    #[serde(default)]
+    pub risk_guard: RiskGuardConfig,
+    // 演练模式，发消息、拉人、踢人等接口只记录日志不实际执行
+    #[serde(default)]
+    pub dry_run: bool,
+    // 使用内存模拟器代替真实微信，需以 mock feature 编译
+    #[serde(default)]
+    pub mock: bool,
+    // 原始消息记录
+    #[serde(default)]
+    pub tap: TapConfig,
+    // 心跳上报
+    #[serde(default)]
+    pub heartbeat: HeartbeatConfig,
+    // 内部异常通知管理员
+    #[serde(default)]
+    pub admin_notify: AdminNotifyConfig,
+    // 客户端自更新
+    #[serde(default)]
+    pub updater: UpdaterConfig,
+    // 群聊指令
+    #[serde(default)]
+    pub command: CommandConfig,
+    // 群打卡
+    #[serde(default)]
+    pub checkin: CheckinConfig,
+    // 免打扰时段，期间自动发送的消息排队到时段结束后再发
+    #[serde(default)]
+    pub quiet_hours: Vec<QuietHoursConfig>,
+    // 消息量异常检测
+    #[serde(default)]
+    pub anomaly: AnomalyConfig,
+    // 消息接收看门狗
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+    // 账号被挤下线后的处理
+    #[serde(default)]
+    pub session: SessionConfig,
+    // 把掉线、注入失败、异常退出等关键事件写入 Windows 事件日志
+    #[serde(default)]
+    pub event_log: bool,
+    // 命名管道接口
+    #[serde(default)]
+    pub pipe: PipeConfig,
+    // 本地模式：只监听 127.0.0.1、关闭 Swagger 页面并强制校验访问令牌
+    #[serde(default)]
+    pub local_only: bool,
+    // 访问令牌，配置后所有接口都需要带上 Authorization: Bearer <令牌> 或 X-Api-Token 请求头
+    #[serde(default)]
+    pub api_token: Secret,
+    // 发送后确认消息已发出
+    #[serde(default)]
+    pub send_verify: SendVerifyConfig,
+    // 自动发送的节奏
+    #[serde(default)]
+    pub pacing: PacingConfig,
+    // 自动发送前的内容审核
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    // 落盘和推送给下游前的敏感信息脱敏
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    // 本地消息库及保留期限
+    #[serde(default)]
+    pub message_store: MessageStoreConfig,
+    // 定时备份及远端存储
+    #[serde(default)]
+    pub backup: BackupConfig,
+    // 轮询消息数据库补收接收回调漏掉的消息
+    #[serde(default)]
+    pub db_poll: DbPollConfig,
+    // 在本地只读快照上执行 /sql 查询
+    #[serde(default)]
+    pub sql_snapshot: SqlSnapshotConfig,
+    // 定时发送群词云图片
+    #[serde(default)]
+    pub word_cloud: WordCloudConfig,
+    // 群通知确认
+    #[serde(default)]
+    pub announcement: AnnouncementConfig,
+    // 大文件分发到多个群
+    #[serde(default)]
+    pub distribute: DistributeConfig,
+    // 收到的文件自动转存
+    #[serde(default)]
+    pub file_intake: FileIntakeConfig,
+    // 识别指定联系人发来的票据照片
+    #[serde(default)]
+    pub receipt: ReceiptConfig,
+    // 按微信里的消息免打扰设置跳过推送
+    #[serde(default)]
+    pub dnd: DndConfig,
+    // 公众号和服务通知消息的推送方式
+    #[serde(default)]
+    pub official: OfficialFilterConfig,
+    // 被拍一拍时拍回去或回复
+    #[serde(default)]
+    pub pat: PatConfig,
+    // @所有人
+    #[serde(default)]
+    pub at_all: AtAllConfig,
+    // 接收外部系统的 webhook 并转成微信消息
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    // 消息规则，按顺序匹配，命中的规则依次执行动作
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    // 规则中的脚本动作，默认关闭
+    #[serde(default)]
+    pub rule_scripts: RuleScriptConfig,
+    // 规则变量的数量上限和过期时间
+    #[serde(default)]
+    pub rule_vars: RuleVarConfig,
+    // 批量发送
+    #[serde(default)]
+    pub batch: BatchConfig,
+    // Markdown 渲染为图片
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RuleConfig {
+    pub name: String,
+    // 生效的会话，为空时不限
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    // 只处理这些人发的消息，为空时不限
+    #[serde(default)]
+    pub senders: Vec<String>,
+    // 匹配文本消息内容的正则，为空时匹配全部文本消息；分组可以在模板中用 {1}、{name} 引用
+    #[serde(default)]
+    pub pattern: String,
+    pub actions: Vec<RuleAction>,
+    // 数值大的先匹配，相同时按配置中的顺序
+    #[serde(default)]
+    pub priority: i32,
+    // 所属分组，同一分组的规则可以一起停用
+    #[serde(default)]
+    pub group: String,
+    // 命中后不再匹配后面的规则
+    #[serde(default)]
+    pub stop: bool,
+    // 同一会话中两次触发的最小间隔，单位秒
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    // 同一会话中每小时最多触发的次数，为 0 时不限
+    #[serde(default)]
+    pub max_per_hour: u32,
+    // 只在这些时段内生效，为空时不限
+    #[serde(default)]
+    pub windows: Vec<RuleWindow>,
+    // 变量需要全部满足这些条件
+    #[serde(default)]
+    pub when: Vec<VarCondition>,
+    // 组合条件，与上面的条件同时满足
+    #[serde(default)]
+    pub condition: Option<Condition>,
+}
+
+// 可以嵌套的条件，例如 {"all": [{"is_at_me": true}, {"not": {"sender": ["wxid_bot"]}}]}
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    // 全部满足
+    All(Vec<Condition>),
+    // 满足任意一个
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    // 发送人是其中之一
+    Sender(Vec<String>),
+    // 会话是其中之一
+    Room(Vec<String>),
+    // 消息类型是其中之一，例如 1 文本、3 图片；出现 type 或下面的媒体条件时规则才处理非文本消息
+    Type(Vec<u32>),
+    // 是否为图片消息
+    IsImage(bool),
+    // 文件消息的扩展名是其中之一，不区分大小写
+    Extension(Vec<String>),
+    // 图片、语音、视频或文件的大小，单位字节
+    Size(Bounds),
+    // 语音或视频的时长，单位秒
+    Duration(Bounds),
+    // 内容匹配正则，分组可以在模板中引用
+    Regex(String),
+    // 消息时间在时段内
+    Time(RuleWindow),
+    // 消息是否 @ 了自己
+    IsAtMe(bool),
+}
+
+// 包含两端的范围，为空的一端不限
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Bounds {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VarCondition {
+    // 变量名，可以使用模板变量，例如 count_{roomid}
+    pub var: String,
+    #[serde(default)]
+    pub op: CompareOp,
+    // 两边都是数字时按数值比较，否则按字符串比较；不存在的变量视为空字符串，与数字比较时视为 0
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompareOp {
+    #[default]
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RuleWindow {
+    // 开始时间，格式 HH:MM
+    pub start: String,
+    // 结束时间，早于开始时间时表示跨天
+    pub end: String,
+    // 1 到 7 表示周一到周日，为空时每天；跨天时段按开始的那天算
+    #[serde(default)]
+    pub weekdays: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RuleAction {
+    // 回复固定内容，可以使用模板变量
+    Reply { text: String },
+    // 调用外部接口
+    Http(HttpAction),
+    // 运行本机脚本，需要开启 rule_scripts 且规则限定了 senders
+    Script(ScriptAction),
+    // 设置变量，var 和 value 都可以使用模板变量
+    Set { var: String, value: String },
+    // 计数器加 by，默认加 1
+    Incr {
+        var: String,
+        #[serde(default = "default_incr")]
+        by: i64,
+    },
+}
+
+fn default_incr() -> i64 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HttpAction {
+    #[serde(default = "default_http_method")]
+    pub method: String,
+    // 模板中的变量会做 URL 编码
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // 请求体模板，Content-Type 为 json 时变量按 JSON 字符串转义；为空时发送原始消息的 JSON
+    #[serde(default)]
+    pub body: String,
+    // 把响应发回会话，响应为 JSON 时取其中的 reply 字段
+    #[serde(default)]
+    pub reply: bool,
+    #[serde(default = "default_http_timeout")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScriptAction {
+    // rule_scripts.dir 下的脚本，不能跳出该目录
+    pub script: String,
+    // 固定参数，不做模板替换
+    #[serde(default)]
+    pub args: Vec<String>,
+    // 把标准输出发回会话
+    #[serde(default)]
+    pub reply: bool,
+    #[serde(default = "default_http_timeout")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RuleScriptConfig {
+    pub enabled: bool,
+    // 允许运行的脚本所在目录
+    pub dir: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RuleVarConfig {
+    // 最多保留的变量数，超出时删除最久没有修改的，0 为不限
+    pub max_vars: usize,
+    // 超过这么多天没有修改的变量会被删除，0 为永久保留
+    pub ttl_days: u64,
+}
+
+impl Default for RuleVarConfig {
+    fn default() -> Self {
+        RuleVarConfig {
+            max_vars: 10000,
+            ttl_days: 90,
+        }
+    }
+}
+
+fn default_http_method() -> String {
+    "POST".to_string()
+}
+
+fn default_http_timeout() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BatchConfig {
+    // 两条消息之间的默认间隔，单位毫秒，请求中可以单独指定
+    pub interval_ms: u64,
+    // 一次最多提交的消息数
+    pub max_items: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            interval_ms: 1000,
+            max_items: 500,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub hooks: Vec<WebhookHook>,
+    // 请求头中的时间戳与本机时间相差超过该值时拒绝，单位秒
+    pub max_skew_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            hooks: vec![],
+            max_skew_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookHook {
+    // 接收地址为 /webhook/{name}
+    pub name: String,
+    // 签名密钥，对 "{时间戳}.{nonce}.{请求体}" 做 HMAC-SHA256，放在 X-Webhook-Signature 请求头。为空时拒绝所有投递
+    pub secret: Secret,
+    // 消息接收人
+    pub receiver: String,
+    // 消息内容，{a.b} 替换为请求体中对应字段的值
+    pub template: String,
+    // 请求体需满足的 JSON Schema，为空时不校验
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AtAllConfig {
+    // 每个群每天最多 @所有人 的次数，0 为不限制
+    pub per_room_daily: u32,
+}
+
+impl Default for AtAllConfig {
+    fn default() -> Self {
+        AtAllConfig { per_room_daily: 3 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PatConfig {
+    // 被拍时拍回去
+    pub pat_back: bool,
+    // 被拍时回复的内容，为空时不回复，{from} 替换为拍的人的 wxid
+    pub reply: String,
+    // 生效的会话，为空时全部生效
+    pub rooms: Vec<String>,
+    // 同一个人在同一会话里多次拍时，间隔内只响应一次，单位秒
+    pub cooldown_secs: u64,
+}
+
+impl Default for PatConfig {
+    fn default() -> Self {
+        PatConfig {
+            pat_back: false,
+            reply: String::new(),
+            rooms: vec![],
+            cooldown_secs: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OfficialFilterConfig {
+    pub mode: OfficialMode,
+    // route 模式下公众号消息的 http 回调地址
+    pub cburl: Vec<String>,
+}
+
+/// 公众号和服务通知消息的处理方式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OfficialMode {
+    /// 与其他消息一样推送
+    #[default]
+    Forward,
+    /// 不推送
+    Drop,
+    /// 只推送到 official.cburl，不推送到 socketIO
+    Route,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DndConfig {
+    // 遵循免打扰设置的下游，可选 http、socketio，为空时全部照常推送
+    pub sinks: Vec<String>,
+    // 即使在微信里设了免打扰也照常推送的会话
+    pub unmute: Vec<String>,
+    // 在微信里没设免打扰也不推送的会话
+    pub mute: Vec<String>,
+    // 重新读取免打扰设置的间隔，单位秒
+    pub refresh_secs: u64,
+}
+
+impl Default for DndConfig {
+    fn default() -> Self {
+        DndConfig {
+            sinks: vec!["http".to_string(), "socketio".to_string()],
+            unmute: vec![],
+            mute: vec![],
+            refresh_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ReceiptConfig {
+    // 只识别这些人发来的图片，为空时不识别
+    pub senders: Vec<String>,
+    // 结果追加到的 CSV 文件，为空时为 data/receipts.csv
+    pub csv: String,
+    // 外部解析程序，从标准输入读取识别出的文字，输出 {"amount":..,"date":..,"vendor":..}；为空时使用内置规则
+    pub parser: String,
+    // 外部解析程序最长运行时间，超时后终止并改用内置规则，单位秒
+    pub parser_timeout_secs: u64,
+    // 等待图片下载和文字识别的时长，单位秒
+    pub timeout_secs: u64,
+}
+
+impl Default for ReceiptConfig {
+    fn default() -> Self {
+        ReceiptConfig {
+            senders: vec![],
+            csv: String::new(),
+            parser: String::new(),
+            parser_timeout_secs: 10,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FileIntakeConfig {
+    // 按顺序匹配，使用第一条匹配的规则
+    pub rules: Vec<FileIntakeRule>,
+    // 等待文件下载完成的时长，单位秒
+    pub timeout_secs: u64,
+}
+
+impl Default for FileIntakeConfig {
+    fn default() -> Self {
+        FileIntakeConfig {
+            rules: vec![],
+            timeout_secs: 120,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileIntakeRule {
+    // 只处理这些会话的文件，为空时不限
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    // 只处理这些人发的文件，为空时不限
+    #[serde(default)]
+    pub senders: Vec<String>,
+    // 扩展名，如 pdf、docx，不区分大小写，为空时不限
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    pub target: BackupTarget,
+    // 回复中链接的前缀，对应目标目录的公开访问地址，为空时使用上传地址，S3 为 7 天内有效的预签名地址
+    #[serde(default)]
+    pub link_base: String,
+    // 转存后的回复，{name} 为文件名，{link} 为链接，为空时不回复
+    #[serde(default = "default_intake_reply")]
+    pub reply: String,
+}
+
+fn default_intake_reply() -> String {
+    "已保存 {name}：{link}".to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DistributeConfig {
+    // 两个群之间的间隔，单位毫秒
+    pub interval_ms: u64,
+    // 每发完多少个群暂停一次
+    pub batch_size: usize,
+    // 批次之间的暂停，单位秒
+    pub batch_pause_secs: u64,
+    // 单个群发送失败后的重试次数
+    pub retries: u32,
+    // 等待消息回调确认的时长，单位秒，大文件上传较慢
+    pub verify_timeout_secs: u64,
+}
+
+impl Default for DistributeConfig {
+    fn default() -> Self {
+        DistributeConfig {
+            interval_ms: 5000,
+            batch_size: 10,
+            batch_pause_secs: 60,
+            retries: 2,
+            verify_timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AnnouncementConfig {
+    // 回复以这些词开头算确认，发通知时可单独指定
+    pub ack_keywords: Vec<String>,
+}
+
+impl Default for AnnouncementConfig {
+    fn default() -> Self {
+        AnnouncementConfig {
+            ack_keywords: ["收到", "+1", "好的", "ok"].iter().map(|k| k.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WordCloudConfig {
+    pub rooms: Vec<String>,
+    // 每天发送时间，格式 HH:MM，为空时不发送
+    pub time: String,
+    pub period: WordPeriod,
+    // 图片上最多的词数
+    pub limit: usize,
+    // 字体文件，为空时依次尝试微软雅黑、黑体、宋体
+    pub font: String,
+}
+
+impl Default for WordCloudConfig {
+    fn default() -> Self {
+        WordCloudConfig {
+            rooms: vec![],
+            time: String::new(),
+            period: WordPeriod::Day,
+            limit: 100,
+            font: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    // 图片宽度，单位像素
+    pub width: u32,
+    // 图片最大高度，超出的部分会被截掉
+    pub max_height: u32,
+    // 字体文件，为空时依次尝试微软雅黑、黑体、宋体
+    pub font: String,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig {
+            width: 800,
+            max_height: 10000,
+            font: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SqlSnapshotConfig {
+    // 开启后已有快照的库直接查快照，不经过微信；没有快照的库照常走 RPC
+    pub enabled: bool,
+    // 存放目录，为空时为程序目录下的 data/snapshots
+    pub dir: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DbPollConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    // 消息入库后等待多久仍未从回调收到才补发，避免抢在回调之前
+    pub grace_secs: u64,
+}
+
+impl Default for DbPollConfig {
+    fn default() -> Self {
+        DbPollConfig {
+            enabled: false,
+            interval_secs: 5,
+            grace_secs: 10,
+        }
+    }
+}
+
+// 备份不加密，消息库没有开启加密时聊天记录以明文上传；其中的密钥只能在同一 Windows 账号下恢复
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BackupConfig {
+    // 开启定时备份，手动备份不受影响
+    pub enabled: bool,
+    pub interval_hours: u64,
+    // 本地和每个远端保留的备份份数，0 表示全部保留
+    pub keep: usize,
+    // 同时打包 file_dir 下的媒体文件
+    pub include_media: bool,
+    pub targets: Vec<BackupTarget>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            enabled: false,
+            interval_hours: 24,
+            keep: 7,
+            include_media: false,
+            targets: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackupTarget {
+    // S3 兼容存储，endpoint 如 https://s3.amazonaws.com 或 MinIO 地址
+    S3 {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        // 对象名前缀，如 wcf/
+        #[serde(default)]
+        prefix: String,
+        access_key: String,
+        secret_key: Secret,
+    },
+    // WebDAV 目录地址
+    Webdav {
+        url: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: Secret,
+    },
+    // 本地目录，也可以是挂载的 NAS 共享目录
+    Local {
+        dir: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MessageStoreConfig {
+    pub enabled: bool,
+    // 数据库文件，为空时使用 data/messages.db
+    pub path: String,
+    // 消息保留天数，0 表示一直保留
+    pub retention_days: u64,
+    // 媒体文件保留天数，0 表示一直保留；只清理消息库中的消息引用的、位于 file_dir 下的文件
+    pub media_retention_days: u64,
+    // 使用 sqlcipher 加密，密钥用 DPAPI 保护后保存在 data 下；已有的明文库会自动转为加密库
+    pub encrypted: bool,
+}
+
+impl Default for MessageStoreConfig {
+    fn default() -> Self {
+        MessageStoreConfig {
+            enabled: false,
+            path: String::new(),
+            retention_days: 0,
+            media_retention_days: 0,
+            encrypted: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RedactionConfig {
+    pub enabled: bool,
+    // 手机号
+    pub phone: bool,
+    // 身份证号
+    pub id_card: bool,
+    // 银行卡号
+    pub bank_card: bool,
+    pub email: bool,
+    // 自定义正则，匹配到的内容整体替换
+    pub patterns: Vec<String>,
+    // 替换成的文本
+    pub mask: String,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        RedactionConfig {
+            enabled: false,
+            phone: true,
+            id_card: true,
+            bank_card: false,
+            email: false,
+            patterns: Vec::new(),
+            mask: "***".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    // 拦下不发
+    #[default]
+    Block,
+    // 照常发送，只通知管理员
+    Flag,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ModerationConfig {
+    pub enabled: bool,
+    // 关键词，不区分大小写
+    pub keywords: Vec<String>,
+    // 外部审核接口，POST {"text"}，返回 {"flagged", "reason"}，为空时只检查关键词
+    pub api_url: String,
+    pub api_key: Secret,
+    pub action: ModerationAction,
+    // 审核接口不可用时拦下，默认按通过处理
+    pub fail_closed: bool,
+    // 请求带上该令牌时跳过审核
+    pub override_token: Secret,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PacingConfig {
+    pub enabled: bool,
+    // 自动发送文本前的停顿：min_delay_ms + 字数 * ms_per_char，不超过 max_delay_ms
+    pub min_delay_ms: u64,
+    pub ms_per_char: u64,
+    pub max_delay_ms: u64,
+    // 停顿和批量间隔的随机浮动比例，0.3 即上下浮动 30%
+    pub jitter: f64,
+    // 0-23 点每小时的活跃度，停顿按 1/活跃度 缩放，为空时不调整
+    pub hourly: Vec<f64>,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        PacingConfig {
+            enabled: false,
+            min_delay_ms: 800,
+            ms_per_char: 120,
+            max_delay_ms: 8000,
+            jitter: 0.3,
+            hourly: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SendVerifyConfig {
+    // 所有发送接口都确认，关闭时仍可用 verify=true 单次开启
+    pub enabled: bool,
+    // 确认时等待消息回调的时长，单位秒
+    pub timeout_secs: u64,
+    // 不确认时也短暂等待回调以取得消息 id，单位毫秒，默认 0 不等待，需要消息 id 时用 verify=true
+    pub capture_ms: u64,
+}
+
+impl Default for SendVerifyConfig {
+    fn default() -> Self {
+        SendVerifyConfig {
+            enabled: false,
+            timeout_secs: 5,
+            capture_ms: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PipeConfig {
+    pub enabled: bool,
+    // 管道名称
+    pub name: String,
+}
+
+impl Default for PipeConfig {
+    fn default() -> Self {
+        PipeConfig {
+            enabled: false,
+            name: r"\\.\pipe\wcfrust".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SessionConfig {
+    // 被挤下线或强制退出后自动刷新登录二维码并推送到界面
+    pub relogin: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    // 登录状态下超过多少分钟没收到消息时重建消息接收，0 为不启用
+    pub silent_mins: u64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig { silent_mins: 30 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    // 是否告警，默认关闭；关闭时也会学习基线，可以先通过 /message-volume 确认基线符合实际再开启
+    pub enabled: bool,
+    // 统计窗口，单位分钟
+    pub window_mins: u64,
+    // 超过基线多少倍或低于基线几分之一时告警
+    pub factor: f64,
+    // 暴增时消息数、骤降时基线至少达到该值才告警，避免冷门群误报
+    pub min_messages: u64,
+    // 每个小时分别学习基线，该小时学习多少个窗口后才开始告警
+    pub learn_windows: u32,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        AnomalyConfig {
+            enabled: false,
+            window_mins: 10,
+            factor: 5.0,
+            min_messages: 30,
+            learn_windows: 12,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct QuietHoursConfig {
+    // 生效的群或联系人 wxid，* 表示全部
+    pub targets: Vec<String>,
+    // 开始时间，格式 HH:MM
+    pub start: String,
+    // 结束时间，早于开始时间时表示跨天
+    pub end: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CheckinConfig {
+    // 统计打卡的群
+    pub rooms: Vec<String>,
+    // 消息内容包含该关键词即算打卡
+    pub keyword: String,
+    // 每日汇总发送时间，格式 HH:MM，为空时不发送
+    pub summary_time: String,
+}
+
+impl Default for CheckinConfig {
+    fn default() -> Self {
+        CheckinConfig {
+            rooms: vec![],
+            keyword: "打卡".to_string(),
+            summary_time: "22:00".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CommandConfig {
+    pub enabled: bool,
+    // 指令前缀
+    pub prefix: String,
+}
+
+impl Default for CommandConfig {
+    fn default() -> Self {
+        CommandConfig {
+            enabled: false,
+            prefix: "/".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct UpdaterConfig {
+    // 更新源地址，返回 Tauri updater 格式的 json，为空时不启用
+    pub feed: String,
+    // 更新包签名公钥，tauri signer generate 生成，未配置时拒绝检查和安装更新
+    pub pubkey: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AdminNotifyConfig {
+    // 管理员 wxid，为空时不通知
+    pub wxid: String,
+    // 同类异常最短通知间隔，单位秒
+    pub throttle_secs: u64,
+    // 下游连续失败多少次后通知
+    pub sink_failure_threshold: u32,
+}
+
+impl Default for AdminNotifyConfig {
+    fn default() -> Self {
+        AdminNotifyConfig {
+            wxid: String::new(),
+            throttle_secs: 600,
+            sink_failure_threshold: 3,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    // 上报地址，为空时不启用
+    pub url: String,
+    // 上报间隔，单位秒
+    pub interval: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            url: String::new(),
+            interval: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct TapConfig {
+    pub enabled: bool,
+    // 存放目录，为空时为程序目录下的 data/tap
+    pub dir: String,
+    // 单个文件上限，单位 MB，0 为只按天切分
+    pub max_size_mb: u64,
+    // 保留天数，0 为不清理
+    pub retention_days: u64,
+}
+
+impl Default for TapConfig {
+    fn default() -> Self {
+        TapConfig {
+            enabled: false,
+            dir: String::new(),
+            max_size_mb: 100,
+            retention_days: 7,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RiskGuardConfig {
+    // 关闭后不做任何限制
+    pub enabled: bool,
+    pub friend_add: u32,
+    pub group_add: u32,
+    pub group_invite: u32,
+    pub group_kick: u32,
+    // 批量发送的消息条数
+    pub bulk_send: u32,
+    pub friend_check: u32,
+    // 所有群合计的 @所有人 次数
+    pub at_all: u32,
+}
+
+impl Default for RiskGuardConfig {
+    fn default() -> Self {
+        RiskGuardConfig {
+            enabled: true,
+            friend_add: 20,
+            group_add: 50,
+            group_invite: 50,
+            group_kick: 50,
+            bulk_send: 500,
+            friend_check: 100,
+            at_all: 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HttpCompression {
+    // 是否启用，客户端需通过 Accept-Encoding 声明支持 gzip 或 deflate
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // 小于该字节数的响应不压缩
+    #[serde(default = "default_compression_min_size")]
+    pub min_size: usize,
+}
+
+impl Default for HttpCompression {
+    fn default() -> Self {
+        HttpCompression {
+            enabled: true,
+            min_size: default_compression_min_size(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_compression_min_size() -> usize {
+    1024
+}
+
+/// 推送流的编码格式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamEncoding {
+    #[default]
+    Json,
+    /// wcf.proto 中的 WxMsg，不区分格式版本
+    Protobuf,
+    /// 与 json 内容相同的 MessagePack
+    Msgpack,
+}
+
+/// 推送消息格式版本
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadVersion {
+    /// 原始 wcf 字段
+    #[default]
+    #[serde(rename = "v1")]
+    V1,
+    /// 原始字段加解析后的扩展信息
+    #[serde(rename = "v2")]
+    V2,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PayloadVersions {
+    // http 回调
+    #[serde(default)]
+    pub http: PayloadVersion,
+    // socketIO
+    #[serde(default)]
+    pub socketio: PayloadVersion,
 }